@@ -3,7 +3,8 @@ use clap::Parser;
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
 use replicant_client::{Client, ClientDatabase};
-use replicant_core::models::Document;
+use replicant_core::models::{Document, SyncStatus};
+use replicant_core::SyncError;
 use serde_json::{json, Value};
 use sqlx::Row;
 use uuid::Uuid;
@@ -72,13 +73,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Some(id) => Uuid::parse_str(&id)?,
         None => match db.get_user_id().await {
             Ok(id) => id,
-            Err(_) => {
+            Err(SyncError::NotInitialized) => {
                 let id = Uuid::new_v4();
                 println!("🆕 Creating new user: {}", id.to_string().yellow());
                 let client_id = Uuid::new_v4();
                 setup_user(&db, id, client_id, &cli.server, &cli.api_key).await?;
                 id
             }
+            Err(e) => return Err(e.into()),
         },
     };
 
@@ -328,6 +330,8 @@ async fn create_task(
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         db.save_document(&doc).await?;
@@ -13,10 +13,10 @@ use ratatui::{
     Frame, Terminal,
 };
 use replicant_client::events::SyncEvent;
-use replicant_client::{Client, ClientDatabase};
-use replicant_core::models::Document;
-use serde_json::{json, Value};
-use sqlx::Row;
+use replicant_client::{Client, ClientDatabase, ConnectionState};
+use replicant_core::models::{user_id_from_email, Document, SyncStatus as DocumentSyncStatus};
+use replicant_core::SyncError;
+use serde_json::json;
 use std::{
     error::Error,
     io::{self, Write},
@@ -82,7 +82,7 @@ struct Task {
     created_at: chrono::DateTime<chrono::Utc>,
     updated_at: chrono::DateTime<chrono::Utc>,
     sync_revision: i64,
-    sync_status: Option<String>,
+    sync_status: DocumentSyncStatus,
 }
 
 struct ActivityEntry {
@@ -457,16 +457,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Get or create user
     let user_id = match db.get_user_id().await {
         Ok(id) => id,
-        Err(_) => {
+        Err(SyncError::NotInitialized) => {
             // Generate deterministic user ID based on user identifier or create random
             let id = if let Some(user_identifier) = &cli.user {
-                // Use UUID v5 for deterministic ID generation
-                // This creates a two-level namespace hierarchy:
-                // 1. DNS namespace -> Application namespace (using APP_ID)
-                // 2. Application namespace -> User ID (using user identifier)
-                // This ensures our user IDs are unique to our application
-                let app_namespace = Uuid::new_v5(&Uuid::NAMESPACE_DNS, APP_ID.as_bytes());
-                Uuid::new_v5(&app_namespace, user_identifier.as_bytes())
+                // The same ID the server derives, unique to our application
+                user_id_from_email(APP_ID, user_identifier)
             } else {
                 Uuid::new_v4()
             };
@@ -476,6 +471,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             setup_user(&db, id, client_id, &cli.server, &cli.api_key).await?;
             id
         }
+        Err(e) => return Err(e.into()),
     };
 
     // Create shared state
@@ -610,9 +606,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                 ActivityType::Error,
                             );
                         }
-                        SyncEvent::ConnectionLost { server_url } => {
+                        SyncEvent::ConnectionLost { server_url, reason } => {
                             app_state.add_activity(
-                                format!("Lost connection to {}", server_url),
+                                format!("Lost connection to {} ({:?})", server_url, reason),
                                 ActivityType::Disconnected,
                             );
                             app_state.sync_status.connected = false;
@@ -634,6 +630,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
                             app_state.sync_status.connected = true;
                             app_state.sync_status.connection_state = "Connected".to_string();
                         }
+                        SyncEvent::Reconnecting { attempt } => {
+                            app_state.sync_status.last_attempt = Some(Instant::now());
+                            app_state.sync_status.connection_state =
+                                format!("Reconnecting (attempt {})", attempt);
+                        }
                         SyncEvent::ConflictDetected { document_id, .. } => {
                             app_state.add_activity(
                                 format!(
@@ -643,6 +644,33 @@ async fn main() -> Result<(), Box<dyn Error>> {
                                 ActivityType::Error,
                             );
                         }
+                        SyncEvent::LockChanged {
+                            document_id,
+                            holder,
+                        } => {
+                            let state = if holder.is_some() {
+                                "locked"
+                            } else {
+                                "unlocked"
+                            };
+                            app_state.add_activity(
+                                format!(
+                                    "Task {}... {}",
+                                    &document_id[..8.min(document_id.len())],
+                                    state
+                                ),
+                                ActivityType::Updated,
+                            );
+                        }
+                        SyncEvent::PresenceChanged { .. }
+                        | SyncEvent::SyncSkipped { .. }
+                        | SyncEvent::DocumentSizeWarning { .. } => {}
+                        SyncEvent::AuthenticationFailed { reason } => {
+                            app_state.add_activity(
+                                format!("Authentication failed: {}", reason),
+                                ActivityType::Error,
+                            );
+                        }
                     }
                 })
             {
@@ -772,6 +800,12 @@ async fn run_app<B: Backend>(
                 _ => {} // No change
             }
             last_connection_state = Some(current_connected);
+
+            // Feed the retry countdown from the reconnection loop
+            state.lock().unwrap().sync_status.next_retry = match engine.connection_state() {
+                ConnectionState::Reconnecting { next_retry_at, .. } => Some(next_retry_at),
+                _ => None,
+            };
         }
 
         // Check if we should refresh tasks
@@ -932,10 +966,10 @@ fn render_task_list(f: &mut Frame, area: Rect, app_state: &AppState) {
                 _ => "⚪",
             };
 
-            let sync_icon = match task.sync_status.as_deref() {
-                Some("pending") => " 📤",
-                Some("conflict") => " ⚠️",
-                _ => "",
+            let sync_icon = match task.sync_status {
+                DocumentSyncStatus::Pending => " 📤",
+                DocumentSyncStatus::Conflict => " ⚠️",
+                DocumentSyncStatus::Synced => "",
             };
 
             let content = format!(
@@ -1662,33 +1696,22 @@ async fn load_tasks(
     // Log the query we're about to execute
     debug_log(&format!("Loading tasks for user_id: {}", user_id));
 
-    let rows = sqlx::query(
-        r#"
-        SELECT id, content, sync_status, created_at, updated_at, sync_revision
-        FROM documents
-        WHERE user_id = ?1 AND deleted_at IS NULL
-        ORDER BY created_at DESC
-        "#,
-    )
-    .bind(user_id.to_string())
-    .fetch_all(&db.pool)
-    .await?;
+    let mut documents: Vec<Document> = db
+        .get_all_documents()
+        .await?
+        .into_iter()
+        .filter(|doc| doc.user_id == user_id)
+        .collect();
+    documents.sort_by_key(|doc| std::cmp::Reverse(doc.created_at));
 
-    debug_log(&format!("Found {} documents", rows.len()));
+    debug_log(&format!("Found {} documents", documents.len()));
 
     let mut tasks = Vec::new();
-    for row in rows {
-        let id = Uuid::parse_str(&row.try_get::<String, _>("id")?)?;
-        let content_str = row.try_get::<String, _>("content")?;
-        let sync_status = row.try_get::<Option<String>, _>("sync_status")?;
-        let created_at = row.try_get::<chrono::DateTime<chrono::Utc>, _>("created_at")?;
-        let updated_at = row.try_get::<chrono::DateTime<chrono::Utc>, _>("updated_at")?;
-        let sync_revision = row.try_get::<i64, _>("sync_revision")?;
-
-        let content: Value = serde_json::from_str(&content_str).unwrap_or_default();
+    for doc in documents {
+        let content = &doc.content;
 
         let task = Task {
-            id,
+            id: doc.id,
             title: content
                 .get("title")
                 .and_then(|v| v.as_str())
@@ -1718,10 +1741,10 @@ async fn load_tasks(
                         .collect()
                 })
                 .unwrap_or_default(),
-            created_at,
-            updated_at,
-            sync_revision,
-            sync_status,
+            created_at: doc.created_at,
+            updated_at: doc.updated_at,
+            sync_revision: doc.sync_revision,
+            sync_status: doc.sync_status,
         };
 
         tasks.push(task);
@@ -1739,21 +1762,12 @@ async fn load_tasks(
 }
 
 async fn update_sync_status(db: &ClientDatabase, state: SharedState) {
-    let pending =
-        sqlx::query("SELECT COUNT(*) as count FROM documents WHERE sync_status = 'pending'")
-            .fetch_one(&db.pool)
-            .await
-            .ok()
-            .and_then(|row| row.try_get::<i64, _>("count").ok())
-            .unwrap_or(0) as usize;
-
-    let conflicts =
-        sqlx::query("SELECT COUNT(*) as count FROM documents WHERE sync_status = 'conflict'")
-            .fetch_one(&db.pool)
-            .await
-            .ok()
-            .and_then(|row| row.try_get::<i64, _>("count").ok())
-            .unwrap_or(0) as usize;
+    let pending = db
+        .get_pending_documents()
+        .await
+        .map(|docs| docs.len())
+        .unwrap_or(0);
+    let conflicts = db.count_conflicts().await.unwrap_or(0) as usize;
 
     let mut app_state = state.lock().unwrap();
     app_state.sync_status.pending_count = pending;
@@ -1865,6 +1879,8 @@ async fn create_sample_task(
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: DocumentSyncStatus::Synced,
         };
 
         let _ = db.save_document(&doc).await;
@@ -1,5 +1,6 @@
-use replicant_client::events::SyncEvent;
+use replicant_client::events::{DocumentSource, SyncEvent};
 use replicant_client::{Client, ClientDatabase};
+use replicant_core::SyncError;
 use serde_json::json;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
@@ -40,12 +41,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Get or create user
     let user_id = match db.get_user_id().await {
         Ok(id) => id,
-        Err(_) => {
+        Err(SyncError::NotInitialized) => {
             let id = Uuid::new_v4();
             let client_id = Uuid::new_v4();
             setup_user(&db, id, client_id, "ws://nonexistent:8080/ws", "test-token").await?;
             id
         }
+        Err(e) => return Err(e.into()),
     };
 
     println!("👤 User ID: {}", user_id);
@@ -88,8 +90,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         SyncEvent::SyncError { message } => {
                             format!("🚨 Sync error: {}", message)
                         }
-                        SyncEvent::ConnectionLost { server_url } => {
-                            format!("❌ Disconnected from {}", server_url)
+                        SyncEvent::ConnectionLost { server_url, reason } => {
+                            format!("❌ Disconnected from {} ({:?})", server_url, reason)
                         }
                         SyncEvent::ConnectionAttempted { server_url } => {
                             format!("🔄 Connecting to {}...", server_url)
@@ -97,9 +99,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         SyncEvent::ConnectionSucceeded { server_url } => {
                             format!("🔗 Connected to {}", server_url)
                         }
+                        SyncEvent::Reconnecting { attempt } => {
+                            format!("🔁 Reconnecting (attempt {})", attempt)
+                        }
                         SyncEvent::ConflictDetected { document_id, .. } => {
                             format!("⚠️ Conflict detected: {}", &document_id[..8])
                         }
+                        SyncEvent::LockChanged {
+                            document_id,
+                            holder,
+                        } => match holder {
+                            Some(holder) => format!(
+                                "🔒 Document {} locked by {}",
+                                &document_id[..8],
+                                &holder[..8]
+                            ),
+                            None => format!("🔓 Document {} unlocked", &document_id[..8]),
+                        },
+                        SyncEvent::PresenceChanged { active_clients } => {
+                            format!("👥 {} client(s) online", active_clients.len())
+                        }
+                        SyncEvent::AuthenticationFailed { reason } => {
+                            format!("🔑 Authentication failed: {}", reason)
+                        }
+                        SyncEvent::SyncSkipped {
+                            document_id,
+                            reason,
+                            ..
+                        } => format!("⏭️ Skipped sync of {}: {}", &document_id[..8], reason),
+                        SyncEvent::DocumentSizeWarning {
+                            document_id,
+                            size,
+                            threshold,
+                        } => format!(
+                            "📦 {} is {} bytes (warning at {})",
+                            &document_id[..8],
+                            size,
+                            threshold
+                        ),
                     };
 
                     if let Ok(mut t) = tracker_clone.lock() {
@@ -154,6 +191,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 created_at: chrono::Utc::now(),
                 updated_at: chrono::Utc::now(),
                 deleted_at: None,
+                expires_at: None,
+                sync_status: replicant_core::models::SyncStatus::Synced,
             };
 
             db.save_document(&doc).await?;
@@ -174,7 +213,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let test_content = json!({"title": "Test Document", "test": "data"});
 
         println!("  📤 Emitting test events...");
-        events.emit_document_created(&test_doc_id, &test_content);
+        events.emit_document_created(&test_doc_id, &test_content, DocumentSource::Local);
         events.emit_sync_started();
         events.emit_sync_completed(42);
         events.emit_connection_succeeded("ws://test-server");
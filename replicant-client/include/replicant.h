@@ -18,6 +18,36 @@
 namespace replicant {
 #endif  // __cplusplus
 
+/**
+ * Why the client considers its connection lost, carried by ConnectionLost
+ */
+typedef enum ReplicantDisconnectReason {
+  /**
+   * A message couldn't be handed to the connection
+   */
+  SendFailed = 0,
+  /**
+   * The heartbeat couldn't reach the server
+   */
+  PingTimeout = 1,
+  /**
+   * Reading from the connection failed
+   */
+  ReceiverError = 2,
+  /**
+   * The server closed the connection cleanly
+   */
+  ServerClosed = 3,
+  /**
+   * The server rejected the client's credentials and closed the connection
+   */
+  AuthFailed = 4,
+  /**
+   * The app disconnected on purpose, e.g. with `Client::set_offline`
+   */
+  Manual = 5,
+} ReplicantDisconnectReason;
+
 /**
  * Event types that can be emitted by the sync client
  */
@@ -62,6 +92,30 @@ typedef enum ReplicantEventType {
    * Successfully connected to the server
    */
   ConnectionSucceeded = 9,
+  /**
+   * The reconnection loop is starting another attempt
+   */
+  Reconnecting = 10,
+  /**
+   * An advisory lock on a document was acquired or released
+   */
+  LockChanged = 11,
+  /**
+   * The set of the user's connected clients changed
+   */
+  PresenceChanged = 12,
+  /**
+   * The server rejected the client's credentials; reconnection has stopped
+   */
+  AuthenticationFailed = 13,
+  /**
+   * A server version of a document was not applied over the local one
+   */
+  SyncSkipped = 14,
+  /**
+   * A document's content passed the size warning threshold
+   */
+  DocumentSizeWarning = 15,
 } ReplicantEventType;
 
 /**
@@ -82,13 +136,18 @@ typedef enum ReplicantSyncResult {
 typedef struct Replicant Replicant;
 
 /**
- * Document event callback for DocumentCreated, DocumentUpdated, DocumentDeleted
+ * Document event callback for DocumentCreated, DocumentUpdated, DocumentDeleted,
+ * LockChanged, SyncSkipped, DocumentSizeWarning
  *
  * # Parameters
  * * `event_type` - The specific document event type
  * * `document_id` - UUID of the document (always non-null)
- * * `title` - Document title (null for Deleted events)
- * * `content` - Full document JSON (null for Deleted events)
+ * * `title` - Document title (null for Deleted and DocumentSizeWarning events);
+ *   for LockChanged, the client ID holding the lock (null once released); for
+ *   SyncSkipped, why the server version was skipped
+ * * `content` - Full document JSON (null for Deleted and LockChanged events); for
+ *   SyncSkipped, `{"local_revision": n, "server_revision": n}`; for
+ *   DocumentSizeWarning, `{"size": n, "threshold": n}` in bytes
  * * `context` - User-defined context pointer
  */
 typedef void (*DocumentEventCallback)(enum ReplicantEventType event_type,
@@ -110,11 +169,12 @@ typedef void (*SyncEventCallback)(enum ReplicantEventType event_type,
                                   void *context);
 
 /**
- * Error event callback for SyncError
+ * Error event callback for SyncError, AuthenticationFailed
  *
  * # Parameters
- * * `event_type` - Always SyncError
- * * `error` - Error message (always non-null)
+ * * `event_type` - SyncError or AuthenticationFailed
+ * * `error` - Error message, or the server's reason for rejecting the
+ *   credentials (always non-null)
  * * `context` - User-defined context pointer
  */
 typedef void (*ErrorEventCallback)(enum ReplicantEventType event_type,
@@ -122,12 +182,15 @@ typedef void (*ErrorEventCallback)(enum ReplicantEventType event_type,
                                    void *context);
 
 /**
- * Connection event callback for ConnectionLost, ConnectionAttempted, ConnectionSucceeded
+ * Connection event callback for ConnectionLost, ConnectionAttempted, ConnectionSucceeded,
+ * Reconnecting, PresenceChanged
  *
  * # Parameters
  * * `event_type` - The connection event type
  * * `connected` - true if connected (valid for Lost/Succeeded), false otherwise
- * * `attempt_number` - Reconnection attempt number (valid for ConnectionAttempted)
+ * * `attempt_number` - Reconnection attempt number (valid for Reconnecting); for
+ *   PresenceChanged, the number of connected clients; for ConnectionLost, the
+ *   `DisconnectReason`
  * * `context` - User-defined context pointer
  */
 typedef void (*ConnectionEventCallback)(enum ReplicantEventType event_type,
@@ -151,6 +214,18 @@ typedef void (*ConflictEventCallback)(enum ReplicantEventType event_type,
                                       const char *losing_content,
                                       void *context);
 
+/**
+ * Patch event callback for DocumentUpdated events whose changes are known
+ *
+ * Invoked alongside the document callback, after it.
+ *
+ * # Parameters
+ * * `document_id` - UUID of the updated document (always non-null)
+ * * `patch` - JSON Patch (RFC 6902) that was applied (always non-null)
+ * * `context` - User-defined context pointer
+ */
+typedef void (*DocumentPatchEventCallback)(const char *document_id, const char *patch, void *context);
+
 /**
  * Document structure for C API
  */
@@ -286,6 +361,21 @@ void replicant_string_free(char *s);
  */
 char *replicant_get_version(void);
 
+/**
+ * Get the user ID the server assigns to an email within an app namespace
+ *
+ * # Arguments
+ * * `app_namespace` - The server's APP_NAMESPACE_ID
+ * * `email` - The user's email
+ *
+ * # Returns
+ * * The UUID string (caller must free with replicant_string_free), or null on invalid input
+ *
+ * # Safety
+ * Caller must ensure app_namespace and email are valid C strings
+ */
+char *replicant_user_id_from_email(const char *app_namespace, const char *email);
+
 /**
  * Register a callback for document events (Created, Updated, Deleted)
  *
@@ -325,7 +415,7 @@ enum ReplicantSyncResult replicant_register_sync_callback(struct Replicant *engi
                                                           void *context);
 
 /**
- * Register a callback for error events (SyncError)
+ * Register a callback for error events (SyncError, AuthenticationFailed)
  *
  * # Arguments
  * * `engine` - Sync engine instance
@@ -378,6 +468,27 @@ enum ReplicantSyncResult replicant_register_conflict_callback(struct Replicant *
                                                               ConflictEventCallback callback,
                                                               void *context);
 
+/**
+ * Register a callback for the JSON Patch behind each document update
+ *
+ * Called after the document callback for DocumentUpdated events whose changes
+ * are known, so apps can highlight just the fields that changed.
+ *
+ * # Arguments
+ * * `engine` - Sync engine instance
+ * * `callback` - C callback function to invoke with each patch
+ * * `context` - User-defined context pointer passed to callback
+ *
+ * # Returns
+ * * SyncResult indicating success or failure
+ *
+ * # Safety
+ * Caller must ensure engine is valid, callback is a valid function pointer, and context pointer outlives the callback registration
+ */
+enum ReplicantSyncResult replicant_register_document_patch_callback(struct Replicant *engine,
+                                                                    DocumentPatchEventCallback callback,
+                                                                    void *context);
+
 /**
  * Process all queued events on the current thread
  *
@@ -553,7 +664,7 @@ enum ReplicantSyncResult replicant_rebuild_search_index(struct Replicant *engine
  *
  * # Arguments
  * * `engine` - Replicant client instance
- * * `event_type` - Event type to emit (0-7)
+ * * `event_type` - Event type to emit (0-12)
  *
  * # Returns
  * * SyncResult indicating success or failure
@@ -569,6 +680,12 @@ enum ReplicantSyncResult replicant_rebuild_search_index(struct Replicant *engine
  * * 7 - ConnectionLost
  * * 8 - ConnectionAttempted
  * * 9 - ConnectionSucceeded
+ * * 10 - Reconnecting
+ * * 11 - LockChanged
+ * * 12 - PresenceChanged
+ * * 13 - AuthenticationFailed
+ * * 14 - SyncSkipped
+ * * 15 - DocumentSizeWarning
  *
  * # Safety
  * Caller must ensure engine is a valid pointer
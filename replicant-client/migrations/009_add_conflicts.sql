@@ -0,0 +1,8 @@
+-- Both sides of a conflict, kept until the app resolves it
+CREATE TABLE IF NOT EXISTS conflicts (
+    document_id TEXT PRIMARY KEY,
+    local_content JSON NOT NULL,
+    server_content JSON NOT NULL,
+    detected_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents(id)
+);
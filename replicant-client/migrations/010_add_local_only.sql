@@ -0,0 +1,2 @@
+-- Local-only documents live on this device and are never sent to the server
+ALTER TABLE documents ADD COLUMN local_only INTEGER NOT NULL DEFAULT 0;
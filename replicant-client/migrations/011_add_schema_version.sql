@@ -0,0 +1,7 @@
+-- Latest migration applied to this database, checked before migrating so an
+-- older build refuses to open a database written by a newer one
+CREATE TABLE schema_version (
+    version INTEGER NOT NULL
+);
+
+INSERT INTO schema_version (version) VALUES (11);
@@ -0,0 +1,4 @@
+-- Idempotency key of the document's pending operation. Generated when the
+-- operation is first sent and cleared whenever the document is written again,
+-- so resending an unchanged operation reuses the key.
+ALTER TABLE documents ADD COLUMN idempotency_key TEXT;
@@ -0,0 +1,3 @@
+-- zstd-compressed document content. When set, it replaces the JSON in
+-- `content`, which then holds an empty object.
+ALTER TABLE documents ADD COLUMN content_zstd BLOB;
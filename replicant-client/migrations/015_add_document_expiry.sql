@@ -0,0 +1,3 @@
+-- Optional expiry for ephemeral documents. Expired rows are hidden from
+-- listings locally and deleted by the server's expiry sweep.
+ALTER TABLE documents ADD COLUMN expires_at TEXT;
@@ -0,0 +1,29 @@
+-- Index of document tags for tag queries
+
+-- The JSON path tags are read from; a single row
+CREATE TABLE IF NOT EXISTS tag_config (
+    json_path TEXT NOT NULL PRIMARY KEY
+);
+
+INSERT INTO tag_config (json_path) VALUES ('$.tags');
+
+CREATE TABLE IF NOT EXISTS document_tags (
+    document_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (document_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_tags_tag ON document_tags(tag);
+
+-- Plain rows only: compressed content is indexed on its next save
+INSERT OR IGNORE INTO document_tags (document_id, tag)
+SELECT d.id, j.value
+FROM documents d, json_each(d.content, '$.tags') j
+WHERE d.deleted_at IS NULL AND d.content_zstd IS NULL AND j.type = 'text'
+  AND json_type(d.content, '$.tags') = 'array';
+
+INSERT OR IGNORE INTO document_tags (document_id, tag)
+SELECT d.id, json_extract(d.content, '$.tags')
+FROM documents d
+WHERE d.deleted_at IS NULL AND d.content_zstd IS NULL
+  AND json_type(d.content, '$.tags') = 'text';
@@ -0,0 +1,5 @@
+-- Local edit counter, separate from the server-assigned sync_revision. Each
+-- local save takes the next value across all documents, and queue entries
+-- record the value of the edit they carry.
+ALTER TABLE documents ADD COLUMN local_seq INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE sync_queue ADD COLUMN local_seq INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+-- Whether the server has the document. Local creates start at 0 and are
+-- replayed as creates, with any later edits folded in, until one is
+-- confirmed. Existing rows keep what replay used to assume: queued updates
+-- or a pending delete mean the server already has the document.
+ALTER TABLE documents ADD COLUMN uploaded INTEGER NOT NULL DEFAULT 0;
+UPDATE documents SET uploaded = 1
+WHERE sync_status != 'pending'
+   OR deleted_at IS NOT NULL
+   OR EXISTS (SELECT 1 FROM sync_queue q WHERE q.document_id = documents.id);
@@ -0,0 +1,4 @@
+-- Whether a create for the document was ever sent. Its confirmation can be
+-- lost with the connection, so a document deleted before `uploaded` is set
+-- may still exist on the server and needs its delete sent.
+ALTER TABLE documents ADD COLUMN create_sent INTEGER NOT NULL DEFAULT 0;
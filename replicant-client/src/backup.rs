@@ -0,0 +1,78 @@
+//! Portable export and import of the client database.
+//!
+//! Unlike copying the SQLite file, an export only records documents and their
+//! sync state, so it can be restored into a database on a newer schema.
+
+use replicant_core::{
+    models::{Document, SyncStatus},
+    protocol::ChangeEventType,
+    SyncError, SyncResult,
+};
+use serde::{Deserialize, Serialize};
+
+/// Version of the export format written by this build. Imports of anything
+/// newer are refused.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+/// How [`crate::Client::import`] combines an export with the existing data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Discard every local document and restore the export exactly,
+    /// including sync status and queued changes
+    Replace,
+    /// Keep local documents, taking an imported one only where there is no
+    /// local copy or its `sync_revision` is newer. Taken documents are marked
+    /// pending so they re-sync with the server.
+    Merge,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseExport {
+    pub format_version: u32,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub documents: Vec<ExportedDocument>,
+}
+
+/// A document with the local state needed to carry on syncing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedDocument {
+    pub document: Document,
+    pub sync_status: SyncStatus,
+    #[serde(default)]
+    pub local_only: bool,
+    // Changes not yet confirmed by the server, oldest first
+    #[serde(default)]
+    pub queued_operations: Vec<QueuedOperation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedOperation {
+    pub operation_type: ChangeEventType,
+    pub patch: Option<serde_json::Value>,
+    pub old_content_hash: Option<String>,
+}
+
+impl DatabaseExport {
+    pub fn new(documents: Vec<ExportedDocument>) -> Self {
+        Self {
+            format_version: EXPORT_FORMAT_VERSION,
+            exported_at: chrono::Utc::now(),
+            documents,
+        }
+    }
+
+    pub fn to_bytes(&self) -> SyncResult<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> SyncResult<Self> {
+        let export: Self = serde_json::from_slice(data)?;
+        if export.format_version > EXPORT_FORMAT_VERSION {
+            return Err(SyncError::InvalidOperation(format!(
+                "Export format version {} is newer than the supported version {}",
+                export.format_version, EXPORT_FORMAT_VERSION
+            )));
+        }
+        Ok(export)
+    }
+}
@@ -1,28 +1,104 @@
-use crate::{database::ClientDatabase, events::EventDispatcher, websocket::WebSocketClient};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::database::ClientDatabase;
+use crate::{
+    backup::{DatabaseExport, ImportMode, QueuedOperation},
+    database::{
+        Attachment, CompactReport, ConflictRecord, DocumentOrder, PendingDocumentInfo, RepairReport,
+    },
+    encryption::{open_server_message, outgoing_document, outgoing_patch, ContentCipher},
+    events::{
+        DisconnectReason, DocumentSource, EventDispatcher, EventType, SyncEvent, WatchHandle,
+    },
+    loop_guard::{LoopGuard, SyncLoopLimit},
+    runtime::{self, Instant, JoinHandle, Ticker},
+    stats::{ClientStats, StatsCounters},
+    store::DocumentStore,
+    tls::Sha256Fingerprint,
+    transaction::{Tx, TxOp},
+    websocket::{WebSocketClient, DEFAULT_CONNECT_TIMEOUT},
+};
 use replicant_core::{
-    errors::ClientError,
-    models::{Document, SyncStatus},
-    patches::{apply_patch, create_patch},
-    protocol::{ClientMessage, ServerMessage},
-    SyncResult,
+    errors::{ClientError, ServerError},
+    models::{Document, DocumentPatch, SyncStatus},
+    patches::{apply_patch, calculate_checksum, create_patch},
+    protocol::{
+        ChangeEventType, ClientMessage, ConflictResolution, ErrorCode, ServerMessage,
+        SharePermission, TransactionOp, BLOB_CHUNK_BYTES, DEFAULT_MAX_DOCUMENT_BYTES,
+        DEFAULT_MAX_MESSAGE_BYTES,
+    },
+    SyncError, SyncResult,
 };
-use sqlx::Row;
-use std::collections::HashMap;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
-use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, Mutex, Notify};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{field, instrument, Instrument};
 use uuid::Uuid;
 
 // Ping intervals for heartbeat detection
 const PING_INTERVAL: Duration = Duration::from_secs(10); // Send ping every 10 seconds
 
+// WebSocket Ping control frames, for NAT and proxy idle timers rather than liveness
+const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// Server syncs held back during uploads before the overflow policy applies
+const DEFAULT_DEFERRED_QUEUE_CAPACITY: usize = 100;
+
+// How long deleted documents are kept once the server has them
+const DEFAULT_HISTORY_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// How often AutoCompact::AfterChanges looks at the change count
+const AUTO_COMPACT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// How long to wait for the server to answer a lock request
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Each waiter is told the server's rejection reason if its transaction failed
+type TransactionWaiters = Arc<Mutex<HashMap<Uuid, oneshot::Sender<Result<(), String>>>>>;
+
+type BlobDownloads = Arc<Mutex<HashMap<Uuid, BlobDownload>>>;
+
+// refetch_document calls waiting for the server's copy of a document
+type DocumentWaiters = Arc<Mutex<HashMap<Uuid, oneshot::Sender<Document>>>>;
+
+// A blob being received for fetch_blob. The waiter gets the data once all of
+// it has arrived, or None if the server has no such blob.
+struct BlobDownload {
+    data: Vec<u8>,
+    done: oneshot::Sender<Option<Vec<u8>>>,
+}
+
 #[derive(Debug, Clone)]
 struct PendingUpload {
     operation_type: UploadType,
     sent_at: Instant,
+    // The document's local_seq when sent - edits made after it stay queued
+    local_seq: i64,
+}
+
+impl PendingUpload {
+    /// Track an upload of the document as it is now
+    async fn start(
+        operation_type: UploadType,
+        db: &Arc<dyn DocumentStore>,
+        document_id: &Uuid,
+    ) -> SyncResult<Self> {
+        // The confirmation can be lost with the connection, so remember that
+        // the server may have the document
+        if matches!(operation_type, UploadType::Create) {
+            db.mark_create_sent(document_id).await?;
+        }
+        Ok(Self {
+            operation_type,
+            sent_at: Instant::now(),
+            local_seq: db.local_seq(document_id).await?,
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -30,10 +106,300 @@ enum UploadType {
     Create,
     Update,
     Delete,
+    // Update resubmitted after an UpdateRejected - not retried again
+    Rebase,
+}
+
+/// What to do with a server sync that arrives while the deferred queue
+/// (syncs held back during an upload) is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeferredOverflowPolicy {
+    /// Drop the oldest queued sync to make room
+    #[default]
+    DropOldest,
+    /// Drop the incoming sync
+    DropNewest,
+    /// Keep every sync, letting the queue grow past its capacity without
+    /// limit and logging a warning. Nothing waits for room: the message
+    /// handler is also what drains the queue once uploads are confirmed.
+    GrowAndWarn,
+}
+
+/// When a [`Client`] runs [`Client::compact`] by itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoCompact {
+    /// On this schedule. A zero interval turns compaction off.
+    Every(Duration),
+    /// Once this many documents have been written, locally or by the
+    /// server, since the last compaction. Checked every minute.
+    AfterChanges(u64),
+}
+
+// Server syncs held back while an upload is in flight
+struct DeferredQueue {
+    messages: Mutex<Vec<ServerMessage>>,
+    capacity: usize,
+    overflow: DeferredOverflowPolicy,
+}
+
+impl DeferredQueue {
+    async fn push(&self, msg: ServerMessage, event_dispatcher: &Arc<EventDispatcher>) {
+        let mut queue = self.messages.lock().await;
+        if queue.len() < self.capacity {
+            queue.push(msg);
+            return;
+        }
+
+        let dropped = match self.overflow {
+            DeferredOverflowPolicy::DropOldest => {
+                let oldest = queue.remove(0);
+                queue.push(msg);
+                oldest
+            }
+            DeferredOverflowPolicy::DropNewest => msg,
+            DeferredOverflowPolicy::GrowAndWarn => {
+                tracing::warn!(
+                    "Deferred queue over capacity ({} messages), keeping all",
+                    queue.len()
+                );
+                queue.push(msg);
+                return;
+            }
+        };
+
+        // Only syncs are deferred
+        if let ServerMessage::SyncDocument { document, .. } = dropped {
+            tracing::warn!(
+                "Deferred queue full ({} messages), dropped sync for {} v{} ({:?})",
+                queue.len(),
+                document.id,
+                document.sync_revision,
+                self.overflow
+            );
+            event_dispatcher.emit_sync_error(&format!(
+                "Deferred queue full, dropped sync for document {} v{}",
+                document.id, document.sync_revision
+            ));
+        }
+    }
+}
+
+// Signalled on every SyncComplete, carrying the server's document count
+#[derive(Default)]
+struct SyncCompletion {
+    notifier: Notify,
+    synced_count: AtomicUsize,
+    // Mirrors the persisted last_sync_at so it can be read without the store
+    last_sync_at: std::sync::Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+}
+
+// The client state server messages are handled against, built once for
+// each receive loop
+struct MessageContext {
+    db: Arc<dyn DocumentStore>,
+    client_id: Uuid,
+    event_dispatcher: Arc<EventDispatcher>,
+    pending_uploads: Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+    upload_complete_notifier: Arc<Notify>,
+    sync_completion: Arc<SyncCompletion>,
+    sync_protection_mode: Arc<AtomicBool>,
+    deferred_messages: Arc<DeferredQueue>,
+    lock_waiters: Arc<Mutex<HashMap<Uuid, oneshot::Sender<bool>>>>,
+    transaction_waiters: TransactionWaiters,
+    blob_downloads: BlobDownloads,
+    document_waiters: DocumentWaiters,
+    stats: Arc<StatsCounters>,
+    loop_guard: Arc<LoopGuard>,
+    ws_client: Arc<Mutex<Option<WebSocketClient>>>,
+    cipher: Option<Arc<dyn ContentCipher>>,
+}
+
+/// What a [`Client::sync_now`] round trip did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyncSummary {
+    /// Pending local changes the server confirmed
+    pub uploaded: usize,
+    /// Documents the server sent in the full sync
+    pub downloaded: usize,
+    /// Documents that went into conflict during the sync
+    pub conflicted: usize,
+}
+
+/// Connection status as seen by the reconnection loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// Offline and no reconnection attempt has been made yet
+    Disconnected,
+    /// Offline; `attempt` is the latest attempt number and `next_retry_at`
+    /// when the loop will try again (in the past while an attempt is running)
+    Reconnecting {
+        attempt: u32,
+        next_retry_at: Instant,
+    },
+}
+
+/// Pre-save check for document content, see [`Client::set_validator`].
+pub type ContentValidator = Box<dyn Fn(&serde_json::Value) -> Result<(), String> + Send + Sync>;
+
+/// Upgrades document content by one schema version, see
+/// [`Client::register_content_migration`].
+pub type ContentMigration = Box<dyn Fn(&mut serde_json::Value) + Send + Sync>;
+
+/// Picks the server URL for a user ID, see [`ClientConfig::server_resolver`].
+pub type ServerResolver = Arc<dyn Fn(Uuid) -> String + Send + Sync>;
+
+/// Content field holding the schema version content migrations go by.
+/// Content without it is version 0.
+pub const SCHEMA_VERSION_KEY: &str = "_schema_version";
+
+// from_version -> (to_version, migration)
+type ContentMigrations = BTreeMap<u32, (u32, ContentMigration)>;
+
+/// How long the client waits for the server to confirm uploads during the
+/// initial upload-first sync, and how often it retries unconfirmed ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncTimeouts {
+    /// Wait for the first round of uploads before retrying the stragglers
+    pub upload_confirm: Duration,
+    /// Wait for the first retry; doubled on each further attempt
+    pub retry_confirm: Duration,
+    /// Retries before giving up and emitting a sync error
+    pub max_retry_attempts: u32,
+    /// Wait for the server to finish a full sync in [`Client::sync_now`]
+    pub full_sync: Duration,
+    /// Wait for the server to send a blob in [`Client::fetch_blob`]
+    pub blob_fetch: Duration,
+}
+
+impl Default for SyncTimeouts {
+    fn default() -> Self {
+        Self {
+            upload_confirm: Duration::from_secs(10),
+            retry_confirm: Duration::from_secs(5),
+            max_retry_attempts: 3,
+            full_sync: Duration::from_secs(30),
+            blob_fetch: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Options fixed when a [`Client`] is constructed.
+#[derive(Clone, Default)]
+pub struct ClientConfig {
+    /// JSON paths into document content (e.g. `$.body`) indexed for
+    /// [`Client::local_search`]. Titles are always indexed. When empty, any
+    /// previously configured paths are kept.
+    pub search_fields: Vec<String>,
+    /// JSON path into document content (e.g. `$.meta.labels`) read for
+    /// [`Client::documents_with_tag`]. The value there may be a string or an
+    /// array of strings. Defaults to `$.tags`.
+    pub tag_path: Option<String>,
+    /// Encrypts content before it is sent to the server. See
+    /// [`crate::encryption`] for what this mode doesn't support.
+    pub cipher: Option<Arc<dyn ContentCipher>>,
+    /// How the server should settle this client's updates when another client
+    /// changed the document first. By default they are rebased and resent.
+    pub conflict_resolution: Option<ConflictResolution>,
+    /// Ask the server to acknowledge each message as soon as it arrives,
+    /// ahead of its response. The time to each ack shows up in
+    /// [`Client::stats`]. Off by default.
+    pub receipt_acks: bool,
+    /// Largest WebSocket message sent or accepted, in bytes. Defaults to
+    /// [`DEFAULT_MAX_MESSAGE_BYTES`]; keep it in line with the server's limit.
+    pub max_message_bytes: Option<usize>,
+    /// Upload confirmation timeouts, tune these for slow or fast links
+    pub timeouts: SyncTimeouts,
+    /// Server syncs held back while uploads are in flight before
+    /// `deferred_overflow` applies. Defaults to 100.
+    pub deferred_queue_capacity: Option<usize>,
+    pub deferred_overflow: DeferredOverflowPolicy,
+    /// Certificate fingerprints the `wss://` server must present. Empty keeps
+    /// standard CA verification; see [`crate::tls`] for rotating pins.
+    pub tls_pins: Vec<Sha256Fingerprint>,
+    /// Store document content compressed in the local database, see
+    /// [`ClientDatabase::with_compressed_content`]. Only applies to
+    /// [`Client::with_config`]; stores passed to [`Client::with_store`] are
+    /// used as configured.
+    pub compress_content: bool,
+    /// How often to send WebSocket Ping control frames so NAT and proxy
+    /// idle timeouts don't close a quiet connection. Defaults to 30
+    /// seconds; `Duration::ZERO` turns them off. Liveness is still checked
+    /// with application-level pings.
+    pub keepalive_interval: Option<Duration>,
+    /// How long each attempt to reach the server may take, retries included,
+    /// before it fails with `ClientError::Timeout` and the reconnection loop
+    /// tries again later. Defaults to 10 seconds.
+    pub connect_timeout: Option<Duration>,
+    /// Most operations kept in the sync queue while offline. When it is
+    /// full, each document's queued updates are merged; if that doesn't make
+    /// room, changes fail with `SyncError::QueueFull` until uploads drain
+    /// it. Unbounded by default.
+    pub max_queue_size: Option<usize>,
+    /// Longest a client may go without a completed sync and still accept
+    /// changes. Past it, changes fail with `SyncError::ReconcileRequired`
+    /// until [`Client::sync_now`] succeeds, so days-old local state isn't
+    /// silently pushed over the server's on reconnect. With
+    /// `ConflictResolution::ServerWins` a connected client reconciles by
+    /// itself instead. Off by default.
+    pub offline_edit_window: Option<Duration>,
+    /// How long [`Client::compact`] keeps deleted documents after the
+    /// server has them. Defaults to 30 days.
+    pub history_retention: Option<Duration>,
+    /// Compact the local database in the background. Off by default.
+    pub auto_compact: Option<AutoCompact>,
+    /// When a document answering server changes is held back as a sync
+    /// loop. Defaults to [`SyncLoopLimit::default`].
+    pub sync_loop_limit: Option<SyncLoopLimit>,
+    /// Largest serialized content a document may have, in bytes. Bigger
+    /// creates and updates fail with `SyncError::DocumentTooLarge` before
+    /// anything is saved. Defaults to [`DEFAULT_MAX_DOCUMENT_BYTES`]; keep it
+    /// in line with the server's limit.
+    pub max_document_bytes: Option<usize>,
+    /// Content size past which saving a document emits
+    /// `SyncEvent::DocumentSizeWarning`. Defaults to half of
+    /// `max_document_bytes`.
+    pub document_size_warning_bytes: Option<usize>,
+    /// Picks the server for this client's user when users are sharded
+    /// across several servers. It is asked before every connection attempt,
+    /// so a user moved to another shard is followed on the next reconnect.
+    /// Without one the `server_url` the client was created with is used.
+    pub server_resolver: Option<ServerResolver>,
+}
+
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("search_fields", &self.search_fields)
+            .field("tag_path", &self.tag_path)
+            .field("cipher", &self.cipher.is_some())
+            .field("conflict_resolution", &self.conflict_resolution)
+            .field("receipt_acks", &self.receipt_acks)
+            .field("max_message_bytes", &self.max_message_bytes)
+            .field("timeouts", &self.timeouts)
+            .field("deferred_queue_capacity", &self.deferred_queue_capacity)
+            .field("deferred_overflow", &self.deferred_overflow)
+            .field("tls_pins", &self.tls_pins)
+            .field("compress_content", &self.compress_content)
+            .field("keepalive_interval", &self.keepalive_interval)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("max_queue_size", &self.max_queue_size)
+            .field("offline_edit_window", &self.offline_edit_window)
+            .field("history_retention", &self.history_retention)
+            .field("auto_compact", &self.auto_compact)
+            .field("sync_loop_limit", &self.sync_loop_limit)
+            .field("max_document_bytes", &self.max_document_bytes)
+            .field(
+                "document_size_warning_bytes",
+                &self.document_size_warning_bytes,
+            )
+            .field("server_resolver", &self.server_resolver.is_some())
+            .finish()
+    }
 }
 
 pub struct Client {
-    db: Arc<ClientDatabase>,
+    db: Arc<dyn DocumentStore>,
     ws_client: Arc<Mutex<Option<WebSocketClient>>>,
     user_id: Uuid,
     client_id: Uuid,
@@ -41,10 +407,26 @@ pub struct Client {
     event_dispatcher: Arc<EventDispatcher>,
     pending_uploads: Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
     upload_complete_notifier: Arc<Notify>,
+    sync_completion: Arc<SyncCompletion>,
     sync_protection_mode: Arc<AtomicBool>,
     is_connected: Arc<AtomicBool>,
+    // Forced offline via set_offline - the reconnection loop stands down
+    offline_mode: Arc<AtomicBool>,
+    // Set by pause_sync - local changes are queued but not sent immediately
+    sync_paused: AtomicBool,
+    // Wakes the reconnection loop for an immediate attempt
+    reconnect_now: Arc<Notify>,
+    // Set while a reconnection loop is running, so only one is ever spawned
+    reconnection_active: Arc<AtomicBool>,
+    // Set once the server rejects our credentials or protocol version -
+    // reconnecting can't help
+    auth_failed: Arc<AtomicBool>,
+    // (attempt, next_retry_at) while the reconnection loop is retrying
+    reconnect_state: Arc<std::sync::Mutex<Option<(u32, Instant)>>>,
     last_ping_time: Arc<Mutex<Option<Instant>>>,
-    server_url: String,
+    // The server connected to last, as picked by server_resolver if set
+    server_url: Arc<std::sync::Mutex<String>>,
+    server_resolver: Option<ServerResolver>,
     email: String,
     api_key: String,
     api_secret: String,
@@ -52,10 +434,45 @@ pub struct Client {
     reconnect_sync_tx: mpsc::Sender<()>,
     reconnect_sync_rx: Option<mpsc::Receiver<()>>,
     // Queue for deferred sync messages during upload protection
-    deferred_messages: Arc<Mutex<Vec<ServerMessage>>>,
+    deferred_messages: Arc<DeferredQueue>,
+    validator: std::sync::RwLock<Option<ContentValidator>>,
+    content_migrations: std::sync::RwLock<ContentMigrations>,
+    // try_lock calls awaiting the server's LockResponse
+    lock_waiters: Arc<Mutex<HashMap<Uuid, oneshot::Sender<bool>>>>,
+    // transaction calls awaiting the server's TransactionResponse
+    transaction_waiters: TransactionWaiters,
+    // fetch_blob calls receiving a blob's chunks
+    blob_downloads: BlobDownloads,
+    document_waiters: DocumentWaiters,
+    stats: Arc<StatsCounters>,
+    loop_guard: Arc<LoopGuard>,
+    // Documents this client asked to hear about; empty means all of them
+    subscriptions: Arc<std::sync::Mutex<HashSet<Uuid>>>,
+    // Set once on_presence_change subscribed to presence updates
+    presence_subscribed: Arc<AtomicBool>,
+    // Locks taken with try_lock and not yet released
+    held_locks: Arc<std::sync::Mutex<HashSet<Uuid>>>,
+    cipher: Option<Arc<dyn ContentCipher>>,
+    conflict_resolution: Option<ConflictResolution>,
+    receipt_acks: bool,
+    max_message_bytes: usize,
+    max_document_bytes: usize,
+    document_size_warning_bytes: usize,
+    keepalive_interval: Duration,
+    connect_timeout: Duration,
+    max_queue_size: Option<usize>,
+    offline_edit_window: Option<Duration>,
+    history_retention: Duration,
+    auto_compact: Option<AutoCompact>,
+    timeouts: SyncTimeouts,
+    tls_pins: Vec<Sha256Fingerprint>,
+    // Cancelled by shutdown() to stop every background task
+    shutdown_token: CancellationToken,
+    background_tasks: Arc<std::sync::Mutex<Vec<JoinHandle>>>,
 }
 
 impl Client {
+    #[cfg(not(target_arch = "wasm32"))]
     pub async fn new(
         database_url: &str,
         server_url: &str,
@@ -63,9 +480,61 @@ impl Client {
         api_key: &str,
         api_secret: &str,
     ) -> SyncResult<Self> {
-        let db = Arc::new(ClientDatabase::new(database_url).await?);
+        Self::with_config(
+            database_url,
+            server_url,
+            email,
+            api_key,
+            api_secret,
+            ClientConfig::default(),
+        )
+        .await
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn with_config(
+        database_url: &str,
+        server_url: &str,
+        email: &str,
+        api_key: &str,
+        api_secret: &str,
+        config: ClientConfig,
+    ) -> SyncResult<Self> {
+        let db = ClientDatabase::new(database_url)
+            .await?
+            .with_compressed_content(config.compress_content);
         db.run_migrations().await?;
 
+        Self::with_store(Arc::new(db), server_url, email, api_key, api_secret, config).await
+    }
+
+    /// Create a client on a storage backend other than the default SQLite
+    /// database. The store must be ready to use, e.g. already migrated.
+    pub async fn with_store(
+        db: Arc<dyn DocumentStore>,
+        server_url: &str,
+        email: &str,
+        api_key: &str,
+        api_secret: &str,
+        config: ClientConfig,
+    ) -> SyncResult<Self> {
+        // Only rebuild the search index when the indexed fields change
+        if !config.search_fields.is_empty() {
+            let mut fields = config.search_fields.clone();
+            fields.sort();
+            fields.dedup();
+            if db.get_search_paths().await? != fields {
+                db.configure_search(&fields).await?;
+            }
+        }
+
+        // Likewise only re-index tags when the path changes
+        if let Some(path) = &config.tag_path {
+            if db.get_tag_path().await?.as_ref() != Some(path) {
+                db.configure_tags(path).await?;
+            }
+        }
+
         // Ensure user_config exists with deterministic user ID based on email
         db.ensure_user_config_with_identifier(server_url, email)
             .await?;
@@ -82,21 +551,46 @@ impl Client {
         let (reconnect_sync_tx, reconnect_sync_rx) = mpsc::channel(10);
 
         let is_connected = Arc::new(AtomicBool::new(false));
+        let max_message_bytes = config
+            .max_message_bytes
+            .unwrap_or(DEFAULT_MAX_MESSAGE_BYTES);
+        let keepalive_interval = config
+            .keepalive_interval
+            .unwrap_or(DEFAULT_KEEPALIVE_INTERVAL);
+        let connect_timeout = config.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+        let max_document_bytes = config
+            .max_document_bytes
+            .unwrap_or(DEFAULT_MAX_DOCUMENT_BYTES);
+        let document_size_warning_bytes = config
+            .document_size_warning_bytes
+            .unwrap_or(max_document_bytes / 2);
+        let stats = Arc::new(StatsCounters::default());
+        let server_url = match &config.server_resolver {
+            Some(resolve) => resolve(user_id),
+            None => server_url.to_string(),
+        };
         // Try to connect to WebSocket, but don't fail if offline
         let (ws_client, initial_ping_time) = match WebSocketClient::connect(
-            server_url,
+            &server_url,
             email,
             client_id,
             api_key,
             api_secret,
             Some(event_dispatcher.clone()),
+            Some(stats.clone()),
             is_connected.clone(),
+            config.conflict_resolution.clone(),
+            config.receipt_acks,
+            max_message_bytes,
+            &config.tls_pins,
+            keepalive_interval,
+            connect_timeout,
         )
         .await
         {
             Ok((client, receiver)) => {
                 // Start forwarding WebSocket messages to our channel
-                tokio::spawn(async move {
+                runtime::spawn(async move {
                     if let Err(e) = receiver.forward_to(tx).await {
                         tracing::error!("WebSocket receiver error: {}", e);
                     }
@@ -117,16 +611,62 @@ impl Client {
             event_dispatcher: event_dispatcher.clone(),
             pending_uploads: Arc::new(Mutex::new(HashMap::new())),
             upload_complete_notifier: Arc::new(Notify::new()),
+            sync_completion: Arc::new(SyncCompletion {
+                last_sync_at: std::sync::Mutex::new(db.get_last_sync_at().await?),
+                ..Default::default()
+            }),
             sync_protection_mode: Arc::new(AtomicBool::new(false)),
             is_connected: is_connected,
+            offline_mode: Arc::new(AtomicBool::new(false)),
+            sync_paused: AtomicBool::new(false),
+            reconnect_now: Arc::new(Notify::new()),
+            reconnection_active: Arc::new(AtomicBool::new(false)),
+            auth_failed: Arc::new(AtomicBool::new(false)),
+            reconnect_state: Arc::new(std::sync::Mutex::new(None)),
             last_ping_time: Arc::new(Mutex::new(initial_ping_time)),
-            server_url: server_url.to_string(),
+            server_url: Arc::new(std::sync::Mutex::new(server_url)),
+            server_resolver: config.server_resolver.clone(),
             email: email.to_string(),
             api_key: api_key.to_string(),
             api_secret: api_secret.to_string(),
             reconnect_sync_tx,
             reconnect_sync_rx: Some(reconnect_sync_rx),
-            deferred_messages: Arc::new(Mutex::new(Vec::new())),
+            deferred_messages: Arc::new(DeferredQueue {
+                messages: Mutex::new(Vec::new()),
+                capacity: config
+                    .deferred_queue_capacity
+                    .unwrap_or(DEFAULT_DEFERRED_QUEUE_CAPACITY),
+                overflow: config.deferred_overflow,
+            }),
+            validator: std::sync::RwLock::new(None),
+            content_migrations: std::sync::RwLock::new(BTreeMap::new()),
+            lock_waiters: Arc::new(Mutex::new(HashMap::new())),
+            transaction_waiters: Arc::new(Mutex::new(HashMap::new())),
+            blob_downloads: Arc::new(Mutex::new(HashMap::new())),
+            document_waiters: Arc::new(Mutex::new(HashMap::new())),
+            stats,
+            loop_guard: Arc::new(LoopGuard::new(config.sync_loop_limit.unwrap_or_default())),
+            subscriptions: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            presence_subscribed: Arc::new(AtomicBool::new(false)),
+            held_locks: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            cipher: config.cipher.clone(),
+            conflict_resolution: config.conflict_resolution.clone(),
+            receipt_acks: config.receipt_acks,
+            max_message_bytes,
+            max_document_bytes,
+            document_size_warning_bytes,
+            keepalive_interval,
+            connect_timeout,
+            max_queue_size: config.max_queue_size,
+            offline_edit_window: config.offline_edit_window,
+            history_retention: config
+                .history_retention
+                .unwrap_or(DEFAULT_HISTORY_RETENTION),
+            auto_compact: config.auto_compact,
+            timeouts: config.timeouts,
+            tls_pins: config.tls_pins.clone(),
+            shutdown_token: CancellationToken::new(),
+            background_tasks: Arc::new(std::sync::Mutex::new(Vec::new())),
         };
 
         // Automatically start background tasks
@@ -139,120 +679,170 @@ impl Client {
         self.event_dispatcher.clone()
     }
 
+    fn message_context(&self) -> MessageContext {
+        MessageContext {
+            db: self.db.clone(),
+            client_id: self.client_id,
+            event_dispatcher: self.event_dispatcher.clone(),
+            pending_uploads: self.pending_uploads.clone(),
+            upload_complete_notifier: self.upload_complete_notifier.clone(),
+            sync_completion: self.sync_completion.clone(),
+            sync_protection_mode: self.sync_protection_mode.clone(),
+            deferred_messages: self.deferred_messages.clone(),
+            lock_waiters: self.lock_waiters.clone(),
+            transaction_waiters: self.transaction_waiters.clone(),
+            blob_downloads: self.blob_downloads.clone(),
+            document_waiters: self.document_waiters.clone(),
+            stats: self.stats.clone(),
+            loop_guard: self.loop_guard.clone(),
+            ws_client: self.ws_client.clone(),
+            cipher: self.cipher.clone(),
+        }
+    }
+
     async fn spawn_background_tasks(&mut self) -> SyncResult<()> {
         // Take the receiver - can only start once
         let rx = self
             .message_rx
             .take()
-            .ok_or_else(|| ClientError::WebSocket("Client already started".to_string()))?;
+            .ok_or_else(|| ClientError::InvalidState("Client already started".to_string()))?;
 
         // Take the reconnect sync receiver
         let reconnect_sync_rx = self.reconnect_sync_rx.take().ok_or_else(|| {
-            ClientError::WebSocket("Client reconnect sync already started".to_string())
+            ClientError::InvalidState("Client reconnect sync already started".to_string())
         })?;
 
-        let db = self.db.clone();
         let client_id = self.client_id;
-        let event_dispatcher = self.event_dispatcher.clone();
-        let pending_uploads = self.pending_uploads.clone();
-        let upload_complete_notifier = self.upload_complete_notifier.clone();
-        let sync_protection_mode = self.sync_protection_mode.clone();
-        let ws_client = self.ws_client.clone();
-        let deferred_messages = self.deferred_messages.clone();
+        let message_context = self.message_context();
+        let auth_failed = self.auth_failed.clone();
 
         // Clone variables for the reconnection sync handler
-        let db_for_reconnect_sync = db.clone();
-        let pending_uploads_for_reconnect_sync = pending_uploads.clone();
-        let ws_client_for_reconnect_sync = ws_client.clone();
+        let db_for_reconnect_sync = self.db.clone();
+        let pending_uploads_for_reconnect_sync = self.pending_uploads.clone();
+        let event_dispatcher_for_reconnect_sync = self.event_dispatcher.clone();
+        let loop_guard_for_reconnect_sync = self.loop_guard.clone();
+        let lock_waiters_for_reconnect_sync = self.lock_waiters.clone();
+        let subscriptions = self.subscriptions.clone();
+        let presence_subscribed = self.presence_subscribed.clone();
+        let held_locks = self.held_locks.clone();
+        let ws_client_for_reconnect_sync = self.ws_client.clone();
+        let cipher_for_reconnect_sync = self.cipher.clone();
+
+        let shutdown_token = self.shutdown_token.clone();
+        let shutdown_token_for_reconnect_sync = self.shutdown_token.clone();
 
         self.start_reconnection_loop();
 
         // Spawn message handler with upload tracking
-        tokio::spawn(async move {
-            let mut rx = rx;
-            tracing::info!("CLIENT {}: Message handler started", client_id);
-            while let Some(msg) = rx.recv().await {
-                tracing::info!(
-                    "CLIENT {}: Processing server message: {:?}",
-                    client_id,
-                    std::mem::discriminant(&msg)
-                );
-                if let Err(e) = Self::handle_server_message_with_tracking(
-                    msg,
-                    &db,
-                    client_id,
-                    &event_dispatcher,
-                    &pending_uploads,
-                    &upload_complete_notifier,
-                    &sync_protection_mode,
-                    &deferred_messages,
-                )
-                .await
-                {
-                    tracing::error!("CLIENT {}: Error handling server message: {}", client_id, e);
-                } else {
+        let message_handler = runtime::spawn(
+            async move {
+                let mut rx = rx;
+                tracing::info!("Message handler started");
+                while let Some(msg) = tokio::select! {
+                    msg = rx.recv() => msg,
+                    _ = shutdown_token.cancelled() => None,
+                } {
                     tracing::info!(
-                        "CLIENT {}: Successfully processed server message",
-                        client_id
+                        "Processing server message: {:?}",
+                        std::mem::discriminant(&msg)
                     );
+                    Self::note_auth_failure(&msg, &auth_failed, &message_context.event_dispatcher);
+                    if let Err(e) =
+                        Self::handle_server_message_with_tracking(msg, &message_context).await
+                    {
+                        tracing::error!("Error handling server message: {}", e);
+                    } else {
+                        tracing::info!("Successfully processed server message");
+                    }
                 }
+                tracing::warn!("Message handler terminated");
             }
-            tracing::warn!("CLIENT {}: Message handler terminated", client_id);
-        });
+            .instrument(tracing::info_span!("message_handler", %client_id)),
+        );
 
         // Spawn reconnection sync handler
-        tokio::spawn(async move {
-            let mut reconnect_sync_rx = reconnect_sync_rx;
-            tracing::info!("CLIENT {}: Reconnection sync handler started", client_id);
-
-            #[allow(clippy::redundant_pattern_matching)] // Preserve drop order
-            while let Some(_) = reconnect_sync_rx.recv().await {
-                tracing::info!("CLIENT {}: Received reconnection sync trigger", client_id);
-
-                // Perform pending sync using the actual engine components
-                if let Err(e) = Self::perform_pending_sync_after_reconnection(
-                    &db_for_reconnect_sync,
-                    &ws_client_for_reconnect_sync,
-                    client_id,
-                    &pending_uploads_for_reconnect_sync,
-                )
-                .await
-                {
-                    tracing::error!(
-                        "CLIENT {}: Failed to sync pending documents after reconnection: {}",
+        let reconnect_sync_handler = runtime::spawn(
+            async move {
+                let mut reconnect_sync_rx = reconnect_sync_rx;
+                tracing::info!("Reconnection sync handler started");
+
+                #[allow(clippy::redundant_pattern_matching)] // Preserve drop order
+                while let Some(_) = tokio::select! {
+                    trigger = reconnect_sync_rx.recv() => trigger,
+                    _ = shutdown_token_for_reconnect_sync.cancelled() => None,
+                } {
+                    tracing::info!("Received reconnection sync trigger");
+
+                    // The server dropped everything tied to the old
+                    // connection; restore it before catching up on documents
+                    if let Err(e) = Self::replay_session_state(
+                        &ws_client_for_reconnect_sync,
+                        &subscriptions,
+                        &presence_subscribed,
+                        &held_locks,
+                        &lock_waiters_for_reconnect_sync,
+                        &event_dispatcher_for_reconnect_sync,
+                    )
+                    .await
+                    {
+                        tracing::warn!("Failed to restore session state: {}", e);
+                    }
+
+                    // Perform pending sync using the actual engine components
+                    if let Err(e) = Self::perform_pending_sync_after_reconnection(
+                        &db_for_reconnect_sync,
+                        &ws_client_for_reconnect_sync,
                         client_id,
-                        e
-                    );
-                } else {
-                    tracing::info!(
-                        "✅ CLIENT {}: Pending documents sync completed after reconnection",
-                        client_id
-                    );
+                        &pending_uploads_for_reconnect_sync,
+                        &event_dispatcher_for_reconnect_sync,
+                        &loop_guard_for_reconnect_sync,
+                        cipher_for_reconnect_sync.as_deref(),
+                    )
+                    .await
+                    {
+                        tracing::error!(
+                            "Failed to sync pending documents after reconnection: {}",
+                            e
+                        );
+                    } else {
+                        tracing::info!("✅ Pending documents sync completed after reconnection");
 
-                    // NOW request full sync after uploads are complete
-                    tracing::info!(
-                        "🔄 CLIENT {}: Requesting full sync to get missed updates",
-                        client_id
-                    );
-                    if let Some(client) = ws_client_for_reconnect_sync.lock().await.as_ref() {
-                        if let Err(e) = client.send(ClientMessage::RequestFullSync).await {
-                            tracing::error!(
-                                "CLIENT {}: Failed to request full sync after reconnection: {}",
-                                client_id,
-                                e
-                            );
-                        } else {
-                            tracing::info!(
-                                "✅ CLIENT {}: RequestFullSync sent after pending uploads complete",
-                                client_id
-                            );
+                        // NOW request full sync after uploads are complete
+                        tracing::info!("🔄 Requesting full sync to get missed updates");
+                        if let Some(client) = ws_client_for_reconnect_sync.lock().await.as_ref() {
+                            if let Err(e) = client.send(ClientMessage::RequestFullSync).await {
+                                tracing::error!(
+                                    "Failed to request full sync after reconnection: {}",
+                                    e
+                                );
+                            } else {
+                                tracing::info!(
+                                    "✅ RequestFullSync sent after pending uploads complete"
+                                );
+                            }
                         }
                     }
+
+                    if let Err(e) = Self::resume_blob_uploads(
+                        &db_for_reconnect_sync,
+                        &ws_client_for_reconnect_sync,
+                    )
+                    .await
+                    {
+                        tracing::error!("Failed to resume blob uploads after reconnection: {}", e);
+                    }
                 }
+
+                tracing::warn!("Reconnection sync handler terminated");
             }
+            .instrument(tracing::info_span!("reconnect_sync", %client_id)),
+        );
 
-            tracing::warn!("CLIENT {}: Reconnection sync handler terminated", client_id);
-        });
+        self.background_tasks
+            .lock()
+            .unwrap()
+            .extend([message_handler, reconnect_sync_handler]);
+        self.spawn_auto_compaction();
 
         // Only perform initial sync if connected
         if self.is_connected.load(Ordering::Relaxed) {
@@ -261,56 +851,43 @@ impl Client {
 
             // Enable protection mode during upload phase
             self.sync_protection_mode.store(true, Ordering::Relaxed);
-            tracing::info!(
-                "CLIENT {}: Protection mode ENABLED - blocking server overwrites during upload",
-                self.client_id
-            );
+            tracing::info!("Protection mode ENABLED - blocking server overwrites during upload");
 
             // First: Upload any pending documents that were created/modified offline
-            tracing::info!(
-                "CLIENT {}: Starting upload-first sync - uploading pending changes",
-                self.client_id
-            );
+            tracing::info!("Starting upload-first sync - uploading pending changes");
             self.sync_pending_documents().await?;
 
             // Wait for upload confirmations with timeout
             if !self.pending_uploads.lock().await.is_empty() {
                 let upload_count = self.pending_uploads.lock().await.len();
-                tracing::info!(
-                    "CLIENT {}: Waiting for {} upload confirmations",
-                    self.client_id,
-                    upload_count
-                );
+                tracing::info!("Waiting for {} upload confirmations", upload_count);
 
                 tokio::select! {
                     _ = self.upload_complete_notifier.notified() => {
-                        tracing::info!("CLIENT {}: All uploads confirmed successfully", self.client_id);
+                        tracing::info!("All uploads confirmed successfully");
                     }
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(10)) => {
+                    _ = runtime::sleep(self.timeouts.upload_confirm) => {
                         let remaining = self.pending_uploads.lock().await.len();
                         if remaining > 0 {
-                            tracing::warn!("CLIENT {}: Upload timeout - {} uploads still pending", self.client_id, remaining);
+                            tracing::warn!("Upload timeout - {} uploads still pending", remaining);
 
                             // Enhanced fallback: Retry failed uploads before proceeding
-                            tracing::info!("CLIENT {}: Retrying failed uploads before sync", self.client_id);
+                            tracing::info!("Retrying failed uploads before sync");
                             if let Err(e) = self.retry_failed_uploads().await {
-                                tracing::error!("CLIENT {}: Retry failed: {}", self.client_id, e);
+                                tracing::error!("Retry failed: {}", e);
                             }
                         } else {
-                            tracing::info!("CLIENT {}: Upload timeout but all uploads completed", self.client_id);
+                            tracing::info!("Upload timeout but all uploads completed");
                         }
                     }
                 }
             } else {
-                tracing::info!("CLIENT {}: No pending uploads to wait for", self.client_id);
+                tracing::info!("No pending uploads to wait for");
             }
 
             // Disable protection mode - now safe to receive server sync
             self.sync_protection_mode.store(false, Ordering::Relaxed);
-            tracing::info!(
-                "CLIENT {}: Protection mode DISABLED - server sync now allowed",
-                self.client_id
-            );
+            tracing::info!("Protection mode DISABLED - server sync now allowed");
 
             // Process any deferred messages that were queued during upload phase
             if let Err(e) = Self::process_deferred_messages(
@@ -318,41 +895,296 @@ impl Client {
                 &self.db,
                 self.client_id,
                 &self.event_dispatcher,
+                &self.ws_client,
             )
             .await
             {
-                tracing::error!(
-                    "CLIENT {}: Error processing deferred messages: {}",
-                    self.client_id,
-                    e
-                );
+                tracing::error!("Error processing deferred messages: {}", e);
+            }
+
+            if let Err(e) = Self::resume_blob_uploads(&self.db, &self.ws_client).await {
+                tracing::warn!("Failed to resume blob uploads: {}", e);
             }
 
             // Second: Download current server state (which now includes our uploaded documents)
-            tracing::info!(
-                "CLIENT {}: Upload phase complete, requesting server state",
-                self.client_id
-            );
+            tracing::info!("Upload phase complete, requesting server state");
             self.sync_all().await?;
         } else {
-            tracing::info!(
-                "CLIENT {}: Starting in offline mode - will sync when connection available",
-                self.client_id
+            tracing::info!("Starting in offline mode - will sync when connection available");
+        }
+
+        Ok(())
+    }
+
+    /// Reject invalid content locally before it is saved or queued for sync.
+    ///
+    /// The validator runs in [`Client::create_document`] and
+    /// [`Client::update_document`]; an `Err` fails the call with
+    /// `SyncError::Validation` and nothing is written.
+    pub fn set_validator(&self, validator: ContentValidator) {
+        *self.validator.write().unwrap() = Some(validator);
+    }
+
+    fn validate_content(&self, content: &serde_json::Value) -> Result<(), String> {
+        match self.validator.read().unwrap().as_ref() {
+            Some(validator) => validator(content),
+            None => Ok(()),
+        }
+    }
+
+    // Refuse content over the size limit before it is saved or queued, and
+    // warn when it gets close
+    fn check_content_size(&self, id: &Uuid, content: &serde_json::Value) -> SyncResult<()> {
+        let size = serde_json::to_string(content)?.len();
+        if size > self.max_document_bytes {
+            tracing::warn!("Refusing {} byte content for document {}", size, id);
+            return Err(SyncError::DocumentTooLarge {
+                size,
+                limit: self.max_document_bytes,
+            });
+        }
+        if size > self.document_size_warning_bytes {
+            tracing::warn!("Document {} content is {} bytes", id, size);
+            self.event_dispatcher.emit_document_size_warning(
+                id,
+                size,
+                self.document_size_warning_bytes,
             );
         }
+        Ok(())
+    }
+
+    /// Upgrade content from schema version `from_version` to `to_version`.
+    ///
+    /// Documents are migrated lazily when read through
+    /// [`Client::get_document`], [`Client::get_all_documents`] and friends:
+    /// migrations are chained until none applies, [`SCHEMA_VERSION_KEY`] is
+    /// set to the final version and the result is saved and synced like any
+    /// other edit. New content should carry the current version itself.
+    pub fn register_content_migration<F>(
+        &self,
+        from_version: u32,
+        to_version: u32,
+        migrate: F,
+    ) -> SyncResult<()>
+    where
+        F: Fn(&mut serde_json::Value) + Send + Sync + 'static,
+    {
+        if to_version <= from_version {
+            return Err(SyncError::InvalidOperation(format!(
+                "Content migration must move forward, not from {} to {}",
+                from_version, to_version
+            )));
+        }
+        self.content_migrations
+            .write()
+            .unwrap()
+            .insert(from_version, (to_version, Box::new(migrate)));
+        Ok(())
+    }
+
+    /// Apply the registered migrations to `content`, returning whether any ran
+    fn migrate_content(&self, content: &mut serde_json::Value) -> bool {
+        let migrations = self.content_migrations.read().unwrap();
+        if migrations.is_empty() || !content.is_object() {
+            return false;
+        }
+
+        let mut version = content
+            .get(SCHEMA_VERSION_KEY)
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u32;
+        let mut migrated = false;
+        while let Some((to_version, migrate)) = migrations.get(&version) {
+            migrate(content);
+            version = *to_version;
+            migrated = true;
+        }
+        if migrated {
+            if let Some(object) = content.as_object_mut() {
+                object.insert(SCHEMA_VERSION_KEY.to_string(), version.into());
+            }
+        }
+        migrated
+    }
+
+    /// Bring a freshly read document up to the current schema, persisting
+    /// the result.
+    ///
+    /// The migrated content is saved and queued directly rather than through
+    /// [`Client::update_document`]: a read isn't an edit, so it never trips the
+    /// edit window, queue cap or validation, and waits for the next sync
+    /// instead of uploading right away. If saving fails the migrated copy is
+    /// still returned, and the migration runs again on the next read.
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %doc.id))]
+    async fn migrate_document(&self, mut doc: Document) -> Document {
+        let old_content = doc.content.clone();
+        if doc.deleted_at.is_some() || !self.migrate_content(&mut doc.content) {
+            return doc;
+        }
+
+        tracing::info!(
+            "Migrated content of document {} to schema version {}",
+            doc.id,
+            doc.content[SCHEMA_VERSION_KEY]
+        );
+        doc.content_hash = None;
+        match self.save_migrated_document(&doc, &old_content).await {
+            Ok(()) => doc.sync_status = SyncStatus::Pending,
+            Err(e) => tracing::warn!("Failed to save migrated document {}: {}", doc.id, e),
+        }
+        doc
+    }
+
+    async fn save_migrated_document(
+        &self,
+        doc: &Document,
+        old_content: &serde_json::Value,
+    ) -> SyncResult<()> {
+        let (patch, old_content_hash) =
+            outgoing_patch(self.cipher.as_deref(), old_content, &doc.content)?;
 
+        use replicant_core::protocol::ChangeEventType;
+        self.db
+            .save_document_and_queue_patch(
+                doc,
+                &patch,
+                ChangeEventType::Update,
+                Some(old_content_hash),
+            )
+            .await?;
         Ok(())
     }
 
     pub async fn create_document(&self, content: serde_json::Value) -> SyncResult<Document> {
-        self.create_document_with_id(Uuid::new_v4(), content).await
+        self.create_document_with_id(Uuid::new_v4(), content, false)
+            .await
+    }
+
+    /// Create a document that expires `ttl` from now, e.g. for share links.
+    ///
+    /// Once expired it no longer appears in [`Client::get_all_documents`],
+    /// and the server deletes it on its next expiry sweep.
+    pub async fn create_document_with_ttl(
+        &self,
+        content: serde_json::Value,
+        ttl: Duration,
+    ) -> SyncResult<Document> {
+        let ttl = chrono::Duration::from_std(ttl)
+            .map_err(|e| SyncError::Validation(format!("Invalid TTL: {}", e)))?;
+        self.insert_new_document(
+            Uuid::new_v4(),
+            content,
+            false,
+            Some(chrono::Utc::now() + ttl),
+        )
+        .await
+    }
+
+    /// Create or update the document identified by a natural key in its
+    /// content, so importing the same record twice leaves a single document.
+    ///
+    /// `key_path` is a `$.field` path (nested as `$.a.b`) whose value must be
+    /// present and not null. The ID is a UUID v5 of the path and the key's
+    /// JSON value, namespaced by user: the same key from the same user always
+    /// maps to the same document, while `"7"` and `7` are different keys. If
+    /// the document already exists its content is replaced with `content`. A
+    /// deleted one is not revived, and this fails with
+    /// `SyncError::InvalidOperation`.
+    pub async fn create_document_deduped(
+        &self,
+        content: serde_json::Value,
+        key_path: &str,
+    ) -> SyncResult<Document> {
+        let id = self.deduped_document_id(&content, key_path)?;
+
+        match self.db.get_document(&id).await.ok() {
+            Some(existing) if existing.deleted_at.is_some() => Err(SyncError::InvalidOperation(
+                format!("Document {} for this key has been deleted", id),
+            )),
+            Some(_) => {
+                self.update_document(id, content).await?;
+                self.db.get_document(&id).await
+            }
+            None => self.create_document_with_id(id, content, false).await,
+        }
+    }
+
+    fn deduped_document_id(&self, content: &serde_json::Value, key_path: &str) -> SyncResult<Uuid> {
+        let pointer = key_path
+            .strip_prefix("$.")
+            .map(|path| format!("/{}", path.replace('.', "/")))
+            .ok_or_else(|| {
+                SyncError::Validation(format!("Key path must start with \"$.\": {}", key_path))
+            })?;
+        let key = content
+            .pointer(&pointer)
+            .filter(|value| !value.is_null())
+            .ok_or_else(|| {
+                SyncError::Validation(format!("Content has no value at {}", key_path))
+            })?;
+
+        let name = format!("{}={}", key_path, key);
+        Ok(Uuid::new_v5(&self.user_id, name.as_bytes()))
+    }
+
+    /// Create a document whose content is `value` serialized to JSON
+    pub async fn create_typed<T: Serialize>(&self, value: &T) -> SyncResult<Document> {
+        self.create_document(serde_json::to_value(value)?).await
+    }
+
+    /// Load a document and deserialize its content into `T`.
+    ///
+    /// Fails with `SyncError::Deserialize` if the content doesn't match `T`.
+    pub async fn get_typed<T: DeserializeOwned>(&self, id: Uuid) -> SyncResult<T> {
+        self.get_document(id).await?.parse()
     }
 
+    /// Load a document, applying any pending content migrations
+    pub async fn get_document(&self, id: Uuid) -> SyncResult<Document> {
+        let doc = self.db.get_document(&id).await?;
+        Ok(self.migrate_document(doc).await)
+    }
+
+    /// Create a document with a caller-chosen ID.
+    ///
+    /// A `local_only` document stays on this device: its changes are never
+    /// queued or sent to the server, though it is still returned by
+    /// [`Client::get_all_documents`]. [`Client::promote_to_synced`] starts
+    /// syncing it later.
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
     pub async fn create_document_with_id(
         &self,
         id: Uuid,
         content: serde_json::Value,
+        local_only: bool,
+    ) -> SyncResult<Document> {
+        self.insert_new_document(id, content, local_only, None)
+            .await
+    }
+
+    async fn insert_new_document(
+        &self,
+        id: Uuid,
+        content: serde_json::Value,
+        local_only: bool,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
     ) -> SyncResult<Document> {
+        self.validate_content(&content)
+            .map_err(SyncError::Validation)?;
+        self.check_content_size(&id, &content)?;
+        if !local_only {
+            self.ensure_edit_window().await?;
+            self.ensure_queue_capacity().await?;
+        }
+        // Recreating a locally deleted document starts it over: it is
+        // uploaded as a create and edits queued for the old one are dropped
+        if let Ok(existing) = self.db.get_document(&id).await {
+            if existing.deleted_at.is_some() {
+                self.db.remove_from_sync_queue(&id).await?;
+            }
+        }
+
         let doc = Document {
             id,
             user_id: self.user_id,
@@ -363,24 +1195,26 @@ impl Client {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at,
+            sync_status: SyncStatus::Pending,
         };
 
-        tracing::info!(
-            "CLIENT {}: Creating document locally: {}",
-            self.client_id,
-            doc.id
-        );
-        self.db
-            .save_document_with_status(&doc, Some(SyncStatus::Pending))
-            .await?;
+        tracing::info!("Creating document locally: {}", doc.id);
+        if local_only {
+            self.db.save_local_document(&doc).await?;
+        } else {
+            self.db
+                .save_document_with_status(&doc, Some(SyncStatus::Pending))
+                .await?;
+        }
 
         self.event_dispatcher
-            .emit_document_created(&doc.id, &doc.content);
+            .emit_document_created(&doc.id, &doc.content, DocumentSource::Local);
+        self.stats.local_create();
 
         if let Err(e) = self.try_immediate_sync(&doc).await {
             tracing::warn!(
-                "CLIENT {}: Failed to immediately sync new document {}: {}. Will retry later.",
-                self.client_id,
+                "Failed to immediately sync new document {}: {}. Will retry later.",
                 doc.id,
                 e
             );
@@ -389,26 +1223,39 @@ impl Client {
         Ok(doc)
     }
 
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
     pub async fn update_document(
         &self,
         id: Uuid,
         new_content: serde_json::Value,
     ) -> SyncResult<()> {
+        self.validate_content(&new_content)
+            .map_err(SyncError::Validation)?;
+        self.check_content_size(&id, &new_content)?;
+
         let mut doc = self.db.get_document(&id).await?;
         let old_content = doc.content.clone();
         let old_version = doc.sync_revision;
 
-        tracing::info!("CLIENT {}: 📝 UPDATING DOCUMENT {}", self.client_id, id);
-        tracing::info!(
-            "CLIENT {}: OLD: content={:?}, version={}",
-            self.client_id,
-            old_content,
-            old_version
-        );
-        tracing::info!("CLIENT {}: NEW: content={:?}", self.client_id, new_content);
-
-        // Create patch for sync
-        let patch = create_patch(&old_content, &new_content)?;
+        // Nothing changed - don't queue an empty patch or emit an update
+        if calculate_checksum(&old_content) == calculate_checksum(&new_content) {
+            tracing::debug!("Skipping no-op update for document {}", id);
+            return Ok(());
+        }
+        self.ensure_edit_window().await?;
+        self.ensure_queue_capacity().await?;
+
+        tracing::debug!(sync_revision = old_version, "Updating document");
+
+        // Create patch for sync, along with the hash of the old content for
+        // optimistic locking (both over the sealed content when encrypting)
+        let (patch, old_content_hash) =
+            outgoing_patch(self.cipher.as_deref(), &old_content, &new_content)?;
+        // The sealed patch means nothing to the app, so diff the plaintext
+        let changes = match self.cipher {
+            Some(_) => create_patch(&old_content, &new_content)?,
+            None => patch.clone(),
+        };
 
         // Update document
         doc.content = new_content.clone();
@@ -417,25 +1264,77 @@ impl Client {
         doc.content_hash = None; // Will be recalculated
         doc.updated_at = chrono::Utc::now();
 
-        tracing::info!(
-            "CLIENT {}: 💾 SAVING LOCALLY: version={}, marking as pending",
-            self.client_id,
-            doc.sync_revision
-        );
-
         // CRITICAL: Atomically save document and queue patch
         // This prevents data loss if app crashes between operations
-        use replicant_core::patches::calculate_checksum;
         use replicant_core::protocol::ChangeEventType;
 
-        // Calculate hash of old content for optimistic locking
-        let old_content_hash = calculate_checksum(&old_content);
+        self.db
+            .save_document_and_queue_patch(
+                &doc,
+                &patch,
+                ChangeEventType::Update,
+                Some(old_content_hash),
+            )
+            .await?;
+        tracing::debug!("Saved document and queued patch");
 
-        tracing::info!(
-            "CLIENT {}: 📋 Atomically saving document and queueing patch for doc {}",
-            self.client_id,
-            doc.id
+        // Emit event
+        self.event_dispatcher.emit_document_updated_with_patch(
+            &doc.id,
+            &doc.content,
+            &changes,
+            DocumentSource::Local,
         );
+        self.stats.local_update();
+
+        // Attempt immediate sync if connected
+        if let Err(e) = self.try_immediate_sync(&doc).await {
+            // Document stays in "pending" status for next sync attempt
+            tracing::warn!(
+                "Failed to immediately sync updated document {}: {}. Changes saved locally for later sync.",
+                doc.id,
+                e
+            );
+        } else {
+            tracing::debug!("Sent update for immediate sync");
+        }
+
+        Ok(())
+    }
+
+    /// The patch [`Client::update_document`] would generate for `new_content`,
+    /// without saving or queueing anything.
+    pub async fn preview_update(
+        &self,
+        id: Uuid,
+        new_content: &serde_json::Value,
+    ) -> SyncResult<json_patch::Patch> {
+        let doc = self.db.get_document(&id).await?;
+        create_patch(&doc.content, new_content)
+    }
+
+    /// Mark a document as changed without changing its content.
+    ///
+    /// `updated_at` moves to now and an empty update is queued, which the
+    /// server applies like any other: it bumps its own timestamp and tells
+    /// the user's other clients. Unlike an [`Client::update_document`] with
+    /// unchanged content, this is never skipped, and it is reported like one
+    /// that changed: a `DocumentUpdated` event with an empty patch, counted
+    /// in [`Client::stats`].
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
+    pub async fn touch_document(&self, id: Uuid) -> SyncResult<()> {
+        let mut doc = self.db.get_document(&id).await?;
+        if doc.deleted_at.is_some() {
+            return Err(SyncError::DocumentNotFound(id));
+        }
+        self.ensure_edit_window().await?;
+        self.ensure_queue_capacity().await?;
+
+        let (patch, old_content_hash) =
+            outgoing_patch(self.cipher.as_deref(), &doc.content, &doc.content)?;
+        doc.updated_at = chrono::Utc::now();
+
+        use replicant_core::protocol::ChangeEventType;
         self.db
             .save_document_and_queue_patch(
                 &doc,
@@ -444,402 +1343,1012 @@ impl Client {
                 Some(old_content_hash),
             )
             .await?;
-        tracing::info!(
-            "CLIENT {}: ✅ Successfully saved document and queued patch atomically",
-            self.client_id
-        );
 
-        // Verify it was saved correctly and check its sync status
-        let saved_doc = self.db.get_document(&id).await?;
-        tracing::info!(
-            "CLIENT {}: ✅ SAVED: content={:?}, version={}",
-            self.client_id,
-            saved_doc.content,
-            saved_doc.sync_revision
+        self.event_dispatcher.emit_document_updated_with_patch(
+            &doc.id,
+            &doc.content,
+            &json_patch::Patch(Vec::new()),
+            DocumentSource::Local,
         );
+        self.stats.local_update();
 
-        // Check sync status after save
-        let sync_status_result = sqlx::query("SELECT sync_status FROM documents WHERE id = ?")
-            .bind(id.to_string())
-            .fetch_one(&self.db.pool)
-            .await;
-
-        match sync_status_result {
-            Ok(row) => {
-                let sync_status: String = row
-                    .try_get("sync_status")
-                    .unwrap_or_else(|_| "unknown".to_string());
-                tracing::info!(
-                    "CLIENT {}: 📊 Document {} sync_status after save: {}",
-                    self.client_id,
-                    id,
-                    sync_status
-                );
-            }
-            Err(e) => {
-                tracing::error!(
-                    "CLIENT {}: Failed to check sync_status: {}",
-                    self.client_id,
-                    e
-                );
-            }
+        if let Err(e) = self.try_immediate_sync(&doc).await {
+            tracing::warn!(
+                "Failed to immediately sync touched document {}: {}. Will retry later.",
+                id,
+                e
+            );
         }
+        Ok(())
+    }
 
-        // Emit event
-        self.event_dispatcher
-            .emit_document_updated(&doc.id, &doc.content);
+    /// Apply a JSON Patch to a document's current content and queue exactly
+    /// that patch for sync, without diffing old and new content.
+    ///
+    /// Fails without changing anything if the patch doesn't apply cleanly.
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
+    pub async fn patch_document(&self, id: Uuid, patch: json_patch::Patch) -> SyncResult<Document> {
+        let mut doc = self.db.get_document(&id).await?;
+        let old_content = doc.content.clone();
 
-        // Attempt immediate sync if connected
-        tracing::info!(
-            "CLIENT {}: 🚀 Attempting immediate sync for updated document {}",
-            self.client_id,
-            doc.id
+        let mut new_content = old_content.clone();
+        apply_patch(&mut new_content, &patch)?;
+        self.validate_content(&new_content)
+            .map_err(SyncError::Validation)?;
+        self.check_content_size(&id, &new_content)?;
+        self.ensure_edit_window().await?;
+        self.ensure_queue_capacity().await?;
+        let changes = patch.clone();
+
+        let (patch, old_content_hash) = match self.cipher.as_deref() {
+            // Encrypted content can only be replaced whole
+            Some(cipher) => outgoing_patch(Some(cipher), &old_content, &new_content)?,
+            None => (patch, calculate_checksum(&old_content)),
+        };
+
+        doc.content = new_content;
+        doc.content_hash = None;
+        doc.updated_at = chrono::Utc::now();
+
+        use replicant_core::protocol::ChangeEventType;
+        self.db
+            .save_document_and_queue_patch(
+                &doc,
+                &patch,
+                ChangeEventType::Update,
+                Some(old_content_hash),
+            )
+            .await?;
+
+        self.event_dispatcher.emit_document_updated_with_patch(
+            &doc.id,
+            &doc.content,
+            &changes,
+            DocumentSource::Local,
         );
+        self.stats.local_update();
+
         if let Err(e) = self.try_immediate_sync(&doc).await {
-            tracing::warn!("CLIENT {}: ⚠️  OFFLINE EDIT - Failed to immediately sync updated document {}: {}. Changes saved locally for later sync.", 
-                         self.client_id, doc.id, e);
-            // Document stays in "pending" status for next sync attempt
+            tracing::warn!(
+                "Failed to immediately sync patched document {}: {}. Changes saved locally for later sync.",
+                doc.id,
+                e
+            );
+        }
 
-            // Double-check sync status after failed immediate sync
-            let sync_status_result = sqlx::query("SELECT sync_status FROM documents WHERE id = ?")
-                .bind(id.to_string())
-                .fetch_one(&self.db.pool)
-                .await;
-
-            match sync_status_result {
-                Ok(row) => {
-                    let sync_status: String = row
-                        .try_get("sync_status")
-                        .unwrap_or_else(|_| "unknown".to_string());
-                    tracing::warn!(
-                        "CLIENT {}: 📊 Document {} sync_status after FAILED immediate sync: {}",
-                        self.client_id,
-                        id,
-                        sync_status
-                    );
-                }
-                Err(e) => {
-                    tracing::error!(
-                        "CLIENT {}: Failed to check sync_status after failed sync: {}",
-                        self.client_id,
-                        e
-                    );
-                }
+        Ok(doc)
+    }
+
+    /// Both sides of the document's unresolved conflict, if it has one
+    pub async fn get_conflict(&self, id: Uuid) -> SyncResult<Option<ConflictRecord>> {
+        self.db.get_conflict(&id).await
+    }
+
+    /// Settle a conflict with `resolved_content`, which replaces the server's
+    /// version and is broadcast to the user's other clients. Clears the
+    /// document's `conflict` status and its stored conflict record.
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
+    pub async fn resolve_conflict(
+        &self,
+        id: Uuid,
+        resolved_content: serde_json::Value,
+    ) -> SyncResult<()> {
+        self.validate_content(&resolved_content)
+            .map_err(SyncError::Validation)?;
+        self.check_content_size(&id, &resolved_content)?;
+
+        let mut doc = self.db.get_document(&id).await?;
+
+        // Also queued as a regular update against the current content, so the
+        // resolution still syncs if it can't be sent now
+        let (patch, old_content_hash) =
+            outgoing_patch(self.cipher.as_deref(), &doc.content, &resolved_content)?;
+        doc.content = resolved_content;
+        doc.content_hash = None;
+        doc.updated_at = chrono::Utc::now();
+
+        use replicant_core::protocol::ChangeEventType;
+        self.db.remove_from_sync_queue(&id).await?;
+        self.db
+            .save_document_and_queue_patch(
+                &doc,
+                &patch,
+                ChangeEventType::Update,
+                Some(old_content_hash),
+            )
+            .await?;
+        self.db.delete_conflict(&id).await?;
+
+        self.event_dispatcher
+            .emit_document_updated(&doc.id, &doc.content, DocumentSource::Local);
+        self.stats.local_update();
+
+        if self.is_sync_paused() {
+            return Ok(());
+        }
+
+        let content = outgoing_document(self.cipher.as_deref(), &doc)?.content;
+        let ws_client = self.ws_client.lock().await;
+        if let Some(client) = ws_client.as_ref() {
+            let upload = PendingUpload::start(UploadType::Update, &self.db, &id).await?;
+            self.pending_uploads.lock().await.insert(id, upload);
+            if let Err(e) = client
+                .send(ClientMessage::ResolveConflict {
+                    document_id: id,
+                    content,
+                })
+                .await
+            {
+                self.pending_uploads.lock().await.remove(&id);
+                tracing::warn!(
+                    "Failed to send conflict resolution for {}: {}. Will sync later.",
+                    id,
+                    e
+                );
             }
-        } else {
-            tracing::info!(
-                "CLIENT {}: ✅ Immediate sync successful for document {}",
-                self.client_id,
-                doc.id
-            );
         }
 
         Ok(())
     }
 
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
     pub async fn delete_document(&self, id: Uuid) -> SyncResult<()> {
+        self.ensure_edit_window().await?;
+        self.ensure_queue_capacity().await?;
+
         // Mark as deleted locally first
         self.db.delete_document(&id).await?;
 
         // Emit event
         self.event_dispatcher.emit_document_deleted(&id);
+        self.stats.local_delete();
+
+        if self.db.is_local_only(&id).await? || self.is_sync_paused() {
+            return Ok(());
+        }
 
         // Try to send delete to server if connected
         let ws_client = self.ws_client.lock().await;
         if let Some(client) = ws_client.as_ref() {
             if let Err(e) = client
-                .send(ClientMessage::DeleteDocument { document_id: id })
+                .send(ClientMessage::DeleteDocument {
+                    document_id: id,
+                    idempotency_key: Some(self.db.idempotency_key(&id).await?),
+                })
                 .await
             {
-                tracing::warn!(
-                    "CLIENT {}: Failed to send delete to server: {}. Will sync later.",
-                    self.client_id,
-                    e
-                );
+                tracing::warn!("Failed to send delete to server: {}. Will sync later.", e);
                 self.is_connected.store(false, Ordering::Relaxed);
-                self.event_dispatcher.emit_connection_lost(&self.server_url);
+                self.event_dispatcher
+                    .emit_connection_lost(&self.server_url(), DisconnectReason::SendFailed);
                 drop(ws_client);
                 self.start_reconnection_loop();
             }
         } else {
-            tracing::info!(
-                "CLIENT {}: Offline - delete will sync when connection available",
-                self.client_id
-            );
+            tracing::info!("Offline - delete will sync when connection available");
         }
 
         Ok(())
     }
 
+    /// Create, update and delete several documents together, or not at all.
+    ///
+    /// `f` records the changes on a [`Tx`] and its return value is passed
+    /// back, e.g. the IDs of created documents. The changes are written
+    /// locally in one step and sent to the server as a single
+    /// `ClientMessage::Transaction`, which it applies in one database
+    /// transaction. Requires a connection: if the server rejects any change,
+    /// or doesn't answer in time, every local change is rolled back and this
+    /// fails with `ServerError::ServerSync` or `SyncError::NetworkError`.
+    ///
+    /// Updates must be based on the server's current content, so a
+    /// transaction touching a document with unsynced edits is rejected.
+    pub async fn transaction<F, R>(&self, f: F) -> SyncResult<R>
+    where
+        F: FnOnce(&mut Tx) -> R,
+    {
+        let mut tx = Tx::default();
+        let output = f(&mut tx);
+        if tx.ops.is_empty() {
+            return Ok(output);
+        }
+
+        // Check every change and build both sides of it before writing anything
+        let now = chrono::Utc::now();
+        let mut seen = HashSet::new();
+        let mut ops = Vec::with_capacity(tx.ops.len());
+        let mut changed = Vec::with_capacity(tx.ops.len());
+        let mut originals = Vec::new();
+        let mut created = Vec::new();
+        for op in tx.ops {
+            let id = op.document_id();
+            if !seen.insert(id) {
+                return Err(SyncError::InvalidOperation(format!(
+                    "Document {} is changed more than once in the transaction",
+                    id
+                )));
+            }
+            if !matches!(op, TxOp::Create { .. }) && self.db.is_local_only(&id).await? {
+                return Err(SyncError::InvalidOperation(format!(
+                    "Local-only document {} can't be part of a transaction",
+                    id
+                )));
+            }
+            match op {
+                TxOp::Create { id, content } => {
+                    self.validate_content(&content)
+                        .map_err(SyncError::Validation)?;
+                    self.check_content_size(&id, &content)?;
+                    let doc = Document {
+                        id,
+                        user_id: self.user_id,
+                        content,
+                        sync_revision: 1,
+                        content_hash: None,
+                        title: None,
+                        created_at: now,
+                        updated_at: now,
+                        deleted_at: None,
+                        expires_at: None,
+                        sync_status: SyncStatus::Pending,
+                    };
+                    ops.push(TransactionOp::Create {
+                        document: outgoing_document(self.cipher.as_deref(), &doc)?,
+                    });
+                    created.push(id);
+                    changed.push(doc);
+                }
+                TxOp::Update { id, content } => {
+                    self.validate_content(&content)
+                        .map_err(SyncError::Validation)?;
+                    self.check_content_size(&id, &content)?;
+                    let mut doc = self.db.get_document(&id).await?;
+                    let (patch, content_hash) =
+                        outgoing_patch(self.cipher.as_deref(), &doc.content, &content)?;
+                    ops.push(TransactionOp::Update {
+                        patch: DocumentPatch {
+                            document_id: id,
+                            patch,
+                            content_hash,
+                        },
+                    });
+                    originals.push(doc.clone());
+                    doc.content = content;
+                    doc.updated_at = now;
+                    doc.sync_status = SyncStatus::Pending;
+                    changed.push(doc);
+                }
+                TxOp::Delete { id } => {
+                    let mut doc = self.db.get_document(&id).await?;
+                    ops.push(TransactionOp::Delete { document_id: id });
+                    originals.push(doc.clone());
+                    doc.deleted_at = Some(now);
+                    doc.sync_status = SyncStatus::Pending;
+                    changed.push(doc);
+                }
+            }
+        }
+
+        let transaction_id = Uuid::new_v4();
+        let (waiter_tx, mut waiter_rx) = oneshot::channel();
+        {
+            let ws_client = self.ws_client.lock().await;
+            let client = match ws_client.as_ref() {
+                Some(client) if !self.is_sync_paused() => client,
+                _ => return Err(ClientError::Closed.into()),
+            };
+
+            self.db.save_documents_atomically(&changed, &[]).await?;
+            self.transaction_waiters
+                .lock()
+                .await
+                .insert(transaction_id, waiter_tx);
+
+            if let Err(e) = client
+                .send(ClientMessage::Transaction {
+                    transaction_id,
+                    ops,
+                })
+                .await
+            {
+                self.transaction_waiters
+                    .lock()
+                    .await
+                    .remove(&transaction_id);
+                self.db
+                    .save_documents_atomically(&originals, &created)
+                    .await?;
+                return Err(e);
+            }
+        }
+
+        let outcome = match runtime::timeout(self.timeouts.upload_confirm, &mut waiter_rx).await {
+            Ok(outcome) => outcome.ok(),
+            // Unless the response is being applied right now, give up on it
+            Err(_) => match self
+                .transaction_waiters
+                .lock()
+                .await
+                .remove(&transaction_id)
+            {
+                Some(_) => None,
+                None => waiter_rx.await.ok(),
+            },
+        };
+
+        match outcome {
+            Some(Ok(())) => {
+                for doc in &changed {
+                    if doc.deleted_at.is_some() {
+                        self.event_dispatcher.emit_document_deleted(&doc.id);
+                        self.stats.local_delete();
+                    } else if created.contains(&doc.id) {
+                        self.event_dispatcher.emit_document_created(
+                            &doc.id,
+                            &doc.content,
+                            DocumentSource::Local,
+                        );
+                        self.stats.local_create();
+                    } else {
+                        self.event_dispatcher.emit_document_updated(
+                            &doc.id,
+                            &doc.content,
+                            DocumentSource::Local,
+                        );
+                        self.stats.local_update();
+                    }
+                }
+                Ok(output)
+            }
+            Some(Err(reason)) => {
+                self.db
+                    .save_documents_atomically(&originals, &created)
+                    .await?;
+                Err(ServerError::ServerSync(reason).into())
+            }
+            None => {
+                self.db
+                    .save_documents_atomically(&originals, &created)
+                    .await?;
+                Err(SyncError::NetworkError(format!(
+                    "No response to transaction {}",
+                    transaction_id
+                )))
+            }
+        }
+    }
+
+    /// Hand a document to another user.
+    ///
+    /// Requires a connection. Once the server applies the transfer the
+    /// document disappears from this user's clients, including this one, and
+    /// appears on the new owner's. If the transfer is refused, for example
+    /// because `new_user_id` doesn't exist, a sync error event is emitted and
+    /// the document stays put.
+    pub async fn transfer_document(&self, id: Uuid, new_user_id: Uuid) -> SyncResult<()> {
+        let ws_client = self.ws_client.lock().await;
+        match ws_client.as_ref() {
+            Some(client) => {
+                client
+                    .send(ClientMessage::TransferOwnership {
+                        document_id: id,
+                        new_user_id,
+                    })
+                    .await
+            }
+            None => Err(ClientError::Closed.into()),
+        }
+    }
+
+    /// Give another user access to one of this user's documents.
+    ///
+    /// Requires a connection and a server with document sharing enabled. The
+    /// document appears on the other user's clients, and with
+    /// `SharePermission::Write` they can update it too. Sharing again replaces
+    /// the earlier permission. Refusals are reported as sync error events.
+    pub async fn share_document(
+        &self,
+        id: Uuid,
+        user_id: Uuid,
+        permission: SharePermission,
+    ) -> SyncResult<()> {
+        let ws_client = self.ws_client.lock().await;
+        match ws_client.as_ref() {
+            Some(client) => {
+                client
+                    .send(ClientMessage::ShareDocument {
+                        document_id: id,
+                        user_id,
+                        permission,
+                    })
+                    .await
+            }
+            None => Err(ClientError::Closed.into()),
+        }
+    }
+
+    /// Whether a document was created local-only and hasn't been promoted
+    pub async fn is_local_only(&self, id: Uuid) -> SyncResult<bool> {
+        self.db.is_local_only(&id).await
+    }
+
+    /// Start syncing a document created with `local_only`. Its current
+    /// content is uploaded as a new document, now if connected or with the
+    /// other pending changes once a connection is available.
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
+    pub async fn promote_to_synced(&self, id: Uuid) -> SyncResult<()> {
+        let doc = self.db.get_document(&id).await?;
+        if doc.deleted_at.is_some() {
+            return Err(SyncError::DocumentNotFound(id));
+        }
+        if !self.db.is_local_only(&id).await? {
+            return Ok(());
+        }
+
+        self.db.set_local_only(&id, false).await?;
+        if let Err(e) = self.try_immediate_sync(&doc).await {
+            tracing::warn!(
+                "Failed to immediately sync promoted document {}: {}. Will retry later.",
+                id,
+                e
+            );
+        }
+        Ok(())
+    }
+
     pub async fn get_all_documents(&self) -> SyncResult<Vec<Document>> {
-        let docs = self.db.get_all_documents().await?;
-        tracing::info!(
-            "CLIENT: get_all_documents() returning {} documents",
-            docs.len()
-        );
+        let mut docs = Vec::new();
+        for doc in self.db.get_all_documents().await? {
+            docs.push(self.migrate_document(doc).await);
+        }
+        tracing::info!("get_all_documents() returning {} documents", docs.len());
         for doc in &docs {
-            tracing::info!(
-                "CLIENT:   - Document: {} (updated: {})",
-                doc.id,
-                doc.updated_at
-            );
+            tracing::info!("- Document: {} (updated: {})", doc.id, doc.updated_at);
+        }
+        Ok(docs)
+    }
+
+    /// Full-text search over the local database, so it works offline.
+    /// Accepts FTS5 query syntax (e.g. `tun*`, `"exact phrase"`).
+    pub async fn local_search(&self, query: &str) -> SyncResult<Vec<Document>> {
+        // A negative LIMIT means no limit in SQLite
+        self.db.search_documents(&self.user_id, query, -1).await
+    }
+
+    /// The user's documents carrying `tag`, read from the local tag index so
+    /// it works offline. Tags come from [`ClientConfig::tag_path`].
+    pub async fn documents_with_tag(&self, tag: &str) -> SyncResult<Vec<Document>> {
+        self.db.documents_with_tag(&self.user_id, tag).await
+    }
+
+    /// One page of documents, for list views that shouldn't load everything.
+    /// Page through with `offset` in steps of `limit`; an empty page means the
+    /// end was reached.
+    pub async fn get_documents_page(
+        &self,
+        offset: usize,
+        limit: usize,
+        order: DocumentOrder,
+    ) -> SyncResult<Vec<Document>> {
+        let mut docs = Vec::new();
+        for doc in self
+            .db
+            .get_documents_page(offset as i64, limit as i64, order)
+            .await?
+        {
+            docs.push(self.migrate_document(doc).await);
+        }
+        Ok(docs)
+    }
+
+    /// Documents updated after `since`, oldest first, e.g. for a recent
+    /// activity view. Deleted documents are included only if
+    /// `include_deleted` is set.
+    pub async fn documents_changed_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        include_deleted: bool,
+    ) -> SyncResult<Vec<Document>> {
+        let mut docs = Vec::new();
+        for doc in self
+            .db
+            .get_documents_changed_since(since, include_deleted)
+            .await?
+        {
+            docs.push(self.migrate_document(doc).await);
         }
         Ok(docs)
     }
 
     pub async fn count_documents(&self) -> SyncResult<usize> {
-        let docs = self.db.get_all_documents().await?;
-        Ok(docs.len())
+        Ok(self.db.count_documents().await? as usize)
+    }
+
+    /// Operations waiting in the sync queue, see [`ClientConfig::max_queue_size`]
+    pub async fn queue_depth(&self) -> SyncResult<usize> {
+        Ok(self.db.queue_depth().await? as usize)
+    }
+
+    // Make room in the sync queue for another change, or refuse it
+    async fn ensure_queue_capacity(&self) -> SyncResult<()> {
+        let Some(limit) = self.max_queue_size else {
+            return Ok(());
+        };
+
+        let mut depth = self.queue_depth().await?;
+        if depth >= limit {
+            let removed = self.db.coalesce_sync_queue().await?;
+            tracing::info!(
+                "Sync queue at its limit of {}, merged away {} entries",
+                limit,
+                removed
+            );
+            depth -= removed as usize;
+        }
+        if depth >= limit {
+            let error = SyncError::QueueFull { depth, limit };
+            self.event_dispatcher.emit_sync_error(&error.to_string());
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    // Changes wait for a sync once the last one is older than the offline
+    // edit window. A database that never synced has nothing to go stale.
+    async fn ensure_edit_window(&self) -> SyncResult<()> {
+        let Some(window) = self.offline_edit_window else {
+            return Ok(());
+        };
+        let Some(since) = self.time_since_last_sync() else {
+            return Ok(());
+        };
+        if since <= window {
+            return Ok(());
+        }
+
+        if self.is_connected()
+            && matches!(
+                self.conflict_resolution,
+                Some(ConflictResolution::ServerWins)
+            )
+        {
+            tracing::info!(
+                "Last sync was {:?} ago, reconciling with the server before accepting changes",
+                since
+            );
+            self.sync_now().await?;
+            return Ok(());
+        }
+
+        let error = SyncError::ReconcileRequired { since, window };
+        self.event_dispatcher.emit_sync_error(&error.to_string());
+        Err(error)
+    }
+
+    pub async fn count_pending_sync(&self) -> SyncResult<usize> {
+        let pending_docs = self.db.get_pending_documents().await?;
+        Ok(pending_docs.len())
+    }
+
+    /// Schema version of the local database
+    pub async fn schema_version(&self) -> SyncResult<i64> {
+        self.db.schema_version().await
+    }
+
+    /// Reconcile the sync queue with document sync status, e.g. after a
+    /// crash left them disagreeing. Leftover queue entries are removed and
+    /// previously uploaded pending documents with nothing queued get an
+    /// empty update, so the next sync doesn't send them as new documents.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn repair(&self) -> SyncResult<RepairReport> {
+        let stale_queue_entries = self.db.remove_stale_queue_entries().await?;
+
+        let mut requeued_documents = Vec::new();
+        for doc in self.db.get_unqueued_pending_documents().await? {
+            let (patch, old_content_hash) =
+                outgoing_patch(self.cipher.as_deref(), &doc.content, &doc.content)?;
+            self.db
+                .replace_queued_patch(&doc, &patch, old_content_hash)
+                .await?;
+            requeued_documents.push(doc.id);
+        }
+
+        let report = RepairReport {
+            stale_queue_entries,
+            requeued_documents,
+        };
+        if !report.is_clean() {
+            tracing::warn!("Repaired sync state: {:?}", report);
+        }
+        Ok(report)
+    }
+
+    /// Shrink the local database: drop queue entries for operations the
+    /// server has confirmed, purge deleted documents older than
+    /// `ClientConfig::history_retention` and `VACUUM` the file.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn compact(&self) -> SyncResult<CompactReport> {
+        let report = self.db.compact(self.history_retention).await?;
+        tracing::info!(
+            "Compacted local database, {} bytes reclaimed: {:?}",
+            report.reclaimed_bytes(),
+            report
+        );
+        Ok(report)
+    }
+
+    // Runs compact on the configured AutoCompact schedule until shutdown
+    fn spawn_auto_compaction(&self) {
+        let check_every = match self.auto_compact {
+            Some(AutoCompact::Every(interval)) if !interval.is_zero() => interval,
+            Some(AutoCompact::AfterChanges(_)) => AUTO_COMPACT_CHECK_INTERVAL,
+            _ => return,
+        };
+        let schedule = self.auto_compact;
+        let db = self.db.clone();
+        let stats = self.stats.clone();
+        let history_retention = self.history_retention;
+        let shutdown_token = self.shutdown_token.clone();
+        let client_id = self.client_id;
+
+        let compactor = runtime::spawn(
+            async move {
+                let mut ticks = Ticker::new(check_every);
+                let mut changes_at_last_compaction = 0;
+                loop {
+                    tokio::select! {
+                        _ = ticks.tick() => {}
+                        _ = shutdown_token.cancelled() => break,
+                    }
+                    let changes = stats.snapshot().document_changes();
+                    if let Some(AutoCompact::AfterChanges(threshold)) = schedule {
+                        if changes - changes_at_last_compaction < threshold {
+                            continue;
+                        }
+                    }
+                    match db.compact(history_retention).await {
+                        Ok(report) => {
+                            changes_at_last_compaction = changes;
+                            tracing::info!(
+                                "Compacted local database, {} bytes reclaimed",
+                                report.reclaimed_bytes()
+                            );
+                        }
+                        Err(e) => tracing::warn!("Automatic compaction failed: {}", e),
+                    }
+                }
+            }
+            .instrument(tracing::info_span!("compactor", %client_id)),
+        );
+        self.background_tasks.lock().unwrap().push(compactor);
     }
 
-    pub async fn count_pending_sync(&self) -> SyncResult<usize> {
-        let pending_docs = self.db.get_pending_documents().await?;
-        Ok(pending_docs.len())
+    /// Serialize every local document, with its sync status and queued
+    /// changes, into a versioned backup that [`Client::import`] can restore.
+    pub async fn export_all(&self) -> SyncResult<Vec<u8>> {
+        DatabaseExport::new(self.db.export_documents().await?).to_bytes()
     }
 
-    async fn sync_pending_documents(&self) -> SyncResult<()> {
-        let pending_docs = self.db.get_pending_documents().await?;
-        // Also check sync_queue for debugging
-        let sync_queue_result = sqlx::query("SELECT COUNT(*) as count FROM sync_queue")
-            .fetch_one(&self.db.pool)
-            .await;
-
-        match sync_queue_result {
-            Ok(row) => {
-                let count: i64 = row.try_get("count").unwrap_or(0);
-                tracing::info!(
-                    "CLIENT {}: 📋 sync_queue contains {} entries",
-                    self.client_id,
-                    count
-                );
-
-                if count > 0 {
-                    // Show what's in the sync_queue
-                    let queue_entries = sqlx::query(
-                        "SELECT document_id, operation_type, created_at FROM sync_queue",
-                    )
-                    .fetch_all(&self.db.pool)
-                    .await;
+    /// Restore a backup made by [`Client::export_all`], returning how many
+    /// documents were written. See [`ImportMode`] for how it combines with
+    /// the existing data. Pending changes are uploaded now if connected.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn import(&self, data: &[u8], mode: ImportMode) -> SyncResult<usize> {
+        let export = DatabaseExport::from_bytes(data)?;
+
+        let documents = match mode {
+            ImportMode::Replace => export.documents,
+            ImportMode::Merge => {
+                let mut taken = Vec::new();
+                for mut imported in export.documents {
+                    let local = self.db.get_document(&imported.document.id).await.ok();
+                    if local
+                        .as_ref()
+                        .is_some_and(|doc| doc.sync_revision >= imported.document.sync_revision)
+                    {
+                        continue;
+                    }
 
-                    match queue_entries {
-                        Ok(rows) => {
-                            for row in rows {
-                                let doc_id: String = row
-                                    .try_get("document_id")
-                                    .unwrap_or_else(|_| "unknown".to_string());
-                                let op_type: String = row
-                                    .try_get("operation_type")
-                                    .unwrap_or_else(|_| "unknown".to_string());
-                                let created_at: String = row
-                                    .try_get("created_at")
-                                    .unwrap_or_else(|_| "unknown".to_string());
-                                tracing::info!("CLIENT {}: 📋 sync_queue entry: doc_id={}, op_type={}, created_at={}", 
-                                             self.client_id, doc_id, op_type, created_at);
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!(
-                                "CLIENT {}: Failed to query sync_queue entries: {}",
-                                self.client_id,
-                                e
-                            );
+                    if !imported.local_only {
+                        // Unconfirmed changes are replayed from the queue. Otherwise
+                        // an empty update checks the content against the server, so
+                        // documents it already has aren't uploaded as new ones.
+                        let never_uploaded = local.is_none()
+                            && imported.sync_status == SyncStatus::Pending
+                            && imported.document.sync_revision == 1;
+                        if imported.queued_operations.is_empty()
+                            && imported.document.deleted_at.is_none()
+                            && !never_uploaded
+                        {
+                            let content = &imported.document.content;
+                            let (patch, old_content_hash) =
+                                outgoing_patch(self.cipher.as_deref(), content, content)?;
+                            imported.queued_operations.push(QueuedOperation {
+                                operation_type: ChangeEventType::Update,
+                                patch: Some(serde_json::to_value(&patch)?),
+                                old_content_hash: Some(old_content_hash),
+                            });
                         }
+                        imported.sync_status = SyncStatus::Pending;
                     }
+                    taken.push(imported);
                 }
+                taken
             }
-            Err(e) => {
-                tracing::error!(
-                    "CLIENT {}: Failed to count sync_queue: {}",
-                    self.client_id,
+        };
+
+        self.db.import_documents(&documents, mode).await?;
+        tracing::info!("Imported {} documents ({:?})", documents.len(), mode);
+
+        if self.is_connected() {
+            if let Err(e) = self.sync_pending_documents().await {
+                tracing::warn!(
+                    "Failed to sync imported documents: {}. Will retry later.",
                     e
                 );
             }
         }
 
+        Ok(documents.len())
+    }
+
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    async fn sync_pending_documents(&self) -> SyncResult<()> {
+        let pending_docs = self.db.get_pending_documents().await?;
+
         if pending_docs.is_empty() {
-            tracing::info!("CLIENT {}: No pending documents to sync", self.client_id);
+            tracing::info!("No pending documents to sync");
             return Ok(());
         }
 
-        tracing::info!(
-            "CLIENT {}: 📤 UPLOADING {} PENDING DOCUMENTS",
-            self.client_id,
-            pending_docs.len()
-        );
+        tracing::info!("📤 UPLOADING {} PENDING DOCUMENTS", pending_docs.len());
 
-        // Show details of each pending document
         for (i, pending_info) in pending_docs.iter().enumerate() {
-            if let Ok(doc) = self.db.get_document(&pending_info.id).await {
-                tracing::info!(
-                    "CLIENT {}: PENDING {}/{}: doc_id={}, content={:?}, version={}",
-                    self.client_id,
-                    i + 1,
-                    pending_docs.len(),
-                    pending_info.id,
-                    doc.content,
-                    doc.sync_revision
-                );
-            }
+            tracing::debug!(
+                document_id = %pending_info.id,
+                "Pending document {}/{}",
+                i + 1,
+                pending_docs.len()
+            );
         }
 
         for pending_info in pending_docs {
+            if !self
+                .loop_guard
+                .admit(pending_info.id, &self.event_dispatcher)
+            {
+                continue;
+            }
             match self.db.get_document(&pending_info.id).await {
                 Ok(doc) => {
-                    let upload_type = if pending_info.is_deleted {
-                        // Handle pending delete
-                        tracing::info!(
-                            "CLIENT {}: Uploading pending delete for doc {}",
-                            self.client_id,
-                            pending_info.id
-                        );
-
-                        // Track this upload
-                        self.pending_uploads.lock().await.insert(
-                            pending_info.id,
-                            PendingUpload {
-                                operation_type: UploadType::Delete,
-                                sent_at: Instant::now(),
-                            },
-                        );
-
-                        let ws_client = self.ws_client.lock().await;
-                        if let Some(client) = ws_client.as_ref() {
-                            client
-                                .send(ClientMessage::DeleteDocument {
-                                    document_id: pending_info.id,
-                                })
-                                .await?;
-                        } else {
-                            return Err(ClientError::WebSocket("Not connected".to_string()))?;
-                        }
-
-                        UploadType::Delete
-                    } else {
-                        // Check if we have a patch stored in sync_queue to determine if this is create or update
-                        // With server-authoritative versioning, we can't rely on version number anymore
-                        match self.db.get_queued_patch(&pending_info.id).await? {
-                            Some((stored_patch, old_hash_opt)) => {
-                                // Have a queued patch = this is an UPDATE
-                                tracing::info!(
-                                    "CLIENT {}: 📋 Found stored patch in sync_queue for doc {} - treating as UPDATE",
-                                    self.client_id,
-                                    pending_info.id
-                                );
-
-                                // Track this upload
-                                self.pending_uploads.lock().await.insert(
-                                    pending_info.id,
-                                    PendingUpload {
-                                        operation_type: UploadType::Update,
-                                        sent_at: Instant::now(),
-                                    },
-                                );
-
-                                // Use the stored patch for UpdateDocument
-                                use replicant_core::models::DocumentPatch;
-                                use replicant_core::patches::calculate_checksum;
-
-                                let content_hash = old_hash_opt
-                                    .unwrap_or_else(|| calculate_checksum(&doc.content));
-
-                                let document_patch = DocumentPatch {
-                                    document_id: pending_info.id,
-                                    patch: stored_patch,
-                                    content_hash,
-                                };
-
-                                let ws_client = self.ws_client.lock().await;
-                                if let Some(client) = ws_client.as_ref() {
-                                    tracing::info!(
-                                        "CLIENT {}: ✅ Sending UpdateDocument with stored patch",
-                                        self.client_id
-                                    );
-                                    client
-                                        .send(ClientMessage::UpdateDocument {
-                                            patch: document_patch,
-                                        })
-                                        .await?;
-                                } else {
-                                    return Err(ClientError::WebSocket(
-                                        "Not connected".to_string(),
-                                    ))?;
-                                }
-
-                                UploadType::Update
-                            }
-                            None => {
-                                // No queued patch = this is a CREATE
-                                tracing::info!(
-                                    "CLIENT {}: No queued patch found for doc {} - treating as CREATE",
-                                    self.client_id,
-                                    pending_info.id
-                                );
-
-                                // Track this upload
-                                self.pending_uploads.lock().await.insert(
-                                    pending_info.id,
-                                    PendingUpload {
-                                        operation_type: UploadType::Create,
-                                        sent_at: Instant::now(),
-                                    },
-                                );
-
-                                let ws_client = self.ws_client.lock().await;
-                                if let Some(client) = ws_client.as_ref() {
-                                    client
-                                        .send(ClientMessage::CreateDocument {
-                                            document: doc.clone(),
-                                        })
-                                        .await?;
-                                } else {
-                                    return Err(ClientError::WebSocket(
-                                        "Not connected".to_string(),
-                                    ))?;
-                                }
-
-                                UploadType::Create
-                            }
-                        }
+                    let Some((upload_type, operation)) = Self::replay_operation(
+                        &self.db,
+                        self.cipher.as_deref(),
+                        &pending_info,
+                        &doc,
+                    )
+                    .await?
+                    else {
+                        continue;
                     };
 
                     tracing::debug!(
-                        "CLIENT {}: Tracked upload for document {} ({:?})",
-                        self.client_id,
+                        "Tracking upload for document {} ({:?})",
                         pending_info.id,
                         upload_type
                     );
+                    let upload =
+                        PendingUpload::start(upload_type, &self.db, &pending_info.id).await?;
+                    self.pending_uploads
+                        .lock()
+                        .await
+                        .insert(pending_info.id, upload);
+
+                    let ws_client = self.ws_client.lock().await;
+                    let Some(client) = ws_client.as_ref() else {
+                        return Err(ClientError::Closed)?;
+                    };
+                    client.send(operation).await?;
                 }
                 Err(e) => {
-                    tracing::error!(
-                        "CLIENT {}: Failed to get pending document {}: {}",
-                        self.client_id,
-                        pending_info.id,
-                        e
-                    );
+                    tracing::error!("Failed to get pending document {}: {}", pending_info.id, e);
                 }
             }
         }
 
         tracing::info!(
-            "CLIENT {}: Upload tracking: {} operations pending confirmation",
-            self.client_id,
+            "Upload tracking: {} operations pending confirmation",
             self.pending_uploads.lock().await.len()
         );
         Ok(())
     }
 
+    /// Take the server's revision and timestamps from a confirmed upload
+    /// that later local edits overtook. The edits up to `sent_seq` leave the
+    /// queue; the later ones stay, with the document pending.
+    async fn confirm_overtaken_upload(
+        msg: &ServerMessage,
+        db: &Arc<dyn DocumentStore>,
+        sent_seq: i64,
+    ) -> SyncResult<()> {
+        let (document_id, sync_revision, created_at, updated_at) = match msg {
+            ServerMessage::DocumentCreatedResponse {
+                document_id,
+                sync_revision,
+                created_at,
+                updated_at,
+                ..
+            } => (document_id, *sync_revision, *created_at, *updated_at),
+            ServerMessage::DocumentUpdatedResponse {
+                document_id,
+                sync_revision,
+                updated_at,
+                ..
+            } => (document_id, *sync_revision, None, *updated_at),
+            _ => return Ok(()),
+        };
+        tracing::info!(
+            "Upload of {} confirmed, keeping local edits made after local_seq {}",
+            document_id,
+            sent_seq
+        );
+        db.set_server_timestamps(document_id, created_at, updated_at)
+            .await?;
+        if let Some(new_revision) = sync_revision {
+            db.update_sync_revision(document_id, new_revision).await?;
+        }
+        db.remove_from_sync_queue_through(document_id, sent_seq)
+            .await
+    }
+
     // Enhanced message handler with upload tracking and protection
+    #[instrument(skip_all, fields(client_id = %ctx.client_id, document_id = msg.document_id().map(field::display)))]
     async fn handle_server_message_with_tracking(
         msg: ServerMessage,
-        db: &Arc<ClientDatabase>,
-        client_id: Uuid,
-        event_dispatcher: &Arc<EventDispatcher>,
-        pending_uploads: &Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
-        upload_complete_notifier: &Arc<Notify>,
-        sync_protection_mode: &Arc<AtomicBool>,
-        deferred_messages: &Arc<Mutex<Vec<ServerMessage>>>,
+        ctx: &MessageContext,
     ) -> SyncResult<()> {
+        let MessageContext {
+            db,
+            client_id,
+            event_dispatcher,
+            pending_uploads,
+            upload_complete_notifier,
+            sync_completion,
+            sync_protection_mode,
+            deferred_messages,
+            lock_waiters,
+            transaction_waiters,
+            blob_downloads,
+            document_waiters,
+            stats,
+            loop_guard,
+            ws_client,
+            cipher,
+        } = ctx;
+        let client_id = *client_id;
+        let cipher = cipher.as_deref();
+
+        // Everything below works on plaintext
+        let msg = open_server_message(cipher, msg)?;
+
+        // The server doesn't echo a client's own changes back to it
+        match &msg {
+            ServerMessage::DocumentCreated { .. } => stats.remote_create(),
+            ServerMessage::DocumentUpdated { .. } => stats.remote_update(),
+            ServerMessage::DocumentDeleted { .. } => stats.remote_delete(),
+            _ => {}
+        }
+        match &msg {
+            ServerMessage::DocumentCreated { document }
+            | ServerMessage::SyncDocument { document, .. } => {
+                loop_guard.server_changed(document.id)
+            }
+            ServerMessage::DocumentUpdated { patch } => {
+                loop_guard.server_changed(patch.document_id)
+            }
+            _ => {}
+        }
+
+        // A document refetch_document asked for replaces the local copy
+        // outright, without deferral or conflict handling
+        if let ServerMessage::SyncDocument { document, .. } = &msg {
+            if let Some(waiter) = document_waiters.lock().await.remove(&document.id) {
+                Self::reset_to_server_copy(document, db, event_dispatcher, pending_uploads).await?;
+                let _ = waiter.send(document.clone());
+                return Ok(());
+            }
+        }
+
         match &msg {
+            ServerMessage::LockResponse {
+                document_id,
+                acquired,
+                holder,
+            } => {
+                if let Some(waiter) = lock_waiters.lock().await.remove(document_id) {
+                    let _ = waiter.send(*acquired);
+                }
+                if *acquired {
+                    event_dispatcher.emit_lock_changed(document_id, holder.as_ref());
+                }
+                Ok(())
+            }
+
+            ServerMessage::BlobUploadAck { .. }
+            | ServerMessage::BlobChunk { .. }
+            | ServerMessage::BlobDeleted { .. } => {
+                Self::handle_blob_message(msg, db, blob_downloads, ws_client).await
+            }
+
+            // A confirmed transaction is stored as synced before the waiting
+            // Client::transaction returns. One it already gave up on and
+            // rolled back is applied here like any other server change.
+            ServerMessage::TransactionResponse {
+                transaction_id,
+                success,
+                error,
+                documents,
+                deleted_ids,
+            } => {
+                let waiter = transaction_waiters.lock().await.remove(transaction_id);
+                if *success {
+                    for document in documents {
+                        let existed = db.get_document(&document.id).await.is_ok();
+                        db.save_document_with_status(document, Some(SyncStatus::Synced))
+                            .await?;
+                        if waiter.is_none() {
+                            if existed {
+                                event_dispatcher.emit_document_updated(
+                                    &document.id,
+                                    &document.content,
+                                    DocumentSource::Local,
+                                );
+                            } else {
+                                event_dispatcher.emit_document_created(
+                                    &document.id,
+                                    &document.content,
+                                    DocumentSource::Local,
+                                );
+                            }
+                        }
+                    }
+                    for document_id in deleted_ids {
+                        db.delete_document(document_id).await?;
+                        db.mark_synced(document_id).await?;
+                        if waiter.is_none() {
+                            event_dispatcher.emit_document_deleted(document_id);
+                        }
+                    }
+                }
+                if let Some(waiter) = waiter {
+                    let outcome = match success {
+                        true => Ok(()),
+                        false => Err(error
+                            .clone()
+                            .unwrap_or_else(|| "Transaction rejected".to_string())),
+                    };
+                    let _ = waiter.send(outcome);
+                }
+                Ok(())
+            }
+
+            // Wake sync_now once the documents sent before this are applied
+            ServerMessage::SyncComplete { synced_count } => {
+                let synced_count = *synced_count;
+                let result =
+                    Self::handle_server_message(msg, db, event_dispatcher, ws_client).await;
+                let now = chrono::Utc::now();
+                *sync_completion.last_sync_at.lock().unwrap() = Some(now);
+                if let Err(e) = db.set_last_sync_at(now).await {
+                    tracing::warn!("Failed to record sync time: {}", e);
+                }
+                sync_completion
+                    .synced_count
+                    .store(synced_count, Ordering::Release);
+                sync_completion.notifier.notify_waiters();
+                result
+            }
+
             // Handle upload confirmations first
             ServerMessage::DocumentCreatedResponse {
                 document_id,
@@ -856,14 +2365,15 @@ impl Client {
                 success,
                 ..
             } => {
+                let mut sent_seq = None;
                 if *success {
                     // Remove from pending uploads
                     let mut uploads = pending_uploads.lock().await;
                     if let Some(upload) = uploads.remove(document_id) {
+                        sent_seq = Some(upload.local_seq);
                         let elapsed = upload.sent_at.elapsed();
                         tracing::info!(
-                            "CLIENT {}: Upload confirmed for {} ({:?}) in {:?}",
-                            client_id,
+                            "Upload confirmed for {} ({:?}) in {:?}",
                             document_id,
                             upload.operation_type,
                             elapsed
@@ -871,10 +2381,7 @@ impl Client {
 
                         // If this was the last pending upload, notify
                         if uploads.is_empty() {
-                            tracing::info!(
-                                "CLIENT {}: All uploads confirmed - notifying completion",
-                                client_id
-                            );
+                            tracing::info!("All uploads confirmed - notifying completion");
                             upload_complete_notifier.notify_one();
                         }
                     }
@@ -888,131 +2395,279 @@ impl Client {
                         db,
                         client_id,
                         event_dispatcher,
+                        ws_client,
                     )
                     .await
                     {
-                        tracing::error!(
-                            "CLIENT {}: Error processing deferred messages after upload: {}",
-                            client_id,
-                            e
-                        );
+                        tracing::error!("Error processing deferred messages after upload: {}", e);
                     }
                 } else {
-                    tracing::error!(
-                        "CLIENT {}: Upload failed for document {}",
-                        client_id,
+                    tracing::error!("Upload failed for document {}", document_id);
+                }
+
+                // Local edits made while the upload was in flight are still
+                // queued - keep them rather than marking the document synced
+                if let Some(sent_seq) = sent_seq {
+                    if !matches!(msg, ServerMessage::DocumentDeletedResponse { .. })
+                        && db.local_seq(document_id).await? > sent_seq
+                    {
+                        return Self::confirm_overtaken_upload(&msg, db, sent_seq).await;
+                    }
+                }
+
+                // Continue with normal processing
+                return Self::handle_server_message(msg, db, event_dispatcher, ws_client).await;
+            }
+
+            // Optimistic lock failure - rebase our pending edit onto the server state
+            ServerMessage::UpdateRejected {
+                document_id,
+                server_document,
+            } => {
+                let already_rebased = matches!(
+                    pending_uploads
+                        .lock()
+                        .await
+                        .get(document_id)
+                        .map(|upload| &upload.operation_type),
+                    Some(UploadType::Rebase)
+                );
+                if already_rebased {
+                    // Only resubmit once - leave the document pending for the next sync
+                    pending_uploads.lock().await.remove(document_id);
+                    tracing::warn!(
+                        "Rebased update for {} was rejected again - leaving pending",
                         document_id
                     );
+                    event_dispatcher
+                        .emit_sync_error(&format!("Update rejected after rebase: {}", document_id));
+                    return Ok(());
                 }
 
-                // Continue with normal processing
-                return Self::handle_server_message(msg, db, client_id, event_dispatcher).await;
+                // Syncs queued while the upload was in flight are superseded by the
+                // server state we are rebasing onto
+                deferred_messages.messages.lock().await.retain(|queued| {
+                    !matches!(queued, ServerMessage::SyncDocument { document, .. }
+                        if document.id == *document_id
+                            && document.sync_revision <= server_document.sync_revision)
+                });
+
+                let Some(patch) =
+                    Self::rebase_rejected_update(db, event_dispatcher, server_document, cipher)
+                        .await?
+                else {
+                    // Nothing left to upload - just take the server state
+                    pending_uploads.lock().await.remove(document_id);
+
+                    if db.get_sync_status(document_id).await? == SyncStatus::Conflict {
+                        let local_doc = db.get_document(document_id).await?;
+                        db.save_conflict(document_id, &local_doc.content, &server_document.content)
+                            .await?;
+                        db.save_document_with_status(server_document, Some(SyncStatus::Conflict))
+                            .await?;
+                        event_dispatcher.emit_document_updated(
+                            &server_document.id,
+                            &server_document.content,
+                            DocumentSource::Server,
+                        );
+                        return Ok(());
+                    }
+
+                    return Self::handle_server_message(
+                        ServerMessage::SyncDocument {
+                            document: server_document.clone(),
+                            relayed: false,
+                        },
+                        db,
+                        event_dispatcher,
+                        ws_client,
+                    )
+                    .await;
+                };
+
+                tracing::info!(
+                    "Resubmitting update for {} rebased on server v{}",
+                    document_id,
+                    server_document.sync_revision
+                );
+                let upload = PendingUpload::start(UploadType::Rebase, db, document_id).await?;
+                pending_uploads.lock().await.insert(*document_id, upload);
+                if let Some(client) = ws_client.lock().await.as_ref() {
+                    client
+                        .send(ClientMessage::UpdateDocument {
+                            patch,
+                            idempotency_key: Some(db.idempotency_key(document_id).await?),
+                        })
+                        .await?;
+                } else {
+                    // Rebased patch stays queued and is sent on the next sync
+                    pending_uploads.lock().await.remove(document_id);
+                }
+                Ok(())
             }
 
             // Apply protection for sync messages during upload phase
-            ServerMessage::SyncDocument { document } => {
+            ServerMessage::SyncDocument { document, relayed } => {
                 // Check if we're in protection mode
                 if sync_protection_mode.load(Ordering::Relaxed) {
                     tracing::info!(
-                        "CLIENT {}: 🔒 QUEUEING sync for {} v{} (protection mode active)",
-                        client_id,
+                        "🔒 QUEUEING sync for {} v{} (protection mode active)",
                         document.id,
                         document.sync_revision
                     );
                     // Queue message for later processing instead of dropping it
-                    const MAX_DEFERRED_MESSAGES: usize = 100;
-                    let mut queue = deferred_messages.lock().await;
-                    if queue.len() >= MAX_DEFERRED_MESSAGES {
-                        tracing::warn!(
-                            "CLIENT {}: Deferred queue full ({} messages), dropping oldest",
-                            client_id,
-                            queue.len()
-                        );
-                        queue.remove(0);
-                    }
-                    queue.push(ServerMessage::SyncDocument {
-                        document: document.clone(),
-                    });
+                    deferred_messages
+                        .push(
+                            ServerMessage::SyncDocument {
+                                document: document.clone(),
+                                relayed: *relayed,
+                            },
+                            event_dispatcher,
+                        )
+                        .await;
                     return Ok(());
                 }
 
                 // Check if document has pending changes
-                if let Ok(_local_doc) = db.get_document(&document.id).await {
+                if let Ok(local_doc) = db.get_document(&document.id).await {
+                    // A rebased upload already sits on top of this revision - the
+                    // sync is stale and would clobber the rebased content
+                    let rebasing = matches!(
+                        pending_uploads
+                            .lock()
+                            .await
+                            .get(&document.id)
+                            .map(|upload| &upload.operation_type),
+                        Some(UploadType::Rebase)
+                    );
+                    if rebasing && document.sync_revision <= local_doc.sync_revision {
+                        tracing::info!(
+                            "Dropping sync for {} v{} superseded by rebase",
+                            document.id,
+                            document.sync_revision
+                        );
+                        event_dispatcher.emit_sync_skipped(
+                            &document.id,
+                            local_doc.sync_revision,
+                            document.sync_revision,
+                            "Superseded by a rebased local edit awaiting upload",
+                        );
+                        return Ok(());
+                    }
+
                     // Check if this document has an active upload in progress
                     // This is our primary protection mechanism
                     if Self::has_pending_upload(pending_uploads, &document.id).await {
                         tracing::info!(
-                            "CLIENT {}: 🔒 QUEUEING sync for {} v{} (upload in progress)",
-                            client_id,
+                            "🔒 QUEUEING sync for {} v{} (upload in progress)",
                             document.id,
                             document.sync_revision
                         );
                         // Queue message for later processing instead of dropping it
-                        const MAX_DEFERRED_MESSAGES: usize = 100;
-                        let mut queue = deferred_messages.lock().await;
-                        if queue.len() >= MAX_DEFERRED_MESSAGES {
-                            tracing::warn!(
-                                "CLIENT {}: Deferred queue full ({} messages), dropping oldest",
-                                client_id,
-                                queue.len()
-                            );
-                            queue.remove(0);
-                        }
-                        queue.push(ServerMessage::SyncDocument {
-                            document: document.clone(),
-                        });
+                        deferred_messages
+                            .push(
+                                ServerMessage::SyncDocument {
+                                    document: document.clone(),
+                                    relayed: *relayed,
+                                },
+                                event_dispatcher,
+                            )
+                            .await;
                         return Ok(());
                     }
                 }
 
                 // Safe to proceed with sync
-                return Self::handle_server_message(msg, db, client_id, event_dispatcher).await;
+                return Self::handle_server_message(msg, db, event_dispatcher, ws_client).await;
             }
 
             _ => {
                 // For all other messages, use normal handling
-                return Self::handle_server_message(msg, db, client_id, event_dispatcher).await;
+                return Self::handle_server_message(msg, db, event_dispatcher, ws_client).await;
             }
         }
     }
 
+    /// Re-derive the queued local edits against the server's current state.
+    /// Returns the patch to resubmit, or `None` if there is no pending update.
+    ///
+    /// Edits that no longer apply to the server content are not resubmitted:
+    /// they are dropped from the queue and the document is marked conflicted,
+    /// so the caller keeps both sides for [`Client::resolve_conflict`].
+    async fn rebase_rejected_update(
+        db: &Arc<dyn DocumentStore>,
+        event_dispatcher: &Arc<EventDispatcher>,
+        server_document: &Document,
+        cipher: Option<&dyn ContentCipher>,
+    ) -> SyncResult<Option<DocumentPatch>> {
+        let queued_patches = db.get_queued_patches(&server_document.id).await?;
+        if queued_patches.is_empty() {
+            return Ok(None);
+        }
+        let mut local_doc = db.get_document(&server_document.id).await?;
+
+        // Replay the local edits on top of the server content so concurrent changes
+        // to other fields survive
+        let mut rebased = server_document.content.clone();
+        for patch in &queued_patches {
+            if let Err(e) = apply_patch(&mut rebased, patch) {
+                tracing::warn!(
+                    "Local edits to {} don't apply to server v{} - recording conflict: {}",
+                    server_document.id,
+                    server_document.sync_revision,
+                    e
+                );
+                db.remove_from_sync_queue(&server_document.id).await?;
+                db.set_sync_status(&server_document.id, SyncStatus::Conflict)
+                    .await?;
+                event_dispatcher.emit_conflict_detected(&server_document.id);
+                return Ok(None);
+            }
+        }
+
+        let (patch, content_hash) = outgoing_patch(cipher, &server_document.content, &rebased)?;
+
+        local_doc.content = rebased;
+        local_doc.sync_revision = server_document.sync_revision;
+        local_doc.updated_at = chrono::Utc::now();
+        db.replace_queued_patch(&local_doc, &patch, content_hash.clone())
+            .await?;
+
+        Ok(Some(DocumentPatch {
+            document_id: server_document.id,
+            patch,
+            content_hash,
+        }))
+    }
+
     /// Process all deferred sync messages that were queued during upload protection
+    #[instrument(skip_all, fields(client_id = %client_id))]
     async fn process_deferred_messages(
-        deferred_messages: &Arc<Mutex<Vec<ServerMessage>>>,
-        db: &Arc<ClientDatabase>,
+        deferred_messages: &Arc<DeferredQueue>,
+        db: &Arc<dyn DocumentStore>,
         client_id: Uuid,
         event_dispatcher: &Arc<EventDispatcher>,
+        ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
     ) -> SyncResult<()> {
-        let mut messages = deferred_messages.lock().await;
+        let mut messages = deferred_messages.messages.lock().await;
         let count = messages.len();
 
         if count == 0 {
             return Ok(());
         }
 
-        tracing::info!(
-            "CLIENT {}: Processing {} deferred sync messages",
-            client_id,
-            count
-        );
+        tracing::info!("Processing {} deferred sync messages", count);
 
         // Process all deferred messages in order
         for msg in messages.drain(..) {
-            if let Err(e) = Self::handle_server_message(msg, db, client_id, event_dispatcher).await
+            if let Err(e) = Self::handle_server_message(msg, db, event_dispatcher, ws_client).await
             {
-                tracing::error!(
-                    "CLIENT {}: Error processing deferred message: {}",
-                    client_id,
-                    e
-                );
+                tracing::error!("Error processing deferred message: {}", e);
                 // Continue processing remaining messages even if one fails
             }
         }
 
-        tracing::info!(
-            "CLIENT {}: Completed processing deferred messages",
-            client_id
-        );
+        tracing::info!("Completed processing deferred messages");
 
         Ok(())
     }
@@ -1026,91 +2681,100 @@ impl Client {
         uploads.contains_key(document_id)
     }
 
+    #[instrument(skip_all, fields(document_id = msg.document_id().map(field::display)))]
     async fn handle_server_message(
         msg: ServerMessage,
-        db: &Arc<ClientDatabase>,
-        client_id: Uuid,
+        db: &Arc<dyn DocumentStore>,
         event_dispatcher: &Arc<EventDispatcher>,
+        ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
     ) -> SyncResult<()> {
         match msg {
             ServerMessage::DocumentUpdated { patch } => {
                 // Apply patch from server
-                tracing::info!(
-                    "CLIENT {}: Received DocumentUpdated for doc {}",
-                    client_id,
-                    patch.document_id
-                );
+                tracing::info!("Received DocumentUpdated for doc {}", patch.document_id);
                 let mut doc = db.get_document(&patch.document_id).await?;
 
-                tracing::info!(
-                    "CLIENT {}: Document content before patch: {:?}",
-                    client_id,
-                    doc.content
-                );
-                tracing::info!("CLIENT {}: Patch to apply: {:?}", client_id, patch.patch);
-
-                // Apply patch
-                apply_patch(&mut doc.content, &patch.patch)?;
+                // A local base that diverged from the server's can't take the
+                // patch; replace the document with the server's copy instead
+                if let Err(e) = apply_patch(&mut doc.content, &patch.patch) {
+                    tracing::warn!(
+                        "Patch for doc {} failed to apply, fetching full document: {}",
+                        doc.id,
+                        e
+                    );
+                    event_dispatcher.emit_sync_error(&format!(
+                        "Update to document {} couldn't be applied locally, fetching the full document: {}",
+                        doc.id, e
+                    ));
+                    if let Some(client) = ws_client.lock().await.as_ref() {
+                        client
+                            .send(ClientMessage::RequestDocument {
+                                document_id: doc.id,
+                            })
+                            .await?;
+                    }
+                    return Ok(());
+                }
                 doc.content_hash = None; // Will be recalculated
                 doc.updated_at = chrono::Utc::now();
 
-                tracing::info!(
-                    "CLIENT {}: Document content after patch: {:?}",
-                    client_id,
-                    doc.content
-                );
-
                 db.save_document(&doc).await?;
                 db.mark_synced(&doc.id).await?;
 
                 // Emit event for updated document
-                event_dispatcher.emit_document_updated(&doc.id, &doc.content);
+                event_dispatcher.emit_document_updated_with_patch(
+                    &doc.id,
+                    &doc.content,
+                    &patch.patch,
+                    DocumentSource::RemoteClient,
+                );
             }
             ServerMessage::DocumentCreated { document } => {
                 // New document from server - check if we already have it to avoid duplicates
-                tracing::info!(
-                    "CLIENT: Received DocumentCreated from server: {}",
-                    document.id
-                );
+                tracing::info!("Received DocumentCreated from server: {}", document.id);
 
                 // Check if we already have this document (e.g., if we were the creator)
                 match db.get_document(&document.id).await {
                     Ok(existing_doc) => {
                         // We already have this document - just ensure it's marked as synced
                         if existing_doc.sync_revision == document.sync_revision {
-                            tracing::info!("CLIENT: Document {} already exists locally with same sync_revision, marking as synced", document.id);
+                            tracing::info!( "Document {} already exists locally with same sync_revision, marking as synced", document.id);
                             db.mark_synced(&document.id).await?;
                         } else {
                             // Different revision - update it
-                            tracing::info!("CLIENT: Document {} exists locally but has different revision, updating", document.id);
+                            tracing::info!(
+                                "Document {} exists locally but has different revision, updating",
+                                document.id
+                            );
                             db.save_document_with_status(&document, Some(SyncStatus::Synced))
                                 .await?;
 
                             // Emit event for updated document
-                            event_dispatcher.emit_document_updated(&document.id, &document.content);
+                            event_dispatcher.emit_document_updated(
+                                &document.id,
+                                &document.content,
+                                DocumentSource::RemoteClient,
+                            );
                         }
                     }
                     Err(_) => {
                         // Document doesn't exist locally - save it
-                        tracing::info!(
-                            "CLIENT: Document {} is new, saving to local database",
-                            document.id
-                        );
+                        tracing::info!("Document {} is new, saving to local database", document.id);
                         db.save_document_with_status(&document, Some(SyncStatus::Synced))
                             .await?;
 
                         // Emit event for new document from server
-                        event_dispatcher.emit_document_created(&document.id, &document.content);
+                        event_dispatcher.emit_document_created(
+                            &document.id,
+                            &document.content,
+                            DocumentSource::RemoteClient,
+                        );
                     }
                 }
             }
             ServerMessage::DocumentDeleted { document_id } => {
                 // Document deleted from server - we need to delete it locally
-                tracing::info!(
-                    "CLIENT {}: Received DocumentDeleted for doc {}",
-                    client_id,
-                    document_id
-                );
+                tracing::info!("Received DocumentDeleted for doc {}", document_id);
 
                 // Delete the document locally (soft delete)
                 db.delete_document(&document_id).await?;
@@ -1121,99 +2785,137 @@ impl Client {
                 // Emit event for deleted document
                 event_dispatcher.emit_document_deleted(&document_id);
             }
-            ServerMessage::ConflictDetected { document_id, .. } => {
+            ServerMessage::ConflictDetected {
+                document_id,
+                resolution_strategy,
+            } => {
                 tracing::warn!("Conflict detected for document {}", document_id);
 
+                // The server kept its version - drop our queued edits so the
+                // UpdateRejected that follows adopts the server state, keeping
+                // the losing edit as a conflict record
+                if let ConflictResolution::ServerWins = resolution_strategy {
+                    db.remove_from_sync_queue(&document_id).await?;
+                    db.set_sync_status(&document_id, SyncStatus::Conflict)
+                        .await?;
+                }
+
                 // Emit conflict event
                 event_dispatcher.emit_conflict_detected(&document_id);
             }
-            ServerMessage::SyncDocument { document } => {
+            ServerMessage::SyncDocument { document, relayed } => {
                 // Document sync - check if it's newer than what we have
                 tracing::info!(
-                    "CLIENT {}: 📥 RECEIVED SyncDocument: {} (sync_revision: {})",
-                    client_id,
+                    "📥 RECEIVED SyncDocument: {} (sync_revision: {})",
                     document.id,
                     document.sync_revision
                 );
+                let source = if relayed {
+                    DocumentSource::RemoteClient
+                } else {
+                    DocumentSource::Server
+                };
 
                 match db.get_document(&document.id).await {
                     Ok(local_doc) => {
-                        tracing::info!(
-                            "CLIENT {}: LOCAL  DOCUMENT: content={:?}, version={}",
-                            client_id,
-                            local_doc.content,
-                            local_doc.sync_revision
-                        );
-                        tracing::info!(
-                            "CLIENT {}: SERVER DOCUMENT: content={:?}, version={}",
-                            client_id,
-                            document.content,
-                            document.sync_revision
-                        );
-
                         // Compare versions - server wins if version is >= local
                         // This handles the case where server broadcasts back our own update
                         let should_update = document.sync_revision >= local_doc.sync_revision;
 
-                        tracing::info!(
-                            "CLIENT {}: 📊 VERSION COMPARISON for doc {}: server v{} vs local v{} → should_update={}",
-                            client_id,
-                            document.id,
-                            document.sync_revision,
-                            local_doc.sync_revision,
-                            should_update
+                        tracing::debug!(
+                            server_revision = document.sync_revision,
+                            local_revision = local_doc.sync_revision,
+                            should_update,
+                            "Comparing synced document with local copy"
                         );
 
-                        if should_update {
-                            // Check if this might be overwriting local changes by comparing content
-                            if local_doc.content != document.content {
-                                tracing::warn!(
-                                    "CLIENT {}: ⚠️  SERVER OVERWRITING LOCAL CHANGES!",
-                                    client_id
-                                );
-                                tracing::warn!(
-                                    "CLIENT {}: LOCAL: {:?} → SERVER: {:?}",
-                                    client_id,
-                                    local_doc.content,
-                                    document.content
-                                );
-                            }
+                        // A newer server revision arriving on top of unsynced local
+                        // edits can't be ordered - keep both sides for a merge
+                        let diverged = document.sync_revision > local_doc.sync_revision
+                            && local_doc.content != document.content
+                            && db.get_sync_status(&document.id).await? == SyncStatus::Pending;
 
-                            tracing::info!(
-                                "CLIENT {}: 🔄 Updating to newer version ({} -> {})",
-                                client_id,
-                                local_doc.sync_revision,
+                        if diverged {
+                            tracing::warn!(
+                                "Local edits to {} diverged from server v{} - recording conflict",
+                                document.id,
                                 document.sync_revision
                             );
+                            db.save_conflict(&document.id, &local_doc.content, &document.content)
+                                .await?;
+                            db.save_document_with_status(&document, Some(SyncStatus::Conflict))
+                                .await?;
+                            event_dispatcher.emit_document_updated(
+                                &document.id,
+                                &document.content,
+                                source,
+                            );
+                            event_dispatcher.emit_conflict_detected(&document.id);
+                        } else if should_update {
+                            // Check if this might be overwriting local changes by comparing content
+                            if local_doc.content != document.content {
+                                tracing::debug!("Server copy replaces different local content");
+                            }
                             db.save_document_with_status(&document, Some(SyncStatus::Synced))
                                 .await?;
 
                             // Emit event for updated document
-                            event_dispatcher.emit_document_updated(&document.id, &document.content);
+                            event_dispatcher.emit_document_updated(
+                                &document.id,
+                                &document.content,
+                                source,
+                            );
                         } else {
                             tracing::info!(
-                                "CLIENT {}: Skipping older sync (local version {} >= sync version {})",
-                                client_id,
+                                "Skipping older sync (local version {} >= sync version {})",
                                 local_doc.sync_revision,
                                 document.sync_revision
                             );
+                            let reason =
+                                if db.get_sync_status(&document.id).await? == SyncStatus::Pending {
+                                    "Unsynced local edits are newer than the server version"
+                                } else {
+                                    "Local revision is newer than the server version"
+                                };
+                            event_dispatcher.emit_sync_skipped(
+                                &document.id,
+                                local_doc.sync_revision,
+                                document.sync_revision,
+                                reason,
+                            );
                         }
                     }
                     Err(_) => {
                         // Document doesn't exist locally - save it
-                        tracing::info!(
-                            "CLIENT {}: Document {} is new, saving",
-                            client_id,
-                            document.id
-                        );
+                        tracing::info!("Document {} is new, saving", document.id);
                         db.save_document_with_status(&document, Some(SyncStatus::Synced))
                             .await?;
 
                         // Emit event for new document
-                        event_dispatcher.emit_document_created(&document.id, &document.content);
+                        event_dispatcher.emit_document_created(
+                            &document.id,
+                            &document.content,
+                            source,
+                        );
                     }
                 }
             }
+            ServerMessage::LockChanged {
+                document_id,
+                holder,
+            } => {
+                event_dispatcher.emit_lock_changed(&document_id, holder.as_ref());
+            }
+            ServerMessage::PresenceUpdate { client_ids } => {
+                event_dispatcher.emit_presence_changed(&client_ids);
+            }
+            ServerMessage::Error {
+                code: ErrorCode::Locked | ErrorCode::ValidationFailed,
+                message,
+            } => {
+                tracing::warn!("{}", message);
+                event_dispatcher.emit_sync_error(&message);
+            }
             ServerMessage::SyncComplete { synced_count } => {
                 tracing::debug!("Sync complete, received {} documents", synced_count);
 
@@ -1226,20 +2928,26 @@ impl Client {
                 document_id,
                 success,
                 error,
+                sync_revision,
+                created_at,
+                updated_at,
+                ..
             } => {
                 if success {
-                    tracing::info!(
-                        "CLIENT {}: Document creation confirmed by server: {}",
-                        client_id,
-                        document_id
-                    );
+                    tracing::info!("Document creation confirmed by server: {}", document_id);
+                    db.set_server_timestamps(&document_id, created_at, updated_at)
+                        .await?;
+                    // A create that overwrote an existing document bumps the revision,
+                    // and the server doesn't echo the broadcast back to us
+                    if let Some(new_revision) = sync_revision {
+                        db.update_sync_revision(&document_id, new_revision).await?;
+                    }
                     db.mark_synced(&document_id).await?;
                     // Clean up sync_queue
                     db.remove_from_sync_queue(&document_id).await?;
                 } else {
                     tracing::error!(
-                        "CLIENT {}: Document creation failed on server: {} - {}",
-                        client_id,
+                        "Document creation failed on server: {} - {}",
                         document_id,
                         error.as_deref().unwrap_or("unknown error")
                     );
@@ -1256,18 +2964,17 @@ impl Client {
                 success,
                 error,
                 sync_revision,
+                updated_at,
+                ..
             } => {
                 if success {
-                    tracing::info!(
-                        "CLIENT {}: Document update confirmed by server: {}",
-                        client_id,
-                        document_id
-                    );
+                    tracing::info!("Document update confirmed by server: {}", document_id);
+                    db.set_server_timestamps(&document_id, None, updated_at)
+                        .await?;
                     // Update local sync_revision if provided by server
                     if let Some(new_revision) = sync_revision {
                         tracing::info!(
-                            "CLIENT {}: Updating local sync_revision to {} for doc {}",
-                            client_id,
+                            "Updating local sync_revision to {} for doc {}",
                             new_revision,
                             document_id
                         );
@@ -1276,15 +2983,10 @@ impl Client {
                     db.mark_synced(&document_id).await?;
                     // Clean up sync_queue
                     db.remove_from_sync_queue(&document_id).await?;
-                    tracing::info!(
-                        "CLIENT {}: Removed doc {} from sync_queue",
-                        client_id,
-                        document_id
-                    );
+                    tracing::info!("Removed doc {} from sync_queue", document_id);
                 } else {
                     tracing::error!(
-                        "CLIENT {}: Document update failed on server: {} - {}",
-                        client_id,
+                        "Document update failed on server: {} - {}",
                         document_id,
                         error.as_deref().unwrap_or("unknown error")
                     );
@@ -1299,20 +3001,16 @@ impl Client {
                 document_id,
                 success,
                 error,
+                ..
             } => {
                 if success {
-                    tracing::info!(
-                        "CLIENT {}: Document deletion confirmed by server: {}",
-                        client_id,
-                        document_id
-                    );
+                    tracing::info!("Document deletion confirmed by server: {}", document_id);
                     db.mark_synced(&document_id).await?;
                     // Clean up sync_queue
                     db.remove_from_sync_queue(&document_id).await?;
                 } else {
                     tracing::error!(
-                        "CLIENT {}: Document deletion failed on server: {} - {}",
-                        client_id,
+                        "Document deletion failed on server: {} - {}",
                         document_id,
                         error.as_deref().unwrap_or("unknown error")
                     );
@@ -1322,116 +3020,865 @@ impl Client {
                     ));
                 }
             }
-
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Retry timed-out uploads with an exponentially growing wait for each
+    // round of confirmations. Runs under protection mode, so server syncs stay
+    // deferred until the retries settle.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    async fn retry_failed_uploads(&self) -> SyncResult<()> {
+        tracing::info!("Starting upload retry for failed operations");
+
+        let mut wait = self.timeouts.retry_confirm;
+        for attempt in 1..=self.timeouts.max_retry_attempts {
+            // Get current pending uploads (these are the ones that timed out)
+            let timed_out_uploads = self.pending_uploads.lock().await.len();
+            if timed_out_uploads == 0 {
+                tracing::info!("No timed out uploads to retry");
+                return Ok(());
+            }
+
+            tracing::info!(
+                "Retrying {} timed out uploads (attempt {}/{})",
+                timed_out_uploads,
+                attempt,
+                self.timeouts.max_retry_attempts
+            );
+
+            // Clear the pending uploads (we'll re-add them during retry)
+            self.pending_uploads.lock().await.clear();
+
+            // Re-run sync_pending_documents to retry uploads
+            // This will re-query the database for documents with pending status
+            // and re-upload them with fresh tracking
+            match self.sync_pending_documents().await {
+                Ok(()) => {}
+                // The documents stay pending for the sync after reconnecting
+                Err(e) if e.is_retryable() => {
+                    tracing::warn!("Upload retry interrupted: {}", e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            }
+
+            if self.pending_uploads.lock().await.is_empty() {
+                return Ok(());
+            }
+
+            tokio::select! {
+                _ = self.upload_complete_notifier.notified() => {
+                    tracing::info!("All retry uploads confirmed");
+                    return Ok(());
+                }
+                _ = runtime::sleep(wait) => {
+                    let remaining = self.pending_uploads.lock().await.len();
+                    tracing::warn!("Retry timeout - {} uploads still failing", remaining);
+                }
+            }
+            wait *= 2;
+        }
+
+        let remaining = self.pending_uploads.lock().await.len();
+        if remaining > 0 {
+            // Proceed with partial failure - the documents stay pending
+            self.event_dispatcher.emit_sync_error(&format!(
+                "{} uploads unconfirmed after {} retries",
+                remaining, self.timeouts.max_retry_attempts
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Upload pending changes, then run a full sync and wait for the server
+    /// to finish it. Unlike [`Client::sync_all`] this returns once the sync
+    /// is done, or fails with `SyncError::NetworkError` after
+    /// [`SyncTimeouts::full_sync`].
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn sync_now(&self) -> SyncResult<SyncSummary> {
+        if !self.is_connected() {
+            return Err(ClientError::Closed)?;
+        }
+        let conflicts_before = self.db.count_conflicts().await?;
+
+        let pending = self.db.get_pending_documents().await?.len();
+        self.sync_pending_documents().await?;
+        if !self.pending_uploads.lock().await.is_empty() {
+            tokio::select! {
+                _ = self.upload_complete_notifier.notified() => {}
+                _ = runtime::sleep(self.timeouts.upload_confirm) => {
+                    tracing::warn!("Upload timeout during sync_now");
+                }
+            }
+        }
+        let uploaded = pending.saturating_sub(self.pending_uploads.lock().await.len());
+
+        // Listen before asking, so a fast SyncComplete isn't missed
+        let completed = self.sync_completion.notifier.notified();
+        tokio::pin!(completed);
+        completed.as_mut().enable();
+        self.sync_all().await?;
+        runtime::timeout(self.timeouts.full_sync, completed)
+            .await
+            .map_err(|_| {
+                SyncError::NetworkError("Timed out waiting for the full sync to complete".into())
+            })?;
+
+        let conflicts_after = self.db.count_conflicts().await?;
+        Ok(SyncSummary {
+            uploaded,
+            downloaded: self.sync_completion.synced_count.load(Ordering::Acquire),
+            conflicted: (conflicts_after - conflicts_before).max(0) as usize,
+        })
+    }
+
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn sync_all(&self) -> SyncResult<()> {
+        // Request full sync on startup to get all documents
+        tracing::debug!("Requesting full sync from server");
+
+        let ws_client = self.ws_client.lock().await;
+        if let Some(client) = ws_client.as_ref() {
+            client.send(ClientMessage::RequestFullSync).await?;
+        } else {
+            tracing::warn!("Cannot sync - not connected");
+            return Err(ClientError::Closed)?;
+        }
+
+        Ok(())
+    }
+
+    /// Try to take the advisory lock on a document.
+    ///
+    /// Returns `false` if another client already holds it. While locked,
+    /// the server rejects updates and deletes from every other client. Locks
+    /// are released by [`Client::unlock`] or when this client disconnects;
+    /// after reconnecting the client takes them back unless another client
+    /// got there first.
+    pub async fn try_lock(&self, id: Uuid) -> SyncResult<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.lock_waiters.lock().await.insert(id, tx);
+
+        {
+            let ws_client = self.ws_client.lock().await;
+            let sent = match ws_client.as_ref() {
+                Some(client) => {
+                    client
+                        .send(ClientMessage::AcquireLock { document_id: id })
+                        .await
+                }
+                None => Err(ClientError::Closed.into()),
+            };
+            if let Err(e) = sent {
+                self.lock_waiters.lock().await.remove(&id);
+                return Err(e);
+            }
+        }
+
+        match runtime::timeout(LOCK_TIMEOUT, rx).await {
+            Ok(Ok(acquired)) => {
+                if acquired {
+                    self.held_locks.lock().unwrap().insert(id);
+                }
+                Ok(acquired)
+            }
+            _ => {
+                self.lock_waiters.lock().await.remove(&id);
+                Err(ClientError::Timeout(format!("no lock response for document {}", id)).into())
+            }
+        }
+    }
+
+    /// Release a lock taken with [`Client::try_lock`]
+    pub async fn unlock(&self, id: Uuid) -> SyncResult<()> {
+        self.held_locks.lock().unwrap().remove(&id);
+        let ws_client = self.ws_client.lock().await;
+        match ws_client.as_ref() {
+            Some(client) => {
+                client
+                    .send(ClientMessage::ReleaseLock { document_id: id })
+                    .await
+            }
+            // The server drops our locks when the connection goes away
+            None => Ok(()),
+        }
+    }
+
+    /// Attach binary data to a document, returning the attachment's ID.
+    ///
+    /// The data is stored locally and uploaded in chunks apart from the
+    /// document's content; an interrupted upload resumes after reconnecting.
+    /// Reference the attachment from the content by its ID so other clients
+    /// can download it with [`Client::fetch_blob`].
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %document_id))]
+    pub async fn attach_blob(
+        &self,
+        document_id: &Uuid,
+        name: &str,
+        bytes: Vec<u8>,
+    ) -> SyncResult<Uuid> {
+        if bytes.is_empty() {
+            return Err(SyncError::Validation(
+                "Cannot attach an empty blob".to_string(),
+            ));
+        }
+        // Fails for a document that doesn't exist
+        self.db.get_document(document_id).await?;
+
+        let attachment = Attachment {
+            id: Uuid::new_v4(),
+            document_id: *document_id,
+            name: name.to_string(),
+            size: bytes.len() as u64,
+            data: bytes,
+            uploaded: 0,
+        };
+        self.db.save_attachment(&attachment).await?;
+        tracing::info!(
+            "Attached {} byte blob {} as {:?}",
+            attachment.size,
+            attachment.id,
+            attachment.name
+        );
+
+        if self.is_connected()
+            && !self.is_sync_paused()
+            && !self.db.is_local_only(document_id).await?
+        {
+            if let Err(e) = Self::send_blob_chunk(&self.ws_client, &attachment, 0).await {
+                tracing::warn!(
+                    "Failed to upload blob {}: {}. Will retry later.",
+                    attachment.id,
+                    e
+                );
+            }
+        }
+        Ok(attachment.id)
+    }
+
+    /// The data of an attachment, downloaded from the server unless it is
+    /// stored locally. Fails if the server has no such blob.
+    pub async fn fetch_blob(&self, blob_id: &Uuid) -> SyncResult<Vec<u8>> {
+        if let Some(attachment) = self.db.get_attachment(blob_id).await? {
+            return Ok(attachment.data);
+        }
+
+        let (tx, rx) = oneshot::channel();
+        self.blob_downloads.lock().await.insert(
+            *blob_id,
+            BlobDownload {
+                data: Vec::new(),
+                done: tx,
+            },
+        );
+
+        {
+            let ws_client = self.ws_client.lock().await;
+            let sent = match ws_client.as_ref() {
+                Some(client) => {
+                    client
+                        .send(ClientMessage::RequestBlob { blob_id: *blob_id })
+                        .await
+                }
+                None => Err(ClientError::Closed.into()),
+            };
+            if let Err(e) = sent {
+                self.blob_downloads.lock().await.remove(blob_id);
+                return Err(e);
+            }
+        }
+
+        match runtime::timeout(self.timeouts.blob_fetch, rx).await {
+            Ok(Ok(Some(data))) => Ok(data),
+            Ok(Ok(None)) => Err(SyncError::InvalidOperation(format!(
+                "Blob {} not found",
+                blob_id
+            ))),
+            _ => {
+                self.blob_downloads.lock().await.remove(blob_id);
+                Err(ClientError::Timeout(format!("no response for blob {}", blob_id)).into())
+            }
+        }
+    }
+
+    /// Download the server's copy of a document and overwrite the local one
+    /// with it, e.g. to recover a document stuck in conflict.
+    ///
+    /// Any unsynced local changes to the document are discarded, with a sync
+    /// error event saying so. Fails if the server doesn't answer in time,
+    /// which is also the case when it has no such document.
+    #[instrument(skip_all, fields(client_id = %self.client_id, document_id = %id))]
+    pub async fn refetch_document(&self, id: Uuid) -> SyncResult<Document> {
+        const REFETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+        let (tx, rx) = oneshot::channel();
+        self.document_waiters.lock().await.insert(id, tx);
+
+        {
+            let ws_client = self.ws_client.lock().await;
+            let sent = match ws_client.as_ref() {
+                Some(client) => {
+                    client
+                        .send(ClientMessage::RequestDocument { document_id: id })
+                        .await
+                }
+                None => Err(ClientError::Closed.into()),
+            };
+            if let Err(e) = sent {
+                self.document_waiters.lock().await.remove(&id);
+                return Err(e);
+            }
+        }
+
+        match runtime::timeout(REFETCH_TIMEOUT, rx).await {
+            Ok(Ok(document)) => Ok(document),
+            _ => {
+                self.document_waiters.lock().await.remove(&id);
+                Err(ClientError::Timeout(format!("no response for document {}", id)).into())
+            }
+        }
+    }
+
+    // Overwrite the local copy of a document with the server's, dropping
+    // whatever was pending for it
+    async fn reset_to_server_copy(
+        document: &Document,
+        db: &Arc<dyn DocumentStore>,
+        event_dispatcher: &Arc<EventDispatcher>,
+        pending_uploads: &Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+    ) -> SyncResult<()> {
+        let local_status = db.get_sync_status(&document.id).await.ok();
+
+        pending_uploads.lock().await.remove(&document.id);
+        db.remove_from_sync_queue(&document.id).await?;
+        db.delete_conflict(&document.id).await?;
+        db.save_document_with_status(document, Some(SyncStatus::Synced))
+            .await?;
+
+        match local_status {
+            None => event_dispatcher.emit_document_created(
+                &document.id,
+                &document.content,
+                DocumentSource::Server,
+            ),
+            Some(status) => {
+                if status != SyncStatus::Synced {
+                    tracing::warn!(
+                        "Discarded {:?} local changes to document {} for the server copy",
+                        status,
+                        document.id
+                    );
+                    event_dispatcher.emit_sync_error(&format!(
+                        "Discarded unsynced local changes to document {}",
+                        document.id
+                    ));
+                }
+                event_dispatcher.emit_document_updated(
+                    &document.id,
+                    &document.content,
+                    DocumentSource::Server,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete an attachment locally and on the server. While offline only
+    /// the local copy is deleted.
+    pub async fn delete_blob(&self, blob_id: &Uuid) -> SyncResult<()> {
+        self.db.delete_attachment(blob_id).await?;
+        if let Some(client) = self.ws_client.lock().await.as_ref() {
+            client
+                .send(ClientMessage::DeleteBlob { blob_id: *blob_id })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Send the chunk of an attachment's upload starting at `offset`
+    async fn send_blob_chunk(
+        ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
+        attachment: &Attachment,
+        offset: u64,
+    ) -> SyncResult<()> {
+        let start = (offset as usize).min(attachment.data.len());
+        let end = (start + BLOB_CHUNK_BYTES).min(attachment.data.len());
+        let message = ClientMessage::UploadBlob {
+            blob_id: attachment.id,
+            document_id: attachment.document_id,
+            name: attachment.name.clone(),
+            total_size: attachment.size,
+            offset,
+            data: attachment.data[start..end].to_vec(),
+        };
+        match ws_client.lock().await.as_ref() {
+            Some(client) => client.send(message).await,
+            None => Err(ClientError::Closed.into()),
+        }
+    }
+
+    /// Carry on the uploads of attachments the server doesn't hold in full,
+    /// each from the last acknowledged offset. If the server holds a
+    /// different amount it drops the chunk and its ack says where to resume.
+    async fn resume_blob_uploads(
+        db: &Arc<dyn DocumentStore>,
+        ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
+    ) -> SyncResult<()> {
+        for blob_id in db.get_pending_attachments().await? {
+            let Some(attachment) = db.get_attachment(&blob_id).await? else {
+                continue;
+            };
+            if db.is_local_only(&attachment.document_id).await? {
+                continue;
+            }
+            tracing::info!(
+                "Resuming upload of blob {} at {}/{} bytes",
+                blob_id,
+                attachment.uploaded,
+                attachment.size
+            );
+            Self::send_blob_chunk(ws_client, &attachment, attachment.uploaded).await?;
+        }
+        Ok(())
+    }
+
+    /// Send the next chunk of an upload the server acknowledged, collect a
+    /// downloaded chunk, or drop a deleted blob
+    async fn handle_blob_message(
+        msg: ServerMessage,
+        db: &Arc<dyn DocumentStore>,
+        blob_downloads: &BlobDownloads,
+        ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
+    ) -> SyncResult<()> {
+        match msg {
+            ServerMessage::BlobUploadAck {
+                blob_id,
+                received,
+                complete,
+            } => {
+                // Deleted while uploading
+                let Some(attachment) = db.get_attachment(&blob_id).await? else {
+                    return Ok(());
+                };
+                // An ack that doesn't move the upload along answers a
+                // duplicate chunk, whose twin already sent the next one
+                if received == attachment.uploaded {
+                    return Ok(());
+                }
+                db.set_attachment_uploaded(&blob_id, received).await?;
+                if complete {
+                    tracing::info!("Blob {} uploaded", blob_id);
+                } else {
+                    Self::send_blob_chunk(ws_client, &attachment, received).await?;
+                }
+            }
+            ServerMessage::BlobChunk {
+                blob_id,
+                document_id,
+                name,
+                total_size,
+                offset,
+                data,
+            } => {
+                let mut downloads = blob_downloads.lock().await;
+                // Nobody is waiting for it any more
+                let Some(download) = downloads.get_mut(&blob_id) else {
+                    return Ok(());
+                };
+                if download.data.len() as u64 != offset {
+                    tracing::warn!(
+                        "Dropping chunk of blob {} at {}, expected {}",
+                        blob_id,
+                        offset,
+                        download.data.len()
+                    );
+                    return Ok(());
+                }
+                download.data.extend_from_slice(&data);
+                if download.data.len() as u64 >= total_size {
+                    let download = downloads.remove(&blob_id).expect("download was just found");
+                    drop(downloads);
+                    // Kept for next time; the fetch succeeds either way
+                    let attachment = Attachment {
+                        id: blob_id,
+                        document_id,
+                        name,
+                        size: total_size,
+                        data: download.data,
+                        uploaded: total_size,
+                    };
+                    if let Err(e) = db.save_attachment(&attachment).await {
+                        tracing::warn!("Failed to store downloaded blob {}: {}", blob_id, e);
+                    }
+                    let _ = download.done.send(Some(attachment.data));
+                }
+            }
+            ServerMessage::BlobDeleted { blob_id } => {
+                db.delete_attachment(&blob_id).await?;
+                if let Some(download) = blob_downloads.lock().await.remove(&blob_id) {
+                    let _ = download.done.send(None);
+                }
+            }
             _ => {}
         }
-
         Ok(())
     }
 
-    // Retry failed uploads by re-checking pending documents
-    async fn retry_failed_uploads(&self) -> SyncResult<()> {
-        tracing::info!(
-            "CLIENT {}: Starting upload retry for failed operations",
-            self.client_id
-        );
+    /// Call `callback` with the IDs of this user's connected clients whenever
+    /// one connects or disconnects.
+    ///
+    /// Subscribes the current connection to presence updates; the server
+    /// replies straight away with the current list. Like other Rust callbacks,
+    /// `callback` runs from [`EventDispatcher::process_events`].
+    pub async fn on_presence_change<F>(&self, callback: F) -> SyncResult<()>
+    where
+        F: Fn(Vec<String>) + Send + 'static,
+    {
+        self.event_dispatcher.register_rust_callback_filtered(
+            move |event| {
+                if let SyncEvent::PresenceChanged { active_clients } = event {
+                    callback(active_clients);
+                }
+            },
+            EventType::PresenceChanged,
+        )?;
+        self.presence_subscribed.store(true, Ordering::Relaxed);
+
+        let ws_client = self.ws_client.lock().await;
+        match ws_client.as_ref() {
+            Some(client) => client.send(ClientMessage::RequestPresence).await,
+            None => Err(ClientError::Closed.into()),
+        }
+    }
 
-        // Get current pending uploads (these are the ones that timed out)
-        let timed_out_uploads: Vec<Uuid> = {
-            let uploads = self.pending_uploads.lock().await;
-            uploads.keys().cloned().collect()
+    /// Call `callback` with the document whenever it is created, updated or
+    /// deleted, whether locally or from sync, until the handle is dropped.
+    ///
+    /// The document is kept up to date from the change events rather than
+    /// re-read from the store, so `sync_revision` and `content_hash` are those
+    /// it had when the watch started. Like other Rust callbacks, `callback`
+    /// runs from [`EventDispatcher::process_events`].
+    pub async fn watch_document<F>(&self, id: Uuid, callback: F) -> SyncResult<WatchHandle>
+    where
+        F: Fn(Document) + Send + 'static,
+    {
+        let current = match self.db.get_document(&id).await {
+            Ok(doc) => Some(doc),
+            Err(SyncError::DocumentNotFound(_)) => None,
+            Err(e) => return Err(e),
         };
+        let current = std::sync::Mutex::new(current);
+        let user_id = self.user_id;
+
+        self.event_dispatcher.register_document_watch(
+            move |event| {
+                let mut current = current.lock().unwrap();
+                let now = chrono::Utc::now();
+                // Not stored yet when the watch started
+                let doc = current.get_or_insert_with(|| Document {
+                    id,
+                    user_id,
+                    content: serde_json::Value::Null,
+                    sync_revision: 0,
+                    content_hash: None,
+                    title: None,
+                    created_at: now,
+                    updated_at: now,
+                    deleted_at: None,
+                    expires_at: None,
+                    sync_status: SyncStatus::Pending,
+                });
+                match event {
+                    SyncEvent::DocumentCreated {
+                        content, source, ..
+                    }
+                    | SyncEvent::DocumentUpdated {
+                        content, source, ..
+                    } => {
+                        doc.title = content
+                            .get("title")
+                            .and_then(|v| v.as_str())
+                            .map(str::to_string);
+                        doc.content = content;
+                        doc.content_hash = None;
+                        doc.updated_at = now;
+                        doc.deleted_at = None;
+                        doc.sync_status = match source {
+                            DocumentSource::Local => SyncStatus::Pending,
+                            _ => SyncStatus::Synced,
+                        };
+                    }
+                    SyncEvent::DocumentDeleted { .. } => {
+                        doc.updated_at = now;
+                        doc.deleted_at = Some(now);
+                    }
+                    _ => return,
+                }
+                callback(doc.clone());
+            },
+            &id,
+        )
+    }
 
-        if timed_out_uploads.is_empty() {
-            tracing::info!("CLIENT {}: No timed out uploads to retry", self.client_id);
-            return Ok(());
+    /// Only receive live updates for these documents (in addition to any
+    /// already subscribed to).
+    ///
+    /// By default the server sends this client changes to every document the
+    /// user can see. Subscriptions survive reconnects; full syncs are not
+    /// affected.
+    pub async fn subscribe(&self, document_ids: &[Uuid]) -> SyncResult<()> {
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .extend(document_ids.iter().copied());
+
+        let ws_client = self.ws_client.lock().await;
+        match ws_client.as_ref() {
+            Some(client) => {
+                client
+                    .send(ClientMessage::Subscribe {
+                        document_ids: document_ids.to_vec(),
+                    })
+                    .await
+            }
+            // Sent when we reconnect
+            None => Ok(()),
         }
+    }
 
-        tracing::info!(
-            "CLIENT {}: Retrying {} timed out uploads",
-            self.client_id,
-            timed_out_uploads.len()
-        );
+    /// Stop receiving live updates for these documents. Once nothing is
+    /// subscribed, the client hears about every document again.
+    pub async fn unsubscribe(&self, document_ids: &[Uuid]) -> SyncResult<()> {
+        {
+            let mut subscriptions = self.subscriptions.lock().unwrap();
+            for id in document_ids {
+                subscriptions.remove(id);
+            }
+        }
 
-        // Clear the pending uploads (we'll re-add them during retry)
-        self.pending_uploads.lock().await.clear();
+        let ws_client = self.ws_client.lock().await;
+        match ws_client.as_ref() {
+            Some(client) => {
+                client
+                    .send(ClientMessage::Unsubscribe {
+                        document_ids: document_ids.to_vec(),
+                    })
+                    .await
+            }
+            // A new connection starts with no subscriptions anyway
+            None => Ok(()),
+        }
+    }
 
-        // Re-run sync_pending_documents to retry uploads
-        // This will re-query the database for documents with pending status
-        // and re-upload them with fresh tracking
-        self.sync_pending_documents().await?;
+    /// The server this client connects to. With
+    /// [`ClientConfig::server_resolver`] set, the one it picked last.
+    pub fn server_url(&self) -> String {
+        self.server_url.lock().unwrap().clone()
+    }
 
-        // Quick wait for the retry confirmations (shorter timeout)
-        if !self.pending_uploads.lock().await.is_empty() {
-            let retry_count = self.pending_uploads.lock().await.len();
-            tracing::info!(
-                "CLIENT {}: Waiting for {} retry confirmations (short timeout)",
-                self.client_id,
-                retry_count
-            );
+    /// Check if the WebSocket connection is active
+    pub fn is_connected(&self) -> bool {
+        self.is_connected.load(Ordering::Relaxed)
+    }
 
-            tokio::select! {
-                _ = self.upload_complete_notifier.notified() => {
-                    tracing::info!("CLIENT {}: All retry uploads confirmed", self.client_id);
-                }
-                _ = tokio::time::sleep(tokio::time::Duration::from_secs(5)) => {
-                    let remaining = self.pending_uploads.lock().await.len();
-                    tracing::warn!("CLIENT {}: Retry timeout - {} uploads still failing", self.client_id, remaining);
-                    // Don't retry again - proceed with partial failure
-                }
+    /// Whether the local store still has a user configured. `Client::new`
+    /// sets one up, so this is false only if the configuration has since been
+    /// lost or damaged; run first-time setup again in that case.
+    pub async fn is_initialized(&self) -> SyncResult<bool> {
+        match self.db.get_user_and_client_id().await {
+            Ok(_) => Ok(true),
+            Err(SyncError::NotInitialized) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stop all background tasks and close the connection.
+    ///
+    /// Waits briefly for in-flight uploads to be confirmed, then sends a
+    /// WebSocket Close frame and closes the local database. Changes that are
+    /// still unconfirmed stay queued and are uploaded by the next client.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn shutdown(self) -> SyncResult<()> {
+        const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+        tracing::info!("Shutting down");
+
+        // Flush: give uploads already on the wire a chance to be acknowledged
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        while self.is_connected()
+            && !self.pending_uploads.lock().await.is_empty()
+            && Instant::now() < deadline
+        {
+            runtime::sleep(Duration::from_millis(50)).await;
+        }
+
+        self.shutdown_token.cancel();
+        let tasks = std::mem::take(&mut *self.background_tasks.lock().unwrap());
+        for task in tasks {
+            if let Err(e) = task.await {
+                tracing::warn!("Background task failed during shutdown: {}", e);
+            }
+        }
+
+        // Tasks are stopped, so nothing can reconnect behind our back
+        if let Some(client) = self.ws_client.lock().await.take() {
+            if let Err(e) = client.close().await {
+                tracing::debug!("WebSocket already closed: {}", e);
             }
         }
+        self.is_connected.store(false, Ordering::Relaxed);
 
+        self.db.close().await;
+
+        tracing::info!("Shutdown complete");
         Ok(())
     }
 
-    pub async fn sync_all(&self) -> SyncResult<()> {
-        // Request full sync on startup to get all documents
-        tracing::debug!("Requesting full sync from server");
+    /// Force offline mode (e.g. to simulate airplane mode) without touching the network.
+    ///
+    /// Going offline closes the connection and stops reconnection attempts;
+    /// local operations keep working and queue for sync. Going back online
+    /// reconnects immediately and uploads anything pending.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn set_offline(&self, offline: bool) {
+        if self.offline_mode.swap(offline, Ordering::Relaxed) == offline {
+            return;
+        }
 
-        let ws_client = self.ws_client.lock().await;
-        if let Some(client) = ws_client.as_ref() {
-            client.send(ClientMessage::RequestFullSync).await?;
+        if offline {
+            tracing::info!("Entering offline mode");
+            *self.reconnect_state.lock().unwrap() = None;
+            let was_connected = self.is_connected.swap(false, Ordering::Relaxed);
+            if let Some(client) = self.ws_client.lock().await.take() {
+                let _ = client.close().await;
+            }
+            // In-flight uploads won't be confirmed; they stay pending in the database
+            self.pending_uploads.lock().await.clear();
+            if was_connected {
+                self.event_dispatcher
+                    .emit_connection_lost(&self.server_url(), DisconnectReason::Manual);
+            }
         } else {
-            tracing::warn!("CLIENT {}: Cannot sync - not connected", self.client_id);
-            return Err(ClientError::WebSocket("Not connected".to_string()))?;
+            tracing::info!("Leaving offline mode");
+            self.reconnect_now.notify_one();
+        }
+    }
+
+    /// Stop sending local changes as they are made, e.g. during a bulk import.
+    ///
+    /// Edits are still saved and queued as usual, and the connection stays
+    /// up for incoming changes. [`Client::resume_sync`] sends everything
+    /// queued in the meantime in one pass.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub fn pause_sync(&self) {
+        tracing::info!("Pausing sync");
+        self.sync_paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Undo [`Client::pause_sync`] and upload the changes it held back,
+    /// between `SyncStarted` and `SyncCompleted` events. When offline they
+    /// are uploaded on reconnect instead.
+    #[instrument(skip_all, fields(client_id = %self.client_id))]
+    pub async fn resume_sync(&self) -> SyncResult<()> {
+        if !self.sync_paused.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        tracing::info!("Resuming sync");
+        if !self.is_connected() {
+            return Ok(());
         }
 
+        let pending = self.db.get_pending_documents().await?.len();
+        self.event_dispatcher.emit_sync_started();
+        self.sync_pending_documents().await?;
+        Self::resume_blob_uploads(&self.db, &self.ws_client).await?;
+        self.event_dispatcher.emit_sync_completed(pending as u64);
         Ok(())
     }
 
-    /// Check if the WebSocket connection is active
-    pub fn is_connected(&self) -> bool {
-        self.is_connected.load(Ordering::Relaxed)
+    /// Whether sync is paused with [`Client::pause_sync`]
+    pub fn is_sync_paused(&self) -> bool {
+        self.sync_paused.load(Ordering::Relaxed)
+    }
+
+    /// Whether `id` keeps answering server changes with uploads of its own
+    /// and its changes are being held back, see
+    /// `ClientConfig::sync_loop_limit`
+    pub fn is_sync_quarantined(&self, id: Uuid) -> bool {
+        self.loop_guard.is_quarantined(&id)
+    }
+
+    /// Whether offline mode was forced with [`Client::set_offline`]
+    pub fn is_offline(&self) -> bool {
+        self.offline_mode.load(Ordering::Relaxed)
+    }
+
+    /// How long ago a sync with the server last completed, or `None` if this
+    /// database never synced. Persisted, so it survives restarts; together
+    /// with [`Client::connection_state`] it lets UIs warn that local data may
+    /// be out of date.
+    pub fn time_since_last_sync(&self) -> Option<Duration> {
+        let last_sync_at = (*self.sync_completion.last_sync_at.lock().unwrap())?;
+        // A clock set backwards reads as just synced
+        Some(
+            (chrono::Utc::now() - last_sync_at)
+                .to_std()
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Detailed connection status, including reconnection progress
+    pub fn connection_state(&self) -> ConnectionState {
+        if self.is_connected() {
+            return ConnectionState::Connected;
+        }
+        match *self.reconnect_state.lock().unwrap() {
+            Some((attempt, next_retry_at)) => ConnectionState::Reconnecting {
+                attempt,
+                next_retry_at,
+            },
+            None => ConnectionState::Disconnected,
+        }
+    }
+
+    /// Counts of document changes and message traffic since this client was
+    /// created. Changes to local-only documents count too.
+    pub fn stats(&self) -> ClientStats {
+        self.stats.snapshot()
     }
 
     /// Attempt to sync a single document immediately if connected
     async fn try_immediate_sync(&self, document: &Document) -> SyncResult<()> {
+        if self.db.is_local_only(&document.id).await? {
+            return Ok(());
+        }
+        if self.is_sync_paused() {
+            tracing::debug!("Sync paused - document {} stays pending", document.id);
+            return Ok(());
+        }
+
         let connected = self.is_connected();
-        tracing::info!(
-            "CLIENT {}: 🔍 Connection status check: connected={}",
-            self.client_id,
-            connected
-        );
+        tracing::info!("🔍 Connection status check: connected={}", connected);
 
         if !connected {
-            tracing::warn!("CLIENT {}: 📴 OFFLINE - Document {} cannot sync immediately, returning error to mark as pending", 
-                         self.client_id, document.id);
-            return Err(ClientError::WebSocket(
-                "Client is offline - document should remain pending".to_string(),
-            ))?;
+            tracing::warn!("📴 OFFLINE - Document {} cannot sync immediately, returning error to mark as pending", 
+                         document.id);
+            return Err(ClientError::Closed)?;
+        }
+        // A looping document's changes stay pending until its quarantine ends
+        if !self.loop_guard.admit(document.id, &self.event_dispatcher) {
+            return Ok(());
         }
 
-        tracing::info!(
-            "CLIENT {}: 🚀 IMMEDIATE SYNC attempt for document {}",
-            self.client_id,
-            document.id
-        );
-        tracing::info!(
-            "CLIENT {}: Document sync_revision: {}, content: {:?}",
-            self.client_id,
-            document.sync_revision,
-            document.content
+        tracing::debug!(
+            document_id = %document.id,
+            sync_revision = document.sync_revision,
+            "Attempting immediate sync"
         );
 
         // Determine if this is create or update by checking for queued patch
@@ -1441,14 +3888,7 @@ impl Client {
         let (operation_type, message) = match self.db.get_queued_patch(&document.id).await {
             Ok(Some((patch, old_hash_opt))) => {
                 // Have a queued patch = this is an UPDATE
-                tracing::info!(
-                    "CLIENT {}: Sending UPDATE with queued patch for doc {}",
-                    self.client_id,
-                    document.id
-                );
-
-                use replicant_core::models::DocumentPatch;
-                use replicant_core::patches::calculate_checksum;
+                tracing::info!("Sending UPDATE with queued patch for doc {}", document.id);
 
                 // Use the stored old content hash, or calculate from current content as fallback
                 let content_hash =
@@ -1462,35 +3902,36 @@ impl Client {
                             patch,
                             content_hash,
                         },
+                        idempotency_key: Some(self.db.idempotency_key(&document.id).await?),
                     },
                 )
             }
             Ok(None) => {
                 // No queued patch = this is a CREATE
                 tracing::info!(
-                    "CLIENT {}: Sending CREATE for doc {} (no queued patch found)",
-                    self.client_id,
+                    "Sending CREATE for doc {} (no queued patch found)",
                     document.id
                 );
                 (
                     UploadType::Create,
                     ClientMessage::CreateDocument {
-                        document: document.clone(),
+                        document: outgoing_document(self.cipher.as_deref(), document)?,
+                        idempotency_key: Some(self.db.idempotency_key(&document.id).await?),
                     },
                 )
             }
             Err(e) => {
                 // Error querying patch = this is a CREATE
                 tracing::warn!(
-                    "CLIENT {}: Sending CREATE for doc {} (error getting queued patch: {})",
-                    self.client_id,
+                    "Sending CREATE for doc {} (error getting queued patch: {})",
                     document.id,
                     e
                 );
                 (
                     UploadType::Create,
                     ClientMessage::CreateDocument {
-                        document: document.clone(),
+                        document: outgoing_document(self.cipher.as_deref(), document)?,
+                        idempotency_key: Some(self.db.idempotency_key(&document.id).await?),
                     },
                 )
             }
@@ -1498,14 +3939,9 @@ impl Client {
 
         // Add to pending uploads for tracking
         {
+            let upload = PendingUpload::start(operation_type, &self.db, &document.id).await?;
             let mut uploads = self.pending_uploads.lock().await;
-            uploads.insert(
-                document.id,
-                PendingUpload {
-                    operation_type,
-                    sent_at: Instant::now(),
-                },
-            );
+            uploads.insert(document.id, upload);
         }
 
         let ws_client = self.ws_client.lock().await;
@@ -1514,24 +3950,26 @@ impl Client {
                 match client.send(message).await {
                     Ok(_) => {
                         tracing::info!(
-                            "CLIENT {}: ✅ Immediate sync request sent for document {}",
-                            self.client_id,
+                            "✅ Immediate sync request sent for document {}",
                             document.id
                         );
                         Ok(())
                     }
+                    Err(e @ SyncError::Validation(_)) => {
+                        // Too large to send - the connection is fine, the document stays pending
+                        self.pending_uploads.lock().await.remove(&document.id);
+                        Err(e)
+                    }
                     Err(e) => {
                         // Connection failed - mark as disconnected and remove from pending uploads
                         self.is_connected.store(false, Ordering::Relaxed);
-                        self.event_dispatcher.emit_connection_lost(&self.server_url);
+                        self.event_dispatcher
+                            .emit_connection_lost(&self.server_url(), DisconnectReason::SendFailed);
                         {
                             let mut uploads = self.pending_uploads.lock().await;
                             uploads.remove(&document.id);
                         }
-                        tracing::warn!(
-                            "CLIENT {}: WebSocket send failed, marked as disconnected",
-                            self.client_id
-                        );
+                        tracing::warn!("WebSocket send failed, marked as disconnected");
                         // Start reconnection loop if not already running
                         drop(ws_client); // Release lock before starting reconnection
                         self.start_reconnection_loop();
@@ -1540,57 +3978,116 @@ impl Client {
                 }
             }
             None => {
-                tracing::warn!(
-                    "CLIENT {}: No WebSocket connection available for immediate sync",
-                    self.client_id
-                );
+                tracing::warn!("No WebSocket connection available for immediate sync");
                 {
                     let mut uploads = self.pending_uploads.lock().await;
                     uploads.remove(&document.id);
                 }
-                Err(ClientError::WebSocket("Not connected".to_string()))?
+                Err(ClientError::Closed)?
+            }
+        }
+    }
+
+    /// Stops reconnection for good once the server rejects the credentials
+    /// or the protocol version, since every further attempt would be
+    /// rejected the same way.
+    fn note_auth_failure(
+        msg: &ServerMessage,
+        auth_failed: &AtomicBool,
+        event_dispatcher: &EventDispatcher,
+    ) {
+        let reason = match msg {
+            ServerMessage::AuthFailed { reason } => {
+                tracing::error!("Server rejected credentials: {}", reason);
+                reason
+            }
+            ServerMessage::Error {
+                code: ErrorCode::VersionMismatch,
+                message,
+            } => {
+                tracing::error!("Server rejected protocol version: {}", message);
+                message
             }
+            _ => return,
+        };
+        if !auth_failed.swap(true, Ordering::AcqRel) {
+            event_dispatcher.emit_authentication_failed(reason);
         }
     }
 
     /// Start the reconnection loop if not already running
     fn start_reconnection_loop(&self) {
+        if self.shutdown_token.is_cancelled() || self.auth_failed.load(Ordering::Acquire) {
+            return;
+        }
+
+        // Already running - just have it retry now rather than at its next tick
+        if self.reconnection_active.swap(true, Ordering::AcqRel) {
+            tracing::debug!("Reconnection monitor already running - waking it");
+            self.reconnect_now.notify_one();
+            return;
+        }
+
         let is_connected = self.is_connected.clone();
+        let offline_mode = self.offline_mode.clone();
+        let reconnect_now = self.reconnect_now.clone();
+        let reconnect_state = self.reconnect_state.clone();
         let ws_client = self.ws_client.clone();
-        let server_url = self.server_url.clone();
+        let mut server_url = self.server_url();
+        let shared_server_url = self.server_url.clone();
+        let server_resolver = self.server_resolver.clone();
+        let user_id = self.user_id;
         let email = self.email.clone();
         let api_key = self.api_key.clone();
         let api_secret = self.api_secret.clone();
         let client_id = self.client_id;
         let event_dispatcher = self.event_dispatcher.clone();
-        let db = self.db.clone();
+        let message_context = Arc::new(self.message_context());
         let pending_uploads = self.pending_uploads.clone();
-        let upload_complete_notifier = self.upload_complete_notifier.clone();
         let reconnect_sync_tx = self.reconnect_sync_tx.clone();
-        let sync_protection_mode = self.sync_protection_mode.clone();
         let last_ping_time = self.last_ping_time.clone();
-        let deferred_messages = self.deferred_messages.clone();
-
-        tracing::info!(
-            "🔄 CLIENT {}: Starting continuous reconnection monitor (5-second intervals)",
-            client_id
-        );
-
-        tokio::spawn(async move {
+        let stats = self.stats.clone();
+        let conflict_resolution = self.conflict_resolution.clone();
+        let receipt_acks = self.receipt_acks;
+        let max_message_bytes = self.max_message_bytes;
+        let keepalive_interval = self.keepalive_interval;
+        let connect_timeout = self.connect_timeout;
+        let tls_pins = self.tls_pins.clone();
+        let shutdown_token = self.shutdown_token.clone();
+        let reconnection_active = self.reconnection_active.clone();
+        let auth_failed = self.auth_failed.clone();
+
+        tracing::info!("🔄 Starting continuous reconnection monitor (5-second intervals)");
+
+        let handle = runtime::spawn(async move {
             const RECONNECTION_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
-            let mut connection_attempts = 0;
+            let mut connection_attempts: u32 = 0;
 
-            loop {
+            while !shutdown_token.is_cancelled() {
+                if auth_failed.load(Ordering::Acquire) {
+                    tracing::warn!("🔑 Connection refused by server - no longer reconnecting");
+                    *reconnect_state.lock().unwrap() = None;
+                    break;
+                }
                 let currently_connected = is_connected.load(Ordering::Relaxed);
 
-                if !currently_connected {
+                if offline_mode.load(Ordering::Relaxed) {
+                    tracing::debug!("✈️ Offline mode - not reconnecting");
+                    connection_attempts = 0;
+                } else if !currently_connected {
                     connection_attempts += 1;
+                    // The user may have moved to another shard since
+                    if let Some(resolve) = &server_resolver {
+                        server_url = resolve(user_id);
+                        *shared_server_url.lock().unwrap() = server_url.clone();
+                    }
                     tracing::info!(
-                        "🔌 CLIENT {}: Connection attempt #{} to {}",
-                        client_id,
+                        "🔌 Connection attempt #{} to {}",
                         connection_attempts,
                         server_url
                     );
+                    *reconnect_state.lock().unwrap() = Some((connection_attempts, Instant::now()));
+                    event_dispatcher.emit_reconnecting(connection_attempts);
 
                     // Try to connect
                     match WebSocketClient::connect(
@@ -1600,17 +4097,34 @@ impl Client {
                         &api_key,
                         &api_secret,
                         Some(event_dispatcher.clone()),
+                        Some(stats.clone()),
                         is_connected.clone(),
+                        conflict_resolution.clone(),
+                        receipt_acks,
+                        max_message_bytes,
+                        &tls_pins,
+                        keepalive_interval,
+                        connect_timeout,
                     )
                     .await
                     {
+                        Ok((new_client, _)) if shutdown_token.is_cancelled() => {
+                            // Shut down while connecting - don't resurrect the connection
+                            let _ = new_client.close().await;
+                            break;
+                        }
+                        Ok((new_client, _)) if offline_mode.load(Ordering::Relaxed) => {
+                            // Went offline while connecting
+                            let _ = new_client.close().await;
+                            *reconnect_state.lock().unwrap() = None;
+                        }
                         Ok((new_client, receiver)) => {
                             tracing::info!(
-                                "✅ CLIENT {}: Reconnection successful after {} attempts!",
-                                client_id,
+                                "✅ Reconnection successful after {} attempts!",
                                 connection_attempts
                             );
                             connection_attempts = 0;
+                            *reconnect_state.lock().unwrap() = None;
 
                             // Update the client
                             *ws_client.lock().await = Some(new_client);
@@ -1624,70 +4138,79 @@ impl Client {
 
                             // Start message receiver forwarding with connection monitoring
                             let (tx, mut rx) = mpsc::channel(100);
-                            let receiver_is_connected = is_connected.clone();
-                            let receiver_client_id = client_id;
-                            let receiver_event_dispatcher = event_dispatcher.clone();
-                            let receiver_server_url = server_url.clone();
-                            tokio::spawn(async move {
-                                match receiver.forward_to(tx).await {
+                            // How the connection ended, for the message handler to report
+                            let (reason_tx, reason_rx) = oneshot::channel();
+                            runtime::spawn(async move {
+                                let reason = match receiver.forward_to(tx).await {
                                     Ok(_) => {
                                         tracing::info!(
-                                            "🔌 CLIENT {}: WebSocket receiver completed normally",
-                                            receiver_client_id
-                                        );
+                                            "🔌 WebSocket receiver completed normally");
+                                        DisconnectReason::ServerClosed
                                     }
                                     Err(e) => {
-                                        tracing::warn!("❌ CLIENT {}: WebSocket receiver error: {} - marking as disconnected", receiver_client_id, e);
-                                        receiver_is_connected.store(false, Ordering::Relaxed);
-                                        receiver_event_dispatcher
-                                            .emit_connection_lost(&receiver_server_url);
+                                        tracing::warn!("❌ WebSocket receiver error: {} - marking as disconnected", e);
+                                        DisconnectReason::ReceiverError
                                     }
-                                }
-                            });
+                                };
+                                let _ = reason_tx.send(reason);
+                            }.in_current_span());
 
                             // Process messages in background with connection monitoring
-                            let db_clone = db.clone();
+                            let handler_context = message_context.clone();
                             let event_dispatcher_clone = event_dispatcher.clone();
-                            let pending_uploads_clone = pending_uploads.clone();
-                            let upload_complete_notifier_clone = upload_complete_notifier.clone();
-                            let sync_protection_mode_clone = sync_protection_mode.clone();
-                            let deferred_messages_clone = deferred_messages.clone();
                             let handler_is_connected = is_connected.clone();
-                            let handler_client_id = client_id;
                             let handler_server_url = server_url.clone();
-                            tokio::spawn(async move {
-                                while let Some(msg) = rx.recv().await {
+                            let handler_shutdown_token = shutdown_token.clone();
+                            let handler_offline_mode = offline_mode.clone();
+                            let handler_auth_failed = auth_failed.clone();
+                            runtime::spawn(async move {
+                                while let Some(msg) = tokio::select! {
+                                    msg = rx.recv() => msg,
+                                    _ = handler_shutdown_token.cancelled() => None,
+                                } {
+                                    Self::note_auth_failure(
+                                        &msg,
+                                        &handler_auth_failed,
+                                        &event_dispatcher_clone,
+                                    );
                                     if let Err(e) = Self::handle_server_message_with_tracking(
                                         msg,
-                                        &db_clone,
-                                        handler_client_id,
-                                        &event_dispatcher_clone,
-                                        &pending_uploads_clone,
-                                        &upload_complete_notifier_clone,
-                                        &sync_protection_mode_clone,
-                                        &deferred_messages_clone,
+                                        &handler_context,
                                     )
                                     .await
                                     {
                                         tracing::error!(
-                                            "CLIENT {}: Error handling server message: {}",
-                                            handler_client_id,
+                                            "Error handling server message: {}",
                                             e
                                         );
                                     }
                                 }
-                                tracing::warn!("📪 CLIENT {}: Message handler terminated - marking as disconnected", handler_client_id);
+                                // Shutdown and set_offline already handle the disconnect
+                                if handler_shutdown_token.is_cancelled()
+                                    || handler_offline_mode.load(Ordering::Relaxed)
+                                {
+                                    return;
+                                }
+                                // Messages are handled in order, so a rejection
+                                // sent before the close has been noted by now
+                                let reason = if handler_auth_failed.load(Ordering::Acquire) {
+                                    DisconnectReason::AuthFailed
+                                } else {
+                                    reason_rx.await.unwrap_or(DisconnectReason::ServerClosed)
+                                };
+                                tracing::warn!("📪 Message handler terminated ({:?}) - marking as disconnected", reason);
                                 handler_is_connected.store(false, Ordering::Relaxed);
-                                event_dispatcher_clone.emit_connection_lost(&handler_server_url);
-                            });
+                                event_dispatcher_clone
+                                    .emit_connection_lost(&handler_server_url, reason);
+                            }.in_current_span());
 
                             // Clear any stale pending uploads from before disconnection
                             // These are invalid now and will be re-uploaded if needed
                             {
                                 let mut uploads = pending_uploads.lock().await;
                                 if !uploads.is_empty() {
-                                    tracing::info!("CLIENT {}: Clearing {} stale pending uploads from before reconnection",
-                                                 client_id, uploads.len());
+                                    tracing::info!("Clearing {} stale pending uploads from before reconnection",
+                                                 uploads.len());
                                     uploads.clear();
                                 }
                             }
@@ -1695,26 +4218,29 @@ impl Client {
                             // Trigger pending sync on the real sync engine via channel
                             // This will upload any pending documents and THEN request full sync
                             tracing::info!(
-                                "📤 CLIENT {}: Triggering post-reconnection sync on real engine",
-                                client_id
-                            );
+                                "📤 Triggering post-reconnection sync on real engine");
 
                             if let Err(e) = reconnect_sync_tx.try_send(()) {
                                 tracing::error!(
-                                    "CLIENT {}: Failed to trigger reconnection sync: {}",
-                                    client_id,
+                                    "Failed to trigger reconnection sync: {}",
                                     e
                                 );
                             } else {
                                 tracing::info!(
-                                    "✅ CLIENT {}: Reconnection sync trigger sent to real engine",
-                                    client_id
-                                );
+                                    "✅ Reconnection sync trigger sent to real engine");
                             }
                             // The pending sync handler will request full sync after uploads complete
                         }
+                        Err(e) if !e.is_retryable() => {
+                            tracing::warn!("🔑 Connection attempt #{} failed for good: {} - no longer reconnecting", connection_attempts, e);
+                            *reconnect_state.lock().unwrap() = None;
+                            event_dispatcher.emit_sync_error(&e.to_string());
+                            break;
+                        }
                         Err(e) => {
-                            tracing::debug!("❌ CLIENT {}: Connection attempt #{} failed: {} - will retry in {}s", client_id, connection_attempts, e, RECONNECTION_INTERVAL.as_secs());
+                            tracing::debug!("❌ Connection attempt #{} failed: {} - will retry in {}s", connection_attempts, e, RECONNECTION_INTERVAL.as_secs());
+                            *reconnect_state.lock().unwrap() =
+                                Some((connection_attempts, Instant::now() + RECONNECTION_INTERVAL));
                             event_dispatcher.emit_connection_attempted(&server_url);
                         }
                     }
@@ -1739,9 +4265,7 @@ impl Client {
                     if should_ping {
                         // Try to send a ping to verify connection is alive
                         tracing::info!(
-                            "💓 CLIENT {}: Sending heartbeat ping to verify connection",
-                            client_id
-                        );
+                            "💓 Sending heartbeat ping to verify connection");
                         let client_guard = ws_client.lock().await;
                         match client_guard.as_ref() {
                             Some(client) => {
@@ -1749,21 +4273,28 @@ impl Client {
                                     Ok(_) => {
                                         // Ping successful, update last ping time
                                         *last_ping_time.lock().await = Some(Instant::now());
-                                        tracing::info!("✅ CLIENT {}: Heartbeat ping successful - connection alive", client_id);
+                                        tracing::info!("✅ Heartbeat ping successful - connection alive");
                                     }
                                     Err(e) => {
                                         // Ping failed - connection is broken
-                                        tracing::error!("💥 CLIENT {}: Heartbeat ping FAILED: {} - marking as disconnected and starting reconnection", client_id, e);
+                                        tracing::error!("💥 Heartbeat ping FAILED: {} - marking as disconnected and starting reconnection", e);
                                         is_connected.store(false, Ordering::Relaxed);
-                                        event_dispatcher.emit_connection_lost(&server_url);
+                                        event_dispatcher.emit_connection_lost(
+                                            &server_url,
+                                            DisconnectReason::PingTimeout,
+                                        );
                                     }
                                 }
                             }
                             None => {
                                 // No client but connection flag says connected - inconsistent state
-                                tracing::error!("⚠️ CLIENT {}: Connection flag says connected but no client found - marking as disconnected", client_id);
+                                tracing::error!("⚠️ Connection flag says connected but no client found - marking as disconnected");
                                 is_connected.store(false, Ordering::Relaxed);
-                                event_dispatcher.emit_connection_lost(&server_url);
+                                // Nothing to ping the server through
+                                event_dispatcher.emit_connection_lost(
+                                    &server_url,
+                                    DisconnectReason::PingTimeout,
+                                );
                             }
                         }
                     } else {
@@ -1772,181 +4303,255 @@ impl Client {
                         match *last_ping {
                             Some(last_time) => {
                                 let elapsed = last_time.elapsed();
-                                tracing::debug!("💤 CLIENT {}: Heartbeat check - last ping was {:.1}s ago (will ping in {:.1}s)", 
-                                    client_id, elapsed.as_secs_f32(), (PING_INTERVAL - elapsed).as_secs_f32());
+                                tracing::debug!("💤 Heartbeat check - last ping was {:.1}s ago (will ping in {:.1}s)", 
+                                    elapsed.as_secs_f32(), (PING_INTERVAL - elapsed).as_secs_f32());
                             }
                             None => {
                                 tracing::debug!(
-                                    "💤 CLIENT {}: Heartbeat check - no ping sent yet",
-                                    client_id
-                                );
+                                    "💤 Heartbeat check - no ping sent yet");
                             }
                         }
                     }
                 }
 
                 // Wait before next check/retry
-                tokio::time::sleep(RECONNECTION_INTERVAL).await;
+                tokio::select! {
+                    _ = runtime::sleep(RECONNECTION_INTERVAL) => {}
+                    _ = reconnect_now.notified() => {}
+                    _ = shutdown_token.cancelled() => break,
+                }
+            }
+            reconnection_active.store(false, Ordering::Release);
+            tracing::info!("Reconnection monitor stopped");
+        }.instrument(tracing::info_span!("reconnection_loop", %client_id)));
+        self.background_tasks.lock().unwrap().push(handle);
+    }
+
+    /// Restore what the server dropped with the old connection: document
+    /// subscriptions, presence updates and held locks. A lock another client
+    /// took in the meantime is forgotten, with a sync error.
+    async fn replay_session_state(
+        ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
+        subscriptions: &std::sync::Mutex<HashSet<Uuid>>,
+        presence_subscribed: &AtomicBool,
+        held_locks: &std::sync::Mutex<HashSet<Uuid>>,
+        lock_waiters: &Arc<Mutex<HashMap<Uuid, oneshot::Sender<bool>>>>,
+        event_dispatcher: &EventDispatcher,
+    ) -> SyncResult<()> {
+        let subscribed: Vec<Uuid> = subscriptions.lock().unwrap().iter().copied().collect();
+        let locks: Vec<Uuid> = held_locks.lock().unwrap().iter().copied().collect();
+
+        let mut lock_responses = Vec::with_capacity(locks.len());
+        {
+            let ws_client = ws_client.lock().await;
+            let Some(client) = ws_client.as_ref() else {
+                return Err(ClientError::Closed)?;
+            };
+            if !subscribed.is_empty() {
+                client
+                    .send(ClientMessage::Subscribe {
+                        document_ids: subscribed,
+                    })
+                    .await?;
+            }
+            if presence_subscribed.load(Ordering::Relaxed) {
+                client.send(ClientMessage::RequestPresence).await?;
+            }
+            for id in locks {
+                let (tx, rx) = oneshot::channel();
+                lock_waiters.lock().await.insert(id, tx);
+                client
+                    .send(ClientMessage::AcquireLock { document_id: id })
+                    .await?;
+                lock_responses.push((id, rx));
+            }
+        }
+
+        for (id, rx) in lock_responses {
+            if let Ok(Ok(true)) = runtime::timeout(LOCK_TIMEOUT, rx).await {
+                continue;
+            }
+            lock_waiters.lock().await.remove(&id);
+            held_locks.lock().unwrap().remove(&id);
+            event_dispatcher.emit_sync_error(&format!(
+                "Lost the lock on document {} while reconnecting",
+                id
+            ));
+        }
+        Ok(())
+    }
+
+    /// The operation that replays a pending document. A document the server
+    /// doesn't have yet goes up as a create carrying its current content, so
+    /// edits made since are folded in; one that was also deleted before any
+    /// create was sent never needs to reach the server and is settled
+    /// locally, returning `None`. For the
+    /// rest a delete supersedes queued updates, and the latest queued patch
+    /// is sent as an update.
+    async fn replay_operation(
+        db: &Arc<dyn DocumentStore>,
+        cipher: Option<&dyn ContentCipher>,
+        pending_info: &PendingDocumentInfo,
+        doc: &Document,
+    ) -> SyncResult<Option<(UploadType, ClientMessage)>> {
+        let id = pending_info.id;
+        if pending_info.is_deleted && !pending_info.uploaded && !pending_info.create_sent {
+            tracing::info!(
+                "Doc {} was created and deleted offline, nothing to send",
+                id
+            );
+            db.remove_from_sync_queue(&id).await?;
+            db.mark_synced(&id).await?;
+            return Ok(None);
+        }
+
+        let idempotency_key = Some(db.idempotency_key(&id).await?);
+        if pending_info.is_deleted {
+            tracing::info!("Uploading pending delete for doc {}", id);
+            return Ok(Some((
+                UploadType::Delete,
+                ClientMessage::DeleteDocument {
+                    document_id: id,
+                    idempotency_key,
+                },
+            )));
+        }
+
+        // Without a confirmed create there's nothing on the server to patch.
+        // Otherwise a queued patch means an update; none means a document
+        // that became syncable again, uploaded as a create.
+        let queued_patch = if pending_info.uploaded {
+            db.get_queued_patch(&id).await.ok().flatten()
+        } else {
+            None
+        };
+        match queued_patch {
+            Some((patch, old_hash_opt)) => {
+                tracing::info!("Found queued patch for doc {} - using UpdateDocument", id);
+                let content_hash = old_hash_opt.unwrap_or_else(|| calculate_checksum(&doc.content));
+                Ok(Some((
+                    UploadType::Update,
+                    ClientMessage::UpdateDocument {
+                        patch: DocumentPatch {
+                            document_id: id,
+                            patch,
+                            content_hash,
+                        },
+                        idempotency_key,
+                    },
+                )))
+            }
+            None => {
+                tracing::info!("Uploading doc {} with CreateDocument", id);
+                Ok(Some((
+                    UploadType::Create,
+                    ClientMessage::CreateDocument {
+                        document: outgoing_document(cipher, doc)?,
+                        idempotency_key,
+                    },
+                )))
             }
-        });
+        }
     }
 
     /// Static method to perform pending sync after reconnection
     /// This is called from the reconnection loop and operates on real engine components
+    #[instrument(skip_all, fields(client_id = %client_id))]
     async fn perform_pending_sync_after_reconnection(
-        db: &Arc<ClientDatabase>,
+        db: &Arc<dyn DocumentStore>,
         ws_client: &Arc<Mutex<Option<WebSocketClient>>>,
         client_id: Uuid,
         pending_uploads: &Arc<Mutex<HashMap<Uuid, PendingUpload>>>,
+        event_dispatcher: &EventDispatcher,
+        loop_guard: &LoopGuard,
+        cipher: Option<&dyn ContentCipher>,
     ) -> SyncResult<()> {
-        tracing::info!(
-            "CLIENT {}: Starting post-reconnection pending sync using real engine components",
-            client_id
-        );
+        tracing::info!("Starting post-reconnection pending sync using real engine components");
 
         let pending_docs = db.get_pending_documents().await?;
 
         if pending_docs.is_empty() {
-            tracing::info!(
-                "CLIENT {}: No pending documents to sync after reconnection",
-                client_id
-            );
+            tracing::info!("No pending documents to sync after reconnection");
             return Ok(());
         }
 
         tracing::info!(
-            "CLIENT {}: 📤 UPLOADING {} PENDING DOCUMENTS after reconnection",
-            client_id,
+            "📤 UPLOADING {} PENDING DOCUMENTS after reconnection",
             pending_docs.len()
         );
 
+        // Documents replay in the order of their latest local edit, each as
+        // the one operation that brings the server up to date with it
+        let mut operations = Vec::with_capacity(pending_docs.len());
         for pending_info in pending_docs {
+            if !loop_guard.admit(pending_info.id, event_dispatcher) {
+                continue;
+            }
             match db.get_document(&pending_info.id).await {
                 Ok(doc) => {
-                    if pending_info.is_deleted {
-                        // Handle pending delete
-                        tracing::info!(
-                            "CLIENT {}: Uploading pending delete for doc {}",
-                            client_id,
-                            pending_info.id
-                        );
-
-                        // Track this upload
-                        pending_uploads.lock().await.insert(
-                            pending_info.id,
-                            PendingUpload {
-                                operation_type: UploadType::Delete,
-                                sent_at: Instant::now(),
-                            },
-                        );
-
-                        let ws_client_guard = ws_client.lock().await;
-                        if let Some(client) = ws_client_guard.as_ref() {
-                            client
-                                .send(ClientMessage::DeleteDocument {
-                                    document_id: pending_info.id,
-                                })
-                                .await?;
-                        } else {
-                            return Err(ClientError::WebSocket(
-                                "Not connected during reconnection sync".to_string(),
-                            ))?;
-                        }
-                    } else {
-                        // Check if we have a queued patch to determine if this is create or update
-                        // With server-authoritative versioning, we can't rely on version number anymore
-                        match db.get_queued_patch(&pending_info.id).await {
-                            Ok(Some((json_patch, old_hash_opt))) => {
-                                // Have a queued patch = this is an UPDATE
-                                tracing::info!(
-                                    "CLIENT {}: Found queued patch for doc {} - using UpdateDocument",
-                                    client_id,
-                                    pending_info.id
-                                );
-
-                                // Convert the stored patch to DocumentPatch
-                                use replicant_core::models::DocumentPatch;
-                                use replicant_core::patches::calculate_checksum;
-
-                                let content_hash = old_hash_opt
-                                    .unwrap_or_else(|| calculate_checksum(&doc.content));
-
-                                let patch_result = DocumentPatch {
-                                    document_id: pending_info.id,
-                                    patch: json_patch,
-                                    content_hash,
-                                };
-
-                                // Track this upload
-                                pending_uploads.lock().await.insert(
-                                    pending_info.id,
-                                    PendingUpload {
-                                        operation_type: UploadType::Update,
-                                        sent_at: Instant::now(),
-                                    },
-                                );
-
-                                let ws_client_guard = ws_client.lock().await;
-                                if let Some(client) = ws_client_guard.as_ref() {
-                                    client
-                                        .send(ClientMessage::UpdateDocument {
-                                            patch: patch_result,
-                                        })
-                                        .await?;
-                                } else {
-                                    return Err(ClientError::WebSocket(
-                                        "Not connected during reconnection sync".to_string(),
-                                    ))?;
-                                }
-                            }
-                            Ok(None) | Err(_) => {
-                                // No queued patch = this is a CREATE
-                                tracing::info!(
-                                    "CLIENT {}: No queued patch for doc {} - using CreateDocument",
-                                    client_id,
-                                    pending_info.id
-                                );
-
-                                // Track this upload
-                                pending_uploads.lock().await.insert(
-                                    pending_info.id,
-                                    PendingUpload {
-                                        operation_type: UploadType::Create,
-                                        sent_at: Instant::now(),
-                                    },
-                                );
+                    let Some((operation_type, operation)) =
+                        Self::replay_operation(db, cipher, &pending_info, &doc).await?
+                    else {
+                        continue;
+                    };
 
-                                let ws_client_guard = ws_client.lock().await;
-                                if let Some(client) = ws_client_guard.as_ref() {
-                                    client
-                                        .send(ClientMessage::CreateDocument {
-                                            document: doc.clone(),
-                                        })
-                                        .await?;
-                                } else {
-                                    return Err(ClientError::WebSocket(
-                                        "Not connected during reconnection sync".to_string(),
-                                    ))?;
-                                }
-                            }
-                        }
-                    }
+                    // Track this upload
+                    let upload = PendingUpload::start(operation_type, db, &pending_info.id).await?;
+                    pending_uploads.lock().await.insert(pending_info.id, upload);
+                    operations.push(operation);
                 }
                 Err(e) => {
-                    tracing::error!(
-                        "CLIENT {}: Failed to get pending document {}: {}",
-                        client_id,
-                        pending_info.id,
-                        e
-                    );
+                    tracing::error!("Failed to get pending document {}: {}", pending_info.id, e);
                 }
             }
         }
 
+        // Send everything in a few batches; the responses are matched to
+        // pending uploads as they arrive rather than awaited one by one
+        let ws_client_guard = ws_client.lock().await;
+        let Some(client) = ws_client_guard.as_ref() else {
+            return Err(ClientError::Closed)?;
+        };
+        let operation_count = operations.len();
+        let frames = client.send_batched(operations).await?;
+
         tracing::info!(
-            "CLIENT {}: ✅ Completed uploading pending documents after reconnection",
-            client_id
+            "✅ Completed uploading {} pending documents in {} messages after reconnection",
+            operation_count,
+            frames
         );
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_single_reconnection_loop() {
+        let client = Client::new(
+            &format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()),
+            "ws://127.0.0.1:1/ws",
+            "reconnect@test.local",
+            "key",
+            "secret",
+        )
+        .await
+        .unwrap();
+        // Message handler, reconnection sync handler and the reconnection loop
+        assert_eq!(client.background_tasks.lock().unwrap().len(), 3);
+
+        // Send failures in quick succession each ask for a reconnection loop
+        for _ in 0..5 {
+            client.start_reconnection_loop();
+        }
+        assert_eq!(client.background_tasks.lock().unwrap().len(), 3);
+        let reconnection_active = client.reconnection_active.clone();
+        assert!(reconnection_active.load(Ordering::Acquire));
+
+        // The flag is cleared once the loop exits
+        client.shutdown().await.unwrap();
+        assert!(!reconnection_active.load(Ordering::Acquire));
+    }
+}
@@ -1,23 +1,133 @@
-use crate::queries::{DbHelpers, Queries};
-use json_patch;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::backup::{ExportedDocument, ImportMode, QueuedOperation};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::queries::{DbHelpers, DocumentParams, Queries};
+#[cfg(not(target_arch = "wasm32"))]
 use replicant_core::protocol::ChangeEventType;
+#[cfg(not(target_arch = "wasm32"))]
 use replicant_core::{
-    models::{Document, SyncStatus},
-    SyncResult,
+    models::{extract_tags, user_id_from_email, Document, SyncStatus, DEFAULT_APP_NAMESPACE},
+    SyncError, SyncResult,
 };
-use sqlx::{sqlite::SqlitePoolOptions, Row, SqlitePool};
+#[cfg(not(target_arch = "wasm32"))]
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqlitePoolOptions, SqliteRow},
+    Row, Sqlite, SqlitePool, Transaction,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
 use uuid::Uuid;
 
+#[cfg(not(target_arch = "wasm32"))]
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct PendingDocumentInfo {
     pub id: Uuid,
     pub is_deleted: bool,
+    /// The server has the document. Until a create is confirmed it is
+    /// replayed as a create carrying its current content.
+    pub uploaded: bool,
+    /// A create was sent, confirmed or not, so the server may have the
+    /// document even when it isn't `uploaded`
+    pub create_sent: bool,
+}
+
+/// Both versions of a document whose local edit lost a conflict
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictRecord {
+    pub document_id: Uuid,
+    pub local_content: serde_json::Value,
+    pub server_content: serde_json::Value,
+    pub detected_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A binary attachment, stored apart from its document's content
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub name: String,
+    pub size: u64,
+    pub data: Vec<u8>,
+    /// Bytes the server has acknowledged, where an upload resumes
+    pub uploaded: u64,
+}
+
+/// Sort order for [`ClientDatabase::get_documents_page`]. Ties are broken by
+/// document ID so pages never overlap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentOrder {
+    /// Most recently updated first
+    #[default]
+    UpdatedDesc,
+    UpdatedAsc,
+    /// Newest first
+    CreatedDesc,
+    CreatedAsc,
+    /// Alphabetical by title
+    Title,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DocumentOrder {
+    fn order_by(self) -> &'static str {
+        match self {
+            DocumentOrder::UpdatedDesc => "updated_at DESC, id",
+            DocumentOrder::UpdatedAsc => "updated_at ASC, id",
+            DocumentOrder::CreatedDesc => "created_at DESC, id",
+            DocumentOrder::CreatedAsc => "created_at ASC, id",
+            DocumentOrder::Title => "title, id",
+        }
+    }
+}
+
+/// What [`crate::Client::repair`] found out of step between the documents
+/// and the sync queue
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    /// Queue entries left over for synced or local-only documents, removed
+    pub stale_queue_entries: u64,
+    /// Pending documents the server already has but with nothing queued,
+    /// re-queued so they upload as updates rather than creates
+    pub requeued_documents: Vec<Uuid>,
+}
+
+impl RepairReport {
+    pub fn is_clean(&self) -> bool {
+        self.stale_queue_entries == 0 && self.requeued_documents.is_empty()
+    }
+}
+
+/// What [`crate::Client::compact`] removed and how much space it gave back
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Queue entries left over for synced or local-only documents
+    pub queue_entries_removed: u64,
+    /// Deleted documents past the history retention, dropped entirely
+    pub documents_purged: u64,
+    /// Change events past the history retention
+    pub change_events_removed: u64,
+    /// Database size before compacting, in bytes
+    pub bytes_before: u64,
+    /// Database size after compacting, in bytes
+    pub bytes_after: u64,
+}
+
+impl CompactReport {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 pub struct ClientDatabase {
     pub pool: SqlitePool,
+    compress_content: bool,
 }
 
+#[cfg(not(target_arch = "wasm32"))]
 impl ClientDatabase {
     pub async fn new(database_url: &str) -> SyncResult<Self> {
         let pool = SqlitePoolOptions::new()
@@ -25,11 +135,76 @@ impl ClientDatabase {
             .connect(database_url)
             .await?;
 
-        Ok(Self { pool })
+        Ok(Self {
+            pool,
+            compress_content: false,
+        })
+    }
+
+    /// Store document content zstd-compressed, trading CPU for disk and IO on
+    /// large documents. Rows are compressed as they are next written, and
+    /// compressed rows stay readable with this turned off again.
+    ///
+    /// A compressed row keeps an empty object in the `content` column, so SQL
+    /// that reads `content` directly must decompress `content_zstd` instead.
+    /// Full-text search handles both.
+    pub fn with_compressed_content(mut self, enabled: bool) -> Self {
+        self.compress_content = enabled;
+        self
+    }
+
+    fn document_params(
+        &self,
+        doc: &Document,
+        sync_status: Option<SyncStatus>,
+    ) -> SyncResult<DocumentParams> {
+        let mut params = DbHelpers::document_to_params(doc, sync_status)?;
+        if self.compress_content {
+            params.9 = Some(DbHelpers::compress_content(&params.2)?);
+            params.2 = "{}".to_string();
+        }
+        Ok(params)
+    }
+
+    /// Latest schema version this build knows how to migrate to
+    pub fn supported_schema_version() -> i64 {
+        MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0)
+    }
+
+    /// Schema version recorded in the database, or 0 if it predates versioning
+    /// or has never been migrated
+    pub async fn schema_version(&self) -> SyncResult<i64> {
+        let has_table = sqlx::query(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        )
+        .fetch_optional(&self.pool)
+        .await?
+        .is_some();
+        if !has_table {
+            return Ok(0);
+        }
+
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_version")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(version.unwrap_or(0))
     }
 
+    /// Bring the schema up to date. Refuses to touch a database written by a
+    /// newer build, e.g. after the app was downgraded, since the older
+    /// migrations and queries could corrupt it.
     pub async fn run_migrations(&self) -> SyncResult<()> {
-        sqlx::migrate!("./migrations").run(&self.pool).await?;
+        let found = self.schema_version().await?;
+        let supported = Self::supported_schema_version();
+        if found > supported {
+            return Err(SyncError::SchemaTooNew { found, supported });
+        }
+
+        MIGRATOR.run(&self.pool).await?;
+        sqlx::query("UPDATE schema_version SET version = ?1")
+            .bind(supported)
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
@@ -89,44 +264,76 @@ impl ClientDatabase {
         Ok(())
     }
 
-    fn generate_deterministic_user_id(user_identifier: &str) -> Uuid {
-        // Generate deterministic user ID using UUID v5
-        // Use the same logic as the task list example
-        const APP_ID: &str = "com.example.sync-task-list";
-
-        // Create a two-level namespace hierarchy:
-        // 1. DNS namespace -> Application namespace (using APP_ID)
-        // 2. Application namespace -> User ID (using user identifier)
-        let app_namespace = Uuid::new_v5(&Uuid::NAMESPACE_DNS, APP_ID.as_bytes());
-        Uuid::new_v5(&app_namespace, user_identifier.as_bytes())
+    pub(crate) fn generate_deterministic_user_id(user_identifier: &str) -> Uuid {
+        // Must match the server's ID for the same email
+        user_id_from_email(DEFAULT_APP_NAMESPACE, user_identifier)
     }
 
+    /// Fails with `SyncError::NotInitialized` until a user is configured
     pub async fn get_user_id(&self) -> SyncResult<Uuid> {
-        let row = sqlx::query(Queries::GET_USER_ID)
-            .fetch_one(&self.pool)
-            .await?;
-
-        let user_id: String = row.try_get("user_id")?;
-        Ok(Uuid::parse_str(&user_id)?)
+        let row = self.user_config_row(Queries::GET_USER_ID).await?;
+        Self::config_id(&row, "user_id")
     }
 
+    /// Fails with `SyncError::NotInitialized` until a user is configured
     pub async fn get_client_id(&self) -> SyncResult<Uuid> {
-        let row = sqlx::query(Queries::GET_CLIENT_ID)
-            .fetch_one(&self.pool)
+        let row = self.user_config_row(Queries::GET_CLIENT_ID).await?;
+        Self::config_id(&row, "client_id")
+    }
+
+    /// Fails with `SyncError::NotInitialized` until a user is configured
+    pub async fn get_user_and_client_id(&self) -> SyncResult<(Uuid, Uuid)> {
+        let row = self
+            .user_config_row(Queries::GET_USER_AND_CLIENT_ID)
             .await?;
+        Ok((
+            Self::config_id(&row, "user_id")?,
+            Self::config_id(&row, "client_id")?,
+        ))
+    }
 
-        let client_id: String = row.try_get("client_id")?;
-        Ok(Uuid::parse_str(&client_id)?)
+    /// Whether a user has been configured, i.e. first-time setup has run
+    pub async fn is_initialized(&self) -> SyncResult<bool> {
+        match self.get_user_and_client_id().await {
+            Ok(_) => Ok(true),
+            Err(SyncError::NotInitialized) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
-    pub async fn get_user_and_client_id(&self) -> SyncResult<(Uuid, Uuid)> {
-        let row = sqlx::query(Queries::GET_USER_AND_CLIENT_ID)
-            .fetch_one(&self.pool)
+    async fn user_config_row(&self, query: &str) -> SyncResult<SqliteRow> {
+        sqlx::query(query)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(SyncError::NotInitialized)
+    }
+
+    // A missing or unparseable ID leaves the row as good as absent
+    fn config_id(row: &SqliteRow, column: &str) -> SyncResult<Uuid> {
+        row.try_get::<Option<String>, _>(column)?
+            .and_then(|id| Uuid::parse_str(&id).ok())
+            .ok_or(SyncError::NotInitialized)
+    }
+
+    /// Record when a sync with the server last completed
+    pub async fn set_last_sync_at(&self, at: chrono::DateTime<chrono::Utc>) -> SyncResult<()> {
+        sqlx::query("UPDATE user_config SET last_sync_at = ?")
+            .bind(at.to_rfc3339())
+            .execute(&self.pool)
             .await?;
+        Ok(())
+    }
 
-        let user_id: String = row.try_get("user_id")?;
-        let client_id: String = row.try_get("client_id")?;
-        Ok((Uuid::parse_str(&user_id)?, Uuid::parse_str(&client_id)?))
+    pub async fn get_last_sync_at(&self) -> SyncResult<Option<chrono::DateTime<chrono::Utc>>> {
+        let last_sync_at: Option<String> =
+            sqlx::query_scalar("SELECT last_sync_at FROM user_config LIMIT 1")
+                .fetch_optional(&self.pool)
+                .await?
+                .flatten();
+        Ok(last_sync_at
+            .map(|at| chrono::DateTime::parse_from_rfc3339(&at))
+            .transpose()?
+            .map(|at| at.with_timezone(&chrono::Utc)))
     }
 
     pub async fn get_document(&self, id: &Uuid) -> SyncResult<Document> {
@@ -142,6 +349,64 @@ impl ClientDatabase {
         self.save_document_with_status(doc, None).await
     }
 
+    /// Save a new document that stays on this device. It is never queued or
+    /// uploaded until [`ClientDatabase::set_local_only`] clears the flag.
+    pub(crate) async fn save_local_document(&self, doc: &Document) -> SyncResult<()> {
+        let params = self.document_params(doc, Some(SyncStatus::Pending))?;
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(Queries::UPSERT_DOCUMENT)
+            .bind(params.0) // id
+            .bind(params.1) // user_id
+            .bind(params.2) // content
+            .bind(params.3) // version
+            .bind(params.4) // created_at
+            .bind(params.5) // updated_at
+            .bind(params.6) // deleted_at
+            .bind(params.7) // sync_status
+            .bind(params.8) // title
+            .bind(params.9) // content_zstd
+            .bind(params.10) // expires_at
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE documents SET local_only = 1 WHERE id = ?")
+            .bind(doc.id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        Self::next_local_seq_in_tx(&mut tx, &doc.id).await?;
+
+        Self::update_indexes_in_tx(&mut tx, &doc.id).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn is_local_only(&self, document_id: &Uuid) -> SyncResult<bool> {
+        let local_only: bool = sqlx::query_scalar("SELECT local_only FROM documents WHERE id = ?")
+            .bind(document_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(local_only)
+    }
+
+    /// Mark a document local-only or syncable. Either way it is left pending,
+    /// so a document that becomes syncable is uploaded as a create.
+    pub async fn set_local_only(&self, document_id: &Uuid, local_only: bool) -> SyncResult<()> {
+        sqlx::query(
+            "UPDATE documents SET local_only = ?, sync_status = ?, idempotency_key = NULL \
+             WHERE id = ?",
+        )
+        .bind(local_only)
+        .bind(SyncStatus::Pending.to_string())
+        .bind(document_id.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
     pub(crate) async fn save_document_with_status(
         &self,
         doc: &Document,
@@ -158,7 +423,10 @@ impl ClientDatabase {
             doc.sync_revision
         );
 
-        let params = DbHelpers::document_to_params(doc, sync_status)?;
+        let params = self.document_params(doc, sync_status)?;
+
+        // Document and FTS entry are written together so search never goes stale
+        let mut tx = self.pool.begin().await?;
 
         sqlx::query(Queries::UPSERT_DOCUMENT)
             .bind(params.0) // id
@@ -170,16 +438,22 @@ impl ClientDatabase {
             .bind(params.6) // deleted_at
             .bind(params.7) // sync_status
             .bind(params.8) // title
-            .execute(&self.pool)
+            .bind(params.9) // content_zstd
+            .bind(params.10) // expires_at
+            .execute(&mut *tx)
             .await?;
 
-        tracing::info!("DATABASE: ✅ Document {} saved successfully", doc.id);
-
-        // Update FTS index for this document
-        if let Err(e) = self.update_fts_for_document(&doc.id).await {
-            tracing::warn!("FTS: Failed to update index for {}: {:?}", doc.id, e);
+        // Only local changes are saved as pending; server copies keep their seq
+        if sync_status == Some(SyncStatus::Pending) {
+            Self::next_local_seq_in_tx(&mut tx, &doc.id).await?;
         }
 
+        Self::update_indexes_in_tx(&mut tx, &doc.id).await?;
+
+        tx.commit().await?;
+
+        tracing::info!("DATABASE: ✅ Document {} saved successfully", doc.id);
+
         Ok(())
     }
 
@@ -200,12 +474,15 @@ impl ClientDatabase {
             let doc_info = PendingDocumentInfo {
                 id: Uuid::parse_str(&id)?,
                 is_deleted: deleted_at.is_some(),
+                uploaded: row.try_get("uploaded")?,
+                create_sent: row.try_get("create_sent")?,
             };
 
             tracing::info!(
-                "DATABASE: Pending doc: {} | Deleted: {}",
+                "DATABASE: Pending doc: {} | Deleted: {} | Uploaded: {}",
                 doc_info.id,
-                doc_info.is_deleted
+                doc_info.is_deleted,
+                doc_info.uploaded
             );
 
             pending_docs.push(doc_info);
@@ -232,6 +509,182 @@ impl ClientDatabase {
         Ok(())
     }
 
+    pub async fn get_sync_status(&self, document_id: &Uuid) -> SyncResult<SyncStatus> {
+        let status: String = sqlx::query_scalar("SELECT sync_status FROM documents WHERE id = ?")
+            .bind(document_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        status
+            .parse()
+            .map_err(|_| SyncError::InvalidOperation(format!("Unknown sync status '{}'", status)))
+    }
+
+    pub async fn set_sync_status(&self, document_id: &Uuid, status: SyncStatus) -> SyncResult<()> {
+        sqlx::query(Queries::UPDATE_SYNC_STATUS)
+            .bind(document_id.to_string())
+            .bind(status.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Idempotency key for the document's pending operation. The key is
+    /// created on first use and kept until the document or its sync status is
+    /// written again, so resending an unchanged operation reuses it.
+    pub async fn idempotency_key(&self, document_id: &Uuid) -> SyncResult<Uuid> {
+        sqlx::query(
+            "UPDATE documents SET idempotency_key = ? WHERE id = ? AND idempotency_key IS NULL",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(document_id.to_string())
+        .execute(&self.pool)
+        .await?;
+
+        let key: String = sqlx::query_scalar("SELECT idempotency_key FROM documents WHERE id = ?")
+            .bind(document_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(Uuid::parse_str(&key)?)
+    }
+
+    /// Record both sides of a conflict, replacing any earlier one for the document
+    pub async fn save_conflict(
+        &self,
+        document_id: &Uuid,
+        local_content: &serde_json::Value,
+        server_content: &serde_json::Value,
+    ) -> SyncResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO conflicts (document_id, local_content, server_content, detected_at)
+             VALUES (?, ?, ?, ?)",
+        )
+        .bind(document_id.to_string())
+        .bind(serde_json::to_string(local_content)?)
+        .bind(serde_json::to_string(server_content)?)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_conflict(&self, document_id: &Uuid) -> SyncResult<Option<ConflictRecord>> {
+        let row = sqlx::query(
+            "SELECT local_content, server_content, detected_at FROM conflicts WHERE document_id = ?",
+        )
+        .bind(document_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let local_content: String = row.try_get("local_content")?;
+        let server_content: String = row.try_get("server_content")?;
+        let detected_at: String = row.try_get("detected_at")?;
+        Ok(Some(ConflictRecord {
+            document_id: *document_id,
+            local_content: serde_json::from_str(&local_content)?,
+            server_content: serde_json::from_str(&server_content)?,
+            detected_at: chrono::DateTime::parse_from_rfc3339(&detected_at)?
+                .with_timezone(&chrono::Utc),
+        }))
+    }
+
+    pub async fn delete_conflict(&self, document_id: &Uuid) -> SyncResult<()> {
+        sqlx::query("DELETE FROM conflicts WHERE document_id = ?")
+            .bind(document_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Store an attachment, replacing any with the same ID
+    pub async fn save_attachment(&self, attachment: &Attachment) -> SyncResult<()> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO attachments (id, document_id, name, size, data, uploaded)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(attachment.id.to_string())
+        .bind(attachment.document_id.to_string())
+        .bind(&attachment.name)
+        .bind(attachment.size as i64)
+        .bind(&attachment.data)
+        .bind(attachment.uploaded as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn get_attachment(&self, id: &Uuid) -> SyncResult<Option<Attachment>> {
+        let row = sqlx::query(
+            "SELECT document_id, name, size, data, uploaded FROM attachments WHERE id = ?",
+        )
+        .bind(id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let document_id: String = row.try_get("document_id")?;
+        let size: i64 = row.try_get("size")?;
+        let uploaded: i64 = row.try_get("uploaded")?;
+        Ok(Some(Attachment {
+            id: *id,
+            document_id: Uuid::parse_str(&document_id)?,
+            name: row.try_get("name")?,
+            size: size as u64,
+            data: row.try_get("data")?,
+            uploaded: uploaded as u64,
+        }))
+    }
+
+    pub async fn set_attachment_uploaded(&self, id: &Uuid, uploaded: u64) -> SyncResult<()> {
+        sqlx::query("UPDATE attachments SET uploaded = ? WHERE id = ?")
+            .bind(uploaded as i64)
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Attachments the server doesn't hold in full yet, oldest first
+    pub async fn get_pending_attachments(&self) -> SyncResult<Vec<Uuid>> {
+        let ids: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM attachments WHERE uploaded < size ORDER BY created_at, rowid",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        ids.iter().map(|id| Ok(Uuid::parse_str(id)?)).collect()
+    }
+
+    pub async fn delete_attachment(&self, id: &Uuid) -> SyncResult<()> {
+        sqlx::query("DELETE FROM attachments WHERE id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Adopt the server's timestamps for a document. `None` leaves a
+    /// timestamp unchanged.
+    pub async fn set_server_timestamps(
+        &self,
+        document_id: &Uuid,
+        created_at: Option<chrono::DateTime<chrono::Utc>>,
+        updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> SyncResult<()> {
+        sqlx::query(
+            "UPDATE documents SET created_at = COALESCE(?, created_at), updated_at = COALESCE(?, updated_at) WHERE id = ?",
+        )
+        .bind(created_at.map(|dt| dt.to_rfc3339()))
+        .bind(updated_at.map(|dt| dt.to_rfc3339()))
+        .bind(document_id.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
     pub async fn update_sync_revision(
         &self,
         document_id: &Uuid,
@@ -259,23 +712,114 @@ impl ClientDatabase {
     }
 
     pub async fn delete_document(&self, document_id: &Uuid) -> SyncResult<()> {
-        sqlx::query("UPDATE documents SET deleted_at = ?, sync_status = ? WHERE id = ?")
-            .bind(chrono::Utc::now())
-            .bind(SyncStatus::Pending.to_string())
-            .bind(document_id.to_string())
-            .execute(&self.pool)
-            .await?;
+        let mut tx = self.pool.begin().await?;
+
+        let deleted = sqlx::query(
+            "UPDATE documents SET deleted_at = ?, sync_status = ?, idempotency_key = NULL \
+             WHERE id = ?",
+        )
+        .bind(chrono::Utc::now())
+        .bind(SyncStatus::Pending.to_string())
+        .bind(document_id.to_string())
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if deleted > 0 {
+            Self::next_local_seq_in_tx(&mut tx, document_id).await?;
+        }
+
+        // Remove from FTS index (the entry query skips deleted documents)
+        Self::update_indexes_in_tx(&mut tx, document_id).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Save `documents`, each with its own sync status, and remove the
+    /// `removed` documents entirely, in one transaction
+    pub async fn save_documents_atomically(
+        &self,
+        documents: &[Document],
+        removed: &[Uuid],
+    ) -> SyncResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        for doc in documents {
+            let params = self.document_params(doc, Some(doc.sync_status))?;
+            sqlx::query(Queries::UPSERT_DOCUMENT)
+                .bind(params.0) // id
+                .bind(params.1) // user_id
+                .bind(params.2) // content
+                .bind(params.3) // version
+                .bind(params.4) // created_at
+                .bind(params.5) // updated_at
+                .bind(params.6) // deleted_at
+                .bind(params.7) // sync_status
+                .bind(params.8) // title
+                .bind(params.9) // content_zstd
+                .bind(params.10) // expires_at
+                .execute(&mut *tx)
+                .await?;
+
+            Self::update_indexes_in_tx(&mut tx, &doc.id).await?;
+        }
+
+        for document_id in removed {
+            let id = document_id.to_string();
+            sqlx::query("DELETE FROM sync_queue WHERE document_id = ?")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM conflicts WHERE document_id = ?")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM documents WHERE id = ?")
+                .bind(&id)
+                .execute(&mut *tx)
+                .await?;
 
-        // Remove from FTS index
-        if let Err(e) = self.update_fts_for_document(document_id).await {
-            tracing::warn!("FTS: Failed to remove {} from index: {:?}", document_id, e);
+            Self::update_indexes_in_tx(&mut tx, document_id).await?;
         }
 
+        tx.commit().await?;
+
         Ok(())
     }
 
+    /// Every live document. Expired documents are left out even before the
+    /// server's sweep deletes them.
     pub async fn get_all_documents(&self) -> SyncResult<Vec<Document>> {
-        let rows = sqlx::query("SELECT * FROM documents WHERE deleted_at IS NULL")
+        let rows = sqlx::query(
+            "SELECT * FROM documents WHERE deleted_at IS NULL \
+             AND (expires_at IS NULL OR julianday(expires_at) > julianday('now'))",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| DbHelpers::parse_document(&row))
+            .collect()
+    }
+
+    /// Up to `limit` documents starting `offset` into `order`, so long lists
+    /// can be shown without loading every document
+    pub async fn get_documents_page(
+        &self,
+        offset: i64,
+        limit: i64,
+        order: DocumentOrder,
+    ) -> SyncResult<Vec<Document>> {
+        let query = format!(
+            "SELECT * FROM documents WHERE deleted_at IS NULL \
+             AND (expires_at IS NULL OR julianday(expires_at) > julianday('now')) \
+             ORDER BY {} LIMIT ? OFFSET ?",
+            order.order_by()
+        );
+        let rows = sqlx::query(&query)
+            .bind(limit)
+            .bind(offset)
             .fetch_all(&self.pool)
             .await?;
 
@@ -283,11 +827,50 @@ impl ClientDatabase {
             .map(|row| DbHelpers::parse_document(&row))
             .collect()
     }
+
+    /// Documents updated after `since`, oldest first. Deleted documents are
+    /// left out unless `include_deleted` is set.
+    pub async fn get_documents_changed_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        include_deleted: bool,
+    ) -> SyncResult<Vec<Document>> {
+        // Timestamps are stored as RFC 3339 text with varying precision, so
+        // SQLite narrows the rows down and the exact comparison happens here
+        let rows = sqlx::query(
+            "SELECT * FROM documents
+             WHERE julianday(updated_at) >= julianday(?) AND (? OR deleted_at IS NULL)",
+        )
+        .bind(since.to_rfc3339())
+        .bind(include_deleted)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut documents = rows
+            .into_iter()
+            .map(|row| DbHelpers::parse_document(&row))
+            .collect::<SyncResult<Vec<_>>>()?;
+        documents.retain(|doc| doc.updated_at > since);
+        documents.sort_by_key(|doc| doc.updated_at);
+        Ok(documents)
+    }
+
+    /// How many documents [`ClientDatabase::get_all_documents`] returns
     pub async fn count_documents(&self) -> SyncResult<i64> {
-        let count: i64 =
-            sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE deleted_at IS NULL")
-                .fetch_one(&self.pool)
-                .await?;
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM documents WHERE deleted_at IS NULL \
+             AND (expires_at IS NULL OR julianday(expires_at) > julianday('now'))",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    pub async fn count_conflicts(&self) -> SyncResult<i64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents WHERE sync_status = ?")
+            .bind(SyncStatus::Conflict.to_string())
+            .fetch_one(&self.pool)
+            .await?;
         Ok(count)
     }
 
@@ -344,19 +927,20 @@ impl ClientDatabase {
     }
 
     /// CRITICAL: Atomically save document and queue patch
-    /// This prevents data loss if app crashes between separate operations
+    /// This prevents data loss if app crashes between separate operations.
+    /// Returns the edit's `local_seq`, which the queue entry records too.
     pub async fn save_document_and_queue_patch(
         &self,
         doc: &Document,
         patch: &json_patch::Patch,
         operation_type: ChangeEventType,
         old_content_hash: Option<String>,
-    ) -> SyncResult<()> {
+    ) -> SyncResult<i64> {
         // Start a transaction for atomicity
         let mut tx = self.pool.begin().await?;
 
         // Save document with pending status (in transaction)
-        let params = DbHelpers::document_to_params(doc, Some(SyncStatus::Pending))?;
+        let params = self.document_params(doc, Some(SyncStatus::Pending))?;
 
         sqlx::query(Queries::UPSERT_DOCUMENT)
             .bind(params.0) // id
@@ -368,23 +952,33 @@ impl ClientDatabase {
             .bind(params.6) // deleted_at
             .bind(params.7) // sync_status
             .bind(params.8) // title
+            .bind(params.9) // content_zstd
+            .bind(params.10) // expires_at
             .execute(&mut *tx)
             .await?;
 
+        let local_seq = Self::next_local_seq_in_tx(&mut tx, &doc.id).await?;
+
+        // Local-only documents are never queued for sync
+        let local_only: bool = sqlx::query_scalar("SELECT local_only FROM documents WHERE id = ?")
+            .bind(doc.id.to_string())
+            .fetch_one(&mut *tx)
+            .await?;
+
         // Queue sync operation (in transaction)
         let patch_json = serde_json::to_string(patch)?;
 
         // Store old_content_hash if provided (for update operations)
-        if let Some(hash) = old_content_hash {
-            sqlx::query(
-                "INSERT INTO sync_queue (document_id, operation_type, patch, old_content_hash) VALUES (?, ?, ?, ?)"
-            )
-            .bind(doc.id.to_string())
-            .bind(operation_type.to_string())
-            .bind(patch_json)
-            .bind(hash)
-            .execute(&mut *tx)
-            .await?;
+        if local_only {
+            tracing::debug!("DATABASE: Not queueing patch for local-only doc {}", doc.id);
+        } else if let Some(hash) = old_content_hash {
+            sqlx::query(Queries::INSERT_SYNC_QUEUE_WITH_HASH)
+                .bind(doc.id.to_string())
+                .bind(operation_type.to_string())
+                .bind(patch_json)
+                .bind(hash)
+                .execute(&mut *tx)
+                .await?;
         } else {
             sqlx::query(Queries::INSERT_SYNC_QUEUE)
                 .bind(doc.id.to_string()) // document_id
@@ -394,19 +988,51 @@ impl ClientDatabase {
                 .await?;
         }
 
-        // Commit atomically - both operations succeed or both fail
+        Self::update_indexes_in_tx(&mut tx, &doc.id).await?;
+
+        // Commit atomically - all operations succeed or all fail
         tx.commit().await?;
 
         tracing::info!(
-            "DATABASE: Atomically saved document {} with pending status and queued patch",
-            doc.id
+            "DATABASE: Atomically saved document {} with pending status and queued patch (local_seq {})",
+            doc.id,
+            local_seq
         );
 
-        // Update FTS index for this document
-        if let Err(e) = self.update_fts_for_document(&doc.id).await {
-            tracing::warn!("FTS: Failed to update index for {}: {:?}", doc.id, e);
-        }
+        Ok(local_seq)
+    }
+
+    /// Give a document the next local edit number, as part of the caller's
+    /// transaction
+    async fn next_local_seq_in_tx(
+        tx: &mut Transaction<'_, Sqlite>,
+        document_id: &Uuid,
+    ) -> SyncResult<i64> {
+        let local_seq = sqlx::query_scalar(Queries::NEXT_LOCAL_SEQ)
+            .bind(document_id.to_string())
+            .fetch_one(&mut **tx)
+            .await?;
+        Ok(local_seq)
+    }
+
+    /// Number of the document's latest local edit. Unlike `sync_revision`
+    /// it changes on every local save and never comes from the server; 0
+    /// means the document was never edited here.
+    pub async fn local_seq(&self, document_id: &Uuid) -> SyncResult<i64> {
+        let local_seq = sqlx::query_scalar(Queries::GET_LOCAL_SEQ)
+            .bind(document_id.to_string())
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(local_seq)
+    }
 
+    /// Record that a create for the document went out, see
+    /// [`PendingDocumentInfo::create_sent`]
+    pub async fn mark_create_sent(&self, document_id: &Uuid) -> SyncResult<()> {
+        sqlx::query("UPDATE documents SET create_sent = 1 WHERE id = ?")
+            .bind(document_id.to_string())
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
@@ -415,7 +1041,7 @@ impl ClientDatabase {
         document_id: &Uuid,
     ) -> SyncResult<Option<(json_patch::Patch, Option<String>)>> {
         let row = sqlx::query(
-            "SELECT patch, old_content_hash FROM sync_queue WHERE document_id = ? AND operation_type = 'update' ORDER BY created_at DESC LIMIT 1"
+            "SELECT patch, old_content_hash FROM sync_queue WHERE document_id = ? AND operation_type = 'update' ORDER BY local_seq DESC, id DESC LIMIT 1"
         )
         .bind(document_id.to_string())
         .fetch_optional(&self.pool)
@@ -434,6 +1060,72 @@ impl ClientDatabase {
         }
     }
 
+    /// Get all queued update patches for a document, oldest first
+    pub async fn get_queued_patches(
+        &self,
+        document_id: &Uuid,
+    ) -> SyncResult<Vec<json_patch::Patch>> {
+        let rows = sqlx::query(
+            "SELECT patch FROM sync_queue WHERE document_id = ? AND operation_type = 'update' AND patch IS NOT NULL ORDER BY local_seq ASC, id ASC"
+        )
+        .bind(document_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut patches = Vec::with_capacity(rows.len());
+        for row in rows {
+            let json: String = row.try_get("patch")?;
+            patches.push(serde_json::from_str(&json)?);
+        }
+        Ok(patches)
+    }
+
+    /// Atomically replace all queued operations for a document with a single
+    /// rebased update patch (used after the server rejects a stale update)
+    pub async fn replace_queued_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        old_content_hash: String,
+    ) -> SyncResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM sync_queue WHERE document_id = ?")
+            .bind(doc.id.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+        let params = self.document_params(doc, Some(SyncStatus::Pending))?;
+        sqlx::query(Queries::UPSERT_DOCUMENT)
+            .bind(params.0) // id
+            .bind(params.1) // user_id
+            .bind(params.2) // content
+            .bind(params.3) // version
+            .bind(params.4) // created_at
+            .bind(params.5) // updated_at
+            .bind(params.6) // deleted_at
+            .bind(params.7) // sync_status
+            .bind(params.8) // title
+            .bind(params.9) // content_zstd
+            .bind(params.10) // expires_at
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(Queries::INSERT_SYNC_QUEUE_WITH_HASH)
+            .bind(doc.id.to_string())
+            .bind(ChangeEventType::Update.to_string())
+            .bind(serde_json::to_string(patch)?)
+            .bind(old_content_hash)
+            .execute(&mut *tx)
+            .await?;
+
+        Self::update_indexes_in_tx(&mut tx, &doc.id).await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
     pub async fn remove_from_sync_queue(&self, document_id: &Uuid) -> SyncResult<()> {
         sqlx::query("DELETE FROM sync_queue WHERE document_id = ?")
             .bind(document_id.to_string())
@@ -442,6 +1134,320 @@ impl ClientDatabase {
         Ok(())
     }
 
+    /// Remove a document's queue entries for edits up to `local_seq`,
+    /// keeping any made after them
+    pub async fn remove_from_sync_queue_through(
+        &self,
+        document_id: &Uuid,
+        local_seq: i64,
+    ) -> SyncResult<()> {
+        sqlx::query("DELETE FROM sync_queue WHERE document_id = ? AND local_seq <= ?")
+            .bind(document_id.to_string())
+            .bind(local_seq)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // ===== Repair =====
+
+    /// Drop queue entries left behind for synced or local-only documents,
+    /// returning how many were removed. A crash between `mark_synced` and
+    /// `remove_from_sync_queue` leaves these, and they would later turn a
+    /// fresh edit into a stale update.
+    pub async fn remove_stale_queue_entries(&self) -> SyncResult<u64> {
+        let result = sqlx::query(
+            "DELETE FROM sync_queue WHERE document_id IN \
+             (SELECT id FROM documents WHERE sync_status = ? OR local_only = 1)",
+        )
+        .bind(SyncStatus::Synced.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Pending documents with nothing in the sync queue that have already
+    /// been uploaded, judging by a server-assigned revision
+    pub async fn get_unqueued_pending_documents(&self) -> SyncResult<Vec<Document>> {
+        let ids: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM documents d WHERE sync_status = ? AND local_only = 0 \
+             AND deleted_at IS NULL AND sync_revision > 1 \
+             AND NOT EXISTS (SELECT 1 FROM sync_queue q WHERE q.document_id = d.id)",
+        )
+        .bind(SyncStatus::Pending.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut documents = Vec::with_capacity(ids.len());
+        for id in ids {
+            documents.push(self.get_document(&Uuid::parse_str(&id)?).await?);
+        }
+        Ok(documents)
+    }
+
+    /// Number of operations waiting in the sync queue
+    pub async fn queue_depth(&self) -> SyncResult<i64> {
+        let depth: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sync_queue")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(depth)
+    }
+
+    /// Merge each document's queued updates into one: their patches in
+    /// order, based on the content hash of the oldest. Returns how many queue
+    /// entries were removed.
+    pub async fn coalesce_sync_queue(&self) -> SyncResult<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let rows = sqlx::query(
+            "SELECT document_id, patch, old_content_hash FROM sync_queue \
+             WHERE operation_type = ? AND patch IS NOT NULL ORDER BY local_seq ASC, id ASC",
+        )
+        .bind(ChangeEventType::Update.to_string())
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // document_id -> (combined patch, base hash, entries), in queue order
+        let mut merged: Vec<(String, json_patch::Patch, Option<String>, u64)> = Vec::new();
+        for row in rows {
+            let document_id: String = row.try_get("document_id")?;
+            let patch: json_patch::Patch =
+                serde_json::from_str(&row.try_get::<String, _>("patch")?)?;
+            match merged.iter_mut().find(|entry| entry.0 == document_id) {
+                Some(entry) => {
+                    entry.1 .0.extend(patch.0);
+                    entry.3 += 1;
+                }
+                None => {
+                    let old_content_hash: Option<String> = row.try_get("old_content_hash")?;
+                    merged.push((document_id, patch, old_content_hash, 1));
+                }
+            }
+        }
+
+        let mut removed = 0;
+        for (document_id, patch, old_content_hash, entries) in merged {
+            if entries < 2 {
+                continue;
+            }
+            sqlx::query(
+                "DELETE FROM sync_queue WHERE document_id = ? AND operation_type = ? \
+                 AND patch IS NOT NULL",
+            )
+            .bind(&document_id)
+            .bind(ChangeEventType::Update.to_string())
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(Queries::INSERT_SYNC_QUEUE_WITH_HASH)
+                .bind(&document_id)
+                .bind(ChangeEventType::Update.to_string())
+                .bind(serde_json::to_string(&patch)?)
+                .bind(old_content_hash)
+                .execute(&mut *tx)
+                .await?;
+            removed += entries - 1;
+        }
+
+        tx.commit().await?;
+        Ok(removed)
+    }
+
+    // ===== Compaction =====
+
+    /// Prune what the database no longer needs, then `VACUUM` it to give the
+    /// space back. Deleted documents the server already knows about are
+    /// purged once they have been deleted for longer than `history_retention`,
+    /// along with change events that old.
+    pub async fn compact(&self, history_retention: Duration) -> SyncResult<CompactReport> {
+        let bytes_before = self.size_in_bytes().await?;
+        let queue_entries_removed = self.remove_stale_queue_entries().await?;
+        let retention_days = history_retention.as_secs_f64() / 86_400.0;
+
+        let mut tx = self.pool.begin().await?;
+        let purged: Vec<String> = sqlx::query_scalar(
+            "SELECT id FROM documents d WHERE deleted_at IS NOT NULL \
+             AND julianday(deleted_at) <= julianday('now') - ? \
+             AND (sync_status = ? OR local_only = 1) \
+             AND NOT EXISTS (SELECT 1 FROM sync_queue q WHERE q.document_id = d.id)",
+        )
+        .bind(retention_days)
+        .bind(SyncStatus::Synced.to_string())
+        .fetch_all(&mut *tx)
+        .await?;
+        for id in &purged {
+            for sql in [
+                "DELETE FROM conflicts WHERE document_id = ?",
+                "DELETE FROM attachments WHERE document_id = ?",
+                "DELETE FROM documents WHERE id = ?",
+            ] {
+                sqlx::query(sql).bind(id).execute(&mut *tx).await?;
+            }
+            Self::update_indexes_in_tx(&mut tx, &Uuid::parse_str(id)?).await?;
+        }
+        let change_events_removed = sqlx::query(
+            "DELETE FROM change_events WHERE julianday(created_at) <= julianday('now') - ?",
+        )
+        .bind(retention_days)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        // In WAL mode the vacuumed pages only reach the file at a checkpoint
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(CompactReport {
+            queue_entries_removed,
+            documents_purged: purged.len() as u64,
+            change_events_removed,
+            bytes_before,
+            bytes_after: self.size_in_bytes().await?,
+        })
+    }
+
+    /// Size of the database's pages, which is its file size on disk
+    pub async fn size_in_bytes(&self) -> SyncResult<u64> {
+        let pages: i64 = sqlx::query_scalar("PRAGMA page_count")
+            .fetch_one(&self.pool)
+            .await?;
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((pages * page_size) as u64)
+    }
+
+    // ===== Export / Import =====
+
+    /// Every document, deleted ones included, with its sync state and queue
+    pub async fn export_documents(&self) -> SyncResult<Vec<ExportedDocument>> {
+        let mut queued: std::collections::HashMap<String, Vec<QueuedOperation>> =
+            std::collections::HashMap::new();
+        let rows = sqlx::query(
+            "SELECT document_id, operation_type, patch, old_content_hash FROM sync_queue ORDER BY id ASC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        for row in rows {
+            let Some(document_id) = row.try_get::<Option<String>, _>("document_id")? else {
+                continue;
+            };
+            let operation_type: String = row.try_get("operation_type")?;
+            let Ok(operation_type) = operation_type.parse() else {
+                continue;
+            };
+            let patch = row
+                .try_get::<Option<String>, _>("patch")?
+                .map(|json| serde_json::from_str(&json))
+                .transpose()?;
+            queued
+                .entry(document_id)
+                .or_default()
+                .push(QueuedOperation {
+                    operation_type,
+                    patch,
+                    old_content_hash: row.try_get("old_content_hash")?,
+                });
+        }
+
+        let rows = sqlx::query("SELECT * FROM documents ORDER BY created_at ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                let document = DbHelpers::parse_document(row)?;
+                let sync_status: String = row.try_get("sync_status")?;
+                Ok(ExportedDocument {
+                    sync_status: sync_status.parse().map_err(|_| {
+                        SyncError::InvalidOperation(format!(
+                            "Unknown sync status '{}'",
+                            sync_status
+                        ))
+                    })?,
+                    local_only: row.try_get("local_only")?,
+                    queued_operations: queued.remove(&document.id.to_string()).unwrap_or_default(),
+                    document,
+                })
+            })
+            .collect()
+    }
+
+    /// Write exported documents in one transaction, each with its given
+    /// status, local-only flag and queue. `Replace` first removes every
+    /// existing document; `Merge` only overwrites documents with the same ID.
+    pub async fn import_documents(
+        &self,
+        documents: &[ExportedDocument],
+        mode: ImportMode,
+    ) -> SyncResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        if mode == ImportMode::Replace {
+            sqlx::query("DELETE FROM sync_queue")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM conflicts")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM documents_fts")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(Queries::CLEAR_DOCUMENT_TAGS)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM documents")
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for imported in documents {
+            let doc = &imported.document;
+            let params = self.document_params(doc, Some(imported.sync_status))?;
+            sqlx::query(Queries::UPSERT_DOCUMENT)
+                .bind(params.0) // id
+                .bind(params.1) // user_id
+                .bind(params.2) // content
+                .bind(params.3) // version
+                .bind(params.4) // created_at
+                .bind(params.5) // updated_at
+                .bind(params.6) // deleted_at
+                .bind(params.7) // sync_status
+                .bind(params.8) // title
+                .bind(params.9) // content_zstd
+                .bind(params.10) // expires_at
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("UPDATE documents SET local_only = ? WHERE id = ?")
+                .bind(imported.local_only)
+                .bind(doc.id.to_string())
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("DELETE FROM sync_queue WHERE document_id = ?")
+                .bind(doc.id.to_string())
+                .execute(&mut *tx)
+                .await?;
+            for op in &imported.queued_operations {
+                sqlx::query(Queries::INSERT_SYNC_QUEUE_WITH_HASH)
+                    .bind(doc.id.to_string())
+                    .bind(op.operation_type.to_string())
+                    .bind(op.patch.as_ref().map(|patch| patch.to_string()))
+                    .bind(&op.old_content_hash)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+
+            Self::update_indexes_in_tx(&mut tx, &doc.id).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
     // ===== FTS (Full-Text Search) Methods =====
 
     /// Configure which JSON paths to index for full-text search.
@@ -471,6 +1477,7 @@ impl ClientDatabase {
         sqlx::query(Queries::REBUILD_FTS_INDEX)
             .execute(&mut *tx)
             .await?;
+        Self::index_compressed_in_tx(&mut tx).await?;
 
         tx.commit().await?;
 
@@ -484,9 +1491,31 @@ impl ClientDatabase {
     /// Update the FTS index entry for a single document.
     /// Call this after creating or updating a document.
     pub async fn update_fts_for_document(&self, document_id: &Uuid) -> SyncResult<()> {
+        // Use transaction to ensure atomicity (no orphaned entries on crash)
+        let mut tx = self.pool.begin().await?;
+        Self::update_fts_in_tx(&mut tx, document_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Refresh everything derived from a document's content: its FTS entry
+    /// and its tags.
+    async fn update_indexes_in_tx(
+        tx: &mut Transaction<'_, Sqlite>,
+        document_id: &Uuid,
+    ) -> SyncResult<()> {
+        Self::update_fts_in_tx(tx, document_id).await?;
+        Self::update_tags_in_tx(tx, document_id).await
+    }
+
+    /// Refresh a document's FTS entry as part of the caller's transaction.
+    async fn update_fts_in_tx(
+        tx: &mut Transaction<'_, Sqlite>,
+        document_id: &Uuid,
+    ) -> SyncResult<()> {
         // Skip FTS update if no search paths are configured
         let has_config: (i32,) = sqlx::query_as(Queries::HAS_SEARCH_CONFIG)
-            .fetch_one(&self.pool)
+            .fetch_one(&mut **tx)
             .await?;
         if has_config.0 == 0 {
             return Ok(());
@@ -494,25 +1523,151 @@ impl ClientDatabase {
 
         let doc_id_str = document_id.to_string();
 
-        // Use transaction to ensure atomicity (no orphaned entries on crash)
-        let mut tx = self.pool.begin().await?;
-
         // Delete existing entry
         sqlx::query(Queries::DELETE_FTS_ENTRY)
             .bind(&doc_id_str)
-            .execute(&mut *tx)
+            .execute(&mut **tx)
             .await?;
 
+        // Compressed content can't be read by json_extract in place
+        let compressed: Option<Vec<u8>> =
+            sqlx::query_scalar("SELECT content_zstd FROM documents WHERE id = ?")
+                .bind(&doc_id_str)
+                .fetch_optional(&mut **tx)
+                .await?
+                .flatten();
+        let content = compressed
+            .map(|c| DbHelpers::decompress_content(&c))
+            .transpose()?;
+
         // Insert new entry (query handles deleted_at check internally)
         sqlx::query(Queries::UPDATE_FTS_ENTRY)
             .bind(&doc_id_str)
+            .bind(content)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Index the documents REBUILD_FTS_INDEX skips because their content is compressed.
+    async fn index_compressed_in_tx(tx: &mut Transaction<'_, Sqlite>) -> SyncResult<()> {
+        let ids: Vec<String> = sqlx::query_scalar(Queries::GET_COMPRESSED_DOCUMENT_IDS)
+            .fetch_all(&mut **tx)
+            .await?;
+        for id in ids {
+            Self::update_fts_in_tx(tx, &Uuid::parse_str(&id)?).await?;
+        }
+        Ok(())
+    }
+
+    // ===== Tag Methods =====
+
+    /// Read tags from `json_path` instead of the current path and re-index
+    /// every document.
+    pub async fn configure_tags(&self, json_path: &str) -> SyncResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(Queries::CLEAR_TAG_CONFIG)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(Queries::INSERT_TAG_PATH)
+            .bind(json_path)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(Queries::CLEAR_DOCUMENT_TAGS)
             .execute(&mut *tx)
             .await?;
 
+        let ids: Vec<String> = sqlx::query_scalar(Queries::GET_LIVE_DOCUMENT_IDS)
+            .fetch_all(&mut *tx)
+            .await?;
+        for id in &ids {
+            Self::update_tags_in_tx(&mut tx, &Uuid::parse_str(id)?).await?;
+        }
+
         tx.commit().await?;
+
+        tracing::info!(
+            "Tags: Indexing {} and re-indexed {} documents",
+            json_path,
+            ids.len()
+        );
         Ok(())
     }
 
+    /// The JSON path tags are read from, if tags are indexed.
+    pub async fn get_tag_path(&self) -> SyncResult<Option<String>> {
+        let path = sqlx::query_scalar(Queries::GET_TAG_PATH)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(path)
+    }
+
+    /// Replace a document's tags as part of the caller's transaction.
+    async fn update_tags_in_tx(
+        tx: &mut Transaction<'_, Sqlite>,
+        document_id: &Uuid,
+    ) -> SyncResult<()> {
+        let path: Option<String> = sqlx::query_scalar(Queries::GET_TAG_PATH)
+            .fetch_optional(&mut **tx)
+            .await?;
+        let Some(path) = path else {
+            return Ok(());
+        };
+
+        let doc_id_str = document_id.to_string();
+        sqlx::query(Queries::DELETE_DOCUMENT_TAGS)
+            .bind(&doc_id_str)
+            .execute(&mut **tx)
+            .await?;
+
+        let row = sqlx::query(
+            "SELECT content, content_zstd FROM documents WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(&doc_id_str)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some(row) = row else {
+            return Ok(());
+        };
+        let content = match row.try_get::<Option<Vec<u8>>, _>("content_zstd")? {
+            Some(compressed) => DbHelpers::decompress_content(&compressed)?,
+            None => row.try_get::<String, _>("content")?,
+        };
+        let content: serde_json::Value = serde_json::from_str(&content)?;
+
+        for tag in extract_tags(&content, &path) {
+            sqlx::query(Queries::INSERT_DOCUMENT_TAG)
+                .bind(&doc_id_str)
+                .bind(tag)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// A user's live documents tagged `tag`, most recently updated first.
+    pub async fn documents_with_tag(&self, user_id: &Uuid, tag: &str) -> SyncResult<Vec<Document>> {
+        let rows = sqlx::query(Queries::GET_DOCUMENTS_WITH_TAG)
+            .bind(user_id.to_string())
+            .bind(tag)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| DbHelpers::parse_document(&row))
+            .collect()
+    }
+
+    /// JSON paths currently indexed for full-text search.
+    pub async fn get_search_paths(&self) -> SyncResult<Vec<String>> {
+        let paths = sqlx::query_scalar(Queries::GET_SEARCH_PATHS)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(paths)
+    }
+
     /// Rebuild the entire FTS index from all documents.
     pub async fn rebuild_fts_index(&self) -> SyncResult<()> {
         // Clear existing index
@@ -521,18 +1676,27 @@ impl ClientDatabase {
             .await?;
 
         // Rebuild from all non-deleted documents
+        let mut tx = self.pool.begin().await?;
         sqlx::query(Queries::REBUILD_FTS_INDEX)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+        Self::index_compressed_in_tx(&mut tx).await?;
+        tx.commit().await?;
 
         tracing::info!("FTS: Index rebuilt");
         Ok(())
     }
 
-    /// Search documents using FTS5 full-text search.
-    /// Returns all documents matching the query.
-    pub async fn search_documents(&self, query: &str, limit: i64) -> SyncResult<Vec<Document>> {
+    /// Search a user's documents using FTS5 full-text search.
+    /// Returns all matching documents, best match first.
+    pub async fn search_documents(
+        &self,
+        user_id: &Uuid,
+        query: &str,
+        limit: i64,
+    ) -> SyncResult<Vec<Document>> {
         let rows = sqlx::query(Queries::SEARCH_DOCUMENTS)
+            .bind(user_id.to_string())
             .bind(query)
             .bind(limit)
             .fetch_all(&self.pool)
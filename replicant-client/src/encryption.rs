@@ -0,0 +1,236 @@
+//! End-to-end content encryption
+//!
+//! When a [`ContentCipher`] is configured on the client, document content is
+//! sealed into an envelope before it is sent and opened again on receipt, so
+//! the server only ever stores ciphertext:
+//!
+//! ```json
+//! { "ciphertext": "<base64>" }
+//! ```
+//!
+//! The local database keeps plaintext, so local queries and search still work.
+//!
+//! # Limitations
+//!
+//! The server can't see inside the envelope, so field-level patches and
+//! operational transforms don't apply to encrypted content. Every update is
+//! sent as a replacement of the whole envelope, and concurrent edits resolve
+//! to whichever full document the server accepts last. Server-side schema
+//! validation and title indexing likewise only see the envelope.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use json_patch::{Patch, PatchOperation, ReplaceOperation};
+use replicant_core::{
+    errors::ClientError,
+    models::Document,
+    patches::{calculate_checksum, create_patch},
+    protocol::ServerMessage,
+    SyncResult,
+};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const CIPHERTEXT_FIELD: &str = "ciphertext";
+const NONCE_LEN: usize = 12;
+
+/// Encrypts document content before it leaves the client.
+///
+/// Implementations must be deterministic: encrypting the same plaintext twice
+/// must produce the same ciphertext. The server detects concurrent updates by
+/// hashing the content it stores, and the client recomputes that hash from
+/// its local plaintext.
+pub trait ContentCipher: Send + Sync {
+    fn encrypt(&self, plaintext: &[u8]) -> SyncResult<Vec<u8>>;
+    fn decrypt(&self, ciphertext: &[u8]) -> SyncResult<Vec<u8>>;
+}
+
+/// AES-256-GCM with a synthetic nonce derived from the plaintext.
+///
+/// The nonce is an HMAC of the plaintext, so identical content yields
+/// identical ciphertext (revealing only that two documents are equal) and a
+/// nonce is never reused for different content.
+pub struct AesGcmCipher {
+    cipher: Aes256Gcm,
+    nonce_key: [u8; 32],
+}
+
+impl AesGcmCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        // Derive a separate key for nonce generation
+        let mut mac =
+            <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC can take key of any size");
+        mac.update(b"replicant-content-nonce");
+        Self {
+            cipher: Aes256Gcm::new(key.into()),
+            nonce_key: mac.finalize().into_bytes().into(),
+        }
+    }
+
+    fn nonce_for(&self, plaintext: &[u8]) -> [u8; NONCE_LEN] {
+        let mut mac = <HmacSha256 as Mac>::new_from_slice(&self.nonce_key)
+            .expect("HMAC can take key of any size");
+        mac.update(plaintext);
+        let digest = mac.finalize().into_bytes();
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&digest[..NONCE_LEN]);
+        nonce
+    }
+}
+
+impl ContentCipher for AesGcmCipher {
+    /// Output is the nonce followed by the ciphertext and tag
+    fn encrypt(&self, plaintext: &[u8]) -> SyncResult<Vec<u8>> {
+        let nonce = self.nonce_for(plaintext);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|e| ClientError::Encryption(e.to_string()))?;
+        let mut output = nonce.to_vec();
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> SyncResult<Vec<u8>> {
+        if ciphertext.len() < NONCE_LEN {
+            return Err(ClientError::Encryption("Ciphertext too short".to_string()).into());
+        }
+        let (nonce, ciphertext) = ciphertext.split_at(NONCE_LEN);
+        Ok(self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| ClientError::Encryption("Failed to decrypt content".to_string()))?)
+    }
+}
+
+/// Wrap content in a ciphertext envelope
+pub fn seal(cipher: &dyn ContentCipher, content: &Value) -> SyncResult<Value> {
+    let ciphertext = cipher.encrypt(&serde_json::to_vec(content)?)?;
+    Ok(json!({ CIPHERTEXT_FIELD: STANDARD.encode(ciphertext) }))
+}
+
+/// Unwrap a ciphertext envelope. Content that isn't an envelope (e.g. written
+/// before encryption was enabled) is returned unchanged.
+pub fn open(cipher: &dyn ContentCipher, content: &Value) -> SyncResult<Value> {
+    let Some(encoded) = envelope_ciphertext(content) else {
+        return Ok(content.clone());
+    };
+    let ciphertext = STANDARD
+        .decode(encoded)
+        .map_err(|e| ClientError::Encryption(e.to_string()))?;
+    Ok(serde_json::from_slice(&cipher.decrypt(&ciphertext)?)?)
+}
+
+fn envelope_ciphertext(content: &Value) -> Option<&str> {
+    match content.as_object() {
+        Some(fields) if fields.len() == 1 => fields.get(CIPHERTEXT_FIELD)?.as_str(),
+        _ => None,
+    }
+}
+
+/// Patch and base-content hash to send the server for an edit from `old` to
+/// `new`. With a cipher the patch replaces the whole envelope.
+pub(crate) fn outgoing_patch(
+    cipher: Option<&dyn ContentCipher>,
+    old: &Value,
+    new: &Value,
+) -> SyncResult<(Patch, String)> {
+    match cipher {
+        Some(cipher) => {
+            let old = seal(cipher, old)?;
+            let new = seal(cipher, new)?;
+            Ok((create_patch(&old, &new)?, calculate_checksum(&old)))
+        }
+        None => Ok((create_patch(old, new)?, calculate_checksum(old))),
+    }
+}
+
+/// Copy of a document with its content sealed for upload
+pub(crate) fn outgoing_document(
+    cipher: Option<&dyn ContentCipher>,
+    document: &Document,
+) -> SyncResult<Document> {
+    let mut document = document.clone();
+    if let Some(cipher) = cipher {
+        document.content = seal(cipher, &document.content)?;
+        if document.content_hash.is_some() {
+            document.content_hash = Some(calculate_checksum(&document.content));
+        }
+    }
+    Ok(document)
+}
+
+/// Open every envelope carried by a server message
+pub(crate) fn open_server_message(
+    cipher: Option<&dyn ContentCipher>,
+    mut msg: ServerMessage,
+) -> SyncResult<ServerMessage> {
+    let Some(cipher) = cipher else {
+        return Ok(msg);
+    };
+    match &mut msg {
+        ServerMessage::SyncDocument { document, .. }
+        | ServerMessage::DocumentCreated { document }
+        | ServerMessage::UpdateRejected {
+            server_document: document,
+            ..
+        } => {
+            document.content = open(cipher, &document.content)?;
+        }
+        ServerMessage::TransactionResponse { documents, .. } => {
+            for document in documents {
+                document.content = open(cipher, &document.content)?;
+            }
+        }
+        ServerMessage::DocumentUpdated { patch } => {
+            // An envelope replacement becomes a whole-document replacement
+            let mut operations = Vec::with_capacity(patch.patch.0.len());
+            for operation in &patch.patch.0 {
+                operations.push(match operation {
+                    PatchOperation::Replace(op) if op.path == format!("/{}", CIPHERTEXT_FIELD) => {
+                        PatchOperation::Replace(ReplaceOperation {
+                            path: String::new(),
+                            value: open(cipher, &json!({ CIPHERTEXT_FIELD: op.value }))?,
+                        })
+                    }
+                    other => other.clone(),
+                });
+            }
+            patch.patch = Patch(operations);
+        }
+        _ => {}
+    }
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open_round_trip() {
+        let cipher = AesGcmCipher::new(&[7u8; 32]);
+        let content = json!({ "title": "Secret plans", "body": "Meet at noon" });
+
+        let sealed = seal(&cipher, &content).unwrap();
+        assert!(envelope_ciphertext(&sealed).is_some());
+        assert!(!sealed.to_string().contains("Secret plans"));
+        assert_eq!(open(&cipher, &sealed).unwrap(), content);
+
+        // Deterministic, so the server-side hash can be recomputed
+        assert_eq!(seal(&cipher, &content).unwrap(), sealed);
+
+        // Plaintext content passes through untouched
+        assert_eq!(open(&cipher, &content).unwrap(), content);
+    }
+
+    #[test]
+    fn test_open_with_wrong_key_fails() {
+        let sealed = seal(&AesGcmCipher::new(&[1u8; 32]), &json!({ "title": "x" })).unwrap();
+        assert!(open(&AesGcmCipher::new(&[2u8; 32]), &sealed).is_err());
+    }
+}
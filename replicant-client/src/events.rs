@@ -15,11 +15,14 @@
 //!
 //! # Callback Types
 //!
-//! - `DocumentEventCallback`: DocumentCreated, DocumentUpdated, DocumentDeleted
+//! - `DocumentEventCallback`: DocumentCreated, DocumentUpdated, DocumentDeleted, LockChanged,
+//!   SyncSkipped, DocumentSizeWarning
 //! - `SyncEventCallback`: SyncStarted, SyncCompleted
-//! - `ErrorEventCallback`: SyncError
-//! - `ConnectionEventCallback`: ConnectionLost, ConnectionAttempted, ConnectionSucceeded
+//! - `ErrorEventCallback`: SyncError, AuthenticationFailed
+//! - `ConnectionEventCallback`: ConnectionLost, ConnectionAttempted, ConnectionSucceeded,
+//!   Reconnecting, PresenceChanged
 //! - `ConflictEventCallback`: ConflictDetected
+//! - `DocumentPatchEventCallback`: the JSON Patch behind a DocumentUpdated, when known
 //!
 //! # Thread Safety
 //!
@@ -33,7 +36,8 @@
 
 use replicant_core::{errors::ClientError, SyncResult};
 use std::ffi::{c_char, c_void, CString};
-use std::sync::{mpsc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread::{self, ThreadId};
 use uuid::Uuid;
 
@@ -61,6 +65,72 @@ pub enum EventType {
     ConnectionAttempted = 8,
     /// Successfully connected to the server
     ConnectionSucceeded = 9,
+    /// The reconnection loop is starting another attempt
+    Reconnecting = 10,
+    /// An advisory lock on a document was acquired or released
+    LockChanged = 11,
+    /// The set of the user's connected clients changed
+    PresenceChanged = 12,
+    /// The server rejected the client's credentials; reconnection has stopped
+    AuthenticationFailed = 13,
+    /// A server version of a document was not applied over the local one
+    SyncSkipped = 14,
+    /// A document's content passed the size warning threshold
+    DocumentSizeWarning = 15,
+}
+
+/// Why the client considers its connection lost, carried by ConnectionLost
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// A message couldn't be handed to the connection
+    SendFailed = 0,
+    /// The heartbeat couldn't reach the server
+    PingTimeout = 1,
+    /// Reading from the connection failed
+    ReceiverError = 2,
+    /// The server closed the connection cleanly
+    ServerClosed = 3,
+    /// The server rejected the client's credentials and closed the connection
+    AuthFailed = 4,
+    /// The app disconnected on purpose, e.g. with `Client::set_offline`
+    Manual = 5,
+}
+
+impl DisconnectReason {
+    fn from_code(code: u64) -> Self {
+        match code {
+            0 => DisconnectReason::SendFailed,
+            1 => DisconnectReason::PingTimeout,
+            2 => DisconnectReason::ReceiverError,
+            3 => DisconnectReason::ServerClosed,
+            4 => DisconnectReason::AuthFailed,
+            _ => DisconnectReason::Manual,
+        }
+    }
+}
+
+/// Where the change behind a DocumentCreated or DocumentUpdated came from
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentSource {
+    /// Made through this client
+    Local = 0,
+    /// Made by another of the user's clients and relayed as it happened
+    RemoteClient = 1,
+    /// The server's copy, from a sync or pushed by the server itself, e.g.
+    /// documents created through the admin API or while this client was offline
+    Server = 2,
+}
+
+impl DocumentSource {
+    fn from_code(code: u64) -> Self {
+        match code {
+            1 => DocumentSource::RemoteClient,
+            2 => DocumentSource::Server,
+            _ => DocumentSource::Local,
+        }
+    }
 }
 
 // =============================================================================
@@ -98,12 +168,16 @@ pub enum SyncEvent {
         id: String,
         title: String,
         content: serde_json::Value,
+        source: DocumentSource,
     },
-    /// An existing document was updated
+    /// An existing document was updated. `patch` holds the changes that were
+    /// applied, when known, so UIs can highlight just those fields.
     DocumentUpdated {
         id: String,
         title: String,
         content: serde_json::Value,
+        patch: Option<json_patch::Patch>,
+        source: DocumentSource,
     },
     /// A document was deleted
     DocumentDeleted { id: String },
@@ -120,11 +194,44 @@ pub enum SyncEvent {
         losing_content: Option<String>,
     },
     /// Connection to server was lost
-    ConnectionLost { server_url: String },
+    ConnectionLost {
+        server_url: String,
+        reason: DisconnectReason,
+    },
     /// A connection attempt was made
     ConnectionAttempted { server_url: String },
     /// Successfully connected to server
     ConnectionSucceeded { server_url: String },
+    /// Reconnection attempt number `attempt` is starting
+    Reconnecting { attempt: u32 },
+    /// A document lock changed hands; `holder` is the owning client ID, or
+    /// `None` once released
+    LockChanged {
+        document_id: String,
+        holder: Option<String>,
+    },
+    /// The user's connected clients changed; `active_clients` lists their IDs
+    PresenceChanged { active_clients: Vec<String> },
+    /// The server rejected the client's credentials. The client no longer
+    /// tries to reconnect; create a new one with fresh credentials.
+    AuthenticationFailed { reason: String },
+    /// A server version of a document was ignored because the local copy is
+    /// at the same or a later revision. When the local copy has unsynced
+    /// edits, those edits are hiding the server's changes until they sync.
+    SyncSkipped {
+        document_id: String,
+        local_revision: i64,
+        server_revision: i64,
+        reason: String,
+    },
+    /// A document's serialized content grew past
+    /// `ClientConfig::document_size_warning_bytes`. It was still saved;
+    /// past `ClientConfig::max_document_bytes` changes are refused.
+    DocumentSizeWarning {
+        document_id: String,
+        size: u64,
+        threshold: u64,
+    },
 }
 
 impl SyncEvent {
@@ -141,6 +248,12 @@ impl SyncEvent {
             SyncEvent::ConnectionLost { .. } => EventType::ConnectionLost,
             SyncEvent::ConnectionAttempted { .. } => EventType::ConnectionAttempted,
             SyncEvent::ConnectionSucceeded { .. } => EventType::ConnectionSucceeded,
+            SyncEvent::Reconnecting { .. } => EventType::Reconnecting,
+            SyncEvent::LockChanged { .. } => EventType::LockChanged,
+            SyncEvent::PresenceChanged { .. } => EventType::PresenceChanged,
+            SyncEvent::AuthenticationFailed { .. } => EventType::AuthenticationFailed,
+            SyncEvent::SyncSkipped { .. } => EventType::SyncSkipped,
+            SyncEvent::DocumentSizeWarning { .. } => EventType::DocumentSizeWarning,
         }
     }
 
@@ -158,6 +271,7 @@ impl SyncEvent {
                     .as_ref()
                     .and_then(|c| serde_json::from_str(c).ok())
                     .unwrap_or(serde_json::Value::Null),
+                source: DocumentSource::from_code(event.numeric_data),
             },
             EventType::DocumentUpdated => SyncEvent::DocumentUpdated {
                 id: event.document_id.clone().unwrap_or_default(),
@@ -170,6 +284,11 @@ impl SyncEvent {
                     .as_ref()
                     .and_then(|c| serde_json::from_str(c).ok())
                     .unwrap_or(serde_json::Value::Null),
+                patch: event
+                    .patch
+                    .as_ref()
+                    .and_then(|p| serde_json::from_str(p).ok()),
+                source: DocumentSource::from_code(event.numeric_data),
             },
             EventType::DocumentDeleted => SyncEvent::DocumentDeleted {
                 id: event.document_id.clone().unwrap_or_default(),
@@ -191,6 +310,7 @@ impl SyncEvent {
             },
             EventType::ConnectionLost => SyncEvent::ConnectionLost {
                 server_url: event.title.clone().unwrap_or_default(),
+                reason: DisconnectReason::from_code(event.numeric_data),
             },
             EventType::ConnectionAttempted => SyncEvent::ConnectionAttempted {
                 server_url: event.title.clone().unwrap_or_default(),
@@ -198,6 +318,48 @@ impl SyncEvent {
             EventType::ConnectionSucceeded => SyncEvent::ConnectionSucceeded {
                 server_url: event.title.clone().unwrap_or_default(),
             },
+            EventType::Reconnecting => SyncEvent::Reconnecting {
+                attempt: event.numeric_data as u32,
+            },
+            EventType::LockChanged => SyncEvent::LockChanged {
+                document_id: event.document_id.clone().unwrap_or_default(),
+                holder: event.title.clone(),
+            },
+            EventType::PresenceChanged => SyncEvent::PresenceChanged {
+                active_clients: event
+                    .content
+                    .as_ref()
+                    .and_then(|c| serde_json::from_str(c).ok())
+                    .unwrap_or_default(),
+            },
+            EventType::AuthenticationFailed => SyncEvent::AuthenticationFailed {
+                reason: event.error.clone().unwrap_or_default(),
+            },
+            EventType::SyncSkipped => {
+                let revisions: serde_json::Value = event
+                    .content
+                    .as_ref()
+                    .and_then(|c| serde_json::from_str(c).ok())
+                    .unwrap_or_default();
+                SyncEvent::SyncSkipped {
+                    document_id: event.document_id.clone().unwrap_or_default(),
+                    local_revision: revisions["local_revision"].as_i64().unwrap_or_default(),
+                    server_revision: revisions["server_revision"].as_i64().unwrap_or_default(),
+                    reason: event.title.clone().unwrap_or_default(),
+                }
+            }
+            EventType::DocumentSizeWarning => {
+                let sizes: serde_json::Value = event
+                    .content
+                    .as_ref()
+                    .and_then(|c| serde_json::from_str(c).ok())
+                    .unwrap_or_default();
+                SyncEvent::DocumentSizeWarning {
+                    document_id: event.document_id.clone().unwrap_or_default(),
+                    size: sizes["size"].as_u64().unwrap_or_default(),
+                    threshold: sizes["threshold"].as_u64().unwrap_or_default(),
+                }
+            }
         }
     }
 }
@@ -206,13 +368,18 @@ impl SyncEvent {
 // Type-Specific Callback Types (C FFI)
 // =============================================================================
 
-/// Document event callback for DocumentCreated, DocumentUpdated, DocumentDeleted
+/// Document event callback for DocumentCreated, DocumentUpdated, DocumentDeleted,
+/// LockChanged, SyncSkipped, DocumentSizeWarning
 ///
 /// # Parameters
 /// * `event_type` - The specific document event type
 /// * `document_id` - UUID of the document (always non-null)
-/// * `title` - Document title (null for Deleted events)
-/// * `content` - Full document JSON (null for Deleted events)
+/// * `title` - Document title (null for Deleted and DocumentSizeWarning events);
+///   for LockChanged, the client ID holding the lock (null once released); for
+///   SyncSkipped, why the server version was skipped
+/// * `content` - Full document JSON (null for Deleted and LockChanged events); for
+///   SyncSkipped, `{"local_revision": n, "server_revision": n}`; for
+///   DocumentSizeWarning, `{"size": n, "threshold": n}` in bytes
 /// * `context` - User-defined context pointer
 pub type DocumentEventCallback = extern "C" fn(
     event_type: EventType,
@@ -231,21 +398,25 @@ pub type DocumentEventCallback = extern "C" fn(
 pub type SyncEventCallback =
     extern "C" fn(event_type: EventType, document_count: u64, context: *mut c_void);
 
-/// Error event callback for SyncError
+/// Error event callback for SyncError, AuthenticationFailed
 ///
 /// # Parameters
-/// * `event_type` - Always SyncError
-/// * `error` - Error message (always non-null)
+/// * `event_type` - SyncError or AuthenticationFailed
+/// * `error` - Error message, or the server's reason for rejecting the
+///   credentials (always non-null)
 /// * `context` - User-defined context pointer
 pub type ErrorEventCallback =
     extern "C" fn(event_type: EventType, error: *const c_char, context: *mut c_void);
 
-/// Connection event callback for ConnectionLost, ConnectionAttempted, ConnectionSucceeded
+/// Connection event callback for ConnectionLost, ConnectionAttempted, ConnectionSucceeded,
+/// Reconnecting, PresenceChanged
 ///
 /// # Parameters
 /// * `event_type` - The connection event type
 /// * `connected` - true if connected (valid for Lost/Succeeded), false otherwise
-/// * `attempt_number` - Reconnection attempt number (valid for ConnectionAttempted)
+/// * `attempt_number` - Reconnection attempt number (valid for Reconnecting); for
+///   PresenceChanged, the number of connected clients; for ConnectionLost, the
+///   `DisconnectReason`
 /// * `context` - User-defined context pointer
 pub type ConnectionEventCallback = extern "C" fn(
     event_type: EventType,
@@ -270,6 +441,17 @@ pub type ConflictEventCallback = extern "C" fn(
     context: *mut c_void,
 );
 
+/// Patch event callback for DocumentUpdated events whose changes are known
+///
+/// Invoked alongside the document callback, after it.
+///
+/// # Parameters
+/// * `document_id` - UUID of the updated document (always non-null)
+/// * `patch` - JSON Patch (RFC 6902) that was applied (always non-null)
+/// * `context` - User-defined context pointer
+pub type DocumentPatchEventCallback =
+    extern "C" fn(document_id: *const c_char, patch: *const c_char, context: *mut c_void);
+
 // =============================================================================
 // Callback Entry Types (Internal)
 // =============================================================================
@@ -300,6 +482,11 @@ struct ConflictCallbackEntry {
     context: *mut c_void,
 }
 
+struct PatchCallbackEntry {
+    callback: DocumentPatchEventCallback,
+    context: *mut c_void,
+}
+
 // Safety: Callback entries are only accessed from the registered thread
 unsafe impl Send for DocumentCallbackEntry {}
 unsafe impl Sync for DocumentCallbackEntry {}
@@ -311,6 +498,8 @@ unsafe impl Send for ConnectionCallbackEntry {}
 unsafe impl Sync for ConnectionCallbackEntry {}
 unsafe impl Send for ConflictCallbackEntry {}
 unsafe impl Sync for ConflictCallbackEntry {}
+unsafe impl Send for PatchCallbackEntry {}
+unsafe impl Sync for PatchCallbackEntry {}
 
 // =============================================================================
 // Rust Callback Entry (Internal)
@@ -319,6 +508,31 @@ unsafe impl Sync for ConflictCallbackEntry {}
 struct RustCallbackEntry {
     callback: Box<dyn Fn(SyncEvent) + Send>,
     event_filter: Option<EventType>,
+    document_filter: Option<String>,
+    // Cleared by the WatchHandle on drop; removed on the next process_events
+    active: Option<Arc<AtomicBool>>,
+}
+
+impl RustCallbackEntry {
+    fn is_active(&self) -> bool {
+        self.active
+            .as_ref()
+            .is_none_or(|active| active.load(Ordering::Relaxed))
+    }
+}
+
+/// Keeps a callback from [`EventDispatcher::register_document_watch`]
+/// registered. Dropping it stops the callback.
+#[must_use = "the watch stops when the handle is dropped"]
+pub struct WatchHandle {
+    active: Arc<AtomicBool>,
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        // Only a flag, so handles can be dropped from inside a callback
+        self.active.store(false, Ordering::Relaxed);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -328,6 +542,7 @@ pub struct QueuedEvent {
     title: Option<String>,
     content: Option<String>,
     error: Option<String>,
+    patch: Option<String>,
     numeric_data: u64,
     boolean_data: bool,
 }
@@ -377,6 +592,7 @@ pub struct EventDispatcher {
     error_callbacks: Mutex<Vec<ErrorCallbackEntry>>,
     connection_callbacks: Mutex<Vec<ConnectionCallbackEntry>>,
     conflict_callbacks: Mutex<Vec<ConflictCallbackEntry>>,
+    patch_callbacks: Mutex<Vec<PatchCallbackEntry>>,
     // Rust-native callback storage
     rust_callbacks: Mutex<Vec<RustCallbackEntry>>,
     // Event queue
@@ -394,6 +610,7 @@ impl EventDispatcher {
             error_callbacks: Mutex::new(Vec::new()),
             connection_callbacks: Mutex::new(Vec::new()),
             conflict_callbacks: Mutex::new(Vec::new()),
+            patch_callbacks: Mutex::new(Vec::new()),
             rust_callbacks: Mutex::new(Vec::new()),
             event_queue: Mutex::new(receiver),
             event_sender: sender,
@@ -467,7 +684,7 @@ impl EventDispatcher {
         Ok(())
     }
 
-    /// Register a callback for error events (SyncError)
+    /// Register a callback for error events (SyncError, AuthenticationFailed)
     ///
     /// # Parameters
     /// * `callback` - Function to call for error events
@@ -533,6 +750,28 @@ impl EventDispatcher {
         Ok(())
     }
 
+    /// Register a callback for the patches behind document updates
+    ///
+    /// # Parameters
+    /// * `callback` - Function to call with each known patch
+    /// * `context` - User-defined context pointer passed to callback
+    pub fn register_document_patch_callback(
+        &self,
+        callback: DocumentPatchEventCallback,
+        context: *mut c_void,
+    ) -> SyncResult<()> {
+        self.ensure_callback_thread()?;
+
+        let mut callbacks = self
+            .patch_callbacks
+            .lock()
+            .map_err(|_| ClientError::LockError("patch_callbacks".into()))?;
+
+        callbacks.push(PatchCallbackEntry { callback, context });
+
+        Ok(())
+    }
+
     /// Register a Rust-native callback for all events
     ///
     /// This provides an idiomatic Rust interface using the `SyncEvent` enum.
@@ -577,6 +816,8 @@ impl EventDispatcher {
         callbacks.push(RustCallbackEntry {
             callback: Box::new(callback),
             event_filter: None,
+            document_filter: None,
+            active: None,
         });
 
         Ok(())
@@ -605,12 +846,51 @@ impl EventDispatcher {
         callbacks.push(RustCallbackEntry {
             callback: Box::new(callback),
             event_filter: Some(event_filter),
+            document_filter: None,
+            active: None,
         });
 
         Ok(())
     }
 
-    pub fn emit_document_created(&self, document_id: &Uuid, content: &serde_json::Value) {
+    /// Register a Rust-native callback for the events of one document,
+    /// until the returned handle is dropped
+    ///
+    /// # Parameters
+    /// * `callback` - Closure to call for events
+    /// * `document_id` - Only receive events about this document
+    pub fn register_document_watch<F>(
+        &self,
+        callback: F,
+        document_id: &Uuid,
+    ) -> SyncResult<WatchHandle>
+    where
+        F: Fn(SyncEvent) + Send + 'static,
+    {
+        self.ensure_callback_thread()?;
+
+        let mut callbacks = self
+            .rust_callbacks
+            .lock()
+            .map_err(|_| ClientError::LockError("rust_callbacks".into()))?;
+
+        let active = Arc::new(AtomicBool::new(true));
+        callbacks.push(RustCallbackEntry {
+            callback: Box::new(callback),
+            event_filter: None,
+            document_filter: Some(document_id.to_string()),
+            active: Some(active.clone()),
+        });
+
+        Ok(WatchHandle { active })
+    }
+
+    pub fn emit_document_created(
+        &self,
+        document_id: &Uuid,
+        content: &serde_json::Value,
+        source: DocumentSource,
+    ) {
         // Extract title from content if present
         let title = content
             .get("title")
@@ -622,12 +902,17 @@ impl EventDispatcher {
             Some(title),
             Some(content),
             None,
-            0,
+            source as u64,
             false,
         );
     }
 
-    pub fn emit_document_updated(&self, document_id: &Uuid, content: &serde_json::Value) {
+    pub fn emit_document_updated(
+        &self,
+        document_id: &Uuid,
+        content: &serde_json::Value,
+        source: DocumentSource,
+    ) {
         // Extract title from content if present
         let title = content
             .get("title")
@@ -639,11 +924,36 @@ impl EventDispatcher {
             Some(title),
             Some(content),
             None,
-            0,
+            source as u64,
             false,
         );
     }
 
+    /// Like [`EventDispatcher::emit_document_updated`], also carrying the
+    /// patch that turned the previous content into `content`
+    pub fn emit_document_updated_with_patch(
+        &self,
+        document_id: &Uuid,
+        content: &serde_json::Value,
+        patch: &json_patch::Patch,
+        source: DocumentSource,
+    ) {
+        let title = content
+            .get("title")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Untitled");
+        self.send_event(QueuedEvent {
+            event_type: EventType::DocumentUpdated,
+            document_id: Some(document_id.to_string()),
+            title: Some(title.to_string()),
+            content: Some(serde_json::to_string(content).unwrap_or_else(|_| "{}".to_string())),
+            error: None,
+            patch: serde_json::to_string(patch).ok(),
+            numeric_data: source as u64,
+            boolean_data: false,
+        });
+    }
+
     pub fn emit_document_deleted(&self, document_id: &Uuid) {
         self.queue_event(
             EventType::DocumentDeleted,
@@ -696,14 +1006,14 @@ impl EventDispatcher {
         );
     }
 
-    pub fn emit_connection_lost(&self, server_url: &str) {
+    pub fn emit_connection_lost(&self, server_url: &str, reason: DisconnectReason) {
         self.queue_event(
             EventType::ConnectionLost,
             None,
             Some(server_url),
             None,
             None,
-            0,
+            reason as u64,
             false,
         );
     }
@@ -732,6 +1042,92 @@ impl EventDispatcher {
         );
     }
 
+    pub fn emit_reconnecting(&self, attempt: u32) {
+        self.queue_event(
+            EventType::Reconnecting,
+            None,
+            None,
+            None,
+            None,
+            attempt as u64,
+            false,
+        );
+    }
+
+    pub fn emit_lock_changed(&self, document_id: &Uuid, holder: Option<&Uuid>) {
+        let holder = holder.map(|id| id.to_string());
+        self.queue_event(
+            EventType::LockChanged,
+            Some(document_id),
+            holder.as_deref(),
+            None,
+            None,
+            0,
+            false,
+        );
+    }
+
+    pub fn emit_presence_changed(&self, active_clients: &[Uuid]) {
+        let ids: Vec<String> = active_clients.iter().map(|id| id.to_string()).collect();
+        self.queue_event(
+            EventType::PresenceChanged,
+            None,
+            None,
+            Some(&serde_json::json!(ids)),
+            None,
+            active_clients.len() as u64,
+            true,
+        );
+    }
+
+    pub fn emit_sync_skipped(
+        &self,
+        document_id: &Uuid,
+        local_revision: i64,
+        server_revision: i64,
+        reason: &str,
+    ) {
+        self.queue_event(
+            EventType::SyncSkipped,
+            Some(document_id),
+            Some(reason),
+            Some(&serde_json::json!({
+                "local_revision": local_revision,
+                "server_revision": server_revision,
+            })),
+            None,
+            0,
+            false,
+        );
+    }
+
+    pub fn emit_document_size_warning(&self, document_id: &Uuid, size: usize, threshold: usize) {
+        self.queue_event(
+            EventType::DocumentSizeWarning,
+            Some(document_id),
+            None,
+            Some(&serde_json::json!({
+                "size": size,
+                "threshold": threshold,
+            })),
+            None,
+            size as u64,
+            false,
+        );
+    }
+
+    pub fn emit_authentication_failed(&self, reason: &str) {
+        self.queue_event(
+            EventType::AuthenticationFailed,
+            None,
+            None,
+            None,
+            Some(reason),
+            0,
+            false,
+        );
+    }
+
     /// Queue an event for later processing on the callback thread
     #[allow(clippy::too_many_arguments)] // FFI callback constraints
     fn queue_event(
@@ -750,10 +1146,14 @@ impl EventDispatcher {
             title: title.map(|t| t.to_string()),
             content: content.map(|c| serde_json::to_string(c).unwrap_or_else(|_| "{}".to_string())),
             error: error.map(|e| e.to_string()),
+            patch: None,
             numeric_data,
             boolean_data,
         };
+        self.send_event(queued_event);
+    }
 
+    fn send_event(&self, queued_event: QueuedEvent) {
         if self.event_sender.send(queued_event).is_err() {
             tracing::error!("Failed to queue event - receiver may have been dropped");
         }
@@ -781,6 +1181,10 @@ impl EventDispatcher {
             .conflict_callbacks
             .lock()
             .map_err(|_| ClientError::LockError("conflict_callbacks".into()))?;
+        let patch = self
+            .patch_callbacks
+            .lock()
+            .map_err(|_| ClientError::LockError("patch_callbacks".into()))?;
         let rust = self
             .rust_callbacks
             .lock()
@@ -791,6 +1195,7 @@ impl EventDispatcher {
             || !error.is_empty()
             || !conn.is_empty()
             || !conflict.is_empty()
+            || !patch.is_empty()
             || !rust.is_empty())
     }
 
@@ -836,10 +1241,15 @@ impl EventDispatcher {
             .conflict_callbacks
             .lock()
             .map_err(|_| ClientError::LockError("conflict_callbacks".into()))?;
-        let rust_callbacks = self
+        let patch_callbacks = self
+            .patch_callbacks
+            .lock()
+            .map_err(|_| ClientError::LockError("patch_callbacks".into()))?;
+        let mut rust_callbacks = self
             .rust_callbacks
             .lock()
             .map_err(|_| ClientError::LockError("rust_callbacks".into()))?;
+        rust_callbacks.retain(RustCallbackEntry::is_active);
 
         let receiver = self
             .event_queue
@@ -860,6 +1270,14 @@ impl EventDispatcher {
                             continue;
                         }
                     }
+                    if let Some(filter) = &entry.document_filter {
+                        if queued_event.document_id.as_ref() != Some(filter) {
+                            continue;
+                        }
+                    }
+                    if !entry.is_active() {
+                        continue;
+                    }
                     (entry.callback)(sync_event.clone());
                 }
             }
@@ -900,7 +1318,10 @@ impl EventDispatcher {
             match queued_event.event_type {
                 EventType::DocumentCreated
                 | EventType::DocumentUpdated
-                | EventType::DocumentDeleted => {
+                | EventType::DocumentDeleted
+                | EventType::LockChanged
+                | EventType::SyncSkipped
+                | EventType::DocumentSizeWarning => {
                     let doc_id_ptr = document_id_cstr.unwrap_or(std::ptr::null());
                     let title_ptr = title_cstr.unwrap_or(std::ptr::null());
                     let content_ptr = content_cstr.unwrap_or(std::ptr::null());
@@ -919,6 +1340,14 @@ impl EventDispatcher {
                             entry.context,
                         );
                     }
+
+                    if let Some(patch) = queued_event.patch.as_ref() {
+                        let patch_cstr = CString::new(patch.as_str())
+                            .unwrap_or_else(|_| CString::new("[]").unwrap());
+                        for entry in patch_callbacks.iter() {
+                            (entry.callback)(doc_id_ptr, patch_cstr.as_ptr(), entry.context);
+                        }
+                    }
                 }
 
                 EventType::SyncStarted | EventType::SyncCompleted => {
@@ -931,7 +1360,7 @@ impl EventDispatcher {
                     }
                 }
 
-                EventType::SyncError => {
+                EventType::SyncError | EventType::AuthenticationFailed => {
                     let error_ptr = error_cstr.unwrap_or(std::ptr::null());
                     for entry in error_callbacks.iter() {
                         (entry.callback)(queued_event.event_type, error_ptr, entry.context);
@@ -940,7 +1369,9 @@ impl EventDispatcher {
 
                 EventType::ConnectionLost
                 | EventType::ConnectionAttempted
-                | EventType::ConnectionSucceeded => {
+                | EventType::ConnectionSucceeded
+                | EventType::Reconnecting
+                | EventType::PresenceChanged => {
                     for entry in connection_callbacks.iter() {
                         (entry.callback)(
                             queued_event.event_type,
@@ -1033,8 +1464,11 @@ mod tests {
 
         // Emit an event
         let doc_id = Uuid::new_v4();
-        dispatcher
-            .emit_document_created(&doc_id, &serde_json::json!({"title": "Test", "test": true}));
+        dispatcher.emit_document_created(
+            &doc_id,
+            &serde_json::json!({"title": "Test", "test": true}),
+            DocumentSource::Local,
+        );
 
         // Process events (should invoke callback)
         let processed = dispatcher.process_events().unwrap();
@@ -1083,13 +1517,21 @@ mod tests {
         let doc_id = Uuid::new_v4();
 
         // Emit created event
-        dispatcher.emit_document_created(&doc_id, &serde_json::json!({"title": "Test"}));
+        dispatcher.emit_document_created(
+            &doc_id,
+            &serde_json::json!({"title": "Test"}),
+            DocumentSource::Local,
+        );
         dispatcher.process_events().unwrap();
         assert_eq!(created_count.load(Ordering::SeqCst), 1);
         assert_eq!(updated_count.load(Ordering::SeqCst), 0);
 
         // Emit updated event
-        dispatcher.emit_document_updated(&doc_id, &serde_json::json!({"title": "Test"}));
+        dispatcher.emit_document_updated(
+            &doc_id,
+            &serde_json::json!({"title": "Test"}),
+            DocumentSource::Local,
+        );
         dispatcher.process_events().unwrap();
         assert_eq!(created_count.load(Ordering::SeqCst), 1);
         assert_eq!(updated_count.load(Ordering::SeqCst), 1);
@@ -1178,8 +1620,16 @@ mod tests {
 
         // Emit multiple events of different types
         let doc_id = Uuid::new_v4();
-        dispatcher.emit_document_created(&doc_id, &serde_json::json!({"title": "Test1"}));
-        dispatcher.emit_document_updated(&doc_id, &serde_json::json!({"title": "Test2"}));
+        dispatcher.emit_document_created(
+            &doc_id,
+            &serde_json::json!({"title": "Test1"}),
+            DocumentSource::Local,
+        );
+        dispatcher.emit_document_updated(
+            &doc_id,
+            &serde_json::json!({"title": "Test2"}),
+            DocumentSource::Local,
+        );
         dispatcher.emit_document_deleted(&doc_id);
         dispatcher.emit_sync_started();
         dispatcher.emit_sync_completed(5);
@@ -1247,7 +1697,7 @@ mod tests {
             )
             .unwrap();
 
-        dispatcher.emit_connection_lost("ws://localhost:8080");
+        dispatcher.emit_connection_lost("ws://localhost:8080", DisconnectReason::SendFailed);
         dispatcher.emit_connection_attempted("ws://localhost:8080");
         dispatcher.emit_connection_succeeded("ws://localhost:8080");
 
@@ -1256,6 +1706,226 @@ mod tests {
         assert_eq!(conn_count.load(Ordering::SeqCst), 3);
     }
 
+    #[test]
+    fn test_reconnecting_event_carries_attempt() {
+        let dispatcher = EventDispatcher::new();
+        let last_attempt = Arc::new(AtomicUsize::new(0));
+        let attempt_clone = last_attempt.clone();
+
+        extern "C" fn conn_callback(
+            event_type: EventType,
+            _connected: bool,
+            attempt: u32,
+            context: *mut c_void,
+        ) {
+            assert_eq!(event_type, EventType::Reconnecting);
+            let last = unsafe { &*(context as *const AtomicUsize) };
+            last.store(attempt as usize, Ordering::SeqCst);
+        }
+
+        dispatcher
+            .register_connection_callback(
+                conn_callback,
+                &*attempt_clone as *const AtomicUsize as *mut c_void,
+            )
+            .unwrap();
+
+        let rust_events = Arc::new(Mutex::new(Vec::new()));
+        let rust_clone = rust_events.clone();
+        dispatcher
+            .register_rust_callback(move |event| rust_clone.lock().unwrap().push(event))
+            .unwrap();
+
+        dispatcher.emit_reconnecting(3);
+        dispatcher.process_events().unwrap();
+
+        assert_eq!(last_attempt.load(Ordering::SeqCst), 3);
+        let events = rust_events.lock().unwrap();
+        assert!(matches!(
+            events[..],
+            [SyncEvent::Reconnecting { attempt: 3 }]
+        ));
+    }
+
+    #[test]
+    fn test_connection_lost_event_carries_reason() {
+        let dispatcher = EventDispatcher::new();
+        let last_reason = Arc::new(AtomicUsize::new(usize::MAX));
+        let reason_clone = last_reason.clone();
+
+        extern "C" fn conn_callback(
+            event_type: EventType,
+            _connected: bool,
+            reason: u32,
+            context: *mut c_void,
+        ) {
+            assert_eq!(event_type, EventType::ConnectionLost);
+            let last = unsafe { &*(context as *const AtomicUsize) };
+            last.store(reason as usize, Ordering::SeqCst);
+        }
+
+        dispatcher
+            .register_connection_callback(
+                conn_callback,
+                &*reason_clone as *const AtomicUsize as *mut c_void,
+            )
+            .unwrap();
+
+        let rust_events = Arc::new(Mutex::new(Vec::new()));
+        let rust_clone = rust_events.clone();
+        dispatcher
+            .register_rust_callback(move |event| rust_clone.lock().unwrap().push(event))
+            .unwrap();
+
+        dispatcher.emit_connection_lost("ws://localhost:8080", DisconnectReason::ServerClosed);
+        dispatcher.process_events().unwrap();
+
+        assert_eq!(
+            last_reason.load(Ordering::SeqCst),
+            DisconnectReason::ServerClosed as usize
+        );
+        let events = rust_events.lock().unwrap();
+        assert!(matches!(
+            events[..],
+            [SyncEvent::ConnectionLost {
+                reason: DisconnectReason::ServerClosed,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_lock_changed_event_carries_holder() {
+        let dispatcher = EventDispatcher::new();
+        let rust_events = Arc::new(Mutex::new(Vec::new()));
+        let rust_clone = rust_events.clone();
+        dispatcher
+            .register_rust_callback(move |event| rust_clone.lock().unwrap().push(event))
+            .unwrap();
+
+        let document_id = Uuid::new_v4();
+        let holder = Uuid::new_v4();
+        dispatcher.emit_lock_changed(&document_id, Some(&holder));
+        dispatcher.emit_lock_changed(&document_id, None);
+        dispatcher.process_events().unwrap();
+
+        let events = rust_events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            SyncEvent::LockChanged {
+                document_id: id,
+                holder: Some(h),
+            } => {
+                assert_eq!(*id, document_id.to_string());
+                assert_eq!(*h, holder.to_string());
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(
+            events[1],
+            SyncEvent::LockChanged { holder: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_presence_changed_event_lists_clients() {
+        let dispatcher = EventDispatcher::new();
+        let rust_events = Arc::new(Mutex::new(Vec::new()));
+        let rust_clone = rust_events.clone();
+        dispatcher
+            .register_rust_callback_filtered(
+                move |event| rust_clone.lock().unwrap().push(event),
+                EventType::PresenceChanged,
+            )
+            .unwrap();
+
+        let clients = [Uuid::new_v4(), Uuid::new_v4()];
+        dispatcher.emit_presence_changed(&clients);
+        dispatcher.emit_presence_changed(&[]);
+        dispatcher.process_events().unwrap();
+
+        let events = rust_events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            SyncEvent::PresenceChanged { active_clients } => assert_eq!(
+                *active_clients,
+                vec![clients[0].to_string(), clients[1].to_string()]
+            ),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(
+            &events[1],
+            SyncEvent::PresenceChanged { active_clients } if active_clients.is_empty()
+        ));
+    }
+
+    #[test]
+    fn test_document_updated_event_carries_patch() {
+        let dispatcher = EventDispatcher::new();
+        let rust_events = Arc::new(Mutex::new(Vec::new()));
+        let rust_clone = rust_events.clone();
+        dispatcher
+            .register_rust_callback_filtered(
+                move |event| rust_clone.lock().unwrap().push(event),
+                EventType::DocumentUpdated,
+            )
+            .unwrap();
+
+        let patch_count = Arc::new(AtomicUsize::new(0));
+        extern "C" fn patch_callback(
+            _doc_id: *const c_char,
+            patch: *const c_char,
+            context: *mut c_void,
+        ) {
+            let patch = unsafe { std::ffi::CStr::from_ptr(patch) }.to_str().unwrap();
+            assert!(patch.contains("\"/done\""));
+            let count = unsafe { &*(context as *const AtomicUsize) };
+            count.fetch_add(1, Ordering::SeqCst);
+        }
+        dispatcher
+            .register_document_patch_callback(
+                patch_callback,
+                &*patch_count as *const AtomicUsize as *mut c_void,
+            )
+            .unwrap();
+
+        let doc_id = Uuid::new_v4();
+        let content = serde_json::json!({"title": "Task", "done": true});
+        let patch: json_patch::Patch = serde_json::from_value(
+            serde_json::json!([{"op": "add", "path": "/done", "value": true}]),
+        )
+        .unwrap();
+        dispatcher.emit_document_updated_with_patch(
+            &doc_id,
+            &content,
+            &patch,
+            DocumentSource::Local,
+        );
+        dispatcher.emit_document_updated(&doc_id, &content, DocumentSource::Local);
+        dispatcher.process_events().unwrap();
+
+        // Only the update with known changes reaches the patch callback
+        assert_eq!(patch_count.load(Ordering::SeqCst), 1);
+
+        let events = rust_events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        match &events[0] {
+            SyncEvent::DocumentUpdated {
+                content: received,
+                patch: Some(received_patch),
+                ..
+            } => {
+                assert_eq!(*received, content);
+                assert_eq!(*received_patch, patch);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(matches!(
+            &events[1],
+            SyncEvent::DocumentUpdated { patch: None, .. }
+        ));
+    }
+
     #[test]
     fn test_conflict_callback() {
         let dispatcher = EventDispatcher::new();
@@ -1313,7 +1983,11 @@ mod tests {
 
         // Emit events
         let doc_id = Uuid::new_v4();
-        dispatcher.emit_document_created(&doc_id, &serde_json::json!({"title": "Test Doc"}));
+        dispatcher.emit_document_created(
+            &doc_id,
+            &serde_json::json!({"title": "Test Doc"}),
+            DocumentSource::Local,
+        );
         dispatcher.emit_sync_completed(42);
         dispatcher.emit_connection_succeeded("ws://localhost:8080");
 
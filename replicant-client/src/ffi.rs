@@ -3,6 +3,7 @@
 //! This module provides C-compatible functions for using the sync client from C/C++.
 //! The generated header file will be available after building.
 
+use replicant_core::patches::create_patch;
 use serde_json::Value;
 use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
@@ -12,8 +13,9 @@ use tokio::runtime::Runtime;
 use uuid::Uuid;
 
 use crate::events::{
-    ConflictEventCallback, ConnectionEventCallback, DocumentEventCallback, ErrorEventCallback,
-    EventDispatcher, EventType, SyncEventCallback,
+    ConflictEventCallback, ConnectionEventCallback, DocumentEventCallback,
+    DocumentPatchEventCallback, DocumentSource, ErrorEventCallback, EventDispatcher, EventType,
+    SyncEventCallback,
 };
 use crate::{Client as CoreClient, ClientDatabase};
 
@@ -148,7 +150,11 @@ pub unsafe extern "C" fn replicant_create(
 #[no_mangle]
 pub unsafe extern "C" fn replicant_destroy(engine: *mut Replicant) {
     if !engine.is_null() {
-        let _ = Box::from_raw(engine);
+        let mut engine = Box::from_raw(engine);
+        // Stop background tasks before the runtime is dropped
+        if let Some(client) = engine.engine.take() {
+            let _ = engine.runtime.block_on(client.shutdown());
+        }
     }
 }
 
@@ -194,9 +200,11 @@ pub unsafe extern "C" fn replicant_create_document(
         {
             Ok(doc) => {
                 // Emit event to FFI event dispatcher
-                engine
-                    .event_dispatcher
-                    .emit_document_created(&doc.id, &content);
+                engine.event_dispatcher.emit_document_created(
+                    &doc.id,
+                    &content,
+                    DocumentSource::Local,
+                );
                 doc.id
             }
             Err(_) => return SyncResult::ErrorConnection,
@@ -222,6 +230,8 @@ pub unsafe extern "C" fn replicant_create_document(
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: replicant_core::models::SyncStatus::Pending,
         };
 
         if engine
@@ -235,7 +245,7 @@ pub unsafe extern "C" fn replicant_create_document(
         // Emit event for offline document creation
         engine
             .event_dispatcher
-            .emit_document_created(&doc_id, &content);
+            .emit_document_created(&doc_id, &content, DocumentSource::Local);
 
         doc_id
     };
@@ -309,13 +319,15 @@ pub unsafe extern "C" fn replicant_create_document_with_id(
         // Online mode - use sync engine
         match engine.runtime.block_on(async {
             sync_engine
-                .create_document_with_id(doc_id, content.clone())
+                .create_document_with_id(doc_id, content.clone(), false)
                 .await
         }) {
             Ok(doc) => {
-                engine
-                    .event_dispatcher
-                    .emit_document_created(&doc.id, &content);
+                engine.event_dispatcher.emit_document_created(
+                    &doc.id,
+                    &content,
+                    DocumentSource::Local,
+                );
             }
             Err(_) => return SyncResult::ErrorConnection,
         }
@@ -339,6 +351,8 @@ pub unsafe extern "C" fn replicant_create_document_with_id(
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: replicant_core::models::SyncStatus::Pending,
         };
 
         if engine
@@ -351,7 +365,7 @@ pub unsafe extern "C" fn replicant_create_document_with_id(
 
         engine
             .event_dispatcher
-            .emit_document_created(&doc_id, &content);
+            .emit_document_created(&doc_id, &content, DocumentSource::Local);
     }
 
     SyncResult::Success
@@ -420,6 +434,7 @@ pub unsafe extern "C" fn replicant_update_document(
             Err(_) => return SyncResult::ErrorDatabase,
         };
 
+        let changes = create_patch(&doc.content, &content).ok();
         let mut updated_doc = doc;
         updated_doc.content = content;
         updated_doc.sync_revision += 1;
@@ -432,9 +447,19 @@ pub unsafe extern "C" fn replicant_update_document(
         {
             Ok(_) => {
                 // Emit event for offline document update
-                engine
-                    .event_dispatcher
-                    .emit_document_updated(&doc_uuid, &updated_doc.content);
+                match changes {
+                    Some(patch) => engine.event_dispatcher.emit_document_updated_with_patch(
+                        &doc_uuid,
+                        &updated_doc.content,
+                        &patch,
+                        DocumentSource::Local,
+                    ),
+                    None => engine.event_dispatcher.emit_document_updated(
+                        &doc_uuid,
+                        &updated_doc.content,
+                        DocumentSource::Local,
+                    ),
+                }
                 SyncResult::Success
             }
             Err(_) => SyncResult::ErrorDatabase,
@@ -520,6 +545,40 @@ pub extern "C" fn replicant_get_version() -> *mut c_char {
     }
 }
 
+/// Get the user ID the server assigns to an email within an app namespace
+///
+/// # Arguments
+/// * `app_namespace` - The server's APP_NAMESPACE_ID
+/// * `email` - The user's email
+///
+/// # Returns
+/// * The UUID string (caller must free with replicant_string_free), or null on invalid input
+///
+/// # Safety
+/// Caller must ensure app_namespace and email are valid C strings
+#[no_mangle]
+pub unsafe extern "C" fn replicant_user_id_from_email(
+    app_namespace: *const c_char,
+    email: *const c_char,
+) -> *mut c_char {
+    if app_namespace.is_null() || email.is_null() {
+        return ptr::null_mut();
+    }
+
+    let (Ok(app_namespace), Ok(email)) = (
+        CStr::from_ptr(app_namespace).to_str(),
+        CStr::from_ptr(email).to_str(),
+    ) else {
+        return ptr::null_mut();
+    };
+
+    let user_id = replicant_core::models::user_id_from_email(app_namespace, email);
+    match CString::new(user_id.to_string()) {
+        Ok(s) => s.into_raw(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
 /// Register a callback for document events (Created, Updated, Deleted)
 ///
 /// # Arguments
@@ -599,7 +658,7 @@ pub unsafe extern "C" fn replicant_register_sync_callback(
     }
 }
 
-/// Register a callback for error events (SyncError)
+/// Register a callback for error events (SyncError, AuthenticationFailed)
 ///
 /// # Arguments
 /// * `engine` - Sync engine instance
@@ -698,6 +757,42 @@ pub unsafe extern "C" fn replicant_register_conflict_callback(
     }
 }
 
+/// Register a callback for the JSON Patch behind each document update
+///
+/// Called after the document callback for DocumentUpdated events whose changes
+/// are known, so apps can highlight just the fields that changed.
+///
+/// # Arguments
+/// * `engine` - Sync engine instance
+/// * `callback` - C callback function to invoke with each patch
+/// * `context` - User-defined context pointer passed to callback
+///
+/// # Returns
+/// * SyncResult indicating success or failure
+///
+/// # Safety
+/// Caller must ensure engine is valid, callback is a valid function pointer, and context pointer outlives the callback registration
+#[no_mangle]
+pub unsafe extern "C" fn replicant_register_document_patch_callback(
+    engine: *mut Replicant,
+    callback: DocumentPatchEventCallback,
+    context: *mut c_void,
+) -> SyncResult {
+    if engine.is_null() {
+        return SyncResult::ErrorInvalidInput;
+    }
+
+    let engine = &*engine;
+
+    match engine
+        .event_dispatcher
+        .register_document_patch_callback(callback, context)
+    {
+        Ok(_) => SyncResult::Success,
+        Err(_) => SyncResult::ErrorUnknown,
+    }
+}
+
 /// Process all queued events on the current thread
 ///
 /// # Arguments
@@ -1034,10 +1129,13 @@ pub unsafe extern "C" fn replicant_search_documents(
 
     let limit = if limit == 0 { 100 } else { limit as i64 };
 
-    let docs = match engine
-        .runtime
-        .block_on(async { engine.database.search_documents(query, limit).await })
-    {
+    let docs = match engine.runtime.block_on(async {
+        let user_id = engine.database.get_user_id().await?;
+        engine
+            .database
+            .search_documents(&user_id, query, limit)
+            .await
+    }) {
         Ok(d) => d,
         Err(_) => return SyncResult::ErrorDatabase,
     };
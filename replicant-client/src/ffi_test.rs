@@ -3,6 +3,7 @@
 //! This module provides test-only C-compatible functions for development and testing.
 //! These functions are only available in debug builds.
 
+use crate::events::{DisconnectReason, DocumentSource};
 use crate::ffi::{Replicant, SyncResult};
 use uuid::Uuid;
 
@@ -10,7 +11,7 @@ use uuid::Uuid;
 ///
 /// # Arguments
 /// * `engine` - Replicant client instance
-/// * `event_type` - Event type to emit (0-7)
+/// * `event_type` - Event type to emit (0-12)
 ///
 /// # Returns
 /// * SyncResult indicating success or failure
@@ -26,6 +27,12 @@ use uuid::Uuid;
 /// * 7 - ConnectionLost
 /// * 8 - ConnectionAttempted
 /// * 9 - ConnectionSucceeded
+/// * 10 - Reconnecting
+/// * 11 - LockChanged
+/// * 12 - PresenceChanged
+/// * 13 - AuthenticationFailed
+/// * 14 - SyncSkipped
+/// * 15 - DocumentSizeWarning
 ///
 /// # Safety
 /// Caller must ensure engine is a valid pointer
@@ -45,16 +52,20 @@ pub unsafe extern "C" fn replicant_emit_test_event(
         0 => {
             let test_id = Uuid::new_v4();
             let test_content = serde_json::json!({"title": "Test Document (Created)", "test": "data", "created_at": chrono::Utc::now()});
-            engine
-                .event_dispatcher
-                .emit_document_created(&test_id, &test_content);
+            engine.event_dispatcher.emit_document_created(
+                &test_id,
+                &test_content,
+                DocumentSource::Local,
+            );
         }
         1 => {
             let test_id = Uuid::new_v4();
             let test_content = serde_json::json!({"title": "Test Document (Updated)", "test": "updated", "updated_at": chrono::Utc::now()});
-            engine
-                .event_dispatcher
-                .emit_document_updated(&test_id, &test_content);
+            engine.event_dispatcher.emit_document_updated(
+                &test_id,
+                &test_content,
+                DocumentSource::Local,
+            );
         }
         2 => {
             let test_id = Uuid::new_v4();
@@ -69,13 +80,39 @@ pub unsafe extern "C" fn replicant_emit_test_event(
             let test_id = Uuid::new_v4();
             engine.event_dispatcher.emit_conflict_detected(&test_id);
         }
-        7 => engine.event_dispatcher.emit_connection_lost("test-server"),
+        7 => engine
+            .event_dispatcher
+            .emit_connection_lost("test-server", DisconnectReason::ServerClosed),
         8 => engine
             .event_dispatcher
             .emit_connection_attempted("test-server"),
         9 => engine
             .event_dispatcher
             .emit_connection_succeeded("test-server"),
+        10 => engine.event_dispatcher.emit_reconnecting(1),
+        11 => {
+            let test_id = Uuid::new_v4();
+            engine
+                .event_dispatcher
+                .emit_lock_changed(&test_id, Some(&Uuid::new_v4()));
+        }
+        12 => engine
+            .event_dispatcher
+            .emit_presence_changed(&[Uuid::new_v4(), Uuid::new_v4()]),
+        13 => engine
+            .event_dispatcher
+            .emit_authentication_failed("Invalid credentials"),
+        14 => engine.event_dispatcher.emit_sync_skipped(
+            &Uuid::new_v4(),
+            3,
+            2,
+            "Local revision is newer",
+        ),
+        15 => engine.event_dispatcher.emit_document_size_warning(
+            &Uuid::new_v4(),
+            2 * 1024 * 1024,
+            1024 * 1024,
+        ),
         _ => return SyncResult::ErrorInvalidInput,
     }
 
@@ -113,9 +150,11 @@ pub unsafe extern "C" fn replicant_emit_test_event_burst(
             "sequence": i,
             "timestamp": chrono::Utc::now()
         });
-        engine
-            .event_dispatcher
-            .emit_document_created(&test_id, &test_content);
+        engine.event_dispatcher.emit_document_created(
+            &test_id,
+            &test_content,
+            DocumentSource::Local,
+        );
     }
 
     SyncResult::Success
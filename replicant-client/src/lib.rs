@@ -1,25 +1,53 @@
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("building for wasm32 needs the `wasm` feature");
+
+pub mod backup;
 pub mod client;
 pub mod database;
+pub mod encryption;
 pub mod events;
+pub mod loop_guard;
+pub mod memory_store;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod offline_queue;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod queries;
+mod runtime;
+pub mod stats;
+pub mod store;
+pub mod tls;
+pub mod transaction;
 pub mod websocket;
 
 // C FFI module
+#[cfg(not(target_arch = "wasm32"))]
 pub mod ffi;
 
 // C FFI test functions (debug builds only)
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
 pub mod ffi_test;
 
-pub use client::Client;
+pub use backup::ImportMode;
+pub use client::{
+    AutoCompact, Client, ClientConfig, ConnectionState, ContentMigration, ContentValidator,
+    DeferredOverflowPolicy, ServerResolver, SyncSummary, SyncTimeouts, SCHEMA_VERSION_KEY,
+};
+#[cfg(not(target_arch = "wasm32"))]
 pub use database::ClientDatabase;
+pub use database::{Attachment, CompactReport, ConflictRecord, DocumentOrder, RepairReport};
+pub use encryption::{AesGcmCipher, ContentCipher};
+pub use loop_guard::SyncLoopLimit;
+pub use memory_store::MemoryStore;
+pub use stats::ClientStats;
+pub use store::DocumentStore;
+pub use tls::Sha256Fingerprint;
+pub use transaction::Tx;
 pub use websocket::WebSocketClient;
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use replicant_core::models::Document;
+    use replicant_core::models::{Document, SyncStatus};
     use serde_json::json;
     use sqlx::Row;
     use uuid::Uuid;
@@ -49,8 +77,27 @@ mod tests {
                 local_changes JSON,
                 sync_status TEXT DEFAULT 'synced',
                 title TEXT,
+                idempotency_key TEXT,
+                content_zstd BLOB,
+                expires_at TEXT,
+                local_seq INTEGER NOT NULL DEFAULT 0,
+                uploaded INTEGER NOT NULL DEFAULT 0,
                 CHECK (sync_status IN ('synced', 'pending', 'conflict'))
             );
+
+            CREATE TABLE search_config (
+                json_path TEXT NOT NULL PRIMARY KEY
+            );
+
+            CREATE TABLE tag_config (
+                json_path TEXT NOT NULL PRIMARY KEY
+            );
+
+            CREATE TABLE document_tags (
+                document_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (document_id, tag)
+            );
             "#,
         )
         .execute(&db.pool)
@@ -86,6 +133,8 @@ mod tests {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         // Save document
@@ -118,7 +167,10 @@ mod tests {
                 created_at: chrono::Utc::now(),
                 updated_at: chrono::Utc::now(),
                 deleted_at: None,
+                expires_at: None,
+                sync_status: SyncStatus::Synced,
             },
+            idempotency_key: None,
         };
 
         assert_eq!(extract_document_id(&create_msg), Some(doc_id));
@@ -127,6 +179,7 @@ mod tests {
         // Test delete message
         let delete_msg = ClientMessage::DeleteDocument {
             document_id: doc_id,
+            idempotency_key: None,
         };
 
         assert_eq!(extract_document_id(&delete_msg), Some(doc_id));
@@ -158,8 +211,27 @@ mod tests {
                 local_changes JSON,
                 sync_status TEXT DEFAULT 'synced',
                 title TEXT,
+                idempotency_key TEXT,
+                content_zstd BLOB,
+                expires_at TEXT,
+                local_seq INTEGER NOT NULL DEFAULT 0,
+                uploaded INTEGER NOT NULL DEFAULT 0,
                 CHECK (sync_status IN ('synced', 'pending', 'conflict'))
             );
+
+            CREATE TABLE search_config (
+                json_path TEXT NOT NULL PRIMARY KEY
+            );
+
+            CREATE TABLE tag_config (
+                json_path TEXT NOT NULL PRIMARY KEY
+            );
+
+            CREATE TABLE document_tags (
+                document_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (document_id, tag)
+            );
             "#,
         )
         .execute(&db.pool)
@@ -190,6 +262,8 @@ mod tests {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         // Save document
@@ -236,8 +310,27 @@ mod tests {
                 local_changes JSON,
                 sync_status TEXT DEFAULT 'synced',
                 title TEXT,
+                idempotency_key TEXT,
+                content_zstd BLOB,
+                expires_at TEXT,
+                local_seq INTEGER NOT NULL DEFAULT 0,
+                uploaded INTEGER NOT NULL DEFAULT 0,
                 CHECK (sync_status IN ('synced', 'pending', 'conflict'))
             );
+
+            CREATE TABLE search_config (
+                json_path TEXT NOT NULL PRIMARY KEY
+            );
+
+            CREATE TABLE tag_config (
+                json_path TEXT NOT NULL PRIMARY KEY
+            );
+
+            CREATE TABLE document_tags (
+                document_id TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (document_id, tag)
+            );
             "#,
         )
         .execute(&db.pool)
@@ -265,6 +358,8 @@ mod tests {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         db.save_document(&doc).await.unwrap();
@@ -286,6 +381,8 @@ mod tests {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         db.save_document(&doc2).await.unwrap();
@@ -0,0 +1,126 @@
+//! Detects documents bouncing between this client and the server.
+//!
+//! Server changes are applied without queueing anything, but an app that
+//! saves a document again whenever it hears about a remote change can still
+//! send it straight back, and two such clients keep each other busy forever.
+//! Each document remembers whether the server changed it since it was last
+//! uploaded; uploads that answer a server change count as bounces, and a
+//! document that bounces too often is held back for a while.
+
+use crate::events::EventDispatcher;
+use crate::runtime::Instant;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often a document may bounce before its uploads are held back, see
+/// `ClientConfig::sync_loop_limit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncLoopLimit {
+    /// Uploads answering a server change allowed within `window`
+    pub max_bounces: usize,
+    pub window: Duration,
+    /// How long a looping document's changes stay local
+    pub quarantine: Duration,
+}
+
+impl Default for SyncLoopLimit {
+    fn default() -> Self {
+        Self {
+            max_bounces: 10,
+            window: Duration::from_secs(10),
+            quarantine: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Bounces {
+    // The server changed the document since it was last uploaded
+    server_changed: bool,
+    at: VecDeque<Instant>,
+    quarantined_until: Option<Instant>,
+}
+
+/// Per-document bounce tracking shared with the connection tasks
+#[derive(Debug)]
+pub struct LoopGuard {
+    limit: SyncLoopLimit,
+    documents: Mutex<HashMap<Uuid, Bounces>>,
+}
+
+impl LoopGuard {
+    pub fn new(limit: SyncLoopLimit) -> Self {
+        Self {
+            limit,
+            documents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Note that a change from the server was applied to `id`
+    pub(crate) fn server_changed(&self, id: Uuid) {
+        self.documents
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_default()
+            .server_changed = true;
+    }
+
+    /// Whether `id` may be uploaded now. Counts the upload if it answers a
+    /// server change, and emits a sync error when that puts the document in
+    /// quarantine. Held-back changes stay pending for the first sync after
+    /// the quarantine ends.
+    pub(crate) fn admit(&self, id: Uuid, event_dispatcher: &EventDispatcher) -> bool {
+        let now = Instant::now();
+        let mut documents = self.documents.lock().unwrap();
+        let Some(bounces) = documents.get_mut(&id) else {
+            return true;
+        };
+
+        if let Some(until) = bounces.quarantined_until {
+            if now < until {
+                return false;
+            }
+            bounces.quarantined_until = None;
+        }
+        if !std::mem::take(&mut bounces.server_changed) {
+            return true;
+        }
+
+        while bounces
+            .at
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > self.limit.window)
+        {
+            bounces.at.pop_front();
+        }
+        bounces.at.push_back(now);
+        if bounces.at.len() <= self.limit.max_bounces {
+            return true;
+        }
+
+        bounces.at.clear();
+        bounces.quarantined_until = Some(now + self.limit.quarantine);
+        tracing::warn!("Document {} is stuck in a sync loop, holding it back", id);
+        event_dispatcher.emit_sync_error(&format!(
+            "Document {} synced {} times in {:?} in answer to server changes; keeping its changes local for {:?}",
+            id,
+            self.limit.max_bounces + 1,
+            self.limit.window,
+            self.limit.quarantine
+        ));
+        false
+    }
+
+    /// Whether `id` is currently held back
+    pub fn is_quarantined(&self, id: &Uuid) -> bool {
+        self.documents
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|bounces| bounces.quarantined_until)
+            .is_some_and(|until| Instant::now() < until)
+    }
+}
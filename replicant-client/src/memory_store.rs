@@ -0,0 +1,744 @@
+//! In-memory [`DocumentStore`].
+//!
+//! Nothing is persisted, so everything not yet uploaded is lost when the
+//! store is dropped. Useful for tests, and for targets without SQLite such as
+//! the browser.
+
+use crate::backup::{ExportedDocument, ImportMode, QueuedOperation};
+use crate::database::{Attachment, ConflictRecord, DocumentOrder, PendingDocumentInfo};
+use crate::store::DocumentStore;
+use async_trait::async_trait;
+use replicant_core::{
+    errors::ClientError,
+    models::{
+        extract_tags, user_id_from_email, Document, SyncStatus, DEFAULT_APP_NAMESPACE,
+        DEFAULT_TAG_PATH,
+    },
+    protocol::ChangeEventType,
+    SyncError, SyncResult,
+};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+struct StoredDocument {
+    document: Document,
+    sync_status: SyncStatus,
+    local_only: bool,
+    idempotency_key: Option<Uuid>,
+    local_seq: i64,
+    // The server has the document, see `PendingDocumentInfo::uploaded`
+    uploaded: bool,
+    // See `PendingDocumentInfo::create_sent`
+    create_sent: bool,
+}
+
+impl StoredDocument {
+    /// The document as returned to callers, carrying its sync status
+    fn snapshot(&self) -> Document {
+        Document {
+            sync_status: self.sync_status,
+            ..self.document.clone()
+        }
+    }
+}
+
+struct QueueEntry {
+    document_id: Uuid,
+    operation: QueuedOperation,
+    // The document's local_seq when queued
+    local_seq: i64,
+}
+
+#[derive(Default)]
+struct State {
+    identity: Option<(Uuid, Uuid)>,
+    last_sync_at: Option<chrono::DateTime<chrono::Utc>>,
+    documents: HashMap<Uuid, StoredDocument>,
+    // Oldest first, like the SQLite queue's autoincrement order
+    queue: Vec<QueueEntry>,
+    conflicts: HashMap<Uuid, ConflictRecord>,
+    // Oldest first, like the SQLite store's insertion order
+    attachments: Vec<Attachment>,
+    // Last local_seq handed out, shared by all documents
+    last_local_seq: i64,
+    // Where tags are read from, `DEFAULT_TAG_PATH` until configured
+    tag_path: Option<String>,
+}
+
+impl State {
+    fn document(&mut self, id: &Uuid) -> SyncResult<&mut StoredDocument> {
+        self.documents
+            .get_mut(id)
+            .ok_or(SyncError::DocumentNotFound(*id))
+    }
+
+    /// Insert or overwrite a document, keeping the local-only flag of an
+    /// existing one. Like the SQLite store, a synced save means the server
+    /// has the document and bringing back a locally deleted one uploads it
+    /// afresh.
+    fn upsert(&mut self, doc: &Document, sync_status: SyncStatus) {
+        let mut document = doc.clone();
+        // Match the SQLite store, which stores a title and never a hash
+        document.title = Some(title_of(doc));
+        document.content_hash = None;
+        let (local_only, local_seq, uploaded, create_sent) = match self.documents.get(&doc.id) {
+            Some(stored) => (
+                stored.local_only,
+                stored.local_seq,
+                stored.uploaded
+                    && !(stored.document.deleted_at.is_some() && doc.deleted_at.is_none()),
+                stored.create_sent,
+            ),
+            None => (false, 0, false, false),
+        };
+        self.documents.insert(
+            doc.id,
+            StoredDocument {
+                document,
+                sync_status,
+                local_only,
+                idempotency_key: None,
+                local_seq,
+                uploaded: uploaded || sync_status == SyncStatus::Synced,
+                create_sent,
+            },
+        );
+    }
+
+    /// Give a document the next local edit number
+    fn next_local_seq(&mut self, id: &Uuid) -> SyncResult<i64> {
+        self.last_local_seq += 1;
+        let local_seq = self.last_local_seq;
+        self.document(id)?.local_seq = local_seq;
+        Ok(local_seq)
+    }
+
+    fn enqueue(&mut self, document_id: Uuid, operation: QueuedOperation) {
+        let local_seq = self
+            .documents
+            .get(&document_id)
+            .map_or(0, |stored| stored.local_seq);
+        self.queue.push(QueueEntry {
+            document_id,
+            operation,
+            local_seq,
+        });
+    }
+
+    fn queued_updates<'a>(
+        &'a self,
+        document_id: &'a Uuid,
+    ) -> impl Iterator<Item = &'a QueuedOperation> + 'a {
+        self.queue
+            .iter()
+            .filter(move |entry| {
+                entry.document_id == *document_id
+                    && entry.operation.operation_type == ChangeEventType::Update
+            })
+            .map(|entry| &entry.operation)
+    }
+}
+
+fn title_of(doc: &Document) -> String {
+    doc.title.clone().unwrap_or_else(|| {
+        doc.content
+            .get("title")
+            .and_then(|v| v.as_str())
+            .map(|s| s.chars().take(128).collect::<String>())
+            .unwrap_or_else(|| doc.created_at.format("%Y-%m-%d|%H:%M:%S%.3f").to_string())
+    })
+}
+
+fn parse_patch(value: &serde_json::Value) -> SyncResult<json_patch::Patch> {
+    Ok(serde_json::from_value(value.clone())?)
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> SyncResult<MutexGuard<'_, State>> {
+        self.state
+            .lock()
+            .map_err(|_| ClientError::LockError("store".to_string()).into())
+    }
+}
+
+#[async_trait]
+impl DocumentStore for MemoryStore {
+    async fn ensure_user_config_with_identifier(
+        &self,
+        _server_url: &str,
+        user_identifier: &str,
+    ) -> SyncResult<()> {
+        let mut state = self.state()?;
+        if state.identity.is_none() {
+            // Must match the server's ID for the same email
+            let user_id = user_id_from_email(DEFAULT_APP_NAMESPACE, user_identifier);
+            state.identity = Some((user_id, Uuid::new_v4()));
+        }
+        Ok(())
+    }
+
+    async fn get_user_and_client_id(&self) -> SyncResult<(Uuid, Uuid)> {
+        self.state()?.identity.ok_or(SyncError::NotInitialized)
+    }
+
+    async fn schema_version(&self) -> SyncResult<i64> {
+        Ok(0)
+    }
+
+    async fn set_last_sync_at(&self, at: chrono::DateTime<chrono::Utc>) -> SyncResult<()> {
+        self.state()?.last_sync_at = Some(at);
+        Ok(())
+    }
+
+    async fn get_last_sync_at(&self) -> SyncResult<Option<chrono::DateTime<chrono::Utc>>> {
+        Ok(self.state()?.last_sync_at)
+    }
+
+    async fn get_document(&self, id: &Uuid) -> SyncResult<Document> {
+        Ok(self.state()?.document(id)?.snapshot())
+    }
+
+    async fn get_all_documents(&self) -> SyncResult<Vec<Document>> {
+        Ok(self
+            .state()?
+            .documents
+            .values()
+            .filter(|stored| stored.document.deleted_at.is_none())
+            .filter(|stored| !stored.document.is_expired())
+            .map(StoredDocument::snapshot)
+            .collect())
+    }
+
+    async fn get_documents_page(
+        &self,
+        offset: i64,
+        limit: i64,
+        order: DocumentOrder,
+    ) -> SyncResult<Vec<Document>> {
+        let mut documents = self.get_all_documents().await?;
+        documents.sort_by(|a, b| {
+            let ordering = match order {
+                DocumentOrder::UpdatedDesc => b.updated_at.cmp(&a.updated_at),
+                DocumentOrder::UpdatedAsc => a.updated_at.cmp(&b.updated_at),
+                DocumentOrder::CreatedDesc => b.created_at.cmp(&a.created_at),
+                DocumentOrder::CreatedAsc => a.created_at.cmp(&b.created_at),
+                DocumentOrder::Title => a.title.cmp(&b.title),
+            };
+            ordering.then_with(|| a.id.to_string().cmp(&b.id.to_string()))
+        });
+        Ok(documents
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    async fn get_documents_changed_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        include_deleted: bool,
+    ) -> SyncResult<Vec<Document>> {
+        let mut documents: Vec<Document> = self
+            .state()?
+            .documents
+            .values()
+            .filter(|stored| include_deleted || stored.document.deleted_at.is_none())
+            .filter(|stored| stored.document.updated_at > since)
+            .map(StoredDocument::snapshot)
+            .collect();
+        documents.sort_by_key(|doc| doc.updated_at);
+        Ok(documents)
+    }
+
+    async fn count_documents(&self) -> SyncResult<i64> {
+        Ok(self
+            .state()?
+            .documents
+            .values()
+            .filter(|stored| stored.document.deleted_at.is_none())
+            .filter(|stored| !stored.document.is_expired())
+            .count() as i64)
+    }
+
+    async fn save_document(&self, doc: &Document) -> SyncResult<()> {
+        self.save_document_with_status(doc, None).await
+    }
+
+    async fn save_local_document(&self, doc: &Document) -> SyncResult<()> {
+        let mut state = self.state()?;
+        state.upsert(doc, SyncStatus::Pending);
+        state.document(&doc.id)?.local_only = true;
+        state.next_local_seq(&doc.id)?;
+        Ok(())
+    }
+
+    async fn save_document_with_status(
+        &self,
+        doc: &Document,
+        sync_status: Option<SyncStatus>,
+    ) -> SyncResult<()> {
+        let mut state = self.state()?;
+        state.upsert(doc, sync_status.unwrap_or(SyncStatus::Pending));
+        if sync_status == Some(SyncStatus::Pending) {
+            state.next_local_seq(&doc.id)?;
+        }
+        Ok(())
+    }
+
+    async fn delete_document(&self, document_id: &Uuid) -> SyncResult<()> {
+        let mut state = self.state()?;
+        let Ok(stored) = state.document(document_id) else {
+            return Ok(());
+        };
+        stored.document.deleted_at = Some(chrono::Utc::now());
+        stored.sync_status = SyncStatus::Pending;
+        stored.idempotency_key = None;
+        state.next_local_seq(document_id)?;
+        Ok(())
+    }
+
+    async fn save_documents_atomically(
+        &self,
+        documents: &[Document],
+        removed: &[Uuid],
+    ) -> SyncResult<()> {
+        let mut state = self.state()?;
+        for doc in documents {
+            state.upsert(doc, doc.sync_status);
+        }
+        for document_id in removed {
+            state.documents.remove(document_id);
+            state.conflicts.remove(document_id);
+            state
+                .queue
+                .retain(|entry| entry.document_id != *document_id);
+            state
+                .attachments
+                .retain(|attachment| attachment.document_id != *document_id);
+        }
+        Ok(())
+    }
+
+    async fn is_local_only(&self, document_id: &Uuid) -> SyncResult<bool> {
+        Ok(self.state()?.document(document_id)?.local_only)
+    }
+
+    async fn set_local_only(&self, document_id: &Uuid, local_only: bool) -> SyncResult<()> {
+        let mut state = self.state()?;
+        if let Ok(stored) = state.document(document_id) {
+            stored.local_only = local_only;
+            stored.sync_status = SyncStatus::Pending;
+            stored.idempotency_key = None;
+        }
+        Ok(())
+    }
+
+    async fn set_server_timestamps(
+        &self,
+        document_id: &Uuid,
+        created_at: Option<chrono::DateTime<chrono::Utc>>,
+        updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> SyncResult<()> {
+        let mut state = self.state()?;
+        if let Ok(stored) = state.document(document_id) {
+            if let Some(created_at) = created_at {
+                stored.document.created_at = created_at;
+            }
+            if let Some(updated_at) = updated_at {
+                stored.document.updated_at = updated_at;
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_sync_revision(&self, document_id: &Uuid, sync_revision: i64) -> SyncResult<()> {
+        let mut state = self.state()?;
+        if let Ok(stored) = state.document(document_id) {
+            stored.document.sync_revision = sync_revision;
+        }
+        Ok(())
+    }
+
+    async fn get_pending_documents(&self) -> SyncResult<Vec<PendingDocumentInfo>> {
+        let state = self.state()?;
+        let mut pending: Vec<&StoredDocument> = state
+            .documents
+            .values()
+            .filter(|stored| stored.sync_status == SyncStatus::Pending && !stored.local_only)
+            .collect();
+        pending.sort_by_key(|stored| (stored.local_seq, stored.document.id));
+        Ok(pending
+            .into_iter()
+            .map(|stored| PendingDocumentInfo {
+                id: stored.document.id,
+                is_deleted: stored.document.deleted_at.is_some(),
+                uploaded: stored.uploaded,
+                create_sent: stored.create_sent,
+            })
+            .collect())
+    }
+
+    async fn get_sync_status(&self, document_id: &Uuid) -> SyncResult<SyncStatus> {
+        Ok(self.state()?.document(document_id)?.sync_status)
+    }
+
+    async fn set_sync_status(&self, document_id: &Uuid, status: SyncStatus) -> SyncResult<()> {
+        let mut state = self.state()?;
+        if let Ok(stored) = state.document(document_id) {
+            stored.sync_status = status;
+            stored.idempotency_key = None;
+            stored.uploaded |= status == SyncStatus::Synced;
+        }
+        Ok(())
+    }
+
+    async fn mark_synced(&self, document_id: &Uuid) -> SyncResult<()> {
+        self.set_sync_status(document_id, SyncStatus::Synced).await
+    }
+
+    async fn idempotency_key(&self, document_id: &Uuid) -> SyncResult<Uuid> {
+        let mut state = self.state()?;
+        let stored = state.document(document_id)?;
+        Ok(*stored.idempotency_key.get_or_insert_with(Uuid::new_v4))
+    }
+
+    async fn save_conflict(
+        &self,
+        document_id: &Uuid,
+        local_content: &serde_json::Value,
+        server_content: &serde_json::Value,
+    ) -> SyncResult<()> {
+        self.state()?.conflicts.insert(
+            *document_id,
+            ConflictRecord {
+                document_id: *document_id,
+                local_content: local_content.clone(),
+                server_content: server_content.clone(),
+                detected_at: chrono::Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_conflict(&self, document_id: &Uuid) -> SyncResult<Option<ConflictRecord>> {
+        Ok(self.state()?.conflicts.get(document_id).cloned())
+    }
+
+    async fn delete_conflict(&self, document_id: &Uuid) -> SyncResult<()> {
+        self.state()?.conflicts.remove(document_id);
+        Ok(())
+    }
+
+    async fn save_attachment(&self, attachment: &Attachment) -> SyncResult<()> {
+        let mut state = self.state()?;
+        match state.attachments.iter_mut().find(|a| a.id == attachment.id) {
+            Some(existing) => *existing = attachment.clone(),
+            None => state.attachments.push(attachment.clone()),
+        }
+        Ok(())
+    }
+
+    async fn get_attachment(&self, id: &Uuid) -> SyncResult<Option<Attachment>> {
+        Ok(self
+            .state()?
+            .attachments
+            .iter()
+            .find(|attachment| attachment.id == *id)
+            .cloned())
+    }
+
+    async fn set_attachment_uploaded(&self, id: &Uuid, uploaded: u64) -> SyncResult<()> {
+        let mut state = self.state()?;
+        if let Some(attachment) = state.attachments.iter_mut().find(|a| a.id == *id) {
+            attachment.uploaded = uploaded;
+        }
+        Ok(())
+    }
+
+    async fn get_pending_attachments(&self) -> SyncResult<Vec<Uuid>> {
+        Ok(self
+            .state()?
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.uploaded < attachment.size)
+            .map(|attachment| attachment.id)
+            .collect())
+    }
+
+    async fn delete_attachment(&self, id: &Uuid) -> SyncResult<()> {
+        self.state()?
+            .attachments
+            .retain(|attachment| attachment.id != *id);
+        Ok(())
+    }
+
+    async fn count_conflicts(&self) -> SyncResult<i64> {
+        Ok(self
+            .state()?
+            .documents
+            .values()
+            .filter(|stored| stored.sync_status == SyncStatus::Conflict)
+            .count() as i64)
+    }
+
+    async fn save_document_and_queue_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        operation_type: ChangeEventType,
+        old_content_hash: Option<String>,
+    ) -> SyncResult<i64> {
+        let mut state = self.state()?;
+        state.upsert(doc, SyncStatus::Pending);
+        let local_seq = state.next_local_seq(&doc.id)?;
+        // Local-only documents are never queued for sync
+        if !state.document(&doc.id)?.local_only {
+            state.enqueue(
+                doc.id,
+                QueuedOperation {
+                    operation_type,
+                    patch: Some(serde_json::to_value(patch)?),
+                    old_content_hash,
+                },
+            );
+        }
+        Ok(local_seq)
+    }
+
+    async fn local_seq(&self, document_id: &Uuid) -> SyncResult<i64> {
+        Ok(self.state()?.document(document_id)?.local_seq)
+    }
+
+    async fn mark_create_sent(&self, document_id: &Uuid) -> SyncResult<()> {
+        self.state()?.document(document_id)?.create_sent = true;
+        Ok(())
+    }
+
+    async fn get_queued_patch(
+        &self,
+        document_id: &Uuid,
+    ) -> SyncResult<Option<(json_patch::Patch, Option<String>)>> {
+        let state = self.state()?;
+        let Some(latest) = state.queued_updates(document_id).last() else {
+            return Ok(None);
+        };
+        match &latest.patch {
+            Some(patch) => Ok(Some((parse_patch(patch)?, latest.old_content_hash.clone()))),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_queued_patches(&self, document_id: &Uuid) -> SyncResult<Vec<json_patch::Patch>> {
+        self.state()?
+            .queued_updates(document_id)
+            .filter_map(|op| op.patch.as_ref())
+            .map(parse_patch)
+            .collect()
+    }
+
+    async fn replace_queued_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        old_content_hash: String,
+    ) -> SyncResult<()> {
+        let mut state = self.state()?;
+        state.queue.retain(|entry| entry.document_id != doc.id);
+        state.upsert(doc, SyncStatus::Pending);
+        state.enqueue(
+            doc.id,
+            QueuedOperation {
+                operation_type: ChangeEventType::Update,
+                patch: Some(serde_json::to_value(patch)?),
+                old_content_hash: Some(old_content_hash),
+            },
+        );
+        Ok(())
+    }
+
+    async fn remove_from_sync_queue(&self, document_id: &Uuid) -> SyncResult<()> {
+        self.state()?
+            .queue
+            .retain(|entry| entry.document_id != *document_id);
+        Ok(())
+    }
+
+    async fn remove_from_sync_queue_through(
+        &self,
+        document_id: &Uuid,
+        local_seq: i64,
+    ) -> SyncResult<()> {
+        self.state()?
+            .queue
+            .retain(|entry| entry.document_id != *document_id || entry.local_seq > local_seq);
+        Ok(())
+    }
+
+    async fn remove_stale_queue_entries(&self) -> SyncResult<u64> {
+        let mut state = self.state()?;
+        let State {
+            documents, queue, ..
+        } = &mut *state;
+        let before = queue.len();
+        queue.retain(|entry| {
+            documents
+                .get(&entry.document_id)
+                .is_none_or(|stored| stored.sync_status != SyncStatus::Synced && !stored.local_only)
+        });
+        Ok((before - queue.len()) as u64)
+    }
+
+    async fn get_unqueued_pending_documents(&self) -> SyncResult<Vec<Document>> {
+        let state = self.state()?;
+        Ok(state
+            .documents
+            .values()
+            .filter(|stored| {
+                stored.sync_status == SyncStatus::Pending
+                    && !stored.local_only
+                    && stored.document.deleted_at.is_none()
+                    && stored.document.sync_revision > 1
+                    && !state
+                        .queue
+                        .iter()
+                        .any(|entry| entry.document_id == stored.document.id)
+            })
+            .map(StoredDocument::snapshot)
+            .collect())
+    }
+
+    async fn queue_depth(&self) -> SyncResult<i64> {
+        Ok(self.state()?.queue.len() as i64)
+    }
+
+    async fn coalesce_sync_queue(&self) -> SyncResult<u64> {
+        let mut state = self.state()?;
+        let before = state.queue.len();
+
+        // Fold later updates into the first one queued for the same document
+        let mut queue: Vec<QueueEntry> = Vec::with_capacity(before);
+        for entry in std::mem::take(&mut state.queue) {
+            let is_update = entry.operation.operation_type == ChangeEventType::Update;
+            let earlier = queue.iter_mut().find(|earlier| {
+                is_update
+                    && earlier.document_id == entry.document_id
+                    && earlier.operation.operation_type == ChangeEventType::Update
+            });
+            match (earlier, entry.operation.patch) {
+                (
+                    Some(QueueEntry {
+                        operation:
+                            QueuedOperation {
+                                patch: Some(serde_json::Value::Array(ops)),
+                                ..
+                            },
+                        local_seq,
+                        ..
+                    }),
+                    Some(serde_json::Value::Array(more)),
+                ) => {
+                    ops.extend(more);
+                    *local_seq = entry.local_seq;
+                }
+                (_, patch) => queue.push(QueueEntry {
+                    operation: QueuedOperation {
+                        patch,
+                        ..entry.operation
+                    },
+                    ..entry
+                }),
+            }
+        }
+        state.queue = queue;
+        Ok((before - state.queue.len()) as u64)
+    }
+
+    async fn export_documents(&self) -> SyncResult<Vec<ExportedDocument>> {
+        let state = self.state()?;
+        let mut documents: Vec<ExportedDocument> = state
+            .documents
+            .values()
+            .map(|stored| ExportedDocument {
+                document: stored.document.clone(),
+                sync_status: stored.sync_status,
+                local_only: stored.local_only,
+                queued_operations: state
+                    .queue
+                    .iter()
+                    .filter(|entry| entry.document_id == stored.document.id)
+                    .map(|entry| entry.operation.clone())
+                    .collect(),
+            })
+            .collect();
+        documents.sort_by_key(|exported| exported.document.created_at);
+        Ok(documents)
+    }
+
+    async fn import_documents(
+        &self,
+        documents: &[ExportedDocument],
+        mode: ImportMode,
+    ) -> SyncResult<()> {
+        let mut state = self.state()?;
+
+        if mode == ImportMode::Replace {
+            state.queue.clear();
+            state.conflicts.clear();
+            state.attachments.clear();
+            state.documents.clear();
+        }
+
+        for imported in documents {
+            let doc = &imported.document;
+            state.upsert(doc, imported.sync_status);
+            state.document(&doc.id)?.local_only = imported.local_only;
+            state.queue.retain(|entry| entry.document_id != doc.id);
+            for op in &imported.queued_operations {
+                state.enqueue(doc.id, op.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    // No tag index: documents are scanned at the configured path
+
+    async fn configure_tags(&self, json_path: &str) -> SyncResult<()> {
+        self.state()?.tag_path = Some(json_path.to_string());
+        Ok(())
+    }
+
+    async fn get_tag_path(&self) -> SyncResult<Option<String>> {
+        let state = self.state()?;
+        Ok(Some(
+            state
+                .tag_path
+                .as_deref()
+                .unwrap_or(DEFAULT_TAG_PATH)
+                .to_string(),
+        ))
+    }
+
+    async fn documents_with_tag(&self, user_id: &Uuid, tag: &str) -> SyncResult<Vec<Document>> {
+        let path = self.get_tag_path().await?.unwrap_or_default();
+        Ok(self
+            .get_all_documents()
+            .await?
+            .into_iter()
+            .filter(|doc| {
+                doc.user_id == *user_id
+                    && extract_tags(&doc.content, &path).iter().any(|t| t == tag)
+            })
+            .collect())
+    }
+}
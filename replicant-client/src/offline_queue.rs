@@ -52,13 +52,21 @@ impl OfflineQueue {
                         .execute(&self.pool)
                         .await?;
                 }
-                Err(_) => {
+                Err(e) if e.is_retryable() => {
                     // Increment retry count
                     sqlx::query(Queries::INCREMENT_RETRY_COUNT)
                         .bind(id)
                         .execute(&self.pool)
                         .await?;
                 }
+                Err(e) => {
+                    // Sending it again would fail the same way
+                    tracing::warn!("Dropping queued message {} that can't be sent: {}", id, e);
+                    sqlx::query(Queries::DELETE_FROM_QUEUE)
+                        .bind(id)
+                        .execute(&self.pool)
+                        .await?;
+                }
             }
         }
 
@@ -68,8 +76,10 @@ impl OfflineQueue {
 
 pub fn extract_document_id(message: &ClientMessage) -> Option<Uuid> {
     match message {
-        ClientMessage::CreateDocument { document } => Some(document.id),
-        ClientMessage::UpdateDocument { patch } => Some(patch.document_id),
+        ClientMessage::CreateDocument { document, .. } => Some(document.id),
+        ClientMessage::UpdateDocument { patch, .. } => Some(patch.document_id),
+        ClientMessage::MergePatchDocument { document_id, .. }
+        | ClientMessage::ResolveConflict { document_id, .. } => Some(*document_id),
         ClientMessage::DeleteDocument { document_id, .. } => Some(*document_id),
         _ => None,
     }
@@ -78,7 +88,9 @@ pub fn extract_document_id(message: &ClientMessage) -> Option<Uuid> {
 pub fn operation_type(message: &ClientMessage) -> &'static str {
     match message {
         ClientMessage::CreateDocument { .. } => "create",
-        ClientMessage::UpdateDocument { .. } => "update",
+        ClientMessage::UpdateDocument { .. }
+        | ClientMessage::MergePatchDocument { .. }
+        | ClientMessage::ResolveConflict { .. } => "update",
         ClientMessage::DeleteDocument { .. } => "delete",
         _ => "other",
     }
@@ -1,22 +1,24 @@
 use chrono::{DateTime, Utc};
 use replicant_core::{
     models::{Document, SyncStatus},
-    SyncResult,
+    SyncError, SyncResult,
 };
 use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
 use uuid::Uuid;
 
 /// Type alias for document parameters tuple
 pub type DocumentParams = (
-    String,         // id
-    String,         // user_id
-    String,         // content
-    i64,            // sync_revision
-    String,         // created_at
-    String,         // updated_at
-    Option<String>, // deleted_at
-    String,         // sync_status
-    String,         // title
+    String,          // id
+    String,          // user_id
+    String,          // content
+    i64,             // sync_revision
+    String,          // created_at
+    String,          // updated_at
+    Option<String>,  // deleted_at
+    String,          // sync_status
+    String,          // title
+    Option<Vec<u8>>, // content_zstd
+    Option<String>,  // expires_at
 );
 
 /// SQL queries for client database operations
@@ -77,24 +79,35 @@ impl Queries {
 
     // Document queries
     pub const GET_DOCUMENT: &'static str = r#"
-        SELECT id, user_id, content, sync_revision,
-               created_at, updated_at, deleted_at, title
+        SELECT id, user_id, content, content_zstd, sync_revision,
+               created_at, updated_at, deleted_at, expires_at, title, sync_status
         FROM documents
         WHERE id = ?1
     "#;
 
+    // A synced save means the server has the document; bringing back a
+    // locally deleted one uploads it afresh
     pub const UPSERT_DOCUMENT: &'static str = r#"
         INSERT INTO documents (
             id, user_id, content, sync_revision,
-            created_at, updated_at, deleted_at, sync_status, title
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            created_at, updated_at, deleted_at, sync_status, title, content_zstd,
+            expires_at, uploaded
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?8 = 'synced')
         ON CONFLICT(id) DO UPDATE SET
+            uploaded = CASE
+                WHEN excluded.sync_status = 'synced' THEN 1
+                WHEN documents.deleted_at IS NOT NULL AND excluded.deleted_at IS NULL THEN 0
+                ELSE documents.uploaded
+            END,
             content = excluded.content,
+            content_zstd = excluded.content_zstd,
             sync_revision = excluded.sync_revision,
             updated_at = excluded.updated_at,
             deleted_at = excluded.deleted_at,
+            expires_at = excluded.expires_at,
             sync_status = excluded.sync_status,
-            title = excluded.title
+            title = excluded.title,
+            idempotency_key = NULL
     "#;
 
     pub const LIST_USER_DOCUMENTS: &'static str = r#"
@@ -104,34 +117,54 @@ impl Queries {
         ORDER BY updated_at DESC
     "#;
 
+    // In the order of each document's latest local edit
     pub const GET_PENDING_DOCUMENTS: &'static str = r#"
-        SELECT id, deleted_at FROM documents
-        WHERE sync_status = ?
-        ORDER BY updated_at ASC
+        SELECT id, deleted_at, uploaded, create_sent FROM documents
+        WHERE sync_status = ? AND local_only = 0
+        ORDER BY local_seq ASC, id ASC
     "#;
 
     pub const MARK_DOCUMENT_SYNCED: &'static str = r#"
         UPDATE documents
-        SET sync_status = ?
+        SET sync_status = ?, idempotency_key = NULL, uploaded = 1
         WHERE id = ?
     "#;
 
-    pub const UPDATE_SYNC_STATUS: &'static str =
-        "UPDATE documents SET sync_status = ?2 WHERE id = ?1";
+    pub const UPDATE_SYNC_STATUS: &'static str = r#"
+        UPDATE documents
+        SET sync_status = ?2, idempotency_key = NULL, uploaded = uploaded OR ?2 = 'synced'
+        WHERE id = ?1
+    "#;
 
     pub const COUNT_BY_SYNC_STATUS: &'static str =
         "SELECT COUNT(*) as count FROM documents WHERE sync_status = ?1";
 
     // Sync queue queries
+    // Entries take the document's current local_seq
     pub const INSERT_SYNC_QUEUE: &'static str = r#"
-        INSERT INTO sync_queue (document_id, operation_type, patch)
-        VALUES (?1, ?2, ?3)
+        INSERT INTO sync_queue (document_id, operation_type, patch, local_seq)
+        VALUES (?1, ?2, ?3, (SELECT local_seq FROM documents WHERE id = ?1))
+    "#;
+
+    pub const INSERT_SYNC_QUEUE_WITH_HASH: &'static str = r#"
+        INSERT INTO sync_queue (document_id, operation_type, patch, old_content_hash, local_seq)
+        VALUES (?1, ?2, ?3, ?4, (SELECT local_seq FROM documents WHERE id = ?1))
     "#;
 
+    // Local edit counter
+    pub const NEXT_LOCAL_SEQ: &'static str = r#"
+        UPDATE documents
+        SET local_seq = (SELECT COALESCE(MAX(local_seq), 0) + 1 FROM documents)
+        WHERE id = ?1
+        RETURNING local_seq
+    "#;
+
+    pub const GET_LOCAL_SEQ: &'static str = "SELECT local_seq FROM documents WHERE id = ?1";
+
     pub const GET_SYNC_QUEUE: &'static str = r#"
         SELECT id, document_id, operation_type, patch, retry_count
         FROM sync_queue
-        ORDER BY created_at ASC
+        ORDER BY local_seq ASC, id ASC
         LIMIT 100
     "#;
 
@@ -148,23 +181,28 @@ impl Queries {
 
     pub const INSERT_SEARCH_PATH: &'static str = "INSERT INTO search_config (json_path) VALUES (?)";
 
+    pub const GET_SEARCH_PATHS: &'static str =
+        "SELECT json_path FROM search_config ORDER BY json_path";
+
     pub const DELETE_FTS_ENTRY: &'static str = "DELETE FROM documents_fts WHERE document_id = ?";
 
+    /// Takes the decompressed content as ?2 for compressed rows, NULL otherwise
     pub const UPDATE_FTS_ENTRY: &'static str = r#"
         INSERT INTO documents_fts (document_id, title, body)
         SELECT
             d.id,
             COALESCE(d.title, ''),
             COALESCE(
-                (SELECT GROUP_CONCAT(json_extract(d.content, sc.json_path), ' ')
+                (SELECT GROUP_CONCAT(json_extract(COALESCE(?2, d.content), sc.json_path), ' ')
                  FROM search_config sc
-                 WHERE json_extract(d.content, sc.json_path) IS NOT NULL),
+                 WHERE json_extract(COALESCE(?2, d.content), sc.json_path) IS NOT NULL),
                 ''
             )
         FROM documents d
-        WHERE d.id = ? AND d.deleted_at IS NULL
+        WHERE d.id = ?1 AND d.deleted_at IS NULL
     "#;
 
+    /// Compressed rows are skipped, see `GET_COMPRESSED_DOCUMENT_IDS`
     pub const REBUILD_FTS_INDEX: &'static str = r#"
         INSERT INTO documents_fts (document_id, title, body)
         SELECT
@@ -177,17 +215,52 @@ impl Queries {
                 ''
             )
         FROM documents d
-        WHERE d.deleted_at IS NULL
+        WHERE d.deleted_at IS NULL AND d.content_zstd IS NULL
     "#;
 
+    pub const GET_COMPRESSED_DOCUMENT_IDS: &'static str =
+        "SELECT id FROM documents WHERE deleted_at IS NULL AND content_zstd IS NOT NULL";
+
     pub const CLEAR_FTS_INDEX: &'static str = "DELETE FROM documents_fts";
 
+    // Tag queries
+    pub const GET_TAG_PATH: &'static str = "SELECT json_path FROM tag_config LIMIT 1";
+
+    pub const CLEAR_TAG_CONFIG: &'static str = "DELETE FROM tag_config";
+
+    pub const INSERT_TAG_PATH: &'static str = "INSERT INTO tag_config (json_path) VALUES (?)";
+
+    pub const CLEAR_DOCUMENT_TAGS: &'static str = "DELETE FROM document_tags";
+
+    pub const DELETE_DOCUMENT_TAGS: &'static str =
+        "DELETE FROM document_tags WHERE document_id = ?";
+
+    pub const INSERT_DOCUMENT_TAG: &'static str =
+        "INSERT OR IGNORE INTO document_tags (document_id, tag) VALUES (?, ?)";
+
+    pub const GET_LIVE_DOCUMENT_IDS: &'static str =
+        "SELECT id FROM documents WHERE deleted_at IS NULL";
+
+    pub const GET_DOCUMENTS_WITH_TAG: &'static str = r#"
+        SELECT d.id, d.user_id, d.content, d.content_zstd, d.sync_revision,
+               d.created_at, d.updated_at, d.deleted_at, d.expires_at, d.title, d.sync_status
+        FROM documents d
+        JOIN document_tags t ON d.id = t.document_id
+        WHERE d.user_id = ?
+          AND t.tag = ?
+          AND d.deleted_at IS NULL
+          AND (d.expires_at IS NULL OR julianday(d.expires_at) > julianday('now'))
+        ORDER BY d.updated_at DESC
+    "#;
+
     pub const SEARCH_DOCUMENTS: &'static str = r#"
-        SELECT d.id, d.user_id, d.content, d.sync_revision,
-               d.created_at, d.updated_at, d.deleted_at, d.title
+        SELECT d.id, d.user_id, d.content, d.content_zstd, d.sync_revision,
+               d.created_at, d.updated_at, d.deleted_at, d.expires_at, d.title, d.sync_status
         FROM documents d
         JOIN documents_fts fts ON d.id = fts.document_id
-        WHERE d.deleted_at IS NULL
+        WHERE d.user_id = ?
+          AND d.deleted_at IS NULL
+          AND (d.expires_at IS NULL OR julianday(d.expires_at) > julianday('now'))
           AND documents_fts MATCH ?
         ORDER BY rank
         LIMIT ?
@@ -208,14 +281,24 @@ impl DbHelpers {
     pub fn parse_document(row: &SqliteRow) -> SyncResult<Document> {
         let id: String = row.get("id");
         let user_id: String = row.get("user_id");
-        let content: String = row.get("content");
+        let content: String = match row.try_get::<Option<Vec<u8>>, _>("content_zstd") {
+            Ok(Some(compressed)) => Self::decompress_content(&compressed)?,
+            _ => row.get("content"),
+        };
         let sync_revision: i64 = row
             .try_get::<i64, _>("sync_revision")
             .or_else(|_| row.try_get::<i32, _>("sync_revision").map(|v| v as i64))?;
         let created_at: String = row.get("created_at");
         let updated_at: String = row.get("updated_at");
         let deleted_at: Option<String> = row.get("deleted_at");
+        let expires_at: Option<String> = row.try_get("expires_at").ok().flatten();
         let title: Option<String> = row.try_get("title").ok();
+        let sync_status = row
+            .try_get::<Option<String>, _>("sync_status")
+            .ok()
+            .flatten()
+            .and_then(|status| status.parse().ok())
+            .unwrap_or_default();
 
         Ok(Document {
             id: Uuid::parse_str(&id)?,
@@ -229,6 +312,10 @@ impl DbHelpers {
             deleted_at: deleted_at
                 .and_then(|dt| DateTime::parse_from_rfc3339(&dt).ok())
                 .map(|dt| dt.with_timezone(&Utc)),
+            expires_at: expires_at
+                .and_then(|dt| DateTime::parse_from_rfc3339(&dt).ok())
+                .map(|dt| dt.with_timezone(&Utc)),
+            sync_status,
         })
     }
 
@@ -257,9 +344,22 @@ impl DbHelpers {
             doc.deleted_at.map(|dt| dt.to_rfc3339()),
             status,
             title,
+            None,
+            doc.expires_at.map(|dt| dt.to_rfc3339()),
         ))
     }
 
+    /// zstd-compress serialized content for the `content_zstd` column
+    pub fn compress_content(content: &str) -> SyncResult<Vec<u8>> {
+        Ok(zstd::encode_all(content.as_bytes(), 0)?)
+    }
+
+    pub fn decompress_content(compressed: &[u8]) -> SyncResult<String> {
+        let bytes = zstd::decode_all(compressed)?;
+        String::from_utf8(bytes)
+            .map_err(|e| SyncError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+    }
+
     /// Get count of documents by sync status
     pub async fn count_by_status(pool: &SqlitePool, status: SyncStatus) -> SyncResult<i64> {
         let row = sqlx::query(Queries::COUNT_BY_SYNC_STATUS)
@@ -0,0 +1,114 @@
+//! Tasks and timers, from tokio natively and from the browser's event loop
+//! on wasm32, where tokio has no runtime to drive them.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use native::*;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasm::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::time::Duration;
+
+    pub(crate) use std::time::Instant;
+    pub(crate) use tokio::spawn;
+    pub(crate) use tokio::time::{sleep, timeout};
+
+    pub(crate) type JoinHandle = tokio::task::JoinHandle<()>;
+
+    pub(crate) struct Ticker(tokio::time::Interval);
+
+    impl Ticker {
+        pub(crate) fn new(period: Duration) -> Self {
+            let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            Self(ticks)
+        }
+
+        pub(crate) async fn tick(&mut self) {
+            self.0.tick().await;
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use futures_util::future::{self, Either};
+    use std::future::Future;
+    use std::pin::{pin, Pin};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+
+    pub(crate) use web_time::Instant;
+
+    /// Resolves once the task has finished, or with an error if it was
+    /// dropped without finishing
+    pub(crate) struct JoinHandle(oneshot::Receiver<()>);
+
+    #[derive(Debug)]
+    pub(crate) struct JoinError;
+
+    impl std::fmt::Display for JoinError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("task ended without finishing")
+        }
+    }
+
+    impl Future for JoinHandle {
+        type Output = Result<(), JoinError>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            Pin::new(&mut self.0).poll(cx).map_err(|_| JoinError)
+        }
+    }
+
+    // Browser futures hold JavaScript objects, so tasks needn't be Send
+    pub(crate) fn spawn<F>(future: F) -> JoinHandle
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let (done_tx, done_rx) = oneshot::channel();
+        wasm_bindgen_futures::spawn_local(async move {
+            future.await;
+            let _ = done_tx.send(());
+        });
+        JoinHandle(done_rx)
+    }
+
+    pub(crate) async fn sleep(duration: Duration) {
+        gloo_timers::future::sleep(duration).await;
+    }
+
+    #[derive(Debug)]
+    pub(crate) struct Elapsed;
+
+    impl std::fmt::Display for Elapsed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("deadline has elapsed")
+        }
+    }
+
+    pub(crate) async fn timeout<F: Future>(
+        duration: Duration,
+        future: F,
+    ) -> Result<F::Output, Elapsed> {
+        match future::select(pin!(future), pin!(sleep(duration))).await {
+            Either::Left((output, _)) => Ok(output),
+            Either::Right(_) => Err(Elapsed),
+        }
+    }
+
+    pub(crate) struct Ticker(Duration);
+
+    impl Ticker {
+        pub(crate) fn new(period: Duration) -> Self {
+            Self(period)
+        }
+
+        // Timers aren't shared, so a late tick delays the rest anyway
+        pub(crate) async fn tick(&mut self) {
+            sleep(self.0).await;
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Per-session activity counters, see [`crate::Client::stats`].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// What a [`crate::Client`] has done since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    /// Documents created through this client
+    pub local_creates: u64,
+    /// Documents updated through this client
+    pub local_updates: u64,
+    /// Documents deleted through this client
+    pub local_deletes: u64,
+    /// Creations by other clients the server relayed
+    pub remote_creates: u64,
+    /// Updates by other clients the server relayed
+    pub remote_updates: u64,
+    /// Deletions by other clients the server relayed
+    pub remote_deletes: u64,
+    /// Message bytes written to the server
+    pub bytes_sent: u64,
+    /// Message bytes read from the server
+    pub bytes_received: u64,
+    /// Receipt acks from the server, with `ClientConfig::receipt_acks` on
+    pub acks_received: u64,
+    /// Time from sending a message to the server acknowledging it, for the
+    /// most recent ack
+    pub last_ack_latency: Option<Duration>,
+}
+
+/// Live counters behind [`ClientStats`], shared with the connection tasks
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    local_creates: AtomicU64,
+    local_updates: AtomicU64,
+    local_deletes: AtomicU64,
+    remote_creates: AtomicU64,
+    remote_updates: AtomicU64,
+    remote_deletes: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    acks_received: AtomicU64,
+    last_ack_latency_micros: AtomicU64,
+}
+
+impl ClientStats {
+    /// Documents written, locally or by the server, in total
+    pub fn document_changes(&self) -> u64 {
+        self.local_creates
+            + self.local_updates
+            + self.local_deletes
+            + self.remote_creates
+            + self.remote_updates
+            + self.remote_deletes
+    }
+}
+
+impl StatsCounters {
+    pub(crate) fn local_create(&self) {
+        self.local_creates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn local_update(&self) {
+        self.local_updates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn local_delete(&self) {
+        self.local_deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remote_create(&self) {
+        self.remote_creates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remote_update(&self) {
+        self.remote_updates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remote_delete(&self) {
+        self.remote_deletes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn received(&self, bytes: usize) {
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn ack(&self, latency: Duration) {
+        self.last_ack_latency_micros
+            .store(latency.as_micros() as u64, Ordering::Relaxed);
+        self.acks_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A copy of the current counts
+    pub fn snapshot(&self) -> ClientStats {
+        let acks_received = self.acks_received.load(Ordering::Relaxed);
+        ClientStats {
+            local_creates: self.local_creates.load(Ordering::Relaxed),
+            local_updates: self.local_updates.load(Ordering::Relaxed),
+            local_deletes: self.local_deletes.load(Ordering::Relaxed),
+            remote_creates: self.remote_creates.load(Ordering::Relaxed),
+            remote_updates: self.remote_updates.load(Ordering::Relaxed),
+            remote_deletes: self.remote_deletes.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            acks_received,
+            last_ack_latency: (acks_received > 0).then(|| {
+                Duration::from_micros(self.last_ack_latency_micros.load(Ordering::Relaxed))
+            }),
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! Storage backend used by [`crate::Client`].
+//!
+//! [`ClientDatabase`] is the SQLite implementation the client uses by default.
+//! Other backends, e.g. an in-memory store for tests or platform-native
+//! storage on embedded targets, implement [`DocumentStore`] and are passed to
+//! [`crate::Client::with_store`].
+
+use crate::backup::{ExportedDocument, ImportMode};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::database::ClientDatabase;
+use crate::database::{
+    Attachment, CompactReport, ConflictRecord, DocumentOrder, PendingDocumentInfo,
+};
+use async_trait::async_trait;
+use replicant_core::{
+    models::{extract_tags, Document, SyncStatus, DEFAULT_TAG_PATH},
+    protocol::ChangeEventType,
+    SyncError, SyncResult,
+};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    // Identity
+
+    /// Create the user config for `user_identifier` if there is none yet
+    async fn ensure_user_config_with_identifier(
+        &self,
+        server_url: &str,
+        user_identifier: &str,
+    ) -> SyncResult<()>;
+    async fn get_user_and_client_id(&self) -> SyncResult<(Uuid, Uuid)>;
+    /// Schema version of the underlying storage, 0 if unversioned
+    async fn schema_version(&self) -> SyncResult<i64>;
+    async fn set_last_sync_at(&self, at: chrono::DateTime<chrono::Utc>) -> SyncResult<()>;
+    async fn get_last_sync_at(&self) -> SyncResult<Option<chrono::DateTime<chrono::Utc>>>;
+
+    // Documents
+
+    async fn get_document(&self, id: &Uuid) -> SyncResult<Document>;
+    async fn get_all_documents(&self) -> SyncResult<Vec<Document>>;
+    /// Up to `limit` non-deleted documents starting `offset` into `order`
+    async fn get_documents_page(
+        &self,
+        offset: i64,
+        limit: i64,
+        order: DocumentOrder,
+    ) -> SyncResult<Vec<Document>>;
+    /// Documents updated after `since`, oldest first, with deleted ones
+    /// only if `include_deleted` is set
+    async fn get_documents_changed_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        include_deleted: bool,
+    ) -> SyncResult<Vec<Document>>;
+    /// Number of non-deleted documents
+    async fn count_documents(&self) -> SyncResult<i64>;
+    /// Save a document as pending
+    async fn save_document(&self, doc: &Document) -> SyncResult<()>;
+    /// Save a document that is never sent to the server
+    async fn save_local_document(&self, doc: &Document) -> SyncResult<()>;
+    /// Save a document with `sync_status`, or pending when `None`
+    async fn save_document_with_status(
+        &self,
+        doc: &Document,
+        sync_status: Option<SyncStatus>,
+    ) -> SyncResult<()>;
+    /// Mark a document deleted and pending, so the delete gets synced
+    async fn delete_document(&self, document_id: &Uuid) -> SyncResult<()>;
+    /// Save `documents`, each with its own `sync_status`, and remove the
+    /// `removed` documents entirely, all in one step
+    async fn save_documents_atomically(
+        &self,
+        documents: &[Document],
+        removed: &[Uuid],
+    ) -> SyncResult<()>;
+    async fn is_local_only(&self, document_id: &Uuid) -> SyncResult<bool>;
+    async fn set_local_only(&self, document_id: &Uuid, local_only: bool) -> SyncResult<()>;
+    async fn set_server_timestamps(
+        &self,
+        document_id: &Uuid,
+        created_at: Option<chrono::DateTime<chrono::Utc>>,
+        updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> SyncResult<()>;
+    async fn update_sync_revision(&self, document_id: &Uuid, sync_revision: i64) -> SyncResult<()>;
+
+    // Sync status
+
+    async fn get_pending_documents(&self) -> SyncResult<Vec<PendingDocumentInfo>>;
+    async fn get_sync_status(&self, document_id: &Uuid) -> SyncResult<SyncStatus>;
+    async fn set_sync_status(&self, document_id: &Uuid, status: SyncStatus) -> SyncResult<()>;
+    async fn mark_synced(&self, document_id: &Uuid) -> SyncResult<()>;
+    /// Idempotency key for the document's pending operation, reused until
+    /// the document or its sync status changes
+    async fn idempotency_key(&self, document_id: &Uuid) -> SyncResult<Uuid>;
+
+    // Conflicts
+
+    async fn save_conflict(
+        &self,
+        document_id: &Uuid,
+        local_content: &serde_json::Value,
+        server_content: &serde_json::Value,
+    ) -> SyncResult<()>;
+    async fn get_conflict(&self, document_id: &Uuid) -> SyncResult<Option<ConflictRecord>>;
+    async fn delete_conflict(&self, document_id: &Uuid) -> SyncResult<()>;
+    async fn count_conflicts(&self) -> SyncResult<i64>;
+
+    // Attachments. Removing a document removes its attachments too.
+
+    /// Store an attachment, replacing any with the same ID
+    async fn save_attachment(&self, attachment: &Attachment) -> SyncResult<()>;
+    async fn get_attachment(&self, id: &Uuid) -> SyncResult<Option<Attachment>>;
+    async fn set_attachment_uploaded(&self, id: &Uuid, uploaded: u64) -> SyncResult<()>;
+    /// Attachments the server doesn't hold in full yet, oldest first
+    async fn get_pending_attachments(&self) -> SyncResult<Vec<Uuid>>;
+    async fn delete_attachment(&self, id: &Uuid) -> SyncResult<()>;
+
+    // Sync queue
+
+    /// The latest queued update for a document, with the content hash it
+    /// was based on
+    async fn get_queued_patch(
+        &self,
+        document_id: &Uuid,
+    ) -> SyncResult<Option<(json_patch::Patch, Option<String>)>>;
+    async fn get_queued_patches(&self, document_id: &Uuid) -> SyncResult<Vec<json_patch::Patch>>;
+    /// Save a document as pending and queue `patch` in one step, returning
+    /// the edit's `local_seq`
+    async fn save_document_and_queue_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        operation_type: ChangeEventType,
+        old_content_hash: Option<String>,
+    ) -> SyncResult<i64>;
+    /// Number of the document's latest local edit, see
+    /// [`ClientDatabase::local_seq`]
+    async fn local_seq(&self, document_id: &Uuid) -> SyncResult<i64>;
+    /// Record that a create for the document went out, see
+    /// [`PendingDocumentInfo::create_sent`]
+    async fn mark_create_sent(&self, document_id: &Uuid) -> SyncResult<()>;
+    /// Save a document as pending with `patch` as its only queued change
+    async fn replace_queued_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        old_content_hash: String,
+    ) -> SyncResult<()>;
+    async fn remove_from_sync_queue(&self, document_id: &Uuid) -> SyncResult<()>;
+    /// Remove queue entries for edits up to `local_seq`
+    async fn remove_from_sync_queue_through(
+        &self,
+        document_id: &Uuid,
+        local_seq: i64,
+    ) -> SyncResult<()>;
+    /// Remove queue entries for synced or local-only documents
+    async fn remove_stale_queue_entries(&self) -> SyncResult<u64>;
+    /// Pending documents the server already has, with nothing queued
+    async fn get_unqueued_pending_documents(&self) -> SyncResult<Vec<Document>>;
+    async fn queue_depth(&self) -> SyncResult<i64>;
+    /// Merge each document's queued updates into one, returning how many
+    /// entries were removed
+    async fn coalesce_sync_queue(&self) -> SyncResult<u64>;
+
+    // Backup
+
+    async fn export_documents(&self) -> SyncResult<Vec<ExportedDocument>>;
+    async fn import_documents(
+        &self,
+        documents: &[ExportedDocument],
+        mode: ImportMode,
+    ) -> SyncResult<()>;
+
+    // Search. Backends without a full-text index can keep the defaults,
+    // which match the query as a substring of the content.
+
+    async fn configure_search(&self, _json_paths: &[String]) -> SyncResult<()> {
+        Ok(())
+    }
+
+    async fn get_search_paths(&self) -> SyncResult<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn search_documents(
+        &self,
+        user_id: &Uuid,
+        query: &str,
+        limit: i64,
+    ) -> SyncResult<Vec<Document>> {
+        let query = query.to_lowercase();
+        Ok(self
+            .get_all_documents()
+            .await?
+            .into_iter()
+            .filter(|doc| {
+                doc.user_id == *user_id && doc.content.to_string().to_lowercase().contains(&query)
+            })
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+
+    // Tags. Backends without a tag index can keep the defaults, which scan
+    // every document for tags at `DEFAULT_TAG_PATH` and refuse any other.
+
+    async fn configure_tags(&self, json_path: &str) -> SyncResult<()> {
+        if json_path == DEFAULT_TAG_PATH {
+            return Ok(());
+        }
+        Err(SyncError::InvalidOperation(format!(
+            "This store only reads tags from {}, not {}",
+            DEFAULT_TAG_PATH, json_path
+        )))
+    }
+
+    async fn get_tag_path(&self) -> SyncResult<Option<String>> {
+        Ok(Some(DEFAULT_TAG_PATH.to_string()))
+    }
+
+    async fn documents_with_tag(&self, user_id: &Uuid, tag: &str) -> SyncResult<Vec<Document>> {
+        Ok(self
+            .get_all_documents()
+            .await?
+            .into_iter()
+            .filter(|doc| {
+                doc.user_id == *user_id
+                    && extract_tags(&doc.content, DEFAULT_TAG_PATH)
+                        .iter()
+                        .any(|t| t == tag)
+            })
+            .collect())
+    }
+
+    // Compaction. Backends with no space to give back can keep the
+    // default, which only prunes the sync queue.
+
+    async fn compact(&self, _history_retention: Duration) -> SyncResult<CompactReport> {
+        Ok(CompactReport {
+            queue_entries_removed: self.remove_stale_queue_entries().await?,
+            ..Default::default()
+        })
+    }
+
+    /// Release the underlying storage on shutdown
+    async fn close(&self) {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[async_trait]
+impl DocumentStore for ClientDatabase {
+    async fn ensure_user_config_with_identifier(
+        &self,
+        server_url: &str,
+        user_identifier: &str,
+    ) -> SyncResult<()> {
+        ClientDatabase::ensure_user_config_with_identifier(self, server_url, user_identifier).await
+    }
+
+    async fn get_user_and_client_id(&self) -> SyncResult<(Uuid, Uuid)> {
+        ClientDatabase::get_user_and_client_id(self).await
+    }
+
+    async fn schema_version(&self) -> SyncResult<i64> {
+        ClientDatabase::schema_version(self).await
+    }
+
+    async fn set_last_sync_at(&self, at: chrono::DateTime<chrono::Utc>) -> SyncResult<()> {
+        ClientDatabase::set_last_sync_at(self, at).await
+    }
+
+    async fn get_last_sync_at(&self) -> SyncResult<Option<chrono::DateTime<chrono::Utc>>> {
+        ClientDatabase::get_last_sync_at(self).await
+    }
+
+    async fn get_document(&self, id: &Uuid) -> SyncResult<Document> {
+        ClientDatabase::get_document(self, id).await
+    }
+
+    async fn get_all_documents(&self) -> SyncResult<Vec<Document>> {
+        ClientDatabase::get_all_documents(self).await
+    }
+
+    async fn get_documents_page(
+        &self,
+        offset: i64,
+        limit: i64,
+        order: DocumentOrder,
+    ) -> SyncResult<Vec<Document>> {
+        ClientDatabase::get_documents_page(self, offset, limit, order).await
+    }
+
+    async fn get_documents_changed_since(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        include_deleted: bool,
+    ) -> SyncResult<Vec<Document>> {
+        ClientDatabase::get_documents_changed_since(self, since, include_deleted).await
+    }
+
+    async fn count_documents(&self) -> SyncResult<i64> {
+        ClientDatabase::count_documents(self).await
+    }
+
+    async fn save_document(&self, doc: &Document) -> SyncResult<()> {
+        ClientDatabase::save_document(self, doc).await
+    }
+
+    async fn save_local_document(&self, doc: &Document) -> SyncResult<()> {
+        ClientDatabase::save_local_document(self, doc).await
+    }
+
+    async fn save_document_with_status(
+        &self,
+        doc: &Document,
+        sync_status: Option<SyncStatus>,
+    ) -> SyncResult<()> {
+        ClientDatabase::save_document_with_status(self, doc, sync_status).await
+    }
+
+    async fn delete_document(&self, document_id: &Uuid) -> SyncResult<()> {
+        ClientDatabase::delete_document(self, document_id).await
+    }
+
+    async fn save_documents_atomically(
+        &self,
+        documents: &[Document],
+        removed: &[Uuid],
+    ) -> SyncResult<()> {
+        ClientDatabase::save_documents_atomically(self, documents, removed).await
+    }
+
+    async fn is_local_only(&self, document_id: &Uuid) -> SyncResult<bool> {
+        ClientDatabase::is_local_only(self, document_id).await
+    }
+
+    async fn set_local_only(&self, document_id: &Uuid, local_only: bool) -> SyncResult<()> {
+        ClientDatabase::set_local_only(self, document_id, local_only).await
+    }
+
+    async fn set_server_timestamps(
+        &self,
+        document_id: &Uuid,
+        created_at: Option<chrono::DateTime<chrono::Utc>>,
+        updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> SyncResult<()> {
+        ClientDatabase::set_server_timestamps(self, document_id, created_at, updated_at).await
+    }
+
+    async fn update_sync_revision(&self, document_id: &Uuid, sync_revision: i64) -> SyncResult<()> {
+        ClientDatabase::update_sync_revision(self, document_id, sync_revision).await
+    }
+
+    async fn get_pending_documents(&self) -> SyncResult<Vec<PendingDocumentInfo>> {
+        ClientDatabase::get_pending_documents(self).await
+    }
+
+    async fn get_sync_status(&self, document_id: &Uuid) -> SyncResult<SyncStatus> {
+        ClientDatabase::get_sync_status(self, document_id).await
+    }
+
+    async fn set_sync_status(&self, document_id: &Uuid, status: SyncStatus) -> SyncResult<()> {
+        ClientDatabase::set_sync_status(self, document_id, status).await
+    }
+
+    async fn mark_synced(&self, document_id: &Uuid) -> SyncResult<()> {
+        ClientDatabase::mark_synced(self, document_id).await
+    }
+
+    async fn idempotency_key(&self, document_id: &Uuid) -> SyncResult<Uuid> {
+        ClientDatabase::idempotency_key(self, document_id).await
+    }
+
+    async fn save_conflict(
+        &self,
+        document_id: &Uuid,
+        local_content: &serde_json::Value,
+        server_content: &serde_json::Value,
+    ) -> SyncResult<()> {
+        ClientDatabase::save_conflict(self, document_id, local_content, server_content).await
+    }
+
+    async fn get_conflict(&self, document_id: &Uuid) -> SyncResult<Option<ConflictRecord>> {
+        ClientDatabase::get_conflict(self, document_id).await
+    }
+
+    async fn delete_conflict(&self, document_id: &Uuid) -> SyncResult<()> {
+        ClientDatabase::delete_conflict(self, document_id).await
+    }
+
+    async fn count_conflicts(&self) -> SyncResult<i64> {
+        ClientDatabase::count_conflicts(self).await
+    }
+
+    async fn save_attachment(&self, attachment: &Attachment) -> SyncResult<()> {
+        ClientDatabase::save_attachment(self, attachment).await
+    }
+
+    async fn get_attachment(&self, id: &Uuid) -> SyncResult<Option<Attachment>> {
+        ClientDatabase::get_attachment(self, id).await
+    }
+
+    async fn set_attachment_uploaded(&self, id: &Uuid, uploaded: u64) -> SyncResult<()> {
+        ClientDatabase::set_attachment_uploaded(self, id, uploaded).await
+    }
+
+    async fn get_pending_attachments(&self) -> SyncResult<Vec<Uuid>> {
+        ClientDatabase::get_pending_attachments(self).await
+    }
+
+    async fn delete_attachment(&self, id: &Uuid) -> SyncResult<()> {
+        ClientDatabase::delete_attachment(self, id).await
+    }
+
+    async fn get_queued_patch(
+        &self,
+        document_id: &Uuid,
+    ) -> SyncResult<Option<(json_patch::Patch, Option<String>)>> {
+        ClientDatabase::get_queued_patch(self, document_id).await
+    }
+
+    async fn get_queued_patches(&self, document_id: &Uuid) -> SyncResult<Vec<json_patch::Patch>> {
+        ClientDatabase::get_queued_patches(self, document_id).await
+    }
+
+    async fn save_document_and_queue_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        operation_type: ChangeEventType,
+        old_content_hash: Option<String>,
+    ) -> SyncResult<i64> {
+        ClientDatabase::save_document_and_queue_patch(
+            self,
+            doc,
+            patch,
+            operation_type,
+            old_content_hash,
+        )
+        .await
+    }
+
+    async fn local_seq(&self, document_id: &Uuid) -> SyncResult<i64> {
+        ClientDatabase::local_seq(self, document_id).await
+    }
+
+    async fn mark_create_sent(&self, document_id: &Uuid) -> SyncResult<()> {
+        ClientDatabase::mark_create_sent(self, document_id).await
+    }
+
+    async fn replace_queued_patch(
+        &self,
+        doc: &Document,
+        patch: &json_patch::Patch,
+        old_content_hash: String,
+    ) -> SyncResult<()> {
+        ClientDatabase::replace_queued_patch(self, doc, patch, old_content_hash).await
+    }
+
+    async fn remove_from_sync_queue(&self, document_id: &Uuid) -> SyncResult<()> {
+        ClientDatabase::remove_from_sync_queue(self, document_id).await
+    }
+
+    async fn remove_from_sync_queue_through(
+        &self,
+        document_id: &Uuid,
+        local_seq: i64,
+    ) -> SyncResult<()> {
+        ClientDatabase::remove_from_sync_queue_through(self, document_id, local_seq).await
+    }
+
+    async fn remove_stale_queue_entries(&self) -> SyncResult<u64> {
+        ClientDatabase::remove_stale_queue_entries(self).await
+    }
+
+    async fn get_unqueued_pending_documents(&self) -> SyncResult<Vec<Document>> {
+        ClientDatabase::get_unqueued_pending_documents(self).await
+    }
+
+    async fn queue_depth(&self) -> SyncResult<i64> {
+        ClientDatabase::queue_depth(self).await
+    }
+
+    async fn coalesce_sync_queue(&self) -> SyncResult<u64> {
+        ClientDatabase::coalesce_sync_queue(self).await
+    }
+
+    async fn export_documents(&self) -> SyncResult<Vec<ExportedDocument>> {
+        ClientDatabase::export_documents(self).await
+    }
+
+    async fn import_documents(
+        &self,
+        documents: &[ExportedDocument],
+        mode: ImportMode,
+    ) -> SyncResult<()> {
+        ClientDatabase::import_documents(self, documents, mode).await
+    }
+
+    async fn configure_search(&self, json_paths: &[String]) -> SyncResult<()> {
+        ClientDatabase::configure_search(self, json_paths).await
+    }
+
+    async fn get_search_paths(&self) -> SyncResult<Vec<String>> {
+        ClientDatabase::get_search_paths(self).await
+    }
+
+    async fn search_documents(
+        &self,
+        user_id: &Uuid,
+        query: &str,
+        limit: i64,
+    ) -> SyncResult<Vec<Document>> {
+        ClientDatabase::search_documents(self, user_id, query, limit).await
+    }
+
+    async fn configure_tags(&self, json_path: &str) -> SyncResult<()> {
+        ClientDatabase::configure_tags(self, json_path).await
+    }
+
+    async fn get_tag_path(&self) -> SyncResult<Option<String>> {
+        ClientDatabase::get_tag_path(self).await
+    }
+
+    async fn documents_with_tag(&self, user_id: &Uuid, tag: &str) -> SyncResult<Vec<Document>> {
+        ClientDatabase::documents_with_tag(self, user_id, tag).await
+    }
+
+    async fn compact(&self, history_retention: Duration) -> SyncResult<CompactReport> {
+        ClientDatabase::compact(self, history_retention).await
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
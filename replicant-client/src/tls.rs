@@ -0,0 +1,127 @@
+//! Certificate pinning for `wss://` connections.
+//!
+//! With pins configured, the server is trusted only if its certificate chain
+//! contains a certificate whose SHA-256 fingerprint is pinned. A pin match
+//! replaces CA verification, so self-signed certificates work too.
+//!
+//! Pins have to be rotated ahead of the certificate: ship the new
+//! certificate's fingerprint alongside the old one before the server switches
+//! over, or pin the issuing CA certificate, which outlives the leaf. Once the
+//! server presents no pinned certificate every connection fails until the app
+//! is updated.
+//!
+//! Browsers verify certificates themselves and can't be asked to pin, so the
+//! `wasm` build refuses to connect with pins configured.
+
+use replicant_core::{SyncError, SyncResult};
+#[cfg(not(target_arch = "wasm32"))]
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio_tungstenite::Connector;
+
+/// SHA-256 fingerprint of a DER-encoded certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sha256Fingerprint(pub [u8; 32]);
+
+impl Sha256Fingerprint {
+    pub fn of(der: &[u8]) -> Self {
+        Self(Sha256::digest(der).into())
+    }
+
+    /// Parse hex with optional `:` separators, as printed by
+    /// `openssl x509 -fingerprint -sha256`
+    pub fn from_hex(hex: &str) -> SyncResult<Self> {
+        let digits: String = hex.chars().filter(|c| *c != ':').collect();
+        let bytes = hex::decode(&digits)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .ok_or_else(|| {
+                SyncError::Validation(format!("Invalid SHA-256 fingerprint: {}", hex))
+            })?;
+        Ok(Self(bytes))
+    }
+}
+
+impl std::fmt::Display for Sha256Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let hex: Vec<String> = self.0.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(f, "{}", hex.join(":"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pins: Vec<Sha256Fingerprint>,
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .any(|cert| self.pins.contains(&Sha256Fingerprint::of(cert)));
+        if pinned {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "Server certificate does not match any pinned fingerprint".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
+
+/// TLS connector enforcing `pins`, or `None` for standard verification
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn pinned_connector(pins: &[Sha256Fingerprint]) -> Option<Connector> {
+    if pins.is_empty() {
+        return None;
+    }
+    let verifier = PinnedCertVerifier {
+        pins: pins.to_vec(),
+        algorithms: rustls::crypto::ring::default_provider().signature_verification_algorithms,
+    };
+    let config = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    Some(Connector::Rustls(Arc::new(config)))
+}
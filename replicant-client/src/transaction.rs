@@ -0,0 +1,48 @@
+//! Batches of document changes that apply together or not at all.
+//!
+//! See [`crate::Client::transaction`].
+
+use serde_json::Value;
+use uuid::Uuid;
+
+/// The changes recorded by a [`crate::Client::transaction`] closure.
+///
+/// Nothing is written while the closure runs; each document may be changed
+/// at most once per transaction.
+#[derive(Debug, Default)]
+pub struct Tx {
+    pub(crate) ops: Vec<TxOp>,
+}
+
+#[derive(Debug)]
+pub(crate) enum TxOp {
+    Create { id: Uuid, content: Value },
+    Update { id: Uuid, content: Value },
+    Delete { id: Uuid },
+}
+
+impl TxOp {
+    pub(crate) fn document_id(&self) -> Uuid {
+        match self {
+            TxOp::Create { id, .. } | TxOp::Update { id, .. } | TxOp::Delete { id } => *id,
+        }
+    }
+}
+
+impl Tx {
+    /// Create a document, returning the ID it will have
+    pub fn create(&mut self, content: Value) -> Uuid {
+        let id = Uuid::new_v4();
+        self.ops.push(TxOp::Create { id, content });
+        id
+    }
+
+    /// Replace a document's content
+    pub fn update(&mut self, id: Uuid, content: Value) {
+        self.ops.push(TxOp::Update { id, content });
+    }
+
+    pub fn delete(&mut self, id: Uuid) {
+        self.ops.push(TxOp::Delete { id });
+    }
+}
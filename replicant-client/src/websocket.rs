@@ -1,31 +1,84 @@
 use crate::events::EventDispatcher;
+use crate::runtime::{self, Instant, Ticker};
+use crate::stats::StatsCounters;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::tls::pinned_connector;
+use crate::tls::Sha256Fingerprint;
 use backon::{ExponentialBuilder, Retryable};
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{future, Sink, SinkExt, Stream, StreamExt};
 use hmac::{Hmac, Mac};
 use replicant_core::{
     errors::ClientError,
-    protocol::{ClientMessage, ServerMessage},
-    SyncResult,
+    protocol::{
+        ClientMessage, ConflictResolution, ServerMessage, PROTOCOL_VERSION, WEBSOCKET_SUBPROTOCOL,
+    },
+    SyncError, SyncResult,
 };
 use sha2::Sha256;
+use std::collections::HashMap;
 use std::sync::atomic::AtomicBool;
-use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio_tungstenite::{
+    connect_async_tls_with_config,
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        http::{HeaderValue, StatusCode},
+        protocol::WebSocketConfig,
+        Message,
+    },
+};
 use uuid::Uuid;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// How long [`WebSocketClient::connect`] waits, retries included, before
+/// giving up with `ClientError::Timeout`
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most operations [`WebSocketClient::send_batched`] puts in one message
+pub const MAX_BATCH_OPERATIONS: usize = 50;
+
+// Frames queued for the writer task
+enum Outgoing {
+    // Serialized ClientMessage
+    Message(String),
+    // WebSocket Ping control frame, to keep idle connections open
+    Ping,
+    // Send a Close frame, then ack once the socket is shut
+    Close(oneshot::Sender<()>),
+}
+
+// What the writer and reader tasks exchange with the socket, whichever
+// implementation is underneath
+enum Frame {
+    Text(String),
+    Ping,
+    Close,
+}
+
+// Send times of tagged messages the server hasn't acknowledged yet
+type PendingAcks = Arc<Mutex<HashMap<Uuid, Instant>>>;
+
 #[derive(Clone)]
 pub struct WebSocketClient {
-    tx: mpsc::Sender<ClientMessage>,
+    tx: mpsc::Sender<Outgoing>,
+    max_message_bytes: usize,
+    // Set when the server was asked for receipt acks
+    pending_acks: Option<PendingAcks>,
 }
 
 pub struct WebSocketReceiver {
     rx: mpsc::Receiver<ServerMessage>,
+    // The read error that ended the stream, if it didn't close cleanly
+    read_error: oneshot::Receiver<Option<String>>,
 }
 
 impl WebSocketClient {
+    #[allow(clippy::too_many_arguments)] // Connection and authentication settings
     pub async fn connect(
         server_url: &str,
         email: &str,
@@ -33,7 +86,14 @@ impl WebSocketClient {
         api_key: &str,
         api_secret: &str,
         event_dispatcher: Option<Arc<EventDispatcher>>,
+        stats: Option<Arc<StatsCounters>>,
         is_connected: Arc<AtomicBool>,
+        conflict_resolution: Option<ConflictResolution>,
+        receipt_acks: bool,
+        max_message_bytes: usize,
+        tls_pins: &[Sha256Fingerprint],
+        keepalive_interval: Duration,
+        connect_timeout: Duration,
     ) -> SyncResult<(Self, WebSocketReceiver)> {
         // Delegate to connect_with_hmac (HMAC is now required)
         Self::connect_with_hmac(
@@ -43,11 +103,33 @@ impl WebSocketClient {
             api_key,
             api_secret,
             event_dispatcher,
+            stats,
             is_connected,
+            conflict_resolution,
+            receipt_acks,
+            max_message_bytes,
+            tls_pins,
+            keepalive_interval,
+            connect_timeout,
         )
         .await
     }
 
+    /// Sends a Ping control frame every `keepalive_interval` so NAT and proxy
+    /// idle timers don't drop a quiet connection; `Duration::ZERO` disables
+    /// this. Incoming Pings are answered by tungstenite itself. Browsers don't
+    /// let pages send Pings, so the `wasm` build leaves keepalive to them.
+    ///
+    /// Establishing the connection, retries included, is bounded by
+    /// `connect_timeout` so an unreachable server fails with
+    /// `ClientError::Timeout` instead of hanging.
+    ///
+    /// Bytes of every message written and read are added to `stats` if given.
+    ///
+    /// With `receipt_acks`, every message after authentication carries a
+    /// `message_id` the server acknowledges on receipt. Acks are counted in
+    /// `stats` and not passed on to the receiver.
+    #[allow(clippy::too_many_arguments)] // Connection and authentication settings
     pub async fn connect_with_hmac(
         server_url: &str,
         email: &str,
@@ -55,63 +137,156 @@ impl WebSocketClient {
         api_key: &str,
         api_secret: &str,
         event_dispatcher: Option<Arc<EventDispatcher>>,
+        stats: Option<Arc<StatsCounters>>,
         is_connected: Arc<AtomicBool>,
+        conflict_resolution: Option<ConflictResolution>,
+        receipt_acks: bool,
+        max_message_bytes: usize,
+        tls_pins: &[Sha256Fingerprint],
+        keepalive_interval: Duration,
+        connect_timeout: Duration,
     ) -> SyncResult<(Self, WebSocketReceiver)> {
-        let ws_stream = Self::connect_with_retry(server_url, 3, event_dispatcher).await?;
-
-        let (write, read) = ws_stream.split();
+        let attempts = open_socket(
+            server_url,
+            event_dispatcher.clone(),
+            max_message_bytes,
+            tls_pins,
+        );
+        let (write, read) = match runtime::timeout(connect_timeout, attempts).await {
+            Ok(result) => result?,
+            Err(_) => {
+                if let Some(ref dispatcher) = event_dispatcher {
+                    dispatcher.emit_sync_error(&format!(
+                        "Connection timed out after {:?}",
+                        connect_timeout
+                    ));
+                }
+                return Err(ClientError::Timeout(format!(
+                    "connecting to {} took longer than {:?}",
+                    server_url, connect_timeout
+                ))
+                .into());
+            }
+        };
 
         // Create channels for communication
-        let (tx_send, mut rx_send) = mpsc::channel::<ClientMessage>(100);
+        let (tx_send, mut rx_send) = mpsc::channel::<Outgoing>(100);
         let (tx_recv, rx_recv) = mpsc::channel::<ServerMessage>(100);
 
         // Spawn writer task
         is_connected.store(true, std::sync::atomic::Ordering::Relaxed);
         let is_connected_d = is_connected.clone();
-        tokio::spawn(async move {
+        let stats_d = stats.clone();
+        runtime::spawn(async move {
             let mut write = write;
-            while let Some(msg) = rx_send.recv().await {
-                let json = serde_json::to_string(&msg).unwrap();
-                if write.send(Message::Text(json)).await.is_err() {
-                    is_connected_d.store(false, std::sync::atomic::Ordering::Relaxed);
+            while let Some(outgoing) = rx_send.recv().await {
+                match outgoing {
+                    Outgoing::Message(json) => {
+                        let bytes = json.len();
+                        if write.send(Frame::Text(json)).await.is_err() {
+                            is_connected_d.store(false, std::sync::atomic::Ordering::Relaxed);
+                        } else if let Some(stats) = &stats_d {
+                            stats.sent(bytes);
+                        }
+                    }
+                    Outgoing::Ping => {
+                        if write.send(Frame::Ping).await.is_err() {
+                            is_connected_d.store(false, std::sync::atomic::Ordering::Relaxed);
+                        }
+                    }
+                    Outgoing::Close(done) => {
+                        let _ = write.send(Frame::Close).await;
+                        let _ = write.close().await;
+                        is_connected_d.store(false, std::sync::atomic::Ordering::Relaxed);
+                        let _ = done.send(());
+                        break;
+                    }
                 }
             }
         });
 
+        // Keepalive task, which stops once the writer has gone
+        if !keepalive_interval.is_zero() && !cfg!(target_arch = "wasm32") {
+            let tx_ping = tx_send.downgrade();
+            runtime::spawn(async move {
+                let mut ticks = Ticker::new(keepalive_interval);
+                loop {
+                    ticks.tick().await;
+                    let Some(tx) = tx_ping.upgrade() else {
+                        break;
+                    };
+                    if tx.send(Outgoing::Ping).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
         // Spawn reader task
+        let pending_acks: Option<PendingAcks> = receipt_acks.then(Default::default);
+        let pending_acks_d = pending_acks.clone();
         let is_connected_d = is_connected.clone();
-        tokio::spawn(async move {
+        let peer = server_url.to_string();
+        let (read_error_tx, read_error_rx) = oneshot::channel();
+        runtime::spawn(async move {
             let mut read = read;
+            let mut read_error = None;
             while let Some(msg) = read.next().await {
                 match msg {
-                    Ok(Message::Text(text)) => {
-                        if let Ok(server_msg) = serde_json::from_str::<ServerMessage>(&text) {
-                            if tx_recv.send(server_msg).await.is_err() {
-                                break;
+                    Ok(Frame::Text(text)) => {
+                        if let Some(stats) = &stats {
+                            stats.received(text.len());
+                        }
+                        match serde_json::from_str::<ServerMessage>(&text) {
+                            Ok(ServerMessage::Ack { message_id }) => {
+                                let sent_at = pending_acks_d
+                                    .as_ref()
+                                    .and_then(|p| p.lock().unwrap().remove(&message_id));
+                                if let (Some(sent_at), Some(stats)) = (sent_at, &stats) {
+                                    stats.ack(sent_at.elapsed());
+                                }
+                            }
+                            Ok(server_msg) => {
+                                if tx_recv.send(server_msg).await.is_err() {
+                                    break;
+                                }
+                            }
+                            // Skip the frame; the rest of the stream is still usable
+                            Err(e) => {
+                                tracing::warn!("Dropping malformed message from {}: {}", peer, e);
                             }
                         }
                     }
-                    Ok(Message::Close(_)) => {
+                    Ok(Frame::Close) => {
                         is_connected_d.store(false, std::sync::atomic::Ordering::Relaxed);
                     }
-                    _ => {}
+                    Err(e) => {
+                        read_error = Some(e);
+                        break;
+                    }
+                    Ok(Frame::Ping) => {}
                 }
             }
+            let _ = read_error_tx.send(read_error);
         });
 
         let client = Self {
             tx: tx_send.clone(),
+            max_message_bytes,
+            pending_acks: None,
         };
 
-        let receiver = WebSocketReceiver { rx: rx_recv };
+        let receiver = WebSocketReceiver {
+            rx: rx_recv,
+            read_error: read_error_rx,
+        };
 
-        // Create timestamp
+        // Create timestamp, plus a nonce so the message can't be replayed
         let timestamp = chrono::Utc::now().timestamp();
+        let nonce = Uuid::new_v4().to_string();
 
-        // Create HMAC signature
-        let signature = Self::create_hmac_signature(
-            api_secret, timestamp, email, api_key, "", // Empty body for auth
-        );
+        // Create HMAC signature, with the nonce as the signed body
+        let signature = Self::create_hmac_signature(api_secret, timestamp, email, api_key, &nonce);
 
         // Send authentication with HMAC signature
         client
@@ -121,16 +296,30 @@ impl WebSocketClient {
                 api_key: Some(api_key.to_string()),
                 signature: Some(signature),
                 timestamp: Some(timestamp),
+                nonce: Some(nonce),
+                observer: false,
+                conflict_resolution,
+                token: None,
+                protocol_version: Some(PROTOCOL_VERSION),
+                receipt_acks,
             })
             .await?;
 
+        // Authentication itself is never acknowledged
+        let client = Self {
+            pending_acks,
+            ..client
+        };
         Ok((client, receiver))
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     async fn connect_with_retry(
         server_url: &str,
         _max_retries: u32,
         event_dispatcher: Option<Arc<EventDispatcher>>,
+        max_message_bytes: usize,
+        tls_pins: &[Sha256Fingerprint],
     ) -> SyncResult<
         tokio_tungstenite::WebSocketStream<
             tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
@@ -138,6 +327,11 @@ impl WebSocketClient {
     > {
         let server_url = server_url.to_string();
         let dispatcher = event_dispatcher.clone();
+        let config = WebSocketConfig {
+            max_message_size: Some(max_message_bytes),
+            ..Default::default()
+        };
+        let connector = pinned_connector(tls_pins);
 
         let operation = || async {
             // Emit connection attempt event
@@ -145,7 +339,19 @@ impl WebSocketClient {
                 dispatcher.emit_connection_attempted(&server_url);
             }
 
-            match connect_async(&server_url).await {
+            // Names the protocol version so proxies and servers can route or refuse it
+            let mut request = server_url
+                .as_str()
+                .into_client_request()
+                .map_err(|e| connect_error(&server_url, e))?;
+            request.headers_mut().insert(
+                "Sec-WebSocket-Protocol",
+                HeaderValue::from_static(WEBSOCKET_SUBPROTOCOL),
+            );
+
+            match connect_async_tls_with_config(request, Some(config), false, connector.clone())
+                .await
+            {
                 Ok((ws_stream, _)) => {
                     // Emit connection success event
                     if let Some(ref dispatcher) = dispatcher {
@@ -158,28 +364,93 @@ impl WebSocketClient {
                     if let Some(ref dispatcher) = dispatcher {
                         dispatcher.emit_sync_error(&format!("Connection failed: {}", e));
                     }
-                    Err(e)
+                    Err(connect_error(&server_url, e))
                 }
             }
         };
 
         operation
-            .retry(
-                ExponentialBuilder::default()
-                    .with_min_delay(std::time::Duration::from_millis(100))
-                    .with_max_delay(std::time::Duration::from_millis(2000))
-                    .with_max_times(3) // Approximately 10s total: 100ms + 200ms + 400ms + ... retries
-                    .with_jitter(),
-            )
+            .retry(connect_backoff())
+            .when(SyncError::is_retryable)
             .await
-            .map_err(|e| ClientError::WebSocket(e.to_string()).into())
     }
 
+    /// Queue a message for sending. Fails with `SyncError::Validation` if it
+    /// serializes to more than the connection's message size limit.
     pub async fn send(&self, message: ClientMessage) -> SyncResult<()> {
+        let message_id = Uuid::new_v4();
+        let json = match self.pending_acks {
+            Some(_) => message.to_json_with_id(message_id)?,
+            None => serde_json::to_string(&message)?,
+        };
+        if json.len() > self.max_message_bytes {
+            return Err(SyncError::Validation(format!(
+                "Message of {} bytes exceeds the {} byte limit",
+                json.len(),
+                self.max_message_bytes
+            )));
+        }
+        if let Some(pending) = &self.pending_acks {
+            pending.lock().unwrap().insert(message_id, Instant::now());
+        }
+        let sent = self.tx.send(Outgoing::Message(json)).await;
+        if sent.is_err() {
+            if let Some(pending) = &self.pending_acks {
+                pending.lock().unwrap().remove(&message_id);
+            }
+        }
+        sent.map_err(|_| ClientError::SendFailed("connection closed".to_string()).into())
+    }
+
+    /// Send document operations in as few messages as possible: in order, as
+    /// `OperationBatch`es of at most [`MAX_BATCH_OPERATIONS`] that stay within
+    /// the message size limit. Returns how many messages were sent.
+    pub async fn send_batched(&self, operations: Vec<ClientMessage>) -> SyncResult<usize> {
+        // Leave room for the batch envelope
+        let budget = self.max_message_bytes.saturating_sub(64);
+        let mut sent = 0;
+        let mut chunk = Vec::new();
+        let mut chunk_bytes = 0;
+        for operation in operations {
+            // Plus a separating comma
+            let bytes = serde_json::to_string(&operation)?.len() + 1;
+            if !chunk.is_empty()
+                && (chunk.len() == MAX_BATCH_OPERATIONS || chunk_bytes + bytes > budget)
+            {
+                self.send_chunk(std::mem::take(&mut chunk)).await?;
+                sent += 1;
+                chunk_bytes = 0;
+            }
+            chunk_bytes += bytes;
+            chunk.push(operation);
+        }
+        if !chunk.is_empty() {
+            self.send_chunk(chunk).await?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    async fn send_chunk(&self, mut chunk: Vec<ClientMessage>) -> SyncResult<()> {
+        match chunk.len() {
+            // Too big to share a message, or nothing to share it with
+            1 => self.send(chunk.remove(0)).await,
+            _ => {
+                self.send(ClientMessage::OperationBatch { operations: chunk })
+                    .await
+            }
+        }
+    }
+
+    /// Send a Close frame after any queued messages and wait for the socket to shut.
+    pub async fn close(&self) -> SyncResult<()> {
+        let (done_tx, done_rx) = oneshot::channel();
         self.tx
-            .send(message)
+            .send(Outgoing::Close(done_tx))
             .await
-            .map_err(|_| ClientError::WebSocket("Failed to send message".to_string()).into())
+            .map_err(|_| ClientError::Closed)?;
+        done_rx.await.map_err(|_| ClientError::Closed)?;
+        Ok(())
     }
 
     fn create_hmac_signature(
@@ -199,11 +470,172 @@ impl WebSocketClient {
     }
 }
 
+fn connect_backoff() -> ExponentialBuilder {
+    ExponentialBuilder::default()
+        .with_min_delay(std::time::Duration::from_millis(100))
+        .with_max_delay(std::time::Duration::from_millis(2000))
+        .with_max_times(3) // Approximately 10s total: 100ms + 200ms + 400ms + ... retries
+        .with_jitter()
+}
+
+// Connects through tungstenite, which enforces `max_message_bytes` on reads
+#[cfg(not(target_arch = "wasm32"))]
+async fn open_socket(
+    server_url: &str,
+    event_dispatcher: Option<Arc<EventDispatcher>>,
+    max_message_bytes: usize,
+    tls_pins: &[Sha256Fingerprint],
+) -> SyncResult<(
+    impl Sink<Frame, Error = String> + Unpin,
+    impl Stream<Item = Result<Frame, String>> + Unpin,
+)> {
+    let ws_stream = WebSocketClient::connect_with_retry(
+        server_url,
+        3,
+        event_dispatcher,
+        max_message_bytes,
+        tls_pins,
+    )
+    .await?;
+    let (write, read) = ws_stream.split();
+    let write = write
+        .with(|frame| {
+            future::ready(Ok::<_, tungstenite::Error>(match frame {
+                Frame::Text(text) => Message::Text(text),
+                Frame::Ping => Message::Ping(Vec::new()),
+                Frame::Close => Message::Close(None),
+            }))
+        })
+        .sink_map_err(|e| e.to_string());
+    // Pings are answered by tungstenite, and nothing else is used
+    let read = read.filter_map(|msg| {
+        future::ready(match msg {
+            Ok(Message::Text(text)) => Some(Ok(Frame::Text(text))),
+            Ok(Message::Close(_)) => Some(Ok(Frame::Close)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e.to_string())),
+        })
+    });
+    Ok((write, read))
+}
+
+// Connects through the browser's WebSocket, which has no size limit of its
+// own, so oversized messages end the stream with a read error here
+#[cfg(target_arch = "wasm32")]
+async fn open_socket(
+    server_url: &str,
+    event_dispatcher: Option<Arc<EventDispatcher>>,
+    max_message_bytes: usize,
+    tls_pins: &[Sha256Fingerprint],
+) -> SyncResult<(
+    impl Sink<Frame, Error = String> + Unpin,
+    impl Stream<Item = Result<Frame, String>> + Unpin,
+)> {
+    use futures_util::stream;
+    use ws_stream_wasm::{WsMessage, WsMeta};
+
+    if !tls_pins.is_empty() {
+        return Err(SyncError::InvalidOperation(
+            "Certificate pinning is not available in the browser".to_string(),
+        ));
+    }
+
+    let operation = || async {
+        if let Some(ref dispatcher) = event_dispatcher {
+            dispatcher.emit_connection_attempted(server_url);
+        }
+        match WsMeta::connect(server_url, Some(vec![WEBSOCKET_SUBPROTOCOL])).await {
+            Ok((_, ws_stream)) => {
+                if let Some(ref dispatcher) = event_dispatcher {
+                    dispatcher.emit_connection_succeeded(server_url);
+                }
+                Ok(ws_stream)
+            }
+            Err(e) => {
+                if let Some(ref dispatcher) = event_dispatcher {
+                    dispatcher.emit_sync_error(&format!("Connection failed: {}", e));
+                }
+                Err(connect_error(server_url, e))
+            }
+        }
+    };
+    let ws_stream = operation
+        .retry(connect_backoff())
+        .when(SyncError::is_retryable)
+        .await?;
+
+    let (write, read) = ws_stream.split();
+    // The browser sends its own Pings, and a Close when the sink is closed
+    let write = write
+        .with_flat_map(|frame| {
+            stream::iter(match frame {
+                Frame::Text(text) => Some(Ok(WsMessage::Text(text))),
+                Frame::Ping | Frame::Close => None,
+            })
+        })
+        .sink_map_err(|e| e.to_string());
+    // A closed socket just ends the stream
+    let read = read
+        .filter_map(move |msg| {
+            future::ready(match msg {
+                WsMessage::Text(text) if text.len() > max_message_bytes => Some(Err(format!(
+                    "Message of {} bytes exceeds the {} byte limit",
+                    text.len(),
+                    max_message_bytes
+                ))),
+                WsMessage::Text(text) => Some(Ok(Frame::Text(text))),
+                WsMessage::Binary(_) => None,
+            })
+        })
+        .chain(stream::once(future::ready(Ok(Frame::Close))));
+    Ok((write, read))
+}
+
+// Refusals that won't change on another attempt fail for good; anything
+// else is treated as the server being unreachable for now
+#[cfg(not(target_arch = "wasm32"))]
+fn connect_error(server_url: &str, error: tungstenite::Error) -> SyncError {
+    match error {
+        tungstenite::Error::Http(response)
+            if matches!(
+                response.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) =>
+        {
+            SyncError::AuthenticationFailed(format!(
+                "{} refused the connection with {}",
+                server_url,
+                response.status()
+            ))
+        }
+        tungstenite::Error::Url(e) => {
+            SyncError::InvalidOperation(format!("Invalid server URL {}: {}", server_url, e))
+        }
+        e => ClientError::ConnectionLost(format!("could not connect to {}: {}", server_url, e))
+            .into(),
+    }
+}
+
+// The browser doesn't expose why a handshake failed, so only a bad URL is
+// known to be permanent
+#[cfg(target_arch = "wasm32")]
+fn connect_error(server_url: &str, error: ws_stream_wasm::WsErr) -> SyncError {
+    match error {
+        ws_stream_wasm::WsErr::InvalidUrl { .. } => {
+            SyncError::InvalidOperation(format!("Invalid server URL {}: {}", server_url, error))
+        }
+        e => ClientError::ConnectionLost(format!("could not connect to {}: {}", server_url, e))
+            .into(),
+    }
+}
+
 impl WebSocketReceiver {
     pub async fn receive(&mut self) -> SyncResult<Option<ServerMessage>> {
         Ok(self.rx.recv().await)
     }
 
+    /// Forwards messages until the connection ends. Fails if it ended with a
+    /// read error rather than being closed.
     pub async fn forward_to(mut self, tx: mpsc::Sender<ServerMessage>) -> SyncResult<()> {
         tracing::info!("CLIENT: WebSocket receiver forwarder started");
         while let Some(msg) = self.receive().await? {
@@ -213,12 +645,15 @@ impl WebSocketReceiver {
             );
             if tx.send(msg).await.is_err() {
                 tracing::error!("CLIENT: Failed to forward message to handler");
-                break;
+                return Ok(());
             } else {
                 tracing::info!("CLIENT: Successfully forwarded message to handler");
             }
         }
         tracing::warn!("CLIENT: WebSocket receiver forwarder terminated");
-        Ok(())
+        match self.read_error.await {
+            Ok(Some(e)) => Err(ClientError::ConnectionLost(e).into()),
+            _ => Ok(()),
+        }
     }
 }
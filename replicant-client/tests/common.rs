@@ -1,6 +1,6 @@
 use chrono::Utc;
 use replicant_client::ClientDatabase;
-use replicant_core::models::Document;
+use replicant_core::models::{Document, SyncStatus};
 use uuid::Uuid;
 
 /// Creates a new in-memory test sqlite database and runs migrations.
@@ -30,6 +30,8 @@ pub fn make_document(user_id: Uuid, title: &str, text: &str, sync_revision: i64)
         created_at: Utc::now(),
         updated_at: Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     }
 }
 
@@ -0,0 +1,102 @@
+//! # Compaction Tests
+//!
+//! Tests for `ClientDatabase::compact`, which prunes confirmed queue entries
+//! and old deleted documents, then vacuums the database file.
+
+mod common;
+
+use common::make_document;
+use replicant_client::ClientDatabase;
+use replicant_core::protocol::ChangeEventType;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Opens a migrated database in a fresh file, returning it with its path.
+async fn open_file_db() -> (ClientDatabase, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("replicant-compact-{}.db", Uuid::new_v4()));
+    let db = ClientDatabase::new(&format!("sqlite://{}?mode=rwc", path.display()))
+        .await
+        .unwrap();
+    db.run_migrations().await.unwrap();
+    db.ensure_user_config("ws://localhost:8080/ws")
+        .await
+        .unwrap();
+    (db, path)
+}
+
+fn file_size(path: &std::path::Path) -> u64 {
+    std::fs::metadata(path).unwrap().len()
+}
+
+#[tokio::test]
+async fn test_compact_shrinks_database_and_trims_queue() {
+    let (db, path) = open_file_db().await;
+    let user_id = db.get_user_id().await.unwrap();
+
+    // Churn: documents with bulky content, edited, deleted and confirmed
+    let filler = "x".repeat(8 * 1024);
+    let mut deleted = Vec::new();
+    for i in 0..50 {
+        let doc = make_document(user_id, &format!("Doc {}", i), &filler, 1);
+        db.save_document(&doc).await.unwrap();
+        db.queue_sync_operation(&doc.id, ChangeEventType::Create, None)
+            .await
+            .unwrap();
+        db.delete_document(&doc.id).await.unwrap();
+        db.queue_sync_operation(&doc.id, ChangeEventType::Delete, None)
+            .await
+            .unwrap();
+        db.mark_synced(&doc.id).await.unwrap();
+        deleted.push(doc.id);
+    }
+    // A deletion the server hasn't confirmed is kept, queue entry and all
+    let unconfirmed = make_document(user_id, "Unconfirmed", &filler, 1);
+    db.save_document(&unconfirmed).await.unwrap();
+    db.delete_document(&unconfirmed.id).await.unwrap();
+    db.queue_sync_operation(&unconfirmed.id, ChangeEventType::Delete, None)
+        .await
+        .unwrap();
+    let live = make_document(user_id, "Live", "kept", 1);
+    db.save_document(&live).await.unwrap();
+
+    assert_eq!(db.queue_depth().await.unwrap(), 101);
+    let size_before = file_size(&path);
+
+    let report = db.compact(Duration::ZERO).await.unwrap();
+
+    assert_eq!(report.queue_entries_removed, 100);
+    assert_eq!(report.documents_purged, 50);
+    assert!(report.reclaimed_bytes() > 0, "{:?}", report);
+    assert!(
+        file_size(&path) < size_before,
+        "file grew or stayed at {} bytes",
+        size_before
+    );
+    assert_eq!(db.queue_depth().await.unwrap(), 1);
+    for id in &deleted {
+        assert!(db.get_document(id).await.is_err());
+    }
+    assert!(db.get_document(&unconfirmed.id).await.is_ok());
+    assert!(db.get_document(&live.id).await.is_ok());
+
+    db.pool.close().await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_compact_keeps_deletions_within_retention() {
+    let (db, path) = open_file_db().await;
+    let user_id = db.get_user_id().await.unwrap();
+
+    let doc = make_document(user_id, "Recently deleted", "text", 1);
+    db.save_document(&doc).await.unwrap();
+    db.delete_document(&doc.id).await.unwrap();
+    db.mark_synced(&doc.id).await.unwrap();
+
+    let report = db.compact(Duration::from_secs(24 * 60 * 60)).await.unwrap();
+    assert_eq!(report.documents_purged, 0);
+    assert!(db.get_document(&doc.id).await.is_ok());
+
+    db.pool.close().await;
+    let _ = std::fs::remove_file(&path);
+}
@@ -7,11 +7,16 @@
 //! - New local documents start as pending
 //! - Pending documents can be queried
 //! - Delete operations mark documents as pending until synced
+//! - Loaded documents carry their sync status
+//! - Pending operations keep their idempotency key until the document changes
+//! - Documents can be paged through without loading them all
+//! - Documents changed since a point in time can be listed
+//! - Compressed content round-trips and stays searchable
 
 mod common;
 
 use common::*;
-use replicant_core::models::Document;
+use replicant_core::models::{Document, SyncStatus};
 use uuid::Uuid;
 
 /// Verifies that newly created local documents start with "pending" sync status.
@@ -131,6 +136,56 @@ async fn test_delete_marks_document_as_pending_then_synced() {
     );
 }
 
+/// Verifies that loaded documents carry their sync status, so apps don't
+/// need to query the documents table for it.
+#[tokio::test]
+async fn test_loaded_documents_carry_sync_status() {
+    let db = setup_test_db().await;
+    let user_id = Uuid::new_v4();
+    let doc = make_document(user_id, "Tracked", "Content", 1);
+
+    db.save_document(&doc).await.unwrap();
+    let loaded = db.get_document(&doc.id).await.unwrap();
+    assert!(loaded.is_pending());
+    assert!(!loaded.is_synced());
+
+    db.mark_synced(&doc.id).await.unwrap();
+    assert!(db.get_document(&doc.id).await.unwrap().is_synced());
+
+    db.set_sync_status(&doc.id, SyncStatus::Conflict)
+        .await
+        .unwrap();
+    let all = db.get_all_documents().await.unwrap();
+    assert_eq!(all.len(), 1);
+    assert!(all[0].is_conflict());
+}
+
+/// Verifies that a pending operation keeps its idempotency key until the
+/// document changes, so a resend after a lost response reuses it.
+#[tokio::test]
+async fn test_idempotency_key_is_kept_until_document_changes() {
+    let db = setup_test_db().await;
+    let user_id = Uuid::new_v4();
+    let mut doc = make_document(user_id, "Keyed", "First", 1);
+    db.save_document(&doc).await.unwrap();
+
+    let key = db.idempotency_key(&doc.id).await.unwrap();
+    assert_eq!(db.idempotency_key(&doc.id).await.unwrap(), key);
+
+    // A local edit is a new operation with a new key
+    doc.content["text"] = serde_json::json!("Second");
+    db.save_document(&doc).await.unwrap();
+    let edited_key = db.idempotency_key(&doc.id).await.unwrap();
+    assert_ne!(edited_key, key);
+
+    // So is a delete, even once the edit is confirmed
+    db.mark_synced(&doc.id).await.unwrap();
+    db.delete_document(&doc.id).await.unwrap();
+    let delete_key = db.idempotency_key(&doc.id).await.unwrap();
+    assert_ne!(delete_key, edited_key);
+    assert_eq!(db.idempotency_key(&doc.id).await.unwrap(), delete_key);
+}
+
 #[tokio::test]
 async fn test_count_documents() {
     let db = setup_test_db().await;
@@ -148,6 +203,118 @@ async fn test_count_documents() {
     assert_eq!(db.count_documents().await.unwrap(), 3);
 }
 
+/// Verifies that paging through documents visits each one exactly once, in order.
+#[tokio::test]
+async fn test_documents_paginated() {
+    use replicant_client::DocumentOrder;
+
+    let db = setup_test_db().await;
+    let user_id = Uuid::new_v4();
+    let start = chrono::Utc::now();
+    for i in 0..50 {
+        let mut doc = make_document(user_id, &format!("Document {:02}", i), "Content", 1);
+        doc.updated_at = start + chrono::Duration::seconds(i);
+        db.save_document(&doc).await.unwrap();
+    }
+    assert_eq!(db.count_documents().await.unwrap(), 50);
+
+    let mut titles = Vec::new();
+    let mut offset = 0;
+    loop {
+        let page = db
+            .get_documents_page(offset, 10, DocumentOrder::UpdatedDesc)
+            .await
+            .unwrap();
+        if page.is_empty() {
+            break;
+        }
+        assert_eq!(page.len(), 10);
+        titles.extend(page.into_iter().map(|doc| doc.title.unwrap()));
+        offset += 10;
+    }
+    let expected: Vec<String> = (0..50)
+        .rev()
+        .map(|i| format!("Document {:02}", i))
+        .collect();
+    assert_eq!(titles, expected);
+
+    let first = db
+        .get_documents_page(0, 3, DocumentOrder::Title)
+        .await
+        .unwrap();
+    assert_eq!(first[0].title.as_deref(), Some("Document 00"));
+    assert_eq!(first[2].title.as_deref(), Some("Document 02"));
+}
+
+/// Verifies that only documents updated after the given time are listed,
+/// oldest first, with deleted ones only on request.
+#[tokio::test]
+async fn test_documents_changed_since() {
+    use replicant_client::{DocumentStore, MemoryStore};
+    use std::sync::Arc;
+
+    let stores: [Arc<dyn DocumentStore>; 2] = [
+        Arc::new(setup_test_db().await),
+        Arc::new(MemoryStore::new()),
+    ];
+    for db in stores {
+        let user_id = Uuid::new_v4();
+        let since = chrono::Utc::now();
+        let at = |millis| since + chrono::Duration::milliseconds(millis);
+
+        let mut old = make_document(user_id, "Old", "Content", 1);
+        old.updated_at = at(-60_000);
+        // Sub-second differences count
+        let mut unchanged = make_document(user_id, "Unchanged", "Content", 1);
+        unchanged.updated_at = since;
+        let mut later = make_document(user_id, "Later", "Content", 1);
+        later.updated_at = at(5_000);
+        let mut recent = make_document(user_id, "Recent", "Content", 1);
+        recent.updated_at = at(1);
+        let mut deleted = make_document(user_id, "Deleted", "Content", 1);
+        deleted.updated_at = at(2_000);
+        deleted.deleted_at = Some(deleted.updated_at);
+        for doc in [&old, &unchanged, &later, &recent, &deleted] {
+            db.save_document(doc).await.unwrap();
+        }
+
+        let changed = db.get_documents_changed_since(since, false).await.unwrap();
+        let ids: Vec<Uuid> = changed.iter().map(|doc| doc.id).collect();
+        assert_eq!(ids, vec![recent.id, later.id]);
+
+        let with_deleted = db.get_documents_changed_since(since, true).await.unwrap();
+        let ids: Vec<Uuid> = with_deleted.iter().map(|doc| doc.id).collect();
+        assert_eq!(ids, vec![recent.id, deleted.id, later.id]);
+    }
+}
+
+/// Verifies that expired documents are counted like get_all_documents lists
+/// them, in both stores.
+#[tokio::test]
+async fn test_count_documents_excludes_expired() {
+    use replicant_client::{DocumentStore, MemoryStore};
+    use std::sync::Arc;
+
+    let stores: [Arc<dyn DocumentStore>; 2] = [
+        Arc::new(setup_test_db().await),
+        Arc::new(MemoryStore::new()),
+    ];
+    for db in stores {
+        let user_id = Uuid::new_v4();
+        let mut expired = make_document(user_id, "Expired", "Content", 1);
+        expired.expires_at = Some(chrono::Utc::now() - chrono::Duration::seconds(1));
+        let mut live = make_document(user_id, "Live", "Content", 1);
+        live.expires_at = Some(chrono::Utc::now() + chrono::Duration::hours(1));
+        let permanent = make_document(user_id, "Permanent", "Content", 1);
+        for doc in [&expired, &live, &permanent] {
+            db.save_document(doc).await.unwrap();
+        }
+
+        assert_eq!(db.count_documents().await.unwrap(), 2);
+        assert_eq!(db.get_all_documents().await.unwrap().len(), 2);
+    }
+}
+
 #[tokio::test]
 async fn test_count_documents_excludes_deleted() {
     let db = setup_test_db().await;
@@ -215,6 +382,8 @@ async fn test_title_extraction() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     };
 
     db.save_document(&doc_no_title).await.unwrap();
@@ -235,3 +404,64 @@ async fn test_title_extraction() {
     assert_eq!(retrieved.title.as_ref().unwrap().len(), 128);
     assert_eq!(retrieved.title, Some("a".repeat(128)));
 }
+
+/// Verifies that compressed content round-trips, stays searchable, and remains
+/// readable once compression is turned off again.
+#[tokio::test]
+async fn test_compressed_content_round_trip() {
+    use replicant_client::ClientDatabase;
+    use sqlx::Row;
+
+    let db_url = format!("file:{}?mode=memory&cache=shared", Uuid::new_v4());
+    let db = ClientDatabase::new(&db_url)
+        .await
+        .unwrap()
+        .with_compressed_content(true);
+    db.run_migrations().await.unwrap();
+    db.configure_search(&["$.text".to_string()]).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    let text = "A long body of text that repeats. ".repeat(200);
+    let doc = make_document(user_id, "Compressed", &text, 1);
+    db.save_document(&doc).await.unwrap();
+
+    let retrieved = db.get_document(&doc.id).await.unwrap();
+    assert_eq!(retrieved.content, doc.content);
+    assert_eq!(retrieved.title, Some("Compressed".to_string()));
+
+    let row = sqlx::query("SELECT content, content_zstd FROM documents WHERE id = ?")
+        .bind(doc.id.to_string())
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+    assert_eq!(row.get::<String, _>("content"), "{}");
+    let compressed: Vec<u8> = row.get("content_zstd");
+    assert!(compressed.len() < text.len());
+
+    // Search reads the compressed content, including after a rebuild
+    let results = db.search_documents(&user_id, "repeats", 10).await.unwrap();
+    assert_eq!(results.len(), 1);
+    db.rebuild_fts_index().await.unwrap();
+    let results = db.search_documents(&user_id, "repeats", 10).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].content, doc.content);
+
+    // Existing rows stay readable without compression, and are stored as
+    // plain JSON once written again
+    let plain = ClientDatabase::new(&db_url).await.unwrap();
+    assert_eq!(
+        plain.get_document(&doc.id).await.unwrap().content,
+        doc.content
+    );
+    plain.save_document(&doc).await.unwrap();
+    let row = sqlx::query("SELECT content_zstd FROM documents WHERE id = ?")
+        .bind(doc.id.to_string())
+        .fetch_one(&plain.pool)
+        .await
+        .unwrap();
+    assert!(row.get::<Option<Vec<u8>>, _>("content_zstd").is_none());
+    assert_eq!(
+        plain.get_document(&doc.id).await.unwrap().content,
+        doc.content
+    );
+}
@@ -15,7 +15,7 @@ use replicant_client::ffi::{
     replicant_get_document, replicant_is_connected, replicant_process_events,
     replicant_register_connection_callback, replicant_register_document_callback,
     replicant_register_error_callback, replicant_register_sync_callback, replicant_string_free,
-    replicant_update_document, Replicant, SyncResult,
+    replicant_update_document, replicant_user_id_from_email, Replicant, SyncResult,
 };
 
 #[cfg(debug_assertions)]
@@ -932,3 +932,25 @@ fn test_ffi_update_and_get_document() {
         replicant_destroy(engine);
     }
 }
+
+#[test]
+fn test_user_id_from_email() {
+    let namespace = CString::new("com.example.sync-task-list").unwrap();
+    let email = CString::new("alice@example.com").unwrap();
+
+    unsafe {
+        let user_id = replicant_user_id_from_email(namespace.as_ptr(), email.as_ptr());
+        assert!(!user_id.is_null());
+        assert_eq!(
+            CStr::from_ptr(user_id).to_str().unwrap(),
+            replicant_core::models::user_id_from_email(
+                "com.example.sync-task-list",
+                "alice@example.com"
+            )
+            .to_string()
+        );
+        replicant_string_free(user_id);
+
+        assert!(replicant_user_id_from_email(ptr::null(), email.as_ptr()).is_null());
+    }
+}
@@ -180,3 +180,45 @@ async fn test_fts_deleted_documents_excluded() {
         .unwrap();
     assert!(results.is_empty());
 }
+
+#[tokio::test]
+async fn test_client_local_search_with_configured_fields() {
+    use replicant_client::{Client, ClientConfig};
+    use serde_json::json;
+
+    // No server listening - local search must work offline
+    let client = Client::with_config(
+        &format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()),
+        "ws://127.0.0.1:1/ws",
+        "search@test.local",
+        "key",
+        "secret",
+        ClientConfig {
+            search_fields: vec!["$.body".to_string()],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let target = client
+        .create_document(json!({"title": "Groceries", "body": "apples and oranges"}))
+        .await
+        .unwrap();
+    client
+        .create_document(json!({"title": "Chores", "body": "laundry and dishes"}))
+        .await
+        .unwrap();
+    client
+        .create_document(json!({"title": "Reading", "body": "a novel about pears"}))
+        .await
+        .unwrap();
+
+    let results = client.local_search("oranges").await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, target.id);
+
+    // Deleting the document drops it from the index
+    client.delete_document(target.id).await.unwrap();
+    assert!(client.local_search("oranges").await.unwrap().is_empty());
+}
@@ -0,0 +1,54 @@
+//! # Initialization Tests
+//!
+//! Tests for how a database without a configured user is reported: the
+//! identity lookups fail with `SyncError::NotInitialized` rather than a raw
+//! SQL error, so apps know to run first-time setup.
+
+mod common;
+
+use common::setup_test_db;
+use replicant_core::SyncError;
+
+#[tokio::test]
+async fn test_fresh_database_is_not_initialized() {
+    let db = setup_test_db().await;
+
+    assert!(!db.is_initialized().await.unwrap());
+    assert!(matches!(
+        db.get_user_id().await,
+        Err(SyncError::NotInitialized)
+    ));
+    assert!(matches!(
+        db.get_client_id().await,
+        Err(SyncError::NotInitialized)
+    ));
+    assert!(matches!(
+        db.get_user_and_client_id().await,
+        Err(SyncError::NotInitialized)
+    ));
+
+    db.ensure_user_config("ws://localhost:8080/ws")
+        .await
+        .unwrap();
+    assert!(db.is_initialized().await.unwrap());
+    assert!(db.get_user_id().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_corrupted_user_config_is_not_initialized() {
+    let db = setup_test_db().await;
+    db.ensure_user_config("ws://localhost:8080/ws")
+        .await
+        .unwrap();
+
+    sqlx::query("UPDATE user_config SET user_id = 'not-a-uuid'")
+        .execute(&db.pool)
+        .await
+        .unwrap();
+
+    assert!(!db.is_initialized().await.unwrap());
+    assert!(matches!(
+        db.get_user_id().await,
+        Err(SyncError::NotInitialized)
+    ));
+}
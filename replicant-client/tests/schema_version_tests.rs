@@ -0,0 +1,79 @@
+//! # Schema Version Tests
+//!
+//! Tests for the schema version guard in `run_migrations`, which protects a
+//! database from being opened by an older build after the app is downgraded.
+
+use replicant_client::ClientDatabase;
+use replicant_core::SyncError;
+use uuid::Uuid;
+
+/// Opens a named in-memory database shared by every handle to the same name,
+/// so a second handle behaves like reopening the database.
+async fn open(name: Uuid) -> ClientDatabase {
+    ClientDatabase::new(&format!("file:{}?mode=memory&cache=shared", name))
+        .await
+        .unwrap()
+}
+
+async fn set_version(db: &ClientDatabase, version: i64) {
+    sqlx::query("UPDATE schema_version SET version = ?1")
+        .bind(version)
+        .execute(&db.pool)
+        .await
+        .unwrap();
+}
+
+/// Migrating a database already at the current version is a no-op.
+#[tokio::test]
+async fn test_equal_schema_version_migrates() {
+    let name = Uuid::new_v4();
+    let db = open(name).await;
+    assert_eq!(db.schema_version().await.unwrap(), 0);
+
+    db.run_migrations().await.unwrap();
+    let supported = ClientDatabase::supported_schema_version();
+    assert_eq!(db.schema_version().await.unwrap(), supported);
+
+    let reopened = open(name).await;
+    reopened.run_migrations().await.unwrap();
+    assert_eq!(reopened.schema_version().await.unwrap(), supported);
+}
+
+/// A database written by an older build is upgraded to the current version.
+#[tokio::test]
+async fn test_older_schema_version_is_upgraded() {
+    let name = Uuid::new_v4();
+    let db = open(name).await;
+    db.run_migrations().await.unwrap();
+    set_version(&db, 1).await;
+
+    let reopened = open(name).await;
+    reopened.run_migrations().await.unwrap();
+    assert_eq!(
+        reopened.schema_version().await.unwrap(),
+        ClientDatabase::supported_schema_version()
+    );
+}
+
+/// A database written by a newer build is refused and left untouched.
+#[tokio::test]
+async fn test_newer_schema_version_is_refused() {
+    let name = Uuid::new_v4();
+    let db = open(name).await;
+    db.run_migrations().await.unwrap();
+    let supported = ClientDatabase::supported_schema_version();
+    set_version(&db, supported + 1).await;
+
+    let reopened = open(name).await;
+    match reopened.run_migrations().await {
+        Err(SyncError::SchemaTooNew {
+            found,
+            supported: max,
+        }) => {
+            assert_eq!(found, supported + 1);
+            assert_eq!(max, supported);
+        }
+        other => panic!("Expected SchemaTooNew, got {:?}", other),
+    }
+    assert_eq!(reopened.schema_version().await.unwrap(), supported + 1);
+}
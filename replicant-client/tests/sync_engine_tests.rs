@@ -1,13 +1,16 @@
 mod common;
 
 use futures_util::{SinkExt, StreamExt};
-use replicant_client::{Client, ClientDatabase};
+use replicant_client::{
+    AesGcmCipher, Client, ClientConfig, ClientDatabase, DocumentStore, MemoryStore, SyncTimeouts,
+};
 use replicant_core::protocol::{ClientMessage, ServerMessage};
 use replicant_core::ConflictResolution;
 use serde_json::json;
 use sqlx::Row;
 use std::net::SocketAddr;
 use std::os::fd::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpListener;
@@ -29,6 +32,8 @@ struct MockServer {
     _listener_fd: RawFd,
     // Stop signal for listener threads
     shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    // OperationBatch frames received; their operations are delivered one by one
+    pub batches_received: Arc<AtomicUsize>,
 }
 
 impl MockServer {
@@ -48,6 +53,7 @@ impl MockServer {
             from_client_rx,
             _listener_fd: fd,
             shutdown_tx: None,
+            batches_received: Arc::new(AtomicUsize::new(0)),
         }
     }
     pub async fn stop(&mut self) {
@@ -68,6 +74,7 @@ impl MockServer {
         self.from_client_rx = from_client_rx;
         let (shutdown_tx, mut shutdown_rx) = tokio::sync::oneshot::channel();
         self.shutdown_tx = Some(shutdown_tx);
+        let batches_received = self.batches_received.clone();
         self.handle = Some(tokio::spawn(async move {
             if let Ok((stream, _)) = listener.accept().await {
                 let (mut ws_tx, mut ws_rx) = accept_async(stream).await.unwrap().split();
@@ -96,10 +103,21 @@ impl MockServer {
                     loop {
                         if let Some(Ok(msg)) = ws_rx.next().await {
                             if let Message::Text(text) = msg {
-                                if let Ok(client_msg) = serde_json::from_str(&text) {
-                                    if let Err(e) = from_client_tx.send(client_msg).await {
-                                        println!("{:?}", e)
+                                match serde_json::from_str(&text) {
+                                    Ok(ClientMessage::OperationBatch { operations }) => {
+                                        batches_received.fetch_add(1, Ordering::SeqCst);
+                                        for operation in operations {
+                                            if let Err(e) = from_client_tx.send(operation).await {
+                                                println!("{:?}", e)
+                                            }
+                                        }
                                     }
+                                    Ok(client_msg) => {
+                                        if let Err(e) = from_client_tx.send(client_msg).await {
+                                            println!("{:?}", e)
+                                        }
+                                    }
+                                    Err(e) => println!("Dropping malformed message: {}", e),
                                 }
                             } else if msg.is_close() {
                                 break;
@@ -136,6 +154,10 @@ struct TestSetup {
 
 /// Creates a new Client connected to an in-memory database and a mock server.
 async fn setup() -> TestSetup {
+    setup_with_config(ClientConfig::default()).await
+}
+
+async fn setup_with_config(config: ClientConfig) -> TestSetup {
     // Use a unique database for each test to ensure isolation
     let db_id = Uuid::new_v4();
     let db = Arc::new(
@@ -152,12 +174,13 @@ async fn setup() -> TestSetup {
     let api_key = "test-key";
     let api_secret = "test-secret";
 
-    let engine = Client::new(
+    let engine = Client::with_config(
         &format!("file:{}?mode=memory&cache=shared", db_id),
         &server_url,
         email,
         api_key,
         api_secret,
+        config,
     )
     .await
     .unwrap();
@@ -191,7 +214,7 @@ async fn test_create_document_online() {
     // 1. Verify a `CreateDocument` message was sent to the server.
     let create_msg = setup.server.expect_client_message().await;
     match create_msg {
-        ClientMessage::CreateDocument { document } => {
+        ClientMessage::CreateDocument { document, .. } => {
             assert_eq!(document.id, doc.id);
             assert_eq!(document.content, content);
         }
@@ -215,6 +238,10 @@ async fn test_create_document_online() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -224,6 +251,42 @@ async fn test_create_document_online() {
     assert!(pending_docs_after.is_empty());
 }
 
+/// The server's clock is authoritative, so a skewed local timestamp is replaced by the one in
+/// the create response
+#[tokio::test]
+async fn test_create_response_applies_server_timestamps() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // consume auth
+    let _ = setup.server.expect_client_message().await; // consume sync
+
+    let doc = setup
+        .engine
+        .create_document(serde_json::json!({ "title": "Skewed" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // consume create
+
+    // Pretend the local clock is an hour behind the server
+    let server_time = doc.created_at + chrono::Duration::hours(1);
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: Some(server_time),
+            updated_at: Some(server_time),
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.created_at, server_time);
+    assert_eq!(local_doc.updated_at, server_time);
+}
+
 /// Tests the flow for document creation -> sync -> document update -> sync between a client server
 /// pair
 #[tokio::test]
@@ -247,6 +310,10 @@ async fn test_update_document_online() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -262,7 +329,7 @@ async fn test_update_document_online() {
     // 3. Verify an `UpdateDocument` message with patch was sent.
     let update_msg = setup.server.expect_client_message().await;
     match update_msg {
-        ClientMessage::UpdateDocument { patch } => {
+        ClientMessage::UpdateDocument { patch, .. } => {
             assert_eq!(patch.document_id, doc.id);
             // Should have a patch with operations
             assert!(!patch.patch.0.is_empty(), "Patch should contain operations");
@@ -282,10 +349,232 @@ async fn test_update_document_online() {
             success: true,
             error: None,
             sync_revision: Some(2),
+            sequence: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+}
+
+/// A broadcast patch that doesn't fit the local document is recovered from by
+/// fetching the whole document
+#[tokio::test]
+async fn test_failed_patch_falls_back_to_full_document() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::models::DocumentPatch;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let errors_clone = errors.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::SyncError { message } = event {
+                errors_clone.lock().unwrap().push(message);
+            }
+        })
+        .unwrap();
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Diverged" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // consume create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The patch replaces a path the local copy doesn't have
+    let patch: json_patch::Patch = serde_json::from_value(json!([
+        { "op": "replace", "path": "/missing/path", "value": "x" }
+    ]))
+    .unwrap();
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentUpdated {
+            patch: DocumentPatch {
+                document_id: doc.id,
+                patch,
+                content_hash: String::new(),
+            },
+        })
+        .await;
+
+    match setup.server.expect_client_message().await {
+        ClientMessage::RequestDocument { document_id } => assert_eq!(document_id, doc.id),
+        other => panic!("Expected RequestDocument, got {:?}", other),
+    }
+    let server_content = json!({ "title": "Diverged", "missing": { "path": "x" } });
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: replicant_core::models::Document {
+                content: server_content.clone(),
+                sync_revision: 2,
+                ..doc.clone()
+            },
+            relayed: false,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local.content, server_content);
+    assert_eq!(local.sync_revision, 2);
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert!(errors
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|message| message.contains("couldn't be applied")));
+}
+
+/// Updating a document to identical content is a no-op
+#[tokio::test]
+async fn test_update_with_identical_content_is_skipped() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let content = json!({ "title": "Unchanged" });
+    let doc = setup.engine.create_document(content.clone()).await.unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+
+    setup.engine.update_document(doc.id, content).await.unwrap();
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+}
+
+/// Previewing an update returns its patch without changing anything
+#[tokio::test]
+async fn test_preview_update_has_no_side_effects() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let content = json!({ "title": "Draft", "body": "Text" });
+    let doc = setup.engine.create_document(content.clone()).await.unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let patch = setup
+        .engine
+        .preview_update(doc.id, &json!({ "title": "Final", "body": "Text" }))
+        .await
+        .unwrap();
+    let expected: json_patch::Patch =
+        serde_json::from_value(json!([{ "op": "replace", "path": "/title", "value": "Final" }]))
+            .unwrap();
+    assert_eq!(patch, expected);
+
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, content);
     assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+
+    // Unknown documents are an error, as with update_document
+    assert!(setup
+        .engine
+        .preview_update(Uuid::new_v4(), &content)
+        .await
+        .is_err());
+}
+
+/// A partial update sends the caller's patch as-is
+#[tokio::test]
+async fn test_patch_document_sends_given_patch() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Original", "body": "Unchanged" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let patch: json_patch::Patch =
+        serde_json::from_value(json!([{ "op": "replace", "path": "/title", "value": "Patched" }]))
+            .unwrap();
+    let patched = setup
+        .engine
+        .patch_document(doc.id, patch.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        patched.content,
+        json!({ "title": "Patched", "body": "Unchanged" })
+    );
+
+    match setup.server.expect_client_message().await {
+        ClientMessage::UpdateDocument { patch: sent, .. } => {
+            assert_eq!(sent.document_id, doc.id);
+            assert_eq!(sent.patch, patch);
+        }
+        other => panic!("Expected UpdateDocument, got {:?}", other),
+    }
+
+    // A patch that doesn't apply leaves the document untouched
+    let bad: json_patch::Patch =
+        serde_json::from_value(json!([{ "op": "remove", "path": "/missing" }])).unwrap();
+    assert!(setup.engine.patch_document(doc.id, bad).await.is_err());
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, patched.content);
 }
 
 /// Tests the flow for document creation -> sync -> document update -> sync between a client server
@@ -309,6 +598,10 @@ async fn test_delete_document_online() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -331,6 +624,7 @@ async fn test_delete_document_online() {
             document_id: doc.id,
             success: true,
             error: None,
+            sequence: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -369,7 +663,7 @@ async fn test_offline_document_creation_and_sync() {
     // 5. Should auto-sync the pending document
     let create_msg = setup.server.expect_client_message().await;
     match create_msg {
-        ClientMessage::CreateDocument { document } => {
+        ClientMessage::CreateDocument { document, .. } => {
             assert_eq!(document.id, doc.id);
         }
         _ => panic!("Expected CreateDocument after reconnect"),
@@ -382,6 +676,10 @@ async fn test_offline_document_creation_and_sync() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -412,6 +710,10 @@ async fn test_client_reconnect_sync_document_online() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -442,6 +744,7 @@ async fn test_client_reconnect_sync_document_online() {
             document_id: doc.id,
             success: true,
             error: None,
+            sequence: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -481,6 +784,7 @@ async fn test_sync_protection_mode_blocks_server_updates() {
         .server
         .send_server_message(ServerMessage::SyncDocument {
             document: server_doc.clone(),
+            relayed: false,
         })
         .await;
 
@@ -491,6 +795,10 @@ async fn test_sync_protection_mode_blocks_server_updates() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -522,6 +830,10 @@ async fn test_receive_server_document_sync() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
@@ -535,6 +847,7 @@ async fn test_receive_server_document_sync() {
         .server
         .send_server_message(ServerMessage::SyncDocument {
             document: updated_doc.clone(),
+            relayed: false,
         })
         .await;
 
@@ -545,6 +858,65 @@ async fn test_receive_server_document_sync() {
     assert_eq!(local_doc.content["version"], json!(2));
 }
 
+/// Content is sealed before upload and opened again on receipt
+#[tokio::test]
+async fn test_encrypted_content_round_trip() {
+    let key = [9u8; 32];
+    let mut setup = setup_with_config(ClientConfig {
+        cipher: Some(Arc::new(AesGcmCipher::new(&key))),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let content = json!({ "title": "Top secret" });
+    let doc = setup.engine.create_document(content.clone()).await.unwrap();
+
+    // 1. The server only sees the envelope
+    let mut uploaded = match setup.server.expect_client_message().await {
+        ClientMessage::CreateDocument { document, .. } => document,
+        other => panic!("Expected CreateDocument, got {:?}", other),
+    };
+    assert!(uploaded.content["ciphertext"].is_string());
+    assert!(!uploaded.content.to_string().contains("Top secret"));
+
+    // 2. Local storage stays plaintext
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, content);
+
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 3. A sealed document from the server is stored decrypted
+    let new_content = json!({ "title": "Still secret" });
+    uploaded.content =
+        replicant_client::encryption::seal(&AesGcmCipher::new(&key), &new_content).unwrap();
+    uploaded.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: uploaded,
+            relayed: false,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, new_content);
+}
+
 /// Test handling failed document creation response
 #[tokio::test]
 async fn test_create_document_failure_response() {
@@ -566,6 +938,10 @@ async fn test_create_document_failure_response() {
             document_id: doc.id,
             success: false,
             error: Some("Validation failed".to_string()),
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -627,6 +1003,10 @@ async fn test_multiple_offline_documents_sync() {
                 document_id: doc.id,
                 success: true,
                 error: None,
+                sync_revision: None,
+                sequence: None,
+                created_at: None,
+                updated_at: None,
             })
             .await;
     }
@@ -747,7 +1127,7 @@ async fn test_offline_document_creation_with_reconnection_sync() {
     for _ in 0..3 {
         let msg = setup.server.expect_client_message().await;
         match msg {
-            ClientMessage::CreateDocument { document } => {
+            ClientMessage::CreateDocument { document, .. } => {
                 received_docs.push(document.id);
             }
             _ => panic!("Expected CreateDocument, got {:?}", msg),
@@ -767,6 +1147,10 @@ async fn test_offline_document_creation_with_reconnection_sync() {
                 document_id: doc_id,
                 success: true,
                 error: None,
+                sync_revision: None,
+                sequence: None,
+                created_at: None,
+                updated_at: None,
             })
             .await;
     }
@@ -800,6 +1184,10 @@ async fn test_offline_update_with_patch_recovery() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -840,7 +1228,7 @@ async fn test_offline_update_with_patch_recovery() {
     // 8. Should receive UpdateDocument with stored patch (hits lines 1323-1369)
     let update_msg = setup.server.expect_client_message().await;
     match update_msg {
-        ClientMessage::UpdateDocument { patch } => {
+        ClientMessage::UpdateDocument { patch, .. } => {
             assert_eq!(patch.document_id, doc.id);
             println!("✅ Received UpdateDocument with stored patch");
         }
@@ -855,6 +1243,8 @@ async fn test_offline_update_with_patch_recovery() {
             success: true,
             error: None,
             sync_revision: Some(2),
+            sequence: None,
+            updated_at: None,
         })
         .await;
 
@@ -898,6 +1288,10 @@ async fn test_offline_delete_sync_on_reconnection() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -935,6 +1329,7 @@ async fn test_offline_delete_sync_on_reconnection() {
             document_id: doc.id,
             success: true,
             error: None,
+            sequence: None,
         })
         .await;
 
@@ -946,28 +1341,45 @@ async fn test_offline_delete_sync_on_reconnection() {
     println!("✅ OFFLINE DELETE TEST: Successfully synced offline delete");
 }
 
-/// Tests mixed offline operations (creates, updates, deletes)
+/// Tests mixed offline operations (creates, updates, deletes) replay in order
 #[tokio::test]
 async fn test_mixed_offline_operations_sync() {
     let mut setup = setup().await;
     let _ = setup.server.expect_client_message().await; // auth
     let _ = setup.server.expect_client_message().await; // sync
 
-    // 1. Create doc1 online and sync it
-    let doc1 = setup
+    // 1. Create doc1 and doc6 online and sync them
+    let mut synced = Vec::new();
+    for id in [1, 6] {
+        let doc = setup
+            .engine
+            .create_document(json!({ "id": id }))
+            .await
+            .unwrap();
+        let _ = setup.server.expect_client_message().await;
+        setup
+            .server
+            .send_server_message(ServerMessage::DocumentCreatedResponse {
+                document_id: doc.id,
+                success: true,
+                error: None,
+                sync_revision: None,
+                sequence: None,
+                created_at: None,
+                updated_at: None,
+            })
+            .await;
+        synced.push(doc);
+    }
+    let (doc1, doc6) = (&synced[0], &synced[1]);
+
+    // doc7's create reaches the server but the confirmation never arrives
+    let doc7 = setup
         .engine
-        .create_document(json!({ "id": 1 }))
+        .create_document(json!({ "id": 7 }))
         .await
         .unwrap();
     let _ = setup.server.expect_client_message().await;
-    setup
-        .server
-        .send_server_message(ServerMessage::DocumentCreatedResponse {
-            document_id: doc1.id,
-            success: true,
-            error: None,
-        })
-        .await;
     tokio::time::sleep(Duration::from_millis(100)).await;
 
     // 2. Go offline
@@ -975,7 +1387,7 @@ async fn test_mixed_offline_operations_sync() {
     tokio::time::sleep(Duration::from_millis(200)).await;
 
     // 3. Mixed operations:
-    let _doc2 = setup
+    let doc2 = setup
         .engine
         .create_document(json!({ "id": 2 }))
         .await
@@ -986,83 +1398,124 @@ async fn test_mixed_offline_operations_sync() {
         .await
         .unwrap(); // Update existing
     setup.engine.delete_document(doc1.id).await.unwrap(); // Delete the updated one
-    let _doc3 = setup
+    let doc3 = setup
         .engine
         .create_document(json!({ "id": 3 }))
         .await
         .unwrap(); // Another create
+    let doc4 = setup
+        .engine
+        .create_document(json!({ "id": 4 }))
+        .await
+        .unwrap();
+    setup
+        .engine
+        .update_document(doc4.id, json!({ "id": 4, "updated": true }))
+        .await
+        .unwrap(); // Update a document the server hasn't seen
+    let doc5 = setup
+        .engine
+        .create_document(json!({ "id": 5 }))
+        .await
+        .unwrap();
+    setup.engine.delete_document(doc5.id).await.unwrap(); // Gone before the server saw it
+    setup.engine.delete_document(doc6.id).await.unwrap();
+    setup
+        .engine
+        .create_document_with_id(doc6.id, json!({ "id": 6, "recreated": true }), false)
+        .await
+        .unwrap();
+    setup
+        .engine
+        .update_document(
+            doc6.id,
+            json!({ "id": 6, "recreated": true, "updated": true }),
+        )
+        .await
+        .unwrap(); // Update the recreated document
+    setup.engine.delete_document(doc7.id).await.unwrap(); // The server may have it
 
-    // 4. Should have 3 pending (doc2 create, doc1 delete, doc3 create)
-    let pending = setup.engine.count_pending_sync().await.unwrap();
-    assert!(
-        pending >= 2,
-        "Should have at least 2 pending operations, got {}",
-        pending
-    );
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 7);
 
-    // 5. Reconnect
+    // 4. Reconnect
     setup.server.start().await;
     tokio::time::sleep(Duration::from_millis(4000)).await;
     let _ = setup.server.expect_client_message().await; // auth
 
-    // 6. Collect all sync messages
-    let mut creates = 0;
-    let mut deletes = 0;
-    let mut updates = 0;
-
-    for _ in 0..5 {
-        if let Ok(msg) = tokio::time::timeout(
-            Duration::from_millis(500),
-            setup.server.expect_client_message(),
-        )
-        .await
-        {
-            match msg {
-                ClientMessage::CreateDocument { document } => {
-                    creates += 1;
-                    setup
-                        .server
-                        .send_server_message(ServerMessage::DocumentCreatedResponse {
-                            document_id: document.id,
-                            success: true,
-                            error: None,
-                        })
-                        .await;
-                }
-                ClientMessage::UpdateDocument { patch } => {
-                    updates += 1;
-                    setup
-                        .server
-                        .send_server_message(ServerMessage::DocumentUpdatedResponse {
-                            document_id: patch.document_id,
-                            success: true,
-                            error: None,
-                            sync_revision: Some(2),
-                        })
-                        .await;
-                }
-                ClientMessage::DeleteDocument { document_id, .. } => {
-                    deletes += 1;
-                    setup
-                        .server
-                        .send_server_message(ServerMessage::DocumentDeletedResponse {
-                            document_id,
-                            success: true,
-                            error: None,
-                        })
-                        .await;
-                }
-                _ => {}
+    // 5. Collect all sync messages in the order they arrive
+    let mut received = Vec::new();
+    while let Ok(msg) = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await
+    {
+        match msg {
+            ClientMessage::CreateDocument { document, .. } => {
+                received.push(("create", document.id, Some(document.content)));
+                setup
+                    .server
+                    .send_server_message(ServerMessage::DocumentCreatedResponse {
+                        document_id: document.id,
+                        success: true,
+                        error: None,
+                        sync_revision: None,
+                        sequence: None,
+                        created_at: None,
+                        updated_at: None,
+                    })
+                    .await;
+            }
+            ClientMessage::UpdateDocument { patch, .. } => {
+                received.push(("update", patch.document_id, None));
+                setup
+                    .server
+                    .send_server_message(ServerMessage::DocumentUpdatedResponse {
+                        document_id: patch.document_id,
+                        success: true,
+                        error: None,
+                        sync_revision: Some(2),
+                        sequence: None,
+                        updated_at: None,
+                    })
+                    .await;
+            }
+            ClientMessage::DeleteDocument { document_id, .. } => {
+                received.push(("delete", document_id, None));
+                setup
+                    .server
+                    .send_server_message(ServerMessage::DocumentDeletedResponse {
+                        document_id,
+                        success: true,
+                        error: None,
+                        sequence: None,
+                    })
+                    .await;
             }
+            _ => {}
         }
     }
 
-    println!(
-        "✅ MIXED OPERATIONS: creates={}, updates={}, deletes={}",
-        creates, updates, deletes
+    // Documents replay in the order of their latest edit. Documents the
+    // server never saw go up as creates with their edits folded in, the
+    // created-then-deleted doc5 is never sent, the recreated doc6
+    // replaces the server's copy in one create, and doc7, whose create was
+    // sent without a confirmation, is deleted on the server.
+    assert_eq!(
+        received,
+        vec![
+            ("create", doc2.id, Some(json!({ "id": 2 }))),
+            ("delete", doc1.id, None),
+            ("create", doc3.id, Some(json!({ "id": 3 }))),
+            ("create", doc4.id, Some(json!({ "id": 4, "updated": true }))),
+            (
+                "create",
+                doc6.id,
+                Some(json!({ "id": 6, "recreated": true, "updated": true }))
+            ),
+            ("delete", doc7.id, None),
+        ]
     );
-    assert!(creates >= 2, "Should have at least 2 creates");
-    assert!(deletes >= 1, "Should have at least 1 delete");
 
     tokio::time::sleep(Duration::from_millis(300)).await;
 
@@ -1110,24 +1563,32 @@ async fn test_partial_upload_failure() {
     let _msg3 = setup.server.expect_client_message().await;
 
     // Confirm ONLY 2 out of 3 (simulate partial failure)
-    if let ClientMessage::CreateDocument { document } = msg1 {
+    if let ClientMessage::CreateDocument { document, .. } = msg1 {
         setup
             .server
             .send_server_message(ServerMessage::DocumentCreatedResponse {
                 document_id: document.id,
                 success: true,
                 error: None,
+                sync_revision: None,
+                sequence: None,
+                created_at: None,
+                updated_at: None,
             })
             .await;
     }
 
-    if let ClientMessage::CreateDocument { document } = msg2 {
+    if let ClientMessage::CreateDocument { document, .. } = msg2 {
         setup
             .server
             .send_server_message(ServerMessage::DocumentCreatedResponse {
                 document_id: document.id,
                 success: true,
                 error: None,
+                sync_revision: None,
+                sequence: None,
+                created_at: None,
+                updated_at: None,
             })
             .await;
     }
@@ -1164,6 +1625,10 @@ async fn test_upload_failure_response() {
             document_id: doc.id,
             success: false,
             error: Some("Server validation failed".to_string()),
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
 
@@ -1195,6 +1660,10 @@ async fn test_server_sends_document_updated_patch() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -1249,6 +1718,8 @@ async fn test_server_sends_new_document_created() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
     };
 
     setup
@@ -1267,6 +1738,234 @@ async fn test_server_sends_new_document_created() {
     println!("✅ SERVER CREATE TEST: Received and stored DocumentCreated from server");
 }
 
+/// Tests that an older server version is skipped with a SyncSkipped event
+#[tokio::test]
+async fn test_older_server_version_emits_sync_skipped() {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let skipped = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let skipped_clone = skipped.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::SyncSkipped { .. } = event {
+                skipped_clone.lock().unwrap().push(event);
+            }
+        })
+        .unwrap();
+
+    let (user_id, _) = setup.db.get_user_and_client_id().await.unwrap();
+    let local_doc = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id,
+        content: json!({ "title": "Local v3" }),
+        sync_revision: 3,
+        content_hash: None,
+        title: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+    setup
+        .db
+        .save_document_with_status(&local_doc, Some(replicant_core::models::SyncStatus::Synced))
+        .await
+        .unwrap();
+
+    let mut older = local_doc.clone();
+    older.content = json!({ "title": "Server v2" });
+    older.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: older,
+            relayed: false,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The local copy is kept
+    let stored = setup.db.get_document(&local_doc.id).await.unwrap();
+    assert_eq!(stored.content["title"], "Local v3");
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    let skipped = skipped.lock().unwrap();
+    assert_eq!(skipped.len(), 1);
+    match &skipped[0] {
+        SyncEvent::SyncSkipped {
+            document_id,
+            local_revision,
+            server_revision,
+            reason,
+        } => {
+            assert_eq!(*document_id, local_doc.id.to_string());
+            assert_eq!(*local_revision, 3);
+            assert_eq!(*server_revision, 2);
+            assert_eq!(reason, "Local revision is newer than the server version");
+        }
+        other => panic!("Expected SyncSkipped, got {:?}", other),
+    }
+}
+
+/// Tests that created events say whether the document came from this client,
+/// another client or the server
+#[tokio::test]
+async fn test_created_events_carry_document_source() {
+    use replicant_client::events::{DocumentSource, SyncEvent};
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let sources = Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let sources_clone = sources.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::DocumentCreated { id, source, .. } = event {
+                sources_clone.lock().unwrap().insert(id, source);
+            }
+        })
+        .unwrap();
+
+    let (user_id, _) = setup.db.get_user_and_client_id().await.unwrap();
+    let remote_document = |title: &str| replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id,
+        content: json!({ "title": title }),
+        sync_revision: 1,
+        content_hash: None,
+        title: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+
+    // Relayed from another device as it was created
+    let from_client = remote_document("From another device");
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreated {
+            document: from_client.clone(),
+        })
+        .await;
+    // Pushed by the server, e.g. created through the admin API
+    let from_server = remote_document("From the server");
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: from_server.clone(),
+            relayed: false,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let local = setup
+        .engine
+        .create_document(json!({ "title": "Local" }))
+        .await
+        .unwrap();
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    let sources = sources.lock().unwrap();
+    assert_eq!(
+        sources.get(&from_client.id.to_string()),
+        Some(&DocumentSource::RemoteClient)
+    );
+    assert_eq!(
+        sources.get(&from_server.id.to_string()),
+        Some(&DocumentSource::Server)
+    );
+    assert_eq!(
+        sources.get(&local.id.to_string()),
+        Some(&DocumentSource::Local)
+    );
+}
+
+/// Test another device's edits are told apart from the server's own copy
+#[tokio::test]
+async fn test_relayed_updates_carry_remote_client_source() {
+    use replicant_client::events::{DocumentSource, SyncEvent};
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let sources = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sources_clone = sources.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::DocumentUpdated { title, source, .. } = event {
+                sources_clone.lock().unwrap().push((title, source));
+            }
+        })
+        .unwrap();
+
+    let (user_id, _) = setup.db.get_user_and_client_id().await.unwrap();
+    let mut document = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id,
+        content: json!({ "title": "Original" }),
+        sync_revision: 1,
+        content_hash: None,
+        title: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreated {
+            document: document.clone(),
+        })
+        .await;
+
+    // Edited on another device and passed on by the server
+    document.content = json!({ "title": "Edited elsewhere" });
+    document.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: document.clone(),
+            relayed: true,
+        })
+        .await;
+    // The server's copy, as sent in a sync
+    document.content = json!({ "title": "Synced" });
+    document.sync_revision = 3;
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: document.clone(),
+            relayed: false,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(
+        *sources.lock().unwrap(),
+        vec![
+            ("Edited elsewhere".to_string(), DocumentSource::RemoteClient),
+            ("Synced".to_string(), DocumentSource::Server),
+        ]
+    );
+}
+
 /// Tests server sending DocumentDeleted
 #[tokio::test]
 async fn test_server_sends_document_deleted() {
@@ -1287,6 +1986,10 @@ async fn test_server_sends_document_deleted() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -1361,6 +2064,10 @@ async fn test_sync_document_generation_comparison() {
             document_id: doc.id,
             success: true,
             error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
         })
         .await;
     tokio::time::sleep(Duration::from_millis(200)).await;
@@ -1374,6 +2081,7 @@ async fn test_sync_document_generation_comparison() {
         .server
         .send_server_message(ServerMessage::SyncDocument {
             document: server_doc.clone(),
+            relayed: false,
         })
         .await;
 
@@ -1414,6 +2122,7 @@ async fn test_sync_document_rejects_older_version() {
         .server
         .send_server_message(ServerMessage::SyncDocument {
             document: old_server_doc,
+            relayed: false,
         })
         .await;
 
@@ -1447,3 +2156,3573 @@ async fn test_update_nonexistent_document() {
     );
     println!("✅ NOT FOUND TEST: Correctly handled missing document");
 }
+
+/// Tests that a rejected update is rebased onto the server state and resubmitted once
+#[tokio::test]
+async fn test_update_rejected_rebases_and_resubmits() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Shared", "status": "todo", "owner": "nobody" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // This client edits "status" while another client has already changed
+    // "status" and "owner" on the server
+    setup
+        .engine
+        .update_document(
+            doc.id,
+            json!({ "title": "Shared", "status": "done", "owner": "nobody" }),
+        )
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // UpdateDocument
+
+    use replicant_core::patches::{apply_patch, calculate_checksum};
+
+    let mut server_document = doc.clone();
+    server_document.content = json!({ "title": "Shared", "status": "in_progress", "owner": "bob" });
+    server_document.sync_revision = 2;
+
+    setup
+        .server
+        .send_server_message(ServerMessage::UpdateRejected {
+            document_id: doc.id,
+            server_document: server_document.clone(),
+        })
+        .await;
+
+    // The client should resubmit a patch derived against the server content
+    let expected = json!({ "title": "Shared", "status": "done", "owner": "bob" });
+    match setup.server.expect_client_message().await {
+        ClientMessage::UpdateDocument { patch, .. } => {
+            assert_eq!(patch.document_id, doc.id);
+            assert_eq!(
+                patch.content_hash,
+                calculate_checksum(&server_document.content)
+            );
+            let mut rebased = server_document.content.clone();
+            apply_patch(&mut rebased, &patch.patch).unwrap();
+            assert_eq!(rebased, expected);
+        }
+        other => panic!("Expected rebased UpdateDocument, got {:?}", other),
+    }
+
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, expected);
+    assert_eq!(local_doc.sync_revision, 2);
+
+    // A second rejection is not retried automatically
+    setup
+        .server
+        .send_server_message(ServerMessage::UpdateRejected {
+            document_id: doc.id,
+            server_document,
+        })
+        .await;
+    let resubmitted = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await;
+    assert!(resubmitted.is_err(), "Should only resubmit once");
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 1);
+}
+
+/// A rejected update whose edits no longer apply to the server state is kept
+/// as a conflict instead of overwriting the server with the local copy
+#[tokio::test]
+async fn test_update_rejected_unrebasable_edit_becomes_conflict() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::models::SyncStatus;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let conflicts = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let conflicts_clone = conflicts.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::ConflictDetected { document_id, .. } = event {
+                conflicts_clone.lock().unwrap().push(document_id);
+            }
+        })
+        .unwrap();
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Shared", "status": "todo" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Shared", "status": "done" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // UpdateDocument
+
+    // Another client removed the field this edit replaces
+    let mut server_document = doc.clone();
+    server_document.content = json!({ "title": "Shared" });
+    server_document.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::UpdateRejected {
+            document_id: doc.id,
+            server_document: server_document.clone(),
+        })
+        .await;
+
+    let resubmitted = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await;
+    assert!(resubmitted.is_err(), "Should not resubmit the local copy");
+
+    let conflict = setup.db.get_conflict(&doc.id).await.unwrap().unwrap();
+    assert_eq!(
+        conflict.local_content,
+        json!({ "title": "Shared", "status": "done" })
+    );
+    assert_eq!(conflict.server_content, server_document.content);
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, server_document.content);
+    assert_eq!(
+        setup.db.get_sync_status(&doc.id).await.unwrap(),
+        SyncStatus::Conflict
+    );
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(*conflicts.lock().unwrap(), vec![doc.id.to_string()]);
+}
+
+/// A ServerWins client drops its conflicting edit and adopts the server state
+#[tokio::test]
+async fn test_server_wins_conflict_discards_local_edit() {
+    let mut setup = setup_with_config(ClientConfig {
+        conflict_resolution: Some(ConflictResolution::ServerWins),
+        ..Default::default()
+    })
+    .await;
+    match setup.server.expect_client_message().await {
+        ClientMessage::Authenticate {
+            conflict_resolution,
+            ..
+        } => assert!(matches!(
+            conflict_resolution,
+            Some(ConflictResolution::ServerWins)
+        )),
+        other => panic!("Expected Authenticate, got {:?}", other),
+    }
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Shared" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Mine" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // UpdateDocument
+
+    let mut server_document = doc.clone();
+    server_document.content = json!({ "title": "Theirs" });
+    server_document.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::ConflictDetected {
+            document_id: doc.id,
+            resolution_strategy: ConflictResolution::ServerWins,
+        })
+        .await;
+    setup
+        .server
+        .send_server_message(ServerMessage::UpdateRejected {
+            document_id: doc.id,
+            server_document,
+        })
+        .await;
+
+    // Nothing is resubmitted
+    let resubmitted = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await;
+    assert!(resubmitted.is_err(), "Should not resubmit a losing edit");
+
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, json!({ "title": "Theirs" }));
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+}
+
+/// A lost conflict is kept for the app and settled with resolve_conflict
+#[tokio::test]
+async fn test_conflict_manual_resolution() {
+    use replicant_core::models::SyncStatus;
+
+    let mut setup = setup_with_config(ClientConfig {
+        conflict_resolution: Some(ConflictResolution::ServerWins),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Shared" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Mine" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // UpdateDocument
+
+    let mut server_document = doc.clone();
+    server_document.content = json!({ "title": "Theirs" });
+    server_document.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::ConflictDetected {
+            document_id: doc.id,
+            resolution_strategy: ConflictResolution::ServerWins,
+        })
+        .await;
+    setup
+        .server
+        .send_server_message(ServerMessage::UpdateRejected {
+            document_id: doc.id,
+            server_document,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Both sides are kept and the document is flagged
+    let conflict = setup.db.get_conflict(&doc.id).await.unwrap().unwrap();
+    assert_eq!(conflict.local_content, json!({ "title": "Mine" }));
+    assert_eq!(conflict.server_content, json!({ "title": "Theirs" }));
+    assert_eq!(
+        setup.db.get_sync_status(&doc.id).await.unwrap(),
+        SyncStatus::Conflict
+    );
+
+    // The app merges them and resolves
+    let merged = json!({ "title": "Mine and Theirs" });
+    setup
+        .engine
+        .resolve_conflict(doc.id, merged.clone())
+        .await
+        .unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::ResolveConflict {
+            document_id,
+            content,
+        } => {
+            assert_eq!(document_id, doc.id);
+            assert_eq!(content, merged);
+        }
+        other => panic!("Expected ResolveConflict, got {:?}", other),
+    }
+    assert!(setup.db.get_conflict(&doc.id).await.unwrap().is_none());
+
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentUpdatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: Some(3),
+            sequence: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local_doc = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local_doc.content, merged);
+    assert_eq!(local_doc.sync_revision, 3);
+    assert_eq!(
+        setup.db.get_sync_status(&doc.id).await.unwrap(),
+        SyncStatus::Synced
+    );
+}
+
+/// A newer server revision over unsynced local edits is recorded as a conflict
+#[tokio::test]
+async fn test_sync_over_pending_edit_records_conflict() {
+    use replicant_core::models::SyncStatus;
+    use replicant_core::patches::{calculate_checksum, create_patch};
+    use replicant_core::protocol::ChangeEventType;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Shared" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // CreateDocument
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(setup.engine.get_conflict(doc.id).await.unwrap().is_none());
+
+    // A local edit that hasn't been uploaded yet
+    let mut edited = setup.db.get_document(&doc.id).await.unwrap();
+    edited.content = json!({ "title": "Mine" });
+    setup
+        .db
+        .save_document_and_queue_patch(
+            &edited,
+            &create_patch(&doc.content, &edited.content).unwrap(),
+            ChangeEventType::Update,
+            Some(calculate_checksum(&doc.content)),
+        )
+        .await
+        .unwrap();
+
+    let mut server_document = doc.clone();
+    server_document.content = json!({ "title": "Theirs" });
+    server_document.sync_revision = 2;
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: server_document,
+            relayed: false,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let conflict = setup.engine.get_conflict(doc.id).await.unwrap().unwrap();
+    assert_eq!(conflict.document_id, doc.id);
+    assert_eq!(conflict.local_content, json!({ "title": "Mine" }));
+    assert_eq!(conflict.server_content, json!({ "title": "Theirs" }));
+    assert_eq!(
+        setup.db.get_sync_status(&doc.id).await.unwrap(),
+        SyncStatus::Conflict
+    );
+}
+
+/// Test connection_state reports reconnection progress while the server is down
+#[tokio::test]
+async fn test_connection_state_reports_reconnecting() {
+    use replicant_client::events::SyncEvent;
+    use replicant_client::ConnectionState;
+
+    // Reserve a port with nothing listening on it
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    let engine = Client::new(
+        &format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()),
+        &format!("ws://{}", addr),
+        "test@user.com",
+        "test-key",
+        "test-secret",
+    )
+    .await
+    .unwrap();
+
+    let attempts = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let attempts_clone = attempts.clone();
+    engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::Reconnecting { attempt } = event {
+                attempts_clone.lock().unwrap().push(attempt);
+            }
+        })
+        .unwrap();
+
+    // Each attempt retries internally, so wait for the first one to fail
+    let state = tokio::time::timeout(Duration::from_secs(4), async {
+        loop {
+            if let state @ ConnectionState::Reconnecting { next_retry_at, .. } =
+                engine.connection_state()
+            {
+                if next_retry_at > std::time::Instant::now() {
+                    return state;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("Reconnection attempt never finished");
+    assert!(matches!(
+        state,
+        ConnectionState::Reconnecting { attempt: 1, .. }
+    ));
+
+    engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(*attempts.lock().unwrap(), vec![1]);
+}
+
+/// Test connecting gives up with a timeout when the server never answers
+#[tokio::test]
+async fn test_connect_times_out_on_unresponsive_server() {
+    use replicant_client::WebSocketClient;
+    use replicant_core::errors::ClientError;
+    use replicant_core::protocol::DEFAULT_MAX_MESSAGE_BYTES;
+    use replicant_core::SyncError;
+
+    // Accepts TCP connections but never answers the WebSocket handshake, so
+    // the connect hangs just as it would against an unroutable address
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    let started = std::time::Instant::now();
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        WebSocketClient::connect(
+            &url,
+            "test@user.com",
+            Uuid::new_v4(),
+            "test-key",
+            "test-secret",
+            None,
+            None,
+            Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            None,
+            false,
+            DEFAULT_MAX_MESSAGE_BYTES,
+            &[],
+            Duration::ZERO,
+            Duration::from_millis(300),
+        ),
+    )
+    .await
+    .expect("Connect should not hang");
+    match result {
+        Err(SyncError::Client(ClientError::Timeout(_))) => {}
+        Err(e) => panic!("Expected a timeout, got {}", e),
+        Ok(_) => panic!("Connect should fail"),
+    }
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    // The client falls back to offline mode just as promptly
+    let started = std::time::Instant::now();
+    let engine = Client::with_config(
+        &format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()),
+        &url,
+        "test@user.com",
+        "test-key",
+        "test-secret",
+        ClientConfig {
+            connect_timeout: Some(Duration::from_millis(300)),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(!engine.is_connected());
+}
+
+/// Test shutdown stops background tasks and closes the WebSocket cleanly
+#[tokio::test]
+async fn test_shutdown_closes_connection() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    tokio::time::timeout(Duration::from_secs(5), setup.engine.shutdown())
+        .await
+        .expect("Shutdown should not hang")
+        .unwrap();
+
+    // The mock server stops forwarding once it sees the Close frame
+    let after_close =
+        tokio::time::timeout(Duration::from_secs(2), setup.server.from_client_rx.recv())
+            .await
+            .expect("Server should see the connection close");
+    assert!(after_close.is_none());
+}
+
+/// Test forced offline mode queues work locally and syncs on return
+#[tokio::test]
+async fn test_set_offline_and_back_online() {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let events_clone = events.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| match event {
+            SyncEvent::ConnectionLost { .. } => events_clone.lock().unwrap().push("lost"),
+            SyncEvent::ConnectionSucceeded { .. } => events_clone.lock().unwrap().push("succeeded"),
+            _ => {}
+        })
+        .unwrap();
+    // Drop events from the initial connection
+    setup.engine.event_dispatcher().process_events().unwrap();
+    events.lock().unwrap().clear();
+
+    setup.engine.set_offline(true).await;
+    assert!(!setup.engine.is_connected());
+    assert!(setup.engine.is_offline());
+
+    // The connection is closed rather than left dangling
+    let after_close =
+        tokio::time::timeout(Duration::from_secs(2), setup.server.from_client_rx.recv())
+            .await
+            .expect("Server should see the connection close");
+    assert!(after_close.is_none());
+
+    // Local operations keep working while offline
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Written offline" }))
+        .await
+        .unwrap();
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 1);
+
+    // Ready a fresh listener, then come back online - no reconnect interval wait
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    setup.server.start().await;
+    setup.engine.set_offline(false).await;
+
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+    match setup.server.expect_client_message().await {
+        ClientMessage::CreateDocument { document, .. } => assert_eq!(document.id, doc.id),
+        other => panic!("Expected pending CreateDocument, got {:?}", other),
+    }
+    assert!(setup.engine.is_connected());
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    let events = events.lock().unwrap();
+    assert_eq!(events.first(), Some(&"lost"));
+    assert_eq!(events.last(), Some(&"succeeded"));
+    assert!(!events[1..].contains(&"lost"));
+}
+
+/// ConnectionLost tells a deliberate disconnect apart from the server closing
+#[tokio::test]
+async fn test_connection_lost_reports_reason() {
+    use replicant_client::events::{DisconnectReason, SyncEvent};
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let reasons = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reasons_clone = reasons.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::ConnectionLost { reason, .. } = event {
+                reasons_clone.lock().unwrap().push(reason);
+            }
+        })
+        .unwrap();
+
+    setup.engine.set_offline(true).await;
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(*reasons.lock().unwrap(), vec![DisconnectReason::Manual]);
+
+    // Reconnect, then have the server close the new connection
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    setup.server.start().await;
+    setup.engine.set_offline(false).await;
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(
+        *reasons.lock().unwrap(),
+        vec![DisconnectReason::Manual, DisconnectReason::ServerClosed]
+    );
+}
+
+/// Rejected credentials stop the reconnection loop rather than being retried
+#[tokio::test]
+async fn test_auth_failure_stops_reconnecting() {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let reasons = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reasons_clone = reasons.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::AuthenticationFailed { reason } = event {
+                reasons_clone.lock().unwrap().push(reason);
+            }
+        })
+        .unwrap();
+
+    setup
+        .server
+        .send_server_message(ServerMessage::AuthFailed {
+            reason: "Invalid credentials".to_string(),
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // The server closes the connection; a fresh listener is there to accept
+    // a reconnect, but the client must not try
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    setup.server.start().await;
+    tokio::time::sleep(Duration::from_millis(6000)).await;
+
+    let attempt = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.from_client_rx.recv(),
+    )
+    .await;
+    assert!(
+        attempt.is_err(),
+        "Client reconnected with rejected credentials"
+    );
+    assert!(!setup.engine.is_connected());
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(
+        *reasons.lock().unwrap(),
+        vec!["Invalid credentials".to_string()]
+    );
+}
+
+/// The client names its protocol version in the handshake and in Authenticate
+#[tokio::test]
+async fn test_connect_sends_protocol_version() {
+    use replicant_client::WebSocketClient;
+    use replicant_core::protocol::{PROTOCOL_VERSION, WEBSOCKET_SUBPROTOCOL};
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+    let listener = TcpListener::bind("localhost:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+
+    let (client, (subprotocol, auth)) = tokio::join!(
+        WebSocketClient::connect(
+            &url,
+            "test@user.com",
+            Uuid::new_v4(),
+            "test-key",
+            "test-secret",
+            None,
+            None,
+            Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            None,
+            false,
+            replicant_core::protocol::DEFAULT_MAX_MESSAGE_BYTES,
+            &[],
+            Duration::ZERO,
+            Duration::from_secs(2),
+        ),
+        async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut subprotocol = None;
+            let mut ws = tokio_tungstenite::accept_hdr_async(
+                stream,
+                |request: &Request, response: Response| {
+                    subprotocol = request
+                        .headers()
+                        .get("Sec-WebSocket-Protocol")
+                        .map(|value| value.to_str().unwrap().to_string());
+                    Ok(response)
+                },
+            )
+            .await
+            .unwrap();
+            let auth = loop {
+                if let Message::Text(text) = ws.next().await.unwrap().unwrap() {
+                    break serde_json::from_str::<ClientMessage>(&text).unwrap();
+                }
+            };
+            (subprotocol, auth)
+        }
+    );
+    client.unwrap();
+
+    assert_eq!(subprotocol.as_deref(), Some(WEBSOCKET_SUBPROTOCOL));
+    match auth {
+        ClientMessage::Authenticate {
+            protocol_version, ..
+        } => assert_eq!(protocol_version, Some(PROTOCOL_VERSION)),
+        other => panic!("Expected Authenticate, got {:?}", other),
+    }
+}
+
+/// A server on another protocol version is treated like rejected credentials
+#[tokio::test]
+async fn test_protocol_version_mismatch_fails_authentication() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::protocol::ErrorCode;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let reasons = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reasons_clone = reasons.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::AuthenticationFailed { reason } = event {
+                reasons_clone.lock().unwrap().push(reason);
+            }
+        })
+        .unwrap();
+
+    setup
+        .server
+        .send_server_message(ServerMessage::Error {
+            code: ErrorCode::VersionMismatch,
+            message: "Protocol version 1 is not supported".to_string(),
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(
+        *reasons.lock().unwrap(),
+        vec!["Protocol version 1 is not supported".to_string()]
+    );
+}
+
+/// Quiet connections are kept open with WebSocket Ping control frames, and the
+/// server's own Pings are answered
+#[tokio::test]
+async fn test_keepalive_sends_ping_frames() {
+    let listener = TcpListener::bind("localhost:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let db_url = format!("file:{}?mode=memory&cache=shared", Uuid::new_v4());
+    let server_url = format!("ws://{}", addr);
+
+    let (engine, frames) = tokio::join!(
+        Client::with_config(
+            &db_url,
+            &server_url,
+            "test@user.com",
+            "test-key",
+            "test-secret",
+            ClientConfig {
+                keepalive_interval: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        ),
+        async {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(stream).await.unwrap();
+            ws.send(Message::Ping(b"server".to_vec())).await.unwrap();
+
+            let mut pinged = false;
+            let mut ponged = false;
+            while !(pinged && ponged) {
+                let frame = tokio::time::timeout(Duration::from_secs(2), ws.next())
+                    .await
+                    .expect("Timed out waiting for keepalive frames")
+                    .unwrap()
+                    .unwrap();
+                match frame {
+                    Message::Ping(_) => pinged = true,
+                    Message::Pong(payload) => {
+                        assert_eq!(payload, b"server");
+                        ponged = true;
+                    }
+                    _ => {}
+                }
+            }
+            ws
+        }
+    );
+    let _engine = engine.unwrap();
+    drop(frames);
+}
+
+/// A transaction the server rejects leaves no trace locally
+#[tokio::test]
+async fn test_rejected_transaction_rolls_back() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let original = json!({ "title": "Original" });
+    let doc = setup
+        .engine
+        .create_document(original.clone())
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // consume create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let server = &mut setup.server;
+    let (result, _) = tokio::join!(
+        setup.engine.transaction(|tx| {
+            tx.update(doc.id, json!({ "title": "Changed" }));
+            tx.create(json!({ "title": "New" }))
+        }),
+        async {
+            match server.expect_client_message().await {
+                ClientMessage::Transaction {
+                    transaction_id,
+                    ops,
+                } => {
+                    assert_eq!(ops.len(), 2);
+                    server
+                        .send_server_message(ServerMessage::TransactionResponse {
+                            transaction_id,
+                            success: false,
+                            error: Some("Content hash mismatch".to_string()),
+                            documents: vec![],
+                            deleted_ids: vec![],
+                        })
+                        .await;
+                }
+                other => panic!("Expected Transaction, got {:?}", other),
+            }
+        }
+    );
+    assert!(result.is_err());
+
+    let restored = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(restored.content, original);
+    let all = setup.engine.get_all_documents().await.unwrap();
+    assert_eq!(all.len(), 1);
+    assert!(setup.db.get_pending_documents().await.unwrap().is_empty());
+}
+
+/// Test try_lock resolves from the server's LockResponse and lock changes are emitted
+#[tokio::test]
+async fn test_try_lock_and_unlock() {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let holders = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let holders_clone = holders.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::LockChanged { holder, .. } = event {
+                holders_clone.lock().unwrap().push(holder);
+            }
+        })
+        .unwrap();
+
+    let doc_id = Uuid::new_v4();
+    let other_client = Uuid::new_v4();
+
+    // Another client already holds the lock
+    let server = &mut setup.server;
+    let (refused, _) = tokio::join!(setup.engine.try_lock(doc_id), async {
+        match server.expect_client_message().await {
+            ClientMessage::AcquireLock { document_id } => assert_eq!(document_id, doc_id),
+            other => panic!("Expected AcquireLock, got {:?}", other),
+        }
+        server
+            .send_server_message(ServerMessage::LockResponse {
+                document_id: doc_id,
+                acquired: false,
+                holder: Some(other_client),
+            })
+            .await;
+    });
+    assert!(!refused.unwrap());
+
+    // It's released, and our second attempt wins
+    server
+        .send_server_message(ServerMessage::LockChanged {
+            document_id: doc_id,
+            holder: None,
+        })
+        .await;
+    let (acquired, _) = tokio::join!(setup.engine.try_lock(doc_id), async {
+        let _ = server.expect_client_message().await;
+        server
+            .send_server_message(ServerMessage::LockResponse {
+                document_id: doc_id,
+                acquired: true,
+                holder: Some(Uuid::new_v4()),
+            })
+            .await;
+    });
+    assert!(acquired.unwrap());
+
+    setup.engine.unlock(doc_id).await.unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::ReleaseLock { document_id } => assert_eq!(document_id, doc_id),
+        other => panic!("Expected ReleaseLock, got {:?}", other),
+    }
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    let holders = holders.lock().unwrap();
+    assert_eq!(holders.len(), 2);
+    assert!(holders[0].is_none());
+    assert!(holders[1].is_some());
+}
+
+/// Test a blob smaller than a chunk uploads in one message and is then read locally
+#[tokio::test]
+async fn test_attach_small_blob() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Receipt" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+
+    let bytes = vec![7u8; 100];
+    let blob_id = setup
+        .engine
+        .attach_blob(&doc.id, "receipt.png", bytes.clone())
+        .await
+        .unwrap();
+
+    match setup.server.expect_client_message().await {
+        ClientMessage::UploadBlob {
+            blob_id: id,
+            document_id,
+            name,
+            total_size,
+            offset,
+            data,
+        } => {
+            assert_eq!(id, blob_id);
+            assert_eq!(document_id, doc.id);
+            assert_eq!(name, "receipt.png");
+            assert_eq!(total_size, 100);
+            assert_eq!(offset, 0);
+            assert_eq!(data, bytes);
+        }
+        other => panic!("Expected UploadBlob, got {:?}", other),
+    }
+    setup
+        .server
+        .send_server_message(ServerMessage::BlobUploadAck {
+            blob_id,
+            received: 100,
+            complete: true,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let stored = setup.db.get_attachment(&blob_id).await.unwrap().unwrap();
+    assert_eq!(stored.uploaded, 100);
+    assert!(setup.db.get_pending_attachments().await.unwrap().is_empty());
+
+    // Served from the local copy without asking the server
+    assert_eq!(setup.engine.fetch_blob(&blob_id).await.unwrap(), bytes);
+
+    setup.engine.delete_blob(&blob_id).await.unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::DeleteBlob { blob_id: id } => assert_eq!(id, blob_id),
+        other => panic!("Expected DeleteBlob, got {:?}", other),
+    }
+    assert!(setup.db.get_attachment(&blob_id).await.unwrap().is_none());
+
+    // Unknown to the server as well
+    let server = &mut setup.server;
+    let (missing, _) = tokio::join!(setup.engine.fetch_blob(&blob_id), async {
+        let _ = server.expect_client_message().await;
+        server
+            .send_server_message(ServerMessage::BlobDeleted { blob_id })
+            .await;
+    });
+    assert!(missing.is_err());
+}
+
+/// Test a multi-chunk blob uploads one acknowledged chunk at a time and
+/// downloads in chunks
+#[tokio::test]
+async fn test_attach_multi_chunk_blob() {
+    use replicant_core::protocol::BLOB_CHUNK_BYTES;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Album" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+
+    let size = BLOB_CHUNK_BYTES * 2 + 1000;
+    let bytes: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+    let blob_id = setup
+        .engine
+        .attach_blob(&doc.id, "album.zip", bytes.clone())
+        .await
+        .unwrap();
+
+    let mut uploaded = Vec::new();
+    for expected_offset in [0, BLOB_CHUNK_BYTES, BLOB_CHUNK_BYTES * 2] {
+        let (offset, data) = match setup.server.expect_client_message().await {
+            ClientMessage::UploadBlob {
+                total_size,
+                offset,
+                data,
+                ..
+            } => {
+                assert_eq!(total_size, size as u64);
+                (offset, data)
+            }
+            other => panic!("Expected UploadBlob, got {:?}", other),
+        };
+        assert_eq!(offset, expected_offset as u64);
+        uploaded.extend_from_slice(&data);
+
+        let received = uploaded.len() as u64;
+        setup
+            .server
+            .send_server_message(ServerMessage::BlobUploadAck {
+                blob_id,
+                received,
+                complete: received == size as u64,
+            })
+            .await;
+        if expected_offset == 0 {
+            // A repeated ack doesn't send the next chunk twice
+            setup
+                .server
+                .send_server_message(ServerMessage::BlobUploadAck {
+                    blob_id,
+                    received,
+                    complete: false,
+                })
+                .await;
+        }
+    }
+    assert_eq!(uploaded, bytes);
+    assert!(
+        tokio::time::timeout(
+            Duration::from_millis(300),
+            setup.server.from_client_rx.recv()
+        )
+        .await
+        .is_err(),
+        "Nothing more should be sent once the upload is complete"
+    );
+    assert!(setup.db.get_pending_attachments().await.unwrap().is_empty());
+
+    // Another client's blob arrives in chunks and is kept locally
+    let remote_id = Uuid::new_v4();
+    let server = &mut setup.server;
+    let (fetched, _) = tokio::join!(setup.engine.fetch_blob(&remote_id), async {
+        match server.expect_client_message().await {
+            ClientMessage::RequestBlob { blob_id } => assert_eq!(blob_id, remote_id),
+            other => panic!("Expected RequestBlob, got {:?}", other),
+        }
+        for (i, chunk) in bytes.chunks(BLOB_CHUNK_BYTES).enumerate() {
+            server
+                .send_server_message(ServerMessage::BlobChunk {
+                    blob_id: remote_id,
+                    document_id: doc.id,
+                    name: "remote.zip".to_string(),
+                    total_size: size as u64,
+                    offset: (i * BLOB_CHUNK_BYTES) as u64,
+                    data: chunk.to_vec(),
+                })
+                .await;
+        }
+    });
+    assert_eq!(fetched.unwrap(), bytes);
+
+    let stored = setup.db.get_attachment(&remote_id).await.unwrap().unwrap();
+    assert_eq!(stored.name, "remote.zip");
+    assert_eq!(stored.data, bytes);
+}
+
+/// Test on_presence_change subscribes and reports the server's client lists
+#[tokio::test]
+async fn test_on_presence_change_reports_active_clients() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reported_clone = reported.clone();
+    setup
+        .engine
+        .on_presence_change(move |clients| reported_clone.lock().unwrap().push(clients))
+        .await
+        .unwrap();
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::RequestPresence
+    ));
+
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    setup
+        .server
+        .send_server_message(ServerMessage::PresenceUpdate {
+            client_ids: vec![first, second],
+        })
+        .await;
+    setup
+        .server
+        .send_server_message(ServerMessage::PresenceUpdate {
+            client_ids: vec![first],
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    let reported = reported.lock().unwrap();
+    assert_eq!(
+        *reported,
+        vec![
+            vec![first.to_string(), second.to_string()],
+            vec![first.to_string()]
+        ]
+    );
+}
+
+/// Test watch_document only reports the watched document, until dropped
+#[tokio::test]
+async fn test_watch_document_reports_only_that_document() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let watched = setup
+        .engine
+        .create_document(json!({ "title": "Watched" }))
+        .await
+        .unwrap();
+    let other = setup
+        .engine
+        .create_document(json!({ "title": "Other" }))
+        .await
+        .unwrap();
+
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen_clone = seen.clone();
+    let handle = setup
+        .engine
+        .watch_document(watched.id, move |doc| seen_clone.lock().unwrap().push(doc))
+        .await
+        .unwrap();
+
+    setup
+        .engine
+        .update_document(watched.id, json!({ "title": "Watched, edited" }))
+        .await
+        .unwrap();
+    setup
+        .engine
+        .update_document(other.id, json!({ "title": "Other, edited" }))
+        .await
+        .unwrap();
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentDeleted {
+            document_id: other.id,
+        })
+        .await;
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentDeleted {
+            document_id: watched.id,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    {
+        let seen = seen.lock().unwrap();
+        // The creation was still queued when the watch started
+        let titles: Vec<_> = seen.iter().map(|doc| doc.title_or_default()).collect();
+        assert_eq!(
+            titles,
+            vec!["Watched", "Watched, edited", "Watched, edited"]
+        );
+        assert!(seen.iter().all(|doc| doc.id == watched.id));
+        assert_eq!(seen[1].created_at, watched.created_at);
+        assert!(seen[1].deleted_at.is_none());
+        assert!(seen[2].deleted_at.is_some());
+    }
+
+    // Nothing more once the handle is dropped
+    drop(handle);
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreated {
+            document: replicant_core::models::Document {
+                content: json!({ "title": "Watched, restored" }),
+                sync_revision: 5,
+                ..watched.clone()
+            },
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(seen.lock().unwrap().len(), 3);
+}
+
+/// Test messages over max_message_bytes stay local instead of being sent
+#[tokio::test]
+async fn test_oversized_message_is_not_sent() {
+    let mut setup = setup_with_config(ClientConfig {
+        max_message_bytes: Some(4096),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    // Saved locally, but too big to upload
+    let big = setup
+        .engine
+        .create_document(json!({ "title": "Big", "text": "x".repeat(8192) }))
+        .await
+        .unwrap();
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 1);
+
+    // The next message the server sees is the small document
+    let small = setup
+        .engine
+        .create_document(json!({ "title": "Small" }))
+        .await
+        .unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::CreateDocument { document, .. } => {
+            assert_eq!(document.id, small.id);
+            assert_ne!(document.id, big.id);
+        }
+        other => panic!("Expected CreateDocument, got {:?}", other),
+    }
+}
+
+/// Test typed documents round-trip through create_typed and get_typed
+#[tokio::test]
+async fn test_typed_document_round_trip() {
+    use replicant_core::SyncError;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Task {
+        title: String,
+        priority: u8,
+        done: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Note {
+        #[allow(dead_code)]
+        body: String,
+    }
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let task = Task {
+        title: "Write docs".to_string(),
+        priority: 1,
+        done: false,
+    };
+    let doc = setup.engine.create_typed(&task).await.unwrap();
+    assert_eq!(doc.title(), Some("Write docs"));
+
+    let loaded: Task = setup.engine.get_typed(doc.id).await.unwrap();
+    assert_eq!(loaded, task);
+
+    let result = setup.engine.get_typed::<Note>(doc.id).await;
+    assert!(matches!(result, Err(SyncError::Deserialize(_))));
+}
+
+/// Test a validator rejecting empty titles keeps invalid docs out of the database
+#[tokio::test]
+async fn test_validator_rejects_empty_title() {
+    use replicant_core::SyncError;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    setup.engine.set_validator(Box::new(|content| {
+        match content.get("title").and_then(|t| t.as_str()) {
+            Some(title) if !title.trim().is_empty() => Ok(()),
+            _ => Err("title must not be empty".to_string()),
+        }
+    }));
+
+    let result = setup.engine.create_document(json!({ "title": "" })).await;
+    assert!(matches!(result, Err(SyncError::Validation(ref msg)) if msg.contains("title")));
+
+    // Nothing was written or queued
+    assert_eq!(setup.engine.count_documents().await.unwrap(), 0);
+    let queued: i64 = sqlx::query("SELECT COUNT(*) as count FROM sync_queue")
+        .fetch_one(&setup.db.pool)
+        .await
+        .unwrap()
+        .get("count");
+    assert_eq!(queued, 0);
+
+    // Updates are checked too, leaving the stored content untouched
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Valid" }))
+        .await
+        .unwrap();
+    let result = setup
+        .engine
+        .update_document(doc.id, json!({ "title": "  " }))
+        .await;
+    assert!(matches!(result, Err(SyncError::Validation(_))));
+    let stored = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(stored.content, json!({ "title": "Valid" }));
+}
+
+/// Test transfer_document sends TransferOwnership and drops the local copy once applied
+#[tokio::test]
+async fn test_transfer_document() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::protocol::ErrorCode;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let errors_clone = errors.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::SyncError { message } = event {
+                errors_clone.lock().unwrap().push(message);
+            }
+        })
+        .unwrap();
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Hand off" }))
+        .await
+        .unwrap();
+    let new_owner = Uuid::new_v4();
+    setup
+        .engine
+        .transfer_document(doc.id, new_owner)
+        .await
+        .unwrap();
+
+    loop {
+        match setup.server.expect_client_message().await {
+            ClientMessage::TransferOwnership {
+                document_id,
+                new_user_id,
+            } => {
+                assert_eq!(document_id, doc.id);
+                assert_eq!(new_user_id, new_owner);
+                break;
+            }
+            ClientMessage::CreateDocument { .. } => continue,
+            other => panic!("Expected TransferOwnership, got {:?}", other),
+        }
+    }
+
+    // A refused transfer surfaces as a sync error and leaves the document alone
+    setup
+        .server
+        .send_server_message(ServerMessage::Error {
+            code: ErrorCode::ValidationFailed,
+            message: format!("Target user {} does not exist", new_owner),
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(errors.lock().unwrap().len(), 1);
+    assert_eq!(setup.engine.count_documents().await.unwrap(), 1);
+
+    // Once applied, the server's DocumentDeleted removes the local copy
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentDeleted {
+            document_id: doc.id,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(setup.engine.count_documents().await.unwrap(), 0);
+}
+
+/// Local-only documents never reach the server until promoted
+#[tokio::test]
+async fn test_local_only_documents_are_not_synced() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let draft = setup
+        .engine
+        .create_document_with_id(Uuid::new_v4(), json!({ "title": "Draft" }), true)
+        .await
+        .unwrap();
+    setup
+        .engine
+        .update_document(draft.id, json!({ "title": "Draft", "text": "Still local" }))
+        .await
+        .unwrap();
+    let settings = setup
+        .engine
+        .create_document_with_id(Uuid::new_v4(), json!({ "theme": "dark" }), true)
+        .await
+        .unwrap();
+    setup.engine.delete_document(settings.id).await.unwrap();
+
+    let sent = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await;
+    assert!(sent.is_err(), "Local-only changes must not be sent");
+
+    // Still listed, but never pending or queued
+    let docs = setup.engine.get_all_documents().await.unwrap();
+    assert!(docs.iter().any(|doc| doc.id == draft.id));
+    assert!(setup.engine.is_local_only(draft.id).await.unwrap());
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+    assert!(setup
+        .db
+        .get_queued_patch(&draft.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Promoting uploads the current content as a new document
+    setup.engine.promote_to_synced(draft.id).await.unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::CreateDocument { document, .. } => {
+            assert_eq!(document.id, draft.id);
+            assert_eq!(document.content["text"], "Still local");
+        }
+        other => panic!("Expected CreateDocument, got {:?}", other),
+    }
+    assert!(!setup.engine.is_local_only(draft.id).await.unwrap());
+
+    // Deleted local-only documents can't be promoted
+    assert!(setup.engine.promote_to_synced(settings.id).await.is_err());
+}
+
+/// An export restores into another database, and merging only takes newer documents
+#[tokio::test]
+async fn test_export_import_round_trip() {
+    use replicant_client::ImportMode;
+    use replicant_core::models::SyncStatus;
+
+    let mut source = setup().await;
+    let _ = source.server.expect_client_message().await; // auth
+    let _ = source.server.expect_client_message().await; // sync
+
+    let note = source
+        .engine
+        .create_document(json!({ "title": "Note", "text": "v1" }))
+        .await
+        .unwrap();
+    source
+        .engine
+        .update_document(note.id, json!({ "title": "Note", "text": "v2" }))
+        .await
+        .unwrap();
+    let draft = source
+        .engine
+        .create_document_with_id(Uuid::new_v4(), json!({ "title": "Draft" }), true)
+        .await
+        .unwrap();
+
+    let backup = source.engine.export_all().await.unwrap();
+
+    // Replace restores documents, local-only flags and queued patches
+    let mut target = setup().await;
+    let _ = target.server.expect_client_message().await; // auth
+    let _ = target.server.expect_client_message().await; // sync
+    target
+        .engine
+        .create_document(json!({ "title": "Discarded" }))
+        .await
+        .unwrap();
+
+    let imported = target
+        .engine
+        .import(&backup, ImportMode::Replace)
+        .await
+        .unwrap();
+    assert_eq!(imported, 2);
+    let docs = target.engine.get_all_documents().await.unwrap();
+    assert_eq!(docs.len(), 2);
+    let restored = target.db.get_document(&note.id).await.unwrap();
+    assert_eq!(restored.content["text"], "v2");
+    assert!(target.engine.is_local_only(draft.id).await.unwrap());
+    assert!(!target.engine.is_local_only(note.id).await.unwrap());
+    assert_eq!(
+        target.db.get_queued_patch(&note.id).await.unwrap(),
+        source.db.get_queued_patch(&note.id).await.unwrap()
+    );
+
+    // Merging the same export changes nothing
+    let imported = target
+        .engine
+        .import(&backup, ImportMode::Merge)
+        .await
+        .unwrap();
+    assert_eq!(imported, 0);
+
+    // A newer revision is taken and marked pending to re-sync
+    let mut newer = source.db.get_document(&note.id).await.unwrap();
+    newer.content = json!({ "title": "Note", "text": "v3" });
+    newer.sync_revision = 5;
+    source.db.save_document(&newer).await.unwrap();
+    source.db.remove_from_sync_queue(&note.id).await.unwrap();
+    let backup = source.engine.export_all().await.unwrap();
+
+    let imported = target
+        .engine
+        .import(&backup, ImportMode::Merge)
+        .await
+        .unwrap();
+    assert_eq!(imported, 1);
+    let merged = target.db.get_document(&note.id).await.unwrap();
+    assert_eq!(merged.content["text"], "v3");
+    assert_eq!(merged.sync_revision, 5);
+    assert_eq!(
+        target.db.get_sync_status(&note.id).await.unwrap(),
+        SyncStatus::Pending
+    );
+    assert!(target
+        .db
+        .get_queued_patch(&note.id)
+        .await
+        .unwrap()
+        .is_some());
+
+    // Exports from a newer format are refused
+    let mut future: serde_json::Value = serde_json::from_slice(&backup).unwrap();
+    future["format_version"] = json!(99);
+    let future = serde_json::to_vec(&future).unwrap();
+    assert!(target
+        .engine
+        .import(&future, ImportMode::Merge)
+        .await
+        .is_err());
+}
+
+/// Edits made while sync is paused go out in one burst on resume
+#[tokio::test]
+async fn test_pause_and_resume_sync() {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let events_clone = events.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| match event {
+            SyncEvent::SyncStarted => events_clone.lock().unwrap().push("started".to_string()),
+            SyncEvent::SyncCompleted { document_count } => events_clone
+                .lock()
+                .unwrap()
+                .push(format!("completed {}", document_count)),
+            _ => {}
+        })
+        .unwrap();
+
+    // Drop the events from connecting
+    setup.engine.event_dispatcher().process_events().unwrap();
+    events.lock().unwrap().clear();
+
+    setup.engine.pause_sync();
+    assert!(setup.engine.is_sync_paused());
+    let mut ids = Vec::new();
+    for i in 0..10 {
+        let doc = setup
+            .engine
+            .create_document(json!({ "title": format!("Item {}", i) }))
+            .await
+            .unwrap();
+        ids.push(doc.id);
+    }
+
+    let sent = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await;
+    assert!(sent.is_err(), "Nothing should be sent while paused");
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 10);
+
+    setup.engine.resume_sync().await.unwrap();
+    assert!(!setup.engine.is_sync_paused());
+
+    let mut uploaded = Vec::new();
+    for _ in 0..10 {
+        match setup.server.expect_client_message().await {
+            ClientMessage::CreateDocument { document, .. } => uploaded.push(document.id),
+            other => panic!("Expected CreateDocument, got {:?}", other),
+        }
+    }
+    uploaded.sort();
+    ids.sort();
+    assert_eq!(uploaded, ids);
+
+    let extra = tokio::time::timeout(
+        Duration::from_millis(500),
+        setup.server.expect_client_message(),
+    )
+    .await;
+    assert!(extra.is_err(), "Each edit should be sent once");
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["started".to_string(), "completed 10".to_string()]
+    );
+}
+
+/// An unconfirmed upload from the initial sync is retried once the (short) upload timeout passes
+#[tokio::test]
+async fn test_short_timeouts_retry_unconfirmed_uploads() {
+    let db_id = Uuid::new_v4();
+    let database_url = format!("file:{}?mode=memory&cache=shared", db_id);
+    let db = Arc::new(ClientDatabase::new(&database_url).await.unwrap());
+    db.run_migrations().await.unwrap();
+
+    let mut server = MockServer::new().await;
+    server.start().await;
+    let server_url = format!("ws://{}", server.addr);
+    let email = "test@user.com";
+
+    // Leave a document pending from an earlier offline session
+    db.ensure_user_config_with_identifier(&server_url, email)
+        .await
+        .unwrap();
+    let (user_id, _) = db.get_user_and_client_id().await.unwrap();
+    let doc = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id,
+        content: json!({ "title": "Offline" }),
+        sync_revision: 1,
+        content_hash: None,
+        title: Some("Offline".to_string()),
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+    db.save_document(&doc).await.unwrap();
+
+    // The server never confirms, so startup gives up after both short timeouts
+    let started = std::time::Instant::now();
+    let _engine = Client::with_config(
+        &database_url,
+        &server_url,
+        email,
+        "test-key",
+        "test-secret",
+        ClientConfig {
+            timeouts: SyncTimeouts {
+                upload_confirm: Duration::from_millis(100),
+                retry_confirm: Duration::from_millis(100),
+                max_retry_attempts: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    assert!(matches!(
+        server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+    let mut uploads = 0;
+    loop {
+        match server.expect_client_message().await {
+            ClientMessage::CreateDocument { document, .. } => {
+                assert_eq!(document.id, doc.id);
+                uploads += 1;
+            }
+            ClientMessage::RequestFullSync => break,
+            other => panic!("Unexpected message {:?}", other),
+        }
+    }
+    assert_eq!(uploads, 2, "Upload should be sent once and retried once");
+}
+
+/// Uploads keep being retried with growing waits until the server confirms them
+#[tokio::test]
+async fn test_upload_retried_until_confirmed() {
+    let db_id = Uuid::new_v4();
+    let database_url = format!("file:{}?mode=memory&cache=shared", db_id);
+    let db = Arc::new(ClientDatabase::new(&database_url).await.unwrap());
+    db.run_migrations().await.unwrap();
+
+    let mut server = MockServer::new().await;
+    server.start().await;
+    let server_url = format!("ws://{}", server.addr);
+    let email = "test@user.com";
+
+    db.ensure_user_config_with_identifier(&server_url, email)
+        .await
+        .unwrap();
+    let (user_id, _) = db.get_user_and_client_id().await.unwrap();
+    let doc = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id,
+        content: json!({ "title": "Flaky link" }),
+        sync_revision: 1,
+        content_hash: None,
+        title: Some("Flaky link".to_string()),
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+    db.save_document(&doc).await.unwrap();
+
+    // Startup blocks on the upload-first sync, so drive the server alongside it
+    let engine = tokio::spawn({
+        let database_url = database_url.clone();
+        let server_url = server_url.clone();
+        async move {
+            Client::with_config(
+                &database_url,
+                &server_url,
+                email,
+                "test-key",
+                "test-secret",
+                ClientConfig {
+                    timeouts: SyncTimeouts {
+                        upload_confirm: Duration::from_millis(100),
+                        retry_confirm: Duration::from_millis(100),
+                        max_retry_attempts: 3,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+        }
+    });
+
+    assert!(matches!(
+        server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+    // Drop the first two uploads and confirm the third
+    for _ in 0..3 {
+        match server.expect_client_message().await {
+            ClientMessage::CreateDocument { document, .. } => assert_eq!(document.id, doc.id),
+            other => panic!("Expected CreateDocument, got {:?}", other),
+        }
+    }
+    server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+
+    let _engine = engine.await.unwrap();
+    assert!(matches!(
+        server.expect_client_message().await,
+        ClientMessage::RequestFullSync
+    ));
+    assert!(db.get_pending_documents().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_repair_reconciles_sync_queue() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // consume auth
+    let _ = setup.server.expect_client_message().await; // consume sync
+
+    // A synced document whose queue entry survived a crash
+    let synced = setup
+        .engine
+        .create_document(json!({ "title": "Synced" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // consume create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: synced.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    sqlx::query(
+        "INSERT INTO sync_queue (document_id, operation_type, patch) VALUES (?, 'update', '[]')",
+    )
+    .bind(synced.id.to_string())
+    .execute(&setup.db.pool)
+    .await
+    .unwrap();
+
+    // An edited document the server already has, whose queued patch was lost
+    let mut edited = synced.clone();
+    edited.id = Uuid::new_v4();
+    edited.sync_revision = 3;
+    edited.content = json!({ "title": "Edited" });
+    setup.db.save_document(&edited).await.unwrap();
+
+    let report = setup.engine.repair().await.unwrap();
+    assert_eq!(report.stale_queue_entries, 1);
+    assert_eq!(report.requeued_documents, vec![edited.id]);
+
+    assert!(setup
+        .db
+        .get_queued_patch(&synced.id)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(setup
+        .db
+        .get_queued_patch(&edited.id)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(setup.engine.repair().await.unwrap().is_clean());
+}
+
+/// Queues three syncs behind an in-flight upload with room for two, then
+/// confirms the upload. Returns the revision the document ends up at and the
+/// sync errors emitted along the way.
+async fn overflow_deferred_queue(
+    policy: replicant_client::DeferredOverflowPolicy,
+) -> (i64, Vec<String>) {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup_with_config(ClientConfig {
+        deferred_queue_capacity: Some(2),
+        deferred_overflow: policy,
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // consume auth
+    let _ = setup.server.expect_client_message().await; // consume sync
+
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let errors_clone = errors.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::SyncError { message } = event {
+                errors_clone.lock().unwrap().push(message);
+            }
+        })
+        .unwrap();
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Busy" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // consume create
+
+    // The upload is unconfirmed, so these are deferred
+    for revision in 2..=4 {
+        let mut synced = doc.clone();
+        synced.sync_revision = revision;
+        setup
+            .server
+            .send_server_message(ServerMessage::SyncDocument {
+                document: synced,
+                relayed: false,
+            })
+            .await;
+    }
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    let revision = setup.db.get_document(&doc.id).await.unwrap().sync_revision;
+    let errors = errors.lock().unwrap().clone();
+    (revision, errors)
+}
+
+#[tokio::test]
+async fn test_deferred_queue_overflow_policies() {
+    use replicant_client::DeferredOverflowPolicy;
+
+    // Dropping v2 leaves v3 and v4 to apply
+    let (revision, errors) = overflow_deferred_queue(DeferredOverflowPolicy::DropOldest).await;
+    assert_eq!(revision, 4);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("v2"), "{}", errors[0]);
+
+    // Dropping v4 stops at v3
+    let (revision, errors) = overflow_deferred_queue(DeferredOverflowPolicy::DropNewest).await;
+    assert_eq!(revision, 3);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("v4"), "{}", errors[0]);
+
+    // Nothing is dropped past the cap
+    let (revision, errors) = overflow_deferred_queue(DeferredOverflowPolicy::GrowAndWarn).await;
+    assert_eq!(revision, 4);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[tokio::test]
+async fn test_deduped_import_is_idempotent() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // consume auth
+    let _ = setup.server.expect_client_message().await; // consume sync
+
+    let record = json!({ "sku": "A-100", "title": "Widget", "stock": 3 });
+    let first = setup
+        .engine
+        .create_document_deduped(record.clone(), "$.sku")
+        .await
+        .unwrap();
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::CreateDocument { .. }
+    ));
+
+    // Importing the same record again, with fresher data, updates in place
+    let reimported = json!({ "sku": "A-100", "title": "Widget", "stock": 5 });
+    let second = setup
+        .engine
+        .create_document_deduped(reimported.clone(), "$.sku")
+        .await
+        .unwrap();
+    assert_eq!(second.id, first.id);
+    assert_eq!(second.content, reimported);
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM documents")
+        .fetch_one(&setup.db.pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+
+    // A different key is a different document
+    let other = setup
+        .engine
+        .create_document_deduped(json!({ "sku": "A-101" }), "$.sku")
+        .await
+        .unwrap();
+    assert_ne!(other.id, first.id);
+
+    // The key has to be present
+    assert!(setup
+        .engine
+        .create_document_deduped(json!({ "title": "No sku" }), "$.sku")
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_sync_now_reports_summary() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // consume auth
+    let _ = setup.server.expect_client_message().await; // consume sync
+
+    // Held back locally until sync_now
+    setup.engine.pause_sync();
+    let local = setup
+        .engine
+        .create_document(json!({ "title": "Local" }))
+        .await
+        .unwrap();
+
+    let remote = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id: local.user_id,
+        content: json!({ "title": "From server" }),
+        sync_revision: 1,
+        content_hash: None,
+        title: Some("From server".to_string()),
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+
+    let server = async {
+        match setup.server.expect_client_message().await {
+            ClientMessage::CreateDocument { document, .. } => assert_eq!(document.id, local.id),
+            other => panic!("Expected CreateDocument, got {:?}", other),
+        }
+        setup
+            .server
+            .send_server_message(ServerMessage::DocumentCreatedResponse {
+                document_id: local.id,
+                success: true,
+                error: None,
+                sync_revision: None,
+                sequence: None,
+                created_at: None,
+                updated_at: None,
+            })
+            .await;
+
+        assert!(matches!(
+            setup.server.expect_client_message().await,
+            ClientMessage::RequestFullSync
+        ));
+        for document in [local.clone(), remote.clone()] {
+            setup
+                .server
+                .send_server_message(ServerMessage::SyncDocument {
+                    document,
+                    relayed: false,
+                })
+                .await;
+        }
+        setup
+            .server
+            .send_server_message(ServerMessage::SyncComplete { synced_count: 2 })
+            .await;
+    };
+
+    let (summary, ()) = tokio::join!(setup.engine.sync_now(), server);
+    let summary = summary.unwrap();
+    assert_eq!(summary.uploaded, 1);
+    assert_eq!(summary.downloaded, 2);
+    assert_eq!(summary.conflicted, 0);
+
+    // The downloaded document is applied by the time sync_now returns
+    let stored = setup.db.get_document(&remote.id).await.unwrap();
+    assert_eq!(stored.content, remote.content);
+}
+
+#[tokio::test]
+async fn test_client_with_store() {
+    let db = ClientDatabase::new(&format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()))
+        .await
+        .unwrap();
+    db.run_migrations().await.unwrap();
+    let store: Arc<dyn DocumentStore> = Arc::new(db);
+
+    let mut server = MockServer::new().await;
+    server.start().await;
+    let engine = Client::with_store(
+        store.clone(),
+        &format!("ws://{}", server.addr),
+        "test@user.com",
+        "test-key",
+        "test-secret",
+        ClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+
+    // Documents are written through the store and uploaded as usual
+    let doc = engine
+        .create_document(json!({ "title": "Stored" }))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get_document(&doc.id).await.unwrap().content,
+        doc.content
+    );
+    loop {
+        if let ClientMessage::CreateDocument { document, .. } = server.expect_client_message().await
+        {
+            assert_eq!(document.id, doc.id);
+            break;
+        }
+    }
+    assert_eq!(
+        engine.schema_version().await.unwrap(),
+        store.schema_version().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_client_with_memory_store() {
+    let store: Arc<dyn DocumentStore> = Arc::new(MemoryStore::new());
+    let mut server = MockServer::new().await;
+    server.start().await;
+    let engine = Client::with_store(
+        store.clone(),
+        &format!("ws://{}", server.addr),
+        "test@user.com",
+        "test-key",
+        "test-secret",
+        ClientConfig::default(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+
+    let doc = engine
+        .create_document(json!({ "title": "In memory" }))
+        .await
+        .unwrap();
+    let uploaded = loop {
+        if let ClientMessage::CreateDocument { document, .. } = server.expect_client_message().await
+        {
+            break document;
+        }
+    };
+    assert_eq!(uploaded.id, doc.id);
+    server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+
+    // A document from another client arrives through a full sync
+    let remote = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id: uploaded.user_id,
+        content: json!({ "title": "Remote" }),
+        sync_revision: 1,
+        content_hash: None,
+        title: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+    server
+        .send_server_message(ServerMessage::SyncDocument {
+            document: remote.clone(),
+            relayed: false,
+        })
+        .await;
+
+    for _ in 0..50 {
+        if engine.get_all_documents().await.unwrap().len() == 2
+            && engine.count_pending_sync().await.unwrap() == 0
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mut titles: Vec<String> = engine
+        .get_all_documents()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|doc| doc.title.unwrap())
+        .collect();
+    titles.sort();
+    assert_eq!(titles, ["In memory", "Remote"]);
+    assert_eq!(engine.count_pending_sync().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_time_since_last_sync() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // consume auth
+    let _ = setup.server.expect_client_message().await; // consume sync
+    assert_eq!(setup.engine.time_since_last_sync(), None);
+
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncComplete { synced_count: 0 })
+        .await;
+    for _ in 0..50 {
+        if setup.engine.time_since_last_sync().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let just_synced = setup.engine.time_since_last_sync().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let later = setup.engine.time_since_last_sync().unwrap();
+    assert!(later >= just_synced + Duration::from_millis(300));
+    assert!(later < Duration::from_secs(5));
+
+    // Persisted too, which is what a restarted client loads
+    let persisted = setup.db.get_last_sync_at().await.unwrap().unwrap();
+    let age = (chrono::Utc::now() - persisted).to_std().unwrap();
+    assert!(age >= Duration::from_millis(300) && age < Duration::from_secs(5));
+}
+
+/// Test a registered content migration upgrades old documents when they are read
+#[tokio::test]
+async fn test_content_migration_renames_field() {
+    use replicant_client::SCHEMA_VERSION_KEY;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    // Written before the app renamed `name` to `title`
+    let old = setup
+        .engine
+        .create_document(json!({ "name": "Groceries", "items": 3 }))
+        .await
+        .unwrap();
+    let current = setup
+        .engine
+        .create_document(json!({ "title": "Chores", SCHEMA_VERSION_KEY: 1 }))
+        .await
+        .unwrap();
+
+    setup
+        .engine
+        .register_content_migration(0, 1, |content| {
+            if let Some(name) = content.as_object_mut().and_then(|c| c.remove("name")) {
+                content["title"] = name;
+            }
+        })
+        .unwrap();
+    assert!(setup
+        .engine
+        .register_content_migration(2, 1, |_| {})
+        .is_err());
+
+    let expected = json!({ "title": "Groceries", "items": 3, SCHEMA_VERSION_KEY: 1 });
+    let migrated = setup.engine.get_document(old.id).await.unwrap();
+    assert_eq!(migrated.content, expected);
+
+    // The upgraded form was saved, so the next read has nothing to do
+    let stored = setup.db.get_document(&old.id).await.unwrap();
+    assert_eq!(stored.content, expected);
+
+    let all = setup.engine.get_all_documents().await.unwrap();
+    let find = |id| all.iter().find(|d| d.id == id).unwrap().content.clone();
+    assert_eq!(find(old.id), expected);
+    assert_eq!(find(current.id), current.content);
+}
+
+/// Test migrating documents on read isn't refused by a full sync queue
+#[tokio::test]
+async fn test_content_migration_with_full_queue() {
+    use replicant_client::SCHEMA_VERSION_KEY;
+
+    let mut setup = setup_with_config(ClientConfig {
+        max_queue_size: Some(2),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut ids = Vec::new();
+    for name in ["Groceries", "Chores", "Errands"] {
+        let doc = setup
+            .engine
+            .create_document(json!({ "name": name }))
+            .await
+            .unwrap();
+        ids.push(doc.id);
+    }
+    // One update each to the first two fills the queue
+    for id in &ids[..2] {
+        setup
+            .engine
+            .update_document(*id, json!({ "name": "Renamed" }))
+            .await
+            .unwrap();
+    }
+    assert!(setup
+        .engine
+        .update_document(ids[2], json!({ "name": "Refused" }))
+        .await
+        .is_err());
+
+    setup
+        .engine
+        .register_content_migration(0, 1, |content| {
+            if let Some(name) = content.as_object_mut().and_then(|c| c.remove("name")) {
+                content["title"] = name;
+            }
+        })
+        .unwrap();
+
+    let all = setup.engine.get_all_documents().await.unwrap();
+    assert_eq!(all.len(), 3);
+    for doc in &all {
+        assert!(doc.content.get("title").is_some());
+        assert_eq!(doc.content[SCHEMA_VERSION_KEY], 1);
+        let stored = setup.db.get_document(&doc.id).await.unwrap();
+        assert_eq!(stored.content, doc.content);
+    }
+}
+
+/// Test a long offline queue is replayed in a handful of OperationBatch messages
+#[tokio::test]
+async fn test_offline_queue_replayed_in_batches() {
+    use replicant_client::websocket::MAX_BATCH_OPERATIONS;
+
+    const OFFLINE_OPS: usize = 200;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut created = std::collections::HashSet::new();
+    for i in 0..OFFLINE_OPS {
+        let doc = setup
+            .engine
+            .create_document(json!({ "title": format!("Offline {}", i) }))
+            .await
+            .unwrap();
+        created.insert(doc.id);
+    }
+    assert_eq!(
+        setup.engine.count_pending_sync().await.unwrap(),
+        OFFLINE_OPS
+    );
+
+    setup.server.start().await;
+    tokio::time::sleep(Duration::from_millis(4000)).await; // Wait for reconnect loop
+    let _ = setup.server.expect_client_message().await; // auth
+
+    // Every operation arrives, and is confirmed without waiting for the rest
+    let mut received = std::collections::HashSet::new();
+    while received.len() < OFFLINE_OPS {
+        match setup.server.expect_client_message().await {
+            ClientMessage::CreateDocument { document, .. } => {
+                received.insert(document.id);
+                setup
+                    .server
+                    .send_server_message(ServerMessage::DocumentCreatedResponse {
+                        document_id: document.id,
+                        success: true,
+                        error: None,
+                        sync_revision: None,
+                        sequence: None,
+                        created_at: None,
+                        updated_at: None,
+                    })
+                    .await;
+            }
+            other => panic!("Expected CreateDocument, got {:?}", other),
+        }
+    }
+    assert_eq!(received, created);
+    assert_eq!(
+        setup.server.batches_received.load(Ordering::SeqCst),
+        OFFLINE_OPS.div_ceil(MAX_BATCH_OPERATIONS)
+    );
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+}
+
+/// Test a capped sync queue merges per-document updates, then refuses changes until drained
+#[tokio::test]
+async fn test_sync_queue_cap() {
+    use replicant_core::patches::apply_patch;
+    use replicant_core::SyncError;
+
+    let mut setup = setup_with_config(ClientConfig {
+        max_queue_size: Some(3),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut docs = Vec::new();
+    for title in ["A", "B", "C"] {
+        docs.push(
+            setup
+                .engine
+                .create_document(json!({ "title": title }))
+                .await
+                .unwrap(),
+        );
+    }
+    let (a, b, c) = (&docs[0], &docs[1], &docs[2]);
+
+    // Two updates to A and one to B fill the queue
+    for (id, content) in [
+        (a.id, json!({ "title": "A1" })),
+        (a.id, json!({ "title": "A2", "done": true })),
+        (b.id, json!({ "title": "B1" })),
+    ] {
+        setup.engine.update_document(id, content).await.unwrap();
+    }
+    assert_eq!(setup.engine.queue_depth().await.unwrap(), 3);
+
+    // The next change fits once A's updates are merged into one
+    setup
+        .engine
+        .update_document(c.id, json!({ "title": "C1" }))
+        .await
+        .unwrap();
+    assert_eq!(setup.engine.queue_depth().await.unwrap(), 3);
+    let (merged, _) = setup.db.get_queued_patch(&a.id).await.unwrap().unwrap();
+    let mut replayed = a.content.clone();
+    apply_patch(&mut replayed, &merged).unwrap();
+    assert_eq!(replayed, json!({ "title": "A2", "done": true }));
+
+    // Nothing left to merge: changes are refused and not written
+    let result = setup
+        .engine
+        .update_document(a.id, json!({ "title": "A3" }))
+        .await;
+    assert!(matches!(
+        result,
+        Err(SyncError::QueueFull { depth: 3, limit: 3 })
+    ));
+    assert!(setup
+        .engine
+        .create_document(json!({ "title": "D" }))
+        .await
+        .is_err());
+    assert_eq!(
+        setup.db.get_document(&a.id).await.unwrap().content,
+        json!({ "title": "A2", "done": true })
+    );
+
+    // Reconnecting uploads the documents, their queued updates folded into
+    // the creates, and makes room again
+    setup.server.start().await;
+    tokio::time::sleep(Duration::from_millis(4000)).await; // Wait for reconnect loop
+    let _ = setup.server.expect_client_message().await; // auth
+    for _ in 0..3 {
+        match setup.server.expect_client_message().await {
+            ClientMessage::CreateDocument { document, .. } => {
+                setup
+                    .server
+                    .send_server_message(ServerMessage::DocumentCreatedResponse {
+                        document_id: document.id,
+                        success: true,
+                        error: None,
+                        sync_revision: None,
+                        sequence: None,
+                        created_at: None,
+                        updated_at: None,
+                    })
+                    .await;
+            }
+            other => panic!("Expected CreateDocument, got {:?}", other),
+        }
+    }
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(setup.engine.queue_depth().await.unwrap(), 0);
+    setup
+        .engine
+        .update_document(a.id, json!({ "title": "A3" }))
+        .await
+        .unwrap();
+}
+
+/// Test touch_document advances updated_at and sends an empty update
+#[tokio::test]
+async fn test_touch_document() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::patches::calculate_checksum;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let updates = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let updates_clone = updates.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::DocumentUpdated { id, .. } = event {
+                updates_clone.lock().unwrap().push(id);
+            }
+        })
+        .unwrap();
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Touched" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let before = setup.db.get_document(&doc.id).await.unwrap();
+
+    // Unlike an unchanged update, a touch always goes out
+    setup
+        .engine
+        .update_document(doc.id, doc.content.clone())
+        .await
+        .unwrap();
+    setup.engine.touch_document(doc.id).await.unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::UpdateDocument { patch, .. } => {
+            assert_eq!(patch.document_id, doc.id);
+            assert!(patch.patch.0.is_empty());
+            assert_eq!(patch.content_hash, calculate_checksum(&doc.content));
+        }
+        other => panic!("Expected UpdateDocument, got {:?}", other),
+    }
+
+    let after = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(after.content, before.content);
+    assert!(after.updated_at > before.updated_at);
+    assert!(!after.is_synced());
+
+    // Listeners and stats see the touch, but not the skipped update
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(*updates.lock().unwrap(), vec![doc.id.to_string()]);
+    assert_eq!(setup.engine.stats().local_updates, 1);
+}
+
+#[tokio::test]
+async fn test_expired_documents_are_hidden() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document_with_ttl(json!({ "title": "Share link" }), Duration::from_millis(500))
+        .await
+        .unwrap();
+    let permanent = setup
+        .engine
+        .create_document(json!({ "title": "Kept" }))
+        .await
+        .unwrap();
+
+    // The server is told when the document expires
+    match setup.server.expect_client_message().await {
+        ClientMessage::CreateDocument { document, .. } => {
+            assert_eq!(document.id, doc.id);
+            assert_eq!(document.expires_at, doc.expires_at);
+        }
+        other => panic!("Expected CreateDocument, got {:?}", other),
+    }
+
+    let ids = |docs: Vec<replicant_core::models::Document>| {
+        docs.into_iter().map(|d| d.id).collect::<Vec<_>>()
+    };
+    let listed = ids(setup.engine.get_all_documents().await.unwrap());
+    assert!(listed.contains(&doc.id));
+    assert_eq!(setup.engine.count_documents().await.unwrap(), 2);
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    // Hidden locally without waiting for the server's sweep
+    let listed = ids(setup.engine.get_all_documents().await.unwrap());
+    assert_eq!(listed, vec![permanent.id]);
+    assert_eq!(setup.engine.count_documents().await.unwrap(), 1);
+    let loaded = setup.db.get_document(&doc.id).await.unwrap();
+    assert!(loaded.is_expired());
+}
+
+/// refetch_document throws away local edits in favour of the server copy
+#[tokio::test]
+async fn test_refetch_document_resets_diverged_copy() {
+    use replicant_client::events::SyncEvent;
+
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let errors_clone = errors.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::SyncError { message } = event {
+                errors_clone.lock().unwrap().push(message);
+            }
+        })
+        .unwrap();
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Stuck" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // A local edit the server never confirms
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Local edit" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // update
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 1);
+
+    let server_content = json!({ "title": "Server copy" });
+    let server_doc = replicant_core::models::Document {
+        content: server_content.clone(),
+        sync_revision: 3,
+        ..doc.clone()
+    };
+    let server = async {
+        match setup.server.expect_client_message().await {
+            ClientMessage::RequestDocument { document_id } => assert_eq!(document_id, doc.id),
+            other => panic!("Expected RequestDocument, got {:?}", other),
+        }
+        setup
+            .server
+            .send_server_message(ServerMessage::SyncDocument {
+                document: server_doc.clone(),
+                relayed: false,
+            })
+            .await;
+    };
+    let (refetched, ()) = tokio::join!(setup.engine.refetch_document(doc.id), server);
+
+    let refetched = refetched.unwrap();
+    assert_eq!(refetched.content, server_content);
+    let local = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local.content, server_content);
+    assert_eq!(local.sync_revision, 3);
+    assert!(local.is_synced());
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert!(errors
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|message| message.contains("Discarded unsynced local changes")));
+}
+
+#[tokio::test]
+async fn test_stats_count_operations() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Counted" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Counted twice" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // update
+    setup.engine.delete_document(doc.id).await.unwrap();
+    let _ = setup.server.expect_client_message().await; // delete
+
+    let (user_id, _) = setup.db.get_user_and_client_id().await.unwrap();
+    let remote_doc = replicant_core::models::Document {
+        id: Uuid::new_v4(),
+        user_id,
+        content: json!({ "from_server": true }),
+        sync_revision: 1,
+        content_hash: None,
+        title: None,
+        created_at: chrono::Utc::now(),
+        updated_at: chrono::Utc::now(),
+        deleted_at: None,
+        expires_at: None,
+        sync_status: replicant_core::models::SyncStatus::Synced,
+    };
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreated {
+            document: remote_doc.clone(),
+        })
+        .await;
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentDeleted {
+            document_id: remote_doc.id,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let stats = setup.engine.stats();
+    assert_eq!(stats.local_creates, 1);
+    assert_eq!(stats.local_updates, 1);
+    assert_eq!(stats.local_deletes, 1);
+    assert_eq!(stats.remote_creates, 1);
+    assert_eq!(stats.remote_updates, 0);
+    assert_eq!(stats.remote_deletes, 1);
+    assert!(stats.bytes_sent > 0);
+    assert!(stats.bytes_received > 0);
+}
+
+/// Test receipt acks are requested when configured and not counted for unknown messages
+#[tokio::test]
+async fn test_receipt_acks_requested_on_authenticate() {
+    let mut setup = setup_with_config(ClientConfig {
+        receipt_acks: true,
+        ..Default::default()
+    })
+    .await;
+    match setup.server.expect_client_message().await {
+        ClientMessage::Authenticate { receipt_acks, .. } => assert!(receipt_acks),
+        other => panic!("Expected Authenticate, got {:?}", other),
+    }
+    let _ = setup.server.expect_client_message().await; // sync
+
+    // An ack for nothing this client sent is dropped
+    setup
+        .server
+        .send_server_message(ServerMessage::Ack {
+            message_id: Uuid::new_v4(),
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let stats = setup.engine.stats();
+    assert_eq!(stats.acks_received, 0);
+    assert_eq!(stats.last_ack_latency, None);
+    assert!(setup.engine.is_connected());
+}
+
+/// Test changes are refused once the last sync is older than the offline edit window
+#[tokio::test]
+async fn test_offline_edit_window_requires_sync() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::SyncError;
+
+    let mut setup = setup_with_config(ClientConfig {
+        offline_edit_window: Some(Duration::from_millis(300)),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncComplete { synced_count: 0 })
+        .await;
+    for _ in 0..50 {
+        if setup.engine.time_since_last_sync().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let errors_clone = errors.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::SyncError { message } = event {
+                errors_clone.lock().unwrap().push(message);
+            }
+        })
+        .unwrap();
+
+    // Within the window changes go through as usual
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Fresh" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+
+    // A long gap without a sync
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let result = setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Stale" }))
+        .await;
+    assert!(matches!(
+        result,
+        Err(SyncError::ReconcileRequired { window, .. }) if window == Duration::from_millis(300)
+    ));
+    assert!(matches!(
+        setup
+            .engine
+            .create_document(json!({ "title": "Also stale" }))
+            .await,
+        Err(SyncError::ReconcileRequired { .. })
+    ));
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(errors.lock().unwrap().len(), 2);
+    assert_eq!(
+        setup.db.get_document(&doc.id).await.unwrap().content,
+        json!({ "title": "Fresh" })
+    );
+
+    // An explicit sync lifts the gate
+    let server = async {
+        assert!(matches!(
+            setup.server.expect_client_message().await,
+            ClientMessage::RequestFullSync
+        ));
+        setup
+            .server
+            .send_server_message(ServerMessage::SyncComplete { synced_count: 1 })
+            .await;
+    };
+    let (summary, ()) = tokio::join!(setup.engine.sync_now(), server);
+    summary.unwrap();
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Reconciled" }))
+        .await
+        .unwrap();
+}
+
+/// Test a ServerWins client reconciles by itself once past the offline edit window
+#[tokio::test]
+async fn test_offline_edit_window_server_wins_reconciles() {
+    let mut setup = setup_with_config(ClientConfig {
+        offline_edit_window: Some(Duration::from_millis(300)),
+        conflict_resolution: Some(ConflictResolution::ServerWins),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+    setup
+        .server
+        .send_server_message(ServerMessage::SyncComplete { synced_count: 0 })
+        .await;
+    for _ in 0..50 {
+        if setup.engine.time_since_last_sync().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    let server = async {
+        assert!(matches!(
+            setup.server.expect_client_message().await,
+            ClientMessage::RequestFullSync
+        ));
+        setup
+            .server
+            .send_server_message(ServerMessage::SyncComplete { synced_count: 0 })
+            .await;
+        match setup.server.expect_client_message().await {
+            ClientMessage::CreateDocument { .. } => {}
+            other => panic!("Expected CreateDocument, got {:?}", other),
+        }
+    };
+    let (created, ()) = tokio::join!(
+        setup
+            .engine
+            .create_document(json!({ "title": "After reconcile" })),
+        server
+    );
+    created.unwrap();
+    assert!(setup.engine.time_since_last_sync().unwrap() < Duration::from_millis(300));
+}
+
+/// Offline edits advance local_seq, while sync_revision waits for the server
+#[tokio::test]
+async fn test_local_seq_advances_on_offline_edits() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Draft" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let created_seq = setup.db.local_seq(&doc.id).await.unwrap();
+    assert!(created_seq > 0);
+
+    setup.engine.set_offline(true).await;
+    let mut last_seq = created_seq;
+    for title in ["Second", "Third"] {
+        setup
+            .engine
+            .update_document(doc.id, json!({ "title": title }))
+            .await
+            .unwrap();
+        let local_seq = setup.db.local_seq(&doc.id).await.unwrap();
+        assert!(
+            local_seq > last_seq,
+            "local_seq should advance on each edit"
+        );
+        last_seq = local_seq;
+        assert_eq!(
+            setup.db.get_document(&doc.id).await.unwrap().sync_revision,
+            1
+        );
+    }
+    assert_eq!(setup.db.queue_depth().await.unwrap(), 2);
+
+    // Only the server moves sync_revision, and leaves local_seq alone
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    setup.server.start().await;
+    setup.engine.set_offline(false).await;
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+    match setup.server.expect_client_message().await {
+        ClientMessage::UpdateDocument { patch, .. } => assert_eq!(patch.document_id, doc.id),
+        other => panic!("Expected UpdateDocument, got {:?}", other),
+    }
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentUpdatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: Some(2),
+            sequence: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(
+        setup.db.get_document(&doc.id).await.unwrap().sync_revision,
+        2
+    );
+    assert_eq!(setup.db.local_seq(&doc.id).await.unwrap(), last_seq);
+    assert_eq!(setup.engine.count_pending_sync().await.unwrap(), 0);
+}
+
+/// An edit made while an earlier one was uploading isn't dropped when the
+/// server confirms the earlier one
+#[tokio::test]
+async fn test_edit_during_upload_stays_queued() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Draft" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Sent" }))
+        .await
+        .unwrap();
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::UpdateDocument { .. }
+    ));
+
+    // Held back while the first update is still unconfirmed
+    setup.engine.pause_sync();
+    setup
+        .engine
+        .update_document(doc.id, json!({ "title": "Held back" }))
+        .await
+        .unwrap();
+
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentUpdatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: Some(2),
+            sequence: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let local = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local.sync_revision, 2);
+    assert_eq!(local.content, json!({ "title": "Held back" }));
+    assert_eq!(
+        setup.db.get_sync_status(&doc.id).await.unwrap(),
+        replicant_core::models::SyncStatus::Pending
+    );
+    assert_eq!(setup.db.queue_depth().await.unwrap(), 1);
+
+    // The held-back edit goes out on its own once sync resumes
+    setup.engine.resume_sync().await.unwrap();
+    match setup.server.expect_client_message().await {
+        ClientMessage::UpdateDocument { patch, .. } => {
+            assert_eq!(patch.document_id, doc.id);
+            assert_eq!(
+                patch.patch,
+                serde_json::from_value::<json_patch::Patch>(json!([
+                    { "op": "replace", "path": "/title", "value": "Held back" }
+                ]))
+                .unwrap()
+            );
+        }
+        other => panic!("Expected UpdateDocument, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_sync_loop_is_quarantined() {
+    use replicant_client::events::{DocumentSource, SyncEvent};
+    use replicant_client::SyncLoopLimit;
+    use replicant_core::models::DocumentPatch;
+
+    let mut setup = setup_with_config(ClientConfig {
+        sync_loop_limit: Some(SyncLoopLimit {
+            max_bounces: 3,
+            window: Duration::from_secs(10),
+            quarantine: Duration::from_secs(60),
+        }),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let doc = setup
+        .engine
+        .create_document(json!({ "title": "Watched" }))
+        .await
+        .unwrap();
+    let _ = setup.server.expect_client_message().await; // create
+    setup
+        .server
+        .send_server_message(ServerMessage::DocumentCreatedResponse {
+            document_id: doc.id,
+            success: true,
+            error: None,
+            sync_revision: None,
+            sequence: None,
+            created_at: None,
+            updated_at: None,
+        })
+        .await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // An app that stamps every document it hears about from the server and
+    // saves it again, sending each server change straight back
+    let remote = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let errors = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (remote_clone, errors_clone) = (remote.clone(), errors.clone());
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| match event {
+            SyncEvent::DocumentUpdated {
+                content, source, ..
+            } if source != DocumentSource::Local => remote_clone.lock().unwrap().push(content),
+            SyncEvent::SyncError { message } => errors_clone.lock().unwrap().push(message),
+            _ => {}
+        })
+        .unwrap();
+
+    let mut uploads = 0;
+    for round in 0..10 {
+        // Another client doing the same answers every upload
+        setup
+            .server
+            .send_server_message(ServerMessage::DocumentUpdated {
+                patch: DocumentPatch {
+                    document_id: doc.id,
+                    patch: serde_json::from_value(json!([
+                        { "op": "add", "path": "/round", "value": round }
+                    ]))
+                    .unwrap(),
+                    content_hash: String::new(),
+                },
+            })
+            .await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        setup.engine.event_dispatcher().process_events().unwrap();
+
+        let heard: Vec<_> = remote.lock().unwrap().drain(..).collect();
+        for mut content in heard {
+            content["seen"] = json!(round);
+            setup.engine.update_document(doc.id, content).await.unwrap();
+        }
+
+        match tokio::time::timeout(
+            Duration::from_millis(300),
+            setup.server.from_client_rx.recv(),
+        )
+        .await
+        {
+            Ok(Some(ClientMessage::UpdateDocument { .. })) => {
+                uploads += 1;
+                setup
+                    .server
+                    .send_server_message(ServerMessage::DocumentUpdatedResponse {
+                        document_id: doc.id,
+                        success: true,
+                        error: None,
+                        sync_revision: None,
+                        sequence: None,
+                        updated_at: None,
+                    })
+                    .await;
+            }
+            Ok(other) => panic!("Expected UpdateDocument, got {:?}", other),
+            Err(_) => {}
+        }
+    }
+
+    // The fourth bounce tripped the guard and nothing went out after it
+    assert_eq!(uploads, 3);
+    assert!(setup.engine.is_sync_quarantined(doc.id));
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(
+        errors
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message.contains("in answer to server changes"))
+            .count(),
+        1
+    );
+
+    // The stamps are still saved locally
+    let local = setup.db.get_document(&doc.id).await.unwrap();
+    assert_eq!(local.content["round"], 9);
+    assert_eq!(local.content["seen"], 9);
+}
+
+#[tokio::test]
+async fn test_subscriptions_and_locks_survive_reconnect() {
+    let mut setup = setup().await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    let watched = [Uuid::new_v4(), Uuid::new_v4()];
+    setup.engine.subscribe(&watched).await.unwrap();
+    let _ = setup.server.expect_client_message().await; // subscribe
+
+    let locked = Uuid::new_v4();
+    let server = async {
+        let _ = setup.server.expect_client_message().await; // lock
+        setup
+            .server
+            .send_server_message(ServerMessage::LockResponse {
+                document_id: locked,
+                acquired: true,
+                holder: None,
+            })
+            .await;
+    };
+    let (acquired, ()) = tokio::join!(setup.engine.try_lock(locked), server);
+    assert!(acquired.unwrap());
+
+    // A blip drops the connection and everything the server tied to it
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    setup.server.start().await;
+    tokio::time::sleep(Duration::from_millis(4000)).await;
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::Authenticate { .. }
+    ));
+
+    // Both are restored before the client catches up on documents
+    match setup.server.expect_client_message().await {
+        ClientMessage::Subscribe { mut document_ids } => {
+            document_ids.sort();
+            let mut expected = watched.to_vec();
+            expected.sort();
+            assert_eq!(document_ids, expected);
+        }
+        other => panic!("Expected Subscribe, got {:?}", other),
+    }
+    match setup.server.expect_client_message().await {
+        ClientMessage::AcquireLock { document_id } => assert_eq!(document_id, locked),
+        other => panic!("Expected AcquireLock, got {:?}", other),
+    }
+    setup
+        .server
+        .send_server_message(ServerMessage::LockResponse {
+            document_id: locked,
+            acquired: true,
+            holder: None,
+        })
+        .await;
+    assert!(matches!(
+        setup.server.expect_client_message().await,
+        ClientMessage::RequestFullSync
+    ));
+}
+
+/// Test content past the soft size threshold warns and past the hard limit is refused unsaved
+#[tokio::test]
+async fn test_document_size_limits() {
+    use replicant_client::events::SyncEvent;
+    use replicant_core::SyncError;
+
+    let mut setup = setup_with_config(ClientConfig {
+        max_document_bytes: Some(100),
+        document_size_warning_bytes: Some(50),
+        ..Default::default()
+    })
+    .await;
+    let _ = setup.server.expect_client_message().await; // auth
+    let _ = setup.server.expect_client_message().await; // sync
+
+    setup.server.stop().await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let warnings = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let warnings_clone = warnings.clone();
+    setup
+        .engine
+        .event_dispatcher()
+        .register_rust_callback(move |event| {
+            if let SyncEvent::DocumentSizeWarning {
+                size, threshold, ..
+            } = event
+            {
+                warnings_clone.lock().unwrap().push((size, threshold));
+            }
+        })
+        .unwrap();
+
+    // `{"body":"..."}` serializes to the text plus 11 bytes
+    let sized = |bytes: usize| json!({ "body": "x".repeat(bytes - 11) });
+
+    // Exactly at the soft threshold: saved quietly
+    let doc = setup.engine.create_document(sized(50)).await.unwrap();
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert!(warnings.lock().unwrap().is_empty());
+
+    // One byte over: still saved, with a warning
+    setup
+        .engine
+        .update_document(doc.id, sized(51))
+        .await
+        .unwrap();
+    setup.engine.event_dispatcher().process_events().unwrap();
+    assert_eq!(*warnings.lock().unwrap(), vec![(51, 50)]);
+
+    // Exactly at the hard limit: saved
+    setup
+        .engine
+        .update_document(doc.id, sized(100))
+        .await
+        .unwrap();
+    assert_eq!(
+        setup.db.get_document(&doc.id).await.unwrap().content,
+        sized(100)
+    );
+    let pending = setup.engine.queue_depth().await.unwrap();
+
+    // One byte over: refused before anything is written or queued
+    let result = setup.engine.update_document(doc.id, sized(101)).await;
+    assert!(matches!(
+        result,
+        Err(SyncError::DocumentTooLarge {
+            size: 101,
+            limit: 100
+        })
+    ));
+    let result = setup.engine.create_document(sized(101)).await;
+    assert!(matches!(result, Err(SyncError::DocumentTooLarge { .. })));
+    assert_eq!(
+        setup.db.get_document(&doc.id).await.unwrap().content,
+        sized(100)
+    );
+    assert_eq!(setup.engine.count_documents().await.unwrap(), 1);
+    assert_eq!(setup.engine.queue_depth().await.unwrap(), pending);
+}
+
+/// Test a server resolver routes each user to its shard, also on reconnect
+#[tokio::test]
+async fn test_server_resolver_routes_users_to_shards() {
+    use replicant_core::models::{user_id_from_email, DEFAULT_APP_NAMESPACE};
+    use std::collections::HashMap;
+
+    let mut shards = Vec::new();
+    for _ in 0..3 {
+        let mut server = MockServer::new().await;
+        server.start().await;
+        shards.push(server);
+    }
+    let url = |server: &MockServer| format!("ws://{}", server.addr);
+
+    let (alice, bob) = ("alice@shard.test", "bob@shard.test");
+    let routes = Arc::new(std::sync::Mutex::new(HashMap::from([
+        (
+            user_id_from_email(DEFAULT_APP_NAMESPACE, alice),
+            url(&shards[0]),
+        ),
+        (
+            user_id_from_email(DEFAULT_APP_NAMESPACE, bob),
+            url(&shards[1]),
+        ),
+    ])));
+    let routes_clone = routes.clone();
+    let config = ClientConfig {
+        server_resolver: Some(Arc::new(move |user_id| {
+            routes_clone.lock().unwrap()[&user_id].clone()
+        })),
+        ..Default::default()
+    };
+
+    // Nothing listens on the URL given to the constructor
+    let mut clients = Vec::new();
+    for email in [alice, bob] {
+        clients.push(
+            Client::with_config(
+                &format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()),
+                "ws://127.0.0.1:1/ws",
+                email,
+                "test-key",
+                "test-secret",
+                config.clone(),
+            )
+            .await
+            .unwrap(),
+        );
+    }
+
+    for (shard, email) in [(0, alice), (1, bob)] {
+        match shards[shard].expect_client_message().await {
+            ClientMessage::Authenticate {
+                email: authenticated,
+                ..
+            } => assert_eq!(authenticated, email),
+            other => panic!("Expected Authenticate, got {:?}", other),
+        }
+    }
+    assert_eq!(clients[0].server_url(), url(&shards[0]));
+    assert_eq!(clients[1].server_url(), url(&shards[1]));
+
+    // Bob moves to the third shard; his next connection follows
+    routes.lock().unwrap().insert(
+        user_id_from_email(DEFAULT_APP_NAMESPACE, bob),
+        url(&shards[2]),
+    );
+    shards[1].stop().await;
+    tokio::time::sleep(Duration::from_millis(4000)).await; // Wait for reconnect loop
+    match shards[2].expect_client_message().await {
+        ClientMessage::Authenticate { email, .. } => assert_eq!(email, bob),
+        other => panic!("Expected Authenticate, got {:?}", other),
+    }
+    assert_eq!(clients[1].server_url(), url(&shards[2]));
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use common::{make_document, setup_test_db};
+use serde_json::json;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn test_tags_maintained_on_save_and_delete() {
+    let db = setup_test_db().await;
+    db.ensure_user_config("ws://localhost:8080/ws")
+        .await
+        .unwrap();
+    let user_id = db.get_user_id().await.unwrap();
+
+    let mut work = make_document(user_id, "Report", "quarterly numbers", 1);
+    work.content["tags"] = json!(["work", "urgent"]);
+    let mut home = make_document(user_id, "Groceries", "apples", 1);
+    home.content["tags"] = json!("home");
+    let untagged = make_document(user_id, "Notes", "misc", 1);
+
+    db.save_document(&work).await.unwrap();
+    db.save_document(&home).await.unwrap();
+    db.save_document(&untagged).await.unwrap();
+
+    let ids = |docs: Vec<replicant_core::models::Document>| {
+        docs.into_iter().map(|d| d.id).collect::<Vec<_>>()
+    };
+    assert_eq!(
+        ids(db.documents_with_tag(&user_id, "work").await.unwrap()),
+        vec![work.id]
+    );
+    assert_eq!(
+        ids(db.documents_with_tag(&user_id, "home").await.unwrap()),
+        vec![home.id]
+    );
+
+    // Saving new content replaces the old tags
+    work.content["tags"] = json!(["done"]);
+    db.save_document(&work).await.unwrap();
+    assert!(db
+        .documents_with_tag(&user_id, "work")
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        ids(db.documents_with_tag(&user_id, "done").await.unwrap()),
+        vec![work.id]
+    );
+
+    // Deleted documents drop out of the index
+    db.delete_document(&home.id).await.unwrap();
+    assert!(db
+        .documents_with_tag(&user_id, "home")
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Moving the tag path re-indexes existing documents
+    db.configure_tags("$.title").await.unwrap();
+    assert_eq!(db.get_tag_path().await.unwrap().as_deref(), Some("$.title"));
+    assert!(db
+        .documents_with_tag(&user_id, "done")
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        ids(db.documents_with_tag(&user_id, "Notes").await.unwrap()),
+        vec![untagged.id]
+    );
+}
+
+#[tokio::test]
+async fn test_client_documents_with_tag_custom_path() {
+    use replicant_client::{Client, ClientConfig};
+
+    // No server listening - tag queries must work offline
+    let client = Client::with_config(
+        &format!("file:{}?mode=memory&cache=shared", Uuid::new_v4()),
+        "ws://127.0.0.1:1/ws",
+        "tags@test.local",
+        "key",
+        "secret",
+        ClientConfig {
+            tag_path: Some("$.meta.labels".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let target = client
+        .create_document(json!({"title": "Trip", "meta": {"labels": ["travel", "2026"]}}))
+        .await
+        .unwrap();
+    client
+        .create_document(json!({"title": "Budget", "meta": {"labels": ["finance"]}}))
+        .await
+        .unwrap();
+    // Tags at the default path are ignored once another path is configured
+    client
+        .create_document(json!({"title": "Other", "tags": ["travel"]}))
+        .await
+        .unwrap();
+
+    let results = client.documents_with_tag("travel").await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, target.id);
+
+    client
+        .update_document(
+            target.id,
+            json!({"title": "Trip", "meta": {"labels": ["archive"]}}),
+        )
+        .await
+        .unwrap();
+    assert!(client
+        .documents_with_tag("travel")
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(client.documents_with_tag("archive").await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_memory_store_honors_custom_tag_path() {
+    use replicant_client::{DocumentStore, MemoryStore};
+
+    let store = MemoryStore::new();
+    let user_id = Uuid::new_v4();
+    let mut labelled = make_document(user_id, "Trip", "itinerary", 1);
+    labelled.content["meta"] = json!({"labels": ["travel"]});
+    let mut tagged = make_document(user_id, "Other", "misc", 1);
+    tagged.content["tags"] = json!(["travel"]);
+    store.save_document(&labelled).await.unwrap();
+    store.save_document(&tagged).await.unwrap();
+
+    store.configure_tags("$.meta.labels").await.unwrap();
+    assert_eq!(
+        store.get_tag_path().await.unwrap().as_deref(),
+        Some("$.meta.labels")
+    );
+    let results = store.documents_with_tag(&user_id, "travel").await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, labelled.id);
+}
@@ -0,0 +1,127 @@
+use futures_util::StreamExt;
+use replicant_client::websocket::DEFAULT_CONNECT_TIMEOUT;
+use replicant_client::{Sha256Fingerprint, WebSocketClient};
+use replicant_core::protocol::{ClientMessage, DEFAULT_MAX_MESSAGE_BYTES};
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::{
+    pki_types::{CertificateDer, PrivatePkcs8KeyDer},
+    ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::{accept_async, tungstenite::Message};
+use uuid::Uuid;
+
+// Self-signed certificate for `localhost`
+const CERT_DER: &[u8] = include_bytes!("fixtures/pinned_cert.der");
+const KEY_DER: &[u8] = include_bytes!("fixtures/pinned_key.der");
+// openssl x509 -in cert.pem -noout -fingerprint -sha256
+const CERT_FINGERPRINT: &str =
+    "4C:DC:D8:00:04:67:34:F5:14:0D:7D:3F:D2:5B:4C:B8:CD:49:ED:F0:B1:37:5E:E5:DA:AE:AC:AC:C6:C6:27:F5";
+
+/// Starts a `wss://` server presenting the fixture certificate. Messages from
+/// clients that complete the handshake are forwarded on the returned channel.
+async fn start_tls_server() -> (SocketAddr, mpsc::Receiver<ClientMessage>) {
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![CertificateDer::from(CERT_DER.to_vec())],
+            PrivatePkcs8KeyDer::from(KEY_DER.to_vec()).into(),
+        )
+        .unwrap();
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel(10);
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                // Rejected handshakes just drop the connection
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let Ok(mut ws) = accept_async(stream).await else {
+                    return;
+                };
+                while let Some(Ok(msg)) = ws.next().await {
+                    if let Message::Text(text) = msg {
+                        let _ = tx.send(serde_json::from_str(&text).unwrap()).await;
+                    }
+                }
+            });
+        }
+    });
+
+    (addr, rx)
+}
+
+async fn connect(
+    addr: SocketAddr,
+    pins: &[Sha256Fingerprint],
+) -> replicant_core::SyncResult<WebSocketClient> {
+    WebSocketClient::connect(
+        &format!("wss://localhost:{}", addr.port()),
+        "pinned@test.local",
+        Uuid::new_v4(),
+        "key",
+        "secret",
+        None,
+        None,
+        Arc::new(AtomicBool::new(false)),
+        None,
+        false,
+        DEFAULT_MAX_MESSAGE_BYTES,
+        pins,
+        Duration::ZERO,
+        DEFAULT_CONNECT_TIMEOUT,
+    )
+    .await
+    .map(|(client, _)| client)
+}
+
+#[test]
+fn test_fingerprint_parsing() {
+    let pin = Sha256Fingerprint::from_hex(CERT_FINGERPRINT).unwrap();
+    assert_eq!(pin, Sha256Fingerprint::of(CERT_DER));
+    assert_eq!(pin.to_string(), CERT_FINGERPRINT);
+    assert_eq!(
+        Sha256Fingerprint::from_hex(&CERT_FINGERPRINT.replace(':', "").to_lowercase()).unwrap(),
+        pin
+    );
+    assert!(Sha256Fingerprint::from_hex("4C:DC").is_err());
+}
+
+#[tokio::test]
+async fn test_pinned_certificate_is_accepted() {
+    let (addr, mut received) = start_tls_server().await;
+
+    let pin = Sha256Fingerprint::from_hex(CERT_FINGERPRINT).unwrap();
+    let _client = connect(addr, &[pin]).await.unwrap();
+
+    let msg = tokio::time::timeout(Duration::from_secs(2), received.recv())
+        .await
+        .expect("Timed out waiting for authentication")
+        .unwrap();
+    assert!(matches!(msg, ClientMessage::Authenticate { .. }));
+}
+
+#[tokio::test]
+async fn test_unpinned_certificate_is_rejected() {
+    let (addr, mut received) = start_tls_server().await;
+
+    // A different pin fails the handshake even though the server is reachable
+    let other = Sha256Fingerprint::of(b"some other certificate");
+    assert!(connect(addr, &[other]).await.is_err());
+
+    // Without pins the self-signed certificate fails standard verification
+    assert!(connect(addr, &[]).await.is_err());
+
+    assert!(received.try_recv().is_err());
+}
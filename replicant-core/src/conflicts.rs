@@ -1,7 +1,47 @@
 use crate::errors::SyncError;
 use crate::models::Document;
+use crate::protocol::ConflictResolution;
 use crate::SyncResult;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use strum::{Display, EnumString};
+
+/// Reserved content field naming how the server settles conflicting updates
+/// to that document, e.g. `{"_conflict_strategy": "field_merge"}`
+pub const CONFLICT_STRATEGY_FIELD: &str = "_conflict_strategy";
+
+/// Per-document conflict strategy, read by the server from
+/// [`CONFLICT_STRATEGY_FIELD`] in the stored content. It takes precedence
+/// over the `ConflictResolution` of the client sending the update, so one
+/// app can mix strategies across document types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DocumentConflictStrategy {
+    /// The later update is applied over the stored content
+    LastWriteWins,
+    /// The parts of the later update that still apply are kept
+    FieldMerge,
+    /// The stored content stays and the later update is rejected
+    ServerWins,
+}
+
+impl DocumentConflictStrategy {
+    /// The strategy named in `content`, if it names a known one
+    pub fn from_content(content: &Value) -> Option<Self> {
+        content.get(CONFLICT_STRATEGY_FIELD)?.as_str()?.parse().ok()
+    }
+}
+
+impl From<DocumentConflictStrategy> for ConflictResolution {
+    fn from(strategy: DocumentConflictStrategy) -> Self {
+        match strategy {
+            DocumentConflictStrategy::LastWriteWins => ConflictResolution::LastWriteWins,
+            DocumentConflictStrategy::FieldMerge => ConflictResolution::FieldMerge,
+            DocumentConflictStrategy::ServerWins => ConflictResolution::ServerWins,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub enum ConflictStrategy {
@@ -1,10 +1,14 @@
 use crate::protocol;
-use axum::http::StatusCode;
-use axum::response::{IntoResponse, Response};
+#[cfg(not(target_arch = "wasm32"))]
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use chrono::ParseError;
 use std::fmt::{Display, Formatter};
 use thiserror::Error;
 use tokio::sync::mpsc::error::SendError;
+#[cfg(not(target_arch = "wasm32"))]
 use tracing::log::warn;
 #[derive(Error, Debug)]
 #[non_exhaustive]
@@ -24,12 +28,16 @@ pub enum SyncError {
     #[error("Network error: {0}")]
     NetworkError(String),
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("Database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
 
+    #[error("Failed to deserialize document content: {0}")]
+    Deserialize(#[source] serde_json::Error),
+
     #[error("UUID parsing error: {0}")]
     UuidParse(#[from] uuid::Error),
 
@@ -39,15 +47,39 @@ pub enum SyncError {
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
 
+    #[error("Validation failed: {0}")]
+    Validation(String),
+
+    #[error("Document content of {size} bytes exceeds the {limit} byte limit")]
+    DocumentTooLarge { size: usize, limit: usize },
+
+    #[error(
+        "Sync queue is full ({depth} of {limit} entries); changes are refused until it drains"
+    )]
+    QueueFull { depth: usize, limit: usize },
+
+    #[error("Last synced {since:?} ago, beyond the {window:?} offline edit window; sync before making changes")]
+    ReconcileRequired {
+        since: std::time::Duration,
+        window: std::time::Duration,
+    },
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("Date parsing error: {0}")]
     DateParse(#[from] ParseError),
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("Migration error: {0}")]
     MigrationError(#[from] sqlx::migrate::MigrateError),
 
+    #[error("Database schema version {found} is newer than the supported version {supported}")]
+    SchemaTooNew { found: i64, supported: i64 },
+
+    #[error("No user is configured in the local database; run first-time setup")]
+    NotInitialized,
+
     #[error("Client error: {0}")]
     Client(#[from] ClientError),
 
@@ -61,24 +93,32 @@ pub enum ServerError {
     #[error("{0}")]
     ApiError(#[from] ApiError),
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[error("argon2 Library Error: {0}")]
     HashingError(argon2::password_hash::Error),
 
     #[error("Server sync error: {0}")]
     ServerSync(String),
 
+    // Boxed: the unsent message would otherwise make every SyncResult large
     #[error("Server Channel send failed: {0}")]
-    SendError(#[from] SendError<protocol::ServerMessage>),
+    SendError(Box<SendError<protocol::ServerMessage>>),
+
+    #[error("Invalid content schema: {0}")]
+    InvalidSchema(String),
 }
 
 #[derive(Error, Debug)]
 #[non_exhaustive]
 pub enum ClientError {
-    #[error("WebSocket error: {0}")]
-    WebSocket(String),
+    #[error("Connection lost: {0}")]
+    ConnectionLost(String),
+
+    #[error("Not connected to the server")]
+    Closed,
 
-    #[error("Connection lost")]
-    ConnectionLost,
+    #[error("Failed to send message: {0}")]
+    SendFailed(String),
 
     #[error("Invalid state: {0}")]
     InvalidState(String),
@@ -97,8 +137,175 @@ pub enum ClientError {
 
     #[error("Internal channel closed")]
     ChannelClosed,
+
+    #[error("Encryption error: {0}")]
+    Encryption(String),
+
+    #[error("Timed out: {0}")]
+    Timeout(String),
+}
+
+/// Broad kind of a [`SyncError`], for deciding how to react to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The server couldn't be reached or the connection dropped
+    Network,
+    /// The server didn't answer in time
+    Timeout,
+    /// The credentials were refused
+    Authentication,
+    /// The change clashed with someone else's
+    Conflict,
+    /// The document or resource doesn't exist
+    NotFound,
+    /// The request itself was invalid
+    Validation,
+    /// A limit was reached that clears by itself
+    Capacity,
+    /// The local or server database failed
+    Storage,
+    /// Content couldn't be parsed, patched or decrypted
+    Data,
+    /// A bug, or the client being used wrongly
+    Internal,
 }
 
+impl SyncError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            SyncError::NetworkError(_) => ErrorCategory::Network,
+            SyncError::AuthenticationFailed(_) => ErrorCategory::Authentication,
+            SyncError::VersionMismatch { .. }
+            | SyncError::ConflictDetected(_)
+            | SyncError::ReconcileRequired { .. } => ErrorCategory::Conflict,
+            SyncError::DocumentNotFound(_) | SyncError::NotInitialized => ErrorCategory::NotFound,
+            SyncError::InvalidOperation(_)
+            | SyncError::Validation(_)
+            | SyncError::DocumentTooLarge { .. } => ErrorCategory::Validation,
+            SyncError::QueueFull { .. } => ErrorCategory::Capacity,
+            #[cfg(not(target_arch = "wasm32"))]
+            SyncError::DatabaseError(_) | SyncError::MigrationError(_) => ErrorCategory::Storage,
+            SyncError::SchemaTooNew { .. } => ErrorCategory::Storage,
+            SyncError::Io(e) => match e.kind() {
+                std::io::ErrorKind::TimedOut => ErrorCategory::Timeout,
+                kind if is_connection_error(kind) => ErrorCategory::Network,
+                _ => ErrorCategory::Storage,
+            },
+            SyncError::PatchFailed(_)
+            | SyncError::SerializationError(_)
+            | SyncError::Deserialize(_)
+            | SyncError::UuidParse(_)
+            | SyncError::DateParse(_) => ErrorCategory::Data,
+            SyncError::Client(e) => e.category(),
+            SyncError::Server(e) => e.category(),
+        }
+    }
+
+    /// Whether the same operation may succeed if tried again later, once the
+    /// connection is back, the queue has drained or the client has synced.
+    /// Anything else needs the caller to change something first.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SyncError::NetworkError(_)
+            | SyncError::QueueFull { .. }
+            | SyncError::ReconcileRequired { .. } => true,
+            #[cfg(not(target_arch = "wasm32"))]
+            SyncError::DatabaseError(e) => is_transient_database_error(e),
+            SyncError::Io(e) => {
+                matches!(
+                    e.kind(),
+                    std::io::ErrorKind::Interrupted
+                        | std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::TimedOut
+                ) || is_connection_error(e.kind())
+            }
+            SyncError::Client(e) => e.is_retryable(),
+            SyncError::Server(e) => e.is_retryable(),
+            _ => false,
+        }
+    }
+}
+
+impl ClientError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ClientError::ConnectionLost(_) | ClientError::Closed | ClientError::SendFailed(_) => {
+                ErrorCategory::Network
+            }
+            ClientError::Timeout(_) => ErrorCategory::Timeout,
+            ClientError::Encryption(_) => ErrorCategory::Data,
+            ClientError::InvalidState(_)
+            | ClientError::SendError(_)
+            | ClientError::LockError(_)
+            | ClientError::ThreadSafetyViolation
+            | ClientError::NoCallbacksRegistered
+            | ClientError::ChannelClosed => ErrorCategory::Internal,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.category(),
+            ErrorCategory::Network | ErrorCategory::Timeout
+        )
+    }
+}
+
+impl ServerError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            ServerError::ApiError(e) => match e {
+                ApiError::InternalServerError(_) => ErrorCategory::Internal,
+                ApiError::BadRequest(..) => ErrorCategory::Validation,
+                ApiError::Unauthorized(_) => ErrorCategory::Authentication,
+                ApiError::ServiceUnavailable(_) => ErrorCategory::Network,
+                ApiError::NotFound(_) => ErrorCategory::NotFound,
+                ApiError::Conflict(..) => ErrorCategory::Conflict,
+            },
+            #[cfg(not(target_arch = "wasm32"))]
+            ServerError::HashingError(_) => ErrorCategory::Internal,
+            ServerError::ServerSync(_) => ErrorCategory::Internal,
+            ServerError::SendError(_) => ErrorCategory::Network,
+            ServerError::InvalidSchema(_) => ErrorCategory::Validation,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        // The connection a failed send was meant for is gone for good
+        matches!(self, ServerError::ApiError(ApiError::ServiceUnavailable(_)))
+    }
+}
+
+fn is_connection_error(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        kind,
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::BrokenPipe
+            | ErrorKind::NotConnected
+    )
+}
+
+// Pool exhaustion, lost connections, and lock contention that a later attempt
+// can get past: SQLite's BUSY and LOCKED, Postgres serialization failures
+// and deadlocks
+#[cfg(not(target_arch = "wasm32"))]
+fn is_transient_database_error(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::PoolTimedOut | sqlx::Error::Io(_) => true,
+        sqlx::Error::Database(e) => {
+            matches!(
+                e.code().as_deref(),
+                Some("5" | "6" | "517" | "40001" | "40P01")
+            )
+        }
+        _ => false,
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
 impl From<argon2::password_hash::Error> for SyncError {
     fn from(error: argon2::password_hash::Error) -> Self {
         SyncError::Server(ServerError::HashingError(error))
@@ -178,6 +385,7 @@ impl Display for ApiError {
         }
     }
 }
+#[cfg(not(target_arch = "wasm32"))]
 impl IntoResponse for SyncError {
     fn into_response(self) -> Response {
         #[derive(serde::Serialize)]
@@ -217,6 +425,12 @@ impl From<ApiError> for SyncError {
     }
 }
 
+impl From<SendError<protocol::ServerMessage>> for ServerError {
+    fn from(value: SendError<protocol::ServerMessage>) -> Self {
+        ServerError::SendError(Box::new(value))
+    }
+}
+
 impl From<SendError<protocol::ServerMessage>> for SyncError {
     fn from(value: SendError<protocol::ServerMessage>) -> Self {
         SyncError::Server(value.into())
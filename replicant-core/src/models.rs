@@ -1,5 +1,6 @@
+use crate::{errors::SyncError, SyncResult};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use strum::{Display, EnumString};
 use uuid::Uuid;
 
@@ -14,6 +15,13 @@ pub struct Document {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
+    // When the document deletes itself, for ephemeral data like share links
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    // Local sync state, filled in by the client's store. It is never sent
+    // over the wire: documents from the server are synced by definition.
+    #[serde(skip)]
+    pub sync_status: SyncStatus,
 }
 
 impl Document {
@@ -26,12 +34,112 @@ impl Document {
     pub fn title_or_default(&self) -> &str {
         self.title().unwrap_or("Untitled")
     }
+
+    /// Whether the document's expiry time has passed
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now())
+    }
+
+    /// Local changes not yet confirmed by the server
+    pub fn is_pending(&self) -> bool {
+        self.sync_status == SyncStatus::Pending
+    }
+
+    pub fn is_synced(&self) -> bool {
+        self.sync_status == SyncStatus::Synced
+    }
+
+    /// A local edit lost to the server's version and awaits resolution
+    pub fn is_conflict(&self) -> bool {
+        self.sync_status == SyncStatus::Conflict
+    }
+
+    /// Deserialize the content JSON into an application type.
+    ///
+    /// Fails with `SyncError::Deserialize` if the content doesn't match `T`.
+    pub fn parse<T: DeserializeOwned>(&self) -> SyncResult<T> {
+        T::deserialize(&self.content).map_err(SyncError::Deserialize)
+    }
+}
+
+/// The app namespace used when none is configured
+pub const DEFAULT_APP_NAMESPACE: &str = "com.example.sync-task-list";
+
+/// The user ID for an email address within an app namespace.
+///
+/// The server and every client derive IDs this way, so an email maps to the
+/// same user everywhere: a UUIDv5 of the email within a UUIDv5 of the
+/// namespace under the DNS namespace.
+pub fn user_id_from_email(namespace: &str, email: &str) -> Uuid {
+    let app_namespace = Uuid::new_v5(&Uuid::NAMESPACE_DNS, namespace.as_bytes());
+    Uuid::new_v5(&app_namespace, email.as_bytes())
+}
+
+/// Where document tags are read from when no other path is configured
+pub const DEFAULT_TAG_PATH: &str = "$.tags";
+
+/// The tags at `path` in document content, sorted and without duplicates.
+///
+/// `path` is a dotted JSON path such as `$.tags` or `$.meta.labels`. The
+/// value there may be an array of strings or a single string; anything else
+/// means the document has no tags.
+pub fn extract_tags(content: &serde_json::Value, path: &str) -> Vec<String> {
+    let mut value = content;
+    for key in path
+        .trim_start_matches('$')
+        .split('.')
+        .filter(|k| !k.is_empty())
+    {
+        match value.get(key) {
+            Some(inner) => value = inner,
+            None => return Vec::new(),
+        }
+    }
+    let mut tags: Vec<String> = match value {
+        serde_json::Value::String(tag) => vec![tag.clone()],
+        serde_json::Value::Array(items) => items
+            .iter()
+            .filter_map(|item| item.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    tags.sort();
+    tags.dedup();
+    tags
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_extract_tags() {
+        let content = serde_json::json!({
+            "tags": ["work", "urgent", "work", 3],
+            "meta": { "label": "home" }
+        });
+        assert_eq!(
+            extract_tags(&content, DEFAULT_TAG_PATH),
+            vec!["urgent".to_string(), "work".to_string()]
+        );
+        assert_eq!(extract_tags(&content, "$.meta.label"), vec!["home"]);
+        assert!(extract_tags(&content, "$.missing").is_empty());
+        assert!(extract_tags(&serde_json::json!({ "tags": 5 }), DEFAULT_TAG_PATH).is_empty());
+    }
+
+    #[test]
+    fn test_user_id_from_email_is_pinned() {
+        // Changing the derivation would orphan every existing user's data
+        assert_eq!(
+            user_id_from_email(DEFAULT_APP_NAMESPACE, "alice@example.com"),
+            Uuid::parse_str("b1e4bb4b-54e7-5642-a0e5-c4366ba0d27c").unwrap()
+        );
+        assert_ne!(
+            user_id_from_email("com.example.other-app", "alice@example.com"),
+            user_id_from_email(DEFAULT_APP_NAMESPACE, "alice@example.com")
+        );
+    }
+
     #[test]
     fn test_document_title_helpers() {
         // Test document with title
@@ -45,6 +153,8 @@ mod tests {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         assert_eq!(doc_with_title.title(), Some("My Document"));
@@ -61,11 +171,77 @@ mod tests {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         assert_eq!(doc_without_title.title(), None);
         assert_eq!(doc_without_title.title_or_default(), "Untitled");
     }
+
+    #[test]
+    fn test_sync_status_is_not_serialized() {
+        let doc = Document {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            content: serde_json::json!({"title": "Local"}),
+            sync_revision: 1,
+            content_hash: None,
+            title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Pending,
+        };
+        assert!(doc.is_pending());
+
+        let json = serde_json::to_value(&doc).unwrap();
+        assert!(json.get("sync_status").is_none());
+
+        // Documents from the wire are synced
+        let received: Document = serde_json::from_value(json).unwrap();
+        assert!(received.is_synced());
+    }
+
+    #[test]
+    fn test_document_parse() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Task {
+            title: String,
+            priority: u8,
+        }
+
+        let doc = Document {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            content: serde_json::json!({"title": "Ship it", "priority": 2}),
+            sync_revision: 1,
+            content_hash: None,
+            title: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
+        };
+        assert_eq!(
+            doc.parse::<Task>().unwrap(),
+            Task {
+                title: "Ship it".to_string(),
+                priority: 2
+            }
+        );
+
+        let untyped = Document {
+            content: serde_json::json!({"title": "No priority"}),
+            ..doc
+        };
+        assert!(matches!(
+            untyped.parse::<Task>(),
+            Err(SyncError::Deserialize(_))
+        ));
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,10 +251,11 @@ pub struct DocumentPatch {
     pub content_hash: String, // SHA256 hash for integrity verification
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Display, EnumString)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Display, EnumString)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum SyncStatus {
+    #[default]
     Synced,
     Pending,
     Conflict,
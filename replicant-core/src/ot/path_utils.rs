@@ -126,7 +126,7 @@ pub fn adjust_array_index(
 }
 
 /// Helper: Reconstruct path from segments
-fn reconstruct_path(segments: &[PathSegment]) -> String {
+pub(crate) fn reconstruct_path(segments: &[PathSegment]) -> String {
     if segments.is_empty() {
         return "/".to_string();
     }
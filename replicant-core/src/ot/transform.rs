@@ -4,9 +4,9 @@
 //! concurrent JSON Patch operations.
 
 use crate::ot::path_utils::*;
-use crate::ot::types::PathRelation;
+use crate::ot::types::{PathRelation, PathSegment};
 use crate::SyncError;
-use json_patch::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation};
+use json_patch::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation, TestOperation};
 
 // ============================================================================
 // Add vs Add Transformation
@@ -181,6 +181,85 @@ pub fn transform_replace_replace(
     }
 }
 
+// ============================================================================
+// Test (Guard) Operations
+// ============================================================================
+
+/// Transform a Test operation against a concurrent operation
+///
+/// An add or remove earlier in an array the test looks into shifts its index.
+/// Any other write to the tested value, its parents or its children means the
+/// test no longer checks what its author saw, so the transform fails and the
+/// guarded patch is rejected as a whole.
+pub fn transform_test(
+    test: &TestOperation,
+    other: &PatchOperation,
+) -> Result<TestOperation, SyncError> {
+    let (written, shift) = match other {
+        PatchOperation::Test(_) => return Ok(test.clone()),
+        PatchOperation::Add(op) => (vec![op.path.as_str()], 1),
+        PatchOperation::Remove(op) => (vec![op.path.as_str()], -1),
+        PatchOperation::Replace(op) => (vec![op.path.as_str()], 0),
+        PatchOperation::Move(op) => (vec![op.from.as_str(), op.path.as_str()], 0),
+        PatchOperation::Copy(op) => (vec![op.path.as_str()], 0),
+    };
+
+    if shift != 0 {
+        if let Some(path) = shift_test_path(&test.path, written[0], shift)? {
+            return Ok(TestOperation {
+                path,
+                value: test.value.clone(),
+            });
+        }
+    }
+
+    match written.iter().find(|path| paths_conflict(path, &test.path)) {
+        Some(path) => Err(SyncError::PatchFailed(format!(
+            "Test at {} invalidated by a concurrent change to {}",
+            test.path, path
+        ))),
+        None => Ok(test.clone()),
+    }
+}
+
+/// The test path with its index into the array `op_path` adds to or removes
+/// from moved by `shift`, or None if the tested element doesn't move
+fn shift_test_path(
+    test_path: &str,
+    op_path: &str,
+    shift: isize,
+) -> Result<Option<String>, SyncError> {
+    // The whole document can't be shifted
+    if test_path.is_empty() {
+        return Ok(None);
+    }
+    let test = parse_path(test_path)?;
+    let op = parse_path(op_path)?;
+
+    let Some((PathSegment::Array(op_idx), array)) = op.segments.split_last() else {
+        return Ok(None);
+    };
+    if test.segments.len() <= array.len() || !test.segments.starts_with(array) {
+        return Ok(None);
+    }
+    let PathSegment::Array(test_idx) = test.segments[array.len()] else {
+        return Ok(None);
+    };
+
+    // An add at the tested index pushes it along; a remove there deletes it
+    let moved = match shift {
+        1 => *op_idx <= test_idx,
+        _ => *op_idx < test_idx,
+    };
+    if !moved {
+        return Ok(None);
+    }
+
+    let mut segments = test.segments;
+    segments[array.len()] = PathSegment::Array((test_idx as isize + shift) as usize);
+    Ok(Some(reconstruct_path(&segments)))
+}
+
 // ============================================================================
 // Main Transform Function
 // ============================================================================
@@ -190,6 +269,9 @@ pub fn transform_replace_replace(
 /// Handles: Add, Remove, Replace with array index adjustments
 /// Returns conflicts for caller to resolve
 ///
+/// Test operations are guards: they follow the other side's array index
+/// changes, and fail the transform if the other side writes what they test
+///
 /// # Examples
 /// ```
 /// use json_patch::{PatchOperation, AddOperation};
@@ -256,10 +338,15 @@ pub fn transform_operation_pair(
             ))
         }
 
-        // Test operations - pass through (read-only)
-        (PatchOperation::Test(_), _) | (_, PatchOperation::Test(_)) => {
-            Ok((Some(local.clone()), Some(remote.clone())))
-        }
+        // Test operations - read-only guards on what the other side writes
+        (PatchOperation::Test(l), _) => Ok((
+            Some(PatchOperation::Test(transform_test(l, remote)?)),
+            Some(remote.clone()),
+        )),
+        (_, PatchOperation::Test(r)) => Ok((
+            Some(local.clone()),
+            Some(PatchOperation::Test(transform_test(r, local)?)),
+        )),
 
         // Move/Copy - mark as conflict for MVP
         (PatchOperation::Move(_), _)
@@ -576,6 +663,72 @@ mod tests {
         assert!(r.is_some());
     }
 
+    #[test]
+    fn test_transform_test_follows_array_shift() {
+        let test = TestOperation {
+            path: "/items/3/done".into(),
+            value: json!(false),
+        };
+        let insert = PatchOperation::Add(AddOperation {
+            path: "/items/1".into(),
+            value: json!({"done": true}),
+        });
+        let remove = PatchOperation::Remove(RemoveOperation {
+            path: "/items/0".into(),
+        });
+
+        assert_eq!(
+            transform_test(&test, &insert).unwrap().path,
+            "/items/4/done"
+        );
+        assert_eq!(
+            transform_test(&test, &remove).unwrap().path,
+            "/items/2/done"
+        );
+    }
+
+    #[test]
+    fn test_transform_operation_pair_guard_still_holds() {
+        let local = PatchOperation::Test(json_patch::TestOperation {
+            path: "/status".into(),
+            value: json!("draft"),
+        });
+        let remote = PatchOperation::Replace(ReplaceOperation {
+            path: "/title".into(),
+            value: json!("Renamed"),
+        });
+
+        let (l, r) = transform_operation_pair(&local, &remote).unwrap();
+        assert_eq!(l, Some(local));
+        assert_eq!(r, Some(remote));
+    }
+
+    #[test]
+    fn test_transform_operation_pair_guard_invalidated() {
+        let guard = PatchOperation::Test(json_patch::TestOperation {
+            path: "/status".into(),
+            value: json!("draft"),
+        });
+        let concurrent = PatchOperation::Replace(ReplaceOperation {
+            path: "/status".into(),
+            value: json!("published"),
+        });
+        let removed_item = PatchOperation::Test(json_patch::TestOperation {
+            path: "/items/2/done".into(),
+            value: json!(false),
+        });
+        let remove = PatchOperation::Remove(RemoveOperation {
+            path: "/items/2".into(),
+        });
+
+        assert!(matches!(
+            transform_operation_pair(&guard, &concurrent),
+            Err(SyncError::PatchFailed(_))
+        ));
+        assert!(transform_operation_pair(&concurrent, &guard).is_err());
+        assert!(transform_operation_pair(&removed_item, &remove).is_err());
+    }
+
     #[test]
     fn test_transform_operation_pair_move_conflict() {
         let local = PatchOperation::Move(json_patch::MoveOperation {
@@ -5,15 +5,144 @@ use json_patch::{Patch, PatchOperation};
 use serde_json::Value;
 use sha2::{Digest, Sha256};
 
+/// Default cap on the operations in one patch
+pub const DEFAULT_MAX_PATCH_OPERATIONS: usize = 10_000;
+
+/// Default cap on the segments in a patch operation's path
+pub const DEFAULT_MAX_PATCH_PATH_DEPTH: usize = 64;
+
+/// Caps on the shape of a patch, bounding the work that applying or
+/// transforming it takes. Untrusted patches are checked before either.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchLimits {
+    /// Most operations a single patch may carry
+    pub max_operations: usize,
+    /// Most segments in an operation's `path` or `from`
+    pub max_path_depth: usize,
+}
+
+impl Default for PatchLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: DEFAULT_MAX_PATCH_OPERATIONS,
+            max_path_depth: DEFAULT_MAX_PATCH_PATH_DEPTH,
+        }
+    }
+}
+
+impl PatchLimits {
+    /// Fail with `SyncError::Validation` if the patch exceeds these limits
+    pub fn check(&self, patch: &Patch) -> SyncResult<()> {
+        if patch.0.len() > self.max_operations {
+            return Err(SyncError::Validation(format!(
+                "Patch of {} operations exceeds the limit of {}",
+                patch.0.len(),
+                self.max_operations
+            )));
+        }
+
+        for op in &patch.0 {
+            let paths = match op {
+                PatchOperation::Add(op) => [Some(&op.path), None],
+                PatchOperation::Remove(op) => [Some(&op.path), None],
+                PatchOperation::Replace(op) => [Some(&op.path), None],
+                PatchOperation::Test(op) => [Some(&op.path), None],
+                PatchOperation::Move(op) => [Some(&op.path), Some(&op.from)],
+                PatchOperation::Copy(op) => [Some(&op.path), Some(&op.from)],
+            };
+            for path in paths.into_iter().flatten() {
+                let depth = path.matches('/').count();
+                if depth > self.max_path_depth {
+                    return Err(SyncError::Validation(format!(
+                        "Patch path of depth {} exceeds the limit of {}",
+                        depth, self.max_path_depth
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 pub fn create_patch(from: &Value, to: &Value) -> SyncResult<Patch> {
     let diff = json_patch::diff(from, to);
     Ok(diff)
 }
 
+/// Apply a JSON patch atomically: if any operation fails, including a `test`
+/// guard, the document is left as it was.
 pub fn apply_patch(document: &mut Value, patch: &Patch) -> SyncResult<()> {
     json_patch::patch(document, patch).map_err(|e| SyncError::PatchFailed(e.to_string()))
 }
 
+/// Apply a patch from an untrusted source, rejecting it first if it exceeds
+/// `limits`
+pub fn apply_patch_with_limits(
+    document: &mut Value,
+    patch: &Patch,
+    limits: &PatchLimits,
+) -> SyncResult<()> {
+    limits.check(patch)?;
+    apply_patch(document, patch)
+}
+
+/// The operations of `patch` that apply to `document` one at a time, each
+/// on top of those kept before it. Operations that fail are left out.
+pub fn applicable_operations(document: &Value, patch: &Patch) -> Patch {
+    let mut scratch = document.clone();
+    let kept = patch
+        .0
+        .iter()
+        .filter(|op| json_patch::patch(&mut scratch, std::slice::from_ref(*op)).is_ok())
+        .cloned()
+        .collect();
+    Patch(kept)
+}
+
+/// Whether the patch carries `test` operations guarding its changes
+pub fn has_test_guards(patch: &Patch) -> bool {
+    patch
+        .0
+        .iter()
+        .any(|op| matches!(op, PatchOperation::Test(_)))
+}
+
+/// Apply an RFC 7386 JSON merge patch: objects merge recursively, `null`
+/// removes a field and any other value replaces the target outright.
+pub fn apply_merge_patch(document: &mut Value, patch: &Value) {
+    json_patch::merge(document, patch)
+}
+
+/// Build an RFC 7386 merge patch that turns `from` into `to`.
+///
+/// Merge patches can't express setting a field to `null` (it means delete),
+/// and arrays are always replaced whole.
+pub fn create_merge_patch(from: &Value, to: &Value) -> Value {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut patch = serde_json::Map::new();
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, value) in to {
+                match from.get(key) {
+                    Some(old) if old == value => {}
+                    Some(old) => {
+                        patch.insert(key.clone(), create_merge_patch(old, value));
+                    }
+                    None => {
+                        patch.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Value::Object(patch)
+        }
+        _ => to.clone(),
+    }
+}
+
 pub fn calculate_checksum(value: &Value) -> String {
     let json_string = serde_json::to_string(value).unwrap();
     let mut hasher = Sha256::new();
@@ -45,7 +174,8 @@ pub fn compute_reverse_patch(original: &Value, forward_patch: &Patch) -> SyncRes
 /// The OT algorithm handles:
 /// - Array index adjustments when operations affect the same array
 /// - Conflict detection when operations target the same path
-/// - Pass-through for Test operations (read-only)
+/// - Test operations as guards: a concurrent write to a tested path fails
+///   the transform, so the guarded patch is rejected as a whole
 /// - Conflict marking for Move/Copy operations (Post-MVP)
 ///
 /// # Conflict Resolution
@@ -80,6 +210,20 @@ pub fn transform_patches(
     remote: &Patch,
     strategy: TransformStrategy,
 ) -> SyncResult<(Patch, Patch)> {
+    transform_patches_with_limits(local, remote, strategy, &PatchLimits::default())
+}
+
+/// [`transform_patches`], rejecting either patch first if it exceeds `limits`.
+/// Transforming is pairwise, so its cost grows with the product of the two
+/// patches' lengths.
+pub fn transform_patches_with_limits(
+    local: &Patch,
+    remote: &Patch,
+    strategy: TransformStrategy,
+    limits: &PatchLimits,
+) -> SyncResult<(Patch, Patch)> {
+    limits.check(local)?;
+    limits.check(remote)?;
     match strategy {
         TransformStrategy::LastWriteWins => {
             // Simple strategy: remote wins
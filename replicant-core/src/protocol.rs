@@ -3,6 +3,25 @@ use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
 use uuid::Uuid;
 
+/// Default cap on a single WebSocket message, shared by client and server
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Default cap on a document's serialized content
+pub const DEFAULT_MAX_DOCUMENT_BYTES: usize = 8 * 1024 * 1024;
+
+/// Bytes of blob data carried by one UploadBlob or BlobChunk message
+pub const BLOB_CHUNK_BYTES: usize = 256 * 1024;
+
+/// Version of the message format, sent in `Authenticate`. Bump it on any
+/// change old peers can't read; the server refuses clients on another one.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// WebSocket subprotocol clients request, naming [`PROTOCOL_VERSION`]
+pub const WEBSOCKET_SUBPROTOCOL: &str = "replicant.v1";
+
+/// Version assumed for clients that predate version negotiation
+pub const LEGACY_PROTOCOL_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ClientMessage {
@@ -16,17 +35,87 @@ pub enum ClientMessage {
         signature: Option<String>,
         #[serde(skip_serializing_if = "Option::is_none")]
         timestamp: Option<i64>,
+        // Single-use value covered by the signature, so an observed message
+        // can't be replayed while its timestamp is still fresh
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+        // Bearer token for servers that authenticate with their own
+        // identity provider instead of API keys
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+        // Read-only connection: receives broadcasts but may not mutate documents
+        #[serde(default)]
+        observer: bool,
+        // How the server should settle this client's conflicting updates.
+        // `None` keeps the default of rejecting them so the client can rebase.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        conflict_resolution: Option<ConflictResolution>,
+        // Message format the client speaks, see PROTOCOL_VERSION. Missing
+        // from clients that predate it, which speak LEGACY_PROTOCOL_VERSION.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        protocol_version: Option<u32>,
+        // Ask for an Ack on receipt of every later message that carries a
+        // message_id, see MessageTag
+        #[serde(default)]
+        receipt_acks: bool,
     },
 
-    // Document operations
+    // Document operations. Each carries an optional idempotency key: the
+    // server remembers the response to a keyed operation for a while, and a
+    // resent message with the same key gets that response instead of being
+    // applied again.
     CreateDocument {
         document: Document,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<Uuid>,
     },
     UpdateDocument {
         patch: DocumentPatch,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<Uuid>,
+    },
+    // RFC 7386 alternative to UpdateDocument's JSON Patch
+    MergePatchDocument {
+        document_id: Uuid,
+        merge_patch: serde_json::Value,
+        content_hash: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<Uuid>,
     },
     DeleteDocument {
         document_id: Uuid,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<Uuid>,
+    },
+    // Several document operations applied together or not at all. The
+    // transaction ID doubles as its idempotency key.
+    Transaction {
+        transaction_id: Uuid,
+        ops: Vec<TransactionOp>,
+    },
+    // Independent document operations sent in one frame, e.g. when replaying
+    // an offline queue. Each is handled in order exactly as if sent on its
+    // own, with its own response; a failed operation doesn't stop the rest.
+    OperationBatch {
+        operations: Vec<ClientMessage>,
+    },
+    // Settle a conflict: `content` replaces whatever the server holds
+    ResolveConflict {
+        document_id: Uuid,
+        content: serde_json::Value,
+    },
+    // Hand a document to another user. The old owner's clients see it
+    // deleted and the new owner's clients see it created.
+    TransferOwnership {
+        document_id: Uuid,
+        new_user_id: Uuid,
+    },
+    // Give another user access to a document. Only the owner may share, and
+    // the server must have sharing enabled.
+    ShareDocument {
+        document_id: Uuid,
+        user_id: Uuid,
+        permission: SharePermission,
     },
 
     // Sync operations
@@ -34,6 +123,11 @@ pub enum ClientMessage {
         document_ids: Vec<Uuid>,
     },
     RequestFullSync,
+    // The full document, e.g. when a broadcast patch didn't apply locally.
+    // Answered with a SyncDocument alone, without SyncComplete.
+    RequestDocument {
+        document_id: Uuid,
+    },
 
     // New sequence-based sync operations
     GetChangesSince {
@@ -44,10 +138,109 @@ pub enum ClientMessage {
         up_to_sequence: u64, // Client confirms it processed up to this sequence
     },
 
+    // Advisory document locks
+    AcquireLock {
+        document_id: Uuid,
+    },
+    ReleaseLock {
+        document_id: Uuid,
+    },
+
+    // Ask for the user's connected clients; the server replies with a
+    // PresenceUpdate and keeps this connection informed of later changes
+    RequestPresence,
+
+    // Only broadcast SyncDocument and DocumentUpdated for these documents to
+    // this connection. Subscriptions add up; a connection with none hears
+    // about every document.
+    Subscribe {
+        document_ids: Vec<Uuid>,
+    },
+    Unsubscribe {
+        document_ids: Vec<Uuid>,
+    },
+
+    // Binary attachments, transferred in chunks apart from document content.
+    // The server only appends a chunk that starts where its copy ends and
+    // acknowledges how much it holds, so an interrupted upload resumes from
+    // there.
+    UploadBlob {
+        blob_id: Uuid,
+        document_id: Uuid,
+        name: String,
+        total_size: u64,
+        offset: u64,
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    // Answered with the blob's BlobChunks, or BlobDeleted if there is none
+    RequestBlob {
+        blob_id: Uuid,
+    },
+    DeleteBlob {
+        blob_id: Uuid,
+    },
+
+    // The user's live documents carrying `tag`, read from the server's tag
+    // index. Answered with a TagQueryResult. Encrypted content can't be
+    // indexed, so it never matches.
+    QueryByTag {
+        tag: String,
+    },
+
     // Heartbeat
     Ping,
 }
 
+impl ClientMessage {
+    /// The idempotency key of a document operation, if it has one
+    pub fn idempotency_key(&self) -> Option<Uuid> {
+        match self {
+            ClientMessage::CreateDocument {
+                idempotency_key, ..
+            }
+            | ClientMessage::UpdateDocument {
+                idempotency_key, ..
+            }
+            | ClientMessage::MergePatchDocument {
+                idempotency_key, ..
+            }
+            | ClientMessage::DeleteDocument {
+                idempotency_key, ..
+            } => *idempotency_key,
+            ClientMessage::Transaction { transaction_id, .. } => Some(*transaction_id),
+            _ => None,
+        }
+    }
+
+    /// JSON for this message with `message_id` beside its fields, for
+    /// connections that asked for receipt acks
+    pub fn to_json_with_id(&self, message_id: Uuid) -> serde_json::Result<String> {
+        let mut value = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(fields) = &mut value {
+            fields.insert("message_id".to_string(), serde_json::to_value(message_id)?);
+        }
+        serde_json::to_string(&value)
+    }
+}
+
+/// The id a client attached to a message with
+/// [`ClientMessage::to_json_with_id`]. Servers that don't send acks ignore
+/// the field.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MessageTag {
+    #[serde(default)]
+    pub message_id: Option<Uuid>,
+}
+
+impl MessageTag {
+    /// Read the tag from a message's JSON; untagged or unreadable messages
+    /// have none
+    pub fn from_json(json: &str) -> Self {
+        serde_json::from_str(json).unwrap_or_default()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ServerMessage {
@@ -59,6 +252,11 @@ pub enum ServerMessage {
     AuthError {
         reason: String,
     },
+    /// The credentials were rejected; retrying with the same ones won't
+    /// help. `AuthError` covers failures that may pass on a later attempt.
+    AuthFailed {
+        reason: String,
+    },
 
     // Document updates
     DocumentCreated {
@@ -76,22 +274,70 @@ pub enum ServerMessage {
         document_id: Uuid,
         success: bool,
         error: Option<String>,
+        // Revision the server stored, which moves past 1 when a create
+        // overwrites an existing document
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sync_revision: Option<i64>,
+        // Per-user operation log sequence assigned to this mutation, for
+        // tracking a sync high-water mark
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<i64>,
+        // Timestamps from the server's clock, which replace the client's own
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        created_at: Option<chrono::DateTime<chrono::Utc>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        updated_at: Option<chrono::DateTime<chrono::Utc>>,
     },
     DocumentUpdatedResponse {
         document_id: Uuid,
         success: bool,
         error: Option<String>,
         sync_revision: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<i64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        updated_at: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    // Optimistic lock failure: the client's base content is stale. Carries the
+    // current server state so the client can rebase its patch and resubmit.
+    UpdateRejected {
+        document_id: Uuid,
+        server_document: Document,
     },
     DocumentDeletedResponse {
         document_id: Uuid,
         success: bool,
         error: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sequence: Option<i64>,
+    },
+
+    // Outcome of a Transaction. On success, carries the stored state of the
+    // documents it created or updated and the IDs of those it deleted.
+    TransactionResponse {
+        transaction_id: Uuid,
+        success: bool,
+        error: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        documents: Vec<Document>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        deleted_ids: Vec<Uuid>,
+    },
+
+    // Confirms a ShareDocument to the owner's clients
+    DocumentShared {
+        document_id: Uuid,
+        user_id: Uuid,
+        permission: SharePermission,
     },
 
     // Sync responses
     SyncDocument {
         document: Document,
+        // Another client's change passed on as it happened, rather than the
+        // server's copy sent in a sync or pushed by the server itself
+        #[serde(default)]
+        relayed: bool,
     },
     SyncComplete {
         synced_count: usize,
@@ -113,6 +359,56 @@ pub enum ServerMessage {
         sequence: u64,
     },
 
+    // Advisory lock responses. `holder` is the client_id owning the lock.
+    LockResponse {
+        document_id: Uuid,
+        acquired: bool,
+        holder: Option<Uuid>,
+    },
+    // Sent to all of the user's clients when a lock is taken or released
+    LockChanged {
+        document_id: Uuid,
+        holder: Option<Uuid>,
+    },
+    // The user's currently connected clients, sent to presence subscribers
+    // whenever one connects or disconnects
+    PresenceUpdate {
+        client_ids: Vec<Uuid>,
+    },
+
+    // Blob transfer. `received` is how many bytes of an upload the server
+    // holds, where the next chunk must start.
+    BlobUploadAck {
+        blob_id: Uuid,
+        received: u64,
+        complete: bool,
+    },
+    BlobChunk {
+        blob_id: Uuid,
+        document_id: Uuid,
+        name: String,
+        total_size: u64,
+        offset: u64,
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    // The blob was deleted, or a requested one doesn't exist
+    BlobDeleted {
+        blob_id: Uuid,
+    },
+
+    TagQueryResult {
+        tag: String,
+        documents: Vec<Document>,
+    },
+
+    // Sent as soon as a tagged message arrives, before it is handled, on
+    // connections that asked for receipt acks. The outcome still comes in
+    // the usual response.
+    Ack {
+        message_id: Uuid,
+    },
+
     // Errors
     Error {
         code: ErrorCode,
@@ -123,17 +419,104 @@ pub enum ServerMessage {
     Pong,
 }
 
+impl ServerMessage {
+    /// The document a message is about, for messages about a single one
+    pub fn document_id(&self) -> Option<Uuid> {
+        match self {
+            ServerMessage::DocumentCreated { document }
+            | ServerMessage::SyncDocument { document, .. } => Some(document.id),
+            ServerMessage::DocumentUpdated { patch } => Some(patch.document_id),
+            ServerMessage::DocumentDeleted { document_id }
+            | ServerMessage::DocumentCreatedResponse { document_id, .. }
+            | ServerMessage::DocumentUpdatedResponse { document_id, .. }
+            | ServerMessage::UpdateRejected { document_id, .. }
+            | ServerMessage::DocumentDeletedResponse { document_id, .. }
+            | ServerMessage::DocumentShared { document_id, .. }
+            | ServerMessage::ConflictDetected { document_id, .. }
+            | ServerMessage::LockResponse { document_id, .. }
+            | ServerMessage::LockChanged { document_id, .. }
+            | ServerMessage::BlobChunk { document_id, .. } => Some(*document_id),
+            _ => None,
+        }
+    }
+}
+
+/// Blob data as a base64 string rather than JSON's array of numbers
+mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+/// One operation of a [`ClientMessage::Transaction`]. Updates must be based
+/// on the server's current content; there is no conflict resolution inside
+/// a transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransactionOp {
+    Create { document: Document },
+    Update { patch: DocumentPatch },
+    Delete { document_id: Uuid },
+}
+
+impl TransactionOp {
+    pub fn document_id(&self) -> Uuid {
+        match self {
+            TransactionOp::Create { document } => document.id,
+            TransactionOp::Update { patch } => patch.document_id,
+            TransactionOp::Delete { document_id } => *document_id,
+        }
+    }
+}
+
+/// How an update based on stale content is settled.
+///
+/// A client picks its strategy when it authenticates, and the strategy of the
+/// client whose update arrives second is the one applied. So if a `ServerWins`
+/// client and a `ClientWins` client edit concurrently, the `ClientWins` edit
+/// always survives, while two `ServerWins` clients keep whichever edit the
+/// server accepted first. A document naming its own strategy in
+/// [`crate::conflicts::CONFLICT_STRATEGY_FIELD`] overrides the client's.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ConflictResolution {
+    /// Keep the server's content and discard the conflicting update
     ServerWins,
+    /// Apply the client's update over the server's content and broadcast it
     ClientWins,
+    /// The later write wins. Updates are ordered by arrival, so the server
+    /// applies the incoming update as with `ClientWins`.
+    LastWriteWins,
+    /// Apply each operation of the update that still applies and drop the
+    /// rest, so concurrent changes to other fields survive on both sides
+    FieldMerge,
     Manual {
         server_document: Box<Document>,
         client_patch: DocumentPatch,
     },
 }
 
+/// What a user a document is shared with may do with it
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Display, EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SharePermission {
+    /// Receives the document on sync and sees changes to it
+    Read,
+    /// May also update it. Deleting, sharing and transferring stay with the owner.
+    Write,
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum ErrorCode {
@@ -144,6 +527,12 @@ pub enum ErrorCode {
     ServerError,
     RateLimitExceeded,
     InvalidMessage,
+    Unauthorized,
+    Locked,
+    ValidationFailed,
+    /// A temporary server-side failure, such as an unreachable database;
+    /// the request may succeed if retried later
+    Unavailable,
 }
 
 // New types for sequence-based sync
@@ -0,0 +1,70 @@
+//! Tests for per-document conflict strategies and the field merge behind them
+
+use json_patch::Patch;
+use replicant_core::conflicts::DocumentConflictStrategy;
+use replicant_core::patches::{applicable_operations, apply_patch, create_patch};
+use replicant_core::protocol::ConflictResolution;
+use serde_json::json;
+
+#[test]
+fn test_strategy_is_read_from_content() {
+    let counter = json!({ "count": 1, "_conflict_strategy": "field_merge" });
+    let title = json!({ "title": "Note", "_conflict_strategy": "last_write_wins" });
+    let settings = json!({ "theme": "dark", "_conflict_strategy": "server_wins" });
+
+    assert_eq!(
+        DocumentConflictStrategy::from_content(&counter),
+        Some(DocumentConflictStrategy::FieldMerge)
+    );
+    assert_eq!(
+        DocumentConflictStrategy::from_content(&title),
+        Some(DocumentConflictStrategy::LastWriteWins)
+    );
+    assert!(matches!(
+        DocumentConflictStrategy::from_content(&settings).map(ConflictResolution::from),
+        Some(ConflictResolution::ServerWins)
+    ));
+}
+
+#[test]
+fn test_missing_or_unknown_strategy_is_ignored() {
+    assert_eq!(
+        DocumentConflictStrategy::from_content(&json!({ "title": "Plain" })),
+        None
+    );
+    assert_eq!(
+        DocumentConflictStrategy::from_content(&json!({ "_conflict_strategy": "coin_flip" })),
+        None
+    );
+    assert_eq!(
+        DocumentConflictStrategy::from_content(&json!({ "_conflict_strategy": 3 })),
+        None
+    );
+}
+
+#[test]
+fn test_applicable_operations_keep_what_still_applies() {
+    let base = json!({ "title": "Draft", "notes": "a" });
+    let client = json!({ "title": "Draft", "notes": "b", "done": true });
+    let patch = create_patch(&base, &client).unwrap();
+
+    // Meanwhile the server dropped the notes
+    let server = json!({ "title": "Renamed" });
+    assert!(apply_patch(&mut server.clone(), &patch).is_err());
+
+    let merged = applicable_operations(&server, &patch);
+    let mut content = server.clone();
+    apply_patch(&mut content, &merged).unwrap();
+    assert_eq!(content, json!({ "title": "Renamed", "done": true }));
+}
+
+#[test]
+fn test_applicable_operations_of_nothing_applicable_is_empty() {
+    let patch: Patch = serde_json::from_value(json!([
+        { "op": "replace", "path": "/gone", "value": 1 },
+        { "op": "remove", "path": "/also_gone" },
+    ]))
+    .unwrap();
+
+    assert!(applicable_operations(&json!({}), &patch).0.is_empty());
+}
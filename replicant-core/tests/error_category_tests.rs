@@ -0,0 +1,122 @@
+//! Tests for how errors are categorized and which ones are worth retrying
+
+use replicant_core::errors::{ApiError, ClientError, ErrorCategory, ServerError};
+use replicant_core::SyncError;
+use std::io;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[test]
+fn test_connection_failures_are_retryable() {
+    for error in [
+        SyncError::from(ClientError::ConnectionLost("reset by peer".to_string())),
+        ClientError::Closed.into(),
+        ClientError::SendFailed("connection closed".to_string()).into(),
+        SyncError::NetworkError("unreachable".to_string()),
+        io::Error::from(io::ErrorKind::ConnectionRefused).into(),
+    ] {
+        assert_eq!(error.category(), ErrorCategory::Network, "{}", error);
+        assert!(error.is_retryable(), "{}", error);
+    }
+}
+
+#[test]
+fn test_timeouts_are_retryable() {
+    let error = SyncError::from(ClientError::Timeout("no lock response".to_string()));
+    assert_eq!(error.category(), ErrorCategory::Timeout);
+    assert!(error.is_retryable());
+
+    let error = SyncError::from(io::Error::from(io::ErrorKind::TimedOut));
+    assert_eq!(error.category(), ErrorCategory::Timeout);
+    assert!(error.is_retryable());
+}
+
+#[test]
+fn test_limits_that_clear_by_themselves_are_retryable() {
+    let full = SyncError::QueueFull { depth: 3, limit: 3 };
+    assert_eq!(full.category(), ErrorCategory::Capacity);
+    assert!(full.is_retryable());
+
+    let stale = SyncError::ReconcileRequired {
+        since: Duration::from_secs(600),
+        window: Duration::from_secs(60),
+    };
+    assert_eq!(stale.category(), ErrorCategory::Conflict);
+    assert!(stale.is_retryable());
+
+    let unavailable = SyncError::from(ApiError::service_unavailable("restarting"));
+    assert_eq!(unavailable.category(), ErrorCategory::Network);
+    assert!(unavailable.is_retryable());
+}
+
+#[test]
+fn test_permanent_failures_are_not_retryable() {
+    let cases = [
+        (
+            SyncError::AuthenticationFailed("bad signature".to_string()),
+            ErrorCategory::Authentication,
+        ),
+        (
+            SyncError::Validation("too large".to_string()),
+            ErrorCategory::Validation,
+        ),
+        (
+            SyncError::DocumentTooLarge {
+                size: 101,
+                limit: 100,
+            },
+            ErrorCategory::Validation,
+        ),
+        (
+            SyncError::ConflictDetected(Uuid::new_v4()),
+            ErrorCategory::Conflict,
+        ),
+        (
+            SyncError::DocumentNotFound(Uuid::new_v4()),
+            ErrorCategory::NotFound,
+        ),
+        (SyncError::NotInitialized, ErrorCategory::NotFound),
+        (
+            SyncError::PatchFailed("missing path".to_string()),
+            ErrorCategory::Data,
+        ),
+        (
+            SyncError::SchemaTooNew {
+                found: 9,
+                supported: 1,
+            },
+            ErrorCategory::Storage,
+        ),
+        (
+            ClientError::Encryption("bad key".to_string()).into(),
+            ErrorCategory::Data,
+        ),
+        (
+            ClientError::InvalidState("already started".to_string()).into(),
+            ErrorCategory::Internal,
+        ),
+        (
+            ApiError::unauthorized("expired").into(),
+            ErrorCategory::Authentication,
+        ),
+        (
+            ServerError::InvalidSchema("missing title".to_string()).into(),
+            ErrorCategory::Validation,
+        ),
+    ];
+    for (error, category) in cases {
+        assert_eq!(error.category(), category, "{}", error);
+        assert!(!error.is_retryable(), "{}", error);
+    }
+}
+
+#[test]
+fn test_database_errors_retry_only_when_transient() {
+    let timed_out = SyncError::DatabaseError(sqlx::Error::PoolTimedOut);
+    assert_eq!(timed_out.category(), ErrorCategory::Storage);
+    assert!(timed_out.is_retryable());
+
+    let missing = SyncError::DatabaseError(sqlx::Error::RowNotFound);
+    assert_eq!(missing.category(), ErrorCategory::Storage);
+    assert!(!missing.is_retryable());
+}
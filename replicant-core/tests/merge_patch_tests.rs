@@ -0,0 +1,72 @@
+//! Tests for RFC 7386 JSON merge patches
+
+use replicant_core::patches::{apply_merge_patch, create_merge_patch};
+use serde_json::json;
+
+#[test]
+fn test_null_removes_field() {
+    let mut doc = json!({ "title": "Groceries", "notes": "Before noon" });
+    apply_merge_patch(&mut doc, &json!({ "notes": null }));
+    assert_eq!(doc, json!({ "title": "Groceries" }));
+}
+
+#[test]
+fn test_nested_objects_merge() {
+    let mut doc = json!({
+        "title": "Trip",
+        "settings": { "color": "blue", "pinned": true, "reminder": { "at": "09:00" } }
+    });
+    apply_merge_patch(
+        &mut doc,
+        &json!({ "settings": { "color": "red", "pinned": null, "reminder": { "repeat": "daily" } } }),
+    );
+    assert_eq!(
+        doc,
+        json!({
+            "title": "Trip",
+            "settings": { "color": "red", "reminder": { "at": "09:00", "repeat": "daily" } }
+        })
+    );
+}
+
+#[test]
+fn test_arrays_are_replaced_whole() {
+    let mut doc = json!({ "tags": ["a", "b", "c"] });
+    apply_merge_patch(&mut doc, &json!({ "tags": ["z"] }));
+    assert_eq!(doc, json!({ "tags": ["z"] }));
+}
+
+#[test]
+fn test_create_merge_patch_round_trip() {
+    let from = json!({
+        "title": "Trip",
+        "notes": "Pack light",
+        "settings": { "color": "blue", "pinned": true },
+        "tags": ["travel"]
+    });
+    let to = json!({
+        "title": "Trip",
+        "settings": { "color": "green", "pinned": true, "archived": false },
+        "tags": ["travel", "summer"]
+    });
+
+    let patch = create_merge_patch(&from, &to);
+    assert_eq!(
+        patch,
+        json!({
+            "notes": null,
+            "settings": { "color": "green", "archived": false },
+            "tags": ["travel", "summer"]
+        })
+    );
+
+    let mut doc = from.clone();
+    apply_merge_patch(&mut doc, &patch);
+    assert_eq!(doc, to);
+}
+
+#[test]
+fn test_create_merge_patch_unchanged_is_empty() {
+    let doc = json!({ "title": "Same", "nested": { "a": 1 } });
+    assert_eq!(create_merge_patch(&doc, &doc), json!({}));
+}
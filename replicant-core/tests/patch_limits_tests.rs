@@ -0,0 +1,68 @@
+//! Tests for the caps on patch size checked before applying or transforming
+
+use json_patch::{AddOperation, Patch, PatchOperation};
+use replicant_core::patches::{
+    apply_patch_with_limits, transform_patches_with_limits, PatchLimits, TransformStrategy,
+};
+use replicant_core::SyncError;
+use serde_json::json;
+
+fn add(path: &str) -> PatchOperation {
+    PatchOperation::Add(AddOperation {
+        path: path.into(),
+        value: json!(1),
+    })
+}
+
+const LIMITS: PatchLimits = PatchLimits {
+    max_operations: 3,
+    max_path_depth: 4,
+};
+
+#[test]
+fn test_patch_within_limits_applies() {
+    let mut doc = json!({ "a": { "b": {} } });
+    let patch = Patch(vec![add("/x"), add("/a/y"), add("/a/b/z")]);
+
+    apply_patch_with_limits(&mut doc, &patch, &LIMITS).unwrap();
+    assert_eq!(doc, json!({ "x": 1, "a": { "y": 1, "b": { "z": 1 } } }));
+}
+
+#[test]
+fn test_too_many_operations_rejected() {
+    let mut doc = json!({});
+    let patch = Patch((0..4).map(|i| add(&format!("/k{}", i))).collect());
+
+    let result = apply_patch_with_limits(&mut doc, &patch, &LIMITS);
+    assert!(matches!(result, Err(SyncError::Validation(_))));
+    // Rejected before anything was applied
+    assert_eq!(doc, json!({}));
+
+    let small = Patch(vec![add("/x")]);
+    let result =
+        transform_patches_with_limits(&small, &patch, TransformStrategy::Operational, &LIMITS);
+    assert!(matches!(result, Err(SyncError::Validation(_))));
+}
+
+#[test]
+fn test_too_deep_path_rejected() {
+    let mut doc = json!({});
+    let deep = Patch(vec![add("/a/b/c/d/e")]);
+
+    let result = apply_patch_with_limits(&mut doc, &deep, &LIMITS);
+    assert!(matches!(result, Err(SyncError::Validation(_))));
+
+    let move_from_deep: Patch = serde_json::from_value(json!([
+        { "op": "move", "from": "/a/b/c/d/e", "path": "/x" }
+    ]))
+    .unwrap();
+    assert!(LIMITS.check(&move_from_deep).is_err());
+
+    let result = transform_patches_with_limits(
+        &deep,
+        &Patch(vec![add("/x")]),
+        TransformStrategy::Operational,
+        &LIMITS,
+    );
+    assert!(matches!(result, Err(SyncError::Validation(_))));
+}
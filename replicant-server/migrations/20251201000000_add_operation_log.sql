@@ -0,0 +1,22 @@
+-- Add operation_log table with per-user monotonic sequence numbers
+-- Every create/update/delete is appended in the same transaction as the
+-- document write, so sequences have no gaps and follow commit order
+
+-- Per-user sequence counter. The row lock taken when bumping the counter
+-- serializes concurrent mutations for the same user until commit
+CREATE TABLE operation_sequences (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    last_sequence BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE operation_log (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    sequence BIGINT NOT NULL,
+    document_id UUID NOT NULL,
+    operation VARCHAR(10) NOT NULL CHECK (operation IN ('create', 'update', 'delete')),
+    sync_revision BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, sequence)
+);
+
+CREATE INDEX idx_operation_log_document ON operation_log(document_id, sequence);
@@ -0,0 +1,2 @@
+-- Record when a credential was revoked; revoked credentials have is_active = false
+ALTER TABLE api_credentials ADD COLUMN revoked_at TIMESTAMPTZ;
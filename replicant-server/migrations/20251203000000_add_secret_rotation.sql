@@ -0,0 +1,3 @@
+-- Keep the previous secret valid for a grace period after rotation
+ALTER TABLE api_credentials ADD COLUMN previous_secret TEXT;
+ALTER TABLE api_credentials ADD COLUMN previous_valid_until TIMESTAMPTZ;
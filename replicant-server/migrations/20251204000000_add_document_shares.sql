@@ -0,0 +1,11 @@
+-- Documents shared with users other than their owner. Only consulted when the
+-- server runs with document sharing enabled.
+CREATE TABLE document_shares (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    permission VARCHAR(10) NOT NULL CHECK (permission IN ('read', 'write')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (document_id, user_id)
+);
+
+CREATE INDEX idx_document_shares_user ON document_shares(user_id);
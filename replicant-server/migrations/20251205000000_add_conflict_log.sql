@@ -0,0 +1,15 @@
+-- Conflicts the server resolved, for diagnosing lost edits. Only written when
+-- the server runs with conflict logging enabled.
+CREATE TABLE conflict_log (
+    id BIGSERIAL PRIMARY KEY,
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    client_id UUID,
+    -- The client's full content for creates, its JSON patch for updates
+    client_version JSONB NOT NULL,
+    server_version JSONB NOT NULL,
+    resolution VARCHAR(20) NOT NULL,
+    resolved_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_conflict_log_document ON conflict_log(document_id, resolved_at);
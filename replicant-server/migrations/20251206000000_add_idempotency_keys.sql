@@ -0,0 +1,12 @@
+-- Responses to keyed document operations, so a client resending an operation
+-- after a lost response gets the original answer instead of a duplicate.
+-- Rows are only honoured for the server's idempotency TTL.
+CREATE TABLE idempotency_keys (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    idempotency_key UUID NOT NULL,
+    responses JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, idempotency_key)
+);
+
+CREATE INDEX idx_idempotency_keys_created_at ON idempotency_keys(created_at);
@@ -0,0 +1,13 @@
+-- Binary attachments, uploaded in chunks and kept apart from document
+-- content. An upload is complete once the stored data reaches total_size.
+CREATE TABLE blobs (
+    id UUID PRIMARY KEY,
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    total_size BIGINT NOT NULL,
+    data BYTEA NOT NULL DEFAULT '',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_blobs_document ON blobs(document_id);
@@ -0,0 +1,5 @@
+-- Optional expiry for ephemeral documents, deleted by the server's sweep
+ALTER TABLE documents ADD COLUMN expires_at TIMESTAMPTZ;
+
+CREATE INDEX idx_documents_expires_at ON documents(expires_at)
+    WHERE expires_at IS NOT NULL AND deleted_at IS NULL;
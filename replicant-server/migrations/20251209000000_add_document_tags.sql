@@ -0,0 +1,26 @@
+-- Tags read from document content, so documents can be looked up by tag
+-- without scanning them. Rewritten with every document write.
+CREATE TABLE document_tags (
+    document_id UUID NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (document_id, tag)
+);
+
+CREATE INDEX idx_document_tags_tag ON document_tags(tag);
+
+-- Index existing documents from the default $.tags path
+INSERT INTO document_tags (document_id, tag)
+SELECT DISTINCT d.id, t.tag
+FROM documents d
+CROSS JOIN LATERAL (
+    SELECT value #>> '{}' AS tag
+    FROM jsonb_array_elements(
+        CASE jsonb_typeof(d.content->'tags')
+            WHEN 'array' THEN d.content->'tags'
+            WHEN 'string' THEN jsonb_build_array(d.content->'tags')
+            ELSE '[]'::jsonb
+        END
+    )
+    WHERE jsonb_typeof(value) = 'string'
+) t
+WHERE d.deleted_at IS NULL;
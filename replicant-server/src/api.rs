@@ -5,4 +5,293 @@
 // created if they don't exist. See websocket.rs for implementation.
 //
 // Additional REST endpoints can be added here with proper HMAC authentication
-// if needed in the future.
+// if needed in the future. Admin endpoints use a separate bearer token.
+
+use crate::{
+    auth::CredentialInfo,
+    database::{ConflictLogEntry, UserStats},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use replicant_core::{protocol::ServerMessage, SyncError};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+
+/// Server metrics in the Prometheus text format
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    match &state.monitoring {
+        Some(monitoring) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            monitoring.snapshot().to_prometheus(),
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// How long `/health` waits on the database before reporting it unreachable
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize)]
+struct Health {
+    status: &'static str,
+    database: &'static str,
+    connected_clients: usize,
+}
+
+/// Readiness: 200 while the database answers, 503 otherwise, so load
+/// balancers stop routing to an instance that can't serve syncs
+pub async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let database = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, state.db.ping()).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("no answer within {:?}", HEALTH_CHECK_TIMEOUT)),
+    };
+    let connected_clients = state.clients.len();
+    match database {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(Health {
+                status: "ok",
+                database: "ok",
+                connected_clients,
+            }),
+        ),
+        Err(e) => {
+            tracing::warn!("Health check failed, database unreachable: {}", e);
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(Health {
+                    status: "unavailable",
+                    database: "unreachable",
+                    connected_clients,
+                }),
+            )
+        }
+    }
+}
+
+/// Liveness: answers as long as the process is serving requests
+pub async fn live() -> &'static str {
+    "OK"
+}
+
+/// Admin routes for managing API credentials, inspecting resolved conflicts,
+/// reporting per-user storage and pushing documents to clients.
+///
+/// Every request must carry `Authorization: Bearer <ADMIN_TOKEN>`. When the
+/// server has no admin token configured the routes answer 404.
+pub fn admin_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/credentials", get(list_credentials))
+        .route(
+            "/admin/credentials/:api_key/revoke",
+            post(revoke_credentials),
+        )
+        .route("/admin/credentials/:api_key/rotate", post(rotate_secret))
+        .route("/admin/conflicts", get(list_conflicts))
+        .route("/admin/users/:user_id/stats", get(user_stats))
+        .route("/admin/documents/:id/broadcast", post(broadcast_document))
+}
+
+fn authorize_admin(state: &AppState, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(expected) = state.admin_token.as_deref() else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if bool::from(provided.as_bytes().ct_eq(expected.as_bytes())) {
+        Ok(())
+    } else {
+        tracing::warn!("Rejected admin request with invalid token");
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+async fn list_credentials(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<CredentialInfo>>, StatusCode> {
+    authorize_admin(&state, &headers)?;
+
+    state.auth.list_credentials().await.map(Json).map_err(|e| {
+        tracing::error!(%e, "Failed to list credentials");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn revoke_credentials(
+    State(state): State<Arc<AppState>>,
+    Path(api_key): Path<String>,
+    headers: HeaderMap,
+) -> StatusCode {
+    if let Err(status) = authorize_admin(&state, &headers) {
+        return status;
+    }
+
+    match state.auth.revoke_credentials(&api_key).await {
+        Ok(true) => {
+            tracing::info!("Revoked API credential {}", api_key);
+            StatusCode::NO_CONTENT
+        }
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            tracing::error!(%e, "Failed to revoke credential");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RotateParams {
+    // Overrides the server's configured grace period for the old secret
+    grace_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RotatedSecret {
+    api_key: String,
+    secret: String,
+    grace_secs: u64,
+}
+
+async fn rotate_secret(
+    State(state): State<Arc<AppState>>,
+    Path(api_key): Path<String>,
+    Query(params): Query<RotateParams>,
+    headers: HeaderMap,
+) -> Result<Json<RotatedSecret>, StatusCode> {
+    authorize_admin(&state, &headers)?;
+
+    let grace = params
+        .grace_secs
+        .map(Duration::from_secs)
+        .unwrap_or(state.secret_rotation_grace);
+
+    match state.db.rotate_secret(&api_key, grace).await {
+        Ok(Some(secret)) => {
+            tracing::info!(
+                "Rotated secret for API credential {}; old secret valid for {}s",
+                api_key,
+                grace.as_secs()
+            );
+            Ok(Json(RotatedSecret {
+                api_key,
+                secret,
+                grace_secs: grace.as_secs(),
+            }))
+        }
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!(%e, "Failed to rotate secret");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ConflictParams {
+    document_id: Option<uuid::Uuid>,
+    // Defaults to 100, capped at 1000
+    limit: Option<i64>,
+}
+
+async fn list_conflicts(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ConflictParams>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<ConflictLogEntry>>, StatusCode> {
+    authorize_admin(&state, &headers)?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    state
+        .db
+        .get_conflict_log(params.document_id, limit)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!(%e, "Failed to list conflicts");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+async fn user_stats(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<UserStats>, StatusCode> {
+    authorize_admin(&state, &headers)?;
+
+    match state.db.user_exists(&user_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!(%e, "Failed to look up user");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    state.db.user_stats(&user_id).await.map(Json).map_err(|e| {
+        tracing::error!(%e, "Failed to load user stats");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[derive(Debug, Serialize)]
+struct BroadcastResult {
+    // Connected clients of the owner the document was sent to
+    delivered: usize,
+}
+
+// Push the stored version of a document to every connected client of its
+// owner, e.g. after fixing data by hand. Clients apply it with their usual
+// revision checks.
+async fn broadcast_document(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<uuid::Uuid>,
+    headers: HeaderMap,
+) -> Result<Json<BroadcastResult>, StatusCode> {
+    authorize_admin(&state, &headers)?;
+
+    let document = match state.db.get_document(&id).await {
+        Ok(document) => document,
+        Err(SyncError::DatabaseError(sqlx::Error::RowNotFound)) => {
+            return Err(StatusCode::NOT_FOUND)
+        }
+        Err(e) => {
+            tracing::error!(%e, "Failed to load document for broadcast");
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let owner = document.user_id;
+    let delivered = state
+        .send_to_user(
+            owner,
+            ServerMessage::SyncDocument {
+                document,
+                relayed: false,
+            },
+        )
+        .await;
+    tracing::info!(
+        "Admin broadcast of document {} reached {} clients",
+        id,
+        delivered
+    );
+    Ok(Json(BroadcastResult { delivered }))
+}
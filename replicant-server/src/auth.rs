@@ -1,39 +1,103 @@
 use crate::database::ServerDatabase;
+use async_trait::async_trait;
+use dashmap::{mapref::entry::Entry, DashMap};
 use hmac::{Hmac, Mac};
 use rand::Rng;
 use replicant_core::SyncResult;
+use serde::Serialize;
 use sha2::Sha256;
+use sqlx::Row;
 use std::sync::Arc;
 use subtle::ConstantTimeEq;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// How far a signed request's timestamp may be from the server's clock
+const SIGNATURE_WINDOW_SECS: i64 = 300;
+
 pub struct ApiCredentials {
     pub api_key: String,
     pub secret: String,
 }
 
+/// Credential details safe to show an administrator; never includes the secret
+#[derive(Debug, Clone, Serialize)]
+pub struct CredentialInfo {
+    pub name: String,
+    pub api_key: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// What a client presented in its `Authenticate` message
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub email: String,
+    pub api_key: Option<String>,
+    pub signature: Option<String>,
+    pub timestamp: Option<i64>,
+    pub nonce: Option<String>,
+    // Bearer token, such as a JWT, for authenticators that don't use API keys
+    pub token: Option<String>,
+}
+
+/// The user an [`Authenticator`] accepted
+#[derive(Debug, Clone)]
+pub struct AuthedUser {
+    /// Identifies the user's documents; created on first sign-in
+    pub email: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    /// The credentials are wrong; the client should not retry with them
+    #[error("{0}")]
+    Rejected(String),
+    /// The credentials couldn't be checked right now
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+/// Decides whether an `Authenticate` message identifies a user.
+///
+/// [`AuthState`] checks HMAC-signed API credentials against the database.
+/// Deployments with their own identity provider can supply another
+/// implementation, for example one that validates `token` as a JWT.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    async fn authenticate(&self, credentials: &Credentials) -> Result<AuthedUser, AuthError>;
+}
+
 #[derive(Clone)]
 pub struct AuthState {
     db: Arc<ServerDatabase>,
+    // Nonces of accepted Authenticate messages -> when their timestamp goes stale
+    seen_nonces: Arc<DashMap<String, i64>>,
 }
 
 impl AuthState {
     pub fn new(db: Arc<ServerDatabase>) -> Self {
-        Self { db }
+        Self {
+            db,
+            seen_nonces: Arc::new(DashMap::new()),
+        }
     }
 
     pub fn generate_api_credentials() -> ApiCredentials {
-        let mut rng = rand::thread_rng();
-        let api_key_bytes: [u8; 32] = rng.gen();
-        let secret_bytes: [u8; 32] = rng.gen();
+        let api_key_bytes: [u8; 32] = rand::thread_rng().gen();
 
         ApiCredentials {
             api_key: format!("rpa_{}", hex::encode(api_key_bytes)),
-            secret: format!("rps_{}", hex::encode(secret_bytes)),
+            secret: Self::generate_secret(),
         }
     }
 
+    pub fn generate_secret() -> String {
+        let secret_bytes: [u8; 32] = rand::thread_rng().gen();
+        format!("rps_{}", hex::encode(secret_bytes))
+    }
+
     pub async fn save_credentials(
         &self,
         credentials: &ApiCredentials,
@@ -52,6 +116,46 @@ impl AuthState {
         Ok(())
     }
 
+    pub async fn list_credentials(&self) -> SyncResult<Vec<CredentialInfo>> {
+        let rows = sqlx::query(
+            "SELECT name, api_key, created_at, last_used_at, revoked_at
+             FROM api_credentials
+             ORDER BY created_at",
+        )
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let credentials = rows
+            .into_iter()
+            .map(|row| -> Result<_, sqlx::Error> {
+                Ok(CredentialInfo {
+                    name: row.try_get("name")?,
+                    api_key: row.try_get("api_key")?,
+                    created_at: row.try_get("created_at")?,
+                    last_used_at: row.try_get("last_used_at")?,
+                    revoked_at: row.try_get("revoked_at")?,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(credentials)
+    }
+
+    /// Revoke a credential so it can no longer authenticate.
+    /// Returns false if no credential has this key.
+    pub async fn revoke_credentials(&self, api_key: &str) -> SyncResult<bool> {
+        let result = sqlx::query(
+            "UPDATE api_credentials
+             SET is_active = false, revoked_at = COALESCE(revoked_at, NOW())
+             WHERE api_key = $1",
+        )
+        .bind(api_key)
+        .execute(&self.db.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub fn create_hmac_signature(
         secret: &str,
         timestamp: i64,
@@ -78,7 +182,7 @@ impl AuthState {
     ) -> SyncResult<bool> {
         // Validate timestamp (5 minute window)
         let now = chrono::Utc::now().timestamp();
-        if (now - timestamp).abs() > 300 {
+        if (now - timestamp).abs() > SIGNATURE_WINDOW_SECS {
             tracing::warn!("HMAC timestamp outside 5-minute window");
             return Ok(false);
         }
@@ -89,25 +193,31 @@ impl AuthState {
             return Ok(false);
         }
 
-        // Look up credential by api_key
-        let secret: Option<String> = sqlx::query_scalar!(
-            "SELECT secret FROM api_credentials
+        // Look up credential by api_key, including a rotated-out secret still in its grace period
+        let row = sqlx::query(
+            "SELECT secret,
+                    CASE WHEN previous_valid_until > NOW() THEN previous_secret END AS previous_secret
+             FROM api_credentials
              WHERE api_key = $1 AND is_active = true",
-            api_key
         )
+        .bind(api_key)
         .fetch_optional(&self.db.pool)
         .await?;
 
-        let Some(secret) = secret else {
-            tracing::warn!("API key not found");
+        let Some(row) = row else {
+            tracing::warn!("API key not found or revoked");
             return Ok(false);
         };
-
-        // Compute expected signature
-        let expected = Self::create_hmac_signature(&secret, timestamp, email, api_key, body);
+        let secret: String = row.try_get("secret")?;
+        let previous_secret: Option<String> = row.try_get("previous_secret")?;
 
         // Constant-time comparison to prevent timing attacks
-        if !bool::from(signature.as_bytes().ct_eq(expected.as_bytes())) {
+        let matches = |secret: &str| {
+            let expected = Self::create_hmac_signature(secret, timestamp, email, api_key, body);
+            bool::from(signature.as_bytes().ct_eq(expected.as_bytes()))
+        };
+
+        if !matches(&secret) && !previous_secret.as_deref().is_some_and(matches) {
             tracing::warn!("HMAC signature mismatch");
             return Ok(false);
         }
@@ -123,6 +233,22 @@ impl AuthState {
         Ok(true)
     }
 
+    /// Record a nonce from a verified message, returning false if it was
+    /// already used. A nonce is remembered until its message's timestamp
+    /// falls outside the signature window, after which the timestamp check
+    /// rejects the message anyway.
+    pub fn claim_nonce(&self, nonce: &str, timestamp: i64) -> bool {
+        let now = chrono::Utc::now().timestamp();
+        self.seen_nonces.retain(|_, expires_at| *expires_at >= now);
+        match self.seen_nonces.entry(nonce.to_string()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(timestamp + SIGNATURE_WINDOW_SECS);
+                true
+            }
+        }
+    }
+
     // Helper function for testing - verifies HMAC with known secret
     #[cfg(test)]
     pub fn verify_hmac_with_secret(
@@ -138,3 +264,41 @@ impl AuthState {
         expected_signature == signature
     }
 }
+
+#[async_trait]
+impl Authenticator for AuthState {
+    async fn authenticate(&self, credentials: &Credentials) -> Result<AuthedUser, AuthError> {
+        // All HMAC fields required
+        let (Some(api_key), Some(signature), Some(timestamp), Some(nonce)) = (
+            &credentials.api_key,
+            &credentials.signature,
+            credentials.timestamp,
+            &credentials.nonce,
+        ) else {
+            return Err(AuthError::Rejected(
+                "Missing required authentication fields".to_string(),
+            ));
+        };
+
+        // The nonce is signed as the message body
+        match self
+            .verify_hmac(api_key, signature, timestamp, &credentials.email, nonce)
+            .await
+        {
+            Ok(true) if !self.claim_nonce(nonce, timestamp) => {
+                tracing::warn!("Rejecting replayed Authenticate message");
+                Err(AuthError::Rejected("Invalid credentials".to_string()))
+            }
+            Ok(true) => Ok(AuthedUser {
+                email: credentials.email.clone(),
+            }),
+            Ok(false) => Err(AuthError::Rejected("Invalid credentials".to_string())),
+            Err(e) => {
+                tracing::error!("HMAC verification database error: {}", e);
+                Err(AuthError::Unavailable(
+                    "Authentication service temporarily unavailable".to_string(),
+                ))
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Cross-origin access for browser clients
+//
+// Nothing is allowed unless configured: without CORS_ALLOWED_ORIGINS the
+// server sends no CORS headers and browsers refuse cross-origin requests.
+// CORS_PERMISSIVE=true allows everything, for local development only.
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Which browser origins may call the server, and how
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// Exact origins such as `https://app.example.com`
+    pub allowed_origins: Vec<HeaderValue>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    /// Let browsers send cookies and `Authorization` headers along
+    pub allow_credentials: bool,
+    /// Allow any origin, method and header; ignores everything above
+    pub permissive: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![Method::GET, Method::POST],
+            allowed_headers: vec![
+                axum::http::header::AUTHORIZATION,
+                axum::http::header::CONTENT_TYPE,
+            ],
+            allow_credentials: false,
+            permissive: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Read `CORS_ALLOWED_ORIGINS`, `CORS_ALLOWED_METHODS` and
+    /// `CORS_ALLOWED_HEADERS` (comma-separated), `CORS_ALLOW_CREDENTIALS` and
+    /// `CORS_PERMISSIVE`. Unset variables keep the defaults; entries that
+    /// don't parse are logged and skipped.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            allowed_origins: env_list("CORS_ALLOWED_ORIGINS").unwrap_or(defaults.allowed_origins),
+            allowed_methods: env_list("CORS_ALLOWED_METHODS").unwrap_or(defaults.allowed_methods),
+            allowed_headers: env_list("CORS_ALLOWED_HEADERS").unwrap_or(defaults.allowed_headers),
+            allow_credentials: std::env::var("CORS_ALLOW_CREDENTIALS").unwrap_or_default()
+                == "true",
+            permissive: std::env::var("CORS_PERMISSIVE").unwrap_or_default() == "true",
+        }
+    }
+
+    pub fn layer(&self) -> CorsLayer {
+        if self.permissive {
+            tracing::warn!("CORS is permissive: any origin may call this server");
+            return CorsLayer::permissive();
+        }
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(self.allowed_origins.clone()))
+            .allow_methods(self.allowed_methods.clone())
+            .allow_headers(self.allowed_headers.clone())
+            .allow_credentials(self.allow_credentials)
+    }
+}
+
+fn env_list<T: std::str::FromStr>(name: &str) -> Option<Vec<T>> {
+    let value = std::env::var(name).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| match entry.parse() {
+                Ok(parsed) => Some(parsed),
+                Err(_) => {
+                    tracing::warn!("Ignoring invalid {} entry {:?}", name, entry);
+                    None
+                }
+            })
+            .collect(),
+    )
+}
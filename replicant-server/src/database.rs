@@ -1,9 +1,12 @@
 use crate::queries::document_to_params;
+use futures_util::stream::{BoxStream, StreamExt};
 use json_patch::Patch;
-use replicant_core::models::Document;
-use replicant_core::protocol::{ChangeEvent, ChangeEventType};
+use replicant_core::models::{
+    extract_tags, user_id_from_email, Document, SyncStatus, DEFAULT_TAG_PATH,
+};
+use replicant_core::protocol::{ChangeEvent, ChangeEventType, ServerMessage, SharePermission};
 use replicant_core::{SyncError, SyncResult};
-use sqlx::{postgres::PgPoolOptions, PgPool};
+use sqlx::{postgres::PgPoolOptions, PgPool, Row};
 use tracing::instrument;
 use uuid::Uuid;
 
@@ -16,9 +19,82 @@ pub struct ChangeEventParams<'a> {
     pub applied: bool,
 }
 
+/// A single mutation recorded in the operation log.
+#[derive(Debug, Clone)]
+pub struct OperationLogEntry {
+    pub sequence: i64,
+    pub document_id: Uuid,
+    pub operation: ChangeEventType,
+    pub sync_revision: i64,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub struct ConflictLogParams<'a> {
+    pub document_id: &'a Uuid,
+    pub user_id: &'a Uuid,
+    pub client_id: Option<Uuid>,
+    pub client_version: &'a serde_json::Value,
+    pub server_version: &'a serde_json::Value,
+    pub resolution: &'a str,
+}
+
+/// A conflict the server resolved, as recorded in `conflict_log`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConflictLogEntry {
+    pub id: i64,
+    pub document_id: Uuid,
+    pub user_id: Uuid,
+    pub client_id: Option<Uuid>,
+    pub client_version: serde_json::Value,
+    pub server_version: serde_json::Value,
+    pub resolution: String,
+    pub resolved_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Storage used by one user, for capacity planning
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct UserStats {
+    /// Documents that are not deleted
+    pub document_count: i64,
+    /// Serialized size of all stored content, including deleted documents
+    /// that have not been purged yet
+    pub total_bytes: i64,
+    pub deleted_count: i64,
+    /// Most recent update or delete of any of the user's documents
+    pub last_activity: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+pub struct BlobChunkParams<'a> {
+    pub blob_id: &'a Uuid,
+    pub document_id: &'a Uuid,
+    pub user_id: &'a Uuid,
+    pub name: &'a str,
+    pub total_size: i64,
+    pub offset: i64,
+    pub data: &'a [u8],
+}
+
+/// A blob as stored, possibly still being uploaded
+#[derive(Debug, Clone)]
+pub struct StoredBlob {
+    pub id: Uuid,
+    pub document_id: Uuid,
+    pub name: String,
+    pub total_size: i64,
+    pub data: Vec<u8>,
+}
+
+impl StoredBlob {
+    pub fn is_complete(&self) -> bool {
+        self.data.len() as i64 == self.total_size
+    }
+}
+
 pub struct ServerDatabase {
     pub pool: PgPool,
     pub app_namespace_id: String,
+    /// JSON path in document content that tags are indexed from
+    pub tag_path: String,
 }
 
 impl ServerDatabase {
@@ -41,6 +117,7 @@ impl ServerDatabase {
         Ok(Self {
             pool,
             app_namespace_id,
+            tag_path: DEFAULT_TAG_PATH.to_string(),
         })
     }
 
@@ -59,19 +136,65 @@ impl ServerDatabase {
         Ok(Self {
             pool,
             app_namespace_id,
+            tag_path: DEFAULT_TAG_PATH.to_string(),
         })
     }
 
+    /// Connect like [`Self::new`], retrying failed attempts with exponential
+    /// backoff so a server started alongside its database waits for it.
+    /// Returns the last error once `attempts` connections have failed.
+    pub async fn connect_with_retry(
+        database_url: &str,
+        app_namespace_id: String,
+        attempts: u32,
+        initial_backoff: std::time::Duration,
+    ) -> SyncResult<Self> {
+        const MAX_BACKOFF: std::time::Duration = std::time::Duration::from_secs(30);
+
+        let mut backoff = initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match Self::new(database_url, app_namespace_id.clone()).await {
+                Ok(db) => return Ok(db),
+                Err(e) if attempt >= attempts => return Err(e),
+                Err(e) => {
+                    tracing::warn!(
+                        %e,
+                        "Database connection attempt {} of {} failed, retrying in {:?}",
+                        attempt,
+                        attempts,
+                        backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Index tags from `path` instead of [`DEFAULT_TAG_PATH`]. Documents
+    /// already stored keep their old tags until
+    /// [`Self::rebuild_document_tags`] runs.
+    pub fn with_tag_path(mut self, path: impl Into<String>) -> Self {
+        self.tag_path = path.into();
+        self
+    }
+
+    /// Round-trip a trivial query, to check the database is reachable
+    pub async fn ping(&self) -> SyncResult<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
     pub async fn run_migrations(&self) -> SyncResult<()> {
         sqlx::migrate!("./migrations").run(&self.pool).await?;
         Ok(())
     }
 
     pub async fn create_user(&self, email: &str) -> SyncResult<Uuid> {
-        // Generate deterministic user ID using UUID v5
-        // This MUST match the client's logic in ClientDatabase::generate_deterministic_user_id
-        let app_namespace = Uuid::new_v5(&Uuid::NAMESPACE_DNS, self.app_namespace_id.as_bytes());
-        let user_id = Uuid::new_v5(&app_namespace, email.as_bytes());
+        // Deterministic, so clients can derive the same ID from the email
+        let user_id = user_id_from_email(&self.app_namespace_id, email);
 
         let row = sqlx::query!(
             r#"
@@ -88,6 +211,14 @@ impl ServerDatabase {
         Ok(row.id)
     }
 
+    pub async fn user_exists(&self, user_id: &Uuid) -> SyncResult<bool> {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1)")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(exists)
+    }
+
     pub async fn get_user_by_email(&self, email: &str) -> SyncResult<Option<Uuid>> {
         let result = sqlx::query_scalar!("SELECT id FROM users WHERE email = $1", email)
             .fetch_optional(&self.pool)
@@ -96,17 +227,57 @@ impl ServerDatabase {
         Ok(result)
     }
 
+    /// Replace a credential's secret, keeping the old one valid for `grace_period`.
+    /// Returns the new secret, or None if the key is unknown or revoked.
+    pub async fn rotate_secret(
+        &self,
+        api_key: &str,
+        grace_period: std::time::Duration,
+    ) -> SyncResult<Option<String>> {
+        let new_secret = crate::auth::AuthState::generate_secret();
+
+        // SET expressions read the pre-update row, so previous_secret gets the old secret
+        let result = sqlx::query(
+            r#"
+            UPDATE api_credentials
+            SET previous_secret = secret,
+                previous_valid_until = NOW() + make_interval(secs => $3),
+                secret = $2
+            WHERE api_key = $1 AND is_active = true
+            "#,
+        )
+        .bind(api_key)
+        .bind(&new_secret)
+        .bind(grace_period.as_secs_f64())
+        .execute(&self.pool)
+        .await?;
+
+        Ok((result.rows_affected() > 0).then_some(new_secret))
+    }
+
     pub async fn create_document(&self, doc: &Document) -> SyncResult<()> {
         // Start a transaction to ensure atomicity
         let mut tx = self.pool.begin().await?;
+        self.create_document_in_tx(&mut tx, doc).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Create document within an existing transaction
+    pub async fn create_document_in_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        doc: &Document,
+    ) -> SyncResult<()> {
         let params = document_to_params(doc);
 
         sqlx::query!(
             r#"
             INSERT INTO documents (
                 id, user_id, content, sync_revision,
-                created_at, updated_at, deleted_at, content_hash, size_bytes, title
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                created_at, updated_at, deleted_at, content_hash, size_bytes, title,
+                expires_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         "#,
             params.0,      // id
             params.1,      // user_id
@@ -117,17 +288,19 @@ impl ServerDatabase {
             params.6,      // deleted_at
             params.7 as _, // content_hash
             params.8,      // size_bytes
-            params.9 as _  // title
+            params.9 as _, // title
+            params.10      // expires_at
         )
-        .execute(&mut *tx)
+        .execute(&mut **tx)
         .await?;
+        self.set_document_tags_in_tx(tx, doc).await?;
 
         // Log the create event
         // For CREATE: forward_patch contains the full document, reverse_patch is null
         let doc_json = serde_json::to_value(doc)
             .map_err(|e| sqlx::Error::Protocol(format!("Serialization error: {}", e)))?;
         self.log_change_event(
-            &mut tx,
+            tx,
             ChangeEventParams {
                 document_id: &doc.id,
                 user_id: &doc.user_id,
@@ -139,14 +312,14 @@ impl ServerDatabase {
         )
         .await?;
 
-        tx.commit().await?;
         Ok(())
     }
 
     pub async fn get_document(&self, id: &Uuid) -> SyncResult<Document> {
         let row = sqlx::query!(
             r#"
-            SELECT id, user_id, content, sync_revision, content_hash, title, created_at, updated_at, deleted_at
+            SELECT id, user_id, content, sync_revision, content_hash, title, created_at, updated_at, deleted_at,
+                   expires_at
             FROM documents
             WHERE id = $1
         "#,
@@ -165,6 +338,8 @@ impl ServerDatabase {
             created_at: row.created_at,
             updated_at: row.updated_at,
             deleted_at: row.deleted_at,
+            expires_at: row.expires_at,
+            sync_status: SyncStatus::Synced,
         })
     }
 
@@ -176,18 +351,57 @@ impl ServerDatabase {
         Ok(())
     }
 
+    /// Update a document only if it is still at `base_revision`, i.e. nobody else
+    /// committed a change since the caller read it. Returns `VersionMismatch` otherwise.
+    pub async fn update_document_from_base(
+        &self,
+        doc: &Document,
+        patch: Option<&Patch>,
+        base_revision: i64,
+    ) -> SyncResult<()> {
+        let mut tx = self.pool.begin().await?;
+        self.update_document_from_base_in_tx(&mut tx, doc, patch, base_revision)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Same as update_document_from_base, within an existing transaction
+    pub async fn update_document_from_base_in_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        doc: &Document,
+        patch: Option<&Patch>,
+        base_revision: i64,
+    ) -> SyncResult<()> {
+        self.update_document_in_tx_with_base(tx, doc, patch, Some(base_revision))
+            .await
+    }
+
     // Update document within an existing transaction
     pub async fn update_document_in_tx(
         &self,
         tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
         doc: &Document,
         patch: Option<&Patch>,
+    ) -> SyncResult<()> {
+        self.update_document_in_tx_with_base(tx, doc, patch, None)
+            .await
+    }
+
+    async fn update_document_in_tx_with_base(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        doc: &Document,
+        patch: Option<&Patch>,
+        base_revision: Option<i64>,
     ) -> SyncResult<()> {
         // CRITICAL: Read the original document INSIDE the transaction with row lock
         // This prevents race conditions in computing reverse patches
         let original_doc = sqlx::query!(
             r#"
-            SELECT id, user_id, content, sync_revision, content_hash, title, created_at, updated_at, deleted_at
+            SELECT id, user_id, content, sync_revision, content_hash, title, created_at, updated_at, deleted_at,
+                   expires_at
             FROM documents
             WHERE id = $1
             FOR UPDATE
@@ -206,8 +420,20 @@ impl ServerDatabase {
             created_at: row.created_at,
             updated_at: row.updated_at,
             deleted_at: row.deleted_at,
+            expires_at: row.expires_at,
+            sync_status: SyncStatus::Synced,
         })?;
 
+        // The caller's view of the document is stale - another update committed first
+        if let Some(base_revision) = base_revision {
+            if base_revision != original_doc.sync_revision {
+                return Err(SyncError::VersionMismatch {
+                    expected: original_doc.sync_revision,
+                    actual: base_revision,
+                });
+            }
+        }
+
         let params = document_to_params(doc);
         let expected_sync_revision = original_doc.sync_revision;
 
@@ -244,6 +470,7 @@ impl ServerDatabase {
                 actual: doc.sync_revision, // The version the client sent
             });
         }
+        self.set_document_tags_in_tx(tx, doc).await?;
 
         // Compute patches for the event log
         let forward_patch_json = patch.map(|p| serde_json::to_value(p).unwrap());
@@ -276,7 +503,19 @@ impl ServerDatabase {
     pub async fn delete_document(&self, document_id: &Uuid, user_id: &Uuid) -> SyncResult<()> {
         // Start a transaction to ensure atomicity
         let mut tx = self.pool.begin().await?;
+        self.delete_document_in_tx(&mut tx, document_id, user_id)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
+    // Delete document within an existing transaction
+    pub async fn delete_document_in_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        document_id: &Uuid,
+        user_id: &Uuid,
+    ) -> SyncResult<()> {
         // Get the document before deletion (for the reverse patch)
         let doc_to_delete = self.get_document(document_id).await?;
 
@@ -286,15 +525,19 @@ impl ServerDatabase {
             document_id,
             user_id
         )
-        .execute(&mut *tx)
+        .execute(&mut **tx)
         .await?;
+        sqlx::query("DELETE FROM document_tags WHERE document_id = $1")
+            .bind(document_id)
+            .execute(&mut **tx)
+            .await?;
 
         // Log the delete event
         // For DELETE: forward_patch is null, reverse_patch contains the full document
         let doc_json = serde_json::to_value(&doc_to_delete)
             .map_err(|e| sqlx::Error::Protocol(format!("Serialization error: {}", e)))?;
         self.log_change_event(
-            &mut tx,
+            tx,
             ChangeEventParams {
                 document_id,
                 user_id,
@@ -306,15 +549,81 @@ impl ServerDatabase {
         )
         .await?;
 
-        tx.commit().await?;
         Ok(())
     }
 
+    /// Reassign a live document from `from_user` to `to_user` within an existing
+    /// transaction. Logged as a delete for the old owner and a create for the new
+    /// one. Returns the updated document, or None if `from_user` doesn't own it.
+    pub async fn transfer_document_in_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        document_id: &Uuid,
+        from_user: &Uuid,
+        to_user: &Uuid,
+    ) -> SyncResult<Option<Document>> {
+        let row = sqlx::query(
+            r#"
+            UPDATE documents
+            SET user_id = $3, sync_revision = sync_revision + 1, updated_at = NOW()
+            WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+            RETURNING id, user_id, content, sync_revision, content_hash, title,
+                      created_at, updated_at, deleted_at, expires_at
+            "#,
+        )
+        .bind(document_id)
+        .bind(from_user)
+        .bind(to_user)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let doc = crate::queries::parse_document(&row)?;
+
+        // The new owner no longer needs a share of their own document
+        sqlx::query("DELETE FROM document_shares WHERE document_id = $1 AND user_id = $2")
+            .bind(document_id)
+            .bind(to_user)
+            .execute(&mut **tx)
+            .await?;
+
+        let doc_json = serde_json::to_value(&doc)
+            .map_err(|e| sqlx::Error::Protocol(format!("Serialization error: {}", e)))?;
+        self.log_change_event(
+            tx,
+            ChangeEventParams {
+                document_id,
+                user_id: from_user,
+                event_type: ChangeEventType::Delete,
+                forward_patch: None,
+                reverse_patch: Some(&doc_json),
+                applied: true,
+            },
+        )
+        .await?;
+        self.log_change_event(
+            tx,
+            ChangeEventParams {
+                document_id,
+                user_id: to_user,
+                event_type: ChangeEventType::Create,
+                forward_patch: Some(&doc_json),
+                reverse_patch: None,
+                applied: true,
+            },
+        )
+        .await?;
+
+        Ok(Some(doc))
+    }
+
     pub async fn get_user_documents(&self, user_id: &Uuid) -> SyncResult<Vec<Document>> {
         let rows = sqlx::query!(
             r#"
             SELECT id, user_id, content, sync_revision, content_hash, title,
-                   created_at, updated_at, deleted_at
+                   created_at, updated_at, deleted_at, expires_at
             FROM documents
             WHERE user_id = $1 AND deleted_at IS NULL
             ORDER BY updated_at DESC
@@ -336,10 +645,233 @@ impl ServerDatabase {
                 created_at: row.created_at,
                 updated_at: row.updated_at,
                 deleted_at: row.deleted_at,
+                expires_at: row.expires_at,
+                sync_status: SyncStatus::Synced,
             })
             .collect())
     }
 
+    /// Like [`Self::get_user_documents`], but yields rows as Postgres returns
+    /// them, so a full sync never holds the user's whole dataset in memory
+    pub fn stream_user_documents(&self, user_id: &Uuid) -> BoxStream<'_, SyncResult<Document>> {
+        sqlx::query(
+            r#"
+            SELECT id, user_id, content, sync_revision, content_hash, title,
+                   created_at, updated_at, deleted_at, expires_at
+            FROM documents
+            WHERE user_id = $1 AND deleted_at IS NULL
+            ORDER BY updated_at DESC
+            "#,
+        )
+        .bind(*user_id)
+        .fetch(&self.pool)
+        .map(|row| crate::queries::parse_document(&row?))
+        .boxed()
+    }
+
+    // Replace a document's indexed tags with those in its content
+    async fn set_document_tags_in_tx(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        doc: &Document,
+    ) -> SyncResult<()> {
+        sqlx::query("DELETE FROM document_tags WHERE document_id = $1")
+            .bind(doc.id)
+            .execute(&mut **tx)
+            .await?;
+        if doc.deleted_at.is_some() {
+            return Ok(());
+        }
+        let tags = extract_tags(&doc.content, &self.tag_path);
+        if !tags.is_empty() {
+            sqlx::query(
+                "INSERT INTO document_tags (document_id, tag) SELECT $1, UNNEST($2::text[])",
+            )
+            .bind(doc.id)
+            .bind(&tags)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Re-index every live document's tags from [`Self::tag_path`], e.g.
+    /// after the path changed. Returns how many documents were indexed.
+    pub async fn rebuild_document_tags(&self) -> SyncResult<u64> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM document_tags")
+            .execute(&mut *tx)
+            .await?;
+        let rows = sqlx::query(
+            r#"
+            SELECT id, user_id, content, sync_revision, content_hash, title,
+                   created_at, updated_at, deleted_at, expires_at
+            FROM documents
+            WHERE deleted_at IS NULL
+            "#,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in &rows {
+            let doc = crate::queries::parse_document(row)?;
+            self.set_document_tags_in_tx(&mut tx, &doc).await?;
+        }
+        tx.commit().await?;
+        Ok(rows.len() as u64)
+    }
+
+    /// A user's live documents tagged `tag`, most recently updated first.
+    /// With `include_shared`, documents shared with the user count too.
+    pub async fn get_documents_with_tag(
+        &self,
+        user_id: &Uuid,
+        tag: &str,
+        include_shared: bool,
+    ) -> SyncResult<Vec<Document>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT d.id, d.user_id, d.content, d.sync_revision, d.content_hash, d.title,
+                   d.created_at, d.updated_at, d.deleted_at, d.expires_at
+            FROM documents d
+            JOIN document_tags t ON t.document_id = d.id
+            WHERE t.tag = $2 AND d.deleted_at IS NULL
+              AND (d.user_id = $1
+                   OR ($3 AND EXISTS (
+                       SELECT 1 FROM document_shares s
+                       WHERE s.document_id = d.id AND s.user_id = $1)))
+            ORDER BY d.updated_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(tag)
+        .bind(include_shared)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(crate::queries::parse_document).collect()
+    }
+
+    /// Live documents whose expiry has passed, as (document_id, owner) pairs
+    pub async fn get_expired_documents(&self) -> SyncResult<Vec<(Uuid, Uuid)>> {
+        let rows = sqlx::query_as(
+            r#"
+            SELECT id, user_id FROM documents
+            WHERE expires_at <= NOW() AND deleted_at IS NULL
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows)
+    }
+
+    pub async fn user_stats(&self, user_id: &Uuid) -> SyncResult<UserStats> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) FILTER (WHERE deleted_at IS NULL) AS document_count,
+                   COALESCE(SUM(octet_length(content::text)), 0)::BIGINT AS total_bytes,
+                   COUNT(*) FILTER (WHERE deleted_at IS NOT NULL) AS deleted_count,
+                   GREATEST(MAX(updated_at), MAX(deleted_at)) AS last_activity
+            FROM documents
+            WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(UserStats {
+            document_count: row.try_get("document_count")?,
+            total_bytes: row.try_get("total_bytes")?,
+            deleted_count: row.try_get("deleted_count")?,
+            last_activity: row.try_get("last_activity")?,
+        })
+    }
+
+    /// Grant `user_id` access to a document, replacing any earlier permission
+    pub async fn share_document(
+        &self,
+        document_id: &Uuid,
+        user_id: &Uuid,
+        permission: SharePermission,
+    ) -> SyncResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO document_shares (document_id, user_id, permission)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (document_id, user_id) DO UPDATE
+            SET permission = excluded.permission
+            "#,
+        )
+        .bind(document_id)
+        .bind(user_id)
+        .bind(permission.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // The permission a document has been shared with, if any
+    pub async fn get_share_permission(
+        &self,
+        document_id: &Uuid,
+        user_id: &Uuid,
+    ) -> SyncResult<Option<SharePermission>> {
+        let permission: Option<String> = sqlx::query_scalar(
+            "SELECT permission FROM document_shares WHERE document_id = $1 AND user_id = $2",
+        )
+        .bind(document_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(permission.and_then(|p| p.parse().ok()))
+    }
+
+    // Users other than the owner who can see a document
+    pub async fn get_document_share_users(&self, document_id: &Uuid) -> SyncResult<Vec<Uuid>> {
+        let user_ids =
+            sqlx::query_scalar("SELECT user_id FROM document_shares WHERE document_id = $1")
+                .bind(document_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(user_ids)
+    }
+
+    // Live documents other users have shared with this one
+    pub async fn get_shared_documents(&self, user_id: &Uuid) -> SyncResult<Vec<Document>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT d.id, d.user_id, d.content, d.sync_revision, d.content_hash, d.title,
+                   d.created_at, d.updated_at, d.deleted_at, d.expires_at
+            FROM documents d
+            JOIN document_shares s ON s.document_id = d.id
+            WHERE s.user_id = $1 AND d.deleted_at IS NULL
+            ORDER BY d.updated_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(crate::queries::parse_document).collect()
+    }
+
+    /// Streaming counterpart of [`Self::get_shared_documents`]
+    pub fn stream_shared_documents(&self, user_id: &Uuid) -> BoxStream<'_, SyncResult<Document>> {
+        sqlx::query(
+            r#"
+            SELECT d.id, d.user_id, d.content, d.sync_revision, d.content_hash, d.title,
+                   d.created_at, d.updated_at, d.deleted_at, d.expires_at
+            FROM documents d
+            JOIN document_shares s ON s.document_id = d.id
+            WHERE s.user_id = $1 AND d.deleted_at IS NULL
+            ORDER BY d.updated_at DESC
+            "#,
+        )
+        .bind(*user_id)
+        .fetch(&self.pool)
+        .map(|row| crate::queries::parse_document(&row?))
+        .boxed()
+    }
+
     pub async fn create_revision(&self, doc: &Document, patch: Option<&Patch>) -> SyncResult<()> {
         let patch_json = patch.map(|p| serde_json::to_value(p).unwrap());
         let content_json = serde_json::to_value(&doc.content).unwrap();
@@ -421,6 +953,84 @@ impl ServerDatabase {
         Ok(())
     }
 
+    /// Append a mutation to the operation log and return its sequence number.
+    ///
+    /// Must run in the same transaction as the document write: the per-user
+    /// counter row stays locked until commit, so sequences are gapless and
+    /// ordered by commit. The document's post-write `sync_revision` is recorded.
+    pub async fn append_op(
+        &self,
+        tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+        user_id: &Uuid,
+        document_id: &Uuid,
+        operation: ChangeEventType,
+    ) -> SyncResult<i64> {
+        let sequence: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO operation_sequences (user_id, last_sequence)
+            VALUES ($1, 1)
+            ON CONFLICT (user_id) DO UPDATE
+            SET last_sequence = operation_sequences.last_sequence + 1
+            RETURNING last_sequence
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO operation_log (user_id, sequence, document_id, operation, sync_revision)
+            SELECT $1, $2, $3, $4, sync_revision FROM documents WHERE id = $3
+            "#,
+        )
+        .bind(user_id)
+        .bind(sequence)
+        .bind(document_id)
+        .bind(operation.to_string())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(sequence)
+    }
+
+    // Get logged operations after a sequence number, oldest first
+    pub async fn get_operations_since(
+        &self,
+        user_id: &Uuid,
+        since_sequence: i64,
+    ) -> SyncResult<Vec<OperationLogEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT sequence, document_id, operation, sync_revision, created_at
+            FROM operation_log
+            WHERE user_id = $1 AND sequence > $2
+            ORDER BY sequence ASC
+            "#,
+        )
+        .bind(user_id)
+        .bind(since_sequence)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut entries = Vec::with_capacity(rows.len());
+        for row in rows {
+            let operation: String = row.try_get("operation")?;
+            let Ok(operation) = operation.parse::<ChangeEventType>() else {
+                continue;
+            };
+            entries.push(OperationLogEntry {
+                sequence: row.try_get("sequence")?,
+                document_id: row.try_get("document_id")?,
+                operation,
+                sync_revision: row.try_get("sync_revision")?,
+                created_at: row.try_get("created_at")?,
+            });
+        }
+
+        Ok(entries)
+    }
+
     // Get changes since a specific sequence number for sync
     pub async fn get_changes_since(
         &self,
@@ -518,4 +1128,204 @@ impl ServerDatabase {
 
         Ok(events)
     }
+
+    /// The responses recorded for a keyed operation, unless older than `ttl`
+    pub async fn get_idempotent_responses(
+        &self,
+        user_id: &Uuid,
+        idempotency_key: &Uuid,
+        ttl: std::time::Duration,
+    ) -> SyncResult<Option<Vec<ServerMessage>>> {
+        let row = sqlx::query(
+            r#"
+            SELECT responses
+            FROM idempotency_keys
+            WHERE user_id = $1 AND idempotency_key = $2
+              AND created_at > NOW() - make_interval(secs => $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(idempotency_key)
+        .bind(ttl.as_secs_f64())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_value(row.try_get("responses")?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Record the responses to a keyed operation, dropping the user's expired keys
+    pub async fn save_idempotent_responses(
+        &self,
+        user_id: &Uuid,
+        idempotency_key: &Uuid,
+        responses: &[ServerMessage],
+        ttl: std::time::Duration,
+    ) -> SyncResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE user_id = $1 AND created_at <= NOW() - make_interval(secs => $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(ttl.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (user_id, idempotency_key, responses)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, idempotency_key) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(idempotency_key)
+        .bind(serde_json::to_value(responses)?)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Append an upload chunk if it starts where the stored data ends,
+    /// creating the blob on its first chunk. Returns how many bytes are
+    /// stored and the blob's total size, or None if the ID belongs to a blob
+    /// of another document.
+    pub async fn append_blob_chunk(
+        &self,
+        chunk: BlobChunkParams<'_>,
+    ) -> SyncResult<Option<(i64, i64)>> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO blobs (id, document_id, user_id, name, total_size)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        )
+        .bind(chunk.blob_id)
+        .bind(chunk.document_id)
+        .bind(chunk.user_id)
+        .bind(chunk.name)
+        .bind(chunk.total_size)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE blobs SET data = data || $3
+            WHERE id = $1 AND document_id = $2
+              AND octet_length(data) = $4
+              AND octet_length(data) + octet_length($3) <= total_size
+            "#,
+        )
+        .bind(chunk.blob_id)
+        .bind(chunk.document_id)
+        .bind(chunk.data)
+        .bind(chunk.offset)
+        .execute(&mut *tx)
+        .await?;
+
+        let received: Option<(i64, i64)> = sqlx::query_as(
+            "SELECT octet_length(data)::BIGINT, total_size FROM blobs WHERE id = $1 AND document_id = $2",
+        )
+        .bind(chunk.blob_id)
+        .bind(chunk.document_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(received)
+    }
+
+    pub async fn get_blob(&self, blob_id: &Uuid) -> SyncResult<Option<StoredBlob>> {
+        let row =
+            sqlx::query("SELECT id, document_id, name, total_size, data FROM blobs WHERE id = $1")
+                .bind(blob_id)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        match row {
+            Some(row) => Ok(Some(StoredBlob {
+                id: row.try_get("id")?,
+                document_id: row.try_get("document_id")?,
+                name: row.try_get("name")?,
+                total_size: row.try_get("total_size")?,
+                data: row.try_get("data")?,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn delete_blob(&self, blob_id: &Uuid) -> SyncResult<()> {
+        sqlx::query("DELETE FROM blobs WHERE id = $1")
+            .bind(blob_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn log_conflict(&self, params: ConflictLogParams<'_>) -> SyncResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO conflict_log
+                (document_id, user_id, client_id, client_version, server_version, resolution)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(params.document_id)
+        .bind(params.user_id)
+        .bind(params.client_id)
+        .bind(params.client_version)
+        .bind(params.server_version)
+        .bind(params.resolution)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Most recent conflicts first, optionally for one document only
+    pub async fn get_conflict_log(
+        &self,
+        document_id: Option<Uuid>,
+        limit: i64,
+    ) -> SyncResult<Vec<ConflictLogEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, document_id, user_id, client_id, client_version, server_version,
+                   resolution, resolved_at
+            FROM conflict_log
+            WHERE $1::uuid IS NULL OR document_id = $1
+            ORDER BY resolved_at DESC, id DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(document_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(ConflictLogEntry {
+                    id: row.try_get("id")?,
+                    document_id: row.try_get("document_id")?,
+                    user_id: row.try_get("user_id")?,
+                    client_id: row.try_get("client_id")?,
+                    client_version: row.try_get("client_version")?,
+                    server_version: row.try_get("server_version")?,
+                    resolution: row.try_get("resolution")?,
+                    resolved_at: row.try_get("resolved_at")?,
+                })
+            })
+            .collect()
+    }
 }
@@ -1,15 +1,21 @@
 pub mod api;
 pub mod auth;
+pub mod cors;
 pub mod database;
 pub mod monitoring;
 pub mod queries;
 pub mod sync_handler;
+pub mod validation;
 pub mod websocket;
 
-use dashmap::DashMap;
-use replicant_core::protocol::ServerMessage;
+use dashmap::{DashMap, DashSet};
+use replicant_core::patches::PatchLimits;
+use replicant_core::protocol::{
+    ChangeEventType, ServerMessage, DEFAULT_MAX_DOCUMENT_BYTES, DEFAULT_MAX_MESSAGE_BYTES,
+};
 use std::collections::HashSet;
 use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
 use uuid::Uuid;
 
 // Registry of connected clients: (user_id, client_id) -> channel
@@ -18,19 +24,313 @@ pub type ClientRegistry = Arc<DashMap<(Uuid, Uuid), tokio::sync::mpsc::Sender<Se
 // Auxiliary mapping to track which clients belong to which user
 pub type UserClients = Arc<DashMap<Uuid, HashSet<Uuid>>>;
 
+// The live connection for each (user_id, client_id)
+pub type ClientConnections = Arc<DashMap<(Uuid, Uuid), ClientConnection>>;
+
+#[derive(Clone)]
+pub struct ClientConnection {
+    pub connection_id: String,
+    // Notified to close this connection when a reconnect replaces it
+    pub evict: Arc<tokio::sync::Notify>,
+    // Notified to drop this connection when it can't keep up with broadcasts
+    pub overloaded: Arc<tokio::sync::Notify>,
+}
+
+// Advisory document locks: document_id -> (user_id, client_id) of the holder
+pub type DocumentLocks = Arc<DashMap<Uuid, (Uuid, Uuid)>>;
+
+// Connections that asked to hear about presence changes: (user_id, client_id)
+pub type PresenceSubscribers = Arc<DashSet<(Uuid, Uuid)>>;
+
+// Documents each connection subscribed to: (user_id, client_id) -> document
+// IDs. Connections without an entry hear about every document.
+pub type DocumentSubscriptions = Arc<DashMap<(Uuid, Uuid), HashSet<Uuid>>>;
+
+/// How long a new connection may take to authenticate before it is closed
+pub const DEFAULT_AUTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long the server remembers the response to a keyed document operation
+pub const DEFAULT_IDEMPOTENCY_TTL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// How long a rotated-out API secret keeps working unless the rotation request says otherwise
+pub const DEFAULT_SECRET_ROTATION_GRACE: std::time::Duration =
+    std::time::Duration::from_secs(24 * 60 * 60);
+
+/// How often the server deletes documents whose expiry has passed
+pub const DEFAULT_EXPIRY_SWEEP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How many times startup tries to reach the database before giving up
+pub const DEFAULT_DB_CONNECT_ATTEMPTS: u32 = 5;
+
+/// Wait before the second database connection attempt; it doubles after each failure
+pub const DEFAULT_DB_CONNECT_BACKOFF: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Default cap on a single blob
+pub const DEFAULT_MAX_BLOB_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How many outgoing messages may queue up for one client by default
+pub const DEFAULT_CLIENT_QUEUE_LIMIT: usize = 100;
+
+/// What the server does when a client's outgoing queue is full, so one slow
+/// client can't stall broadcasts to everyone else
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlowClientPolicy {
+    /// Drop the client's connection straight away
+    #[default]
+    Disconnect,
+    /// Wait up to this long for room in the queue, then drop the connection
+    Wait(std::time::Duration),
+}
+
+/// Caps on what clients may send
+#[derive(Debug, Clone, Copy)]
+pub struct SizeLimits {
+    /// Largest WebSocket message handled; bigger ones get a ValidationFailed error
+    pub max_message_bytes: usize,
+    /// Largest serialized document content accepted for storage
+    pub max_document_bytes: usize,
+    /// Largest blob accepted for upload
+    pub max_blob_bytes: u64,
+    /// Operation count and path depth caps on incoming JSON patches
+    pub patch: PatchLimits,
+}
+
+impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            max_document_bytes: DEFAULT_MAX_DOCUMENT_BYTES,
+            max_blob_bytes: DEFAULT_MAX_BLOB_BYTES,
+            patch: PatchLimits::default(),
+        }
+    }
+}
+
+impl SizeLimits {
+    /// Hard cap for the WebSocket transport. Messages up to this size are read
+    /// and answered with an error; anything larger closes the connection, so a
+    /// single frame can never exhaust memory.
+    pub fn transport_max_bytes(&self) -> usize {
+        self.max_message_bytes.saturating_mul(2)
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<database::ServerDatabase>,
     pub auth: auth::AuthState,
+    // Checks Authenticate messages; AuthState unless the deployment plugs in its own
+    pub authenticator: Arc<dyn auth::Authenticator>,
     pub monitoring: Option<monitoring::MonitoringLayer>,
     pub clients: ClientRegistry,
     pub user_clients: UserClients,
+    pub connections: ClientConnections,
+    pub document_locks: DocumentLocks,
+    pub presence_subscribers: PresenceSubscribers,
+    pub document_subscriptions: DocumentSubscriptions,
+    pub limits: SizeLimits,
+    // Bearer token for the admin API; None disables it
+    pub admin_token: Option<String>,
+    pub secret_rotation_grace: std::time::Duration,
+    pub auth_timeout: std::time::Duration,
+    // Opt-in content validation; None stores any JSON
+    pub schemas: Option<Arc<validation::ContentSchemas>>,
+    // Opt-in sharing of documents between users; when off every document is
+    // visible to its owner only
+    pub sharing_enabled: bool,
+    // Opt-in record of every conflict the server resolves, for the admin API
+    pub conflict_log_enabled: bool,
+    // How long a resent operation with the same idempotency key gets the
+    // original response
+    pub idempotency_ttl: std::time::Duration,
+    // Outgoing messages queued per client before the slow-client policy applies
+    pub client_queue_limit: usize,
+    pub slow_client_policy: SlowClientPolicy,
+}
+
+impl AppState {
+    /// State with every option at its default and no monitoring, for tests;
+    /// override fields with struct update syntax
+    pub fn for_tests(db: Arc<database::ServerDatabase>) -> Self {
+        Self {
+            auth: auth::AuthState::new(db.clone()),
+            authenticator: Arc::new(auth::AuthState::new(db.clone())),
+            db,
+            monitoring: None,
+            clients: Arc::new(DashMap::new()),
+            user_clients: Arc::new(DashMap::new()),
+            connections: Arc::new(DashMap::new()),
+            document_locks: Arc::new(DashMap::new()),
+            presence_subscribers: Arc::new(DashSet::new()),
+            document_subscriptions: Arc::new(DashMap::new()),
+            limits: SizeLimits::default(),
+            admin_token: None,
+            secret_rotation_grace: DEFAULT_SECRET_ROTATION_GRACE,
+            auth_timeout: DEFAULT_AUTH_TIMEOUT,
+            schemas: None,
+            sharing_enabled: false,
+            conflict_log_enabled: false,
+            idempotency_ttl: DEFAULT_IDEMPOTENCY_TTL,
+            client_queue_limit: DEFAULT_CLIENT_QUEUE_LIMIT,
+            slow_client_policy: SlowClientPolicy::default(),
+        }
+    }
+
+    /// Release every lock held by a client, returning the affected document IDs
+    pub fn release_client_locks(&self, user_id: Uuid, client_id: Uuid) -> Vec<Uuid> {
+        let mut released = Vec::new();
+        self.document_locks.retain(|document_id, holder| {
+            if *holder == (user_id, client_id) {
+                released.push(*document_id);
+                false
+            } else {
+                true
+            }
+        });
+        released
+    }
+
+    /// The user's connected client IDs, sorted for stable output
+    pub fn active_clients(&self, user_id: Uuid) -> Vec<Uuid> {
+        let mut client_ids: Vec<Uuid> = self
+            .user_clients
+            .get(&user_id)
+            .map(|clients| clients.iter().copied().collect())
+            .unwrap_or_default();
+        client_ids.sort();
+        client_ids
+    }
+
+    /// Send a message to every connected client of a user, returning how many
+    /// received it
+    pub async fn send_to_user(&self, user_id: Uuid, message: ServerMessage) -> usize {
+        let senders: Vec<_> = self
+            .active_clients(user_id)
+            .iter()
+            .filter_map(|id| {
+                self.clients
+                    .get(&(user_id, *id))
+                    .map(|tx| (*id, tx.clone()))
+            })
+            .collect();
+        let mut delivered = 0;
+        for (client_id, sender) in senders {
+            if self
+                .deliver(user_id, client_id, &sender, message.clone())
+                .await
+            {
+                delivered += 1;
+            }
+        }
+        delivered
+    }
+
+    /// Queue a message for one client, applying the slow-client policy if its
+    /// queue is full. Returns whether the message was queued; a client that
+    /// fell behind is unregistered and its connection dropped.
+    pub async fn deliver(
+        &self,
+        user_id: Uuid,
+        client_id: Uuid,
+        sender: &tokio::sync::mpsc::Sender<ServerMessage>,
+        message: ServerMessage,
+    ) -> bool {
+        let message = match sender.try_send(message) {
+            Ok(()) => return true,
+            Err(TrySendError::Closed(_)) => return false,
+            Err(TrySendError::Full(message)) => message,
+        };
+        if let SlowClientPolicy::Wait(grace) = self.slow_client_policy {
+            if let Ok(result) = tokio::time::timeout(grace, sender.send(message)).await {
+                return result.is_ok();
+            }
+        }
+
+        tracing::warn!(
+            "Client {} of user {} is not keeping up with its messages, disconnecting",
+            client_id,
+            user_id
+        );
+        // Only the connection behind this queue, not a newer one for the client
+        let removed = self
+            .clients
+            .remove_if(&(user_id, client_id), |_, tx| tx.same_channel(sender))
+            .is_some();
+        if removed {
+            if let Some(connection) = self.connections.get(&(user_id, client_id)) {
+                connection.overloaded.notify_one();
+            }
+        }
+        false
+    }
+
+    /// Whether a connection wants broadcasts about `document_id`
+    pub fn is_subscribed(&self, user_id: Uuid, client_id: Uuid, document_id: Uuid) -> bool {
+        self.document_subscriptions
+            .get(&(user_id, client_id))
+            .is_none_or(|documents| documents.contains(&document_id))
+    }
+
+    /// Delete every document past its expiry and tell the clients that could
+    /// see it. Returns how many documents were deleted.
+    pub async fn sweep_expired_documents(&self) -> replicant_core::SyncResult<usize> {
+        let expired = self.db.get_expired_documents().await?;
+        for (document_id, owner_id) in &expired {
+            // Tell sharers before the delete, while the document is still live
+            let mut user_ids = vec![*owner_id];
+            if self.sharing_enabled {
+                user_ids.extend(self.db.get_document_share_users(document_id).await?);
+            }
+            // Logged like any other delete, so catching-up clients see it
+            let mut tx = self.db.pool.begin().await?;
+            self.db
+                .delete_document_in_tx(&mut tx, document_id, owner_id)
+                .await?;
+            self.db
+                .append_op(&mut tx, owner_id, document_id, ChangeEventType::Delete)
+                .await?;
+            tx.commit().await?;
+            self.document_locks.remove(document_id);
+
+            tracing::info!("Deleted expired document {}", document_id);
+            for user_id in user_ids {
+                self.send_to_user(
+                    user_id,
+                    ServerMessage::DocumentDeleted {
+                        document_id: *document_id,
+                    },
+                )
+                .await;
+            }
+        }
+        Ok(expired.len())
+    }
+
+    /// Send the user's current client list to each of their presence subscribers
+    pub async fn broadcast_presence(&self, user_id: Uuid) {
+        let client_ids = self.active_clients(user_id);
+        let senders: Vec<_> = client_ids
+            .iter()
+            .filter(|id| self.presence_subscribers.contains(&(user_id, **id)))
+            .filter_map(|id| {
+                self.clients
+                    .get(&(user_id, *id))
+                    .map(|tx| (*id, tx.clone()))
+            })
+            .collect();
+        for (client_id, sender) in senders {
+            let message = ServerMessage::PresenceUpdate {
+                client_ids: client_ids.clone(),
+            };
+            self.deliver(user_id, client_id, &sender, message).await;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use replicant_core::models::Document;
+    use replicant_core::models::{Document, SyncStatus};
     use serde_json::json;
     use uuid::Uuid;
 
@@ -71,6 +371,8 @@ mod tests {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         // Save document
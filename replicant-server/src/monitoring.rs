@@ -1,6 +1,13 @@
 use chrono::Local;
 use colored::*;
-use replicant_core::protocol::{ClientMessage, ServerMessage};
+use dashmap::DashMap;
+use hdrhistogram::Histogram;
+use replicant_core::protocol::{ChangeEventType, ClientMessage, ServerMessage};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tracing::info;
 
@@ -32,76 +39,434 @@ pub enum LogMessage {
     },
 }
 
+/// Point-in-time copy of the server's counters
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub connected_clients: u64,
+    /// Messages received, keyed by `ClientMessage` variant
+    pub messages_received: BTreeMap<&'static str, u64>,
+    /// Messages sent, keyed by `ServerMessage` variant
+    pub messages_sent: BTreeMap<&'static str, u64>,
+    pub documents_created: u64,
+    pub documents_updated: u64,
+    pub documents_deleted: u64,
+    pub conflicts_detected: u64,
+    pub errors: u64,
+    /// Number of sync requests handled and their total handling time
+    pub sync_count: u64,
+    pub sync_duration: Duration,
+    /// Handling latency keyed by `ClientMessage` variant; empty unless
+    /// latency histograms are enabled
+    pub latencies: BTreeMap<&'static str, LatencySummary>,
+}
+
+/// Percentiles of message handling time
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl Metrics {
+    /// Render in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let by_type = |counts: &BTreeMap<&'static str, u64>| {
+            counts
+                .iter()
+                .map(|(kind, count)| (format!("type=\"{}\"", kind), *count))
+                .collect::<Vec<_>>()
+        };
+
+        let mut out = String::new();
+        write_metric(
+            &mut out,
+            "replicant_connected_clients",
+            "gauge",
+            "Open WebSocket connections",
+            &[(String::new(), self.connected_clients)],
+        );
+        write_metric(
+            &mut out,
+            "replicant_messages_received_total",
+            "counter",
+            "Client messages received by type",
+            &by_type(&self.messages_received),
+        );
+        write_metric(
+            &mut out,
+            "replicant_messages_sent_total",
+            "counter",
+            "Server messages sent by type",
+            &by_type(&self.messages_sent),
+        );
+        write_metric(
+            &mut out,
+            "replicant_documents_stored_total",
+            "counter",
+            "Document writes committed by operation",
+            &[
+                ("operation=\"create\"".to_string(), self.documents_created),
+                ("operation=\"update\"".to_string(), self.documents_updated),
+                ("operation=\"delete\"".to_string(), self.documents_deleted),
+            ],
+        );
+        write_metric(
+            &mut out,
+            "replicant_conflicts_detected_total",
+            "counter",
+            "Conflicts detected while applying updates",
+            &[(String::new(), self.conflicts_detected)],
+        );
+        write_metric(
+            &mut out,
+            "replicant_errors_total",
+            "counter",
+            "Errors while handling client messages",
+            &[(String::new(), self.errors)],
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP replicant_sync_duration_seconds Time spent handling sync requests"
+        );
+        let _ = writeln!(out, "# TYPE replicant_sync_duration_seconds summary");
+        let _ = writeln!(
+            out,
+            "replicant_sync_duration_seconds_sum {}",
+            self.sync_duration.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "replicant_sync_duration_seconds_count {}",
+            self.sync_count
+        );
+
+        if !self.latencies.is_empty() {
+            let name = "replicant_message_duration_seconds";
+            let _ = writeln!(out, "# HELP {} Message handling time by type", name);
+            let _ = writeln!(out, "# TYPE {} summary", name);
+            for (kind, latency) in &self.latencies {
+                for (quantile, value) in [
+                    ("0.5", latency.p50),
+                    ("0.95", latency.p95),
+                    ("0.99", latency.p99),
+                ] {
+                    let _ = writeln!(
+                        out,
+                        "{}{{type=\"{}\",quantile=\"{}\"}} {}",
+                        name,
+                        kind,
+                        quantile,
+                        value.as_secs_f64()
+                    );
+                }
+                let _ = writeln!(out, "{}_count{{type=\"{}\"}} {}", name, kind, latency.count);
+            }
+        }
+
+        out
+    }
+}
+
+// Write one metric family; samples are (labels, value) with labels unbraced
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(String, u64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+// Lock-free counters shared by every clone of the layer
+#[derive(Default)]
+struct Counters {
+    connected_clients: AtomicU64,
+    messages_received: DashMap<&'static str, AtomicU64>,
+    messages_sent: DashMap<&'static str, AtomicU64>,
+    documents_created: AtomicU64,
+    documents_updated: AtomicU64,
+    documents_deleted: AtomicU64,
+    conflicts_detected: AtomicU64,
+    errors: AtomicU64,
+    sync_count: AtomicU64,
+    sync_duration_micros: AtomicU64,
+}
+
+// Per-message-type handling time in microseconds
+type LatencyHistograms = DashMap<&'static str, Histogram<u64>>;
+
+// Slowest latency tracked precisely; longer samples are clamped to it
+const MAX_TRACKED_LATENCY_MICROS: u64 = 60_000_000;
+
+fn increment(counts: &DashMap<&'static str, AtomicU64>, key: &'static str) {
+    if let Some(count) = counts.get(key) {
+        count.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    counts
+        .entry(key)
+        .or_default()
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+fn collect(counts: &DashMap<&'static str, AtomicU64>) -> BTreeMap<&'static str, u64> {
+    counts
+        .iter()
+        .map(|entry| (*entry.key(), entry.value().load(Ordering::Relaxed)))
+        .collect()
+}
+
+/// Records server activity as metrics and, when a display channel is
+/// attached, streams it to the terminal activity log.
 #[derive(Clone)]
 pub struct MonitoringLayer {
-    tx: mpsc::Sender<LogMessage>,
+    tx: Option<mpsc::Sender<LogMessage>>,
+    counters: Arc<Counters>,
+    latencies: Option<Arc<LatencyHistograms>>,
 }
 
 impl MonitoringLayer {
     pub fn new(tx: mpsc::Sender<LogMessage>) -> Self {
-        Self { tx }
+        Self {
+            tx: Some(tx),
+            counters: Arc::default(),
+            latencies: None,
+        }
     }
 
-    pub async fn log_client_connected(&self, client_id: &str) {
-        let _ = self
-            .tx
-            .send(LogMessage::ClientConnected {
-                client_id: client_id.to_string(),
+    /// Collect metrics without feeding the terminal display
+    pub fn metrics_only() -> Self {
+        Self {
+            tx: None,
+            counters: Arc::default(),
+            latencies: None,
+        }
+    }
+
+    /// Also record per-message-type latency histograms. Off by default since
+    /// every handled message then takes a histogram lock.
+    pub fn with_latency_histograms(mut self) -> Self {
+        self.latencies = Some(Arc::default());
+        self
+    }
+
+    /// Start timing a client message; None when latency histograms are off
+    pub fn start_timer(&self, message: &ClientMessage) -> Option<MessageTimer> {
+        self.latencies.as_ref()?;
+        Some(MessageTimer {
+            message_type: client_message_type(message),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record_latency(&self, timer: MessageTimer) {
+        let Some(latencies) = &self.latencies else {
+            return;
+        };
+        let micros = (timer.started.elapsed().as_micros() as u64).min(MAX_TRACKED_LATENCY_MICROS);
+        latencies
+            .entry(timer.message_type)
+            .or_insert_with(|| {
+                Histogram::new_with_bounds(1, MAX_TRACKED_LATENCY_MICROS, 3)
+                    .expect("valid histogram bounds")
             })
-            .await;
+            .saturating_record(micros);
+    }
+
+    pub fn snapshot(&self) -> Metrics {
+        let c = &self.counters;
+        Metrics {
+            connected_clients: c.connected_clients.load(Ordering::Relaxed),
+            messages_received: collect(&c.messages_received),
+            messages_sent: collect(&c.messages_sent),
+            documents_created: c.documents_created.load(Ordering::Relaxed),
+            documents_updated: c.documents_updated.load(Ordering::Relaxed),
+            documents_deleted: c.documents_deleted.load(Ordering::Relaxed),
+            conflicts_detected: c.conflicts_detected.load(Ordering::Relaxed),
+            errors: c.errors.load(Ordering::Relaxed),
+            sync_count: c.sync_count.load(Ordering::Relaxed),
+            sync_duration: Duration::from_micros(c.sync_duration_micros.load(Ordering::Relaxed)),
+            latencies: self
+                .latencies
+                .iter()
+                .flat_map(|latencies| latencies.iter())
+                .map(|entry| {
+                    let histogram = entry.value();
+                    let percentile = |q: f64| Duration::from_micros(histogram.value_at_quantile(q));
+                    (
+                        *entry.key(),
+                        LatencySummary {
+                            count: histogram.len(),
+                            p50: percentile(0.5),
+                            p95: percentile(0.95),
+                            p99: percentile(0.99),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    async fn display(&self, log: impl FnOnce() -> LogMessage) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(log()).await;
+        }
+    }
+
+    pub async fn log_client_connected(&self, client_id: &str) {
+        self.counters
+            .connected_clients
+            .fetch_add(1, Ordering::Relaxed);
+        self.display(|| LogMessage::ClientConnected {
+            client_id: client_id.to_string(),
+        })
+        .await;
     }
 
     pub async fn log_client_disconnected(&self, client_id: &str) {
-        let _ = self
-            .tx
-            .send(LogMessage::ClientDisconnected {
-                client_id: client_id.to_string(),
-            })
-            .await;
+        self.counters
+            .connected_clients
+            .fetch_sub(1, Ordering::Relaxed);
+        self.display(|| LogMessage::ClientDisconnected {
+            client_id: client_id.to_string(),
+        })
+        .await;
     }
 
-    pub async fn log_message_received(&self, client_id: &str, message: ClientMessage) {
-        let _ = self
-            .tx
-            .send(LogMessage::MessageReceived {
-                client_id: client_id.to_string(),
-                message,
-            })
-            .await;
+    pub async fn log_message_received(&self, client_id: &str, message: &ClientMessage) {
+        increment(
+            &self.counters.messages_received,
+            client_message_type(message),
+        );
+        self.display(|| LogMessage::MessageReceived {
+            client_id: client_id.to_string(),
+            message: message.clone(),
+        })
+        .await;
     }
 
-    pub async fn log_message_sent(&self, client_id: &str, message: ServerMessage) {
-        let _ = self
-            .tx
-            .send(LogMessage::MessageSent {
-                client_id: client_id.to_string(),
-                message,
-            })
-            .await;
+    pub async fn log_message_sent(&self, client_id: &str, message: &ServerMessage) {
+        increment(&self.counters.messages_sent, server_message_type(message));
+        self.display(|| LogMessage::MessageSent {
+            client_id: client_id.to_string(),
+            message: message.clone(),
+        })
+        .await;
     }
 
-    pub async fn log_patch_applied(&self, document_id: &str, patch: &serde_json::Value) {
-        let patch_json = serde_json::to_string_pretty(patch).unwrap_or_default();
-        let _ = self
-            .tx
-            .send(LogMessage::PatchApplied {
-                document_id: document_id.to_string(),
-                patch: patch_json,
-            })
-            .await;
+    pub async fn log_patch_applied(&self, document_id: &str, patch: &impl serde::Serialize) {
+        self.display(|| LogMessage::PatchApplied {
+            document_id: document_id.to_string(),
+            patch: serde_json::to_string_pretty(patch).unwrap_or_default(),
+        })
+        .await;
     }
 
     pub async fn log_conflict_detected(&self, document_id: &str) {
-        let _ = self
-            .tx
-            .send(LogMessage::ConflictDetected {
-                document_id: document_id.to_string(),
-            })
-            .await;
+        self.counters
+            .conflicts_detected
+            .fetch_add(1, Ordering::Relaxed);
+        self.display(|| LogMessage::ConflictDetected {
+            document_id: document_id.to_string(),
+        })
+        .await;
     }
 
     pub async fn log_error(&self, message: String) {
-        let _ = self.tx.send(LogMessage::Error { message }).await;
+        self.counters.errors.fetch_add(1, Ordering::Relaxed);
+        self.display(|| LogMessage::Error { message }).await;
+    }
+
+    /// Count a committed document write
+    pub fn record_document_stored(&self, operation: ChangeEventType) {
+        let counter = match operation {
+            ChangeEventType::Create => &self.counters.documents_created,
+            ChangeEventType::Update => &self.counters.documents_updated,
+            ChangeEventType::Delete => &self.counters.documents_deleted,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_sync_duration(&self, elapsed: Duration) {
+        self.counters.sync_count.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .sync_duration_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// An in-flight measurement started by `MonitoringLayer::start_timer`
+#[derive(Debug)]
+pub struct MessageTimer {
+    message_type: &'static str,
+    started: Instant,
+}
+
+fn client_message_type(message: &ClientMessage) -> &'static str {
+    match message {
+        ClientMessage::Authenticate { .. } => "Authenticate",
+        ClientMessage::CreateDocument { .. } => "CreateDocument",
+        ClientMessage::UpdateDocument { .. } => "UpdateDocument",
+        ClientMessage::MergePatchDocument { .. } => "MergePatchDocument",
+        ClientMessage::DeleteDocument { .. } => "DeleteDocument",
+        ClientMessage::Transaction { .. } => "Transaction",
+        ClientMessage::OperationBatch { .. } => "OperationBatch",
+        ClientMessage::ResolveConflict { .. } => "ResolveConflict",
+        ClientMessage::TransferOwnership { .. } => "TransferOwnership",
+        ClientMessage::ShareDocument { .. } => "ShareDocument",
+        ClientMessage::RequestSync { .. } => "RequestSync",
+        ClientMessage::RequestFullSync => "RequestFullSync",
+        ClientMessage::RequestDocument { .. } => "RequestDocument",
+        ClientMessage::QueryByTag { .. } => "QueryByTag",
+        ClientMessage::Ping => "Ping",
+        ClientMessage::GetChangesSince { .. } => "GetChangesSince",
+        ClientMessage::AckChanges { .. } => "AckChanges",
+        ClientMessage::AcquireLock { .. } => "AcquireLock",
+        ClientMessage::ReleaseLock { .. } => "ReleaseLock",
+        ClientMessage::RequestPresence => "RequestPresence",
+        ClientMessage::Subscribe { .. } => "Subscribe",
+        ClientMessage::Unsubscribe { .. } => "Unsubscribe",
+        ClientMessage::UploadBlob { .. } => "UploadBlob",
+        ClientMessage::RequestBlob { .. } => "RequestBlob",
+        ClientMessage::DeleteBlob { .. } => "DeleteBlob",
+    }
+}
+
+fn server_message_type(message: &ServerMessage) -> &'static str {
+    match message {
+        ServerMessage::AuthSuccess { .. } => "AuthSuccess",
+        ServerMessage::AuthError { .. } => "AuthError",
+        ServerMessage::AuthFailed { .. } => "AuthFailed",
+        ServerMessage::DocumentCreated { .. } => "DocumentCreated",
+        ServerMessage::DocumentUpdated { .. } => "DocumentUpdated",
+        ServerMessage::DocumentDeleted { .. } => "DocumentDeleted",
+        ServerMessage::DocumentCreatedResponse { .. } => "DocumentCreatedResponse",
+        ServerMessage::DocumentUpdatedResponse { .. } => "DocumentUpdatedResponse",
+        ServerMessage::UpdateRejected { .. } => "UpdateRejected",
+        ServerMessage::DocumentDeletedResponse { .. } => "DocumentDeletedResponse",
+        ServerMessage::TransactionResponse { .. } => "TransactionResponse",
+        ServerMessage::DocumentShared { .. } => "DocumentShared",
+        ServerMessage::SyncDocument { .. } => "SyncDocument",
+        ServerMessage::SyncComplete { .. } => "SyncComplete",
+        ServerMessage::ConflictDetected { .. } => "ConflictDetected",
+        ServerMessage::Error { .. } => "Error",
+        ServerMessage::Pong => "Pong",
+        ServerMessage::Changes { .. } => "Changes",
+        ServerMessage::ChangesAcknowledged { .. } => "ChangesAcknowledged",
+        ServerMessage::LockResponse { .. } => "LockResponse",
+        ServerMessage::LockChanged { .. } => "LockChanged",
+        ServerMessage::PresenceUpdate { .. } => "PresenceUpdate",
+        ServerMessage::BlobUploadAck { .. } => "BlobUploadAck",
+        ServerMessage::BlobChunk { .. } => "BlobChunk",
+        ServerMessage::BlobDeleted { .. } => "BlobDeleted",
+        ServerMessage::TagQueryResult { .. } => "TagQueryResult",
+        ServerMessage::Ack { .. } => "Ack",
     }
 }
 
@@ -132,17 +497,7 @@ pub async fn spawn_monitoring_display(mut rx: mpsc::Receiver<LogMessage>) {
                     );
                 }
                 LogMessage::MessageReceived { client_id, message } => {
-                    let msg_type = match message {
-                        ClientMessage::Authenticate { .. } => "Authenticate",
-                        ClientMessage::CreateDocument { .. } => "CreateDocument",
-                        ClientMessage::UpdateDocument { .. } => "UpdateDocument",
-                        ClientMessage::DeleteDocument { .. } => "DeleteDocument",
-                        ClientMessage::RequestSync { .. } => "RequestSync",
-                        ClientMessage::RequestFullSync => "RequestFullSync",
-                        ClientMessage::Ping => "Ping",
-                        ClientMessage::GetChangesSince { .. } => "GetChangesSince",
-                        ClientMessage::AckChanges { .. } => "AckChanges",
-                    };
+                    let msg_type = client_message_type(&message);
                     info!(
                         "{} {} {} from {}",
                         timestamp.to_string().dimmed(),
@@ -152,23 +507,7 @@ pub async fn spawn_monitoring_display(mut rx: mpsc::Receiver<LogMessage>) {
                     );
                 }
                 LogMessage::MessageSent { client_id, message } => {
-                    let msg_type = match message {
-                        ServerMessage::AuthSuccess { .. } => "AuthSuccess",
-                        ServerMessage::AuthError { .. } => "AuthError",
-                        ServerMessage::DocumentCreated { .. } => "DocumentCreated",
-                        ServerMessage::DocumentUpdated { .. } => "DocumentUpdated",
-                        ServerMessage::DocumentDeleted { .. } => "DocumentDeleted",
-                        ServerMessage::DocumentCreatedResponse { .. } => "DocumentCreatedResponse",
-                        ServerMessage::DocumentUpdatedResponse { .. } => "DocumentUpdatedResponse",
-                        ServerMessage::DocumentDeletedResponse { .. } => "DocumentDeletedResponse",
-                        ServerMessage::SyncDocument { .. } => "SyncDocument",
-                        ServerMessage::SyncComplete { .. } => "SyncComplete",
-                        ServerMessage::ConflictDetected { .. } => "ConflictDetected",
-                        ServerMessage::Error { .. } => "Error",
-                        ServerMessage::Pong => "Pong",
-                        ServerMessage::Changes { .. } => "Changes",
-                        ServerMessage::ChangesAcknowledged { .. } => "ChangesAcknowledged",
-                    };
+                    let msg_type = server_message_type(&message);
                     info!(
                         "{} {} {} to {}",
                         timestamp.to_string().dimmed(),
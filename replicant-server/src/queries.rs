@@ -1,4 +1,7 @@
-use replicant_core::{models::Document, SyncResult};
+use replicant_core::{
+    models::{Document, SyncStatus},
+    SyncResult,
+};
 use sqlx::{postgres::PgRow, PgPool, Row};
 use uuid::Uuid;
 
@@ -14,6 +17,7 @@ pub type DocumentParams = (
     Option<String>,                        // content_hash
     i32,                                   // size_bytes
     Option<String>,                        // title
+    Option<chrono::DateTime<chrono::Utc>>, // expires_at
 );
 
 /// Parse a document from a database row
@@ -34,6 +38,12 @@ pub fn parse_document(row: &PgRow) -> SyncResult<Document> {
         deleted_at: row
             .try_get::<Option<chrono::DateTime<chrono::Local>>, _>("deleted_at")?
             .map(|dt| dt.with_timezone(&chrono::Utc)),
+        expires_at: row
+            .try_get::<Option<chrono::DateTime<chrono::Local>>, _>("expires_at")
+            .ok()
+            .flatten()
+            .map(|dt| dt.with_timezone(&chrono::Utc)),
+        sync_status: SyncStatus::Synced,
     })
 }
 
@@ -69,6 +79,7 @@ pub fn document_to_params(doc: &Document) -> DocumentParams {
         Some(content_hash),
         size_bytes,
         title,
+        doc.expires_at,
     )
 }
 
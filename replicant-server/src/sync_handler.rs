@@ -1,10 +1,26 @@
-use crate::{database::ServerDatabase, monitoring::MonitoringLayer, AppState};
+use crate::{
+    database::{BlobChunkParams, ServerDatabase},
+    monitoring::MonitoringLayer,
+    AppState,
+};
+use chrono::SubsecRound;
+use dashmap::mapref::entry::Entry;
+use futures_util::stream::{BoxStream, TryStreamExt};
 use replicant_core::{
+    conflicts::DocumentConflictStrategy,
     errors::ServerError,
-    patches::{apply_patch, calculate_checksum},
-    protocol::{ClientMessage, ErrorCode, ServerMessage},
+    models::{Document, DocumentPatch},
+    patches::{
+        applicable_operations, apply_merge_patch, apply_patch, apply_patch_with_limits,
+        calculate_checksum, create_patch, has_test_guards,
+    },
+    protocol::{
+        ChangeEventType, ClientMessage, ConflictResolution, ErrorCode, ServerMessage,
+        SharePermission, TransactionOp, BLOB_CHUNK_BYTES,
+    },
     SyncError, SyncResult,
 };
+use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use uuid::Uuid;
@@ -21,11 +37,35 @@ fn is_duplicate_key_error(e: &SyncError) -> bool {
     false
 }
 
+/// Check if a SyncError means the database couldn't be reached, such as a
+/// dropped connection or an exhausted pool, which may clear up on its own.
+fn is_database_unavailable(e: &SyncError) -> bool {
+    matches!(
+        e,
+        SyncError::DatabaseError(
+            sqlx::Error::Io(_)
+                | sqlx::Error::PoolTimedOut
+                | sqlx::Error::PoolClosed
+                | sqlx::Error::WorkerCrashed
+        )
+    )
+}
+
+/// A transaction operation that passed its checks, ready to be written
+enum CheckedOp {
+    Create(Document),
+    // The patched document and the patch that produced it
+    Update(Document, json_patch::Patch),
+    Delete(Uuid),
+}
+
 pub struct SyncHandler {
     db: Arc<ServerDatabase>,
     tx: mpsc::Sender<ServerMessage>,
     user_id: Option<Uuid>,
     client_id: Option<Uuid>,
+    observer: bool,
+    conflict_resolution: Option<ConflictResolution>,
     monitoring: Option<MonitoringLayer>,
     app_state: Arc<AppState>,
 }
@@ -42,6 +82,8 @@ impl SyncHandler {
             tx,
             user_id: None,
             client_id: None,
+            observer: false,
+            conflict_resolution: None,
             monitoring,
             app_state,
         }
@@ -55,13 +97,230 @@ impl SyncHandler {
         self.client_id = Some(client_id);
     }
 
+    pub fn set_observer(&mut self, observer: bool) {
+        self.observer = observer;
+    }
+
+    pub fn set_conflict_resolution(&mut self, conflict_resolution: Option<ConflictResolution>) {
+        self.conflict_resolution = conflict_resolution;
+    }
+
     pub async fn handle_message(&mut self, msg: ClientMessage) -> SyncResult<()> {
+        if let ClientMessage::OperationBatch { operations } = msg {
+            return self.handle_operation_batch(operations).await;
+        }
+
+        let is_sync = matches!(
+            msg,
+            ClientMessage::RequestSync { .. }
+                | ClientMessage::RequestFullSync
+                | ClientMessage::GetChangesSince { .. }
+        );
+        let started = std::time::Instant::now();
+        let timer = self
+            .monitoring
+            .as_ref()
+            .and_then(|monitoring| monitoring.start_timer(&msg));
+
+        let result = match (msg.idempotency_key(), self.user_id) {
+            (Some(key), Some(user_id)) => self.dispatch_idempotent(user_id, key, msg).await,
+            _ => self.dispatch_message(msg).await,
+        };
+
+        if let Some(ref monitoring) = self.monitoring {
+            if is_sync {
+                monitoring.record_sync_duration(started.elapsed());
+            }
+            if let Some(timer) = timer {
+                monitoring.record_latency(timer);
+            }
+        }
+        result
+    }
+
+    // Run each operation of a batch as if it arrived on its own. Failures are
+    // reported per operation, the way the connection reports a failed message.
+    async fn handle_operation_batch(&mut self, operations: Vec<ClientMessage>) -> SyncResult<()> {
+        tracing::info!("Processing batch of {} operations", operations.len());
+        for operation in operations {
+            if !matches!(
+                operation,
+                ClientMessage::CreateDocument { .. }
+                    | ClientMessage::UpdateDocument { .. }
+                    | ClientMessage::MergePatchDocument { .. }
+                    | ClientMessage::DeleteDocument { .. }
+            ) {
+                self.send_error(
+                    ErrorCode::InvalidMessage,
+                    "Batches may only contain document operations",
+                )
+                .await?;
+                continue;
+            }
+
+            if let Err(e) = Box::pin(self.handle_message(operation)).await {
+                tracing::error!("Error handling batched operation: {}", e);
+                self.tx.send(failed_message(&e)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Replay the responses to an operation this key already ran, or run it
+    // and record what it sent back. Failed operations aren't recorded, so a
+    // retry runs them again.
+    async fn dispatch_idempotent(
+        &mut self,
+        user_id: Uuid,
+        key: Uuid,
+        msg: ClientMessage,
+    ) -> SyncResult<()> {
+        let ttl = self.app_state.idempotency_ttl;
+        if let Some(responses) = self
+            .db
+            .get_idempotent_responses(&user_id, &key, ttl)
+            .await?
+        {
+            tracing::info!("Replaying responses to repeated operation {}", key);
+            for response in responses {
+                self.tx.send(response).await?;
+            }
+            return Ok(());
+        }
+
+        // Route this client's responses through a channel that copies them
+        // on their way out
+        let (capture_tx, mut capture_rx) = mpsc::channel::<ServerMessage>(100);
+        let tx = std::mem::replace(&mut self.tx, capture_tx);
+        let forward_tx = tx.clone();
+        let forward = tokio::spawn(async move {
+            let mut responses = Vec::new();
+            while let Some(response) = capture_rx.recv().await {
+                responses.push(response.clone());
+                let _ = forward_tx.send(response).await;
+            }
+            responses
+        });
+
+        let result = self.dispatch_message(msg).await;
+        // Dropping the capture sender ends the forwarding task
+        self.tx = tx;
+        let responses = forward.await.unwrap_or_default();
+
+        if result.is_ok() {
+            if let Err(e) = self
+                .db
+                .save_idempotent_responses(&user_id, &key, &responses, ttl)
+                .await
+            {
+                tracing::warn!("Failed to record idempotency key {}: {}", key, e);
+            }
+        }
+        result
+    }
+
+    // Send each streamed document as a SyncDocument, returning how many went out
+    async fn send_sync_documents(
+        &self,
+        mut documents: BoxStream<'_, SyncResult<Document>>,
+    ) -> SyncResult<usize> {
+        let mut sent = 0;
+        while let Some(doc) = documents.try_next().await? {
+            tracing::debug!(
+                document_id = %doc.id,
+                sync_revision = doc.sync_revision,
+                "Sending SyncDocument"
+            );
+            self.tx
+                .send(ServerMessage::SyncDocument {
+                    document: doc,
+                    relayed: false,
+                })
+                .await?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    fn record_document_stored(&self, operation: ChangeEventType) {
+        if let Some(ref monitoring) = self.monitoring {
+            monitoring.record_document_stored(operation);
+        }
+    }
+
+    // Record a resolved conflict when conflict logging is enabled. Failures
+    // are only logged; they never fail the sync itself.
+    async fn log_conflict(
+        &self,
+        document: &Document,
+        client_version: &serde_json::Value,
+        resolution: &str,
+    ) {
+        if !self.app_state.conflict_log_enabled {
+            return;
+        }
+        let params = crate::database::ConflictLogParams {
+            document_id: &document.id,
+            user_id: &document.user_id,
+            client_id: self.client_id,
+            client_version,
+            server_version: &document.content,
+            resolution,
+        };
+        if let Err(e) = self.db.log_conflict(params).await {
+            tracing::warn!("Failed to log conflict for document {}: {}", document.id, e);
+        }
+    }
+
+    async fn dispatch_message(&mut self, msg: ClientMessage) -> SyncResult<()> {
         let user_id = self.user_id.ok_or(ServerError::ServerSync(
             "Unauthorized: user_id not found".to_string(),
         ))?;
 
+        // Merge patches and conflict resolutions go through the regular update
+        // path as a JSON Patch
+        let msg = match msg {
+            ClientMessage::MergePatchDocument {
+                document_id,
+                merge_patch,
+                content_hash,
+                ..
+            } => {
+                self.merge_patch_to_update(document_id, &merge_patch, content_hash)
+                    .await?
+            }
+            ClientMessage::ResolveConflict {
+                document_id,
+                content,
+            } => self.resolution_to_update(document_id, &content).await?,
+            msg => msg,
+        };
+
+        // Observers only receive broadcasts - reject anything that mutates state
+        if self.observer
+            && matches!(
+                msg,
+                ClientMessage::CreateDocument { .. }
+                    | ClientMessage::UpdateDocument { .. }
+                    | ClientMessage::DeleteDocument { .. }
+                    | ClientMessage::Transaction { .. }
+                    | ClientMessage::TransferOwnership { .. }
+                    | ClientMessage::ShareDocument { .. }
+                    | ClientMessage::AcquireLock { .. }
+                    | ClientMessage::UploadBlob { .. }
+                    | ClientMessage::DeleteBlob { .. }
+            )
+        {
+            return self
+                .send_error(
+                    ErrorCode::Unauthorized,
+                    "Observer connections are read-only",
+                )
+                .await;
+        }
+
         match msg {
-            ClientMessage::CreateDocument { document } => {
+            ClientMessage::CreateDocument { mut document, .. } => {
                 tracing::info!(
                     "🔵 Received CreateDocument from user {} for doc {} (sync_revision: {})",
                     user_id,
@@ -119,80 +378,102 @@ impl SyncHandler {
                     }
                 }
 
+                if !self.validate_content(&document.content).await? {
+                    return Ok(());
+                }
+
+                // Timestamps come from the server's clock, not the client's. Postgres
+                // stores microseconds, so round to match what later reads return.
+                let now = chrono::Utc::now().round_subsecs(6);
+                document.created_at = now;
+                document.updated_at = now;
+
                 // Check if document already exists (conflict detection)
                 match self.db.get_document(&document.id).await {
                     Ok(existing_doc) => {
+                        // Overwriting it is an edit like any other
+                        if self.is_locked_by_other(&document.id, user_id) {
+                            self.send_error(
+                                ErrorCode::Locked,
+                                &format!("Document {} is locked by another client", document.id),
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+
                         // Document exists! This is a conflict - handle it
+                        // Apply last-write-wins strategy (client version replaces server version entirely)
+                        // Note: This is NOT a merge - server version is completely overwritten
                         tracing::warn!(
-                            "🔥 CONFLICT DETECTED: Document {} already exists on server",
+                            server_revision = existing_doc.sync_revision,
+                            client_revision = document.sync_revision,
+                            "Create for existing document {}, client version replaces it",
                             document.id
                         );
-                        tracing::warn!(
-                            "   Server sync_revision: {} | Client sync_revision: {}",
-                            existing_doc.sync_revision,
-                            document.sync_revision
-                        );
-                        tracing::warn!("   Server content: {:?}", existing_doc.content);
-                        tracing::warn!("   Client content: {:?}", document.content);
-                        // Apply last-write-wins strategy (client version replaces server version entirely)
-                        // Note: This is NOT a merge - server version is completely overwritten
-                        tracing::info!("🔧 Applying last-write-wins: Client version will replace server version");
 
                         // Use single transaction for atomicity - log conflict AND update document
-                        let result =
-                            async {
-                                let mut tx =
-                                    self.db.pool.begin().await.map_err(|e| {
-                                        format!("Failed to begin transaction: {}", e)
-                                    })?;
-
-                                // Log server's version as conflict loser (applied=false)
-                                let server_content_json =
-                                    serde_json::to_value(&existing_doc.content).map_err(|e| {
-                                        format!("Failed to serialize server content: {}", e)
-                                    })?;
-
-                                self.db
-                                    .log_change_event(
-                                        &mut tx,
-                                        crate::database::ChangeEventParams {
-                                            document_id: &document.id,
-                                            user_id: &user_id,
-                                            event_type:
-                                                replicant_core::protocol::ChangeEventType::Create,
-                                            forward_patch: Some(&server_content_json),
-                                            reverse_patch: None,
-                                            applied: false,
-                                        },
-                                    )
-                                    .await
-                                    .map_err(|e| format!("Failed to log conflict: {}", e))?;
-
-                                tracing::info!(
-                                "📝 Logged server version as conflict loser (sync_revision: {})",
-                                existing_doc.sync_revision
-                            );
-
-                                // Update document to client version IN SAME TRANSACTION
-                                self.db
-                                    .update_document_in_tx(&mut tx, &document, None)
-                                    .await
-                                    .map_err(|e| format!("Failed to update document: {}", e))?;
-
-                                // Commit both operations atomically
-                                tx.commit()
-                                    .await
-                                    .map_err(|e| format!("Failed to commit transaction: {}", e))?;
-
-                                Ok::<(), String>(())
-                            }
-                            .await;
+                        let result = async {
+                            let mut tx = self
+                                .db
+                                .pool
+                                .begin()
+                                .await
+                                .map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+                            // Log server's version as conflict loser (applied=false)
+                            let server_content_json = serde_json::to_value(&existing_doc.content)
+                                .map_err(|e| {
+                                format!("Failed to serialize server content: {}", e)
+                            })?;
+
+                            self.db
+                                .log_change_event(
+                                    &mut tx,
+                                    crate::database::ChangeEventParams {
+                                        document_id: &document.id,
+                                        user_id: &user_id,
+                                        event_type: ChangeEventType::Create,
+                                        forward_patch: Some(&server_content_json),
+                                        reverse_patch: None,
+                                        applied: false,
+                                    },
+                                )
+                                .await
+                                .map_err(|e| format!("Failed to log conflict: {}", e))?;
+
+                            // Update document to client version IN SAME TRANSACTION
+                            self.db
+                                .update_document_in_tx(&mut tx, &document, None)
+                                .await
+                                .map_err(|e| format!("Failed to update document: {}", e))?;
+
+                            let sequence = self
+                                .db
+                                .append_op(&mut tx, &user_id, &document.id, ChangeEventType::Update)
+                                .await
+                                .map_err(|e| format!("Failed to append operation: {}", e))?;
+
+                            // Commit all operations atomically
+                            tx.commit()
+                                .await
+                                .map_err(|e| format!("Failed to commit transaction: {}", e))?;
+
+                            Ok::<i64, String>(sequence)
+                        }
+                        .await;
 
                         match result {
-                            Ok(_) => {
-                                tracing::info!(
-                                    "✅ Client version applied (server version overwritten)"
-                                );
+                            Ok(sequence) => {
+                                self.log_conflict(
+                                    &existing_doc,
+                                    &document.content,
+                                    "last_write_wins",
+                                )
+                                .await;
+                                self.record_document_stored(ChangeEventType::Update);
+
+                                // The stored row keeps the original created_at
+                                let stored = self.db.get_document(&document.id).await?;
 
                                 // Send confirmation to the sender
                                 self.tx
@@ -200,26 +481,36 @@ impl SyncHandler {
                                         document_id: document.id,
                                         success: true,
                                         error: None,
+                                        sync_revision: Some(stored.sync_revision),
+                                        sequence: Some(sequence),
+                                        created_at: Some(stored.created_at),
+                                        updated_at: Some(stored.updated_at),
                                     })
                                     .await?;
 
-                                // Broadcast the client's version to ALL clients for consistency
-                                tracing::info!("📡 Broadcasting client's version to all clients");
-                                self.broadcast_to_user(
+                                // Broadcast the client's version to the other clients; the
+                                // sender learns the new revision from the response
+                                self.broadcast_to_document(
                                     user_id,
+                                    stored.id,
                                     ServerMessage::SyncDocument {
-                                        document: document.clone(),
+                                        document: stored,
+                                        relayed: true,
                                     },
                                 )
                                 .await?;
                             }
                             Err(e) => {
-                                tracing::error!("❌ Failed to apply conflict resolution: {}", e);
+                                tracing::error!("Failed to apply conflict resolution: {}", e);
                                 self.tx
                                     .send(ServerMessage::DocumentCreatedResponse {
                                         document_id: document.id,
                                         success: false,
                                         error: Some(e),
+                                        sync_revision: None,
+                                        sequence: None,
+                                        created_at: None,
+                                        updated_at: None,
                                     })
                                     .await?;
                             }
@@ -229,14 +520,32 @@ impl SyncHandler {
                         // Document doesn't exist - this is a true create operation
                         tracing::info!("📝 Creating new document {} ", document.id);
 
-                        match self.db.create_document(&document).await {
-                            Ok(_) => {
+                        let result = async {
+                            let mut tx = self.db.pool.begin().await?;
+                            self.db.create_document_in_tx(&mut tx, &document).await?;
+                            let sequence = self
+                                .db
+                                .append_op(&mut tx, &user_id, &document.id, ChangeEventType::Create)
+                                .await?;
+                            tx.commit().await?;
+                            Ok::<i64, SyncError>(sequence)
+                        }
+                        .await;
+
+                        match result {
+                            Ok(sequence) => {
+                                self.record_document_stored(ChangeEventType::Create);
+
                                 // Send confirmation to the sender
                                 self.tx
                                     .send(ServerMessage::DocumentCreatedResponse {
                                         document_id: document.id,
                                         success: true,
                                         error: None,
+                                        sync_revision: Some(document.sync_revision),
+                                        sequence: Some(sequence),
+                                        created_at: Some(document.created_at),
+                                        updated_at: Some(document.updated_at),
                                     })
                                     .await?;
 
@@ -259,11 +568,18 @@ impl SyncHandler {
 
                                     // Document was created by a concurrent/retry request
                                     // Return success since the document exists (which is what the client wanted)
+                                    let stored = self.db.get_document(&document.id).await.ok();
                                     self.tx
                                         .send(ServerMessage::DocumentCreatedResponse {
                                             document_id: document.id,
                                             success: true,
                                             error: None,
+                                            sync_revision: stored
+                                                .as_ref()
+                                                .map(|doc| doc.sync_revision),
+                                            sequence: None,
+                                            created_at: stored.as_ref().map(|doc| doc.created_at),
+                                            updated_at: stored.as_ref().map(|doc| doc.updated_at),
                                         })
                                         .await?;
 
@@ -281,6 +597,10 @@ impl SyncHandler {
                                             document_id: document.id,
                                             success: false,
                                             error: Some(e.to_string()),
+                                            sync_revision: None,
+                                            sequence: None,
+                                            created_at: None,
+                                            updated_at: None,
                                         })
                                         .await?;
                                 }
@@ -290,19 +610,25 @@ impl SyncHandler {
                 }
             }
 
-            ClientMessage::UpdateDocument { patch } => {
-                tracing::info!(
-                    "🔵 Received UpdateDocument from client {} for doc {}",
-                    self.client_id.unwrap_or_default(),
-                    patch.document_id
-                );
-                tracing::info!("   Patch content: {:?}", patch.patch);
+            ClientMessage::UpdateDocument { mut patch, .. } => {
+                tracing::debug!(operations = patch.patch.0.len(), "Received UpdateDocument");
+
+                // Bound the work a patch can cause before touching it
+                if let Err(e) = self.app_state.limits.patch.check(&patch.patch) {
+                    tracing::warn!("Rejecting oversized patch for {}: {}", patch.document_id, e);
+                    self.send_error(ErrorCode::ValidationFailed, &e.to_string())
+                        .await?;
+                    return Ok(());
+                }
 
                 // Get current document
                 let mut doc = self.db.get_document(&patch.document_id).await?;
 
-                // Validate ownership
-                if doc.user_id != user_id {
+                // Validate ownership, or a write share when sharing is enabled
+                if doc.user_id != user_id
+                    && self.share_permission(&doc.id, user_id).await?
+                        != Some(SharePermission::Write)
+                {
                     self.send_error(
                         ErrorCode::InvalidAuth,
                         "Cannot update another user's document",
@@ -311,27 +637,129 @@ impl SyncHandler {
                     return Ok(());
                 }
 
+                if self.is_locked_by_other(&doc.id, user_id) {
+                    self.send_error(
+                        ErrorCode::Locked,
+                        &format!("Document {} is locked by another client", doc.id),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
                 // Note: Simple last-write-wins - server applies client patches
                 // Conflict detection happens via optimistic locking (version comparison)
-                tracing::info!("📝 UPDATE for document {}", doc.id);
-                tracing::info!(
-                    "   Version: {} | Content before: {:?}",
-                    doc.sync_revision,
-                    doc.content
-                );
-                tracing::info!("   Patch: {:?}", patch.patch);
+                tracing::debug!(sync_revision = doc.sync_revision, "Applying update");
 
                 // CRITICAL: Verify content hash BEFORE applying patch
                 // This prevents corrupted data from being written to database
                 let calculated_hash = calculate_checksum(&doc.content);
-                if calculated_hash != patch.content_hash {
-                    self.send_error(ErrorCode::InvalidPatch, "Content hash mismatch")
+                let conflicted = calculated_hash != patch.content_hash;
+                if conflicted {
+                    // Another client updated first. The document's own strategy
+                    // decides what happens next, or else the sender's.
+                    let conflict_resolution = DocumentConflictStrategy::from_content(&doc.content)
+                        .map(ConflictResolution::from)
+                        .or_else(|| self.conflict_resolution.clone());
+
+                    // Writes that win are applied over the current content when
+                    // they still apply; a field merge keeps whatever parts do
+                    let overwrite = match &conflict_resolution {
+                        Some(
+                            strategy @ (ConflictResolution::ClientWins
+                            | ConflictResolution::LastWriteWins),
+                        ) if apply_patch(&mut doc.content.clone(), &patch.patch).is_ok() => {
+                            Some(strategy.clone())
+                        }
+                        Some(ConflictResolution::FieldMerge) if !has_test_guards(&patch.patch) => {
+                            let merged = applicable_operations(&doc.content, &patch.patch);
+                            if merged.0.is_empty() {
+                                None
+                            } else {
+                                patch.patch = merged;
+                                Some(ConflictResolution::FieldMerge)
+                            }
+                        }
+                        _ => None,
+                    };
+
+                    let Some(strategy) = overwrite else {
+                        // Hand back the current state so the client can re-derive its
+                        // patch against it and resubmit, or drop it if the server wins
+                        tracing::warn!(
+                            "Content hash mismatch for document {} - rejecting update",
+                            doc.id
+                        );
+                        let resolution = match &conflict_resolution {
+                            Some(ConflictResolution::ServerWins) => "server_wins",
+                            _ => "rejected",
+                        };
+                        self.log_conflict(&doc, &serde_json::to_value(&patch.patch)?, resolution)
+                            .await;
+                        if let Some(ConflictResolution::ServerWins) = conflict_resolution {
+                            self.tx
+                                .send(ServerMessage::ConflictDetected {
+                                    document_id: doc.id,
+                                    resolution_strategy: ConflictResolution::ServerWins,
+                                })
+                                .await?;
+                        }
+                        self.tx
+                            .send(ServerMessage::UpdateRejected {
+                                document_id: doc.id,
+                                server_document: doc,
+                            })
+                            .await?;
+                        return Ok(());
+                    };
+
+                    tracing::warn!(
+                        "Content hash mismatch for document {} - applying update ({:?})",
+                        doc.id,
+                        strategy
+                    );
+                    if let Some(ref monitoring) = self.monitoring {
+                        monitoring.log_conflict_detected(&doc.id.to_string()).await;
+                    }
+                    let resolution = match strategy {
+                        ConflictResolution::ClientWins => "client_wins",
+                        ConflictResolution::FieldMerge => "field_merge",
+                        _ => "last_write_wins",
+                    };
+                    self.log_conflict(&doc, &serde_json::to_value(&patch.patch)?, resolution)
+                        .await;
+                    self.tx
+                        .send(ServerMessage::ConflictDetected {
+                            document_id: doc.id,
+                            resolution_strategy: strategy,
+                        })
+                        .await?;
+                }
+
+                // Apply the client's patch. It applies whole or not at all, so a
+                // failed test guard leaves the content untouched to hand back.
+                if let Err(e) = apply_patch(&mut doc.content, &patch.patch) {
+                    if !has_test_guards(&patch.patch) {
+                        return Err(e);
+                    }
+                    tracing::warn!(
+                        "Test guard failed for document {} - rejecting update: {}",
+                        doc.id,
+                        e
+                    );
+                    self.log_conflict(&doc, &serde_json::to_value(&patch.patch)?, "rejected")
+                        .await;
+                    self.tx
+                        .send(ServerMessage::UpdateRejected {
+                            document_id: doc.id,
+                            server_document: doc,
+                        })
                         .await?;
                     return Ok(());
                 }
 
-                // Apply the client's patch
-                apply_patch(&mut doc.content, &patch.patch)?;
+                if !self.validate_content(&doc.content).await? {
+                    return Ok(());
+                }
 
                 // Update metadata (version will be incremented atomically by database)
                 doc.content_hash = Some(calculate_checksum(&doc.content));
@@ -339,20 +767,45 @@ impl SyncHandler {
 
                 // Log patch applied if monitoring is enabled
                 if let Some(ref monitoring) = self.monitoring {
-                    let patch_json = serde_json::to_value(&patch.patch).unwrap_or_default();
                     monitoring
-                        .log_patch_applied(&doc.id.to_string(), &patch_json)
+                        .log_patch_applied(&doc.id.to_string(), &patch.patch)
                         .await;
                 }
 
-                // Save to database with atomic version increment
-                match self.db.update_document(&doc, Some(&patch.patch)).await {
-                    Ok(_) => {
+                // Save to database with atomic version increment, failing if another
+                // update committed after we verified the content hash
+                let base_revision = doc.sync_revision;
+                let result = async {
+                    let mut tx = self.db.pool.begin().await?;
+                    self.db
+                        .update_document_from_base_in_tx(
+                            &mut tx,
+                            &doc,
+                            Some(&patch.patch),
+                            base_revision,
+                        )
+                        .await?;
+                    // Updates are logged for the owner, whoever makes them
+                    let sequence = self
+                        .db
+                        .append_op(&mut tx, &doc.user_id, &doc.id, ChangeEventType::Update)
+                        .await?;
+                    tx.commit().await?;
+                    Ok::<i64, SyncError>(sequence)
+                }
+                .await;
+
+                match result {
+                    Ok(sequence) => {
+                        self.record_document_stored(ChangeEventType::Update);
+
                         // CRITICAL: Fetch the updated document with incremented version from database
                         let updated_doc = self.db.get_document(&doc.id).await?;
 
-                        tracing::info!("   Content after update: {:?}", updated_doc.content);
-                        tracing::info!("   Version after update: {}", updated_doc.sync_revision);
+                        tracing::debug!(
+                            sync_revision = updated_doc.sync_revision,
+                            "Applied update"
+                        );
 
                         // Send confirmation to the sender
                         self.tx
@@ -361,17 +814,32 @@ impl SyncHandler {
                                 success: true,
                                 error: None,
                                 sync_revision: Some(updated_doc.sync_revision),
+                                // The sequence is in the owner's log
+                                sequence: (updated_doc.user_id == user_id).then_some(sequence),
+                                updated_at: Some(updated_doc.updated_at),
                             })
                             .await?;
 
+                        // Applied over changes the sender hasn't seen, so its copy
+                        // no longer matches what was stored
+                        if conflicted {
+                            self.tx
+                                .send(ServerMessage::SyncDocument {
+                                    document: updated_doc.clone(),
+                                    relayed: false,
+                                })
+                                .await?;
+                        }
+
                         // Broadcast the UPDATED document (with incremented version) to ALL OTHER clients
                         tracing::info!("Broadcasting updated document state for doc {} (sync_revision: {}) to other clients of user {}",
-                                      updated_doc.id, updated_doc.sync_revision, user_id);
-                        self.broadcast_to_user_except(
-                            user_id,
-                            self.client_id,
+                                      updated_doc.id, updated_doc.sync_revision, updated_doc.user_id);
+                        self.broadcast_to_document(
+                            updated_doc.user_id,
+                            updated_doc.id,
                             ServerMessage::SyncDocument {
                                 document: updated_doc,
+                                relayed: true,
                             },
                         )
                         .await?;
@@ -385,29 +853,37 @@ impl SyncHandler {
                             );
 
                             // Fetch the current server state
-                            if let Ok(current_doc) = self.db.get_document(&patch.document_id).await
-                            {
-                                tracing::info!("Sending current server state (sync_revision: {}) back to client with conflict",
-                                              current_doc.sync_revision);
+                            let current_doc = self.db.get_document(&patch.document_id).await?;
+                            tracing::info!(
+                                "Rejecting update with current server state (sync_revision: {})",
+                                current_doc.sync_revision
+                            );
+                            self.log_conflict(
+                                &current_doc,
+                                &serde_json::to_value(&patch.patch)?,
+                                "rejected",
+                            )
+                            .await;
 
-                                // Send to the client that had the conflict
-                                self.tx
-                                    .send(ServerMessage::SyncDocument {
-                                        document: current_doc.clone(),
-                                    })
-                                    .await?;
+                            // Also broadcast to all other clients to ensure convergence
+                            self.broadcast_to_document(
+                                current_doc.user_id,
+                                current_doc.id,
+                                ServerMessage::SyncDocument {
+                                    document: current_doc.clone(),
+                                    relayed: false,
+                                },
+                            )
+                            .await?;
 
-                                // Also broadcast to all other clients to ensure convergence
-                                tracing::info!("Broadcasting current state to all other clients for convergence");
-                                self.broadcast_to_user_except(
-                                    user_id,
-                                    self.client_id,
-                                    ServerMessage::SyncDocument {
-                                        document: current_doc,
-                                    },
-                                )
+                            // Send to the client that had the conflict so it can rebase
+                            self.tx
+                                .send(ServerMessage::UpdateRejected {
+                                    document_id: patch.document_id,
+                                    server_document: current_doc,
+                                })
                                 .await?;
-                            }
+                            return Ok(());
                         }
 
                         // Send error response to the sender
@@ -417,14 +893,36 @@ impl SyncHandler {
                                 success: false,
                                 error: Some(e.to_string()),
                                 sync_revision: None,
+                                sequence: None,
+                                updated_at: None,
                             })
                             .await?;
                     }
                 }
             }
 
-            ClientMessage::DeleteDocument { document_id } => {
-                let doc = self.db.get_document(&document_id).await?;
+            ClientMessage::MergePatchDocument { .. } | ClientMessage::ResolveConflict { .. } => {
+                unreachable!("converted to UpdateDocument above")
+            }
+
+            ClientMessage::DeleteDocument { document_id, .. } => {
+                let doc = match self.db.get_document(&document_id).await {
+                    Ok(doc) => doc,
+                    // A create whose confirmation was lost may never have
+                    // arrived; either way the document is gone
+                    Err(SyncError::DatabaseError(sqlx::Error::RowNotFound)) => {
+                        self.tx
+                            .send(ServerMessage::DocumentDeletedResponse {
+                                document_id,
+                                success: true,
+                                error: None,
+                                sequence: None,
+                            })
+                            .await?;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
 
                 if doc.user_id != user_id {
                     self.send_error(
@@ -435,22 +933,48 @@ impl SyncHandler {
                     return Ok(());
                 }
 
+                if self.is_locked_by_other(&document_id, user_id) {
+                    self.send_error(
+                        ErrorCode::Locked,
+                        &format!("Document {} is locked by another client", document_id),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
                 // Soft delete
-                match self.db.delete_document(&document_id, &user_id).await {
-                    Ok(_) => {
+                let result = async {
+                    let mut tx = self.db.pool.begin().await?;
+                    self.db
+                        .delete_document_in_tx(&mut tx, &document_id, &user_id)
+                        .await?;
+                    let sequence = self
+                        .db
+                        .append_op(&mut tx, &user_id, &document_id, ChangeEventType::Delete)
+                        .await?;
+                    tx.commit().await?;
+                    Ok::<i64, SyncError>(sequence)
+                }
+                .await;
+
+                match result {
+                    Ok(sequence) => {
+                        self.record_document_stored(ChangeEventType::Delete);
+
                         // Send confirmation to the sender
                         self.tx
                             .send(ServerMessage::DocumentDeletedResponse {
                                 document_id,
                                 success: true,
                                 error: None,
+                                sequence: Some(sequence),
                             })
                             .await?;
 
                         // Broadcast deletion to all OTHER connected clients
-                        self.broadcast_to_user_except(
+                        self.broadcast_to_document(
                             user_id,
-                            self.client_id,
+                            document_id,
                             ServerMessage::DocumentDeleted { document_id },
                         )
                         .await?;
@@ -462,19 +986,33 @@ impl SyncHandler {
                                 document_id,
                                 success: false,
                                 error: Some(e.to_string()),
+                                sequence: None,
                             })
                             .await?;
                     }
                 }
             }
 
+            ClientMessage::Transaction {
+                transaction_id,
+                ops,
+            } => {
+                self.apply_transaction(user_id, transaction_id, &ops)
+                    .await?;
+            }
+
             ClientMessage::RequestSync { document_ids } => {
                 let count = document_ids.len();
                 for doc_id in document_ids {
                     if let Ok(doc) = self.db.get_document(&doc_id).await {
-                        if doc.user_id == user_id {
+                        if doc.user_id == user_id
+                            || self.share_permission(&doc.id, user_id).await?.is_some()
+                        {
                             self.tx
-                                .send(ServerMessage::SyncDocument { document: doc })
+                                .send(ServerMessage::SyncDocument {
+                                    document: doc,
+                                    relayed: false,
+                                })
                                 .await?;
                         }
                     }
@@ -487,70 +1025,897 @@ impl SyncHandler {
                     .await?;
             }
 
+            ClientMessage::RequestDocument { document_id } => {
+                let document = match self.db.get_document(&document_id).await {
+                    Ok(doc)
+                        if doc.user_id == user_id
+                            || self.share_permission(&doc.id, user_id).await?.is_some() =>
+                    {
+                        Some(doc)
+                    }
+                    _ => None,
+                };
+                match document {
+                    Some(document) => {
+                        self.tx
+                            .send(ServerMessage::SyncDocument {
+                                document,
+                                relayed: false,
+                            })
+                            .await?;
+                    }
+                    None => {
+                        self.send_error(
+                            ErrorCode::DocumentNotFound,
+                            &format!("Document {} not found", document_id),
+                        )
+                        .await?;
+                    }
+                }
+            }
+
             ClientMessage::RequestFullSync => {
                 tracing::debug!("Received RequestFullSync from user {}", user_id);
-                let documents = self.db.get_user_documents(&user_id).await?;
-                tracing::debug!("Found {} documents for user {}", documents.len(), user_id);
-
-                for doc in &documents {
-                    tracing::debug!("Sending SyncDocument for doc {}", doc.id);
-                    tracing::info!(
-                        "📤 SENDING SyncDocument: {} | Title: {} | Version: {}",
-                        doc.id,
-                        doc.content
-                            .get("title")
-                            .and_then(|v| v.as_str())
-                            .unwrap_or("N/A"),
-                        doc.sync_revision
-                    );
-                    self.tx
-                        .send(ServerMessage::SyncDocument {
-                            document: doc.clone(),
-                        })
+                // Documents are sent as they are read, so memory stays
+                // bounded however many the user has
+                let mut synced_count = self
+                    .send_sync_documents(self.db.stream_user_documents(&user_id))
+                    .await?;
+                if self.app_state.sharing_enabled {
+                    synced_count += self
+                        .send_sync_documents(self.db.stream_shared_documents(&user_id))
                         .await?;
                 }
+                tracing::debug!("Sent {} documents to user {}", synced_count, user_id);
 
                 self.tx
-                    .send(ServerMessage::SyncComplete {
-                        synced_count: documents.len(),
-                    })
+                    .send(ServerMessage::SyncComplete { synced_count })
                     .await?;
             }
 
-            ClientMessage::Ping => {
-                self.tx.send(ServerMessage::Pong).await?;
-            }
-
-            ClientMessage::Authenticate { .. } => {
-                // Authentication is handled in the websocket handler
-                self.send_error(
-                    ErrorCode::InvalidAuth,
-                    "Authentication should be handled before this point",
-                )
-                .await?;
-            }
-
-            ClientMessage::GetChangesSince { .. } => {
-                // TODO: Implement sequence-based sync
-                self.send_error(
-                    ErrorCode::ServerError,
-                    "Sequence-based sync not yet implemented",
-                )
-                .await?;
+            ClientMessage::QueryByTag { tag } => {
+                let documents = self
+                    .db
+                    .get_documents_with_tag(&user_id, &tag, self.app_state.sharing_enabled)
+                    .await?;
+                self.tx
+                    .send(ServerMessage::TagQueryResult { tag, documents })
+                    .await?;
             }
 
-            ClientMessage::AckChanges { .. } => {
-                // TODO: Implement change acknowledgment
-                self.send_error(
-                    ErrorCode::ServerError,
-                    "Change acknowledgment not yet implemented",
-                )
-                .await?;
-            }
-        }
+            ClientMessage::TransferOwnership {
+                document_id,
+                new_user_id,
+            } => {
+                let owned = matches!(
+                    self.db.get_document(&document_id).await,
+                    Ok(doc) if doc.user_id == user_id && doc.deleted_at.is_none()
+                );
+                if !owned {
+                    self.send_error(
+                        ErrorCode::InvalidAuth,
+                        "Cannot transfer a document you do not own",
+                    )
+                    .await?;
+                    return Ok(());
+                }
 
-        Ok(())
-    }
+                if new_user_id == user_id {
+                    self.send_error(
+                        ErrorCode::ValidationFailed,
+                        "Document is already owned by this user",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                if !self.db.user_exists(&new_user_id).await? {
+                    self.send_error(
+                        ErrorCode::ValidationFailed,
+                        &format!("Target user {} does not exist", new_user_id),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                if self.is_locked_by_other(&document_id, user_id) {
+                    self.send_error(
+                        ErrorCode::Locked,
+                        &format!("Document {} is locked by another client", document_id),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                let result = async {
+                    let mut tx = self.db.pool.begin().await?;
+                    let Some(doc) = self
+                        .db
+                        .transfer_document_in_tx(&mut tx, &document_id, &user_id, &new_user_id)
+                        .await?
+                    else {
+                        return Ok(None);
+                    };
+                    self.db
+                        .append_op(&mut tx, &user_id, &document_id, ChangeEventType::Delete)
+                        .await?;
+                    self.db
+                        .append_op(&mut tx, &new_user_id, &document_id, ChangeEventType::Create)
+                        .await?;
+                    tx.commit().await?;
+                    Ok::<_, SyncError>(Some(doc))
+                }
+                .await?;
+
+                let Some(doc) = result else {
+                    // Deleted or transferred by another client since we checked
+                    self.send_error(
+                        ErrorCode::InvalidAuth,
+                        "Cannot transfer a document you do not own",
+                    )
+                    .await?;
+                    return Ok(());
+                };
+
+                tracing::info!(
+                    "Transferred document {} from user {} to user {}",
+                    document_id,
+                    user_id,
+                    new_user_id
+                );
+
+                // The caller's own lock doesn't carry over to the new owner
+                self.app_state.document_locks.remove(&document_id);
+
+                self.broadcast_to_user(user_id, ServerMessage::DocumentDeleted { document_id })
+                    .await?;
+                // Users it's shared with keep access under the new owner
+                if self.app_state.sharing_enabled {
+                    for shared_with in self.db.get_document_share_users(&document_id).await? {
+                        self.broadcast_to_user(
+                            shared_with,
+                            ServerMessage::SyncDocument {
+                                document: doc.clone(),
+                                relayed: true,
+                            },
+                        )
+                        .await?;
+                    }
+                }
+                self.broadcast_to_user(
+                    new_user_id,
+                    ServerMessage::DocumentCreated { document: doc },
+                )
+                .await?;
+            }
+
+            ClientMessage::ShareDocument {
+                document_id,
+                user_id: share_with,
+                permission,
+            } => {
+                if !self.app_state.sharing_enabled {
+                    self.send_error(
+                        ErrorCode::ValidationFailed,
+                        "Document sharing is not enabled on this server",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                let doc = match self.db.get_document(&document_id).await {
+                    Ok(doc) if doc.user_id == user_id && doc.deleted_at.is_none() => doc,
+                    _ => {
+                        self.send_error(
+                            ErrorCode::InvalidAuth,
+                            "Cannot share a document you do not own",
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+                };
+
+                if share_with == user_id {
+                    self.send_error(
+                        ErrorCode::ValidationFailed,
+                        "Cannot share a document with its owner",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                if !self.db.user_exists(&share_with).await? {
+                    self.send_error(
+                        ErrorCode::ValidationFailed,
+                        &format!("Target user {} does not exist", share_with),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                self.db
+                    .share_document(&document_id, &share_with, permission)
+                    .await?;
+                tracing::info!(
+                    "Shared document {} with user {} ({})",
+                    document_id,
+                    share_with,
+                    permission
+                );
+
+                self.broadcast_to_user(
+                    user_id,
+                    ServerMessage::DocumentShared {
+                        document_id,
+                        user_id: share_with,
+                        permission,
+                    },
+                )
+                .await?;
+                self.broadcast_to_user(
+                    share_with,
+                    ServerMessage::DocumentCreated { document: doc },
+                )
+                .await?;
+            }
+
+            ClientMessage::AcquireLock { document_id } => {
+                let client_id = self.client_id.unwrap_or_default();
+
+                // Only the user's own documents can be locked
+                let owned = matches!(
+                    self.db.get_document(&document_id).await,
+                    Ok(doc) if doc.user_id == user_id
+                );
+                if !owned {
+                    self.tx
+                        .send(ServerMessage::LockResponse {
+                            document_id,
+                            acquired: false,
+                            holder: None,
+                        })
+                        .await?;
+                    return Ok(());
+                }
+
+                let (holder, newly_acquired) =
+                    match self.app_state.document_locks.entry(document_id) {
+                        Entry::Occupied(entry) => (*entry.get(), false),
+                        Entry::Vacant(entry) => {
+                            entry.insert((user_id, client_id));
+                            ((user_id, client_id), true)
+                        }
+                    };
+
+                self.tx
+                    .send(ServerMessage::LockResponse {
+                        document_id,
+                        acquired: holder == (user_id, client_id),
+                        holder: Some(holder.1),
+                    })
+                    .await?;
+
+                if newly_acquired {
+                    tracing::info!("🔒 Client {} locked document {}", client_id, document_id);
+                    self.broadcast_to_user_except(
+                        user_id,
+                        self.client_id,
+                        ServerMessage::LockChanged {
+                            document_id,
+                            holder: Some(client_id),
+                        },
+                    )
+                    .await?;
+                }
+            }
+
+            ClientMessage::ReleaseLock { document_id } => {
+                let client_id = self.client_id.unwrap_or_default();
+
+                let released = self
+                    .app_state
+                    .document_locks
+                    .remove_if(&document_id, |_, holder| *holder == (user_id, client_id))
+                    .is_some();
+
+                if released {
+                    tracing::info!("🔓 Client {} unlocked document {}", client_id, document_id);
+                    self.broadcast_to_user(
+                        user_id,
+                        ServerMessage::LockChanged {
+                            document_id,
+                            holder: None,
+                        },
+                    )
+                    .await?;
+                } else if self.app_state.document_locks.contains_key(&document_id) {
+                    self.send_error(
+                        ErrorCode::Locked,
+                        &format!("Document {} is locked by another client", document_id),
+                    )
+                    .await?;
+                }
+            }
+
+            ClientMessage::RequestPresence => {
+                let client_id = self.client_id.unwrap_or_default();
+                self.app_state
+                    .presence_subscribers
+                    .insert((user_id, client_id));
+
+                self.tx
+                    .send(ServerMessage::PresenceUpdate {
+                        client_ids: self.app_state.active_clients(user_id),
+                    })
+                    .await?;
+            }
+
+            ClientMessage::Subscribe { document_ids } => {
+                if !document_ids.is_empty() {
+                    let client_id = self.client_id.unwrap_or_default();
+                    self.app_state
+                        .document_subscriptions
+                        .entry((user_id, client_id))
+                        .or_default()
+                        .extend(document_ids);
+                }
+            }
+
+            ClientMessage::Unsubscribe { document_ids } => {
+                let client_id = self.client_id.unwrap_or_default();
+                if let Entry::Occupied(mut subscribed) = self
+                    .app_state
+                    .document_subscriptions
+                    .entry((user_id, client_id))
+                {
+                    for document_id in &document_ids {
+                        subscribed.get_mut().remove(document_id);
+                    }
+                    // Back to hearing about everything
+                    if subscribed.get().is_empty() {
+                        subscribed.remove();
+                    }
+                }
+            }
+
+            ClientMessage::UploadBlob {
+                blob_id,
+                document_id,
+                name,
+                total_size,
+                offset,
+                data,
+            } => {
+                let writable = matches!(
+                    self.accessible_document(&document_id, user_id).await?,
+                    Some((_, SharePermission::Write))
+                );
+                if !writable {
+                    self.send_error(
+                        ErrorCode::InvalidAuth,
+                        "Cannot attach a blob to another user's document",
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                let max_bytes = self.app_state.limits.max_blob_bytes;
+                if total_size > max_bytes {
+                    self.send_error(
+                        ErrorCode::ValidationFailed,
+                        &format!(
+                            "Blob of {} bytes exceeds the {} byte limit",
+                            total_size, max_bytes
+                        ),
+                    )
+                    .await?;
+                    return Ok(());
+                }
+
+                // A chunk that doesn't start where the stored data ends is
+                // dropped; the ack tells the client where to resume
+                let stored = self
+                    .db
+                    .append_blob_chunk(BlobChunkParams {
+                        blob_id: &blob_id,
+                        document_id: &document_id,
+                        user_id: &user_id,
+                        name: &name,
+                        total_size: total_size as i64,
+                        offset: offset as i64,
+                        data: &data,
+                    })
+                    .await?;
+                match stored {
+                    Some((received, total_size)) => {
+                        self.tx
+                            .send(ServerMessage::BlobUploadAck {
+                                blob_id,
+                                received: received as u64,
+                                complete: received == total_size,
+                            })
+                            .await?;
+                    }
+                    None => {
+                        self.send_error(
+                            ErrorCode::ValidationFailed,
+                            &format!("Blob {} belongs to another document", blob_id),
+                        )
+                        .await?;
+                    }
+                }
+            }
+
+            ClientMessage::RequestBlob { blob_id } => {
+                let blob = match self.db.get_blob(&blob_id).await? {
+                    Some(blob)
+                        if blob.is_complete()
+                            && self
+                                .accessible_document(&blob.document_id, user_id)
+                                .await?
+                                .is_some() =>
+                    {
+                        blob
+                    }
+                    _ => {
+                        self.tx.send(ServerMessage::BlobDeleted { blob_id }).await?;
+                        return Ok(());
+                    }
+                };
+
+                // An empty blob still gets a chunk, carrying its name and size
+                let chunks: Vec<&[u8]> = if blob.data.is_empty() {
+                    vec![&[]]
+                } else {
+                    blob.data.chunks(BLOB_CHUNK_BYTES).collect()
+                };
+                let mut offset = 0;
+                for chunk in chunks {
+                    self.tx
+                        .send(ServerMessage::BlobChunk {
+                            blob_id,
+                            document_id: blob.document_id,
+                            name: blob.name.clone(),
+                            total_size: blob.total_size as u64,
+                            offset,
+                            data: chunk.to_vec(),
+                        })
+                        .await?;
+                    offset += chunk.len() as u64;
+                }
+            }
+
+            ClientMessage::DeleteBlob { blob_id } => {
+                if let Some(blob) = self.db.get_blob(&blob_id).await? {
+                    match self.accessible_document(&blob.document_id, user_id).await? {
+                        Some((doc, SharePermission::Write)) => {
+                            self.db.delete_blob(&blob_id).await?;
+                            tracing::info!("Deleted blob {} of document {}", blob_id, doc.id);
+                            self.broadcast_to_document(
+                                doc.user_id,
+                                doc.id,
+                                ServerMessage::BlobDeleted { blob_id },
+                            )
+                            .await?;
+                        }
+                        _ => {
+                            self.send_error(
+                                ErrorCode::InvalidAuth,
+                                "Cannot delete a blob of another user's document",
+                            )
+                            .await?;
+                            return Ok(());
+                        }
+                    }
+                }
+                self.tx.send(ServerMessage::BlobDeleted { blob_id }).await?;
+            }
+
+            ClientMessage::Ping => {
+                self.tx.send(ServerMessage::Pong).await?;
+            }
+
+            // Unpacked by handle_message, so this is a batch inside a batch
+            ClientMessage::OperationBatch { .. } => {
+                self.send_error(ErrorCode::InvalidMessage, "Batches can't be nested")
+                    .await?;
+            }
+
+            ClientMessage::Authenticate { .. } => {
+                // Authentication is handled in the websocket handler
+                self.send_error(
+                    ErrorCode::InvalidAuth,
+                    "Authentication should be handled before this point",
+                )
+                .await?;
+            }
+
+            ClientMessage::GetChangesSince { .. } => {
+                // TODO: Implement sequence-based sync
+                self.send_error(
+                    ErrorCode::ServerError,
+                    "Sequence-based sync not yet implemented",
+                )
+                .await?;
+            }
+
+            ClientMessage::AckChanges { .. } => {
+                // TODO: Implement change acknowledgment
+                self.send_error(
+                    ErrorCode::ServerError,
+                    "Change acknowledgment not yet implemented",
+                )
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply every operation of a transaction in one database transaction, or
+    /// none of them. The sender gets a single TransactionResponse; the other
+    /// clients see the changes as if they had been made one by one.
+    async fn apply_transaction(
+        &self,
+        user_id: Uuid,
+        transaction_id: Uuid,
+        ops: &[TransactionOp],
+    ) -> SyncResult<()> {
+        tracing::info!(
+            "Received transaction {} with {} operations from user {}",
+            transaction_id,
+            ops.len(),
+            user_id
+        );
+
+        let checked = match self.write_transaction(user_id, ops).await {
+            Ok(checked) => checked,
+            Err(e) => {
+                tracing::warn!("Rejecting transaction {}: {}", transaction_id, e);
+                self.tx
+                    .send(ServerMessage::TransactionResponse {
+                        transaction_id,
+                        success: false,
+                        error: Some(e.to_string()),
+                        documents: Vec::new(),
+                        deleted_ids: Vec::new(),
+                    })
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        // Stored state of created and updated documents, in operation order
+        let mut documents = Vec::new();
+        let mut created_ids = HashSet::new();
+        let mut deleted_ids = Vec::new();
+        for op in checked {
+            match op {
+                CheckedOp::Create(document) => {
+                    self.record_document_stored(ChangeEventType::Create);
+                    created_ids.insert(document.id);
+                    documents.push(self.db.get_document(&document.id).await?);
+                }
+                CheckedOp::Update(document, _) => {
+                    self.record_document_stored(ChangeEventType::Update);
+                    documents.push(self.db.get_document(&document.id).await?);
+                }
+                CheckedOp::Delete(document_id) => {
+                    self.record_document_stored(ChangeEventType::Delete);
+                    deleted_ids.push(document_id);
+                }
+            }
+        }
+
+        self.tx
+            .send(ServerMessage::TransactionResponse {
+                transaction_id,
+                success: true,
+                error: None,
+                documents: documents.clone(),
+                deleted_ids: deleted_ids.clone(),
+            })
+            .await?;
+
+        for document in documents {
+            if created_ids.contains(&document.id) {
+                self.broadcast_to_user_except(
+                    user_id,
+                    self.client_id,
+                    ServerMessage::DocumentCreated { document },
+                )
+                .await?;
+            } else {
+                self.broadcast_to_document(
+                    document.user_id,
+                    document.id,
+                    ServerMessage::SyncDocument {
+                        document,
+                        relayed: true,
+                    },
+                )
+                .await?;
+            }
+        }
+        for document_id in deleted_ids {
+            self.broadcast_to_document(
+                user_id,
+                document_id,
+                ServerMessage::DocumentDeleted { document_id },
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Check every operation of a transaction, then write them all in one
+    /// database transaction. Nothing is written if any check or write fails.
+    async fn write_transaction(
+        &self,
+        user_id: Uuid,
+        ops: &[TransactionOp],
+    ) -> SyncResult<Vec<CheckedOp>> {
+        let mut seen = HashSet::new();
+        let mut checked = Vec::with_capacity(ops.len());
+        for op in ops {
+            let document_id = op.document_id();
+            if !seen.insert(document_id) {
+                return Err(SyncError::InvalidOperation(format!(
+                    "Document {} appears more than once in the transaction",
+                    document_id
+                )));
+            }
+            checked.push(self.check_transaction_op(user_id, op).await?);
+        }
+
+        let mut tx = self.db.pool.begin().await?;
+        for op in &checked {
+            match op {
+                CheckedOp::Create(document) => {
+                    self.db.create_document_in_tx(&mut tx, document).await?;
+                    self.db
+                        .append_op(&mut tx, &user_id, &document.id, ChangeEventType::Create)
+                        .await?;
+                }
+                CheckedOp::Update(document, patch) => {
+                    self.db
+                        .update_document_from_base_in_tx(
+                            &mut tx,
+                            document,
+                            Some(patch),
+                            document.sync_revision,
+                        )
+                        .await?;
+                    self.db
+                        .append_op(
+                            &mut tx,
+                            &document.user_id,
+                            &document.id,
+                            ChangeEventType::Update,
+                        )
+                        .await?;
+                }
+                CheckedOp::Delete(document_id) => {
+                    self.db
+                        .delete_document_in_tx(&mut tx, document_id, &user_id)
+                        .await?;
+                    self.db
+                        .append_op(&mut tx, &user_id, document_id, ChangeEventType::Delete)
+                        .await?;
+                }
+            }
+        }
+        tx.commit().await?;
+
+        Ok(checked)
+    }
+
+    /// The checks the single-document handlers make, as errors instead of
+    /// responses
+    async fn check_transaction_op(
+        &self,
+        user_id: Uuid,
+        op: &TransactionOp,
+    ) -> SyncResult<CheckedOp> {
+        match op {
+            TransactionOp::Create { document } => {
+                if document.user_id != user_id {
+                    return Err(SyncError::InvalidOperation(
+                        "Cannot create document for another user".to_string(),
+                    ));
+                }
+                if document.sync_revision != 1 {
+                    return Err(SyncError::InvalidOperation(format!(
+                        "New documents must have version=1, got version={}",
+                        document.sync_revision
+                    )));
+                }
+                if let Some(ref hash) = document.content_hash {
+                    if calculate_checksum(&document.content) != *hash {
+                        return Err(SyncError::InvalidOperation(format!(
+                            "Content hash mismatch for new document {}",
+                            document.id
+                        )));
+                    }
+                }
+                if let Some(problem) = self.content_problem(&document.content)? {
+                    return Err(SyncError::Validation(problem));
+                }
+                if self.is_locked_by_other(&document.id, user_id) {
+                    return Err(SyncError::InvalidOperation(format!(
+                        "Document {} is locked by another client",
+                        document.id
+                    )));
+                }
+
+                let mut document = document.clone();
+                let now = chrono::Utc::now().round_subsecs(6);
+                document.created_at = now;
+                document.updated_at = now;
+                Ok(CheckedOp::Create(document))
+            }
+            TransactionOp::Update { patch } => {
+                let mut doc = self.db.get_document(&patch.document_id).await?;
+                if doc.user_id != user_id
+                    && self.share_permission(&doc.id, user_id).await?
+                        != Some(SharePermission::Write)
+                {
+                    return Err(SyncError::InvalidOperation(
+                        "Cannot update another user's document".to_string(),
+                    ));
+                }
+                if self.is_locked_by_other(&doc.id, user_id) {
+                    return Err(SyncError::InvalidOperation(format!(
+                        "Document {} is locked by another client",
+                        doc.id
+                    )));
+                }
+                if calculate_checksum(&doc.content) != patch.content_hash {
+                    return Err(SyncError::ConflictDetected(doc.id));
+                }
+                apply_patch_with_limits(
+                    &mut doc.content,
+                    &patch.patch,
+                    &self.app_state.limits.patch,
+                )?;
+                if let Some(problem) = self.content_problem(&doc.content)? {
+                    return Err(SyncError::Validation(problem));
+                }
+                doc.content_hash = Some(calculate_checksum(&doc.content));
+                Ok(CheckedOp::Update(doc, patch.patch.clone()))
+            }
+            TransactionOp::Delete { document_id } => {
+                let doc = self.db.get_document(document_id).await?;
+                if doc.user_id != user_id {
+                    return Err(SyncError::InvalidOperation(
+                        "Cannot delete another user's document".to_string(),
+                    ));
+                }
+                if self.is_locked_by_other(document_id, user_id) {
+                    return Err(SyncError::InvalidOperation(format!(
+                        "Document {} is locked by another client",
+                        document_id
+                    )));
+                }
+                Ok(CheckedOp::Delete(*document_id))
+            }
+        }
+    }
+
+    /// Translate an RFC 7386 merge patch into the equivalent JSON Patch against
+    /// the stored content. The content hash still guards against the content
+    /// changing underneath, since the update is rejected if it doesn't match.
+    async fn merge_patch_to_update(
+        &self,
+        document_id: Uuid,
+        merge_patch: &serde_json::Value,
+        content_hash: String,
+    ) -> SyncResult<ClientMessage> {
+        let doc = self.db.get_document(&document_id).await?;
+        let mut merged = doc.content.clone();
+        apply_merge_patch(&mut merged, merge_patch);
+        Ok(ClientMessage::UpdateDocument {
+            patch: DocumentPatch {
+                document_id,
+                patch: create_patch(&doc.content, &merged)?,
+                content_hash,
+            },
+            idempotency_key: None,
+        })
+    }
+
+    /// Translate a conflict resolution into a JSON Patch from the stored content,
+    /// based on that content so it applies whatever the client last saw
+    async fn resolution_to_update(
+        &self,
+        document_id: Uuid,
+        content: &serde_json::Value,
+    ) -> SyncResult<ClientMessage> {
+        let doc = self.db.get_document(&document_id).await?;
+        Ok(ClientMessage::UpdateDocument {
+            patch: DocumentPatch {
+                document_id,
+                patch: create_patch(&doc.content, content)?,
+                content_hash: calculate_checksum(&doc.content),
+            },
+            idempotency_key: None,
+        })
+    }
+
+    /// The access a document has been shared with `user_id`, always None
+    /// while sharing is disabled
+    async fn share_permission(
+        &self,
+        document_id: &Uuid,
+        user_id: Uuid,
+    ) -> SyncResult<Option<SharePermission>> {
+        if !self.app_state.sharing_enabled {
+            return Ok(None);
+        }
+        self.db.get_share_permission(document_id, &user_id).await
+    }
+
+    /// A live document `user_id` can see, with what they may do to it. The
+    /// owner gets write access.
+    async fn accessible_document(
+        &self,
+        document_id: &Uuid,
+        user_id: Uuid,
+    ) -> SyncResult<Option<(Document, SharePermission)>> {
+        let doc = match self.db.get_document(document_id).await {
+            Ok(doc) if doc.deleted_at.is_none() => doc,
+            _ => return Ok(None),
+        };
+        let permission = if doc.user_id == user_id {
+            Some(SharePermission::Write)
+        } else {
+            self.share_permission(&doc.id, user_id).await?
+        };
+        Ok(permission.map(|permission| (doc, permission)))
+    }
+
+    /// Whether an advisory lock on the document is held by a different client
+    fn is_locked_by_other(&self, document_id: &Uuid, user_id: Uuid) -> bool {
+        let client_id = self.client_id.unwrap_or_default();
+        self.app_state
+            .document_locks
+            .get(document_id)
+            .is_some_and(|holder| *holder != (user_id, client_id))
+    }
+
+    /// Check content against the configured schemas, reporting a
+    /// ValidationFailed error to the sender when it doesn't match
+    async fn validate_content(&self, content: &serde_json::Value) -> SyncResult<bool> {
+        match self.content_problem(content)? {
+            Some(problem) => {
+                self.send_error(ErrorCode::ValidationFailed, &problem)
+                    .await?;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+
+    /// Why content is too large or doesn't match the configured schemas
+    fn content_problem(&self, content: &serde_json::Value) -> SyncResult<Option<String>> {
+        let size = serde_json::to_string(content)?.len();
+        let max_bytes = self.app_state.limits.max_document_bytes;
+        if size > max_bytes {
+            tracing::warn!("Rejecting {} byte document content", size);
+            return Ok(Some(format!(
+                "Document content of {} bytes exceeds the {} byte limit",
+                size, max_bytes
+            )));
+        }
+
+        if let Some(schemas) = &self.app_state.schemas {
+            if let Err(detail) = schemas.validate(content) {
+                tracing::warn!("Rejecting invalid content: {}", detail);
+                return Ok(Some(detail));
+            }
+        }
+        Ok(None)
+    }
 
     async fn send_error(&self, code: ErrorCode, message: &str) -> SyncResult<()> {
         self.tx
@@ -562,6 +1927,30 @@ impl SyncHandler {
         Ok(())
     }
 
+    /// Send to every other client that can see a document: the owner's and
+    /// those of users it's shared with. The sending client is skipped.
+    async fn broadcast_to_document(
+        &self,
+        owner_id: Uuid,
+        document_id: Uuid,
+        message: ServerMessage,
+    ) -> SyncResult<()> {
+        let mut user_ids = vec![owner_id];
+        if self.app_state.sharing_enabled {
+            user_ids.extend(self.db.get_document_share_users(&document_id).await?);
+        }
+        for user_id in user_ids {
+            let exclude = if Some(user_id) == self.user_id {
+                self.client_id
+            } else {
+                None
+            };
+            self.broadcast_to_user_except(user_id, exclude, message.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn broadcast_to_user(&self, user_id: Uuid, message: ServerMessage) -> SyncResult<()> {
         self.broadcast_to_user_except(user_id, None, message).await
     }
@@ -572,8 +1961,14 @@ impl SyncHandler {
         exclude_client_id: Option<Uuid>,
         message: ServerMessage,
     ) -> SyncResult<()> {
-        // Get all connected client IDs for this user
-        if let Some(client_ids) = self.app_state.user_clients.get(&user_id) {
+        // Get all connected client IDs for this user. Copied out so no map
+        // guard is held while a slow client is waited on below.
+        let client_ids: Option<Vec<Uuid>> = self
+            .app_state
+            .user_clients
+            .get(&user_id)
+            .map(|clients| clients.iter().copied().collect());
+        if let Some(client_ids) = client_ids {
             let total_clients = client_ids.len();
             let excluded = if exclude_client_id.is_some() { 1 } else { 0 };
             tracing::info!(
@@ -587,6 +1982,13 @@ impl SyncHandler {
             let mut successful_sends = 0;
             let mut skipped = 0;
 
+            // Updates only go to connections subscribed to the document
+            let document_id = match &message {
+                ServerMessage::SyncDocument { document, .. } => Some(document.id),
+                ServerMessage::DocumentUpdated { patch } => Some(patch.document_id),
+                _ => None,
+            };
+
             // Send message to all clients for this user except the excluded one
             for client_id in client_ids.iter() {
                 // Skip if this is the client to exclude
@@ -602,9 +2004,28 @@ impl SyncHandler {
                     }
                 }
 
-                if let Some(client_tx) = self.app_state.clients.get(&(user_id, *client_id)) {
-                    if client_tx.send(message.clone()).await.is_err() {
-                        // Client disconnected, mark for removal
+                if let Some(document_id) = document_id {
+                    if !self
+                        .app_state
+                        .is_subscribed(user_id, *client_id, document_id)
+                    {
+                        skipped += 1;
+                        continue;
+                    }
+                }
+
+                let client_tx = self
+                    .app_state
+                    .clients
+                    .get(&(user_id, *client_id))
+                    .map(|tx| tx.clone());
+                if let Some(client_tx) = client_tx {
+                    if !self
+                        .app_state
+                        .deliver(user_id, *client_id, &client_tx, message.clone())
+                        .await
+                    {
+                        // Client disconnected or fell behind, mark for removal
                         dead_clients.push(*client_id);
                         tracing::warn!(
                             "Failed to send to client {} for user {}",
@@ -640,11 +2061,16 @@ impl SyncHandler {
 
             // Remove dead clients
             if !dead_clients.is_empty() {
-                drop(client_ids); // Release the read lock
                 if let Some(mut client_ids_mut) = self.app_state.user_clients.get_mut(&user_id) {
                     for dead_client_id in &dead_clients {
                         client_ids_mut.remove(dead_client_id);
                         self.app_state.clients.remove(&(user_id, *dead_client_id));
+                        self.app_state
+                            .presence_subscribers
+                            .remove(&(user_id, *dead_client_id));
+                        self.app_state
+                            .document_subscriptions
+                            .remove(&(user_id, *dead_client_id));
                     }
 
                     // Remove user entry if no clients left
@@ -653,9 +2079,26 @@ impl SyncHandler {
                         self.app_state.user_clients.remove(&user_id);
                     }
                 }
+
+                // Don't leave the dead clients showing as present
+                self.app_state.broadcast_presence(user_id).await;
             }
         }
 
         Ok(())
     }
 }
+
+/// The error a client gets for a message that couldn't be processed. An
+/// unreachable database is reported as `Unavailable` so the client can retry.
+pub(crate) fn failed_message(error: &SyncError) -> ServerMessage {
+    let code = if is_database_unavailable(error) {
+        ErrorCode::Unavailable
+    } else {
+        ErrorCode::ServerError
+    };
+    ServerMessage::Error {
+        code,
+        message: format!("Failed to process message: {}", error),
+    }
+}
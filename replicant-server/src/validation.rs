@@ -0,0 +1,86 @@
+use jsonschema::Validator;
+use replicant_core::{errors::ServerError, SyncResult};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Schema used for documents whose content has no `collection` field
+pub const DEFAULT_COLLECTION: &str = "default";
+
+/// Optional JSON Schemas that document content must satisfy before it is stored.
+///
+/// A document belongs to the collection named by its content's `collection`
+/// field, or [`DEFAULT_COLLECTION`] when absent. Collections without a schema
+/// are not validated.
+#[derive(Default)]
+pub struct ContentSchemas {
+    schemas: HashMap<String, Validator>,
+}
+
+impl ContentSchemas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every `<collection>.json` file in a directory
+    pub fn from_dir(dir: impl AsRef<Path>) -> SyncResult<Self> {
+        let mut schemas = Self::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(collection) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let schema: Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+            schemas.add(collection, &schema)?;
+        }
+        Ok(schemas)
+    }
+
+    /// Compile and register the schema for a collection
+    pub fn add(&mut self, collection: &str, schema: &Value) -> SyncResult<()> {
+        let validator = jsonschema::validator_for(schema).map_err(|e| {
+            ServerError::InvalidSchema(format!("collection '{}': {}", collection, e))
+        })?;
+        self.schemas.insert(collection.to_string(), validator);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.schemas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemas.is_empty()
+    }
+
+    /// Validate document content, returning a description of every violation
+    pub fn validate(&self, content: &Value) -> Result<(), String> {
+        let collection = content
+            .get("collection")
+            .and_then(|c| c.as_str())
+            .unwrap_or(DEFAULT_COLLECTION);
+        let Some(validator) = self.schemas.get(collection) else {
+            return Ok(());
+        };
+
+        let errors: Vec<String> = validator
+            .iter_errors(content)
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{}: {}", path, e),
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Content does not match '{}' schema: {}",
+                collection,
+                errors.join("; ")
+            ))
+        }
+    }
+}
@@ -1,9 +1,17 @@
-use crate::{sync_handler::SyncHandler, AppState};
-use axum::extract::ws::{Message, WebSocket};
-use futures_util::{SinkExt, StreamExt};
-use replicant_core::protocol::{ClientMessage, ServerMessage};
+use crate::{
+    auth::{AuthError, Credentials},
+    sync_handler::{self, SyncHandler},
+    AppState, ClientConnection,
+};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use replicant_core::protocol::{
+    ClientMessage, MessageTag, ServerMessage, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
 use std::collections::HashSet;
 use std::sync::Arc;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
@@ -15,12 +23,13 @@ pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
     }
 
     let (mut sender, mut receiver) = socket.split();
-    let (tx, mut rx) = tokio::sync::mpsc::channel::<ServerMessage>(100);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<ServerMessage>(state.client_queue_limit);
 
     // Spawn task to forward messages to WebSocket
     let monitoring_clone = state.monitoring.clone();
     let connection_id_clone = connection_id.clone();
-    tokio::spawn(async move {
+    // Hands the sink back once every sender is dropped so a Close frame can follow queued messages
+    let writer = tokio::spawn(async move {
         tracing::info!(
             "SERVER: WebSocket sender task started for connection {}",
             connection_id_clone
@@ -29,7 +38,7 @@ pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
             // Log outgoing message if monitoring is enabled
             if let Some(ref monitoring) = monitoring_clone {
                 monitoring
-                    .log_message_sent(&connection_id_clone, msg.clone())
+                    .log_message_sent(&connection_id_clone, &msg)
                     .await;
             }
 
@@ -56,6 +65,7 @@ pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
             "SERVER: WebSocket sender task terminated for connection {}",
             connection_id_clone
         );
+        sender
     });
 
     let mut handler = SyncHandler::new(
@@ -66,185 +76,318 @@ pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
     );
     let mut authenticated_user_id = None;
     let mut authenticated_client_id = None;
+    // Whether the client asked for an Ack on receipt of each tagged message
+    let mut receipt_acks = false;
+
+    // Unauthenticated connections are closed once this passes
+    let auth_deadline = tokio::time::Instant::now() + state.auth_timeout;
+    // Signalled when a reconnect with the same client_id replaces this connection
+    let evicted = Arc::new(Notify::new());
+    // Signalled when this connection's outgoing queue overflows
+    let overloaded = Arc::new(Notify::new());
 
     // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
-        if let Ok(Message::Text(text)) = msg {
-            match serde_json::from_str::<ClientMessage>(&text) {
-                Ok(client_msg) => {
-                    // Log incoming message if monitoring is enabled
-                    if let Some(ref monitoring) = state.monitoring {
-                        monitoring
-                            .log_message_received(&connection_id, client_msg.clone())
-                            .await;
-                    }
+    loop {
+        let next = tokio::select! {
+            next = receiver.next() => next,
+            _ = tokio::time::sleep_until(auth_deadline), if authenticated_user_id.is_none() => {
+                tracing::warn!(
+                    "Connection {} did not authenticate within {:?}, closing",
+                    connection_id,
+                    state.auth_timeout
+                );
+                drop(handler);
+                drop(tx);
+                close_connection(writer, close_code::POLICY, "Authentication timeout").await;
+                break;
+            }
+            _ = evicted.notified() => {
+                tracing::info!(
+                    "Connection {} for client {:?} replaced by a newer connection, closing",
+                    connection_id,
+                    authenticated_client_id
+                );
+                drop(handler);
+                drop(tx);
+                close_connection(writer, close_code::NORMAL, "Replaced by a newer connection")
+                    .await;
+                break;
+            }
+            _ = overloaded.notified() => {
+                tracing::warn!(
+                    "Connection {} for client {:?} fell behind on outgoing messages, closing",
+                    connection_id,
+                    authenticated_client_id
+                );
+                // The writer is stuck on the slow socket, so there's no flushing
+                // the queue or sending a Close frame first
+                writer.abort();
+                break;
+            }
+        };
+        let Some(msg) = next else {
+            break;
+        };
 
-                    match client_msg {
-                        ClientMessage::Authenticate {
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Binary(bytes)) => {
+                tracing::warn!(
+                    "Dropping {} byte binary frame from connection {} (client {:?})",
+                    bytes.len(),
+                    connection_id,
+                    authenticated_client_id
+                );
+                let _ = tx
+                    .send(malformed_message("binary frames are not supported"))
+                    .await;
+                continue;
+            }
+            _ => continue,
+        };
+
+        // Reject oversized messages before parsing them
+        let max_bytes = state.limits.max_message_bytes;
+        if text.len() > max_bytes {
+            tracing::warn!(
+                "Rejecting {} byte message from connection {}",
+                text.len(),
+                connection_id
+            );
+            let _ = tx
+                .send(ServerMessage::Error {
+                    code: replicant_core::protocol::ErrorCode::ValidationFailed,
+                    message: format!(
+                        "Message of {} bytes exceeds the {} byte limit",
+                        text.len(),
+                        max_bytes
+                    ),
+                })
+                .await;
+            continue;
+        }
+
+        match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(client_msg) => {
+                // Log incoming message if monitoring is enabled
+                if let Some(ref monitoring) = state.monitoring {
+                    monitoring
+                        .log_message_received(&connection_id, &client_msg)
+                        .await;
+                }
+
+                match client_msg {
+                    ClientMessage::Authenticate {
+                        email,
+                        client_id,
+                        api_key,
+                        signature,
+                        timestamp,
+                        nonce,
+                        token,
+                        observer,
+                        conflict_resolution,
+                        protocol_version,
+                        receipt_acks: wants_acks,
+                    } => {
+                        // Checked before anything else, so a client speaking another
+                        // message format never gets to touch documents
+                        let version = protocol_version.unwrap_or(LEGACY_PROTOCOL_VERSION);
+                        if version != PROTOCOL_VERSION {
+                            tracing::warn!(
+                                "Refusing connection {}: client speaks protocol v{}, server v{}",
+                                connection_id,
+                                version,
+                                PROTOCOL_VERSION
+                            );
+                            let _ = tx
+                                .send(ServerMessage::Error {
+                                    code: replicant_core::protocol::ErrorCode::VersionMismatch,
+                                    message: format!(
+                                        "Protocol version {} is not supported; this server speaks version {}",
+                                        version, PROTOCOL_VERSION
+                                    ),
+                                })
+                                .await;
+                            break;
+                        }
+
+                        let credentials = Credentials {
                             email,
-                            client_id,
                             api_key,
                             signature,
                             timestamp,
-                        } => {
-                            // All HMAC fields required
-                            let (Some(api_key), Some(signature), Some(timestamp)) =
-                                (api_key, signature, timestamp)
-                            else {
-                                let _ = tx
-                                    .send(ServerMessage::AuthError {
-                                        reason: "Missing required authentication fields"
-                                            .to_string(),
-                                    })
-                                    .await;
+                            nonce,
+                            token,
+                        };
+                        let email = match state.authenticator.authenticate(&credentials).await {
+                            Ok(user) => user.email,
+                            Err(AuthError::Rejected(reason)) => {
+                                let _ = tx.send(ServerMessage::AuthFailed { reason }).await;
+                                break;
+                            }
+                            Err(AuthError::Unavailable(reason)) => {
+                                let _ = tx.send(ServerMessage::AuthError { reason }).await;
                                 break;
-                            };
+                            }
+                        };
 
-                            // Verify HMAC signature
-                            let auth_success = match state
-                                .auth
-                                .verify_hmac(&api_key, &signature, timestamp, &email, "")
-                                .await
-                            {
-                                Ok(valid) => valid,
+                        // Get or create user by email
+                        let user_id = match state.db.get_user_by_email(&email).await {
+                            Ok(Some(id)) => id,
+                            Ok(None) => match state.db.create_user(&email).await {
+                                Ok(id) => id,
                                 Err(e) => {
-                                    tracing::error!("HMAC verification database error: {}", e);
+                                    tracing::error!("Failed to create user: {}", e);
                                     let _ = tx
                                         .send(ServerMessage::AuthError {
-                                            reason:
-                                                "Authentication service temporarily unavailable"
-                                                    .to_string(),
+                                            reason: "Failed to create user".to_string(),
                                         })
                                         .await;
                                     break;
                                 }
-                            };
-
-                            if !auth_success {
+                            },
+                            Err(e) => {
+                                tracing::error!("Failed to query user: {}", e);
                                 let _ = tx
                                     .send(ServerMessage::AuthError {
-                                        reason: "Invalid credentials".to_string(),
+                                        reason: "Database error".to_string(),
                                     })
                                     .await;
                                 break;
                             }
+                        };
 
-                            // Get or create user by email
-                            let user_id = match state.db.get_user_by_email(&email).await {
-                                Ok(Some(id)) => id,
-                                Ok(None) => match state.db.create_user(&email).await {
-                                    Ok(id) => id,
-                                    Err(e) => {
-                                        tracing::error!("Failed to create user: {}", e);
-                                        let _ = tx
-                                            .send(ServerMessage::AuthError {
-                                                reason: "Failed to create user".to_string(),
-                                            })
-                                            .await;
-                                        break;
-                                    }
-                                },
-                                Err(e) => {
-                                    tracing::error!("Failed to query user: {}", e);
-                                    let _ = tx
-                                        .send(ServerMessage::AuthError {
-                                            reason: "Database error".to_string(),
-                                        })
-                                        .await;
-                                    break;
-                                }
-                            };
+                        authenticated_user_id = Some(user_id);
+                        authenticated_client_id = Some(client_id);
+                        handler.set_user_id(user_id);
+                        handler.set_client_id(client_id);
+                        handler.set_observer(observer);
+                        handler.set_conflict_resolution(conflict_resolution);
+                        receipt_acks = wants_acks;
 
-                            authenticated_user_id = Some(user_id);
-                            authenticated_client_id = Some(client_id);
-                            handler.set_user_id(user_id);
-                            handler.set_client_id(client_id);
+                        // A half-dead earlier connection with this client_id is closed
+                        // so the user never has two live registrations for one client
+                        let previous = state.connections.insert(
+                            (user_id, client_id),
+                            ClientConnection {
+                                connection_id: connection_id.clone(),
+                                evict: evicted.clone(),
+                                overloaded: overloaded.clone(),
+                            },
+                        );
+                        if let Some(previous) =
+                            previous.filter(|p| p.connection_id != connection_id)
+                        {
+                            tracing::info!(
+                                "Client {} reconnected, evicting connection {}",
+                                client_id,
+                                previous.connection_id
+                            );
+                            previous.evict.notify_one();
+                        }
 
-                            // Register client in the registry with both user_id and client_id
-                            state.clients.insert((user_id, client_id), tx.clone());
+                        // Register client in the registry with both user_id and client_id
+                        state.clients.insert((user_id, client_id), tx.clone());
 
-                            // Update user_clients mapping
-                            state
-                                .user_clients
-                                .entry(user_id)
-                                .and_modify(|clients| {
-                                    clients.insert(client_id);
-                                })
-                                .or_insert_with(|| {
-                                    let mut set = HashSet::new();
-                                    set.insert(client_id);
-                                    set
-                                });
+                        // Update user_clients mapping
+                        state
+                            .user_clients
+                            .entry(user_id)
+                            .and_modify(|clients| {
+                                clients.insert(client_id);
+                            })
+                            .or_insert_with(|| {
+                                let mut set = HashSet::new();
+                                set.insert(client_id);
+                                set
+                            });
 
-                            // Log total client count
-                            let client_count = state
-                                .user_clients
-                                .get(&user_id)
-                                .map(|c| c.len())
-                                .unwrap_or(0);
-                            tracing::info!(
-                                "User {} (email: {}) now has {} total connected clients",
-                                user_id,
-                                email,
-                                client_count
-                            );
+                        // Log total client count
+                        let client_count = state
+                            .user_clients
+                            .get(&user_id)
+                            .map(|c| c.len())
+                            .unwrap_or(0);
+                        tracing::info!(
+                            "User {} (email: {}) now has {} total connected clients{}",
+                            user_id,
+                            email,
+                            client_count,
+                            if observer { " (observer)" } else { "" }
+                        );
 
+                        let _ = tx
+                            .send(ServerMessage::AuthSuccess {
+                                session_id: Uuid::new_v4(),
+                                client_id,
+                            })
+                            .await;
+
+                        state.broadcast_presence(user_id).await;
+                    }
+                    _ => {
+                        // Require authentication first
+                        if authenticated_user_id.is_none() {
                             let _ = tx
-                                .send(ServerMessage::AuthSuccess {
-                                    session_id: Uuid::new_v4(),
-                                    client_id,
+                                .send(ServerMessage::AuthError {
+                                    reason: "Not authenticated".to_string(),
                                 })
                                 .await;
+                            break;
                         }
-                        _ => {
-                            // Require authentication first
-                            if authenticated_user_id.is_none() {
-                                let _ = tx
-                                    .send(ServerMessage::AuthError {
-                                        reason: "Not authenticated".to_string(),
-                                    })
-                                    .await;
-                                break;
+
+                        // Queued ahead of anything handling the message sends
+                        if receipt_acks {
+                            if let Some(message_id) = MessageTag::from_json(&text).message_id {
+                                let _ = tx.send(ServerMessage::Ack { message_id }).await;
                             }
+                        }
 
-                            // Handle other messages
-                            if let Err(e) = handler.handle_message(client_msg).await {
-                                tracing::error!("Error handling message: {}", e);
-                                let _ = tx
-                                    .send(ServerMessage::Error {
-                                        code: replicant_core::protocol::ErrorCode::ServerError,
-                                        message: format!("Failed to process message: {}", e),
-                                    })
+                        // Handle other messages
+                        if let Err(e) = handler.handle_message(client_msg).await {
+                            tracing::error!("Error handling message: {}", e);
+                            let _ = tx.send(sync_handler::failed_message(&e)).await;
+                            if let Some(ref monitoring) = state.monitoring {
+                                monitoring
+                                    .log_error(format!("Error handling message: {}", e))
                                     .await;
-                                if let Some(ref monitoring) = state.monitoring {
-                                    monitoring
-                                        .log_error(format!("Error handling message: {}", e))
-                                        .await;
-                                }
                             }
                         }
                     }
                 }
-                Err(e) => {
-                    tracing::error!("Failed to parse client message: {}", e);
-                    let _ = tx
-                        .send(ServerMessage::Error {
-                            code: replicant_core::protocol::ErrorCode::InvalidMessage,
-                            message: format!("Invalid JSON: {}", e),
-                        })
-                        .await;
-                }
+            }
+            Err(e) => {
+                // Drop the frame but keep the connection open
+                tracing::warn!(
+                    "Dropping malformed message from connection {} (client {:?}): {}",
+                    connection_id,
+                    authenticated_client_id,
+                    e
+                );
+                let _ = tx.send(malformed_message(e)).await;
             }
         }
     }
 
-    // Clean up on disconnect
-    if let (Some(user_id), Some(client_id)) = (authenticated_user_id, authenticated_client_id) {
+    // Clean up on disconnect, unless a newer connection for this client now owns the registration
+    let registration = match (authenticated_user_id, authenticated_client_id) {
+        (Some(user_id), Some(client_id)) => state
+            .connections
+            .remove_if(&(user_id, client_id), |_, c| {
+                c.connection_id == connection_id
+            })
+            .map(|_| (user_id, client_id)),
+        _ => None,
+    };
+    if let Some((user_id, client_id)) = registration {
         tracing::debug!("Client {} disconnecting for user {}", client_id, user_id);
         state.db.remove_active_connection(&user_id).await.ok();
 
         // Remove client from registry
         state.clients.remove(&(user_id, client_id));
+        state.presence_subscribers.remove(&(user_id, client_id));
+        state.document_subscriptions.remove(&(user_id, client_id));
 
         // Update user_clients mapping
         if let Some(mut clients) = state.user_clients.get_mut(&user_id) {
@@ -258,6 +401,38 @@ pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
                 );
             }
         }
+
+        state.broadcast_presence(user_id).await;
+
+        // Auto-release the client's locks and tell the user's other clients
+        let released = state.release_client_locks(user_id, client_id);
+        if !released.is_empty() {
+            let senders: Vec<_> = state
+                .user_clients
+                .get(&user_id)
+                .map(|client_ids| {
+                    client_ids
+                        .iter()
+                        .filter_map(|id| {
+                            state
+                                .clients
+                                .get(&(user_id, *id))
+                                .map(|tx| (*id, tx.clone()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            for document_id in released {
+                tracing::debug!("Released lock on {} held by {}", document_id, client_id);
+                for (other_id, sender) in &senders {
+                    let message = ServerMessage::LockChanged {
+                        document_id,
+                        holder: None,
+                    };
+                    state.deliver(user_id, *other_id, sender, message).await;
+                }
+            }
+        }
     }
 
     // Log disconnection if monitoring is enabled
@@ -265,3 +440,28 @@ pub async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
         monitoring.log_client_disconnected(&connection_id).await;
     }
 }
+
+// Send a Close frame once the writer has flushed everything queued before it.
+// Every other sender for the connection must already be dropped.
+async fn close_connection(
+    writer: JoinHandle<SplitSink<WebSocket, Message>>,
+    code: u16,
+    reason: &'static str,
+) {
+    if let Ok(mut sender) = writer.await {
+        let _ = sender
+            .send(Message::Close(Some(CloseFrame {
+                code,
+                reason: reason.into(),
+            })))
+            .await;
+    }
+}
+
+// Tells the client its frame was dropped rather than processed
+fn malformed_message(detail: impl std::fmt::Display) -> ServerMessage {
+    ServerMessage::Error {
+        code: replicant_core::protocol::ErrorCode::ValidationFailed,
+        message: format!("malformed message: {}", detail),
+    }
+}
@@ -8,7 +8,9 @@
 //!
 //! See: docs/testing_guide.md for conventions
 
-use replicant_server::auth::AuthState;
+use replicant_server::auth::{
+    ApiCredentials, AuthError, AuthState, AuthedUser, Authenticator, Credentials,
+};
 use replicant_server::database::ServerDatabase;
 use std::sync::Arc;
 
@@ -446,3 +448,366 @@ async fn test_inactive_credentials_rejected() {
 
     println!("✅ Inactive credentials test passed");
 }
+
+/// Signed Authenticate credentials for `creds`, as the client builds them
+fn signed_credentials(creds: &ApiCredentials, email: &str, timestamp: i64) -> Credentials {
+    let nonce = uuid::Uuid::new_v4().to_string();
+    Credentials {
+        email: email.to_string(),
+        api_key: Some(creds.api_key.clone()),
+        signature: Some(AuthState::create_hmac_signature(
+            &creds.secret,
+            timestamp,
+            email,
+            &creds.api_key,
+            &nonce,
+        )),
+        timestamp: Some(timestamp),
+        nonce: Some(nonce),
+        token: None,
+    }
+}
+
+/// Tests that a freshly signed Authenticate message is accepted.
+#[tokio::test]
+async fn test_authenticate_with_nonce_succeeds() {
+    let db = match setup_test_db().await {
+        Ok(db) => db,
+        Err(e) => {
+            println!("⏭️ Skipping test: {}", e);
+            return;
+        }
+    };
+
+    let auth = AuthState::new(Arc::new(db));
+    let creds = AuthState::generate_api_credentials();
+    auth.save_credentials(&creds, "test-nonce").await.unwrap();
+
+    let credentials =
+        signed_credentials(&creds, "nonce@example.com", chrono::Utc::now().timestamp());
+    let user = auth.authenticate(&credentials).await.unwrap();
+    assert_eq!(user.email, "nonce@example.com");
+
+    // The nonce is part of the signature
+    let tampered = Credentials {
+        nonce: Some(uuid::Uuid::new_v4().to_string()),
+        ..credentials
+    };
+    assert!(matches!(
+        auth.authenticate(&tampered).await,
+        Err(AuthError::Rejected(_))
+    ));
+}
+
+/// Tests that resending an observed Authenticate message is rejected.
+#[tokio::test]
+async fn test_replayed_authenticate_is_rejected() {
+    let db = match setup_test_db().await {
+        Ok(db) => db,
+        Err(e) => {
+            println!("⏭️ Skipping test: {}", e);
+            return;
+        }
+    };
+
+    let auth = AuthState::new(Arc::new(db));
+    let creds = AuthState::generate_api_credentials();
+    auth.save_credentials(&creds, "test-replay").await.unwrap();
+
+    let credentials =
+        signed_credentials(&creds, "replay@example.com", chrono::Utc::now().timestamp());
+    assert!(auth.authenticate(&credentials).await.is_ok());
+
+    match auth.authenticate(&credentials).await {
+        Err(AuthError::Rejected(reason)) => assert_eq!(reason, "Invalid credentials"),
+        other => panic!("Replayed message should be rejected, got {:?}", other),
+    }
+
+    // Without a nonce there is nothing to stop a replay
+    let no_nonce = Credentials {
+        nonce: None,
+        ..signed_credentials(&creds, "replay@example.com", chrono::Utc::now().timestamp())
+    };
+    assert!(matches!(
+        auth.authenticate(&no_nonce).await,
+        Err(AuthError::Rejected(_))
+    ));
+}
+
+/// Tests that an Authenticate message with a stale timestamp is rejected even
+/// when its nonce has never been seen.
+#[tokio::test]
+async fn test_stale_authenticate_is_rejected() {
+    let db = match setup_test_db().await {
+        Ok(db) => db,
+        Err(e) => {
+            println!("⏭️ Skipping test: {}", e);
+            return;
+        }
+    };
+
+    let auth = AuthState::new(Arc::new(db));
+    let creds = AuthState::generate_api_credentials();
+    auth.save_credentials(&creds, "test-stale").await.unwrap();
+
+    let stale_timestamp = chrono::Utc::now().timestamp() - 600;
+    let credentials = signed_credentials(&creds, "stale@example.com", stale_timestamp);
+    assert!(matches!(
+        auth.authenticate(&credentials).await,
+        Err(AuthError::Rejected(_))
+    ));
+}
+
+/// Accepts any `Authenticate` whose token is "valid:<email>", standing in for
+/// a deployment's own identity provider.
+struct TokenAuthenticator;
+
+#[async_trait::async_trait]
+impl Authenticator for TokenAuthenticator {
+    async fn authenticate(&self, credentials: &Credentials) -> Result<AuthedUser, AuthError> {
+        match credentials
+            .token
+            .as_deref()
+            .and_then(|t| t.strip_prefix("valid:"))
+        {
+            Some(email) => Ok(AuthedUser {
+                email: email.to_string(),
+            }),
+            None => Err(AuthError::Rejected("Unknown token".to_string())),
+        }
+    }
+}
+
+/// Serves `/ws` with [`TokenAuthenticator`] on a free port, returning its URL.
+async fn spawn_token_server(db: Arc<ServerDatabase>) -> String {
+    use axum::extract::{ws::WebSocketUpgrade, State};
+    use replicant_server::{websocket::handle_websocket, AppState};
+
+    let state = Arc::new(AppState {
+        authenticator: Arc::new(TokenAuthenticator),
+        ..AppState::for_tests(db)
+    });
+    let app = axum::Router::new()
+        .route(
+            "/ws",
+            axum::routing::get(
+                |ws: WebSocketUpgrade, State(state): State<Arc<AppState>>| async move {
+                    ws.on_upgrade(move |socket| handle_websocket(socket, state))
+                },
+            ),
+        )
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    url
+}
+
+/// Authenticates over a fresh connection and returns the server's reply.
+async fn authenticate_with(
+    url: &str,
+    token: &str,
+    protocol_version: Option<u32>,
+) -> replicant_core::protocol::ServerMessage {
+    use replicant_core::protocol::{ClientMessage, ServerMessage};
+    use tungstenite::Message;
+
+    let url = url.to_string();
+    let message = ClientMessage::Authenticate {
+        email: "ignored@example.com".to_string(),
+        client_id: uuid::Uuid::new_v4(),
+        api_key: None,
+        signature: None,
+        timestamp: None,
+        nonce: None,
+        token: Some(token.to_string()),
+        protocol_version,
+        receipt_acks: false,
+        observer: false,
+        conflict_resolution: None,
+    };
+    tokio::task::spawn_blocking(move || {
+        let (mut socket, _) = tungstenite::connect(url).unwrap();
+        socket
+            .send(Message::Text(serde_json::to_string(&message).unwrap()))
+            .unwrap();
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str::<ServerMessage>(&text).unwrap();
+            }
+        }
+    })
+    .await
+    .unwrap()
+}
+
+/// Tests that the server authenticates connections with a plugged-in
+/// authenticator instead of API credentials.
+#[tokio::test]
+async fn test_custom_authenticator() {
+    use replicant_core::protocol::{ServerMessage, PROTOCOL_VERSION};
+
+    let db = match setup_test_db().await {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            println!("⏭️ Skipping test: {}", e);
+            return;
+        }
+    };
+    let url = spawn_token_server(db.clone()).await;
+
+    let accepted =
+        authenticate_with(&url, "valid:token-user@example.com", Some(PROTOCOL_VERSION)).await;
+    assert!(matches!(accepted, ServerMessage::AuthSuccess { .. }));
+    // The user comes from the authenticator, not the message's email
+    assert!(db
+        .get_user_by_email("token-user@example.com")
+        .await
+        .unwrap()
+        .is_some());
+
+    let rejected = authenticate_with(&url, "forged", Some(PROTOCOL_VERSION)).await;
+    match rejected {
+        ServerMessage::AuthFailed { reason } => assert_eq!(reason, "Unknown token"),
+        other => panic!("Expected AuthFailed, got {:?}", other),
+    }
+}
+
+/// Tests that clients on another protocol version are refused before they
+/// authenticate, while clients that predate negotiation are still accepted.
+#[tokio::test]
+async fn test_protocol_version_negotiation() {
+    use replicant_core::protocol::{ErrorCode, ServerMessage, PROTOCOL_VERSION};
+
+    let db = match setup_test_db().await {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            println!("⏭️ Skipping test: {}", e);
+            return;
+        }
+    };
+    let url = spawn_token_server(db.clone()).await;
+
+    let matching =
+        authenticate_with(&url, "valid:current@example.com", Some(PROTOCOL_VERSION)).await;
+    assert!(matches!(matching, ServerMessage::AuthSuccess { .. }));
+
+    let legacy = authenticate_with(&url, "valid:legacy@example.com", None).await;
+    assert!(matches!(legacy, ServerMessage::AuthSuccess { .. }));
+
+    let mismatched =
+        authenticate_with(&url, "valid:future@example.com", Some(PROTOCOL_VERSION + 1)).await;
+    match mismatched {
+        ServerMessage::Error { code, message } => {
+            assert!(matches!(code, ErrorCode::VersionMismatch));
+            assert!(message.contains(&(PROTOCOL_VERSION + 1).to_string()));
+        }
+        other => panic!("Expected a VersionMismatch error, got {:?}", other),
+    }
+    // Refused before the authenticator ran
+    assert!(db
+        .get_user_by_email("future@example.com")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// Tests that a connection that asked for receipt acks has each tagged
+/// message acknowledged before the message's own response.
+#[tokio::test]
+async fn test_receipt_ack_precedes_response() {
+    use replicant_core::models::{Document, SyncStatus};
+    use replicant_core::protocol::{ClientMessage, ServerMessage, PROTOCOL_VERSION};
+    use tungstenite::Message;
+
+    let db = match setup_test_db().await {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            println!("⏭️ Skipping test: {}", e);
+            return;
+        }
+    };
+    let url = spawn_token_server(db.clone()).await;
+    let user_id = db.create_user("acks@example.com").await.unwrap();
+
+    let auth = ClientMessage::Authenticate {
+        email: "acks@example.com".to_string(),
+        client_id: uuid::Uuid::new_v4(),
+        api_key: None,
+        signature: None,
+        timestamp: None,
+        nonce: None,
+        token: Some("valid:acks@example.com".to_string()),
+        protocol_version: Some(PROTOCOL_VERSION),
+        receipt_acks: true,
+        observer: false,
+        conflict_resolution: None,
+    };
+    let now = chrono::Utc::now();
+    let document = Document {
+        id: uuid::Uuid::new_v4(),
+        user_id,
+        content: serde_json::json!({"title": "Acknowledged"}),
+        sync_revision: 1,
+        content_hash: None,
+        title: Some("Acknowledged".to_string()),
+        created_at: now,
+        updated_at: now,
+        deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Pending,
+    };
+    let document_id = document.id;
+    let message_id = uuid::Uuid::new_v4();
+    let create = ClientMessage::CreateDocument {
+        document,
+        idempotency_key: None,
+    }
+    .to_json_with_id(message_id)
+    .unwrap();
+
+    let replies = tokio::task::spawn_blocking(move || {
+        let (mut socket, _) = tungstenite::connect(url).unwrap();
+        socket
+            .send(Message::Text(serde_json::to_string(&auth).unwrap()))
+            .unwrap();
+        let mut replies = Vec::new();
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                let reply = serde_json::from_str::<ServerMessage>(&text).unwrap();
+                match reply {
+                    ServerMessage::AuthSuccess { .. } => {
+                        socket.send(Message::Text(create.clone())).unwrap()
+                    }
+                    ServerMessage::DocumentCreatedResponse { .. } => {
+                        replies.push(reply);
+                        return replies;
+                    }
+                    _ => replies.push(reply),
+                }
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    // Collection stops at the response, so the ack is only seen if it came first
+    assert!(
+        replies
+            .iter()
+            .any(|r| matches!(r, ServerMessage::Ack { message_id: id } if *id == message_id)),
+        "create was not acknowledged before its response: {:?}",
+        replies
+    );
+    match replies.last().unwrap() {
+        ServerMessage::DocumentCreatedResponse {
+            document_id: id,
+            success,
+            ..
+        } => {
+            assert_eq!(*id, document_id);
+            assert!(success);
+        }
+        other => panic!("Expected DocumentCreatedResponse, got {:?}", other),
+    }
+}
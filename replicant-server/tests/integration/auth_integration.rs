@@ -265,3 +265,187 @@ crate::integration_test!(
     },
     true
 );
+
+crate::integration_test!(
+    test_revoked_credentials_are_rejected,
+    |ctx: TestContext| async move {
+        use replicant_core::protocol::ServerMessage;
+
+        let email = "revoked@test.local";
+        let (api_key, api_secret) = ctx
+            .generate_test_credentials("test-revoked")
+            .await
+            .expect("Failed to generate credentials");
+
+        let base = ctx.server_url.replace("ws://", "http://");
+        let http = reqwest::Client::new();
+
+        // Admin routes require the admin token
+        let response = http
+            .get(format!("{}/admin/credentials", base))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+        let response = http
+            .get(format!("{}/admin/credentials", base))
+            .bearer_auth("wrong-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        // Listing shows the credential but never its secret
+        let response = http
+            .get(format!("{}/admin/credentials", base))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        let body = response.text().await.unwrap();
+        assert!(!body.contains(&api_secret));
+        let listed: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+        let entry = listed
+            .iter()
+            .find(|c| c["api_key"] == api_key.as_str())
+            .expect("Credential missing from listing");
+        assert_eq!(entry["name"], "test-revoked");
+        assert!(entry["revoked_at"].is_null());
+
+        // The credential works before it is revoked
+        assert!(matches!(
+            ctx.authenticate_with_credentials(email, &api_key, &api_secret)
+                .await,
+            ServerMessage::AuthSuccess { .. }
+        ));
+
+        let response = http
+            .post(format!("{}/admin/credentials/{}/revoke", base, api_key))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+
+        // Unknown keys cannot be revoked
+        let response = http
+            .post(format!("{}/admin/credentials/rpa_unknown/revoke", base))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+
+        // New connections with the revoked key are refused
+        assert!(matches!(
+            ctx.authenticate_with_credentials(email, &api_key, &api_secret)
+                .await,
+            ServerMessage::AuthFailed { .. }
+        ));
+
+        let listed: Vec<serde_json::Value> = http
+            .get(format!("{}/admin/credentials", base))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let entry = listed
+            .iter()
+            .find(|c| c["api_key"] == api_key.as_str())
+            .unwrap();
+        assert!(!entry["revoked_at"].is_null());
+    },
+    true
+);
+
+crate::integration_test!(
+    test_rotated_secret_grace_period,
+    |ctx: TestContext| async move {
+        use replicant_core::protocol::ServerMessage;
+
+        let email = "rotate@test.local";
+        let (api_key, old_secret) = ctx
+            .generate_test_credentials("test-rotate")
+            .await
+            .expect("Failed to generate credentials");
+
+        let base = ctx.server_url.replace("ws://", "http://");
+        let response = reqwest::Client::new()
+            .post(format!(
+                "{}/admin/credentials/{}/rotate?grace_secs=2",
+                base, api_key
+            ))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        let rotated: serde_json::Value = response.json().await.unwrap();
+        let new_secret = rotated["secret"].as_str().unwrap().to_string();
+        assert_ne!(new_secret, old_secret);
+        assert_eq!(rotated["grace_secs"], 2);
+
+        // Both secrets work during the grace period
+        assert!(matches!(
+            ctx.authenticate_with_credentials(email, &api_key, &new_secret)
+                .await,
+            ServerMessage::AuthSuccess { .. }
+        ));
+        assert!(matches!(
+            ctx.authenticate_with_credentials(email, &api_key, &old_secret)
+                .await,
+            ServerMessage::AuthSuccess { .. }
+        ));
+
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+
+        // Only the new secret works once it expires
+        assert!(matches!(
+            ctx.authenticate_with_credentials(email, &api_key, &old_secret)
+                .await,
+            ServerMessage::AuthFailed { .. }
+        ));
+        assert!(matches!(
+            ctx.authenticate_with_credentials(email, &api_key, &new_secret)
+                .await,
+            ServerMessage::AuthSuccess { .. }
+        ));
+    },
+    true
+);
+
+crate::integration_test!(
+    test_unauthenticated_connection_times_out,
+    |ctx: TestContext| async move {
+        use futures_util::StreamExt;
+        use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+        use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+        let (mut ws, _) = connect_async(format!("{}/ws", ctx.server_url))
+            .await
+            .expect("Failed to connect to WebSocket");
+        let started = std::time::Instant::now();
+
+        // Send nothing; the server should close the socket once the window passes
+        let response = tokio::time::timeout(
+            std::time::Duration::from_secs(TEST_AUTH_TIMEOUT_SECS + 5),
+            ws.next(),
+        )
+        .await
+        .expect("Server did not close the unauthenticated connection");
+
+        match response {
+            Some(Ok(Message::Close(Some(frame)))) => {
+                assert_eq!(frame.code, CloseCode::Policy);
+                assert_eq!(frame.reason, "Authentication timeout");
+            }
+            other => panic!("Expected Close frame, got {:?}", other),
+        }
+        assert!(started.elapsed() >= std::time::Duration::from_secs(TEST_AUTH_TIMEOUT_SECS - 1));
+    },
+    true
+);
@@ -304,3 +304,179 @@ crate::integration_test!(
     },
     true
 );
+
+crate::integration_test!(
+    test_racing_updates_same_field_rejected_and_rebased,
+    |ctx: TestContext| async move {
+        use futures_util::{SinkExt, StreamExt};
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::{ClientMessage, ServerMessage};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let email = "erin@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-erin")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws_a = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut ws_b = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn send(
+            ws: &mut (impl SinkExt<Message, Error = tokio_tungstenite::tungstenite::Error> + Unpin),
+            msg: &ClientMessage,
+        ) {
+            ws.send(Message::Text(serde_json::to_string(msg).unwrap()))
+                .await
+                .unwrap();
+        }
+
+        // Read until a message matching the predicate arrives, skipping broadcasts
+        async fn wait_for<S, F>(ws: &mut S, pred: F) -> ServerMessage
+        where
+            S: StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+            F: Fn(&ServerMessage) -> bool,
+        {
+            loop {
+                let next = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = next {
+                    let msg: ServerMessage = serde_json::from_str(&text).unwrap();
+                    if pred(&msg) {
+                        return msg;
+                    }
+                }
+            }
+        }
+
+        let mut doc = TestContext::create_test_document(user_id, "Race");
+        doc.content = json!({"title": "Race", "status": "todo"});
+        let base = doc.content.clone();
+        send(
+            &mut ws_a,
+            &ClientMessage::CreateDocument {
+                document: doc.clone(),
+                idempotency_key: None,
+            },
+        )
+        .await;
+        wait_for(&mut ws_a, |m| {
+            matches!(
+                m,
+                ServerMessage::DocumentCreatedResponse { success: true, .. }
+            )
+        })
+        .await;
+
+        // Both clients edit the same field from the same base
+        let update = |value: &str| ClientMessage::UpdateDocument {
+            patch: DocumentPatch {
+                document_id: doc.id,
+                patch: create_patch(&base, &json!({"title": "Race", "status": value})).unwrap(),
+                content_hash: calculate_checksum(&base),
+            },
+            idempotency_key: None,
+        };
+        send(&mut ws_a, &update("doing")).await;
+        wait_for(&mut ws_a, |m| {
+            matches!(
+                m,
+                ServerMessage::DocumentUpdatedResponse { success: true, .. }
+            )
+        })
+        .await;
+        send(&mut ws_b, &update("done")).await;
+
+        // The loser gets the current server state back instead of a bare error
+        let server_document = match wait_for(&mut ws_b, |m| {
+            matches!(m, ServerMessage::UpdateRejected { .. })
+        })
+        .await
+        {
+            ServerMessage::UpdateRejected {
+                document_id,
+                server_document,
+            } => {
+                assert_eq!(document_id, doc.id);
+                server_document
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(server_document.content["status"], "doing");
+
+        // Re-deriving the patch against the server state succeeds
+        let rebased = ClientMessage::UpdateDocument {
+            patch: DocumentPatch {
+                document_id: doc.id,
+                patch: create_patch(
+                    &server_document.content,
+                    &json!({"title": "Race", "status": "done"}),
+                )
+                .unwrap(),
+                content_hash: calculate_checksum(&server_document.content),
+            },
+            idempotency_key: None,
+        };
+        send(&mut ws_b, &rebased).await;
+        match wait_for(&mut ws_b, |m| {
+            matches!(m, ServerMessage::DocumentUpdatedResponse { .. })
+        })
+        .await
+        {
+            ServerMessage::DocumentUpdatedResponse {
+                success,
+                sync_revision,
+                ..
+            } => {
+                assert!(success);
+                assert_eq!(sync_revision, Some(server_document.sync_revision + 1));
+            }
+            _ => unreachable!(),
+        }
+
+        // Two full clients racing the same field converge on the rebased value
+        let (api_key, api_secret) = ctx
+            .generate_test_credentials("test-erin-clients")
+            .await
+            .expect("Failed to generate credentials");
+        let client1 = ctx
+            .create_test_client(email, user_id, &api_key, &api_secret)
+            .await
+            .expect("Failed to create client");
+        let client2 = ctx
+            .create_test_client(email, user_id, &api_key, &api_secret)
+            .await
+            .expect("Failed to create client");
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+        let (r1, r2) = tokio::join!(
+            client1.update_document(doc.id, json!({"title": "Race", "status": "one"})),
+            client2.update_document(doc.id, json!({"title": "Race", "status": "two"}))
+        );
+        r1.unwrap();
+        r2.unwrap();
+
+        // Both end up with the same winner and nothing left pending
+        assert_eventually(
+            || async {
+                let d1 = client1.get_all_documents().await.unwrap();
+                let d2 = client2.get_all_documents().await.unwrap();
+                d1.len() == 1
+                    && d2.len() == 1
+                    && d1[0].content == d2[0].content
+                    && client1.count_pending_sync().await.unwrap() == 0
+                    && client2.count_pending_sync().await.unwrap() == 0
+            },
+            10,
+        )
+        .await;
+    },
+    true
+);
@@ -367,3 +367,102 @@ crate::integration_test!(
     },
     true
 );
+
+crate::integration_test!(
+    test_rejected_update_is_recorded_in_conflict_log,
+    |ctx: TestContext| async move {
+        use futures_util::{SinkExt, StreamExt};
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::create_patch;
+        use replicant_core::protocol::{ClientMessage, ServerMessage};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let email = "logan@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-logan")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Logged");
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: doc.clone(),
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        assert!(matches!(
+            next_message(&mut ws).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+
+        // An update based on content the server never had is rejected
+        let patch = create_patch(&doc.content, &json!({"title": "Diverged"})).unwrap();
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::UpdateDocument {
+                patch: DocumentPatch {
+                    document_id: doc.id,
+                    patch: patch.clone(),
+                    content_hash: "stale".to_string(),
+                },
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        assert!(matches!(
+            next_message(&mut ws).await,
+            ServerMessage::UpdateRejected { .. }
+        ));
+
+        let base = ctx.server_url.replace("ws://", "http://");
+        let entries: Vec<serde_json::Value> = reqwest::Client::new()
+            .get(format!("{}/admin/conflicts?document_id={}", base, doc.id))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry["document_id"], doc.id.to_string());
+        assert_eq!(
+            entry["client_version"],
+            serde_json::to_value(&patch).unwrap()
+        );
+        assert_eq!(entry["server_version"], doc.content);
+        assert_eq!(entry["resolution"], "rejected");
+        assert!(!entry["resolved_at"].is_null());
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
@@ -11,7 +11,7 @@
 //! - Event log sequence integrity
 
 use super::helpers::TestContext;
-use replicant_core::models::Document;
+use replicant_core::models::{Document, SyncStatus};
 use replicant_server::database::ServerDatabase;
 use serde_json::json;
 use std::sync::Arc;
@@ -53,6 +53,8 @@ async fn test_concurrent_writes_to_same_document() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     };
 
     db.create_document(&doc).await.unwrap();
@@ -75,6 +77,8 @@ async fn test_concurrent_writes_to_same_document() {
                 created_at: chrono::Utc::now(),
                 updated_at: chrono::Utc::now(),
                 deleted_at: None,
+                expires_at: None,
+                sync_status: SyncStatus::Synced,
             };
 
             db_clone.update_document(&updated_doc, None).await
@@ -131,6 +135,8 @@ async fn test_document_update_consistency() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     };
 
     db.create_document(&doc).await.unwrap();
@@ -178,6 +184,8 @@ async fn test_event_log_sequence_integrity() {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         db.create_document(&doc).await.unwrap();
@@ -228,6 +236,8 @@ async fn test_version_vector_comparison_edge_cases() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     };
 
     db.create_document(&doc).await.unwrap();
@@ -269,6 +279,8 @@ async fn test_duplicate_document_id_handling() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     };
 
     db.create_document(&doc1).await.unwrap();
@@ -284,6 +296,8 @@ async fn test_duplicate_document_id_handling() {
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
         deleted_at: None,
+        expires_at: None,
+        sync_status: SyncStatus::Synced,
     };
 
     let result = db.create_document(&doc2).await;
@@ -319,6 +333,8 @@ async fn test_no_orphaned_documents_after_user_deletion() {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         db.create_document(&doc).await.unwrap();
@@ -2,7 +2,7 @@ use anyhow::{Context, Result};
 use hmac::{Hmac, Mac};
 use libc::kill;
 use replicant_client::Client as SyncClient;
-use replicant_core::models::Document;
+use replicant_core::models::{Document, SyncStatus};
 use serde_json::json;
 use sha2::Sha256;
 use std::sync::Arc;
@@ -30,6 +30,14 @@ static CLIENT_CONNECTION_SEMAPHORE: tokio::sync::OnceCell<Arc<Semaphore>> =
 
 // Required for cargo-llvm-cov to cover sync-server artifacts
 const SERVER_BIN: &str = env!("CARGO_BIN_EXE_replicant-server");
+
+// Size limits the test server is started with
+pub const TEST_MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+pub const TEST_MAX_DOCUMENT_BYTES: usize = 256 * 1024;
+// Bearer token for the test server's admin API
+pub const TEST_ADMIN_TOKEN: &str = "test-admin-token";
+// Short so the unauthenticated-connection test doesn't wait the full default
+pub const TEST_AUTH_TIMEOUT_SECS: u64 = 3;
 async fn get_connection_semaphore() -> &'static Arc<Semaphore> {
     CLIENT_CONNECTION_SEMAPHORE
         .get_or_init(|| async {
@@ -258,6 +266,46 @@ impl TestContext {
         &self,
         email: &str,
         _token: &str,
+    ) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
+        self.connect_websocket(email, false, None, Uuid::new_v4())
+            .await
+    }
+
+    /// Connect with a specific client_id, e.g. to simulate a reconnect
+    #[allow(dead_code)]
+    pub async fn create_websocket_with_client_id(
+        &self,
+        email: &str,
+        client_id: Uuid,
+    ) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
+        self.connect_websocket(email, false, None, client_id).await
+    }
+
+    /// Connect a read-only observer that receives broadcasts for the user
+    pub async fn create_observer_websocket(
+        &self,
+        email: &str,
+    ) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
+        self.connect_websocket(email, true, None, Uuid::new_v4())
+            .await
+    }
+
+    /// Connect a client that asks the server to settle its conflicts with `strategy`
+    pub async fn create_websocket_with_conflict_resolution(
+        &self,
+        email: &str,
+        strategy: replicant_core::protocol::ConflictResolution,
+    ) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
+        self.connect_websocket(email, false, Some(strategy), Uuid::new_v4())
+            .await
+    }
+
+    async fn connect_websocket(
+        &self,
+        email: &str,
+        observer: bool,
+        conflict_resolution: Option<replicant_core::protocol::ConflictResolution>,
+        client_id: Uuid,
     ) -> WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>> {
         use futures_util::SinkExt;
         use replicant_core::protocol::ClientMessage;
@@ -271,15 +319,21 @@ impl TestContext {
             .await
             .expect("Failed to connect to WebSocket");
         let now = chrono::Utc::now().timestamp();
-        let signature = create_hmac_signature(&api_secret, now, email, &api_key, "");
+        let nonce = Uuid::new_v4().to_string();
+        let signature = create_hmac_signature(&api_secret, now, email, &api_key, &nonce);
         // Send authenticate message
-        let client_id = Uuid::new_v4();
         let auth_msg = ClientMessage::Authenticate {
             email: email.to_string(),
             client_id,
             api_key: Some(api_key.clone()),
             signature: Some(signature),
             timestamp: Some(now),
+            nonce: Some(nonce),
+            observer,
+            conflict_resolution,
+            token: None,
+            protocol_version: Some(replicant_core::protocol::PROTOCOL_VERSION),
+            receipt_acks: false,
         };
         let json_msg = serde_json::to_string(&auth_msg).unwrap();
         ws.send(Message::Text(json_msg)).await.unwrap();
@@ -293,7 +347,7 @@ impl TestContext {
                 ServerMessage::AuthSuccess { .. } => {
                     // Authentication successful
                 }
-                ServerMessage::AuthError { reason } => {
+                ServerMessage::AuthError { reason } | ServerMessage::AuthFailed { reason } => {
                     panic!("Authentication failed: {}", reason);
                 }
                 _ => panic!("Expected AuthSuccess or AuthError, got {:?}", msg),
@@ -303,6 +357,51 @@ impl TestContext {
         ws
     }
 
+    /// Send an Authenticate message with the given credentials and return the server's reply
+    #[allow(dead_code)]
+    pub async fn authenticate_with_credentials(
+        &self,
+        email: &str,
+        api_key: &str,
+        api_secret: &str,
+    ) -> replicant_core::protocol::ServerMessage {
+        use futures_util::{SinkExt, StreamExt};
+        use replicant_core::protocol::ClientMessage;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let (mut ws, _) = connect_async(&format!("{}/ws", self.server_url))
+            .await
+            .expect("Failed to connect to WebSocket");
+        let now = chrono::Utc::now().timestamp();
+        let nonce = Uuid::new_v4().to_string();
+        let auth_msg = ClientMessage::Authenticate {
+            email: email.to_string(),
+            client_id: Uuid::new_v4(),
+            api_key: Some(api_key.to_string()),
+            signature: Some(create_hmac_signature(
+                api_secret, now, email, api_key, &nonce,
+            )),
+            timestamp: Some(now),
+            nonce: Some(nonce),
+            observer: false,
+            conflict_resolution: None,
+            token: None,
+            protocol_version: Some(replicant_core::protocol::PROTOCOL_VERSION),
+            receipt_acks: false,
+        };
+        ws.send(Message::Text(serde_json::to_string(&auth_msg).unwrap()))
+            .await
+            .unwrap();
+
+        let response = tokio::time::timeout(Duration::from_secs(5), ws.next())
+            .await
+            .expect("Timeout waiting for auth response");
+        match response {
+            Some(Ok(Message::Text(text))) => serde_json::from_str(&text).unwrap(),
+            other => panic!("Expected auth response, got {:?}", other),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn create_test_document(user_id: Uuid, title: &str) -> Document {
         let content = json!({
@@ -320,6 +419,8 @@ impl TestContext {
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         }
     }
 
@@ -564,6 +665,13 @@ impl TestContext {
             .env("DATABASE_URL", &self.db_url)
             .env("BIND_ADDRESS", &bind_address)
             .env("RUST_LOG", "info,sync_client=debug,sync_server=debug")
+            // Small size limits so tests can exceed them cheaply
+            .env("MAX_MESSAGE_BYTES", TEST_MAX_MESSAGE_BYTES.to_string())
+            .env("MAX_DOCUMENT_BYTES", TEST_MAX_DOCUMENT_BYTES.to_string())
+            .env("ADMIN_TOKEN", TEST_ADMIN_TOKEN)
+            .env("AUTH_TIMEOUT_SECS", TEST_AUTH_TIMEOUT_SECS.to_string())
+            .env("DOCUMENT_SHARING", "true")
+            .env("CONFLICT_LOG", "true")
             .stdout(std::process::Stdio::inherit())
             .stderr(std::process::Stdio::inherit())
             .spawn()?;
@@ -253,6 +253,8 @@ crate::integration_test!(
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: replicant_core::models::SyncStatus::Synced,
         };
 
         match client_db.save_document(&offline_doc).await {
@@ -1,8 +1,8 @@
 use crate::integration::helpers::*;
 use chrono::Utc;
 use futures_util::{SinkExt, StreamExt};
-use replicant_core::models::Document;
-use replicant_core::protocol::{ClientMessage, ServerMessage};
+use replicant_core::models::{Document, SyncStatus};
+use replicant_core::protocol::{ClientMessage, ServerMessage, PROTOCOL_VERSION};
 use serde_json::json;
 use tokio_tungstenite::tungstenite::Message;
 use uuid::Uuid;
@@ -76,7 +76,8 @@ crate::integration_test!(
         let ws_url = format!("{}/ws", ctx.server_url);
         let (mut ws, _) = tokio_tungstenite::connect_async(&ws_url).await.unwrap();
         let now = chrono::Utc::now().timestamp();
-        let signature = create_hmac_signature(&api_secret, now, email, &api_key, "");
+        let nonce = Uuid::new_v4().to_string();
+        let signature = create_hmac_signature(&api_secret, now, email, &api_key, &nonce);
         // Send authenticate message
         let client_id = Uuid::new_v4();
         let auth_msg = ClientMessage::Authenticate {
@@ -85,6 +86,12 @@ crate::integration_test!(
             api_key: Some(api_key.clone()),
             signature: Some(signature),
             timestamp: Some(now),
+            nonce: Some(nonce),
+            observer: false,
+            conflict_resolution: None,
+            token: None,
+            protocol_version: Some(PROTOCOL_VERSION),
+            receipt_acks: false,
         };
         let json_msg = serde_json::to_string(&auth_msg).unwrap();
         ws.send(Message::Text(json_msg)).await.unwrap();
@@ -103,7 +110,7 @@ crate::integration_test!(
                 } => {
                     assert!(!session_id.is_nil());
                 }
-                ServerMessage::AuthError { reason } => {
+                ServerMessage::AuthError { reason } | ServerMessage::AuthFailed { reason } => {
                     panic!("Authentication failed: {}", reason);
                 }
                 _ => panic!("Expected AuthSuccess or AuthError, got {:?}", msg),
@@ -113,7 +120,8 @@ crate::integration_test!(
         }
 
         // Test invalid authentication
-        let signature = create_hmac_signature(&email, now, email, &api_key, "");
+        let nonce = Uuid::new_v4().to_string();
+        let signature = create_hmac_signature(&email, now, email, &api_key, &nonce);
         // Send authenticate message
         let client_id = Uuid::new_v4();
         let bad_auth_msg = ClientMessage::Authenticate {
@@ -122,6 +130,12 @@ crate::integration_test!(
             api_key: Some(api_key.clone()),
             signature: Some(signature),
             timestamp: Some(now),
+            nonce: Some(nonce),
+            observer: false,
+            conflict_resolution: None,
+            token: None,
+            protocol_version: Some(PROTOCOL_VERSION),
+            receipt_acks: false,
         };
         ws.send(Message::Text(serde_json::to_string(&bad_auth_msg).unwrap()))
             .await
@@ -133,7 +147,7 @@ crate::integration_test!(
 
         if let Some(Ok(Message::Text(response_text))) = response {
             let msg: ServerMessage = serde_json::from_str(&response_text).unwrap();
-            assert!(matches!(msg, ServerMessage::AuthError { .. }));
+            assert!(matches!(msg, ServerMessage::AuthFailed { .. }));
         } else {
             panic!("Expected auth error response");
         }
@@ -191,10 +205,13 @@ crate::integration_test!(
             created_at: Utc::now(),
             updated_at: Utc::now(),
             deleted_at: None,
+            expires_at: None,
+            sync_status: SyncStatus::Synced,
         };
 
         let create_msg = ClientMessage::CreateDocument {
             document: doc.clone(),
+            idempotency_key: None,
         };
         ws.send(Message::Text(serde_json::to_string(&create_msg).unwrap()))
             .await
@@ -325,3 +342,2741 @@ crate::integration_test!(
     },
     true
 );
+
+crate::integration_test!(
+    test_observer_is_read_only_but_receives_broadcasts,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::ErrorCode;
+
+        let email = "olivia@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-olivia")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut writer = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut observer = ctx.create_observer_websocket(email).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        // Observer may not create documents
+        let rejected = ClientMessage::CreateDocument {
+            document: TestContext::create_test_document(user_id, "Observer Doc"),
+            idempotency_key: None,
+        };
+        observer
+            .send(Message::Text(serde_json::to_string(&rejected).unwrap()))
+            .await
+            .unwrap();
+        match next_message(&mut observer).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::Unauthorized),
+            other => panic!("Expected Unauthorized error, got {:?}", other),
+        }
+
+        // Writer creates a document - observer sees the broadcast
+        let doc = TestContext::create_test_document(user_id, "Live View");
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut writer).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        match next_message(&mut observer).await {
+            ServerMessage::DocumentCreated { document } => assert_eq!(document.id, doc.id),
+            other => panic!("Expected DocumentCreated broadcast, got {:?}", other),
+        }
+
+        // Writer updates it - observer receives the SyncDocument broadcast
+        let mut new_content = doc.content.clone();
+        new_content["text"] = json!("Updated by writer");
+        let patch = DocumentPatch {
+            document_id: doc.id,
+            patch: create_patch(&doc.content, &new_content).unwrap(),
+            content_hash: calculate_checksum(&doc.content),
+        };
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut observer).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.id, doc.id);
+                assert_eq!(document.content, new_content);
+            }
+            other => panic!("Expected SyncDocument broadcast, got {:?}", other),
+        }
+
+        // Observer's own updates are rejected too
+        let patch = DocumentPatch {
+            document_id: doc.id,
+            patch: create_patch(&new_content, &json!({"title": "Hijacked"})).unwrap(),
+            content_hash: calculate_checksum(&new_content),
+        };
+        observer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut observer).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::Unauthorized),
+            other => panic!("Expected Unauthorized error, got {:?}", other),
+        }
+
+        writer.close(None).await.unwrap();
+        observer.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_document_lock_blocks_other_clients_until_disconnect,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::ErrorCode;
+
+        let email = "lena@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-lena")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut holder = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut other = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Form");
+        holder
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut holder).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            next_message(&mut other).await,
+            ServerMessage::DocumentCreated { .. }
+        ));
+
+        // First client takes the lock; the other is told who holds it
+        let acquire = serde_json::to_string(&ClientMessage::AcquireLock {
+            document_id: doc.id,
+        })
+        .unwrap();
+        holder.send(Message::Text(acquire.clone())).await.unwrap();
+        let holder_id = match next_message(&mut holder).await {
+            ServerMessage::LockResponse {
+                acquired: true,
+                holder: Some(holder_id),
+                ..
+            } => holder_id,
+            other => panic!("Expected acquired LockResponse, got {:?}", other),
+        };
+        match next_message(&mut other).await {
+            ServerMessage::LockChanged {
+                document_id,
+                holder,
+            } => {
+                assert_eq!(document_id, doc.id);
+                assert_eq!(holder, Some(holder_id));
+            }
+            other => panic!("Expected LockChanged broadcast, got {:?}", other),
+        }
+
+        // Second client can't take it or write to the document
+        other.send(Message::Text(acquire.clone())).await.unwrap();
+        match next_message(&mut other).await {
+            ServerMessage::LockResponse {
+                acquired, holder, ..
+            } => {
+                assert!(!acquired);
+                assert_eq!(holder, Some(holder_id));
+            }
+            other => panic!("Expected refused LockResponse, got {:?}", other),
+        }
+
+        let patch = DocumentPatch {
+            document_id: doc.id,
+            patch: create_patch(&doc.content, &json!({"title": "Overwritten"})).unwrap(),
+            content_hash: calculate_checksum(&doc.content),
+        };
+        other
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut other).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::Locked),
+            other => panic!("Expected Locked error, got {:?}", other),
+        }
+
+        // Nor overwrite it by creating a document with the same id
+        let mut recreated = doc.clone();
+        recreated.content = json!({"title": "Overwritten"});
+        other
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: recreated,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut other).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::Locked),
+            other => panic!("Expected Locked error, got {:?}", other),
+        }
+
+        // Disconnecting releases the lock
+        holder.close(None).await.unwrap();
+        match next_message(&mut other).await {
+            ServerMessage::LockChanged {
+                document_id,
+                holder: None,
+            } => assert_eq!(document_id, doc.id),
+            other => panic!("Expected lock release broadcast, got {:?}", other),
+        }
+
+        other.send(Message::Text(acquire)).await.unwrap();
+        assert!(matches!(
+            next_message(&mut other).await,
+            ServerMessage::LockResponse { acquired: true, .. }
+        ));
+
+        other.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_merge_patch_document_updates_fields,
+    |ctx: TestContext| async move {
+        use replicant_core::patches::calculate_checksum;
+
+        let email = "mona@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-mona")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Draft");
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: doc.clone(),
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        assert!(matches!(
+            next_message(&mut ws).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+
+        // Change the title and drop the text, leaving the timestamp alone
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::MergePatchDocument {
+                document_id: doc.id,
+                merge_patch: json!({ "title": "Final", "text": null }),
+                content_hash: calculate_checksum(&doc.content),
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::DocumentUpdatedResponse { success, .. } => assert!(success),
+            other => panic!("Expected DocumentUpdatedResponse, got {:?}", other),
+        }
+
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::RequestSync {
+                document_ids: vec![doc.id],
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(
+                    document.content,
+                    json!({ "title": "Final", "timestamp": doc.content["timestamp"] })
+                );
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_conflict_resolution_strategy_per_client,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::ConflictResolution;
+
+        let email = "nadia@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-nadia")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut first = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut client_wins = ctx
+            .create_websocket_with_conflict_resolution(email, ConflictResolution::ClientWins)
+            .await;
+        let mut server_wins = ctx
+            .create_websocket_with_conflict_resolution(email, ConflictResolution::ServerWins)
+            .await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        // Change just the title, based on the document's original content
+        fn update(doc: &Document, title: &str) -> Message {
+            let mut content = doc.content.clone();
+            content["title"] = json!(title);
+            Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch: DocumentPatch {
+                        document_id: doc.id,
+                        patch: create_patch(&doc.content, &content).unwrap(),
+                        content_hash: calculate_checksum(&doc.content),
+                    },
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            )
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Original");
+        first
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut first).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        for ws in [&mut client_wins, &mut server_wins] {
+            assert!(matches!(
+                next_message(ws).await,
+                ServerMessage::DocumentCreated { .. }
+            ));
+        }
+
+        // First client changes the document
+        first.send(update(&doc, "First")).await.unwrap();
+        assert!(matches!(
+            next_message(&mut first).await,
+            ServerMessage::DocumentUpdatedResponse { success: true, .. }
+        ));
+        for ws in [&mut client_wins, &mut server_wins] {
+            assert!(matches!(
+                next_message(ws).await,
+                ServerMessage::SyncDocument { .. }
+            ));
+        }
+
+        // A stale ServerWins update is refused with the server's version
+        server_wins.send(update(&doc, "Ignored")).await.unwrap();
+        assert!(matches!(
+            next_message(&mut server_wins).await,
+            ServerMessage::ConflictDetected {
+                resolution_strategy: ConflictResolution::ServerWins,
+                ..
+            }
+        ));
+        match next_message(&mut server_wins).await {
+            ServerMessage::UpdateRejected {
+                server_document, ..
+            } => assert_eq!(server_document.content["title"], "First"),
+            other => panic!("Expected UpdateRejected, got {:?}", other),
+        }
+
+        // A stale ClientWins update is applied and broadcast to the others
+        client_wins.send(update(&doc, "Second")).await.unwrap();
+        assert!(matches!(
+            next_message(&mut client_wins).await,
+            ServerMessage::ConflictDetected {
+                resolution_strategy: ConflictResolution::ClientWins,
+                ..
+            }
+        ));
+        assert!(matches!(
+            next_message(&mut client_wins).await,
+            ServerMessage::DocumentUpdatedResponse { success: true, .. }
+        ));
+        match next_message(&mut first).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.content["title"], "Second")
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        for mut ws in [first, client_wins, server_wins] {
+            ws.close(None).await.unwrap();
+        }
+    },
+    true
+);
+
+crate::integration_test!(
+    test_conflict_strategy_per_document,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::ConflictResolution;
+
+        let email = "paula@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-paula")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        // Neither connection asks for a strategy of its own
+        let mut first = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut second = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        fn update(doc: &Document, content: &serde_json::Value) -> Message {
+            Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch: DocumentPatch {
+                        document_id: doc.id,
+                        patch: create_patch(&doc.content, content).unwrap(),
+                        content_hash: calculate_checksum(&doc.content),
+                    },
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            )
+        }
+
+        let mut merged = TestContext::create_test_document(user_id, "Merged");
+        merged.content["_conflict_strategy"] = json!("field_merge");
+        merged.content["notes"] = json!("draft");
+        let mut guarded = TestContext::create_test_document(user_id, "Guarded");
+        guarded.content["_conflict_strategy"] = json!("server_wins");
+        for doc in [&merged, &guarded] {
+            first
+                .send(Message::Text(
+                    serde_json::to_string(&ClientMessage::CreateDocument {
+                        document: doc.clone(),
+                        idempotency_key: None,
+                    })
+                    .unwrap(),
+                ))
+                .await
+                .unwrap();
+            assert!(matches!(
+                next_message(&mut first).await,
+                ServerMessage::DocumentCreatedResponse { success: true, .. }
+            ));
+            assert!(matches!(
+                next_message(&mut second).await,
+                ServerMessage::DocumentCreated { .. }
+            ));
+        }
+
+        // The first client renames both and drops the notes of one
+        let mut renamed = merged.content.clone();
+        renamed["title"] = json!("Renamed");
+        renamed.as_object_mut().unwrap().remove("notes");
+        let mut retitled = guarded.content.clone();
+        retitled["title"] = json!("Retitled");
+        for (doc, content) in [(&merged, &renamed), (&guarded, &retitled)] {
+            first.send(update(doc, content)).await.unwrap();
+            assert!(matches!(
+                next_message(&mut first).await,
+                ServerMessage::DocumentUpdatedResponse { success: true, .. }
+            ));
+            assert!(matches!(
+                next_message(&mut second).await,
+                ServerMessage::SyncDocument { .. }
+            ));
+        }
+
+        // The second client, still on the originals, edits the notes and
+        // marks both done. The field merge keeps what still applies.
+        let mut done = merged.content.clone();
+        done["notes"] = json!("final");
+        done["done"] = json!(true);
+        second.send(update(&merged, &done)).await.unwrap();
+        assert!(matches!(
+            next_message(&mut second).await,
+            ServerMessage::ConflictDetected {
+                resolution_strategy: ConflictResolution::FieldMerge,
+                ..
+            }
+        ));
+        assert!(matches!(
+            next_message(&mut second).await,
+            ServerMessage::DocumentUpdatedResponse { success: true, .. }
+        ));
+        // Its copy is brought in line with what was stored
+        match next_message(&mut second).await {
+            ServerMessage::SyncDocument { document, relayed } => {
+                assert_eq!(document.content["title"], "Renamed");
+                assert_eq!(document.content["done"], true);
+                assert!(document.content.get("notes").is_none());
+                assert!(!relayed);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+        // The other client hears of it as the second client's change
+        match next_message(&mut first).await {
+            ServerMessage::SyncDocument { document, relayed } => {
+                assert_eq!(document.content["title"], "Renamed");
+                assert_eq!(document.content["done"], true);
+                assert!(relayed);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        // The server-wins document refuses the same kind of stale edit
+        let mut done = guarded.content.clone();
+        done["done"] = json!(true);
+        second.send(update(&guarded, &done)).await.unwrap();
+        assert!(matches!(
+            next_message(&mut second).await,
+            ServerMessage::ConflictDetected {
+                resolution_strategy: ConflictResolution::ServerWins,
+                ..
+            }
+        ));
+        match next_message(&mut second).await {
+            ServerMessage::UpdateRejected {
+                server_document, ..
+            } => {
+                assert_eq!(server_document.content["title"], "Retitled");
+                assert!(server_document.content.get("done").is_none());
+            }
+            other => panic!("Expected UpdateRejected, got {:?}", other),
+        }
+
+        for mut ws in [first, second] {
+            ws.close(None).await.unwrap();
+        }
+    },
+    true
+);
+
+crate::integration_test!(
+    test_resolve_conflict_replaces_server_content,
+    |ctx: TestContext| async move {
+        let email = "omar@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-omar")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut resolver = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut other = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Conflicted");
+        resolver
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut resolver).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            next_message(&mut other).await,
+            ServerMessage::DocumentCreated { .. }
+        ));
+
+        // No content hash needed - the resolution is authoritative
+        let resolved = json!({ "title": "Resolved" });
+        resolver
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::ResolveConflict {
+                    document_id: doc.id,
+                    content: resolved.clone(),
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut resolver).await {
+            ServerMessage::DocumentUpdatedResponse {
+                success,
+                sync_revision,
+                ..
+            } => {
+                assert!(success);
+                assert_eq!(sync_revision, Some(2));
+            }
+            other => panic!("Expected DocumentUpdatedResponse, got {:?}", other),
+        }
+        match next_message(&mut other).await {
+            ServerMessage::SyncDocument { document, .. } => assert_eq!(document.content, resolved),
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        resolver.close(None).await.unwrap();
+        other.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_operation_sequence_numbers_are_monotonic,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::ChangeEventType;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "oscar@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-oscar")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Ledger");
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: doc.clone(),
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::DocumentCreatedResponse {
+                success, sequence, ..
+            } => {
+                assert!(success);
+                assert_eq!(sequence, Some(1));
+            }
+            other => panic!("Expected DocumentCreatedResponse, got {:?}", other),
+        }
+
+        let mut new_content = doc.content.clone();
+        new_content["text"] = json!("Second entry");
+        let patch = DocumentPatch {
+            document_id: doc.id,
+            patch: create_patch(&doc.content, &new_content).unwrap(),
+            content_hash: calculate_checksum(&doc.content),
+        };
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::UpdateDocument {
+                patch,
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::DocumentUpdatedResponse {
+                success, sequence, ..
+            } => {
+                assert!(success);
+                assert_eq!(sequence, Some(2));
+            }
+            other => panic!("Expected DocumentUpdatedResponse, got {:?}", other),
+        }
+
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::DeleteDocument {
+                document_id: doc.id,
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::DocumentDeletedResponse {
+                success, sequence, ..
+            } => {
+                assert!(success);
+                assert_eq!(sequence, Some(3));
+            }
+            other => panic!("Expected DocumentDeletedResponse, got {:?}", other),
+        }
+
+        // The log can be replayed from any high-water mark
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let ops = db.get_operations_since(&user_id, 1).await.unwrap();
+        assert_eq!(
+            ops.iter().map(|op| op.sequence).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(ops[0].operation, ChangeEventType::Update);
+        assert_eq!(ops[0].sync_revision, 2);
+        assert_eq!(ops[1].operation, ChangeEventType::Delete);
+        assert!(ops.iter().all(|op| op.document_id == doc.id));
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_presence_updates_on_connect_and_disconnect,
+    |ctx: TestContext| async move {
+        let email = "petra@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-petra")
+            .await
+            .expect("Failed to generate credentials");
+        ctx.create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut watcher = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        // Subscribing replies with the current client list
+        watcher
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::RequestPresence).unwrap(),
+            ))
+            .await
+            .unwrap();
+        let watcher_id = match next_message(&mut watcher).await {
+            ServerMessage::PresenceUpdate { client_ids } => {
+                assert_eq!(client_ids.len(), 1);
+                client_ids[0]
+            }
+            other => panic!("Expected PresenceUpdate, got {:?}", other),
+        };
+
+        // A second client connecting is announced to the subscriber
+        let mut visitor = ctx.create_authenticated_websocket(email, &api_key).await;
+        let visitor_id = match next_message(&mut watcher).await {
+            ServerMessage::PresenceUpdate { client_ids } => {
+                assert_eq!(client_ids.len(), 2);
+                assert!(client_ids.contains(&watcher_id));
+                *client_ids.iter().find(|id| **id != watcher_id).unwrap()
+            }
+            other => panic!("Expected PresenceUpdate, got {:?}", other),
+        };
+
+        // Clients that never asked for presence aren't sent it
+        visitor
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::Ping).unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut visitor).await,
+            ServerMessage::Pong
+        ));
+
+        // Disconnecting removes it again
+        visitor.close(None).await.unwrap();
+        match next_message(&mut watcher).await {
+            ServerMessage::PresenceUpdate { client_ids } => {
+                assert_eq!(client_ids, vec![watcher_id]);
+                assert!(!client_ids.contains(&visitor_id));
+            }
+            other => panic!("Expected PresenceUpdate, got {:?}", other),
+        }
+
+        watcher.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_oversized_messages_are_rejected_gracefully,
+    |ctx: TestContext| async move {
+        use replicant_core::protocol::ErrorCode;
+
+        let email = "quinn@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-quinn")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let ping = serde_json::to_string(&ClientMessage::Ping).unwrap();
+
+        // A message over the limit is refused without being processed
+        let mut huge = TestContext::create_test_document(user_id, "Huge");
+        huge.content["text"] = json!("x".repeat(TEST_MAX_MESSAGE_BYTES + 1));
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: huge,
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::Error { code, message } => {
+                assert_eq!(code, ErrorCode::ValidationFailed);
+                assert!(message.contains("byte limit"));
+            }
+            other => panic!("Expected ValidationFailed error, got {:?}", other),
+        }
+
+        // The connection is still usable
+        ws.send(Message::Text(ping.clone())).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Pong));
+
+        // A document over the per-document limit is refused too
+        let mut large = TestContext::create_test_document(user_id, "Large");
+        large.content["text"] = json!("x".repeat(TEST_MAX_DOCUMENT_BYTES + 1));
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: large.clone(),
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::Error { code, message } => {
+                assert_eq!(code, ErrorCode::ValidationFailed);
+                assert!(message.starts_with("Document content"));
+            }
+            other => panic!("Expected ValidationFailed error, got {:?}", other),
+        }
+
+        ws.send(Message::Text(ping)).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Pong));
+
+        // Nothing was stored
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::RequestSync {
+                document_ids: vec![large.id],
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        assert!(matches!(
+            next_message(&mut ws).await,
+            ServerMessage::SyncComplete { .. }
+        ));
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_malformed_messages_do_not_close_connection,
+    |ctx: TestContext| async move {
+        use replicant_core::protocol::ErrorCode;
+
+        let email = "rowan@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-rowan")
+            .await
+            .expect("Failed to generate credentials");
+        ctx.create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let ping = serde_json::to_string(&ClientMessage::Ping).unwrap();
+        let garbage = [
+            Message::Text("{not json".to_string()),
+            Message::Text(r#"{"type":"no_such_message"}"#.to_string()),
+            Message::Binary(vec![0xde, 0xad, 0xbe, 0xef]),
+        ];
+
+        for frame in garbage {
+            ws.send(frame).await.unwrap();
+            match next_message(&mut ws).await {
+                ServerMessage::Error { code, message } => {
+                    assert_eq!(code, ErrorCode::ValidationFailed);
+                    assert!(message.starts_with("malformed message"));
+                }
+                other => panic!("Expected ValidationFailed error, got {:?}", other),
+            }
+
+            // The connection survives each bad frame
+            ws.send(Message::Text(ping.clone())).await.unwrap();
+            assert!(matches!(next_message(&mut ws).await, ServerMessage::Pong));
+        }
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_metrics_endpoint_reports_activity,
+    |ctx: TestContext| async move {
+        let email = "sage@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-sage")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        let doc = TestContext::create_test_document(user_id, "Metrics");
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: doc,
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+            .await
+            .expect("Timeout waiting for create response");
+        assert!(matches!(response, Some(Ok(Message::Text(_)))));
+
+        let base = ctx.server_url.replace("ws://", "http://");
+        let response = reqwest::get(format!("{}/metrics", base)).await.unwrap();
+        assert!(response.status().is_success());
+        let body = response.text().await.unwrap();
+
+        assert!(body.contains("replicant_connected_clients 1\n"), "{}", body);
+        assert!(body.contains("replicant_messages_received_total{type=\"CreateDocument\"} 1\n"));
+        assert!(body.contains("replicant_documents_stored_total{operation=\"create\"} 1\n"));
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_reconnect_with_same_client_id_evicts_old_connection,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+
+        let email = "tess@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-tess")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        // The same client connects twice, as after a half-dead socket
+        let client_id = Uuid::new_v4();
+        let mut stale = ctx.create_websocket_with_client_id(email, client_id).await;
+        let mut fresh = ctx.create_websocket_with_client_id(email, client_id).await;
+
+        // The stale connection is closed by the server
+        let closed = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                match stale.next().await {
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => continue,
+                }
+            }
+        })
+        .await;
+        assert!(closed.is_ok(), "Stale connection was not closed");
+
+        // Only one registration remains for the client
+        fresh
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::RequestPresence).unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut fresh).await {
+            ServerMessage::PresenceUpdate { client_ids } => assert_eq!(client_ids, vec![client_id]),
+            other => panic!("Expected PresenceUpdate, got {:?}", other),
+        }
+
+        // Another client's update reaches the fresh connection exactly once
+        let mut writer = ctx.create_authenticated_websocket(email, &api_key).await;
+        let doc = TestContext::create_test_document(user_id, "Evict");
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        let mut new_content = doc.content.clone();
+        new_content["text"] = json!("Updated once");
+        let patch = DocumentPatch {
+            document_id: doc.id,
+            patch: create_patch(&doc.content, &new_content).unwrap(),
+            content_hash: calculate_checksum(&doc.content),
+        };
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+
+        let mut sync_documents = 0;
+        while let Ok(Some(Ok(message))) =
+            tokio::time::timeout(std::time::Duration::from_secs(2), fresh.next()).await
+        {
+            if let Message::Text(text) = message {
+                if let ServerMessage::SyncDocument { document, .. } =
+                    serde_json::from_str(&text).unwrap()
+                {
+                    assert_eq!(document.id, doc.id);
+                    sync_documents += 1;
+                }
+            }
+        }
+        assert_eq!(sync_documents, 1);
+
+        writer.close(None).await.unwrap();
+        fresh.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_transfer_ownership_moves_document_between_users,
+    |ctx: TestContext| async move {
+        use replicant_core::protocol::ErrorCode;
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-transfer")
+            .await
+            .expect("Failed to generate credentials");
+        let alice_id = ctx
+            .create_test_user("uma@test.local")
+            .await
+            .expect("Failed to create user");
+        let bob_id = ctx
+            .create_test_user("victor@test.local")
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let mut alice = ctx
+            .create_authenticated_websocket("uma@test.local", &api_key)
+            .await;
+        let mut alice_other = ctx
+            .create_authenticated_websocket("uma@test.local", &api_key)
+            .await;
+        let mut bob = ctx
+            .create_authenticated_websocket("victor@test.local", &api_key)
+            .await;
+
+        let doc = TestContext::create_test_document(alice_id, "Hand off");
+        alice
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut alice).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            next_message(&mut alice_other).await,
+            ServerMessage::DocumentCreated { .. }
+        ));
+
+        let transfer = |new_user_id| {
+            Message::Text(
+                serde_json::to_string(&ClientMessage::TransferOwnership {
+                    document_id: doc.id,
+                    new_user_id,
+                })
+                .unwrap(),
+            )
+        };
+
+        // Unknown target users are refused
+        alice.send(transfer(Uuid::new_v4())).await.unwrap();
+        match next_message(&mut alice).await {
+            ServerMessage::Error { code, message } => {
+                assert_eq!(code, ErrorCode::ValidationFailed);
+                assert!(message.contains("does not exist"));
+            }
+            other => panic!("Expected ValidationFailed error, got {:?}", other),
+        }
+
+        // Bob can't take a document he doesn't own
+        bob.send(transfer(bob_id)).await.unwrap();
+        match next_message(&mut bob).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidAuth),
+            other => panic!("Expected InvalidAuth error, got {:?}", other),
+        }
+
+        // Alice hands it to Bob
+        alice.send(transfer(bob_id)).await.unwrap();
+        for ws in [&mut alice, &mut alice_other] {
+            match next_message(ws).await {
+                ServerMessage::DocumentDeleted { document_id } => assert_eq!(document_id, doc.id),
+                other => panic!("Expected DocumentDeleted, got {:?}", other),
+            }
+        }
+        match next_message(&mut bob).await {
+            ServerMessage::DocumentCreated { document } => {
+                assert_eq!(document.id, doc.id);
+                assert_eq!(document.user_id, bob_id);
+                assert_eq!(document.content, doc.content);
+            }
+            other => panic!("Expected DocumentCreated, got {:?}", other),
+        }
+
+        // Alice no longer owns it
+        alice.send(transfer(alice_id)).await.unwrap();
+        match next_message(&mut alice).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidAuth),
+            other => panic!("Expected InvalidAuth error, got {:?}", other),
+        }
+
+        alice.close(None).await.unwrap();
+        alice_other.close(None).await.unwrap();
+        bob.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_shared_document_permissions,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::{ErrorCode, SharePermission};
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-sharing")
+            .await
+            .expect("Failed to generate credentials");
+        let owner_id = ctx
+            .create_test_user("wendy@test.local")
+            .await
+            .expect("Failed to create user");
+        let guest_id = ctx
+            .create_test_user("xavier@test.local")
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let mut owner = ctx
+            .create_authenticated_websocket("wendy@test.local", &api_key)
+            .await;
+        let mut guest = ctx
+            .create_authenticated_websocket("xavier@test.local", &api_key)
+            .await;
+
+        let doc = TestContext::create_test_document(owner_id, "Shared notes");
+        owner
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut owner).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+
+        let share = |permission| {
+            Message::Text(
+                serde_json::to_string(&ClientMessage::ShareDocument {
+                    document_id: doc.id,
+                    user_id: guest_id,
+                    permission,
+                })
+                .unwrap(),
+            )
+        };
+        let update = |content: &serde_json::Value, text: &str| {
+            let mut new_content = content.clone();
+            new_content["text"] = json!(text);
+            let patch = DocumentPatch {
+                document_id: doc.id,
+                patch: create_patch(content, &new_content).unwrap(),
+                content_hash: calculate_checksum(content),
+            };
+            (
+                Message::Text(
+                    serde_json::to_string(&ClientMessage::UpdateDocument {
+                        patch,
+                        idempotency_key: None,
+                    })
+                    .unwrap(),
+                ),
+                new_content,
+            )
+        };
+
+        // Read share: the guest receives the document but can't change it
+        owner.send(share(SharePermission::Read)).await.unwrap();
+        match next_message(&mut owner).await {
+            ServerMessage::DocumentShared {
+                document_id,
+                user_id,
+                permission,
+            } => {
+                assert_eq!(document_id, doc.id);
+                assert_eq!(user_id, guest_id);
+                assert_eq!(permission, SharePermission::Read);
+            }
+            other => panic!("Expected DocumentShared, got {:?}", other),
+        }
+        match next_message(&mut guest).await {
+            ServerMessage::DocumentCreated { document } => {
+                assert_eq!(document.id, doc.id);
+                assert_eq!(document.user_id, owner_id);
+            }
+            other => panic!("Expected DocumentCreated, got {:?}", other),
+        }
+
+        let (message, _) = update(&doc.content, "Guest edit");
+        guest.send(message).await.unwrap();
+        match next_message(&mut guest).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidAuth),
+            other => panic!("Expected InvalidAuth error, got {:?}", other),
+        }
+
+        // Owner edits reach the guest
+        let (message, owner_content) = update(&doc.content, "Owner edit");
+        owner.send(message).await.unwrap();
+        assert!(matches!(
+            next_message(&mut owner).await,
+            ServerMessage::DocumentUpdatedResponse { success: true, .. }
+        ));
+        match next_message(&mut guest).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.content, owner_content)
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        // Write share: guest edits are applied and reach the owner
+        owner.send(share(SharePermission::Write)).await.unwrap();
+        assert!(matches!(
+            next_message(&mut owner).await,
+            ServerMessage::DocumentShared { .. }
+        ));
+        assert!(matches!(
+            next_message(&mut guest).await,
+            ServerMessage::DocumentCreated { .. }
+        ));
+
+        let (message, guest_content) = update(&owner_content, "Guest edit");
+        guest.send(message).await.unwrap();
+        match next_message(&mut guest).await {
+            ServerMessage::DocumentUpdatedResponse {
+                success, sequence, ..
+            } => {
+                assert!(success);
+                assert_eq!(sequence, None, "the sequence is in the owner's log");
+            }
+            other => panic!("Expected DocumentUpdatedResponse, got {:?}", other),
+        }
+        match next_message(&mut owner).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.content, guest_content);
+                assert_eq!(document.user_id, owner_id);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        // Full sync includes shared documents
+        guest
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::RequestFullSync).unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut guest).await {
+            ServerMessage::SyncDocument { document, .. } => assert_eq!(document.id, doc.id),
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+        assert!(matches!(
+            next_message(&mut guest).await,
+            ServerMessage::SyncComplete { synced_count: 1 }
+        ));
+
+        // Deleting and sharing stay with the owner
+        guest
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::DeleteDocument {
+                    document_id: doc.id,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut guest).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidAuth),
+            other => panic!("Expected InvalidAuth error, got {:?}", other),
+        }
+        guest.send(share(SharePermission::Write)).await.unwrap();
+        match next_message(&mut guest).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidAuth),
+            other => panic!("Expected InvalidAuth error, got {:?}", other),
+        }
+
+        // The owner's delete reaches the guest
+        owner
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::DeleteDocument {
+                    document_id: doc.id,
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut owner).await,
+            ServerMessage::DocumentDeletedResponse { success: true, .. }
+        ));
+        match next_message(&mut guest).await {
+            ServerMessage::DocumentDeleted { document_id } => assert_eq!(document_id, doc.id),
+            other => panic!("Expected DocumentDeleted, got {:?}", other),
+        }
+
+        owner.close(None).await.unwrap();
+        guest.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_server_overrides_skewed_client_timestamps,
+    |ctx: TestContext| async move {
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-clock-skew")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user("skew@test.local")
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let mut ws = ctx
+            .create_authenticated_websocket("skew@test.local", &api_key)
+            .await;
+
+        // The client's clock runs an hour behind the server
+        let skewed = Utc::now() - chrono::Duration::hours(1);
+        let mut doc = TestContext::create_test_document(user_id, "Skewed");
+        doc.created_at = skewed;
+        doc.updated_at = skewed;
+
+        let before = Utc::now();
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::CreateDocument {
+                document: doc.clone(),
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+
+        let (created_at, updated_at) = match next_message(&mut ws).await {
+            ServerMessage::DocumentCreatedResponse {
+                success: true,
+                created_at: Some(created_at),
+                updated_at: Some(updated_at),
+                ..
+            } => (created_at, updated_at),
+            other => panic!("Expected DocumentCreatedResponse, got {:?}", other),
+        };
+        let tolerance = chrono::Duration::seconds(5);
+        assert!(created_at > before - tolerance && created_at < Utc::now() + tolerance);
+        assert_eq!(created_at, updated_at);
+
+        // The stored row carries the server's timestamps, not the client's
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::RequestFullSync).unwrap(),
+        ))
+        .await
+        .unwrap();
+        let stored = loop {
+            match next_message(&mut ws).await {
+                ServerMessage::SyncDocument { document, .. } if document.id == doc.id => {
+                    break document
+                }
+                ServerMessage::SyncComplete { .. } => panic!("Document missing from full sync"),
+                _ => {}
+            }
+        };
+        assert_eq!(stored.created_at, created_at);
+        assert_eq!(stored.updated_at, updated_at);
+        assert!(stored.created_at > skewed + chrono::Duration::minutes(30));
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_broadcast_excludes_originating_client,
+    |ctx: TestContext| async move {
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-echo")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user("echo@test.local")
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let mut origin = ctx
+            .create_authenticated_websocket("echo@test.local", &api_key)
+            .await;
+        let mut other = ctx
+            .create_authenticated_websocket("echo@test.local", &api_key)
+            .await;
+
+        let mut doc = TestContext::create_test_document(user_id, "Echo");
+        let create = |doc: &Document| {
+            Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            )
+        };
+
+        origin.send(create(&doc)).await.unwrap();
+        assert!(matches!(
+            next_message(&mut origin).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        match next_message(&mut other).await {
+            ServerMessage::DocumentCreated { document } => assert_eq!(document.id, doc.id),
+            other => panic!("Expected DocumentCreated, got {:?}", other),
+        }
+
+        // Creating it again overwrites the stored version (last write wins)
+        doc.content = json!({"title": "Echo", "text": "overwritten"});
+        origin.send(create(&doc)).await.unwrap();
+        match next_message(&mut origin).await {
+            ServerMessage::DocumentCreatedResponse {
+                success: true,
+                sync_revision,
+                ..
+            } => assert_eq!(sync_revision, Some(2)),
+            other => panic!("Expected DocumentCreatedResponse, got {:?}", other),
+        }
+        match next_message(&mut other).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.content, doc.content);
+                assert_eq!(document.sync_revision, 2);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        // The originator only gets its response, never the broadcast
+        let echo = tokio::time::timeout(std::time::Duration::from_millis(500), async {
+            next_message(&mut origin).await
+        })
+        .await;
+        assert!(echo.is_err(), "Originator received {:?}", echo);
+
+        origin.close(None).await.unwrap();
+        other.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_repeated_create_with_idempotency_key_is_applied_once,
+    |ctx: TestContext| async move {
+        use replicant_server::database::ServerDatabase;
+
+        let email = "ida@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-ida")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        // The same create sent twice, as after a lost response
+        let doc = TestContext::create_test_document(user_id, "Retried");
+        let create = serde_json::to_string(&ClientMessage::CreateDocument {
+            document: doc.clone(),
+            idempotency_key: Some(Uuid::new_v4()),
+        })
+        .unwrap();
+
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            ws.send(Message::Text(create.clone())).await.unwrap();
+            match next_message(&mut ws).await {
+                ServerMessage::DocumentCreatedResponse {
+                    document_id,
+                    success,
+                    sync_revision,
+                    sequence,
+                    ..
+                } => {
+                    assert_eq!(document_id, doc.id);
+                    assert!(success);
+                    responses.push((sync_revision, sequence));
+                }
+                other => panic!("Expected DocumentCreatedResponse, got {:?}", other),
+            }
+        }
+        // The retry gets the original response rather than overwriting
+        assert_eq!(responses[0], responses[1]);
+        assert_eq!(responses[0].0, Some(1));
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let documents = db.get_user_documents(&user_id).await.unwrap();
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].sync_revision, 1);
+        assert_eq!(db.get_operations_since(&user_id, 0).await.unwrap().len(), 1);
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_admin_broadcast_pushes_document_to_all_clients,
+    |ctx: TestContext| async move {
+        use replicant_server::database::ServerDatabase;
+
+        let email = "bea@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-bea")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut first = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut second = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let doc = TestContext::create_test_document(user_id, "Canonical");
+        first
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: doc.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut first).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            next_message(&mut second).await,
+            ServerMessage::DocumentCreated { .. }
+        ));
+
+        // A manual fix that no client has seen
+        let fixed = json!({"title": "Canonical", "text": "Fixed by hand"});
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        sqlx::query("UPDATE documents SET content = $1 WHERE id = $2")
+            .bind(&fixed)
+            .bind(doc.id)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let base = ctx.server_url.replace("ws://", "http://");
+        let http = reqwest::Client::new();
+        let response = http
+            .post(format!("{}/admin/documents/{}/broadcast", base, doc.id))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["delivered"], 2);
+
+        for ws in [&mut first, &mut second] {
+            match next_message(ws).await {
+                ServerMessage::SyncDocument { document, .. } => {
+                    assert_eq!(document.id, doc.id);
+                    assert_eq!(document.content, fixed);
+                }
+                other => panic!("Expected SyncDocument, got {:?}", other),
+            }
+        }
+
+        // Unknown documents and missing tokens are refused
+        let response = http
+            .post(format!(
+                "{}/admin/documents/{}/broadcast",
+                base,
+                Uuid::new_v4()
+            ))
+            .bearer_auth(TEST_ADMIN_TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let response = http
+            .post(format!("{}/admin/documents/{}/broadcast", base, doc.id))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+        first.close(None).await.unwrap();
+        second.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_transaction_applies_all_or_nothing,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::TransactionOp;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "tomas@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-tomas")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let mut writer = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut observer = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let existing = TestContext::create_test_document(user_id, "Existing");
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::CreateDocument {
+                    document: existing.clone(),
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut writer).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            next_message(&mut observer).await,
+            ServerMessage::DocumentCreated { .. }
+        ));
+
+        let mut new_content = existing.content.clone();
+        new_content["text"] = json!("Changed in a transaction");
+        let created = TestContext::create_test_document(user_id, "Created");
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+
+        // A stale base hash on the update rejects the create alongside it
+        let transaction_id = Uuid::new_v4();
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::Transaction {
+                    transaction_id,
+                    ops: vec![
+                        TransactionOp::Create {
+                            document: created.clone(),
+                        },
+                        TransactionOp::Update {
+                            patch: DocumentPatch {
+                                document_id: existing.id,
+                                patch: create_patch(&existing.content, &new_content).unwrap(),
+                                content_hash: calculate_checksum(&json!({"stale": true})),
+                            },
+                        },
+                    ],
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut writer).await {
+            ServerMessage::TransactionResponse {
+                transaction_id: id,
+                success,
+                error,
+                ..
+            } => {
+                assert_eq!(id, transaction_id);
+                assert!(!success);
+                assert!(error.is_some());
+            }
+            other => panic!("Expected TransactionResponse, got {:?}", other),
+        }
+        assert!(db.get_document(&created.id).await.is_err());
+        assert_eq!(
+            db.get_document(&existing.id).await.unwrap().content,
+            existing.content
+        );
+
+        // With the right base both changes apply and reach the other client
+        let transaction_id = Uuid::new_v4();
+        writer
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::Transaction {
+                    transaction_id,
+                    ops: vec![
+                        TransactionOp::Create {
+                            document: created.clone(),
+                        },
+                        TransactionOp::Update {
+                            patch: DocumentPatch {
+                                document_id: existing.id,
+                                patch: create_patch(&existing.content, &new_content).unwrap(),
+                                content_hash: calculate_checksum(&existing.content),
+                            },
+                        },
+                    ],
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        match next_message(&mut writer).await {
+            ServerMessage::TransactionResponse {
+                success, documents, ..
+            } => {
+                assert!(success);
+                assert_eq!(documents.len(), 2);
+            }
+            other => panic!("Expected TransactionResponse, got {:?}", other),
+        }
+        assert_eq!(
+            db.get_document(&existing.id).await.unwrap().content,
+            new_content
+        );
+        assert!(db.get_document(&created.id).await.is_ok());
+
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            match next_message(&mut observer).await {
+                ServerMessage::DocumentCreated { document }
+                | ServerMessage::SyncDocument { document, .. } => seen.push(document.id),
+                other => panic!("Expected a document broadcast, got {:?}", other),
+            }
+        }
+        assert!(seen.contains(&created.id) && seen.contains(&existing.id));
+
+        writer.close(None).await.unwrap();
+        observer.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_full_sync_streams_large_dataset,
+    |ctx: TestContext| async move {
+        use replicant_server::database::ServerDatabase;
+
+        let email = "stream@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-stream")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let total = 1000;
+        for i in 0..total {
+            let doc = TestContext::create_test_document(user_id, &format!("Doc {}", i));
+            db.create_document(&doc).await.unwrap();
+        }
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::RequestFullSync).unwrap(),
+        ))
+        .await
+        .unwrap();
+
+        let mut received = std::collections::HashSet::new();
+        let mut last_updated = None;
+        loop {
+            let response = tokio::time::timeout(std::time::Duration::from_secs(10), ws.next())
+                .await
+                .expect("Timeout waiting for server message");
+            let Some(Ok(Message::Text(text))) = response else {
+                continue;
+            };
+            match serde_json::from_str(&text).unwrap() {
+                ServerMessage::SyncDocument { document, .. } => {
+                    // Streamed newest first, like the collected version
+                    if let Some(previous) = last_updated {
+                        assert!(document.updated_at <= previous);
+                    }
+                    last_updated = Some(document.updated_at);
+                    received.insert(document.id);
+                }
+                ServerMessage::SyncComplete { synced_count } => {
+                    assert_eq!(synced_count, total);
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(received.len(), total);
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_request_document_returns_single_document,
+    |ctx: TestContext| async move {
+        use replicant_core::protocol::ErrorCode;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "single@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-single")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let doc = TestContext::create_test_document(user_id, "Wanted");
+        db.create_document(&doc).await.unwrap();
+        let stranger = TestContext::create_test_document(Uuid::new_v4(), "Not yours");
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::RequestDocument {
+                document_id: doc.id,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.id, doc.id);
+                assert_eq!(document.content, doc.content);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        // Unknown documents get an error rather than an empty sync
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::RequestDocument {
+                document_id: stranger.id,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::DocumentNotFound),
+            other => panic!("Expected DocumentNotFound, got {:?}", other),
+        }
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_subscriptions_filter_broadcasts,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_server::database::ServerDatabase;
+
+        type Ws = tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+        >;
+
+        let email = "subscriber@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-subscriber")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(ws: &mut Ws) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        async fn send(ws: &mut Ws, message: ClientMessage) {
+            ws.send(Message::Text(serde_json::to_string(&message).unwrap()))
+                .await
+                .unwrap();
+        }
+
+        async fn update(ws: &mut Ws, doc: &mut Document, text: &str) {
+            let mut new_content = doc.content.clone();
+            new_content["text"] = json!(text);
+            let patch = DocumentPatch {
+                document_id: doc.id,
+                patch: create_patch(&doc.content, &new_content).unwrap(),
+                content_hash: calculate_checksum(&doc.content),
+            };
+            doc.content = new_content;
+            send(
+                ws,
+                ClientMessage::UpdateDocument {
+                    patch,
+                    idempotency_key: None,
+                },
+            )
+            .await;
+        }
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let mut wanted = TestContext::create_test_document(user_id, "Wanted");
+        let mut ignored = TestContext::create_test_document(user_id, "Ignored");
+        db.create_document(&wanted).await.unwrap();
+        db.create_document(&ignored).await.unwrap();
+
+        let mut ws_a = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut ws_b = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        send(
+            &mut ws_b,
+            ClientMessage::Subscribe {
+                document_ids: vec![wanted.id],
+            },
+        )
+        .await;
+        // The Pong means the subscription has been recorded
+        send(&mut ws_b, ClientMessage::Ping).await;
+        assert!(matches!(next_message(&mut ws_b).await, ServerMessage::Pong));
+
+        // The unsubscribed document changes first but never arrives
+        update(&mut ws_a, &mut ignored, "Nobody is watching").await;
+        update(&mut ws_a, &mut wanted, "Somebody is watching").await;
+        match next_message(&mut ws_b).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.id, wanted.id);
+                assert_eq!(document.content, wanted.content);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        // With nothing subscribed, B hears about every document again
+        send(
+            &mut ws_b,
+            ClientMessage::Unsubscribe {
+                document_ids: vec![wanted.id],
+            },
+        )
+        .await;
+        send(&mut ws_b, ClientMessage::Ping).await;
+        assert!(matches!(next_message(&mut ws_b).await, ServerMessage::Pong));
+
+        update(&mut ws_a, &mut ignored, "Everybody is watching").await;
+        match next_message(&mut ws_b).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.id, ignored.id);
+                assert_eq!(document.content, ignored.content);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        ws_a.close(None).await.unwrap();
+        ws_b.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_update_guarded_by_test_op,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::calculate_checksum;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "guard@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-guard")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let doc = TestContext::create_test_document(user_id, "Original");
+        db.create_document(&doc).await.unwrap();
+
+        // Only edit the text while the title is still "Original"
+        let guarded_edit = |text: &str| -> json_patch::Patch {
+            serde_json::from_value(json!([
+                { "op": "test", "path": "/title", "value": "Original" },
+                { "op": "replace", "path": "/text", "value": text }
+            ]))
+            .unwrap()
+        };
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::UpdateDocument {
+                patch: DocumentPatch {
+                    document_id: doc.id,
+                    patch: guarded_edit("Guarded edit"),
+                    content_hash: calculate_checksum(&doc.content),
+                },
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::DocumentUpdatedResponse { success, .. } => assert!(success),
+            other => panic!("Expected DocumentUpdatedResponse, got {:?}", other),
+        }
+
+        // A concurrent rename invalidates the guard
+        let mut renamed = db.get_document(&doc.id).await.unwrap();
+        assert_eq!(renamed.content["text"], "Guarded edit");
+        renamed.content["title"] = json!("Renamed");
+        db.update_document(&renamed, None).await.unwrap();
+        let current = db.get_document(&doc.id).await.unwrap();
+
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::UpdateDocument {
+                patch: DocumentPatch {
+                    document_id: doc.id,
+                    patch: guarded_edit("Stale edit"),
+                    content_hash: calculate_checksum(&current.content),
+                },
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+        match next_message(&mut ws).await {
+            ServerMessage::UpdateRejected {
+                document_id,
+                server_document,
+            } => {
+                assert_eq!(document_id, doc.id);
+                assert_eq!(server_document.content, current.content);
+            }
+            other => panic!("Expected UpdateRejected, got {:?}", other),
+        }
+
+        // Neither operation of the rejected patch was applied
+        let stored = db.get_document(&doc.id).await.unwrap();
+        assert_eq!(stored.content, current.content);
+        assert_eq!(stored.sync_revision, current.sync_revision);
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_update_with_wrong_base_hash_is_rejected,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::create_patch;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "stale@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-stale")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let doc = TestContext::create_test_document(user_id, "Original");
+        db.create_document(&doc).await.unwrap();
+
+        // A patch that would still apply, but was computed against other content
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut edited = doc.content.clone();
+        edited["title"] = json!("Clobbered");
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::UpdateDocument {
+                patch: DocumentPatch {
+                    document_id: doc.id,
+                    patch: create_patch(&doc.content, &edited).unwrap(),
+                    content_hash: "0".repeat(64),
+                },
+                idempotency_key: None,
+            })
+            .unwrap(),
+        ))
+        .await
+        .unwrap();
+
+        match next_message(&mut ws).await {
+            ServerMessage::UpdateRejected {
+                document_id,
+                server_document,
+            } => {
+                assert_eq!(document_id, doc.id);
+                assert_eq!(server_document.content, doc.content);
+            }
+            other => panic!("Expected UpdateRejected, got {:?}", other),
+        }
+
+        // Nothing was written
+        let stored = db.get_document(&doc.id).await.unwrap();
+        assert_eq!(stored.content, doc.content);
+        assert_eq!(stored.sync_revision, doc.sync_revision);
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_operation_batch_applies_in_order,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::{calculate_checksum, create_patch};
+        use replicant_core::protocol::ErrorCode;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "batch@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-batch")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        // A create followed by an update of the same document, which only
+        // works if they are applied in order, and something that isn't allowed
+        let doc = TestContext::create_test_document(user_id, "Batched");
+        let mut edited = doc.content.clone();
+        edited["title"] = json!("Batched and edited");
+        let operations = vec![
+            ClientMessage::CreateDocument {
+                document: doc.clone(),
+                idempotency_key: None,
+            },
+            ClientMessage::UpdateDocument {
+                patch: DocumentPatch {
+                    document_id: doc.id,
+                    patch: create_patch(&doc.content, &edited).unwrap(),
+                    content_hash: calculate_checksum(&doc.content),
+                },
+                idempotency_key: None,
+            },
+            ClientMessage::RequestPresence,
+        ];
+
+        let mut ws = ctx.create_authenticated_websocket(email, &api_key).await;
+        ws.send(Message::Text(
+            serde_json::to_string(&ClientMessage::OperationBatch { operations }).unwrap(),
+        ))
+        .await
+        .unwrap();
+
+        // One response per operation, in order
+        assert!(matches!(
+            next_message(&mut ws).await,
+            ServerMessage::DocumentCreatedResponse { success: true, .. }
+        ));
+        assert!(matches!(
+            next_message(&mut ws).await,
+            ServerMessage::DocumentUpdatedResponse { success: true, .. }
+        ));
+        match next_message(&mut ws).await {
+            ServerMessage::Error { code, .. } => assert_eq!(code, ErrorCode::InvalidMessage),
+            other => panic!("Expected InvalidMessage, got {:?}", other),
+        }
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        assert_eq!(db.get_document(&doc.id).await.unwrap().content, edited);
+
+        ws.close(None).await.unwrap();
+    },
+    true
+);
+
+crate::integration_test!(
+    test_empty_update_touches_document,
+    |ctx: TestContext| async move {
+        use replicant_core::models::DocumentPatch;
+        use replicant_core::patches::calculate_checksum;
+        use replicant_server::database::ServerDatabase;
+
+        let email = "toucher@test.local";
+
+        let (api_key, _) = ctx
+            .generate_test_credentials("test-toucher")
+            .await
+            .expect("Failed to generate credentials");
+        let user_id = ctx
+            .create_test_user(email)
+            .await
+            .expect("Failed to create user");
+
+        async fn next_message(
+            ws: &mut tokio_tungstenite::WebSocketStream<
+                tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
+            >,
+        ) -> ServerMessage {
+            loop {
+                let response = tokio::time::timeout(std::time::Duration::from_secs(5), ws.next())
+                    .await
+                    .expect("Timeout waiting for server message");
+                if let Some(Ok(Message::Text(text))) = response {
+                    return serde_json::from_str(&text).unwrap();
+                }
+            }
+        }
+
+        let db = ServerDatabase::new(&ctx.db_url, "com.example.sync-task-list".to_string())
+            .await
+            .unwrap();
+        let doc = TestContext::create_test_document(user_id, "Touched");
+        db.create_document(&doc).await.unwrap();
+        let before = db.get_document(&doc.id).await.unwrap();
+
+        let mut toucher = ctx.create_authenticated_websocket(email, &api_key).await;
+        let mut other = ctx.create_authenticated_websocket(email, &api_key).await;
+
+        toucher
+            .send(Message::Text(
+                serde_json::to_string(&ClientMessage::UpdateDocument {
+                    patch: DocumentPatch {
+                        document_id: doc.id,
+                        patch: json_patch::Patch(vec![]),
+                        content_hash: calculate_checksum(&doc.content),
+                    },
+                    idempotency_key: None,
+                })
+                .unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut toucher).await,
+            ServerMessage::DocumentUpdatedResponse { success: true, .. }
+        ));
+
+        match next_message(&mut other).await {
+            ServerMessage::SyncDocument { document, .. } => {
+                assert_eq!(document.id, doc.id);
+                assert_eq!(document.content, doc.content);
+                assert!(document.updated_at > before.updated_at);
+            }
+            other => panic!("Expected SyncDocument, got {:?}", other),
+        }
+
+        toucher.close(None).await.unwrap();
+        other.close(None).await.unwrap();
+    },
+    true
+);
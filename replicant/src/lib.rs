@@ -12,13 +12,17 @@
 //! ```
 
 // Re-export client types
-pub use replicant_client::Client;
+pub use replicant_client::{
+    AesGcmCipher, Client, ClientConfig, ConflictRecord, ConnectionState, ContentCipher,
+    ContentMigration, ContentValidator, DeferredOverflowPolicy, DocumentOrder, DocumentStore,
+    ImportMode, RepairReport, Sha256Fingerprint, SyncSummary, SyncTimeouts, Tx, SCHEMA_VERSION_KEY,
+};
 
 // Re-export server types
 pub use replicant_server::AppState as Server;
 
 // Re-export core types that external applications may need
 pub use replicant_core::errors::SyncError;
-pub use replicant_core::models::Document;
-pub use replicant_core::protocol::{ClientMessage, ServerMessage};
+pub use replicant_core::models::{user_id_from_email, Document};
+pub use replicant_core::protocol::{ClientMessage, ServerMessage, SharePermission};
 pub use replicant_core::SyncResult;